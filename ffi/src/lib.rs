@@ -0,0 +1,423 @@
+//! A C interface for acid-store.
+//!
+//! This crate exposes a [`KeyRepo`] with string keys through a C ABI so that acid-store can be
+//! embedded in applications written in other languages. A C header can be generated with
+//! [cbindgen](https://github.com/mozilla/cbindgen) using the `cbindgen.toml` in this crate.
+//!
+//! Repositories are opened with [`acid_repo_open_directory`] or [`acid_repo_open_memory`], which
+//! return an opaque [`AcidRepo`] handle that must be closed with [`acid_repo_close`]. Objects are
+//! read and written whole as byte buffers. Changes are not persisted until [`acid_repo_commit`] is
+//! called.
+//!
+//! Every function which can fail returns an `int32_t` status. This is [`ACID_OK`] on success, a
+//! positive value equal to [`Error::code`] if the operation failed with an [`Error`], or one of the
+//! negative `ACID_ERROR_*` constants if the arguments were invalid. After a function fails,
+//! [`acid_error_message`] returns a description of the error.
+//!
+//! None of the functions in this crate are thread-safe with respect to the same [`AcidRepo`].
+//!
+//! [`KeyRepo`]: acid_store::repo::key::KeyRepo
+//! [`Error::code`]: acid_store::Error::code
+//! [`Error`]: acid_store::Error
+
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::io::{Read, Write};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+
+use acid_store::repo::key::KeyRepo;
+#[cfg(feature = "encryption")]
+use acid_store::repo::Encryption;
+use acid_store::repo::{Commit, OpenMode, OpenOptions};
+use acid_store::store::{DirectoryConfig, MemoryConfig, OpenStore};
+
+/// The operation succeeded.
+pub const ACID_OK: i32 = 0;
+
+/// A required pointer argument was null.
+pub const ACID_ERROR_NULL_POINTER: i32 = -1;
+
+/// A string argument was not valid UTF-8.
+pub const ACID_ERROR_INVALID_UTF8: i32 = -2;
+
+/// An argument was outside the range of accepted values.
+pub const ACID_ERROR_INVALID_ARGUMENT: i32 = -3;
+
+/// The operation panicked.
+///
+/// This indicates a bug in acid-store.
+pub const ACID_ERROR_PANIC: i32 = -4;
+
+/// Open an existing repository and fail if it doesn't exist.
+pub const ACID_MODE_OPEN: i32 = 0;
+
+/// Open an existing repository or create a new one if it doesn't exist.
+pub const ACID_MODE_CREATE: i32 = 1;
+
+/// Create a new repository and fail if one already exists.
+pub const ACID_MODE_CREATE_NEW: i32 = 2;
+
+thread_local! {
+    /// The message describing the last error which occurred on this thread.
+    static LAST_ERROR: RefCell<Option<CString>> = RefCell::new(None);
+}
+
+/// A repository which maps string keys to binary objects.
+///
+/// This is an opaque handle which must be freed with [`acid_repo_close`].
+pub struct AcidRepo(KeyRepo<String>);
+
+/// An error which is returned through the C interface.
+enum FfiError {
+    Repo(acid_store::Error),
+    Argument(i32, &'static str),
+}
+
+impl From<acid_store::Error> for FfiError {
+    fn from(error: acid_store::Error) -> Self {
+        FfiError::Repo(error)
+    }
+}
+
+impl From<std::io::Error> for FfiError {
+    fn from(error: std::io::Error) -> Self {
+        FfiError::Repo(acid_store::Error::Io(error))
+    }
+}
+
+/// Record the given error `message` as the last error on this thread.
+fn set_last_error(message: String) {
+    // Interior null bytes can't be represented in a C string.
+    let message = CString::new(message.replace('\0', " ")).unwrap();
+    LAST_ERROR.with(|last_error| *last_error.borrow_mut() = Some(message));
+}
+
+/// Call `operation`, converting its result and any panic into a status code.
+fn handle(operation: impl FnOnce() -> Result<(), FfiError>) -> i32 {
+    match catch_unwind(AssertUnwindSafe(operation)) {
+        Ok(Ok(())) => ACID_OK,
+        Ok(Err(FfiError::Repo(error))) => {
+            set_last_error(error.to_string());
+            error.code() as i32
+        }
+        Ok(Err(FfiError::Argument(code, message))) => {
+            set_last_error(message.to_owned());
+            code
+        }
+        Err(_) => {
+            set_last_error(String::from("The operation panicked."));
+            ACID_ERROR_PANIC
+        }
+    }
+}
+
+/// Return a reference to the value behind `pointer`, failing if it is null.
+///
+/// # Safety
+/// If `pointer` is not null, it must be valid for reads for the lifetime `'a`.
+unsafe fn deref<'a, T>(pointer: *const T) -> Result<&'a T, FfiError> {
+    pointer.as_ref().ok_or(FfiError::Argument(
+        ACID_ERROR_NULL_POINTER,
+        "A required argument was null.",
+    ))
+}
+
+/// Return a mutable reference to the value behind `pointer`, failing if it is null.
+///
+/// # Safety
+/// If `pointer` is not null, it must be valid for reads and writes for the lifetime `'a` and not
+/// aliased.
+unsafe fn deref_mut<'a, T>(pointer: *mut T) -> Result<&'a mut T, FfiError> {
+    pointer.as_mut().ok_or(FfiError::Argument(
+        ACID_ERROR_NULL_POINTER,
+        "A required argument was null.",
+    ))
+}
+
+/// Convert the null-terminated C string `string` to a `&str`.
+///
+/// # Safety
+/// If `string` is not null, it must point to a null-terminated string which is valid for the
+/// lifetime `'a`.
+unsafe fn to_str<'a>(string: *const c_char) -> Result<&'a str, FfiError> {
+    if string.is_null() {
+        return Err(FfiError::Argument(
+            ACID_ERROR_NULL_POINTER,
+            "A required argument was null.",
+        ));
+    }
+    CStr::from_ptr(string)
+        .to_str()
+        .map_err(|_| FfiError::Argument(ACID_ERROR_INVALID_UTF8, "A string was not valid UTF-8."))
+}
+
+/// Return a slice of the `len` bytes at `data`.
+///
+/// # Safety
+/// If `len` is not zero, `data` must be valid for reads of `len` bytes for the lifetime `'a`.
+unsafe fn to_slice<'a>(data: *const u8, len: usize) -> Result<&'a [u8], FfiError> {
+    if len == 0 {
+        return Ok(&[]);
+    }
+    if data.is_null() {
+        return Err(FfiError::Argument(
+            ACID_ERROR_NULL_POINTER,
+            "A required argument was null.",
+        ));
+    }
+    Ok(slice::from_raw_parts(data, len))
+}
+
+/// Open a repository with the given store `config` and store it in `repo`.
+///
+/// # Safety
+/// See [`acid_repo_open_directory`].
+unsafe fn open_repo(
+    config: &impl OpenStore,
+    mode: i32,
+    password: *const u8,
+    password_len: usize,
+    repo: *mut *mut AcidRepo,
+) -> Result<(), FfiError> {
+    let repo = deref_mut(repo)?;
+    let mode = match mode {
+        ACID_MODE_OPEN => OpenMode::Open,
+        ACID_MODE_CREATE => OpenMode::Create,
+        ACID_MODE_CREATE_NEW => OpenMode::CreateNew,
+        _ => {
+            return Err(FfiError::Argument(
+                ACID_ERROR_INVALID_ARGUMENT,
+                "The open mode is invalid.",
+            ))
+        }
+    };
+
+    let mut options = OpenOptions::new();
+    options.mode(mode);
+
+    if !password.is_null() {
+        let password = to_slice(password, password_len)?;
+        #[cfg(feature = "encryption")]
+        options.encryption(Encryption::XChaCha20Poly1305);
+        options.password(password);
+    }
+
+    let key_repo: KeyRepo<String> = options.open(config)?;
+    *repo = Box::into_raw(Box::new(AcidRepo(key_repo)));
+
+    Ok(())
+}
+
+/// Open a repository stored in the directory at `path`.
+///
+/// `mode` is one of the `ACID_MODE_*` constants. If `password` is not null, the repository is
+/// encrypted with the `password_len` bytes at `password` when it is created, and the password is
+/// used to decrypt it when it is opened. On success, a new handle is stored in `repo`.
+///
+/// # Safety
+/// - `path` must be a null-terminated string.
+/// - `password` must be null or valid for reads of `password_len` bytes.
+/// - `repo` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn acid_repo_open_directory(
+    path: *const c_char,
+    mode: i32,
+    password: *const u8,
+    password_len: usize,
+    repo: *mut *mut AcidRepo,
+) -> i32 {
+    handle(|| {
+        let config = DirectoryConfig {
+            path: to_str(path)?.into(),
+            durability: Default::default(),
+        };
+        open_repo(&config, mode, password, password_len, repo)
+    })
+}
+
+/// Create a new repository which is stored in memory.
+///
+/// The repository is discarded when it is closed. This is useful for testing. On success, a new
+/// handle is stored in `repo`.
+///
+/// # Safety
+/// `repo` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn acid_repo_open_memory(repo: *mut *mut AcidRepo) -> i32 {
+    handle(|| {
+        open_repo(
+            &MemoryConfig::new(),
+            ACID_MODE_CREATE_NEW,
+            ptr::null(),
+            0,
+            repo,
+        )
+    })
+}
+
+/// Close the given `repo` and free its handle.
+///
+/// Changes which have not been committed are discarded. Passing null does nothing.
+///
+/// # Safety
+/// `repo` must be null or a handle returned by an `acid_repo_open_*` function which has not
+/// already been closed.
+#[no_mangle]
+pub unsafe extern "C" fn acid_repo_close(repo: *mut AcidRepo) {
+    if !repo.is_null() {
+        drop(Box::from_raw(repo));
+    }
+}
+
+/// Replace the contents of the object at `key` with the `len` bytes at `data`.
+///
+/// This creates the object if it doesn't exist.
+///
+/// # Safety
+/// - `repo` must be a valid handle.
+/// - `key` must be a null-terminated string.
+/// - `data` must be valid for reads of `len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn acid_repo_write(
+    repo: *mut AcidRepo,
+    key: *const c_char,
+    data: *const u8,
+    len: usize,
+) -> i32 {
+    handle(|| {
+        let AcidRepo(repo) = deref_mut(repo)?;
+        let key = to_str(key)?;
+        let data = to_slice(data, len)?;
+
+        let mut object = repo.insert(key.to_owned());
+        object.write_all(data)?;
+        object.commit()?;
+
+        Ok(())
+    })
+}
+
+/// Read the contents of the object at `key`.
+///
+/// On success, a pointer to a new buffer is stored in `data` and its length is stored in `len`.
+/// The buffer must be freed with [`acid_buffer_free`]. If there is no object at `key`, this
+/// returns `Error::NotFound`.
+///
+/// # Safety
+/// - `repo` must be a valid handle.
+/// - `key` must be a null-terminated string.
+/// - `data` and `len` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn acid_repo_read(
+    repo: *const AcidRepo,
+    key: *const c_char,
+    data: *mut *mut u8,
+    len: *mut usize,
+) -> i32 {
+    handle(|| {
+        let AcidRepo(repo) = deref(repo)?;
+        let key = to_str(key)?;
+        let data = deref_mut(data)?;
+        let len = deref_mut(len)?;
+
+        let mut object = repo.object(key).ok_or(acid_store::Error::NotFound)?;
+        let mut contents = Vec::new();
+        object.read_to_end(&mut contents)?;
+
+        let contents = contents.into_boxed_slice();
+        *len = contents.len();
+        *data = Box::into_raw(contents) as *mut u8;
+
+        Ok(())
+    })
+}
+
+/// Free a buffer returned by [`acid_repo_read`].
+///
+/// Passing null does nothing.
+///
+/// # Safety
+/// `data` must be null or a buffer returned by [`acid_repo_read`] which has not already been
+/// freed, and `len` must be the length which was returned with it.
+#[no_mangle]
+pub unsafe extern "C" fn acid_buffer_free(data: *mut u8, len: usize) {
+    if !data.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(data, len)));
+    }
+}
+
+/// Store whether there is an object at `key` in `contains`.
+///
+/// # Safety
+/// - `repo` must be a valid handle.
+/// - `key` must be a null-terminated string.
+/// - `contains` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn acid_repo_contains(
+    repo: *const AcidRepo,
+    key: *const c_char,
+    contains: *mut bool,
+) -> i32 {
+    handle(|| {
+        let AcidRepo(repo) = deref(repo)?;
+        *deref_mut(contains)? = repo.contains(to_str(key)?);
+        Ok(())
+    })
+}
+
+/// Remove the object at `key`.
+///
+/// If there is no object at `key`, this returns `Error::NotFound`.
+///
+/// # Safety
+/// - `repo` must be a valid handle.
+/// - `key` must be a null-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn acid_repo_remove(repo: *mut AcidRepo, key: *const c_char) -> i32 {
+    handle(|| {
+        let AcidRepo(repo) = deref_mut(repo)?;
+        if repo.remove(to_str(key)?) {
+            Ok(())
+        } else {
+            Err(acid_store::Error::NotFound.into())
+        }
+    })
+}
+
+/// Commit changes to the repository.
+///
+/// # Safety
+/// `repo` must be a valid handle.
+#[no_mangle]
+pub unsafe extern "C" fn acid_repo_commit(repo: *mut AcidRepo) -> i32 {
+    handle(|| {
+        let AcidRepo(repo) = deref_mut(repo)?;
+        Ok(repo.commit()?)
+    })
+}
+
+/// Roll back changes made since the last commit.
+///
+/// # Safety
+/// `repo` must be a valid handle.
+#[no_mangle]
+pub unsafe extern "C" fn acid_repo_rollback(repo: *mut AcidRepo) -> i32 {
+    handle(|| {
+        let AcidRepo(repo) = deref_mut(repo)?;
+        Ok(repo.rollback()?)
+    })
+}
+
+/// Return a message describing the last error which occurred on the calling thread.
+///
+/// This returns null if no error has occurred. The string is owned by the library and is valid
+/// until the next function in this library fails on the same thread.
+#[no_mangle]
+pub extern "C" fn acid_error_message() -> *const c_char {
+    LAST_ERROR.with(|last_error| match &*last_error.borrow() {
+        Some(message) => message.as_ptr(),
+        None => ptr::null(),
+    })
+}
@@ -0,0 +1,113 @@
+use std::ffi::{CStr, CString};
+use std::ptr;
+use std::slice;
+
+use acid_store_ffi::*;
+use spectral::prelude::*;
+
+/// Read the object at `key` through the C interface.
+fn read(repo: *const AcidRepo, key: &CStr) -> Result<Vec<u8>, i32> {
+    let mut data = ptr::null_mut();
+    let mut len = 0;
+    let status = unsafe { acid_repo_read(repo, key.as_ptr(), &mut data, &mut len) };
+    if status != ACID_OK {
+        return Err(status);
+    }
+
+    let contents = unsafe { slice::from_raw_parts(data, len) }.to_vec();
+    unsafe { acid_buffer_free(data, len) };
+    Ok(contents)
+}
+
+#[test]
+fn write_then_read_object() {
+    let key = CString::new("key").unwrap();
+    let mut repo = ptr::null_mut();
+
+    unsafe {
+        assert_that!(acid_repo_open_memory(&mut repo)).is_equal_to(ACID_OK);
+        assert_that!(acid_repo_write(repo, key.as_ptr(), b"data".as_ptr(), 4)).is_equal_to(ACID_OK);
+    }
+
+    assert_that!(read(repo, &key)).is_ok_containing(b"data".to_vec());
+
+    unsafe { acid_repo_close(repo) };
+}
+
+#[test]
+fn reading_missing_object_returns_error_code() {
+    let key = CString::new("missing").unwrap();
+    let mut repo = ptr::null_mut();
+    unsafe { acid_repo_open_memory(&mut repo) };
+
+    assert_that!(read(repo, &key)).is_err_containing(acid_store::Error::NotFound.code() as i32);
+    let message = unsafe { CStr::from_ptr(acid_error_message()) };
+    assert_that!(message.to_str().unwrap())
+        .is_equal_to(acid_store::Error::NotFound.to_string().as_str());
+
+    unsafe { acid_repo_close(repo) };
+}
+
+#[test]
+fn null_arguments_are_rejected() {
+    let mut repo = ptr::null_mut();
+    unsafe { acid_repo_open_memory(&mut repo) };
+
+    let status = unsafe { acid_repo_write(repo, ptr::null(), ptr::null(), 0) };
+    assert_that!(status).is_equal_to(ACID_ERROR_NULL_POINTER);
+
+    unsafe { acid_repo_close(repo) };
+}
+
+#[test]
+fn remove_object() {
+    let key = CString::new("key").unwrap();
+    let mut repo = ptr::null_mut();
+    let mut contains = true;
+
+    unsafe {
+        acid_repo_open_memory(&mut repo);
+        acid_repo_write(repo, key.as_ptr(), b"data".as_ptr(), 4);
+        assert_that!(acid_repo_remove(repo, key.as_ptr())).is_equal_to(ACID_OK);
+        assert_that!(acid_repo_contains(repo, key.as_ptr(), &mut contains)).is_equal_to(ACID_OK);
+        acid_repo_close(repo);
+    }
+
+    assert_that!(contains).is_false();
+}
+
+#[test]
+fn committed_changes_persist_after_reopening() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let path = CString::new(temp_dir.path().join("store").to_str().unwrap()).unwrap();
+    let key = CString::new("key").unwrap();
+    let password = b"password";
+    let mut repo = ptr::null_mut();
+
+    unsafe {
+        let status = acid_repo_open_directory(
+            path.as_ptr(),
+            ACID_MODE_CREATE_NEW,
+            password.as_ptr(),
+            password.len(),
+            &mut repo,
+        );
+        assert_that!(status).is_equal_to(ACID_OK);
+        acid_repo_write(repo, key.as_ptr(), b"data".as_ptr(), 4);
+        assert_that!(acid_repo_commit(repo)).is_equal_to(ACID_OK);
+        acid_repo_close(repo);
+
+        let status = acid_repo_open_directory(
+            path.as_ptr(),
+            ACID_MODE_OPEN,
+            password.as_ptr(),
+            password.len(),
+            &mut repo,
+        );
+        assert_that!(status).is_equal_to(ACID_OK);
+    }
+
+    assert_that!(read(repo, &key)).is_ok_containing(b"data".to_vec());
+
+    unsafe { acid_repo_close(repo) };
+}
@@ -0,0 +1,451 @@
+//! A command-line tool for administering acid-store repositories.
+//!
+//! This operates on [`FileRepo`] repositories without special files or metadata by default, or on
+//! [`KeyRepo`] repositories with string keys when `--kind key` is passed.
+//!
+//! [`FileRepo`]: acid_store::repo::file::FileRepo
+//! [`KeyRepo`]: acid_store::repo::key::KeyRepo
+
+use std::env;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use acid_store::repo::file::{FileRepo, RelativePath};
+use acid_store::repo::key::KeyRepo;
+use acid_store::repo::{
+    peek_info, upgrade_repo, Commit, Compression, Encryption, LockInfo, OpenMode, OpenOptions,
+};
+#[cfg(feature = "store-sqlite")]
+use acid_store::store::SqliteConfig;
+use acid_store::store::{DataStore, DirectoryConfig, OpenStore};
+use clap::{Parser, Subcommand, ValueEnum};
+
+/// The environment variable which contains the password for encrypted repositories.
+const PASSWORD_VAR: &str = "ACID_STORE_PASSWORD";
+
+/// The prefix of a store location which refers to a SQLite database.
+#[cfg(feature = "store-sqlite")]
+const SQLITE_PREFIX: &str = "sqlite:";
+
+/// Administer acid-store repositories.
+#[derive(Debug, Parser)]
+#[command(name = "acid-store", version)]
+struct Cli {
+    /// The location of the data store containing the repository.
+    ///
+    /// This is the path of a directory, or `sqlite:<path>` for a SQLite database.
+    #[arg(short, long)]
+    store: String,
+
+    /// The type of the repository.
+    #[arg(short, long, value_enum, default_value_t = RepoKind::File)]
+    kind: RepoKind,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+/// The type of a repository.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum RepoKind {
+    /// A virtual file system.
+    File,
+
+    /// An object store with string keys.
+    Key,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Create a new repository.
+    Create {
+        /// Encrypt the repository with a password.
+        ///
+        /// The password is read from the ACID_STORE_PASSWORD environment variable or prompted for.
+        #[arg(long)]
+        encrypt: bool,
+
+        /// Compress the data in the repository.
+        #[arg(long)]
+        compress: bool,
+    },
+
+    /// Show information about a repository without opening it.
+    Info,
+
+    /// List the files or keys in a repository.
+    List {
+        /// The directory to list the descendants of, for file repositories.
+        path: Option<String>,
+    },
+
+    /// Copy a directory tree from the file system into a file repository.
+    Archive {
+        /// The path of the directory tree in the file system.
+        source: PathBuf,
+
+        /// The path to copy it to in the repository.
+        dest: String,
+    },
+
+    /// Copy a tree of entries from a file repository into the file system.
+    Extract {
+        /// The path of the tree in the repository.
+        source: String,
+
+        /// The path to copy it to in the file system.
+        dest: PathBuf,
+    },
+
+    /// Mount a file repository as a FUSE file system until it is unmounted.
+    #[cfg(feature = "fuse-mount")]
+    Mount {
+        /// The directory to mount the repository at.
+        mountpoint: PathBuf,
+    },
+
+    /// Verify the integrity of all the data in a repository.
+    Verify,
+
+    /// Reclaim space in the data store which is no longer used by the repository.
+    Clean,
+
+    /// Change the password of an encrypted repository.
+    ChangePassword,
+
+    /// Upgrade a repository created by an older version of this tool to the current format.
+    ///
+    /// Older versions will not be able to open the repository once it has been upgraded.
+    Upgrade,
+
+    /// Remove an existing lock on a repository.
+    ///
+    /// This is only safe if the process which locked the repository is no longer running.
+    /// Concurrent access to a repository can cause data loss.
+    Unlock {
+        /// Confirm that no other process is using the repository.
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+/// The configuration for a data store given on the command line.
+#[derive(Debug)]
+enum StoreConfig {
+    Directory(DirectoryConfig),
+    #[cfg(feature = "store-sqlite")]
+    Sqlite(SqliteConfig),
+}
+
+impl StoreConfig {
+    /// Parse the store `location` given on the command line.
+    fn parse(location: &str) -> Self {
+        #[cfg(feature = "store-sqlite")]
+        if let Some(path) = location.strip_prefix(SQLITE_PREFIX) {
+            return StoreConfig::Sqlite(SqliteConfig::new(path));
+        }
+
+        StoreConfig::Directory(DirectoryConfig {
+            path: PathBuf::from(location),
+            durability: Default::default(),
+        })
+    }
+}
+
+impl OpenStore for StoreConfig {
+    type Store = Box<dyn DataStore>;
+
+    fn open(&self) -> acid_store::Result<Self::Store> {
+        Ok(match self {
+            StoreConfig::Directory(config) => Box::new(config.open()?),
+            #[cfg(feature = "store-sqlite")]
+            StoreConfig::Sqlite(config) => Box::new(config.open()?),
+        })
+    }
+}
+
+/// A repository of any of the types supported by this tool.
+enum Repo {
+    File(FileRepo),
+    Key(KeyRepo<String>),
+}
+
+impl Repo {
+    /// Open the repository in `config` with the given `options`.
+    fn open(
+        kind: RepoKind,
+        options: &mut OpenOptions,
+        config: &StoreConfig,
+    ) -> acid_store::Result<Self> {
+        Ok(match kind {
+            RepoKind::File => Repo::File(options.open(config)?),
+            RepoKind::Key => Repo::Key(options.open(config)?),
+        })
+    }
+
+    /// Return the file repository, failing if this is not a file repository.
+    fn file(&mut self) -> Result<&mut FileRepo, String> {
+        match self {
+            Repo::File(repo) => Ok(repo),
+            Repo::Key(_) => Err(String::from(
+                "This command is only supported for file repositories.",
+            )),
+        }
+    }
+
+    fn commit(&mut self) -> acid_store::Result<()> {
+        match self {
+            Repo::File(repo) => repo.commit(),
+            Repo::Key(repo) => repo.commit(),
+        }
+    }
+}
+
+/// Return the password from the environment or prompt the user for it.
+fn read_password(prompt: &str) -> Result<Vec<u8>, String> {
+    if let Ok(password) = env::var(PASSWORD_VAR) {
+        return Ok(password.into_bytes());
+    }
+    rpassword::prompt_password(prompt)
+        .map(String::into_bytes)
+        .map_err(|error| error.to_string())
+}
+
+/// Return the password for the repository in `config` if it is encrypted.
+fn repo_password(config: &StoreConfig) -> Result<Option<Vec<u8>>, String> {
+    let info = match peek_info(config) {
+        Ok(info) => info,
+        // The repository metadata is encrypted, so the repository must be encrypted too.
+        Err(acid_store::Error::Password) => return read_password("Password: ").map(Some),
+        Err(error) => return Err(error.to_string()),
+    };
+    if info.needs_upgrade() {
+        return Err(String::from(
+            "This repository uses a previous format version. Run `acid-store upgrade` to upgrade \
+            it.",
+        ));
+    }
+    if info.config().encryption == Encryption::None {
+        Ok(None)
+    } else {
+        read_password("Password: ").map(Some)
+    }
+}
+
+fn run(cli: Cli) -> Result<(), String> {
+    let config = StoreConfig::parse(&cli.store);
+
+    // These commands don't open an existing repository.
+    match &cli.command {
+        Command::Create { encrypt, compress } => {
+            let password = if *encrypt {
+                Some(read_password("New password: ")?)
+            } else {
+                None
+            };
+
+            let mut options = OpenOptions::new();
+            options.mode(OpenMode::CreateNew);
+            if let Some(password) = &password {
+                options.encryption(Encryption::XChaCha20Poly1305);
+                options.password(password);
+            }
+            if *compress {
+                options.compression(Compression::Lz4 { level: 1 });
+            }
+
+            let mut repo = Repo::open(cli.kind, &mut options, &config).map_err(error_string)?;
+            return repo.commit().map_err(error_string);
+        }
+        Command::Info => {
+            let info =
+                match peek_info(&config) {
+                    Ok(info) => info,
+                    Err(acid_store::Error::Password) => return Err(String::from(
+                        "The repository metadata is encrypted and can't be read without opening \
+                        the repository.",
+                    )),
+                    Err(error) => return Err(error_string(error)),
+                };
+            let repo_config = info.config();
+            println!("ID:           {}", info.id().as_ref().as_hyphenated());
+            if let Some(fingerprint) = info.fingerprint() {
+                println!("Fingerprint:  {}", fingerprint);
+            }
+            if info.needs_upgrade() {
+                println!(
+                    "Format:       {} (needs upgrade)",
+                    info.format_version().as_hyphenated()
+                );
+            } else {
+                println!("Format:       {}", info.format_version().as_hyphenated());
+            }
+            match repo_config.preset {
+                Some(preset) => println!("Preset:       {:?}", preset),
+                None => println!("Preset:       none"),
+            }
+            println!("Chunking:     {:?}", repo_config.chunking);
+            println!("Packing:      {:?}", repo_config.packing);
+            println!("Compression:  {:?}", repo_config.compression);
+            println!("Encryption:   {:?}", repo_config.encryption);
+            match repo_config.quota {
+                Some(quota) => println!("Quota:        {} bytes", quota),
+                None => println!("Quota:        none"),
+            }
+            return Ok(());
+        }
+        Command::Upgrade => {
+            if upgrade_repo(&config).map_err(error_string)? {
+                println!("The repository was upgraded.");
+            } else {
+                println!("The repository is already up to date.");
+            }
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    let password = repo_password(&config)?;
+    let mut options = OpenOptions::new();
+    options.lock_description("acid-store");
+    if let Some(password) = &password {
+        options.password(password);
+    }
+    if let Command::Unlock { force } = &cli.command {
+        if !force {
+            return Err(String::from(
+                "Removing a lock can cause data loss. Pass --force if no other process is using the \
+                repository.",
+            ));
+        }
+        options.locking(&[], |context| {
+            if let Some(info) = LockInfo::from_context(context) {
+                println!(
+                    "Removing the lock held by {} (PID {}) on {}.",
+                    info.description(),
+                    info.pid(),
+                    info.hostname()
+                );
+            }
+            true
+        });
+    }
+
+    let mut repo = Repo::open(cli.kind, &mut options, &config).map_err(error_string)?;
+
+    match cli.command {
+        Command::Create { .. } | Command::Info | Command::Upgrade => unreachable!(),
+        Command::List { path } => match &repo {
+            Repo::File(repo) => {
+                let parent = path.unwrap_or_default();
+                for entry_path in repo.descendants(&parent).map_err(error_string)? {
+                    if repo.is_directory(&entry_path) {
+                        println!("{}/", entry_path);
+                    } else {
+                        println!("{}", entry_path);
+                    }
+                }
+            }
+            Repo::Key(repo) => {
+                let mut keys = repo.keys().collect::<Vec<_>>();
+                keys.sort();
+                for key in keys {
+                    println!("{}", key);
+                }
+            }
+        },
+        Command::Archive { source, dest } => {
+            let file_repo = repo.file()?;
+            file_repo
+                .archive_tree(source, RelativePath::new(&dest))
+                .map_err(error_string)?;
+            repo.commit().map_err(error_string)?;
+        }
+        Command::Extract { source, dest } => {
+            repo.file()?
+                .extract_tree(RelativePath::new(&source), dest)
+                .map_err(error_string)?;
+        }
+        #[cfg(feature = "fuse-mount")]
+        Command::Mount { mountpoint } => {
+            repo.file()?
+                .mount(mountpoint, RelativePath::new(""), &[])
+                .map_err(error_string)?;
+            repo.commit().map_err(error_string)?;
+        }
+        Command::Verify => {
+            let corrupt = match &repo {
+                Repo::File(repo) => repo
+                    .verify()
+                    .map_err(error_string)?
+                    .into_iter()
+                    .map(|path| path.to_string())
+                    .collect::<Vec<_>>(),
+                Repo::Key(repo) => repo
+                    .verify()
+                    .map_err(error_string)?
+                    .into_iter()
+                    .cloned()
+                    .collect::<Vec<_>>(),
+            };
+            if !corrupt.is_empty() {
+                for name in &corrupt {
+                    println!("{}", name);
+                }
+                return Err(format!("Found {} corrupt entries.", corrupt.len()));
+            }
+        }
+        Command::Clean => match &mut repo {
+            Repo::File(repo) => repo.clean().map_err(error_string)?,
+            Repo::Key(repo) => repo.clean().map_err(error_string)?,
+        },
+        Command::ChangePassword => {
+            if password.is_none() {
+                return Err(String::from("This repository is not encrypted."));
+            }
+            let new_password = read_password("New password: ")?;
+            match &mut repo {
+                Repo::File(repo) => {
+                    let info = repo.info();
+                    let repo_config = info.config();
+                    repo.change_password(
+                        &new_password,
+                        repo_config.memory_limit,
+                        repo_config.operations_limit,
+                    );
+                }
+                Repo::Key(repo) => {
+                    let info = repo.info();
+                    let repo_config = info.config();
+                    repo.change_password(
+                        &new_password,
+                        repo_config.memory_limit,
+                        repo_config.operations_limit,
+                    );
+                }
+            }
+            repo.commit().map_err(error_string)?;
+        }
+        Command::Unlock { .. } => {
+            // The existing lock was removed when the repository was opened, and the new lock is
+            // released when the repository is dropped.
+        }
+    }
+
+    Ok(())
+}
+
+/// Format the given `error` for the command line.
+fn error_string(error: acid_store::Error) -> String {
+    error.to_string()
+}
+
+fn main() -> ExitCode {
+    match run(Cli::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("error: {}", message);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -3,11 +3,22 @@ use std::result;
 
 use thiserror::Error as DeriveError;
 
+use crate::store::ErrorContext;
+
 /// The error type for operations with a repository.
 ///
 /// This type can be converted `From` and `Into` an `io::Error` for compatibility with types from
 /// `std::io` like `Read`, `Write`, and `Seek`. Even if the payload of the `io::Error` cannot be
 /// downcast to a value of this type, it will be converted to `Error::Io`.
+///
+/// Each variant has a stable error [`code`] and [`code_name`] which can be used for logging or
+/// across an FFI boundary. Errors which originate in the data store also carry an
+/// [`ErrorContext`] describing which operation failed, which is available via [`context`].
+///
+/// [`code`]: crate::Error::code
+/// [`code_name`]: crate::Error::code_name
+/// [`ErrorContext`]: crate::store::ErrorContext
+/// [`context`]: crate::Error::context
 #[derive(Debug, DeriveError)]
 #[non_exhaustive]
 pub enum Error {
@@ -55,6 +66,10 @@ pub enum Error {
     #[error("A transaction is currently in progress for this object.")]
     TransactionInProgress,
 
+    /// The operation would exceed the quota for this repository instance.
+    #[error("The operation would exceed the quota for this repository instance.")]
+    QuotaExceeded,
+
     /// This file type is not supported.
     #[error("This file type is not supported.")]
     FileType,
@@ -87,6 +102,18 @@ pub enum Error {
     #[error("Ciphertext verification failed or data is otherwise invalid.")]
     InvalidData,
 
+    /// The operation was cancelled.
+    #[error("The operation was cancelled.")]
+    Cancelled,
+
+    /// The repository configuration is invalid.
+    #[error("The repository configuration is invalid.")]
+    InvalidConfig,
+
+    /// The operation would exceed a configured size limit.
+    #[error("The operation would exceed a configured size limit.")]
+    LimitExceeded,
+
     /// An I/O error occurred.
     #[error("{0}")]
     Io(io::Error),
@@ -98,6 +125,89 @@ pub enum Error {
     Store(crate::store::Error),
 }
 
+impl Error {
+    /// Return a stable numeric code which identifies the variant of this error.
+    ///
+    /// These codes will not change between versions. New variants will be assigned new codes.
+    pub fn code(&self) -> u32 {
+        match self {
+            Error::AlreadyExists => 1,
+            Error::NotFound => 2,
+            Error::Password => 3,
+            Error::Locked => 4,
+            Error::NotLocked => 5,
+            Error::Corrupt => 6,
+            Error::UnsupportedStore => 7,
+            Error::UnsupportedRepo => 8,
+            Error::InvalidSavepoint => 9,
+            Error::InvalidObject => 10,
+            Error::TransactionInProgress => 11,
+            Error::QuotaExceeded => 12,
+            Error::FileType => 13,
+            Error::InvalidPath => 14,
+            Error::NotEmpty => 15,
+            Error::NotDirectory => 16,
+            Error::NotFile => 17,
+            Error::Serialize => 18,
+            Error::Deserialize => 19,
+            Error::InvalidData => 20,
+            Error::Io(_) => 21,
+            Error::Store(_) => 22,
+            Error::Cancelled => 23,
+            Error::InvalidConfig => 24,
+            Error::LimitExceeded => 25,
+        }
+    }
+
+    /// Return a stable string code which identifies the variant of this error.
+    ///
+    /// These codes will not change between versions. New variants will be assigned new codes.
+    pub fn code_name(&self) -> &'static str {
+        match self {
+            Error::AlreadyExists => "already_exists",
+            Error::NotFound => "not_found",
+            Error::Password => "password",
+            Error::Locked => "locked",
+            Error::NotLocked => "not_locked",
+            Error::Corrupt => "corrupt",
+            Error::UnsupportedStore => "unsupported_store",
+            Error::UnsupportedRepo => "unsupported_repo",
+            Error::InvalidSavepoint => "invalid_savepoint",
+            Error::InvalidObject => "invalid_object",
+            Error::TransactionInProgress => "transaction_in_progress",
+            Error::QuotaExceeded => "quota_exceeded",
+            Error::FileType => "file_type",
+            Error::InvalidPath => "invalid_path",
+            Error::NotEmpty => "not_empty",
+            Error::NotDirectory => "not_directory",
+            Error::NotFile => "not_file",
+            Error::Serialize => "serialize",
+            Error::Deserialize => "deserialize",
+            Error::InvalidData => "invalid_data",
+            Error::Io(_) => "io",
+            Error::Store(_) => "store",
+            Error::Cancelled => "cancelled",
+            Error::InvalidConfig => "invalid_config",
+            Error::LimitExceeded => "limit_exceeded",
+        }
+    }
+
+    /// Return information about the data store operation which caused this error.
+    ///
+    /// This returns `None` if the error didn't originate in the data store or the context is not
+    /// known.
+    pub fn context(&self) -> Option<&ErrorContext> {
+        match self {
+            Error::Store(error) => error.context(),
+            Error::Io(error) => error
+                .get_ref()
+                .and_then(|payload| payload.downcast_ref::<crate::store::Error>())
+                .and_then(|store_error| store_error.context()),
+            _ => None,
+        }
+    }
+}
+
 impl From<Error> for io::Error {
     fn from(error: Error) -> Self {
         io::Error::new(io::ErrorKind::Other, error)
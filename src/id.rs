@@ -76,6 +76,22 @@ impl IdTable {
         id <= self.highest && !self.unused.contains(&id)
     }
 
+    /// Mark the given `id` as used so that it is never returned by `next`.
+    ///
+    /// This returns `true` if the value was reserved or `false` if it was already used.
+    pub fn reserve(&mut self, id: u64) -> bool {
+        if self.contains(id) {
+            return false;
+        }
+        if id > self.highest {
+            self.unused.extend(self.highest + 1..id);
+            self.highest = id;
+        } else {
+            self.unused.remove(&id);
+        }
+        true
+    }
+
     /// Return the given `id` back to the table.
     ///
     /// This returns `true` if the value was returned or `false` if it was unused.
@@ -116,6 +132,20 @@ macro_rules! id_table {
                 $id_name(self.0.next())
             }
 
+            /// Return whether the given `id` is in the table.
+            #[allow(dead_code)]
+            pub fn contains(&self, id: $id_name) -> bool {
+                self.0.contains(id.0)
+            }
+
+            /// Mark the given `id` as used so that it is never returned by `next`.
+            ///
+            /// This returns `true` if the value was reserved or `false` if it was already used.
+            #[allow(dead_code)]
+            pub fn reserve(&mut self, id: $id_name) -> bool {
+                self.0.reserve(id.0)
+            }
+
             /// Return the given `id` back to the table.
             ///
             /// This returns `true` if the value was returned or `false` if it was unused.
@@ -11,6 +11,9 @@
 //! - [`FileRepo`] is a virtual file system which can be mounted via FUSE and supports file
 //! metadata, special files, sparse files, hard links, and importing and exporting files to the
 //! local file system.
+//! - [`SessionRepo`] is a persistent key-value store where values expire after a period of time.
+//! - [`SingleObjectRepo`] stores a single binary blob without exposing keys or instances.
+//! - [`SnapshotRepo`] is an object store which supports named, repository-wide snapshots.
 //! - [`StateRepo`] is a low-level repository type which can be used to implement higher-level
 //! repository types.
 //!
@@ -19,14 +22,19 @@
 //! be found in the [`crate::store`] module.
 //!
 //! - [`DirectoryStore`] stores data in a directory in the local file system.
+//! - [`ShardedDirectoryStore`] spreads data across several directories in the local file system.
 //! - [`SqliteStore`] stores data in a SQLite database.
 //! - [`RedisStore`] stores data on a Redis server.
 //! - [`S3Store`] stores data in an Amazon S3 bucket.
 //! - [`SftpStore`] stores data on an SFTP server.
 //! - [`RcloneStore`] stores data in a varity of cloud storage backends using
 //! [rclone].
+//! - [`WebDavStore`] stores data on a WebDAV server, like Nextcloud or ownCloud.
 //! - [`MemoryStore`] stores data in memory.
 //!
+//! Any data store can also be wrapped in a [`RetryStore`] to retry operations which fail
+//! transiently, or a [`ThrottleStore`] to limit the bandwidth it uses.
+//!
 //! # Examples
 //!
 //! ```
@@ -72,10 +80,13 @@
 //!
 //! These features enable different repository types.
 //!
-//! Feature        | Description
-//! ---            | ---
-//! `repo-value`   | Use the [`ValueRepo`] repository type
-//! `repo-file`    | Use the [`FileRepo`] repository type
+//! Feature         | Description
+//! ---             | ---
+//! `repo-value`    | Use the [`ValueRepo`] repository type
+//! `repo-file`     | Use the [`FileRepo`] repository type
+//! `repo-session`  | Use the [`SessionRepo`] repository type
+//! `repo-single`   | Use the [`SingleObjectRepo`] repository type
+//! `repo-snapshot` | Use the [`SnapshotRepo`] repository type
 //!
 //! These features enable different [`DataStore`] implementations.
 //!
@@ -87,6 +98,7 @@
 //! `store-s3`        | Store data in an Amazon S3 bucket
 //! `store-sftp`      | Store data on an SFTP server
 //! `store-rclone`    | Store data in cloud storage via [rclone]
+//! `store-webdav`    | Store data on a WebDAV server
 //!
 //! These features enable additional functionality.
 //!
@@ -94,8 +106,13 @@
 //! ---               | ---
 //! `encryption`      | Encrypt repositories
 //! `compression`     | Compress repositories
+//! `format-cbor`     | Serialize values as CBOR with [`Cbor`]
+//! `format-json`     | Serialize values as JSON with [`Json`]
 //! `file-metadata`   | Store file metadata and special file types in [`FileRepo`]
 //! `fuse-mount`      | Mount a [`FileRepo`] as a FUSE file system
+//! `observability`   | Emit [`tracing`] spans and collect [`Metrics`]
+//! `testing`         | Use the test helpers in [`crate::testing`]
+//! `cli`             | Build the `acid-store` command-line tool for administering repositories
 //!
 //! These features have native dependencies. This table shows their package names on Ubuntu.
 //!
@@ -105,20 +122,31 @@
 //! `fuse-mount`    | `libfuse3-dev`, `pkg-config` | `fuse3`
 //!
 //! [rclone]: https://rclone.org/
+//! [`tracing`]: https://docs.rs/tracing
 //!
 //! [`KeyRepo`]: crate::repo::key
+//! [`Metrics`]: crate::repo::Metrics
+//! [`Cbor`]: crate::repo::Cbor
+//! [`Json`]: crate::repo::Json
 //! [`FileRepo`]: crate::repo::file
 //! [`ValueRepo`]: crate::repo::value
+//! [`SessionRepo`]: crate::repo::session
+//! [`SingleObjectRepo`]: crate::repo::single
+//! [`SnapshotRepo`]: crate::repo::snapshot
 //! [`StateRepo`]: crate::repo::state
 //!
 //! [`DataStore`]: crate::store::DataStore
 //! [`DirectoryStore`]: crate::store::DirectoryStore
+//! [`ShardedDirectoryStore`]: crate::store::ShardedDirectoryStore
 //! [`SqliteStore`]: crate::store::SqliteStore
 //! [`RedisStore`]: crate::store::RedisStore
 //! [`S3Store`]: crate::store::S3Store
 //! [`SftpStore`]: crate::store::SftpStore
 //! [`RcloneStore`]: crate::store::RcloneStore
+//! [`WebDavStore`]: crate::store::WebDavStore
 //! [`MemoryStore`]: crate::store::MemoryStore
+//! [`RetryStore`]: crate::store::RetryStore
+//! [`ThrottleStore`]: crate::store::ThrottleStore
 
 #![forbid(unsafe_code)]
 
@@ -130,3 +158,6 @@ mod error;
 mod id;
 pub mod repo;
 pub mod store;
+#[cfg(feature = "testing")]
+#[cfg_attr(docsrs, doc(cfg(feature = "testing")))]
+pub mod testing;
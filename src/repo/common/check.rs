@@ -0,0 +1,170 @@
+use std::collections::HashSet;
+
+use super::handle::{Chunk, HandleId};
+use super::state::InstanceId;
+
+/// The inconsistencies found in a repository by [`KeyRepo::check`].
+///
+/// Some inconsistencies only waste space or memory and can be fixed with [`KeyRepo::repair`].
+/// Others mean that data has been lost, and these are reported as the objects and instances which
+/// are damaged.
+///
+/// [`KeyRepo::check`]: crate::repo::key::KeyRepo::check
+/// [`KeyRepo::repair`]: crate::repo::key::KeyRepo::repair
+#[derive(Debug, Clone)]
+pub struct CheckReport<K> {
+    pub(super) orphaned_chunks: usize,
+    pub(super) stale_references: usize,
+    pub(super) missing_references: usize,
+    pub(super) unallocated_handles: usize,
+    pub(super) missing_chunks: usize,
+    pub(super) missing_blocks: usize,
+    pub(super) damaged_objects: HashSet<K>,
+    pub(super) damaged_instances: HashSet<InstanceId>,
+    pub(super) repaired: bool,
+}
+
+impl<K> CheckReport<K> {
+    /// The number of chunks which are not used by any object.
+    ///
+    /// The data for these chunks can't be reclaimed by [`Commit::clean`] until they are removed.
+    ///
+    /// [`Commit::clean`]: crate::repo::Commit::clean
+    pub fn orphaned_chunks(&self) -> usize {
+        self.orphaned_chunks
+    }
+
+    /// The number of references from chunks to objects which no longer exist.
+    pub fn stale_references(&self) -> usize {
+        self.stale_references
+    }
+
+    /// The number of chunks used by objects which don't record those objects as references.
+    ///
+    /// These chunks could be removed while they are still in use.
+    pub fn missing_references(&self) -> usize {
+        self.missing_references
+    }
+
+    /// The number of objects whose IDs aren't allocated in the repository.
+    ///
+    /// The IDs of these objects could be reused by new objects.
+    pub fn unallocated_handles(&self) -> usize {
+        self.unallocated_handles
+    }
+
+    /// The number of chunks used by objects which don't exist in the repository.
+    pub fn missing_chunks(&self) -> usize {
+        self.missing_chunks
+    }
+
+    /// The number of chunks whose data is missing from the data store.
+    ///
+    /// When packing is enabled, this includes chunks which don't have an entry in the pack map.
+    pub fn missing_blocks(&self) -> usize {
+        self.missing_blocks
+    }
+
+    /// The keys of objects in the current instance whose data is missing.
+    pub fn damaged_objects(&self) -> &HashSet<K> {
+        &self.damaged_objects
+    }
+
+    /// The IDs of other instances which have objects whose data is missing.
+    ///
+    /// This includes instances whose object map can't be read.
+    pub fn damaged_instances(&self) -> &HashSet<InstanceId> {
+        &self.damaged_instances
+    }
+
+    /// Whether the inconsistencies in this report were repaired.
+    ///
+    /// This is `true` for reports returned by [`KeyRepo::repair`].
+    ///
+    /// [`KeyRepo::repair`]: crate::repo::key::KeyRepo::repair
+    pub fn is_repaired(&self) -> bool {
+        self.repaired
+    }
+
+    /// Return whether no inconsistencies were found.
+    pub fn is_consistent(&self) -> bool {
+        self.orphaned_chunks == 0
+            && self.stale_references == 0
+            && self.missing_references == 0
+            && self.unallocated_handles == 0
+            && self.is_intact()
+    }
+
+    /// Return whether no data is missing.
+    ///
+    /// If this is `true`, every inconsistency in this report can be fixed by
+    /// [`KeyRepo::repair`].
+    ///
+    /// [`KeyRepo::repair`]: crate::repo::key::KeyRepo::repair
+    pub fn is_intact(&self) -> bool {
+        self.missing_chunks == 0
+            && self.missing_blocks == 0
+            && self.damaged_objects.is_empty()
+            && self.damaged_instances.is_empty()
+    }
+}
+
+/// The inconsistencies found in a repository, with enough detail to repair them.
+#[derive(Debug)]
+pub struct Inconsistencies<K> {
+    /// Chunks which are not used by any object.
+    pub orphaned_chunks: Vec<Chunk>,
+
+    /// References from chunks to objects which no longer exist.
+    pub stale_references: Vec<(Chunk, HandleId)>,
+
+    /// Chunks used by objects which don't record those objects as references.
+    pub missing_references: Vec<(Chunk, HandleId)>,
+
+    /// The IDs of objects which aren't allocated in the handle table.
+    pub unallocated_handles: Vec<HandleId>,
+
+    /// Chunks used by objects which don't exist.
+    pub missing_chunks: HashSet<Chunk>,
+
+    /// The number of chunks whose data is missing from the data store.
+    pub missing_blocks: usize,
+
+    /// The keys of objects in the current instance whose data is missing.
+    pub damaged_objects: HashSet<K>,
+
+    /// The IDs of other instances whose data is missing.
+    pub damaged_instances: HashSet<InstanceId>,
+}
+
+impl<K> Default for Inconsistencies<K> {
+    fn default() -> Self {
+        Self {
+            orphaned_chunks: Vec::new(),
+            stale_references: Vec::new(),
+            missing_references: Vec::new(),
+            unallocated_handles: Vec::new(),
+            missing_chunks: HashSet::new(),
+            missing_blocks: 0,
+            damaged_objects: HashSet::new(),
+            damaged_instances: HashSet::new(),
+        }
+    }
+}
+
+impl<K> Inconsistencies<K> {
+    /// Summarize these inconsistencies as a `CheckReport`.
+    pub fn into_report(self, repaired: bool) -> CheckReport<K> {
+        CheckReport {
+            orphaned_chunks: self.orphaned_chunks.len(),
+            stale_references: self.stale_references.len(),
+            missing_references: self.missing_references.len(),
+            unallocated_handles: self.unallocated_handles.len(),
+            missing_chunks: self.missing_chunks.len(),
+            missing_blocks: self.missing_blocks,
+            damaged_objects: self.damaged_objects,
+            damaged_instances: self.damaged_instances,
+            repaired,
+        }
+    }
+}
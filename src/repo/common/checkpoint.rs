@@ -0,0 +1,34 @@
+use std::collections::btree_map;
+use std::iter::{ExactSizeIterator, FusedIterator};
+
+use crate::store::BlockId;
+
+/// An iterator over the names of the checkpoints in a repository.
+///
+/// This value is created by [`KeyRepo::checkpoints`].
+///
+/// [`KeyRepo::checkpoints`]: crate::repo::key::KeyRepo::checkpoints
+#[derive(Debug, Clone)]
+pub struct Checkpoints<'a>(pub(super) btree_map::Keys<'a, String, BlockId>);
+
+impl<'a> Iterator for Checkpoints<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(String::as_str)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl<'a> DoubleEndedIterator for Checkpoints<'a> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.0.next_back().map(String::as_str)
+    }
+}
+
+impl<'a> FusedIterator for Checkpoints<'a> {}
+
+impl<'a> ExactSizeIterator for Checkpoints<'a> {}
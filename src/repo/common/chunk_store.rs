@@ -1,13 +1,14 @@
 use std::cmp::min;
 use std::collections::HashSet;
+use std::sync::Mutex;
+use std::thread;
 
-use uuid::Uuid;
-
+use super::encryption::Encryption;
 use super::handle::HandleId;
 use super::handle::{chunk_hash, Chunk};
 use super::packing::Packing;
 use super::state::{ChunkInfo, Pack, PackIndex, RepoState};
-use crate::store::{BlockId, BlockKey};
+use crate::store::{BlockId, BlockKey, DataStore};
 
 /// Encode and decode blocks of data.
 pub trait EncodeBlock {
@@ -16,11 +17,22 @@ pub trait EncodeBlock {
 
     /// Decrypt and decompress the given `data` and return it.
     fn decode_data(&self, data: &[u8]) -> crate::Result<Vec<u8>>;
+
+    /// Compress and encrypt the given `data` of the given `chunk`.
+    ///
+    /// If convergent encryption is enabled, this uses a key derived from the hash of `chunk`.
+    /// Otherwise, this is the same as `encode_data`.
+    fn encode_block(&self, chunk: &Chunk, data: &[u8]) -> crate::Result<Vec<u8>>;
+
+    /// Decrypt and decompress the given `data` of the given `chunk`.
+    fn decode_block(&self, chunk: &Chunk, data: &[u8]) -> crate::Result<Vec<u8>>;
 }
 
 impl EncodeBlock for RepoState {
     fn encode_data(&self, data: &[u8]) -> crate::Result<Vec<u8>> {
         let compressed_data = self.metadata.config.compression.compress(data)?;
+        #[cfg(feature = "observability")]
+        self.report_metrics(|metrics| metrics.data_compressed(data.len(), compressed_data.len()));
 
         Ok(self
             .metadata
@@ -41,6 +53,37 @@ impl EncodeBlock for RepoState {
             .compression
             .decompress(decrypted_data.as_slice())
     }
+
+    fn encode_block(&self, chunk: &Chunk, data: &[u8]) -> crate::Result<Vec<u8>> {
+        let block_key = match self.block_key(chunk) {
+            Some(block_key) => block_key,
+            None => return self.encode_data(data),
+        };
+
+        let compressed_data = self.metadata.config.compression.compress(data)?;
+        #[cfg(feature = "observability")]
+        self.report_metrics(|metrics| metrics.data_compressed(data.len(), compressed_data.len()));
+
+        Ok(self
+            .metadata
+            .config
+            .encryption
+            .encrypt_deterministic(compressed_data.as_slice(), &block_key))
+    }
+
+    fn decode_block(&self, chunk: &Chunk, data: &[u8]) -> crate::Result<Vec<u8>> {
+        let block_key = match self.block_key(chunk) {
+            Some(block_key) => block_key,
+            None => return self.decode_data(data),
+        };
+
+        let decrypted_data = self.metadata.config.encryption.decrypt(data, &block_key)?;
+
+        self.metadata
+            .config
+            .compression
+            .decompress(decrypted_data.as_slice())
+    }
 }
 
 /// Read and decode blocks of data.
@@ -57,13 +100,15 @@ pub trait WriteBlock: ReadBlock {
     ///
     /// If a block with the given `id` already exists, it is overwritten.
     ///
-    /// The data is encoded before it is written.
-    fn write_block(&mut self, id: BlockId, data: &[u8]) -> crate::Result<()>;
+    /// The data is encoded before it is written. This returns the size of the data after it was
+    /// compressed.
+    fn write_block(&mut self, id: BlockId, data: &[u8]) -> crate::Result<u32>;
 }
 
 struct PackingBlockReader<'a> {
     repo_state: &'a RepoState,
     store_state: &'a mut StoreState,
+    store: &'a Mutex<Box<dyn DataStore>>,
 }
 
 impl<'a> ReadBlock for PackingBlockReader<'a> {
@@ -76,9 +121,39 @@ impl<'a> ReadBlock for PackingBlockReader<'a> {
         let block_size: u32 = index_list.iter().map(|index| index.size).sum();
         let mut block_buffer = Vec::with_capacity(block_size as usize);
 
+        // Packs are encrypted as a whole, so we can only read part of a pack from the data store
+        // when encryption is disabled. Otherwise, we need the whole pack to decrypt it.
+        let read_ranges = self.repo_state.metadata.config.encryption == Encryption::None
+            && self.store.lock().unwrap().supports_range_reads();
+
         // A block can be spread across multiple packs. Get the data from each pack and concatenate
         // them.
         for pack_index in index_list {
+            let is_buffered = matches!(
+                &self.store_state.read_buffer,
+                Some(pack) if pack.id == pack_index.id
+            );
+
+            // Read only the part of the pack containing the block data.
+            if read_ranges && !is_buffered {
+                let block_data = self
+                    .store
+                    .lock()
+                    .unwrap()
+                    .read_block_range(
+                        BlockKey::Data(pack_index.id),
+                        pack_index.offset as u64,
+                        pack_index.size as u64,
+                    )
+                    .map_err(crate::Error::Store)?
+                    .ok_or(crate::Error::InvalidData)?;
+                if block_data.len() != pack_index.size as usize {
+                    return Err(crate::Error::InvalidData);
+                }
+                block_buffer.extend_from_slice(&block_data);
+                continue;
+            }
+
             // Check if the data we need is already in the read buffer.
             let pack_buffer = match &self.store_state.read_buffer {
                 // Read the data from the read buffer.
@@ -87,7 +162,6 @@ impl<'a> ReadBlock for PackingBlockReader<'a> {
                 // Read a new pack into the read buffer.
                 _ => {
                     let encoded_pack_buffer = self
-                        .repo_state
                         .store
                         .lock()
                         .unwrap()
@@ -134,13 +208,14 @@ impl<'a> ReadBlock for PackingBlockWriter<'a> {
         let mut reader = PackingBlockReader {
             repo_state: self.repo_state,
             store_state: self.store_state,
+            store: &self.repo_state.store,
         };
         reader.read_block(id)
     }
 }
 
 impl<'a> WriteBlock for PackingBlockWriter<'a> {
-    fn write_block(&mut self, id: BlockId, data: &[u8]) -> crate::Result<()> {
+    fn write_block(&mut self, id: BlockId, data: &[u8]) -> crate::Result<u32> {
         let pack_size = self.pack_size;
         let current_pack = self
             .store_state
@@ -153,6 +228,9 @@ impl<'a> WriteBlock for PackingBlockWriter<'a> {
         // of the compressed pack would leak metadata about the contents of the pack, as unlike
         // with encryption, the size of the compressed pack would be based on its contents.
         let compressed_data = self.repo_state.metadata.config.compression.compress(data)?;
+        #[cfg(feature = "observability")]
+        self.repo_state
+            .report_metrics(|metrics| metrics.data_compressed(data.len(), compressed_data.len()));
 
         // The block's offset from the start of the current pack.
         let mut current_offset = current_pack.buffer.len() as u32;
@@ -268,7 +346,7 @@ impl<'a> WriteBlock for PackingBlockWriter<'a> {
                 // do need to replace the pack indices in the pack map, which we do here.
                 self.repo_state.packs.insert(id, new_packs_indices);
 
-                return Ok(());
+                return Ok(compressed_data.len() as u32);
             }
         }
     }
@@ -276,34 +354,106 @@ impl<'a> WriteBlock for PackingBlockWriter<'a> {
 
 struct DirectBlockWriter<'a> {
     state: &'a RepoState,
+    store: &'a Mutex<Box<dyn DataStore>>,
 }
 
-impl<'a> ReadBlock for DirectBlockWriter<'a> {
-    fn read_block(&mut self, id: BlockId) -> crate::Result<Vec<u8>> {
-        let encoded_block = self
-            .state
-            .store
+impl<'a> DirectBlockWriter<'a> {
+    /// Return the encoded bytes of the block with the given `id`.
+    fn read_encoded_block(&self, id: BlockId) -> crate::Result<Vec<u8>> {
+        self.store
             .lock()
             .unwrap()
             .read_block(BlockKey::Data(id))
             .map_err(crate::Error::Store)?
-            .ok_or(crate::Error::InvalidData)?;
+            .ok_or(crate::Error::InvalidData)
+    }
+
+    /// Write the given `encoded_block` to the block with the given `id`.
+    ///
+    /// This returns the size of the data after it was compressed.
+    fn write_encoded_block(&self, id: BlockId, encoded_block: &[u8]) -> crate::Result<u32> {
+        self.store
+            .lock()
+            .unwrap()
+            .write_block(BlockKey::Data(id), encoded_block)
+            .map_err(crate::Error::Store)?;
+        Ok(self.state.compressed_size(encoded_block))
+    }
+
+    /// Return the bytes of the given `chunk`, which is stored in the block with the given `id`.
+    ///
+    /// Unlike `read_block`, this can decode blocks which were written with convergent encryption.
+    fn read_chunk_block(&self, id: BlockId, chunk: &Chunk) -> crate::Result<Vec<u8>> {
+        let encoded_block = self.read_encoded_block(id)?;
+        self.state.decode_block(chunk, encoded_block.as_slice())
+    }
+
+    /// Write the given `data` of the given `chunk` as a new block with the given `id`.
+    ///
+    /// Unlike `write_block`, this uses convergent encryption if it is enabled.
+    fn write_chunk_block(&self, id: BlockId, chunk: &Chunk, data: &[u8]) -> crate::Result<u32> {
+        let encoded_block = self.state.encode_block(chunk, data)?;
+        self.write_encoded_block(id, encoded_block.as_slice())
+    }
+}
+
+impl<'a> ReadBlock for DirectBlockWriter<'a> {
+    fn read_block(&mut self, id: BlockId) -> crate::Result<Vec<u8>> {
+        let encoded_block = self.read_encoded_block(id)?;
         self.state.decode_data(encoded_block.as_slice())
     }
 }
 
 impl<'a> WriteBlock for DirectBlockWriter<'a> {
-    fn write_block(&mut self, id: BlockId, data: &[u8]) -> crate::Result<()> {
+    fn write_block(&mut self, id: BlockId, data: &[u8]) -> crate::Result<u32> {
         let encoded_block = self.state.encode_data(data)?;
-        self.state
-            .store
-            .lock()
-            .unwrap()
-            .write_block(BlockKey::Data(id), encoded_block.as_slice())
-            .map_err(crate::Error::Store)
+        self.write_encoded_block(id, encoded_block.as_slice())
     }
 }
 
+/// Read the block with the given `id` using the `old` packing method and rewrite it using the
+/// `new` packing method.
+///
+/// This does not change the packing method in the repository's configuration.
+pub fn repack_block(
+    repo_state: &mut RepoState,
+    store_state: &mut StoreState,
+    id: BlockId,
+    old: &Packing,
+    new: &Packing,
+) -> crate::Result<()> {
+    let data = match old {
+        Packing::None => DirectBlockWriter {
+            state: repo_state,
+            store: &repo_state.store,
+        }
+        .read_block(id)?,
+        Packing::Fixed(_) => PackingBlockReader {
+            repo_state,
+            store_state,
+            store: &repo_state.store,
+        }
+        .read_block(id)?,
+    };
+
+    // The compressed size of the block doesn't change, so the chunk map doesn't need updating.
+    match new {
+        Packing::None => DirectBlockWriter {
+            state: repo_state,
+            store: &repo_state.store,
+        }
+        .write_block(id, &data)?,
+        Packing::Fixed(pack_size) => PackingBlockWriter {
+            repo_state,
+            store_state,
+            pack_size: *pack_size,
+        }
+        .write_block(id, &data)?,
+    };
+
+    Ok(())
+}
+
 /// The state for a `StoreReader` or `StoreWriter`.
 #[derive(Debug)]
 pub struct StoreState {
@@ -345,6 +495,7 @@ pub trait WriteChunk: ReadChunk {
 pub struct StoreReader<'a> {
     repo_state: &'a RepoState,
     store_state: &'a mut StoreState,
+    store: &'a Mutex<Box<dyn DataStore>>,
 }
 
 impl<'a> StoreReader<'a> {
@@ -353,6 +504,23 @@ impl<'a> StoreReader<'a> {
         StoreReader {
             repo_state,
             store_state,
+            store: &repo_state.store,
+        }
+    }
+
+    /// Create a new instance which borrows the given state and reads from `store`.
+    ///
+    /// This allows threads to read from separate handles to the repository's data store instead
+    /// of sharing the one in `repo_state`.
+    pub fn with_store(
+        repo_state: &'a RepoState,
+        store_state: &'a mut StoreState,
+        store: &'a Mutex<Box<dyn DataStore>>,
+    ) -> Self {
+        StoreReader {
+            repo_state,
+            store_state,
+            store,
         }
     }
 }
@@ -362,10 +530,12 @@ impl<'a> ReadBlock for StoreReader<'a> {
         let mut read_block: Box<dyn ReadBlock> = match &self.repo_state.metadata.config.packing {
             Packing::None => Box::new(DirectBlockWriter {
                 state: self.repo_state,
+                store: self.store,
             }),
             Packing::Fixed(_) => Box::new(PackingBlockReader {
                 repo_state: self.repo_state,
                 store_state: self.store_state,
+                store: self.store,
             }),
         };
         read_block.read_block(id)
@@ -379,7 +549,17 @@ impl<'a> ReadChunk for StoreReader<'a> {
             .chunks
             .get(&chunk)
             .ok_or(crate::Error::InvalidData)?;
-        self.read_block(chunk_info.block_id)
+
+        // Convergent encryption requires that packing is disabled. The key for each block is
+        // derived from the hash of its chunk, which is only known here.
+        match &self.repo_state.metadata.config.packing {
+            Packing::None => DirectBlockWriter {
+                state: self.repo_state,
+                store: self.store,
+            }
+            .read_chunk_block(chunk_info.block_id, &chunk),
+            Packing::Fixed(_) => self.read_block(chunk_info.block_id),
+        }
     }
 }
 
@@ -399,22 +579,187 @@ impl<'a> StoreWriter<'a> {
     }
 }
 
+impl<'a> StoreWriter<'a> {
+    /// Write each of the given `chunks` and append their checksums to `new_chunks`.
+    ///
+    /// If [`RepoConfig::write_threads`] is greater than one, this hashes, compresses, and encrypts
+    /// the chunks concurrently before writing them to the data store. Otherwise, this is
+    /// equivalent to calling `write_chunk` for each chunk.
+    ///
+    /// If this returns `Err`, `new_chunks` contains the checksums of the chunks which were written
+    /// before the error occurred.
+    ///
+    /// [`RepoConfig::write_threads`]: crate::repo::RepoConfig::write_threads
+    pub fn write_chunks(
+        &mut self,
+        chunks: &[Vec<u8>],
+        id: HandleId,
+        new_chunks: &mut Vec<Chunk>,
+    ) -> crate::Result<()> {
+        self.write_chunks_with(chunks, |_| id, new_chunks)
+    }
+
+    /// Write each of the given `chunks` on behalf of the handle with the corresponding ID in `ids`.
+    ///
+    /// This is like [`write_chunks`], except that each chunk can belong to a different handle.
+    /// This allows the chunks of several objects to be written in a single batch.
+    ///
+    /// # Panics
+    /// - `chunks` and `ids` have different lengths.
+    ///
+    /// [`write_chunks`]: StoreWriter::write_chunks
+    pub fn write_batch(
+        &mut self,
+        chunks: &[Vec<u8>],
+        ids: &[HandleId],
+        new_chunks: &mut Vec<Chunk>,
+    ) -> crate::Result<()> {
+        assert_eq!(
+            chunks.len(),
+            ids.len(),
+            "Each chunk must have exactly one handle ID."
+        );
+        self.write_chunks_with(chunks, |index| ids[index], new_chunks)
+    }
+
+    /// Write each of the given `chunks`, using `id_for` to get the handle ID for each chunk index.
+    #[cfg_attr(
+        feature = "observability",
+        tracing::instrument(level = "trace", skip_all, fields(chunks = chunks.len()))
+    )]
+    fn write_chunks_with(
+        &mut self,
+        chunks: &[Vec<u8>],
+        id_for: impl Fn(usize) -> HandleId,
+        new_chunks: &mut Vec<Chunk>,
+    ) -> crate::Result<()> {
+        let threads = self.repo_state.metadata.config.write_threads;
+
+        // Packs must be filled sequentially, so there is nothing to gain from encoding chunks
+        // concurrently when packing is enabled.
+        let is_packing = !matches!(self.repo_state.metadata.config.packing, Packing::None);
+
+        if threads <= 1 || chunks.len() <= 1 || is_packing {
+            for (index, data) in chunks.iter().enumerate() {
+                new_chunks.push(self.write_chunk(data, id_for(index))?);
+            }
+            return Ok(());
+        }
+
+        let repo_state: &RepoState = self.repo_state;
+        let group_size = (chunks.len() + threads - 1) / threads;
+
+        // Hash and encode the chunks concurrently. Chunks which already exist in the repository
+        // don't need to be encoded.
+        let encoded_chunks = thread::scope(|scope| {
+            let workers = chunks
+                .chunks(group_size)
+                .map(|group| {
+                    scope.spawn(move || {
+                        group
+                            .iter()
+                            .map(|data| encode_chunk(repo_state, data))
+                            .collect::<crate::Result<Vec<_>>>()
+                    })
+                })
+                .collect::<Vec<_>>();
+            workers
+                .into_iter()
+                .map(|worker| worker.join().expect("A chunk encoding thread panicked."))
+                .collect::<crate::Result<Vec<_>>>()
+        })?;
+
+        // Write the encoded chunks to the data store sequentially.
+        for (index, (chunk, encoded_chunk)) in encoded_chunks.into_iter().flatten().enumerate() {
+            let id = id_for(index);
+
+            // This chunk may have already been written earlier in this same batch.
+            if let Some(chunk_info) = self.repo_state.chunks.get_mut(&chunk) {
+                chunk_info.references.insert(id);
+                new_chunks.push(chunk);
+                #[cfg(feature = "observability")]
+                self.repo_state
+                    .report_metrics(|metrics| metrics.chunk_deduplicated(chunk.size as usize));
+                continue;
+            }
+
+            let (block_id, encoded_block) =
+                encoded_chunk.expect("A chunk was removed from the repository while writing.");
+            self.repo_state
+                .store
+                .lock()
+                .unwrap()
+                .write_block(BlockKey::Data(block_id), encoded_block.as_slice())
+                .map_err(crate::Error::Store)?;
+
+            let chunk_info = ChunkInfo {
+                block_id,
+                references: {
+                    let mut id_set = HashSet::new();
+                    id_set.insert(id);
+                    id_set
+                },
+                compressed_size: Some(self.repo_state.compressed_size(&encoded_block)),
+            };
+            self.repo_state.chunks.insert(chunk, chunk_info);
+            self.repo_state.chunk_tree.invalidate(&chunk);
+            new_chunks.push(chunk);
+            #[cfg(feature = "observability")]
+            self.repo_state
+                .report_metrics(|metrics| metrics.chunk_written(chunk.size as usize));
+        }
+
+        Ok(())
+    }
+}
+
+/// Hash the given chunk `data` and encode it if it doesn't already exist in the repository.
+///
+/// This returns the chunk and, if the chunk needs to be written, the ID of its new block and its
+/// encoded data.
+fn encode_chunk(
+    repo_state: &RepoState,
+    data: &[u8],
+) -> crate::Result<(Chunk, Option<(BlockId, Vec<u8>)>)> {
+    assert!(
+        data.len() <= u32::MAX as usize,
+        "Given data exceeds maximum chunk size."
+    );
+
+    let chunk = Chunk {
+        hash: chunk_hash(data),
+        size: data.len() as u32,
+    };
+
+    if repo_state.chunks.contains_key(&chunk) {
+        return Ok((chunk, None));
+    }
+
+    let block_id = repo_state.chunk_block_id(&chunk);
+    Ok((
+        chunk,
+        Some((block_id, repo_state.encode_block(&chunk, data)?)),
+    ))
+}
+
 impl<'a> ReadBlock for StoreWriter<'a> {
     fn read_block(&mut self, id: BlockId) -> crate::Result<Vec<u8>> {
         let mut chunk_reader = StoreReader {
             repo_state: self.repo_state,
             store_state: self.store_state,
+            store: &self.repo_state.store,
         };
         chunk_reader.read_block(id)
     }
 }
 
 impl<'a> WriteBlock for StoreWriter<'a> {
-    fn write_block(&mut self, id: BlockId, data: &[u8]) -> crate::Result<()> {
+    fn write_block(&mut self, id: BlockId, data: &[u8]) -> crate::Result<u32> {
         let mut block_writer: Box<dyn WriteBlock> =
             match self.repo_state.metadata.config.packing.clone() {
                 Packing::None => Box::new(DirectBlockWriter {
                     state: self.repo_state,
+                    store: &self.repo_state.store,
                 }),
                 Packing::Fixed(pack_size) => Box::new(PackingBlockWriter {
                     repo_state: self.repo_state,
@@ -431,6 +776,7 @@ impl<'a> ReadChunk for StoreWriter<'a> {
         let mut chunk_reader = StoreReader {
             repo_state: self.repo_state,
             store_state: self.store_state,
+            store: &self.repo_state.store,
         };
         chunk_reader.read_chunk(chunk)
     }
@@ -452,11 +798,21 @@ impl<'a> WriteChunk for StoreWriter<'a> {
         // Check if the chunk already exists.
         if let Some(chunk_info) = self.repo_state.chunks.get_mut(&chunk) {
             chunk_info.references.insert(id);
+            #[cfg(feature = "observability")]
+            self.repo_state
+                .report_metrics(|metrics| metrics.chunk_deduplicated(data.len()));
             return Ok(chunk);
         }
 
-        let block_id = Uuid::new_v4().into();
-        self.write_block(block_id, data)?;
+        let block_id = self.repo_state.chunk_block_id(&chunk);
+        let compressed_size = match self.repo_state.metadata.config.packing {
+            Packing::None => DirectBlockWriter {
+                state: self.repo_state,
+                store: &self.repo_state.store,
+            }
+            .write_chunk_block(block_id, &chunk, data)?,
+            Packing::Fixed(_) => self.write_block(block_id, data)?,
+        };
 
         // Add the chunk to the header.
         let chunk_info = ChunkInfo {
@@ -466,8 +822,13 @@ impl<'a> WriteChunk for StoreWriter<'a> {
                 id_set.insert(id);
                 id_set
             },
+            compressed_size: Some(compressed_size),
         };
         self.repo_state.chunks.insert(chunk, chunk_info);
+        self.repo_state.chunk_tree.invalidate(&chunk);
+        #[cfg(feature = "observability")]
+        self.repo_state
+            .report_metrics(|metrics| metrics.chunk_written(data.len()));
 
         Ok(chunk)
     }
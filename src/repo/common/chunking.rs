@@ -11,6 +11,16 @@ use serde::{Deserialize, Serialize};
 ///
 /// The chunk size affects deduplication ratios, memory usage, and I/O performance. Some
 /// experimentation may be required to determine the optimal chunk size for a given workload.
+///
+/// Neither chunking method is keyed, and chunks are identified by their unkeyed BLAKE3 checksum.
+/// Chunk boundaries depend only on the data and this value, so there is no per-repository secret
+/// involved in chunking which would need to be rotated along with the encryption key. Because
+/// deduplication is based on these checksums, identical data is deduplicated across all instances
+/// of a repository regardless of when it was written or which password was used. If encryption is
+/// enabled, only the encrypted chunks are written to the data store, but the number and size of
+/// chunks may still reveal information about the data unless [`Packing::Fixed`] is used.
+///
+/// [`Packing::Fixed`]: crate::repo::Packing::Fixed
 #[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 #[non_exhaustive]
 pub enum Chunking {
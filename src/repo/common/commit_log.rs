@@ -0,0 +1,124 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use secrecy::ExposeSecret;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::encryption::EncryptionKey;
+use super::fingerprint::Fingerprint;
+use super::metadata::RepoId;
+
+/// The context string used to derive the key for authenticating the commit log.
+const COMMIT_LOG_KEY_CONTEXT: &str = "acid-store 2024-01-01 commit log authentication key";
+
+/// A record of a commit in a repository's commit log.
+///
+/// When [`RepoConfig::commit_log`] is enabled, a record is appended to the commit log each time
+/// changes are committed. Each record contains a sequence number, the time of the commit, the
+/// [`Fingerprint`] of the repository as of the commit, and a MAC which authenticates the record
+/// and every record before it using a key derived from the repository's master key.
+///
+/// [`RepoConfig::commit_log`]: crate::repo::RepoConfig::commit_log
+/// [`Fingerprint`]: crate::repo::Fingerprint
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CommitRecord {
+    sequence: u64,
+    timestamp: SystemTime,
+    fingerprint: Fingerprint,
+    mac: [u8; blake3::OUT_LEN],
+}
+
+impl CommitRecord {
+    /// Create a record which follows the `previous` record in the log.
+    pub(super) fn new(
+        key: &EncryptionKey,
+        repo_id: RepoId,
+        previous: Option<&CommitRecord>,
+        fingerprint: Fingerprint,
+    ) -> Self {
+        let mut record = Self {
+            sequence: previous.map_or(0, |record| record.sequence + 1),
+            timestamp: SystemTime::now(),
+            fingerprint,
+            mac: [0; blake3::OUT_LEN],
+        };
+        record.mac = record.compute_mac(key, repo_id, previous);
+        record
+    }
+
+    /// The sequence number of this commit.
+    ///
+    /// The first commit in the log has a sequence number of `0`, and each subsequent commit has a
+    /// sequence number one greater than the last.
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    /// The time this commit was made according to the system clock.
+    pub fn timestamp(&self) -> SystemTime {
+        self.timestamp
+    }
+
+    /// The fingerprint of the repository as of this commit.
+    pub fn fingerprint(&self) -> Fingerprint {
+        self.fingerprint
+    }
+
+    /// The MAC which authenticates this record and every record before it.
+    ///
+    /// Because this depends on every previous record, it uniquely identifies the history of the
+    /// repository up to this commit.
+    pub fn mac(&self) -> &[u8; blake3::OUT_LEN] {
+        &self.mac
+    }
+
+    /// Compute the MAC for this record given the `previous` record in the log.
+    fn compute_mac(
+        &self,
+        key: &EncryptionKey,
+        repo_id: RepoId,
+        previous: Option<&CommitRecord>,
+    ) -> [u8; blake3::OUT_LEN] {
+        let mac_key = blake3::derive_key(COMMIT_LOG_KEY_CONTEXT, key.expose_secret());
+        let timestamp = self
+            .timestamp
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+
+        let mut hasher = blake3::Hasher::new_keyed(&mac_key);
+        hasher.update(Uuid::from(repo_id).as_bytes());
+        hasher.update(&self.sequence.to_le_bytes());
+        hasher.update(&timestamp.as_secs().to_le_bytes());
+        hasher.update(&timestamp.subsec_nanos().to_le_bytes());
+        hasher.update(self.fingerprint.as_bytes());
+        hasher.update(previous.map_or(&[0; blake3::OUT_LEN], |record| &record.mac));
+        *hasher.finalize().as_bytes()
+    }
+}
+
+/// Verify that each record in the commit `log` is authentic and that none are missing.
+///
+/// # Errors
+/// - `Error::Corrupt`: A record in the log is missing or has been tampered with.
+pub fn verify_commit_log(
+    key: &EncryptionKey,
+    repo_id: RepoId,
+    log: &[CommitRecord],
+) -> crate::Result<()> {
+    let mut previous = None;
+    for record in log {
+        let expected_sequence = previous.map_or(0, |record: &CommitRecord| record.sequence + 1);
+        if record.sequence != expected_sequence {
+            return Err(crate::Error::Corrupt);
+        }
+
+        // Comparing `blake3::Hash` values is constant-time.
+        let expected_mac = blake3::Hash::from(record.compute_mac(key, repo_id, previous));
+        if expected_mac != blake3::Hash::from(record.mac) {
+            return Err(crate::Error::Corrupt);
+        }
+
+        previous = Some(record);
+    }
+    Ok(())
+}
@@ -2,13 +2,44 @@ use serde::{Deserialize, Serialize};
 
 use super::chunking::Chunking;
 use super::compression::Compression;
-use super::encryption::{Encryption, ResourceLimit};
+use super::encryption::{Encryption, EncryptionKey, KeyDerivation, KeySalt, ResourceLimit};
 use super::packing::Packing;
 
+/// A preset configuration for a common kind of workload.
+///
+/// Presets are created with constructors like [`RepoConfig::backup`], and the preset a
+/// configuration was created from is recorded in [`RepoConfig::preset`].
+///
+/// [`RepoConfig::backup`]: crate::repo::RepoConfig::backup
+/// [`RepoConfig::preset`]: crate::repo::RepoConfig::preset
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[non_exhaustive]
+pub enum ConfigPreset {
+    /// The preset created by [`RepoConfig::backup`].
+    ///
+    /// [`RepoConfig::backup`]: crate::repo::RepoConfig::backup
+    Backup,
+
+    /// The preset created by [`RepoConfig::small_files`].
+    ///
+    /// [`RepoConfig::small_files`]: crate::repo::RepoConfig::small_files
+    SmallFiles,
+
+    /// The preset created by [`RepoConfig::media_archive`].
+    ///
+    /// [`RepoConfig::media_archive`]: crate::repo::RepoConfig::media_archive
+    MediaArchive,
+}
+
 /// The configuration for a repository.
 ///
 /// This type is used to configure a repository when it is created. This type implements `Default`
-/// to provide a reasonable default configuration.
+/// to provide a reasonable default configuration. There are also constructors which return presets
+/// tuned for common workloads, like [`RepoConfig::backup`], which choose the chunking, packing, and
+/// compression methods together. Other options, like encryption, can be changed after the preset
+/// is created.
+///
+/// [`RepoConfig::backup`]: crate::repo::RepoConfig::backup
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[non_exhaustive]
 pub struct RepoConfig {
@@ -19,6 +50,8 @@ pub struct RepoConfig {
 
     /// The packing method to use in the repository.
     ///
+    /// This must be `Packing::None` if `convergent_encryption` is enabled.
+    ///
     /// The default value is `Packing::None`.
     pub packing: Packing,
 
@@ -34,13 +67,222 @@ pub struct RepoConfig {
 
     /// The maximum amount of memory key derivation will use if encryption is enabled.
     ///
+    /// This is ignored if `key_derivation` is not `None`.
+    ///
     /// The default value is `ResourceLimit::Interactive`.
     pub memory_limit: ResourceLimit,
 
     /// The maximum number of computations key derivation will perform if encryption is enabled.
     ///
+    /// This is ignored if `key_derivation` is not `None`.
+    ///
     /// The default value is `ResourceLimit::Interactive`.
     pub operations_limit: ResourceLimit,
+
+    /// The number of threads to use to hash, compress, and encrypt chunks when writing data.
+    ///
+    /// When this is greater than one, large writes to an object are split into chunks which are
+    /// processed concurrently before they are written to the data store. This has no effect when
+    /// packing is enabled, because packs must be filled sequentially.
+    ///
+    /// Unlike other options, this is not stored in the repository. The value from the `RepoConfig`
+    /// passed to [`OpenOptions`] is used both when creating a new repository and when opening an
+    /// existing one.
+    ///
+    /// The default value is `1`.
+    ///
+    /// [`OpenOptions`]: crate::repo::OpenOptions
+    #[serde(skip, default = "default_threads")]
+    pub write_threads: usize,
+
+    /// The number of threads to use to read, decrypt, decompress, and hash chunks when verifying
+    /// the repository.
+    ///
+    /// Each thread verifies the chunks in a different set of packs. Threads read blocks from the
+    /// data store concurrently if the data store supports [`DataStore::try_clone`]. Otherwise, they
+    /// share one handle to the data store and only the CPU work is done concurrently.
+    ///
+    /// Unlike other options, this is not stored in the repository. The value from the `RepoConfig`
+    /// passed to [`OpenOptions`] is used both when creating a new repository and when opening an
+    /// existing one.
+    ///
+    /// The default value is `1`.
+    ///
+    /// [`OpenOptions`]: crate::repo::OpenOptions
+    /// [`DataStore::try_clone`]: crate::store::DataStore::try_clone
+    #[serde(skip, default = "default_threads")]
+    pub verify_threads: usize,
+
+    /// The number of threads to use to decompress and decrypt chunks when prefetching data.
+    ///
+    /// When this is greater than one, the chunks fetched by [`Object::prefetch`] and by read-ahead
+    /// for objects advised with [`Access::Sequential`] are decoded concurrently.
+    ///
+    /// Unlike other options, this is not stored in the repository. The value from the `RepoConfig`
+    /// passed to [`OpenOptions`] is used both when creating a new repository and when opening an
+    /// existing one.
+    ///
+    /// The default value is `1`.
+    ///
+    /// [`Object::prefetch`]: crate::repo::Object::prefetch
+    /// [`Access::Sequential`]: crate::repo::Access::Sequential
+    /// [`OpenOptions`]: crate::repo::OpenOptions
+    #[serde(skip, default = "default_threads")]
+    pub read_threads: usize,
+
+    /// The maximum apparent size of each instance of the repository in bytes.
+    ///
+    /// The apparent size of an instance is the sum of the sizes of all the objects in it, as
+    /// reported by [`RepoStats::apparent_size`]. Writing to an object or extending it with
+    /// [`Object::set_len`] returns [`Error::QuotaExceeded`] if it would cause the apparent size of
+    /// the current instance to exceed its quota. Operations which don't increase the size of the
+    /// instance always succeed, even if the instance is already over its quota.
+    ///
+    /// This can be overridden for individual instances with [`KeyRepo::set_quota`].
+    ///
+    /// The default value is `None`, which means there is no limit.
+    ///
+    /// [`RepoStats::apparent_size`]: crate::repo::RepoStats::apparent_size
+    /// [`Object::set_len`]: crate::repo::Object::set_len
+    /// [`Error::QuotaExceeded`]: crate::Error::QuotaExceeded
+    /// [`KeyRepo::set_quota`]: crate::repo::key::KeyRepo::set_quota
+    #[serde(default)]
+    pub quota: Option<u64>,
+
+    /// The key derivation function to use if encryption is enabled.
+    ///
+    /// If this is `None`, the Argon2id key derivation function is used with the presets in
+    /// `memory_limit` and `operations_limit`. Otherwise, the given function is used with its
+    /// explicit parameters. Creating a repository returns [`Error::InvalidConfig`] if those
+    /// parameters are invalid.
+    ///
+    /// The default value is `None`.
+    ///
+    /// [`Error::InvalidConfig`]: crate::Error::InvalidConfig
+    #[serde(default)]
+    pub key_derivation: Option<KeyDerivation>,
+
+    /// Whether to use convergent encryption for chunks of data.
+    ///
+    /// Normally, each chunk is stored in a block with a random ID and encrypted with the
+    /// repository's master key. With convergent encryption, the ID of each block and the key used
+    /// to encrypt it are derived from the contents of the chunk and a convergence secret provided
+    /// with [`OpenOptions::convergence_secret`]. This means repositories which share the same
+    /// convergence secret produce identical blocks for identical chunks, even if they have
+    /// different passwords, which allows a storage backend shared between those repositories to
+    /// deduplicate data across them.
+    ///
+    /// This comes at the cost of confidentiality. Anyone who knows the convergence secret can
+    /// confirm whether a repository stores a known plaintext by computing the IDs of the blocks
+    /// it would be stored in and checking whether they exist, without knowing the repository's
+    /// password. The key used to encrypt each block is derived from the hash of its chunk rather
+    /// than its ID, so the convergence secret alone can't be used to decrypt data which isn't
+    /// already known. Only share the convergence secret between parties which trust each other
+    /// with this information. Repository metadata is still encrypted with the master key.
+    ///
+    /// This requires `packing` to be `Packing::None`. Creating a repository returns
+    /// [`Error::InvalidConfig`] if packing is enabled or a convergence secret is not provided.
+    ///
+    /// The default value is `false`.
+    ///
+    /// [`OpenOptions::convergence_secret`]: crate::repo::OpenOptions::convergence_secret
+    /// [`Error::InvalidConfig`]: crate::Error::InvalidConfig
+    #[serde(default)]
+    pub convergent_encryption: bool,
+
+    /// Whether to keep a tamper-evident log of commits to the repository.
+    ///
+    /// If this is enabled, a [`CommitRecord`] is appended to a log stored in the repository each
+    /// time changes are committed. Each record is authenticated using a key derived from the
+    /// repository's master key, so the log can't be modified without detection unless encryption
+    /// is disabled. The log can be read with [`KeyRepo::commit_log`] and verified with
+    /// [`KeyRepo::verify_commit_log`].
+    ///
+    /// An attacker with access to the data store could still roll back the entire repository to an
+    /// earlier commit, including its commit log. To detect this, store the most recent
+    /// [`CommitRecord`] somewhere outside the data store and compare it with the last record in the
+    /// log when the repository is opened.
+    ///
+    /// The log grows by one record each time changes are committed, and the entire log is
+    /// rewritten with each commit.
+    ///
+    /// The default value is `false`.
+    ///
+    /// [`CommitRecord`]: crate::repo::CommitRecord
+    /// [`KeyRepo::commit_log`]: crate::repo::key::KeyRepo::commit_log
+    /// [`KeyRepo::verify_commit_log`]: crate::repo::key::KeyRepo::verify_commit_log
+    #[serde(default)]
+    pub commit_log: bool,
+
+    /// The number of most recent commits to retain.
+    ///
+    /// Normally, only the most recent commit is retained, and [`Commit::clean`] removes any data
+    /// which isn't referenced by it or by the current uncommitted state. If this is greater than
+    /// `1`, the headers of that many of the most recent commits and the data they reference are
+    /// retained, so the repository can be restored to any of them with
+    /// [`KeyRepo::restore_commit`]. Retaining more commits means less data is reclaimed by
+    /// [`Commit::clean`].
+    ///
+    /// Creating a repository returns [`Error::InvalidConfig`] if this is `0`.
+    ///
+    /// The default value is `1`.
+    ///
+    /// [`Commit::clean`]: crate::repo::Commit::clean
+    /// [`KeyRepo::restore_commit`]: crate::repo::key::KeyRepo::restore_commit
+    /// [`Error::InvalidConfig`]: crate::Error::InvalidConfig
+    #[serde(default = "default_retained_commits")]
+    pub retained_commits: usize,
+
+    /// Whether to encrypt the repository metadata.
+    ///
+    /// Normally, the repository metadata, including this configuration, is stored in plaintext so
+    /// that it can be read with [`peek_info`] and exported with [`export_repo`] without the
+    /// password. This reveals how the repository is configured, like which chunking, packing, and
+    /// compression methods it uses. If this is enabled, the metadata is encrypted with the master
+    /// key, except for what is needed to decrypt the master key: the encryption method, the key
+    /// derivation parameters and salt, and the encrypted master key itself.
+    ///
+    /// [`peek_info`] and [`export_repo`] return [`Error::Password`] for repositories with encrypted
+    /// metadata. Use [`KeyRepo::info`] to get information about the repository once it's open.
+    /// Older versions of this library can't open repositories with encrypted metadata.
+    ///
+    /// This requires `encryption` to not be `Encryption::None`. Creating a repository returns
+    /// [`Error::InvalidConfig`] otherwise.
+    ///
+    /// The default value is `false`.
+    ///
+    /// [`peek_info`]: crate::repo::peek_info
+    /// [`export_repo`]: crate::repo::export_repo
+    /// [`KeyRepo::info`]: crate::repo::key::KeyRepo::info
+    /// [`Error::Password`]: crate::Error::Password
+    /// [`Error::InvalidConfig`]: crate::Error::InvalidConfig
+    #[serde(default)]
+    pub encrypt_metadata: bool,
+
+    /// The preset this configuration was created from, if any.
+    ///
+    /// This is set by preset constructors like [`RepoConfig::backup`] and is stored in the
+    /// repository so it can be inspected later with [`RepoInfo::config`]. It only records where the
+    /// configuration came from; it is not updated if other fields are changed afterwards and has no
+    /// effect on the repository.
+    ///
+    /// The default value is `None`.
+    ///
+    /// [`RepoConfig::backup`]: crate::repo::RepoConfig::backup
+    /// [`RepoInfo::config`]: crate::repo::RepoInfo::config
+    #[serde(default)]
+    pub preset: Option<ConfigPreset>,
+}
+
+/// The default value of `RepoConfig::write_threads`, `RepoConfig::verify_threads`, and
+/// `RepoConfig::read_threads`.
+fn default_threads() -> usize {
+    1
+}
+
+/// The default value of `RepoConfig::retained_commits`.
+fn default_retained_commits() -> usize {
+    1
 }
 
 impl Default for RepoConfig {
@@ -52,6 +294,89 @@ impl Default for RepoConfig {
             encryption: Encryption::None,
             memory_limit: ResourceLimit::Interactive,
             operations_limit: ResourceLimit::Interactive,
+            write_threads: default_threads(),
+            verify_threads: default_threads(),
+            read_threads: default_threads(),
+            quota: None,
+            key_derivation: None,
+            convergent_encryption: false,
+            commit_log: false,
+            retained_commits: default_retained_commits(),
+            encrypt_metadata: false,
+            preset: None,
+        }
+    }
+}
+
+impl RepoConfig {
+    /// Return a preset configuration for backups.
+    ///
+    /// This uses content-defined chunking so that data which shifts within a file is still
+    /// deduplicated between backups, and fast compression if the `compression` feature is enabled.
+    pub fn backup() -> Self {
+        RepoConfig {
+            chunking: Chunking::Zpaq { bits: 20 },
+            packing: Packing::None,
+            #[cfg(feature = "compression")]
+            compression: Compression::Lz4 { level: 1 },
+            preset: Some(ConfigPreset::Backup),
+            ..RepoConfig::default()
+        }
+    }
+
+    /// Return a preset configuration for many small files.
+    ///
+    /// This uses small chunks so that small objects don't waste space and packs them into
+    /// fixed-size blocks so that the data store doesn't need to store a block for each one. It
+    /// also uses compression if the `compression` feature is enabled.
+    pub fn small_files() -> Self {
+        RepoConfig {
+            chunking: Chunking::Fixed { size: 64 * 1024 },
+            packing: Packing::FIXED,
+            #[cfg(feature = "compression")]
+            compression: Compression::Lz4 { level: 4 },
+            preset: Some(ConfigPreset::SmallFiles),
+            ..RepoConfig::default()
+        }
+    }
+
+    /// Return a preset configuration for archiving large media files.
+    ///
+    /// Media like images, audio, and video is usually already compressed and rarely changes in
+    /// place, so this uses large fixed-size chunks and no compression.
+    pub fn media_archive() -> Self {
+        RepoConfig {
+            chunking: Chunking::Fixed {
+                size: 8 * 1024 * 1024,
+            },
+            packing: Packing::None,
+            compression: Compression::None,
+            preset: Some(ConfigPreset::MediaArchive),
+            ..RepoConfig::default()
+        }
+    }
+
+    /// Derive the key which encrypts the master key from the given `password` and `salt`.
+    ///
+    /// # Errors
+    /// - `Error::InvalidConfig`: The parameters of `key_derivation` are invalid.
+    pub(crate) fn derive_key(
+        &self,
+        password: &[u8],
+        salt: &KeySalt,
+    ) -> crate::Result<EncryptionKey> {
+        let size = self.encryption.key_size();
+        match &self.key_derivation {
+            None => Ok(EncryptionKey::derive(
+                password,
+                salt,
+                size,
+                self.memory_limit,
+                self.operations_limit,
+            )),
+            Some(key_derivation) => {
+                EncryptionKey::derive_with(password, salt, size, key_derivation)
+            }
         }
     }
 }
@@ -5,10 +5,13 @@ use serde::{Deserialize, Serialize};
 
 #[cfg(feature = "encryption")]
 use {
+    argon2::{Algorithm, Argon2, Params as Argon2Params, Version},
     rand::rngs::OsRng,
     rand::RngCore,
+    scrypt::Params as ScryptParams,
+    sha2::Sha256,
     sodiumoxide::crypto::aead::xchacha20poly1305_ietf::{
-        gen_nonce, open, seal, Key as ChaChaKey, Nonce, KEYBYTES, NONCEBYTES,
+        gen_nonce, open, seal, Key as ChaChaKey, Nonce, KEYBYTES, NONCEBYTES, TAGBYTES,
     },
     sodiumoxide::crypto::pwhash::argon2id13::{
         derive_key, gen_salt, MemLimit, OpsLimit, Salt, MEMLIMIT_INTERACTIVE, MEMLIMIT_MODERATE,
@@ -20,6 +23,13 @@ use {
 #[cfg(feature = "encryption")]
 static ENCRYPTION_INIT: Once = Once::new();
 
+/// The BLAKE3 context string for deriving a convergence key from a convergence secret.
+const CONVERGENCE_KEY_CONTEXT: &str = "acid-store 2023-07-01 convergence key";
+
+/// The BLAKE3 context string for deriving the nonce for convergent encryption.
+#[cfg(feature = "encryption")]
+const CONVERGENT_NONCE_CONTEXT: &str = "acid-store 2023-07-01 convergent encryption nonce";
+
 /// Initialize the environment for encryption.
 #[cfg(feature = "encryption")]
 fn init() {
@@ -63,6 +73,133 @@ impl ResourceLimit {
     }
 }
 
+/// A key derivation function with explicit parameters.
+///
+/// This is used to derive a key from the user's password if encryption is enabled. See
+/// [`RepoConfig::key_derivation`] for details.
+///
+/// [`RepoConfig::key_derivation`]: crate::repo::RepoConfig::key_derivation
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+#[non_exhaustive]
+pub enum KeyDerivation {
+    /// The Argon2id key derivation function.
+    ///
+    /// This is the same function used with the presets in [`ResourceLimit`], but with explicit
+    /// parameters.
+    ///
+    /// [`ResourceLimit`]: crate::repo::ResourceLimit
+    Argon2id {
+        /// The amount of memory to use in KiB.
+        ///
+        /// This must be at least `8 * parallelism`.
+        memory_kib: u32,
+
+        /// The number of passes over the memory.
+        ///
+        /// This must be at least `1`.
+        iterations: u32,
+
+        /// The number of lanes which are computed in parallel.
+        ///
+        /// This must be at least `1`.
+        parallelism: u32,
+    },
+
+    /// The scrypt key derivation function.
+    Scrypt {
+        /// The base-2 logarithm of the CPU/memory cost parameter `N`.
+        ///
+        /// This must be less than `64`.
+        log_n: u8,
+
+        /// The block size parameter `r`.
+        ///
+        /// This must be at least `1`.
+        r: u32,
+
+        /// The parallelization parameter `p`.
+        ///
+        /// This must be at least `1`.
+        p: u32,
+    },
+
+    /// The PBKDF2 key derivation function using HMAC-SHA256.
+    ///
+    /// This is less resistant to brute-force attacks than the other key derivation functions, but
+    /// it's available in environments which require FIPS-approved algorithms.
+    Pbkdf2Sha256 {
+        /// The number of iterations.
+        ///
+        /// This must be at least `1`.
+        iterations: u32,
+    },
+}
+
+impl KeyDerivation {
+    /// Return an error if the parameters are invalid for deriving a key of the given `size`.
+    ///
+    /// # Errors
+    /// - `Error::InvalidConfig`: The parameters are invalid.
+    #[cfg(feature = "encryption")]
+    pub(crate) fn validate(&self, size: usize) -> crate::Result<()> {
+        let is_valid = match *self {
+            KeyDerivation::Argon2id {
+                memory_kib,
+                iterations,
+                parallelism,
+            } => Argon2Params::new(memory_kib, iterations, parallelism, Some(size)).is_ok(),
+            KeyDerivation::Scrypt { log_n, r, p } => ScryptParams::new(log_n, r, p, size).is_ok(),
+            KeyDerivation::Pbkdf2Sha256 { iterations } => iterations > 0,
+        };
+        if is_valid {
+            Ok(())
+        } else {
+            Err(crate::Error::InvalidConfig)
+        }
+    }
+
+    /// Return an error if the parameters are invalid for deriving a key of the given `size`.
+    #[cfg(not(feature = "encryption"))]
+    pub(crate) fn validate(&self, _size: usize) -> crate::Result<()> {
+        Ok(())
+    }
+
+    /// Fill `output` with a key derived from the given `password` and `salt`.
+    ///
+    /// # Errors
+    /// - `Error::InvalidConfig`: The parameters are invalid.
+    #[cfg(feature = "encryption")]
+    fn derive_into(&self, password: &[u8], salt: &[u8], output: &mut [u8]) -> crate::Result<()> {
+        match *self {
+            KeyDerivation::Argon2id {
+                memory_kib,
+                iterations,
+                parallelism,
+            } => {
+                let params =
+                    Argon2Params::new(memory_kib, iterations, parallelism, Some(output.len()))
+                        .map_err(|_| crate::Error::InvalidConfig)?;
+                Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+                    .hash_password_into(password, salt, output)
+                    .map_err(|_| crate::Error::InvalidConfig)
+            }
+            KeyDerivation::Scrypt { log_n, r, p } => {
+                let params = ScryptParams::new(log_n, r, p, output.len())
+                    .map_err(|_| crate::Error::InvalidConfig)?;
+                scrypt::scrypt(password, salt, &params, output)
+                    .map_err(|_| crate::Error::InvalidConfig)
+            }
+            KeyDerivation::Pbkdf2Sha256 { iterations } => {
+                if iterations == 0 {
+                    return Err(crate::Error::InvalidConfig);
+                }
+                pbkdf2::pbkdf2_hmac::<Sha256>(password, salt, iterations, output);
+                Ok(())
+            }
+        }
+    }
+}
+
 /// A data encryption method.
 #[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 #[non_exhaustive]
@@ -100,6 +237,40 @@ impl Encryption {
         cleartext.to_vec()
     }
 
+    /// Encrypt the given `cleartext` with the given `key` using a nonce derived from both.
+    ///
+    /// Encrypting the same `cleartext` with the same `key` always produces the same ciphertext.
+    /// This must only be used with keys which are derived from the cleartext, as in convergent
+    /// encryption. The output can be decrypted with `decrypt`.
+    #[cfg(feature = "encryption")]
+    pub(crate) fn encrypt_deterministic(&self, cleartext: &[u8], key: &EncryptionKey) -> Vec<u8> {
+        init();
+        match self {
+            Encryption::None => cleartext.to_vec(),
+            Encryption::XChaCha20Poly1305 => {
+                // Deriving the nonce from the cleartext means a key is never reused with the same
+                // nonce for different messages, even if the compression method differs.
+                let mut nonce_bytes = [0u8; NONCEBYTES];
+                let mut nonce_hasher = blake3::Hasher::new_derive_key(CONVERGENT_NONCE_CONTEXT);
+                nonce_hasher.update(key.expose_secret());
+                nonce_hasher.update(cleartext);
+                nonce_hasher.finalize_xof().fill(&mut nonce_bytes);
+                let nonce = Nonce::from_slice(&nonce_bytes).unwrap();
+                let chacha_key = ChaChaKey::from_slice(key.expose_secret()).unwrap();
+                let mut ciphertext = seal(cleartext, None, &nonce, &chacha_key);
+                let mut output = nonce.as_ref().to_vec();
+                output.append(&mut ciphertext);
+                output
+            }
+        }
+    }
+
+    /// Encrypt the given `cleartext` with the given `key` using a nonce derived from both.
+    #[cfg(not(feature = "encryption"))]
+    pub(crate) fn encrypt_deterministic(&self, cleartext: &[u8], _key: &EncryptionKey) -> Vec<u8> {
+        cleartext.to_vec()
+    }
+
     /// Decrypt the given `ciphertext` with the given `key`.
     #[cfg(feature = "encryption")]
     pub(crate) fn decrypt(&self, ciphertext: &[u8], key: &EncryptionKey) -> crate::Result<Vec<u8>> {
@@ -135,6 +306,15 @@ impl Encryption {
             Encryption::XChaCha20Poly1305 => KEYBYTES,
         }
     }
+
+    /// The number of bytes this encryption method adds to the size of the data it encrypts.
+    pub(crate) fn overhead(&self) -> usize {
+        match self {
+            Encryption::None => 0,
+            #[cfg(feature = "encryption")]
+            Encryption::XChaCha20Poly1305 => NONCEBYTES + TAGBYTES,
+        }
+    }
 }
 
 /// Salt for deriving an encryption `Key`.
@@ -204,6 +384,14 @@ impl EncryptionKey {
         panic!("The `encryption` cargo feature is not enabled.")
     }
 
+    /// Derive the key used for convergent encryption from the given convergence `secret`.
+    ///
+    /// Unlike `derive`, this is deterministic and doesn't use a salt, so repositories which use
+    /// the same `secret` derive the same key.
+    pub fn convergent(secret: &[u8]) -> Self {
+        EncryptionKey::new(blake3::derive_key(CONVERGENCE_KEY_CONTEXT, secret).to_vec())
+    }
+
     /// Derive a new encryption key of the given `size` from the given `password` and `salt`.
     ///
     /// This uses the Argon2id key derivation function.
@@ -238,4 +426,32 @@ impl EncryptionKey {
     ) -> Self {
         panic!("The `encryption` cargo feature is not enabled.")
     }
+
+    /// Derive a new encryption key of the given `size` from the given `password` and `salt`.
+    ///
+    /// This uses the given key derivation function instead of the Argon2id presets.
+    ///
+    /// # Errors
+    /// - `Error::InvalidConfig`: The parameters of `key_derivation` are invalid.
+    #[cfg(feature = "encryption")]
+    pub fn derive_with(
+        password: &[u8],
+        salt: &KeySalt,
+        size: usize,
+        key_derivation: &KeyDerivation,
+    ) -> crate::Result<Self> {
+        let mut bytes = vec![0u8; size];
+        key_derivation.derive_into(password, salt.0.as_slice(), &mut bytes)?;
+        Ok(EncryptionKey::new(bytes))
+    }
+
+    #[cfg(not(feature = "encryption"))]
+    pub fn derive_with(
+        _password: &[u8],
+        _salt: &KeySalt,
+        _size: usize,
+        _key_derivation: &KeyDerivation,
+    ) -> crate::Result<Self> {
+        panic!("The `encryption` cargo feature is not enabled.")
+    }
 }
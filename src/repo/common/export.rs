@@ -0,0 +1,170 @@
+use std::io::{Read, Write};
+
+use rmp_serde::{from_read, to_vec};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::metadata::{RepoInfo, RepoMetadata, SuperBlock};
+use super::open_options::VERSION_ID;
+use crate::store::{BlockKey, DataStore, OpenStore};
+
+/// The state of a repository which has been detached from its data store.
+///
+/// This contains the repository metadata and the repository header as of the most recent commit.
+/// The header contains the index of all the chunks and packs in the repository as well as the
+/// locations of the object maps for each instance. It is stored exactly as it is in the data store,
+/// meaning that it is compressed and encrypted according to the repository's configuration.
+///
+/// This value can be written to a file with [`write`] and read back with [`read`]. It can be used
+/// to back up the metadata of a repository separately from its data, and then restore it with
+/// [`attach`] if the metadata in the data store is lost or damaged. This is created by
+/// [`export_repo`].
+///
+/// This does not contain any of the data in the repository. The data store which the state is
+/// attached to must still contain the data which was in the repository when it was exported.
+///
+/// [`write`]: crate::repo::RepoExport::write
+/// [`read`]: crate::repo::RepoExport::read
+/// [`attach`]: crate::repo::RepoExport::attach
+/// [`export_repo`]: crate::repo::export_repo
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RepoExport {
+    /// The version of the repository format.
+    version: Uuid,
+
+    /// The repository metadata.
+    metadata: RepoMetadata,
+
+    /// The encoded repository header.
+    header: Vec<u8>,
+}
+
+impl RepoExport {
+    /// Read an exported repository from `reader`.
+    ///
+    /// # Errors
+    /// - `Error::Deserialize`: The data read from `reader` is not a valid exported repository.
+    /// - `Error::UnsupportedRepo`: The repository was exported from an unsupported format.
+    /// - `Error::Io`: An I/O error occurred.
+    pub fn read(mut reader: impl Read) -> crate::Result<Self> {
+        let mut serialized_export = Vec::new();
+        reader.read_to_end(&mut serialized_export)?;
+        let export: Self =
+            from_read(serialized_export.as_slice()).map_err(|_| crate::Error::Deserialize)?;
+
+        if export.version != VERSION_ID {
+            return Err(crate::Error::UnsupportedRepo);
+        }
+
+        Ok(export)
+    }
+
+    /// Write this exported repository to `writer`.
+    ///
+    /// # Errors
+    /// - `Error::Io`: An I/O error occurred.
+    pub fn write(&self, mut writer: impl Write) -> crate::Result<()> {
+        let serialized_export = to_vec(self).expect("Could not serialize the exported repository.");
+        writer.write_all(&serialized_export)?;
+        Ok(())
+    }
+
+    /// Return information about the exported repository.
+    pub fn info(&self) -> RepoInfo {
+        self.metadata.to_info()
+    }
+
+    /// Attach this exported state to the data store with the given `config`.
+    ///
+    /// This writes the exported metadata and header to the data store, replacing the metadata and
+    /// header of any repository which is already in it. Any changes committed since the state was
+    /// exported are lost, and any changes which were flushed but not committed are discarded. Once
+    /// the state has been attached, the repository can be opened normally.
+    ///
+    /// If the repository was cleaned with [`Commit::clean`] after it was exported, some of the data
+    /// it references may have been removed from the data store.
+    ///
+    /// This must not be called while the repository is open.
+    ///
+    /// # Errors
+    /// - `Error::UnsupportedStore`: The data store is an unsupported format.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    ///
+    /// [`Commit::clean`]: crate::repo::Commit::clean
+    pub fn attach(&self, config: &impl OpenStore) -> crate::Result<()> {
+        let mut store = config.open()?;
+
+        let mut metadata = self.metadata.clone();
+        metadata.flushed_header_id = None;
+        metadata.previous_header_ids.clear();
+
+        // Write the header before the metadata which references it.
+        store
+            .write_block(BlockKey::Header(metadata.header_id), &self.header)
+            .map_err(crate::Error::Store)?;
+
+        let serialized_metadata = to_vec(&metadata).expect("Could not serialize metadata.");
+        store
+            .write_block(BlockKey::Super, &serialized_metadata)
+            .map_err(crate::Error::Store)?;
+
+        // Write the repository version last, because it signifies that the repository is complete.
+        store
+            .write_block(BlockKey::Version, VERSION_ID.as_bytes())
+            .map_err(crate::Error::Store)?;
+
+        Ok(())
+    }
+}
+
+/// Export the state of the repository in a data store without opening it.
+///
+/// This accepts the `config` used to open the data store. This returns the state of the repository
+/// as of the most recent commit. See [`RepoExport`] for details.
+///
+/// This does not require the repository's password, and it does not decrypt any data.
+///
+/// # Errors
+/// - `Error::NotFound`: There is no repository in the data store.
+/// - `Error::Corrupt`: The repository is corrupt. This is most likely unrecoverable.
+/// - `Error::UnsupportedRepo`: The repository is an unsupported format.
+/// - `Error::Password`: The repository metadata is encrypted. See
+/// [`RepoConfig::encrypt_metadata`].
+/// - `Error::UnsupportedStore`: The data store is an unsupported format.
+/// - `Error::Store`: An error occurred with the data store.
+/// - `Error::Io`: An I/O error occurred.
+///
+/// [`RepoExport`]: crate::repo::RepoExport
+/// [`RepoConfig::encrypt_metadata`]: crate::repo::RepoConfig::encrypt_metadata
+pub fn export_repo(config: &impl OpenStore) -> crate::Result<RepoExport> {
+    let mut store = config.open()?;
+
+    // Read the repository version to see if this is a compatible repository.
+    let serialized_version = store
+        .read_block(BlockKey::Version)
+        .map_err(crate::Error::Store)?
+        .ok_or(crate::Error::NotFound)?;
+    let version =
+        Uuid::from_slice(serialized_version.as_slice()).map_err(|_| crate::Error::Corrupt)?;
+    if version != VERSION_ID {
+        return Err(crate::Error::UnsupportedRepo);
+    }
+
+    let serialized_metadata = store
+        .read_block(BlockKey::Super)
+        .map_err(crate::Error::Store)?
+        .ok_or(crate::Error::Corrupt)?;
+    let metadata = SuperBlock::from_bytes(&serialized_metadata)?.into_plain()?;
+
+    let header = store
+        .read_block(BlockKey::Header(metadata.header_id))
+        .map_err(crate::Error::Store)?
+        .ok_or(crate::Error::Corrupt)?;
+
+    Ok(RepoExport {
+        version,
+        metadata,
+        header,
+    })
+}
@@ -0,0 +1,151 @@
+use std::fmt::{self, Display, Formatter};
+
+use serde::{Deserialize, Serialize};
+
+use super::handle::Chunk;
+
+/// The number of buckets at the bottom of a `ChunkTree`.
+///
+/// Chunks are assigned to buckets by the first byte of their hash.
+const BUCKETS: usize = 256;
+
+/// A prefix used to distinguish the hash of a leaf from the hash of an interior node.
+const LEAF_PREFIX: u8 = 0;
+
+/// A prefix used to distinguish the hash of an interior node from the hash of a leaf.
+const NODE_PREFIX: u8 = 1;
+
+/// A prefix used to compute the hash of an empty tree.
+const EMPTY_PREFIX: u8 = 2;
+
+/// A cryptographic fingerprint of the contents of a repository.
+///
+/// This is the root of a Merkle tree over the hashes of every chunk of data in the repository,
+/// across all instances. This includes the chunks which store the keys and object maps of each
+/// instance, so renaming an object changes the fingerprint. It's recomputed each time changes are
+/// committed and is stored in the repository's [`RepoInfo`], so it can be read without decrypting
+/// the repository.
+///
+/// Two repositories with the same fingerprint contain the same data. This does not depend on the
+/// repository's encryption key or the IDs of its blocks, so it can be used to cheaply verify that
+/// a replica of a repository is identical to the original, or to record a tamper-evident hash of
+/// the state of the repository at each commit. Because data is split into chunks according to the
+/// repository's [`Chunking`] configuration, repositories with different chunking configurations
+/// will have different fingerprints even if they contain the same data.
+///
+/// Like the rest of [`RepoInfo`], the fingerprint is not encrypted. It does not reveal anything
+/// about the data in the repository except whether it's identical to some known set of data.
+///
+/// [`RepoInfo`]: crate::repo::RepoInfo
+/// [`Chunking`]: crate::repo::Chunking
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Fingerprint([u8; blake3::OUT_LEN]);
+
+impl Fingerprint {
+    /// Return the bytes of this fingerprint.
+    pub fn as_bytes(&self) -> &[u8; blake3::OUT_LEN] {
+        &self.0
+    }
+}
+
+impl Display for Fingerprint {
+    /// Format this fingerprint as a lowercase hexadecimal string.
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for byte in self.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+/// Return the hash of the leaf representing `chunk`.
+fn leaf_hash(chunk: &Chunk) -> blake3::Hash {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&[LEAF_PREFIX]);
+    hasher.update(&chunk.size.to_le_bytes());
+    hasher.update(&chunk.hash);
+    hasher.finalize()
+}
+
+/// Return the root of a Merkle tree with the given `nodes` at the bottom.
+fn merkle_root(mut nodes: Vec<blake3::Hash>) -> blake3::Hash {
+    if nodes.is_empty() {
+        return blake3::hash(&[EMPTY_PREFIX]);
+    }
+
+    while nodes.len() > 1 {
+        nodes = nodes
+            .chunks(2)
+            .map(|pair| match pair {
+                [left, right] => {
+                    let mut hasher = blake3::Hasher::new();
+                    hasher.update(&[NODE_PREFIX]);
+                    hasher.update(left.as_bytes());
+                    hasher.update(right.as_bytes());
+                    hasher.finalize()
+                }
+                // An odd node at the end of a level is promoted to the next level.
+                [node] => *node,
+                _ => unreachable!(),
+            })
+            .collect();
+    }
+
+    nodes[0]
+}
+
+/// A Merkle tree over the chunks in a repository which can be recomputed incrementally.
+///
+/// Chunks are divided into a fixed number of buckets, and the root of the subtree for each bucket
+/// is cached. When chunks are added or removed, only the buckets they belong to need to be
+/// recomputed.
+#[derive(Debug)]
+pub struct ChunkTree {
+    /// The cached root of the subtree for each bucket, or `None` if the bucket has changed.
+    buckets: Vec<Option<blake3::Hash>>,
+}
+
+impl ChunkTree {
+    /// Return a new `ChunkTree` which must be recomputed in its entirety.
+    pub fn new() -> Self {
+        Self {
+            buckets: vec![None; BUCKETS],
+        }
+    }
+
+    /// Record that `chunk` was added to or removed from the repository.
+    pub fn invalidate(&mut self, chunk: &Chunk) {
+        self.buckets[chunk.hash[0] as usize] = None;
+    }
+
+    /// Record that any chunk in the repository may have changed.
+    pub fn invalidate_all(&mut self) {
+        self.buckets.fill(None);
+    }
+
+    /// Return the fingerprint of the given set of `chunks`.
+    ///
+    /// This only hashes the chunks in buckets which have changed since it was last called.
+    pub fn fingerprint<'a>(&mut self, chunks: impl IntoIterator<Item = &'a Chunk>) -> Fingerprint {
+        if self.buckets.iter().any(Option::is_none) {
+            let mut changed_leaves = vec![Vec::new(); BUCKETS];
+            for chunk in chunks {
+                let bucket = chunk.hash[0] as usize;
+                if self.buckets[bucket].is_none() {
+                    changed_leaves[bucket].push(leaf_hash(chunk));
+                }
+            }
+
+            for (bucket, mut leaves) in changed_leaves.into_iter().enumerate() {
+                if self.buckets[bucket].is_none() {
+                    // Sort the leaves so the root doesn't depend on the iteration order.
+                    leaves.sort_unstable_by(|left, right| left.as_bytes().cmp(right.as_bytes()));
+                    self.buckets[bucket] = Some(merkle_root(leaves));
+                }
+            }
+        }
+
+        let bucket_roots = self.buckets.iter().map(|root| root.unwrap()).collect();
+        Fingerprint(*merkle_root(bucket_roots).as_bytes())
+    }
+}
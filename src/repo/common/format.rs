@@ -0,0 +1,120 @@
+use std::io::{Read, Write};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use uuid::Uuid;
+
+/// A format for serializing values in a repository.
+///
+/// Repositories which store values, like [`ValueRepo`] and [`FileRepo`], accept a `Format` type
+/// parameter which determines how those values are serialized. The default is [`MessagePack`],
+/// which is compact and fast. Other formats are provided through cargo features, like [`Json`],
+/// which is useful for inspecting the contents of a repository while debugging.
+///
+/// The format is part of a repository's [`VersionId`], so opening a repository with a different
+/// format than it was created with returns `Error::UnsupportedRepo` instead of failing to
+/// deserialize values.
+///
+/// [`ValueRepo`]: crate::repo::value::ValueRepo
+/// [`FileRepo`]: crate::repo::file::FileRepo
+/// [`MessagePack`]: crate::repo::MessagePack
+/// [`Json`]: crate::repo::Json
+/// [`VersionId`]: crate::repo::VersionId
+pub trait Format {
+    /// A UUID which uniquely identifies this format.
+    ///
+    /// This is combined with the [`OpenRepo::VERSION_ID`] of repositories which use this format.
+    /// Any backwards-incompatible change to the serialized data format must change this value.
+    ///
+    /// [`OpenRepo::VERSION_ID`]: crate::repo::OpenRepo::VERSION_ID
+    const ID: Uuid;
+
+    /// Serialize the given `value` to `writer`.
+    ///
+    /// # Errors
+    /// - `Error::Serialize`: The value could not be serialized or written.
+    fn serialize<T: Serialize + ?Sized, W: Write>(value: &T, writer: W) -> crate::Result<()>;
+
+    /// Deserialize a value from `reader`.
+    ///
+    /// # Errors
+    /// - `Error::Deserialize`: The value could not be read or deserialized.
+    fn deserialize<T: DeserializeOwned, R: Read>(reader: R) -> crate::Result<T>;
+
+    /// Serialize the given `value` to a buffer.
+    ///
+    /// # Errors
+    /// - `Error::Serialize`: The value could not be serialized.
+    fn to_vec<T: Serialize + ?Sized>(value: &T) -> crate::Result<Vec<u8>> {
+        let mut buffer = Vec::new();
+        Self::serialize(value, &mut buffer)?;
+        Ok(buffer)
+    }
+}
+
+/// A [`Format`] which serializes values as [MessagePack](https://msgpack.org/).
+///
+/// This is the default format.
+///
+/// [`Format`]: crate::repo::Format
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum MessagePack {}
+
+impl Format for MessagePack {
+    // This is nil so that the version IDs of repositories which predate pluggable formats don't
+    // change.
+    const ID: Uuid = Uuid::nil();
+
+    fn serialize<T: Serialize + ?Sized, W: Write>(value: &T, mut writer: W) -> crate::Result<()> {
+        rmp_serde::encode::write(&mut writer, value).map_err(|_| crate::Error::Serialize)
+    }
+
+    fn deserialize<T: DeserializeOwned, R: Read>(reader: R) -> crate::Result<T> {
+        rmp_serde::from_read(reader).map_err(|_| crate::Error::Deserialize)
+    }
+}
+
+/// A [`Format`] which serializes values as [CBOR](https://cbor.io/).
+///
+/// [`Format`]: crate::repo::Format
+#[cfg(feature = "format-cbor")]
+#[cfg_attr(docsrs, doc(cfg(feature = "format-cbor")))]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Cbor {}
+
+#[cfg(feature = "format-cbor")]
+impl Format for Cbor {
+    const ID: Uuid = uuid::uuid!("0b1d3f4e-5a6c-4e8b-9d2f-7c4a1e3b5d60");
+
+    fn serialize<T: Serialize + ?Sized, W: Write>(value: &T, writer: W) -> crate::Result<()> {
+        ciborium::ser::into_writer(value, writer).map_err(|_| crate::Error::Serialize)
+    }
+
+    fn deserialize<T: DeserializeOwned, R: Read>(reader: R) -> crate::Result<T> {
+        ciborium::de::from_reader(reader).map_err(|_| crate::Error::Deserialize)
+    }
+}
+
+/// A [`Format`] which serializes values as JSON.
+///
+/// This is less compact and slower than the other formats, but the serialized values are human
+/// readable, which is useful for debugging.
+///
+/// [`Format`]: crate::repo::Format
+#[cfg(feature = "format-json")]
+#[cfg_attr(docsrs, doc(cfg(feature = "format-json")))]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Json {}
+
+#[cfg(feature = "format-json")]
+impl Format for Json {
+    const ID: Uuid = uuid::uuid!("e6a2c8f1-3b7d-4d59-a0e4-8f2b6c1d9a37");
+
+    fn serialize<T: Serialize + ?Sized, W: Write>(value: &T, writer: W) -> crate::Result<()> {
+        serde_json::to_writer(writer, value).map_err(|_| crate::Error::Serialize)
+    }
+
+    fn deserialize<T: DeserializeOwned, R: Read>(reader: R) -> crate::Result<T> {
+        serde_json::from_reader(reader).map_err(|_| crate::Error::Deserialize)
+    }
+}
@@ -1,8 +1,11 @@
 use std::cmp::min;
+use std::collections::BTreeMap;
+use std::fmt;
 use std::io::{self, Read};
 use std::ops::Range;
 
-use serde::{Deserialize, Serialize};
+use serde::de::{IgnoredAny, MapAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize};
 
 use super::metadata::RepoId;
 
@@ -72,6 +75,10 @@ pub struct ObjectHandle {
 
     /// The extents which make up the object.
     pub extents: Vec<Extent>,
+
+    /// The user-defined attributes of the object.
+    #[serde(default)]
+    pub attributes: BTreeMap<String, Vec<u8>>,
 }
 
 impl ObjectHandle {
@@ -89,12 +96,47 @@ impl ObjectHandle {
     }
 }
 
+/// The object handles in a serialized object map, without their keys.
+///
+/// This allows the object map of an instance to be read without knowing its key type.
+#[derive(Debug)]
+pub struct ObjectHandles(pub Vec<ObjectHandle>);
+
+impl<'de> Deserialize<'de> for ObjectHandles {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct HandlesVisitor;
+
+        impl<'de> Visitor<'de> for HandlesVisitor {
+            type Value = ObjectHandles;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a map of object handles")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                let mut handles = Vec::with_capacity(map.size_hint().unwrap_or(0));
+                while let Some((_, handle)) = map.next_entry::<IgnoredAny, ObjectHandle>()? {
+                    handles.push(handle);
+                }
+                Ok(ObjectHandles(handles))
+            }
+        }
+
+        deserializer.deserialize_map(HandlesVisitor)
+    }
+}
+
 /// A value that represents the identity of an object.
 ///
 /// This value can be used to determine if two [`Object`] or [`ReadOnlyObject`] instances refer to
 /// the same underlying object in the repository. This is different from a [`ContentId`], which is
 /// used to compare the contents of objects.
 ///
+/// An `ObjectId` can be serialized, which allows it to be stored in another object as a reference
+/// to this one. It stays the same when the object is renamed or its contents change, and it can
+/// be resolved back to the object with [`KeyRepo::resolve`]. Once an object is removed, its
+/// `ObjectId` may be reused by a new object.
+///
 /// # Examples
 /// ```
 /// # use acid_store::repo::{OpenOptions, OpenMode};
@@ -117,13 +159,14 @@ impl ObjectHandle {
 /// [`Object`]: crate::repo::Object
 /// [`ReadOnlyObject`]: crate::repo::ReadOnlyObject
 /// [`ContentId`]: crate::repo::ContentId
-#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+/// [`KeyRepo::resolve`]: crate::repo::key::KeyRepo::resolve
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize)]
 pub struct ObjectId {
     // We need to store the repository ID because object handle IDs are only unique within the same
     // repository. However, they are unique among all instances of a repository, so we do not need
     // to store the instance ID.
-    repo_id: RepoId,
-    handle_id: HandleId,
+    pub(super) repo_id: RepoId,
+    pub(super) handle_id: HandleId,
 }
 
 impl ObjectId {
@@ -148,8 +191,19 @@ impl ObjectId {
 /// `ContentId` is opaque, but it can be serialized and deserialized. The value of a `ContentId` is
 /// stable, meaning that they can be compared across invocations of the library.
 ///
+/// # Storing content IDs
+///
+/// Applications which keep their own index of the data in a repository, like a database of
+/// uploaded files, can store content IDs outside the repository. [`to_bytes`] returns a compact
+/// binary encoding of a content ID which is stable across versions of this library, and
+/// [`from_bytes`] decodes it again. A stored content ID can be used to find the objects in a
+/// repository which have those contents with [`KeyRepo::find_by_content_id`].
+///
 /// [`compare_contents`]: crate::repo::ContentId::compare_contents
 /// [`Object::set_len`]: crate::repo::Object::set_len
+/// [`to_bytes`]: crate::repo::ContentId::to_bytes
+/// [`from_bytes`]: crate::repo::ContentId::from_bytes
+/// [`KeyRepo::find_by_content_id`]: crate::repo::key::KeyRepo::find_by_content_id
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Serialize, Deserialize)]
 pub struct ContentId {
     // We can't compare content IDs from different repositories because those repositories may have
@@ -166,11 +220,51 @@ pub struct ContentId {
 const HOLE_BUFFER: usize = 4096;
 
 impl ContentId {
+    /// Encode this content ID as bytes.
+    ///
+    /// The encoding is stable across versions of this library, so the returned bytes can be stored
+    /// by other applications and decoded later with [`from_bytes`]. Two content IDs are equal if
+    /// and only if their encodings are equal.
+    ///
+    /// [`from_bytes`]: crate::repo::ContentId::from_bytes
+    pub fn to_bytes(&self) -> Vec<u8> {
+        rmp_serde::to_vec(self).expect("Could not serialize the content ID.")
+    }
+
+    /// Decode a content ID from bytes returned by [`to_bytes`].
+    ///
+    /// # Errors
+    /// - `Error::Deserialize`: The bytes are not a valid content ID.
+    ///
+    /// [`to_bytes`]: crate::repo::ContentId::to_bytes
+    pub fn from_bytes(bytes: &[u8]) -> crate::Result<Self> {
+        rmp_serde::from_slice(bytes).map_err(|_| crate::Error::Deserialize)
+    }
+
+    /// The ID of the repository this content ID was created in.
+    pub fn repo_id(&self) -> RepoId {
+        self.repo_id
+    }
+
     /// The size of the contents represented by this content ID in bytes.
     pub fn size(&self) -> u64 {
         self.extents.iter().map(|extent| extent.size()).sum()
     }
 
+    /// Return the IDs of the chunks which make up the contents represented by this content ID.
+    ///
+    /// Chunks are returned in the order they appear in the contents. Sparse holes are not
+    /// stored as chunks, so they are not included.
+    pub fn chunk_ids(&self) -> Vec<ChunkId> {
+        self.extents
+            .iter()
+            .filter_map(|extent| match extent {
+                Extent::Chunk(chunk) => Some(ChunkId(*chunk)),
+                Extent::Hole { .. } => None,
+            })
+            .collect()
+    }
+
     /// Return whether this content ID has the same contents as `other`.
     ///
     /// This compares the contents of this content ID with `other` without reading any data from the
@@ -250,6 +344,31 @@ impl ContentId {
     }
 }
 
+/// A value which uniquely identifies a chunk of data.
+///
+/// Data in a repository is deduplicated by splitting it into chunks. A `ChunkId` identifies one of
+/// those chunks by a checksum of its contents, so two chunks with the same `ChunkId` contain the
+/// same data, even if they are in different repositories. You can get the `ChunkId` values for
+/// some data with [`ContentId::chunk_ids`].
+///
+/// This can be used to determine which chunks of some data a repository already has before
+/// sending that data to it with [`KeyRepo::contains_chunk`].
+///
+/// `ChunkId` is opaque, but it can be serialized and deserialized. The value of a `ChunkId` is
+/// stable, meaning that they can be compared across invocations of the library.
+///
+/// [`ContentId::chunk_ids`]: crate::repo::ContentId::chunk_ids
+/// [`KeyRepo::contains_chunk`]: crate::repo::key::KeyRepo::contains_chunk
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize)]
+pub struct ChunkId(pub(super) Chunk);
+
+impl ChunkId {
+    /// The size of the chunk in bytes.
+    pub fn size(&self) -> u64 {
+        self.0.size as u64
+    }
+}
+
 /// Statistics about an [`Object`] or [`ReadOnlyObject`].
 ///
 /// [`Object`]: crate::repo::Object
@@ -259,6 +378,10 @@ pub struct ObjectStats {
     pub(super) apparent_size: u64,
     pub(super) actual_size: u64,
     pub(super) holes: Vec<Range<u64>>,
+    pub(super) chunks: u64,
+    pub(super) compressed_size: Option<u64>,
+    pub(super) shared_chunks: u64,
+    pub(super) packs: Option<u64>,
 }
 
 impl ObjectStats {
@@ -286,10 +409,69 @@ impl ObjectStats {
     /// The locations of sparse holes in the object.
     ///
     /// This returns a slice of the ranges of bytes which are sparse holes created with
-    /// [`Object::set_len`].
+    /// [`Object::set_len`] or [`Object::punch_hole`].
     ///
     /// [`Object::set_len`]: crate::repo::Object::set_len
+    /// [`Object::punch_hole`]: crate::repo::Object::punch_hole
     pub fn holes(&self) -> &[Range<u64>] {
         &self.holes
     }
+
+    /// The number of chunks which make up the object.
+    ///
+    /// If the same chunk appears more than once in the object, it is counted each time. Compare
+    /// this with the [`actual_size`] to find the average chunk size, which is determined by
+    /// [`RepoConfig::chunking`].
+    ///
+    /// [`actual_size`]: crate::repo::ObjectStats::actual_size
+    /// [`RepoConfig::chunking`]: crate::repo::RepoConfig::chunking
+    pub fn chunks(&self) -> u64 {
+        self.chunks
+    }
+
+    /// The size of the object's data after it was compressed.
+    ///
+    /// This is the compressed counterpart of the [`actual_size`]. It does not account for
+    /// encryption or for the padding in packs.
+    ///
+    /// This returns `None` if the object contains data which was written by an older version of
+    /// this library, which did not record the compressed size.
+    ///
+    /// [`actual_size`]: crate::repo::ObjectStats::actual_size
+    pub fn compressed_size(&self) -> Option<u64> {
+        self.compressed_size
+    }
+
+    /// The ratio of the [`actual_size`] to the [`compressed_size`].
+    ///
+    /// Values greater than `1.0` mean compression reduced the size of the data. This returns
+    /// `None` if the compressed size is unknown or the object contains no data.
+    ///
+    /// [`actual_size`]: crate::repo::ObjectStats::actual_size
+    /// [`compressed_size`]: crate::repo::ObjectStats::compressed_size
+    pub fn compression_ratio(&self) -> Option<f64> {
+        match self.compressed_size {
+            Some(compressed_size) if compressed_size > 0 => {
+                Some(self.actual_size as f64 / compressed_size as f64)
+            }
+            _ => None,
+        }
+    }
+
+    /// The number of distinct chunks in the object which are also used by other objects.
+    ///
+    /// This includes objects in other instances of the repository. Data in these chunks is
+    /// deduplicated and is only stored once.
+    pub fn shared_chunks(&self) -> u64 {
+        self.shared_chunks
+    }
+
+    /// The number of distinct packs the object's data is stored in.
+    ///
+    /// This returns `None` if packing is disabled. See [`RepoConfig::packing`].
+    ///
+    /// [`RepoConfig::packing`]: crate::repo::RepoConfig::packing
+    pub fn packs(&self) -> Option<u64> {
+        self.packs
+    }
 }
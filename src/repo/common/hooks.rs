@@ -0,0 +1,86 @@
+use std::fmt::{self, Debug, Formatter};
+
+use super::metadata::RepoInfo;
+
+/// An event in a repository which a hook can be registered for.
+///
+/// Hooks are registered with [`KeyRepo::on_event`].
+///
+/// [`KeyRepo::on_event`]: crate::repo::key::KeyRepo::on_event
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum RepoEvent {
+    /// Changes are about to be committed.
+    ///
+    /// This happens before anything is written to the data store for the commit.
+    PreCommit,
+
+    /// Changes were committed successfully.
+    PostCommit,
+
+    /// The repository was cleaned successfully.
+    PostClean,
+}
+
+/// A value which identifies a hook registered with [`KeyRepo::on_event`].
+///
+/// This can be passed to [`KeyRepo::remove_hook`] to remove the hook.
+///
+/// [`KeyRepo::on_event`]: crate::repo::key::KeyRepo::on_event
+/// [`KeyRepo::remove_hook`]: crate::repo::key::KeyRepo::remove_hook
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct HookId(u64);
+
+/// A callback which is called when an event happens in a repository.
+pub type HookCallback = Box<dyn FnMut(&RepoInfo) + Send>;
+
+/// A callback registered for an event.
+struct Hook {
+    id: HookId,
+    event: RepoEvent,
+    callback: HookCallback,
+}
+
+/// The hooks registered with a repository.
+///
+/// These are not persisted and are shared between all instances of an open repository.
+#[derive(Default)]
+pub struct Hooks {
+    next_id: u64,
+    hooks: Vec<Hook>,
+}
+
+impl Debug for Hooks {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Hooks")
+            .field("count", &self.hooks.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Hooks {
+    /// Register `callback` to be called each time `event` happens and return its ID.
+    pub fn add(&mut self, event: RepoEvent, callback: HookCallback) -> HookId {
+        let id = HookId(self.next_id);
+        self.next_id += 1;
+        self.hooks.push(Hook {
+            id,
+            event,
+            callback,
+        });
+        id
+    }
+
+    /// Remove the hook with the given `id` and return whether it was registered.
+    pub fn remove(&mut self, id: HookId) -> bool {
+        let original_len = self.hooks.len();
+        self.hooks.retain(|hook| hook.id != id);
+        self.hooks.len() != original_len
+    }
+
+    /// Call each hook registered for `event` in the order they were registered.
+    pub fn run(&mut self, event: RepoEvent, info: &RepoInfo) {
+        for hook in self.hooks.iter_mut().filter(|hook| hook.event == event) {
+            (hook.callback)(info);
+        }
+    }
+}
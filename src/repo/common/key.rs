@@ -39,3 +39,32 @@ impl<'a, K> Iterator for Keys<'a, K> {
 impl<'a, K> FusedIterator for Keys<'a, K> {}
 
 impl<'a, K> ExactSizeIterator for Keys<'a, K> {}
+
+/// An iterator over the keys in a [`KeyRepo`] which start with a given prefix.
+///
+/// This value is created by [`KeyRepo::keys_with_prefix`].
+///
+/// [`KeyRepo`]: crate::repo::key::KeyRepo
+/// [`KeyRepo::keys_with_prefix`]: crate::repo::key::KeyRepo::keys_with_prefix
+#[derive(Debug, Clone)]
+pub struct KeysWithPrefix<'a, K> {
+    pub(super) prefix: &'a str,
+    pub(super) inner: hash_map::Keys<'a, K, Arc<RwLock<ObjectHandle>>>,
+}
+
+impl<'a, K: AsRef<str>> Iterator for KeysWithPrefix<'a, K> {
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let prefix = self.prefix;
+        self.inner
+            .by_ref()
+            .find(|key| key.as_ref().starts_with(prefix))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.inner.size_hint().1)
+    }
+}
+
+impl<'a, K: AsRef<str>> FusedIterator for KeysWithPrefix<'a, K> {}
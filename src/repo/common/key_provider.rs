@@ -0,0 +1,41 @@
+use std::fmt::{self, Debug, Formatter};
+
+/// An external mechanism for protecting a repository's master key.
+///
+/// Data in an encrypted repository is encrypted with a randomly generated master key. Normally,
+/// the master key is stored in the repository encrypted with a key derived from the user's
+/// password. A `KeyProvider` instead wraps the master key using some external mechanism, like an OS
+/// keychain, a TPM, a key management service, or a hardware security key. This allows a repository
+/// to be opened without a password, such as in unattended server deployments.
+///
+/// A key provider is selected with [`OpenOptions::key_provider`] when a repository is created.
+/// The wrapped key is stored in the repository's metadata, and the same key provider must be used
+/// to unwrap it every time the repository is opened. Because the wrapped key is stored without
+/// any additional encryption, it must be safe to disclose.
+///
+/// [`OpenOptions::key_provider`]: crate::repo::OpenOptions::key_provider
+pub trait KeyProvider: Send + Sync {
+    /// Wrap the given master `key` so that it can be stored in the repository.
+    ///
+    /// # Errors
+    /// - `Error::Io`: The external mechanism could not be accessed.
+    fn wrap_key(&self, key: &[u8]) -> crate::Result<Vec<u8>>;
+
+    /// Unwrap a master key which was previously wrapped with [`wrap_key`].
+    ///
+    /// Implementations should authenticate the `wrapped_key` so that using the wrong key provider
+    /// is detected.
+    ///
+    /// # Errors
+    /// - `Error::Password`: The key could not be unwrapped by this key provider.
+    /// - `Error::Io`: The external mechanism could not be accessed.
+    ///
+    /// [`wrap_key`]: crate::repo::KeyProvider::wrap_key
+    fn unwrap_key(&self, wrapped_key: &[u8]) -> crate::Result<Vec<u8>>;
+}
+
+impl Debug for dyn KeyProvider {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("KeyProvider")
+    }
+}
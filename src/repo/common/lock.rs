@@ -1,7 +1,16 @@
 use std::fmt::Debug;
 use std::hash::Hash;
-use std::sync::{Arc, Weak};
+use std::process;
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, Weak};
+use std::thread;
+use std::time::{Duration, SystemTime};
 
+use gethostname::gethostname;
+
+use rmp_serde::{from_read, to_vec};
+use secrecy::ExposeSecret;
+use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use weak_table::WeakHashSet;
 
@@ -40,6 +49,271 @@ impl<T: Eq + Hash> LockTable<T> {
     }
 }
 
+/// The bytes at the start of the contents of a lock which has a lease.
+///
+/// Locks without a lease contain only the context value, which is what older versions of this
+/// library expect.
+const LEASE_MAGIC: &[u8] = b"acid-store lease\0";
+
+/// The bytes at the start of a lock context which contains a `LockInfo`.
+const LOCK_INFO_MAGIC: &[u8] = b"acid-store lock info\0";
+
+/// How many times a lease is refreshed within its TTL.
+const HEARTBEATS_PER_LEASE: u32 = 3;
+
+/// A lease on a lock which expires unless it is refreshed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Lease {
+    /// The context value of the lock.
+    context: Vec<u8>,
+
+    /// The time at which the lock expires according to the system clock of its holder.
+    expires: SystemTime,
+}
+
+/// The decrypted contents of a lock block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LockContents {
+    /// The context value of the lock.
+    pub context: Vec<u8>,
+
+    /// The time at which the lock expires, or `None` if it doesn't have a lease.
+    pub expires: Option<SystemTime>,
+}
+
+impl LockContents {
+    /// Create the contents of a lock with the given `context` and optional lease `ttl`.
+    pub fn new(context: &[u8], ttl: Option<Duration>) -> Self {
+        Self {
+            context: context.to_vec(),
+            expires: ttl.map(|ttl| SystemTime::now() + ttl),
+        }
+    }
+
+    /// Parse the contents of a lock from its decrypted `data`.
+    pub fn from_bytes(data: Vec<u8>) -> Self {
+        let lease = data
+            .strip_prefix(LEASE_MAGIC)
+            .and_then(|lease| from_read::<_, Lease>(lease).ok());
+        match lease {
+            Some(Lease { context, expires }) => Self {
+                context,
+                expires: Some(expires),
+            },
+            None => Self {
+                context: data,
+                expires: None,
+            },
+        }
+    }
+
+    /// Serialize the contents of this lock.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self.expires {
+            Some(expires) => {
+                let lease = Lease {
+                    context: self.context.clone(),
+                    expires,
+                };
+                let serialized_lease = to_vec(&lease).expect("Could not serialize the lock lease.");
+                [LEASE_MAGIC, &serialized_lease].concat()
+            }
+            None => self.context.clone(),
+        }
+    }
+
+    /// Return whether this lock has a lease which has expired.
+    pub fn is_expired(&self) -> bool {
+        match self.expires {
+            Some(expires) => expires <= SystemTime::now(),
+            None => false,
+        }
+    }
+}
+
+/// Read the contents of the lock with the given `id`, or `None` if it doesn't exist.
+///
+/// # Errors
+/// - `Error::InvalidData`: Ciphertext verification failed.
+/// - `Error::Store`: An error occurred with the data store.
+pub fn read_lock(
+    store: &mut impl DataStore,
+    encryption: &Encryption,
+    key: &EncryptionKey,
+    id: BlockId,
+) -> crate::Result<Option<LockContents>> {
+    match store
+        .read_block(BlockKey::Lock(id))
+        .map_err(crate::Error::Store)?
+    {
+        Some(encrypted_contents) => Ok(Some(LockContents::from_bytes(
+            encryption.decrypt(&encrypted_contents, key)?,
+        ))),
+        None => Ok(None),
+    }
+}
+
+/// Write the `contents` of the lock with the given `id`.
+///
+/// # Errors
+/// - `Error::Store`: An error occurred with the data store.
+pub fn write_lock(
+    store: &mut impl DataStore,
+    encryption: &Encryption,
+    key: &EncryptionKey,
+    id: BlockId,
+    contents: &LockContents,
+) -> crate::Result<()> {
+    let encrypted_contents = encryption.encrypt(&contents.to_bytes(), key);
+    store
+        .write_block(BlockKey::Lock(id), &encrypted_contents)
+        .map_err(crate::Error::Store)
+}
+
+/// A background thread which periodically refreshes the lease on a lock.
+///
+/// The thread stops when this value is dropped or when the lock is released.
+#[derive(Debug)]
+pub struct Heartbeat {
+    /// A channel which is disconnected to stop the thread.
+    _stop: Sender<()>,
+}
+
+impl Heartbeat {
+    /// Start refreshing the lease on the lock with the given `id` in `store` every so often.
+    ///
+    /// The lease is extended by `ttl` each time it is refreshed. The thread only holds a weak
+    /// reference to `store`, so it stops once the repository is dropped.
+    pub fn start(
+        store: Weak<Mutex<Box<dyn DataStore>>>,
+        encryption: Encryption,
+        key: &EncryptionKey,
+        id: BlockId,
+        ttl: Duration,
+    ) -> Self {
+        let (stop_sender, stop_receiver) = channel::<()>();
+        let key = EncryptionKey::new(key.expose_secret().clone());
+        let interval = ttl / HEARTBEATS_PER_LEASE;
+
+        thread::spawn(move || loop {
+            // This returns early once the `Heartbeat` is dropped.
+            if stop_receiver.recv_timeout(interval) != Err(RecvTimeoutError::Timeout) {
+                break;
+            }
+
+            let store = match store.upgrade() {
+                Some(store) => store,
+                None => break,
+            };
+            let mut store = store.lock().unwrap();
+
+            // The lock is read and written while holding the store so that it isn't recreated if
+            // it's released in the meantime. If there is an error, we try again on the next
+            // heartbeat, which is why the lease is refreshed more than once per TTL.
+            match read_lock(&mut *store, &encryption, &key, id) {
+                Ok(Some(mut contents)) => {
+                    contents.expires = Some(SystemTime::now() + ttl);
+                    write_lock(&mut *store, &encryption, &key, id, &contents).ok();
+                }
+                Ok(None) => break,
+                Err(_) => {}
+            }
+        });
+
+        Self { _stop: stop_sender }
+    }
+}
+
+/// Information about the client which holds a lock on a repository.
+///
+/// This is stored as the context value of a lock when a repository is opened with
+/// [`OpenOptions::lock_description`], so that a lock handler set with [`OpenOptions::locking`] can
+/// tell who holds an existing lock by passing its context to [`LockInfo::from_context`].
+///
+/// # Examples
+///
+/// Report who holds an existing lock on the repository.
+///
+/// ```
+/// # use acid_store::repo::{LockInfo, OpenOptions, OpenMode, key::KeyRepo};
+/// # use acid_store::store::MemoryConfig;
+/// let mut repo: KeyRepo<String> = OpenOptions::new()
+///     .mode(OpenMode::Create)
+///     .lock_description("backup-agent")
+///     .locking(&[], |context| {
+///         if let Some(info) = LockInfo::from_context(context) {
+///             eprintln!(
+///                 "The repository is locked by {} (PID {}) on {}.",
+///                 info.description(),
+///                 info.pid(),
+///                 info.hostname()
+///             );
+///         }
+///         false
+///     })
+///     .open(&MemoryConfig::new())
+///     .unwrap();
+/// ```
+///
+/// [`OpenOptions::lock_description`]: crate::repo::OpenOptions::lock_description
+/// [`OpenOptions::locking`]: crate::repo::OpenOptions::locking
+/// [`LockInfo::from_context`]: crate::repo::LockInfo::from_context
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockInfo {
+    hostname: String,
+    pid: u32,
+    description: String,
+    acquired: SystemTime,
+}
+
+impl LockInfo {
+    /// Create a `LockInfo` for the current process with the given `description`.
+    ///
+    /// The acquisition time is set to the current time.
+    pub fn new(description: &str) -> Self {
+        Self {
+            hostname: gethostname().to_string_lossy().into_owned(),
+            pid: process::id(),
+            description: description.to_owned(),
+            acquired: SystemTime::now(),
+        }
+    }
+
+    /// Parse a `LockInfo` from the context value of a lock.
+    ///
+    /// This returns `None` if the lock was not acquired with a `LockInfo` as its context.
+    pub fn from_context(context: &[u8]) -> Option<Self> {
+        let serialized_info = context.strip_prefix(LOCK_INFO_MAGIC)?;
+        from_read(serialized_info).ok()
+    }
+
+    /// Serialize this `LockInfo` into a lock context value.
+    pub fn to_context(&self) -> Vec<u8> {
+        let serialized_info = to_vec(self).expect("Could not serialize the lock info.");
+        [LOCK_INFO_MAGIC, &serialized_info].concat()
+    }
+
+    /// The hostname of the machine which acquired the lock.
+    pub fn hostname(&self) -> &str {
+        &self.hostname
+    }
+
+    /// The ID of the process which acquired the lock.
+    pub fn pid(&self) -> u32 {
+        self.pid
+    }
+
+    /// The description supplied by the client which acquired the lock.
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+
+    /// The time the lock was acquired according to the system clock of its holder.
+    pub fn acquired(&self) -> SystemTime {
+        self.acquired
+    }
+}
+
 /// A repository which supports locking.
 pub trait Unlock {
     /// Release this repository's lock.
@@ -84,12 +358,29 @@ pub trait Unlock {
     /// [`OpenOptions::locking`]: crate::repo::OpenOptions::locking
     fn context(&self) -> crate::Result<Vec<u8>>;
 
+    /// Get the information about this repository's lock stored in its context value.
+    ///
+    /// This returns `None` if the context value of the lock is not a [`LockInfo`], which is the case
+    /// unless the repository was opened with [`OpenOptions::lock_description`].
+    ///
+    /// # Errors
+    /// - `Error::NotLocked`: This repository is not locked.
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    ///
+    /// [`LockInfo`]: crate::repo::LockInfo
+    /// [`OpenOptions::lock_description`]: crate::repo::OpenOptions::lock_description
+    fn lock_info(&self) -> crate::Result<Option<LockInfo>> {
+        Ok(LockInfo::from_context(&self.context()?))
+    }
+
     /// Update the context value of this repository's lock.
     ///
     /// This method changes the context value associated with this repository's lock on the data
     /// store. This is the same context value which is supplied to [`OpenOptions::locking`].
     ///
-    /// One potential use for this method is to refresh a lease on a timeout-based lock.
+    /// If the repository was opened with a lease via [`OpenOptions::lease`], this also refreshes
+    /// the lease.
     ///
     /// This method is **not** a safe way to re-acquire a released lock. If this repository's lock
     /// has been released by another client via a lock handler, calling this method could cause data
@@ -99,6 +390,7 @@ pub trait Unlock {
     /// - `Error::Store`: An error occurred with the data store.
     ///
     /// [`OpenOptions::locking`]: crate::repo::OpenOptions::locking
+    /// [`OpenOptions::lease`]: crate::repo::OpenOptions::lease
     fn update_context(&self, context: &[u8]) -> crate::Result<()>;
 }
 
@@ -106,6 +398,9 @@ pub trait Unlock {
 ///
 /// This uses a two-phase locking algorithm to avoid race conditions.
 ///
+/// If an existing lock has a lease which has expired, it is removed without invoking `handler`.
+/// If `ttl` is `Some`, the acquired lock has a lease which expires after `ttl`.
+///
 /// This returns the `BlockId` of the block containing the lock or `None` if a lock could not be
 /// acquired.
 ///
@@ -119,6 +414,7 @@ pub fn lock_store<'a>(
     encryption: &Encryption,
     key: &EncryptionKey,
     context: &'a [u8],
+    ttl: Option<Duration>,
     handler: impl FnOnce(&[u8]) -> bool + 'a,
 ) -> crate::Result<BlockId> {
     let current_lock_id = Uuid::new_v4().into();
@@ -134,16 +430,12 @@ pub fn lock_store<'a>(
 
         // There is exactly one existing lock.
         [existing_lock_id] => {
-            let encrypted_existing_lock_context = store
-                .read_block(BlockKey::Lock(existing_lock_id))
-                .map_err(crate::Error::Store)?
-                .ok_or(crate::Error::Locked)?;
-            let existing_lock_context =
-                encryption.decrypt(&encrypted_existing_lock_context, key)?;
-
-            // Invoke the lock handler with the existing lock's lock ID to see if it should be
-            // removed.
-            if handler(existing_lock_context.as_slice()) {
+            let existing_lock =
+                read_lock(store, encryption, key, existing_lock_id)?.ok_or(crate::Error::Locked)?;
+
+            // A lock whose lease has expired is stale. Otherwise, invoke the lock handler with the
+            // existing lock's context to see if it should be removed.
+            if existing_lock.is_expired() || handler(existing_lock.context.as_slice()) {
                 store
                     .remove_block(BlockKey::Lock(existing_lock_id))
                     .map_err(crate::Error::Store)?;
@@ -159,13 +451,13 @@ pub fn lock_store<'a>(
     }
 
     // Acquire a lock on the repository.
-    let encrypted_current_lock_context = encryption.encrypt(context, key);
-    store
-        .write_block(
-            BlockKey::Lock(current_lock_id),
-            &encrypted_current_lock_context,
-        )
-        .map_err(crate::Error::Store)?;
+    write_lock(
+        store,
+        encryption,
+        key,
+        current_lock_id,
+        &LockContents::new(context, ttl),
+    )?;
 
     // Check if any new locks have been acquired since we last checked.
     let existing_locks = store
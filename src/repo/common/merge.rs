@@ -0,0 +1,83 @@
+use std::collections::{HashMap, HashSet};
+
+/// A way in which an object was changed differently in two repositories being merged.
+///
+/// See [`KeyRepo::merge`] and [`ValueRepo::merge`] for details.
+///
+/// [`KeyRepo::merge`]: crate::repo::key::KeyRepo::merge
+/// [`ValueRepo::merge`]: crate::repo::value::ValueRepo::merge
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum MergeConflict {
+    /// The object was changed in both repositories and their contents differ.
+    BothChanged,
+
+    /// The object was added to both repositories and their contents differ.
+    BothAdded,
+
+    /// The object was changed in this repository and removed from the other repository.
+    ChangedAndRemoved,
+
+    /// The object was removed from this repository and changed in the other repository.
+    RemovedAndChanged,
+}
+
+impl MergeConflict {
+    /// Classify a conflicting change based on whether the object exists in the ancestor, this
+    /// repository, and the other repository.
+    pub(crate) fn from_presence(base: bool, ours: bool, theirs: bool) -> Self {
+        match (base, ours, theirs) {
+            (false, _, _) => MergeConflict::BothAdded,
+            (true, true, false) => MergeConflict::ChangedAndRemoved,
+            (true, false, true) => MergeConflict::RemovedAndChanged,
+            _ => MergeConflict::BothChanged,
+        }
+    }
+}
+
+/// The result of merging another repository into this one with [`KeyRepo::merge`] or
+/// [`ValueRepo::merge`].
+///
+/// [`KeyRepo::merge`]: crate::repo::key::KeyRepo::merge
+/// [`ValueRepo::merge`]: crate::repo::value::ValueRepo::merge
+#[derive(Debug, Clone)]
+pub struct MergeReport<K> {
+    pub(crate) updated: HashSet<K>,
+    pub(crate) removed: HashSet<K>,
+    pub(crate) conflicts: HashMap<K, MergeConflict>,
+}
+
+impl<K> Default for MergeReport<K> {
+    fn default() -> Self {
+        Self {
+            updated: HashSet::new(),
+            removed: HashSet::new(),
+            conflicts: HashMap::new(),
+        }
+    }
+}
+
+impl<K> MergeReport<K> {
+    /// The keys of objects which were added or replaced with the contents from the other
+    /// repository.
+    pub fn updated(&self) -> &HashSet<K> {
+        &self.updated
+    }
+
+    /// The keys of objects which were removed because they were removed from the other
+    /// repository.
+    pub fn removed(&self) -> &HashSet<K> {
+        &self.removed
+    }
+
+    /// The keys of objects which could not be merged automatically and how they conflict.
+    ///
+    /// These objects are left as they are in this repository.
+    pub fn conflicts(&self) -> &HashMap<K, MergeConflict> {
+        &self.conflicts
+    }
+
+    /// Return whether there were no conflicts.
+    pub fn is_clean(&self) -> bool {
+        self.conflicts.is_empty()
+    }
+}
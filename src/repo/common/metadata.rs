@@ -1,12 +1,18 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 
-use rmp_serde::from_read;
+use rmp_serde::{from_read, to_vec};
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
+use super::commit_log::CommitRecord;
 use super::config::RepoConfig;
-use super::encryption::{EncryptionKey, KeySalt};
-use super::handle::{Chunk, HandleIdTable};
+use super::encryption::{Encryption, EncryptionKey, KeyDerivation, KeySalt, ResourceLimit};
+use super::fingerprint::Fingerprint;
+use super::handle::{Chunk, HandleIdTable, ObjectHandle};
+use super::key_provider::KeyProvider;
+use super::open_options::VERSION_ID;
 use super::state::{ChunkInfo, InstanceId, InstanceInfo, PackIndex};
+use super::upgrade::{read_version, upgrade_metadata, Migration, MIGRATIONS};
 use crate::store::{BlockId, BlockKey, DataStore, OpenStore};
 
 /// The repository state which is persisted to the data store on each commit.
@@ -23,6 +29,14 @@ pub struct Header {
 
     /// The table of object handle IDs.
     pub handle_table: HandleIdTable,
+
+    /// A map of checkpoint names to the IDs of the header blocks they were saved to.
+    #[serde(default)]
+    pub checkpoints: BTreeMap<String, BlockId>,
+
+    /// The log of commits to the repository if `RepoConfig::commit_log` is enabled.
+    #[serde(default)]
+    pub commit_log: Vec<CommitRecord>,
 }
 
 /// Metadata for a repository.
@@ -34,7 +48,7 @@ pub struct RepoMetadata {
     /// The configuration for the repository.
     pub config: RepoConfig,
 
-    /// The master encryption key encrypted with the user's password.
+    /// The master encryption key encrypted with the user's password or wrapped by a key provider.
     pub master_key: Vec<u8>,
 
     /// The salt used to derive a key from the user's password.
@@ -42,42 +56,269 @@ pub struct RepoMetadata {
 
     /// The ID of the chunk which stores the repository header.
     pub header_id: BlockId,
+
+    /// The ID of the chunk which stores the header written by the most recent flush.
+    ///
+    /// This is `None` if changes have not been flushed since the last commit.
+    #[serde(default)]
+    pub flushed_header_id: Option<BlockId>,
+
+    /// The IDs of the headers of previous commits which are being retained, newest first.
+    ///
+    /// This does not include `header_id`.
+    #[serde(default)]
+    pub previous_header_ids: Vec<BlockId>,
+
+    /// Whether the master encryption key is wrapped by a `KeyProvider` instead of a password.
+    #[serde(default)]
+    pub external_key: bool,
+
+    /// The key used for convergent encryption encrypted with the master key.
+    ///
+    /// This is empty if convergent encryption is disabled.
+    #[serde(default)]
+    pub convergence_key: Vec<u8>,
+
+    /// The fingerprint of the data in the repository as of the most recent commit.
+    ///
+    /// This is `None` for repositories which have not been committed since fingerprints were
+    /// introduced.
+    #[serde(default)]
+    pub fingerprint: Option<Fingerprint>,
+
+    /// The ID of the header which stores a backup of the metadata from before the repository was
+    /// last upgraded to a new format version.
+    ///
+    /// This is `None` if the repository has never been upgraded.
+    #[serde(default)]
+    pub upgrade_backup_id: Option<BlockId>,
 }
 
-impl RepoMetadata {
+/// The first byte of a super block which stores encrypted metadata.
+///
+/// This byte is never used in MessagePack, so it can't be the first byte of plaintext metadata.
+const SEALED_TAG: u8 = 0xc1;
+
+/// The parts of the repository metadata which are needed to decrypt the master key.
+///
+/// These are stored in plaintext even when `RepoConfig::encrypt_metadata` is enabled.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyMetadata {
+    /// The encryption method used in the repository.
+    pub encryption: Encryption,
+
+    /// The memory limit for the key derivation function.
+    pub memory_limit: ResourceLimit,
+
+    /// The operations limit for the key derivation function.
+    pub operations_limit: ResourceLimit,
+
+    /// The explicit key derivation function, if there is one.
+    pub key_derivation: Option<KeyDerivation>,
+
+    /// The master encryption key encrypted with the user's password or wrapped by a key provider.
+    pub master_key: Vec<u8>,
+
+    /// The salt used to derive a key from the user's password.
+    pub salt: KeySalt,
+
+    /// Whether the master encryption key is wrapped by a `KeyProvider` instead of a password.
+    pub external_key: bool,
+}
+
+impl KeyMetadata {
     /// Decrypt and return the master encryption key.
     ///
     /// # Errors
     /// - `Error::Password`: The password provided is invalid.
+    /// - `Error::InvalidConfig`: The key derivation parameters are invalid.
     pub fn decrypt_master_key(&self, password: &[u8]) -> crate::Result<EncryptionKey> {
-        let user_key = EncryptionKey::derive(
-            password,
-            &self.salt,
-            self.config.encryption.key_size(),
-            self.config.memory_limit,
-            self.config.operations_limit,
-        );
+        let config = RepoConfig {
+            encryption: self.encryption.clone(),
+            memory_limit: self.memory_limit,
+            operations_limit: self.operations_limit,
+            key_derivation: self.key_derivation,
+            ..RepoConfig::default()
+        };
+        let user_key = config.derive_key(password, &self.salt)?;
         Ok(EncryptionKey::new(
-            self.config
-                .encryption
+            self.encryption
                 .decrypt(&self.master_key, &user_key)
                 .map_err(|_| crate::Error::Password)?,
         ))
     }
+
+    /// Unwrap and return the master encryption key using the given `key_provider`.
+    ///
+    /// # Errors
+    /// - `Error::Password`: The key could not be unwrapped by `key_provider`.
+    /// - `Error::Io`: An I/O error occurred.
+    pub fn unwrap_master_key(
+        &self,
+        key_provider: &dyn KeyProvider,
+    ) -> crate::Result<EncryptionKey> {
+        let master_key = key_provider.unwrap_key(&self.master_key)?;
+        if master_key.len() != self.encryption.key_size() {
+            return Err(crate::Error::Password);
+        }
+        Ok(EncryptionKey::new(master_key))
+    }
+}
+
+/// Repository metadata which is encrypted with the master key.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SealedMetadata {
+    /// The metadata needed to decrypt the master key.
+    pub key: KeyMetadata,
+
+    /// The serialized `RepoMetadata` encrypted with the master key.
+    pub metadata: Vec<u8>,
+}
+
+/// The repository metadata as it is stored in the super block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SuperBlock {
+    /// The metadata is stored in plaintext.
+    Plain(RepoMetadata),
+
+    /// The metadata is encrypted with the master key.
+    Sealed(SealedMetadata),
+}
+
+impl SuperBlock {
+    /// Deserialize the contents of the super block from `data`.
+    ///
+    /// # Errors
+    /// - `Error::Corrupt`: The super block is corrupt.
+    pub fn from_bytes(data: &[u8]) -> crate::Result<Self> {
+        match data.split_first() {
+            Some((&SEALED_TAG, sealed)) => Ok(SuperBlock::Sealed(
+                from_read(sealed).map_err(|_| crate::Error::Corrupt)?,
+            )),
+            _ => Ok(SuperBlock::Plain(
+                from_read(data).map_err(|_| crate::Error::Corrupt)?,
+            )),
+        }
+    }
+
+    /// Return the metadata needed to decrypt the master key.
+    pub fn key_metadata(&self) -> KeyMetadata {
+        match self {
+            SuperBlock::Plain(metadata) => metadata.key_metadata(),
+            SuperBlock::Sealed(sealed) => sealed.key.clone(),
+        }
+    }
+
+    /// Return the repository metadata, decrypting it with `master_key` if it's encrypted.
+    ///
+    /// # Errors
+    /// - `Error::Corrupt`: The metadata could not be decrypted or deserialized.
+    pub fn open(self, master_key: &EncryptionKey) -> crate::Result<RepoMetadata> {
+        match self {
+            SuperBlock::Plain(metadata) => Ok(metadata),
+            SuperBlock::Sealed(sealed) => {
+                let serialized_metadata = sealed
+                    .key
+                    .encryption
+                    .decrypt(&sealed.metadata, master_key)
+                    .map_err(|_| crate::Error::Corrupt)?;
+                from_read(serialized_metadata.as_slice()).map_err(|_| crate::Error::Corrupt)
+            }
+        }
+    }
+
+    /// Return the repository metadata if it is stored in plaintext.
+    ///
+    /// # Errors
+    /// - `Error::Password`: The metadata is encrypted.
+    pub fn into_plain(self) -> crate::Result<RepoMetadata> {
+        match self {
+            SuperBlock::Plain(metadata) => Ok(metadata),
+            SuperBlock::Sealed(_) => Err(crate::Error::Password),
+        }
+    }
+}
+
+impl RepoMetadata {
+    /// Return the parts of this metadata which are needed to decrypt the master key.
+    pub fn key_metadata(&self) -> KeyMetadata {
+        KeyMetadata {
+            encryption: self.config.encryption.clone(),
+            memory_limit: self.config.memory_limit,
+            operations_limit: self.config.operations_limit,
+            key_derivation: self.config.key_derivation,
+            master_key: self.master_key.clone(),
+            salt: self.salt.clone(),
+            external_key: self.external_key,
+        }
+    }
+
+    /// Serialize this metadata to be stored in the super block.
+    ///
+    /// If `RepoConfig::encrypt_metadata` is enabled, the metadata is encrypted with `master_key`.
+    pub fn to_super_block(&self, master_key: &EncryptionKey) -> Vec<u8> {
+        let serialized_metadata = to_vec(self).expect("Could not serialize repository metadata.");
+        if !self.config.encrypt_metadata {
+            return serialized_metadata;
+        }
+        let sealed = SealedMetadata {
+            key: self.key_metadata(),
+            metadata: self
+                .config
+                .encryption
+                .encrypt(&serialized_metadata, master_key),
+        };
+        let mut super_block = vec![SEALED_TAG];
+        super_block.extend(to_vec(&sealed).expect("Could not serialize repository metadata."));
+        super_block
+    }
+
+    /// Decrypt and return the convergence key using the given `master_key`.
+    ///
+    /// This returns `None` if convergent encryption is disabled.
+    ///
+    /// # Errors
+    /// - `Error::Corrupt`: The convergence key could not be decrypted.
+    pub fn decrypt_convergence_key(
+        &self,
+        master_key: &EncryptionKey,
+    ) -> crate::Result<Option<EncryptionKey>> {
+        if !self.config.convergent_encryption {
+            return Ok(None);
+        }
+        let convergence_key = self
+            .config
+            .encryption
+            .decrypt(&self.convergence_key, master_key)
+            .map_err(|_| crate::Error::Corrupt)?;
+        Ok(Some(EncryptionKey::new(convergence_key)))
+    }
 }
 
 impl RepoMetadata {
     /// Create a `RepoInfo` using the metadata in this struct.
+    ///
+    /// This assumes the repository uses the current format version.
     pub fn to_info(&self) -> RepoInfo {
         RepoInfo {
             id: self.id,
             config: self.config.clone(),
+            fingerprint: self.fingerprint,
+            format_version: VERSION_ID,
         }
     }
 }
 
 /// Return information about the repository in the given `store` without opening it.
 pub fn peek_info_store(store: &mut impl DataStore) -> crate::Result<RepoInfo> {
+    read_info(store, MIGRATIONS)
+}
+
+/// Return information about the repository in `store`, upgrading its metadata in memory using
+/// `migrations` if it uses a previous format version.
+pub fn read_info(store: &mut impl DataStore, migrations: &[Migration]) -> crate::Result<RepoInfo> {
+    let format_version = read_version(store)?;
+
     // Read and deserialize the metadata.
     let serialized_metadata = match store
         .read_block(BlockKey::Super)
@@ -86,19 +327,33 @@ pub fn peek_info_store(store: &mut impl DataStore) -> crate::Result<RepoInfo> {
         Some(data) => data,
         None => return Err(crate::Error::NotFound),
     };
-    let metadata: RepoMetadata =
-        from_read(serialized_metadata.as_slice()).map_err(|_| crate::Error::Corrupt)?;
+    let serialized_metadata = upgrade_metadata(migrations, format_version, serialized_metadata)?;
+    let metadata = SuperBlock::from_bytes(&serialized_metadata)?.into_plain()?;
 
-    Ok(metadata.to_info())
+    Ok(RepoInfo {
+        format_version,
+        ..metadata.to_info()
+    })
 }
 
 /// Return information about the repository in a data store without opening it.
 ///
 /// This accepts the `config` used to open the data store.
 ///
+/// This also works for repositories which use a previous format version and need to be upgraded
+/// with [`upgrade_repo`] before they can be opened. See [`RepoInfo::needs_upgrade`].
+///
+/// [`upgrade_repo`]: crate::repo::upgrade_repo
+/// [`RepoInfo::needs_upgrade`]: crate::repo::RepoInfo::needs_upgrade
+/// [`RepoConfig::encrypt_metadata`]: crate::repo::RepoConfig::encrypt_metadata
+///
 /// # Errors
 /// - `Error::NotFound`: There is no repository in the data store.
 /// - `Error::Corrupt`: The repository is corrupt. This is most likely unrecoverable.
+/// - `Error::UnsupportedRepo`: The repository uses a format version which can't be upgraded to
+/// the current one.
+/// - `Error::Password`: The repository metadata is encrypted. See
+/// [`RepoConfig::encrypt_metadata`].
 /// - `Error::UnsupportedStore`: The data store is an unsupported format. This can happen if
 /// the serialized data format changed or if the storage represented by this value does not
 /// contain a valid data store.
@@ -123,6 +378,8 @@ uuid_type! {
 pub struct RepoInfo {
     id: RepoId,
     config: RepoConfig,
+    fingerprint: Option<Fingerprint>,
+    format_version: Uuid,
 }
 
 impl RepoInfo {
@@ -135,6 +392,31 @@ impl RepoInfo {
     pub fn config(&self) -> &RepoConfig {
         &self.config
     }
+
+    /// The fingerprint of the data in this repository as of the most recent commit.
+    ///
+    /// This returns `None` if the repository was created by an older version of this library and
+    /// has not been committed since. See [`Fingerprint`] for details.
+    ///
+    /// [`Fingerprint`]: crate::repo::Fingerprint
+    pub fn fingerprint(&self) -> Option<Fingerprint> {
+        self.fingerprint
+    }
+
+    /// The ID of the format version of this repository as it is stored in the data store.
+    pub fn format_version(&self) -> Uuid {
+        self.format_version
+    }
+
+    /// Whether this repository uses a previous format version.
+    ///
+    /// A repository which needs to be upgraded can't be opened until it is upgraded with
+    /// [`upgrade_repo`].
+    ///
+    /// [`upgrade_repo`]: crate::repo::upgrade_repo
+    pub fn needs_upgrade(&self) -> bool {
+        self.format_version != VERSION_ID
+    }
 }
 
 /// Statistics about a repository.
@@ -143,6 +425,8 @@ pub struct RepoStats {
     pub(super) apparent_size: u64,
     pub(super) actual_size: u64,
     pub(super) repo_size: u64,
+    pub(super) apparent_header_size: u64,
+    pub(super) header_size: u64,
 }
 
 impl RepoStats {
@@ -178,4 +462,78 @@ impl RepoStats {
     pub fn repo_size(&self) -> u64 {
         self.repo_size
     }
+
+    /// The size of the repository header before it is compressed and encrypted.
+    ///
+    /// The header stores the index of chunks and packs in the repository. It is rewritten each
+    /// time changes are committed, so its size affects how long commits take. This is the size of
+    /// the header as of the most recent commit.
+    pub fn apparent_header_size(&self) -> u64 {
+        self.apparent_header_size
+    }
+
+    /// The size of the repository header as it is stored in the data store.
+    ///
+    /// This is the size of the header after it is compressed and encrypted according to the
+    /// repository's configuration. Comparing this with [`apparent_header_size`] shows how much the
+    /// header benefits from compression. The maps of objects in each instance are stored as
+    /// ordinary data and are compressed like any other object.
+    ///
+    /// [`apparent_header_size`]: crate::repo::RepoStats::apparent_header_size
+    pub fn header_size(&self) -> u64 {
+        self.header_size
+    }
+}
+
+/// Statistics about a subset of the objects in a repository.
+///
+/// This is returned by [`KeyRepo::instance_stats`] for each instance of a repository and by
+/// [`KeyRepo::stats_for_keys`] for the objects with matching keys.
+///
+/// [`KeyRepo::instance_stats`]: crate::repo::key::KeyRepo::instance_stats
+/// [`KeyRepo::stats_for_keys`]: crate::repo::key::KeyRepo::stats_for_keys
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct UsageStats {
+    pub(super) objects: u64,
+    pub(super) apparent_size: u64,
+    pub(super) actual_size: u64,
+}
+
+impl UsageStats {
+    /// The number of objects.
+    pub fn objects(&self) -> u64 {
+        self.objects
+    }
+
+    /// The sum of the apparent sizes of the objects, which includes any sparse holes in them.
+    pub fn apparent_size(&self) -> u64 {
+        self.apparent_size
+    }
+
+    /// The actual number of bytes stored in the objects.
+    ///
+    /// This may be smaller than the [`apparent_size`] due to sparse holes in objects and
+    /// deduplication between the objects. Data which is shared with objects outside this set is
+    /// counted here as well, so the actual sizes of different sets of objects may add up to more
+    /// than the actual size of the repository.
+    ///
+    /// [`apparent_size`]: crate::repo::UsageStats::apparent_size
+    pub fn actual_size(&self) -> u64 {
+        self.actual_size
+    }
+}
+
+impl UsageStats {
+    /// Compute statistics about the objects with the given `handles`.
+    pub(super) fn from_handles<'a>(handles: impl IntoIterator<Item = &'a ObjectHandle>) -> Self {
+        let mut stats = UsageStats::default();
+        let mut chunks = HashSet::new();
+        for handle in handles {
+            stats.objects += 1;
+            stats.apparent_size += handle.size();
+            chunks.extend(handle.chunks());
+        }
+        stats.actual_size = chunks.iter().map(|chunk| chunk.size as u64).sum();
+        stats
+    }
 }
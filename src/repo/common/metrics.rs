@@ -0,0 +1,108 @@
+#![cfg(feature = "observability")]
+
+use std::fmt::{self, Debug, Formatter};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::store::{BlockId, BlockKey, BlockType, DataStore, StoreOperation};
+
+/// A callback interface for collecting metrics about a repository.
+///
+/// Implement this trait to forward measurements to a metrics system and pass it to
+/// [`OpenOptions::metrics`]. Every method has a default implementation which does nothing, so you
+/// only need to implement the ones you're interested in.
+///
+/// These methods are called synchronously while the repository is performing an operation, so
+/// they should return quickly.
+///
+/// [`OpenOptions::metrics`]: crate::repo::OpenOptions::metrics
+#[cfg_attr(docsrs, doc(cfg(feature = "observability")))]
+pub trait Metrics: Send + Sync {
+    /// Called when a new chunk of `size` bytes is written to the repository.
+    ///
+    /// This is not called for chunks which were deduplicated.
+    fn chunk_written(&self, _size: usize) {}
+
+    /// Called when a chunk of `size` bytes was not written because it already exists in the
+    /// repository.
+    fn chunk_deduplicated(&self, _size: usize) {}
+
+    /// Called when `size` bytes of data are compressed to `compressed_size` bytes.
+    ///
+    /// This is called even if compression is disabled, in which case both sizes are equal.
+    fn data_compressed(&self, _size: usize, _compressed_size: usize) {}
+
+    /// Called when an `operation` on the data store completes after taking `duration`.
+    ///
+    /// This can be used to build a latency histogram for the data store.
+    fn store_operation(&self, _operation: StoreOperation, _duration: Duration, _succeeded: bool) {}
+}
+
+impl Debug for dyn Metrics {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("Metrics")
+    }
+}
+
+/// A `DataStore` which reports the latency of each operation on another data store.
+///
+/// Each operation is also wrapped in a `tracing` span.
+#[derive(Debug)]
+pub struct MetricsStore<S> {
+    store: S,
+    metrics: Option<Arc<dyn Metrics>>,
+}
+
+impl<S: DataStore> MetricsStore<S> {
+    /// Wrap the given `store`, reporting to `metrics` if it is not `None`.
+    pub fn new(store: S, metrics: Option<Arc<dyn Metrics>>) -> Self {
+        MetricsStore { store, metrics }
+    }
+
+    /// Time the given `operation` and report it to the metrics callback.
+    fn measure<T>(
+        &mut self,
+        kind: StoreOperation,
+        operation: impl FnOnce(&mut S) -> crate::store::Result<T>,
+    ) -> crate::store::Result<T> {
+        let start = Instant::now();
+        let result = operation(&mut self.store);
+        if let Some(metrics) = &self.metrics {
+            metrics.store_operation(kind, start.elapsed(), result.is_ok());
+        }
+        result
+    }
+}
+
+impl<S: DataStore> DataStore for MetricsStore<S> {
+    fn write_block(&mut self, key: BlockKey, data: &[u8]) -> crate::store::Result<()> {
+        let _span = tracing::trace_span!("write_block", ?key, size = data.len()).entered();
+        self.measure(StoreOperation::WriteBlock, |store| {
+            store.write_block(key, data)
+        })
+    }
+
+    fn read_block(&mut self, key: BlockKey) -> crate::store::Result<Option<Vec<u8>>> {
+        let _span = tracing::trace_span!("read_block", ?key).entered();
+        self.measure(StoreOperation::ReadBlock, |store| store.read_block(key))
+    }
+
+    fn remove_block(&mut self, key: BlockKey) -> crate::store::Result<()> {
+        let _span = tracing::trace_span!("remove_block", ?key).entered();
+        self.measure(StoreOperation::RemoveBlock, |store| store.remove_block(key))
+    }
+
+    fn list_blocks(&mut self, kind: BlockType) -> crate::store::Result<Vec<BlockId>> {
+        let _span = tracing::trace_span!("list_blocks", ?kind).entered();
+        self.measure(StoreOperation::ListBlocks, |store| store.list_blocks(kind))
+    }
+
+    fn available_space(&mut self) -> crate::store::Result<Option<u64>> {
+        self.store.available_space()
+    }
+
+    fn try_clone(&self) -> Option<Box<dyn DataStore>> {
+        let store = self.store.try_clone()?;
+        Some(Box::new(MetricsStore::new(store, self.metrics.clone())))
+    }
+}
@@ -1,30 +1,58 @@
+pub use self::check::CheckReport;
+pub use self::checkpoint::Checkpoints;
 pub use self::chunking::Chunking;
 pub use self::commit::Commit;
+pub use self::commit_log::CommitRecord;
 pub use self::compression::Compression;
-pub use self::config::RepoConfig;
-pub use self::encryption::{Encryption, ResourceLimit};
-pub use self::handle::{ContentId, ObjectId, ObjectStats};
-pub use self::key::{Key, Keys};
-pub use self::lock::Unlock;
-pub use self::metadata::{peek_info, RepoId, RepoInfo, RepoStats};
-pub use self::object::{Object, ReadOnlyObject};
+pub use self::config::{ConfigPreset, RepoConfig};
+pub use self::encryption::{Encryption, KeyDerivation, ResourceLimit};
+pub use self::export::{export_repo, RepoExport};
+pub use self::fingerprint::Fingerprint;
+#[cfg(feature = "format-cbor")]
+pub use self::format::Cbor;
+#[cfg(feature = "format-json")]
+pub use self::format::Json;
+pub use self::format::{Format, MessagePack};
+pub use self::handle::{ChunkId, ContentId, ObjectId, ObjectStats};
+pub use self::hooks::{HookId, RepoEvent};
+pub use self::key::{Key, Keys, KeysWithPrefix};
+pub use self::key_provider::KeyProvider;
+pub use self::lock::{LockInfo, Unlock};
+pub use self::merge::{MergeConflict, MergeReport};
+pub use self::metadata::{peek_info, RepoId, RepoInfo, RepoStats, UsageStats};
+#[cfg(feature = "observability")]
+pub use self::metrics::Metrics;
+pub use self::object::{Access, Object, ObjectStream, ReadOnlyObject};
 pub use self::open_options::{OpenMode, OpenOptions, DEFAULT_INSTANCE};
 pub use self::open_repo::{OpenRepo, SwitchInstance, VersionId};
-pub use self::packing::Packing;
+pub use self::packing::{Packing, RepackOptions};
 pub use self::repository::KeyRepo;
 pub use self::savepoint::{Restore, RestoreSavepoint, Savepoint};
-pub use self::state::InstanceId;
+pub use self::shared::SharedKeyRepo;
+pub use self::state::{InstanceId, InstanceSummary};
+pub use self::undo::UndoRepo;
+pub use self::upgrade::upgrade_repo;
 
+mod check;
+mod checkpoint;
 mod chunk_store;
 mod chunking;
 mod commit;
+mod commit_log;
 mod compression;
 mod config;
 mod encryption;
+mod export;
+mod fingerprint;
+mod format;
 mod handle;
+mod hooks;
 mod key;
+mod key_provider;
 mod lock;
+mod merge;
 mod metadata;
+mod metrics;
 mod object;
 mod object_store;
 mod open_options;
@@ -32,4 +60,7 @@ mod open_repo;
 mod packing;
 mod repository;
 mod savepoint;
+mod shared;
 mod state;
+mod undo;
+mod upgrade;
@@ -1,16 +1,41 @@
 use std::convert::TryFrom;
 use std::fmt::Debug;
 use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
 use std::sync::{Arc, RwLock, Weak};
 
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 use static_assertions::assert_impl_all;
 
+use super::format::{Format, MessagePack};
 use super::handle::{ContentId, ObjectHandle, ObjectId, ObjectStats};
 use super::object_store::ObjectStore;
 use super::state::{ObjectState, RepoState};
 
+/// How an [`Object`] is expected to be read from.
+///
+/// This is a hint which is passed to [`Object::advise`]. It only affects performance, not the data
+/// which is read.
+///
+/// [`Object`]: crate::repo::Object
+/// [`Object::advise`]: crate::repo::Object::advise
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Default)]
+pub enum Access {
+    /// The object is read from in no particular order.
+    ///
+    /// Each chunk is fetched from the data store when it is first read from. This is the default.
+    #[default]
+    Random,
+
+    /// The object is read from start to end.
+    ///
+    /// When a chunk is read from, the chunks which follow it are fetched from the data store ahead
+    /// of time if they haven't been already. This reduces the time spent waiting on data stores
+    /// with high latency.
+    Sequential,
+}
+
 /// A read-write view of data in a repository.
 ///
 /// An `Object` is a view of data in a repository. It implements `Read`, `Write`, and `Seek` for
@@ -53,7 +78,8 @@ use super::state::{ObjectState, RepoState};
 ///
 /// On some platforms, sparse files can be created by seeking past the end of the file and writing
 /// to it. However, objects don't support seeking beyond their size. To create a sparse hole in an
-/// object, you must first extend its size with [`set_len`].
+/// object, you must first extend its size with [`set_len`]. You can also deallocate a region of an
+/// existing object with [`punch_hole`] or fill the holes in a region with [`allocate`].
 ///
 /// # Data Integrity
 ///
@@ -67,6 +93,9 @@ use super::state::{ObjectState, RepoState};
 /// Because `Object` internally buffers data when reading, there's no need to use a buffered reader
 /// like `BufReader`.
 ///
+/// When reading from a data store with high latency, you can use [`advise`] to enable read-ahead
+/// or [`prefetch`] to fetch a specific range of the object ahead of time.
+///
 /// # Errors
 ///
 /// The methods of `Read`, `Write`, and `Seek` return `io::Result`, but the returned `io::Error` can
@@ -80,9 +109,13 @@ use super::state::{ObjectState, RepoState};
 /// [`Error::InvalidObject`]: crate::Error::InvalidObject
 /// [`is_valid`]: crate::repo::Object::is_valid
 /// [`set_len`]: crate::repo::Object::set_len
+/// [`punch_hole`]: crate::repo::Object::punch_hole
+/// [`allocate`]: crate::repo::Object::allocate
 /// [`stats`]: crate::repo::Object::stats
 /// [`Error::InvalidData`]: crate::Error::InvalidData
 /// [`verify`]: crate::repo::Object::verify
+/// [`advise`]: crate::repo::Object::advise
+/// [`prefetch`]: crate::repo::Object::prefetch
 #[derive(Debug)]
 pub struct Object {
     /// The state for the object repository.
@@ -183,6 +216,74 @@ impl Object {
             .verify()
     }
 
+    /// Advise how this object is going to be read from.
+    ///
+    /// See [`Access`] for details. Advising [`Access::Random`] also discards any data which has
+    /// been fetched ahead of time but not yet read.
+    ///
+    /// [`Access`]: crate::repo::Access
+    /// [`Access::Random`]: crate::repo::Access::Random
+    pub fn advise(&mut self, access: Access) {
+        self.object_state.access = access;
+        if access == Access::Random {
+            self.object_state.prefetched.clear();
+        }
+    }
+
+    /// Fetch the data in the given `range` of the object from the data store ahead of time.
+    ///
+    /// The chunks which contain `range` are read and decoded before this method returns and are
+    /// held in memory until they are read from this object, so reading from `range` later doesn't
+    /// need to wait on the data store. If [`RepoConfig::read_threads`] is greater than one, the
+    /// chunks are decoded concurrently. The part of `range` which is past the end of the object is
+    /// ignored.
+    ///
+    /// Prefetched data uses memory until it is read or discarded with [`advise`], so avoid
+    /// prefetching much more data than you are about to read.
+    ///
+    /// # Errors
+    /// - `Error::TransactionInProgress`: A transaction is currently in progress for this object.
+    /// - `Error::InvalidObject`: The object has been invalidated.
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    ///
+    /// [`RepoConfig::read_threads`]: crate::repo::RepoConfig::read_threads
+    /// [`advise`]: crate::repo::Object::advise
+    pub fn prefetch(&mut self, range: Range<u64>) -> crate::Result<()> {
+        ObjectStore::new(&self.repo_state, &self.handle)?
+            .reader_guard(&mut self.object_state)
+            .reader()
+            .prefetch(range)
+    }
+
+    /// Return the size of the write buffer in bytes.
+    ///
+    /// See [`set_write_buffer_size`] for details.
+    ///
+    /// [`set_write_buffer_size`]: crate::repo::Object::set_write_buffer_size
+    pub fn write_buffer_size(&self) -> usize {
+        self.object_state.write_buffer_size
+    }
+
+    /// Set the size of the write buffer to `size` bytes.
+    ///
+    /// Normally, data is split into chunks as soon as it's written, and each complete chunk is
+    /// written to the data store. If `size` is greater than `0`, data written to this object is
+    /// accumulated in memory until at least `size` bytes are buffered, and then it's chunked and
+    /// written all at once. This improves throughput for many small writes, especially when
+    /// [`RepoConfig::write_threads`] is greater than one, because the resulting chunks can be
+    /// encoded together.
+    ///
+    /// The write buffer is always emptied by [`commit`], so this doesn't affect which data is
+    /// committed. The default value is `0`, which disables the write buffer.
+    ///
+    /// [`RepoConfig::write_threads`]: crate::repo::RepoConfig::write_threads
+    /// [`commit`]: crate::repo::Object::commit
+    pub fn set_write_buffer_size(&mut self, size: usize) {
+        self.object_state.write_buffer_size = size;
+    }
+
     /// Truncate or extend the object to the given `size`.
     ///
     /// If the given `size` is greater than the current size of the object, the object will be
@@ -199,6 +300,7 @@ impl Object {
     /// - `Error::InvalidObject`: The object has been invalidated.
     /// - `Error::InvalidData`: Ciphertext verification failed.
     /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::QuotaExceeded`: Extending the object would exceed the quota for this instance.
     /// - `Error::Io`: An I/O error occurred.
     pub fn set_len(&mut self, size: u64) -> crate::Result<()> {
         ObjectStore::new(&self.repo_state, &self.handle)?
@@ -207,6 +309,55 @@ impl Object {
             .set_len(size)
     }
 
+    /// Deallocate the bytes in the given `range`, replacing them with a sparse hole.
+    ///
+    /// After this method returns, reading from `range` will return null bytes, and the range will
+    /// be reported as a hole by [`ObjectStats::holes`]. This does not change the size of the
+    /// object; the part of `range` which is past the end of the object is ignored.
+    ///
+    /// This method starts a new transaction and commits the transaction before it returns.
+    ///
+    /// # Errors
+    /// - `Error::TransactionInProgress`: A transaction is currently in progress for this object.
+    /// - `Error::InvalidObject`: The object has been invalidated.
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    ///
+    /// [`ObjectStats::holes`]: crate::repo::ObjectStats::holes
+    pub fn punch_hole(&mut self, range: Range<u64>) -> crate::Result<()> {
+        ObjectStore::new(&self.repo_state, &self.handle)?
+            .writer_guard(&mut self.object_state)
+            .writer()
+            .punch_hole(range)
+    }
+
+    /// Allocate the bytes in the given `range`, replacing any sparse holes with null bytes.
+    ///
+    /// This is the inverse of [`punch_hole`]. After this method returns, no part of `range` will
+    /// be reported as a hole by [`ObjectStats::holes`]. The contents of the object don't change,
+    /// but the null bytes are stored as data, which may use additional space in the backing data
+    /// store. If the end of `range` is past the end of the object, the object is extended.
+    ///
+    /// This method starts a new transaction and commits the transaction before it returns.
+    ///
+    /// # Errors
+    /// - `Error::TransactionInProgress`: A transaction is currently in progress for this object.
+    /// - `Error::InvalidObject`: The object has been invalidated.
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::QuotaExceeded`: Extending the object would exceed the quota for this instance.
+    /// - `Error::Io`: An I/O error occurred.
+    ///
+    /// [`punch_hole`]: crate::repo::Object::punch_hole
+    /// [`ObjectStats::holes`]: crate::repo::ObjectStats::holes
+    pub fn allocate(&mut self, range: Range<u64>) -> crate::Result<()> {
+        ObjectStore::new(&self.repo_state, &self.handle)?
+            .writer_guard(&mut self.object_state)
+            .writer()
+            .allocate(range)
+    }
+
     /// Serialize the given `value` and write it to the object.
     ///
     /// This is a convenience function that serializes the `value` using a space-efficient binary
@@ -221,12 +372,32 @@ impl Object {
     /// - `Error::InvalidObject`: The object has been invalidated.
     /// - `Error::InvalidData`: Ciphertext verification failed.
     /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::QuotaExceeded`: Writing the value would exceed the quota for this instance.
     /// - `Error::Io`: An I/O error occurred.
     pub fn serialize<T: Serialize>(&mut self, value: &T) -> crate::Result<()> {
+        self.serialize_with::<MessagePack, T>(value)
+    }
+
+    /// Serialize the given `value` using the format `F` and write it to the object.
+    ///
+    /// This is like [`serialize`], but it accepts the [`Format`] to serialize the value with.
+    ///
+    /// # Errors
+    /// - `Error::Serialize`: The given value could not be serialized.
+    /// - `Error::TransactionInProgress`: A transaction is currently in progress for this object.
+    /// - `Error::InvalidObject`: The object has been invalidated.
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::QuotaExceeded`: Writing the value would exceed the quota for this instance.
+    /// - `Error::Io`: An I/O error occurred.
+    ///
+    /// [`serialize`]: crate::repo::Object::serialize
+    /// [`Format`]: crate::repo::Format
+    pub fn serialize_with<F: Format, T: Serialize>(&mut self, value: &T) -> crate::Result<()> {
         ObjectStore::new(&self.repo_state, &self.handle)?
             .writer_guard(&mut self.object_state)
             .writer()
-            .serialize(value)
+            .serialize::<F, T>(value)
     }
 
     /// Deserialize a value serialized with `Object::serialize`.
@@ -242,10 +413,23 @@ impl Object {
     /// - `Error::Store`: An error occurred with the data store.
     /// - `Error::Io`: An I/O error occurred.
     pub fn deserialize<T: DeserializeOwned>(&mut self) -> crate::Result<T> {
+        self.deserialize_with::<MessagePack, T>()
+    }
+
+    /// Deserialize a value serialized with `Object::serialize_with` using the format `F`.
+    ///
+    /// # Errors
+    /// - `Error::Deserialize`: The data could not be deserialized as a value of type `T`.
+    /// - `Error::TransactionInProgress`: A transaction is currently in progress for this object.
+    /// - `Error::InvalidObject`: The object has been invalidated.
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    pub fn deserialize_with<F: Format, T: DeserializeOwned>(&mut self) -> crate::Result<T> {
         ObjectStore::new(&self.repo_state, &self.handle)?
             .reader_guard(&mut self.object_state)
             .reader()
-            .deserialize()
+            .deserialize::<F, T>()
     }
 
     /// Commit changes to this object to the repository.
@@ -279,6 +463,29 @@ impl Object {
     pub fn is_valid(&self) -> bool {
         ObjectStore::new(&self.repo_state, &self.handle).is_ok()
     }
+
+    /// Convert this object into an [`ObjectStream`] for reading its current contents.
+    ///
+    /// See [`ObjectStream`] for details.
+    ///
+    /// # Errors
+    /// - `Error::TransactionInProgress`: A transaction is currently in progress for this object.
+    /// - `Error::InvalidObject`: The object has been invalidated.
+    ///
+    /// [`ObjectStream`]: crate::repo::ObjectStream
+    pub fn into_stream(self) -> crate::Result<ObjectStream> {
+        if self.object_state.transaction_lock.is_some() {
+            return Err(crate::Error::TransactionInProgress);
+        }
+
+        let handle = ObjectStore::new(&self.repo_state, &self.handle)?.clone_handle();
+
+        Ok(ObjectStream {
+            repo_state: self.repo_state,
+            handle: Arc::new(RwLock::new(handle)),
+            object_state: self.object_state,
+        })
+    }
 }
 
 impl Read for Object {
@@ -371,6 +578,24 @@ impl ReadOnlyObject {
         self.0.verify()
     }
 
+    /// Advise how this object is going to be read from.
+    ///
+    /// See [`Object::advise`] for details.
+    ///
+    /// [`Object::advise`]: crate::repo::Object::advise
+    pub fn advise(&mut self, access: Access) {
+        self.0.advise(access)
+    }
+
+    /// Fetch the data in the given `range` of the object from the data store ahead of time.
+    ///
+    /// See [`Object::prefetch`] for details.
+    ///
+    /// [`Object::prefetch`]: crate::repo::Object::prefetch
+    pub fn prefetch(&mut self, range: Range<u64>) -> crate::Result<()> {
+        self.0.prefetch(range)
+    }
+
     /// Deserialize a value serialized with [`Object::serialize`].
     ///
     /// See [`Object::deserialize`] for details.
@@ -381,10 +606,30 @@ impl ReadOnlyObject {
         self.0.deserialize()
     }
 
+    /// Deserialize a value serialized with [`Object::serialize_with`] using the format `F`.
+    ///
+    /// See [`Object::deserialize_with`] for details.
+    ///
+    /// [`Object::serialize_with`]: crate::repo::Object::serialize_with
+    /// [`Object::deserialize_with`]: crate::repo::Object::deserialize_with
+    pub fn deserialize_with<F: Format, T: DeserializeOwned>(&mut self) -> crate::Result<T> {
+        self.0.deserialize_with::<F, T>()
+    }
+
     /// Return whether this object is valid.
     pub fn is_valid(&self) -> bool {
         self.0.is_valid()
     }
+
+    /// Convert this object into an [`ObjectStream`] for reading its current contents.
+    ///
+    /// See [`Object::into_stream`] for details.
+    ///
+    /// [`ObjectStream`]: crate::repo::ObjectStream
+    /// [`Object::into_stream`]: crate::repo::Object::into_stream
+    pub fn into_stream(self) -> crate::Result<ObjectStream> {
+        self.0.into_stream()
+    }
 }
 
 impl TryFrom<Object> for ReadOnlyObject {
@@ -412,3 +657,120 @@ impl Seek for ReadOnlyObject {
         self.0.seek(pos)
     }
 }
+
+/// A detached, read-only view of the contents of an object.
+///
+/// An `ObjectStream` implements `Read` and `Seek` for reading the contents an object had when the
+/// stream was created with [`Object::into_stream`] or [`ReadOnlyObject::into_stream`]. Unlike an
+/// [`Object`], it has its own copy of the object's metadata, so it is not affected by later
+/// changes to the object. Removing or overwriting the object, rolling back the repository, or
+/// restoring the repository to a savepoint does not invalidate the stream, which makes it suitable
+/// for handing off to another thread while the repository continues to be modified.
+///
+/// The stream starts at the seek position the object had when it was converted.
+///
+/// An `ObjectStream` is still invalidated when the repository it is associated with is dropped, in
+/// which case its methods return [`Error::InvalidObject`]. If the object has been removed or
+/// overwritten, calling [`Commit::clean`] may delete the data the stream refers to, in which case
+/// reading from it returns an error.
+///
+/// [`Object::into_stream`]: crate::repo::Object::into_stream
+/// [`ReadOnlyObject::into_stream`]: crate::repo::ReadOnlyObject::into_stream
+/// [`Object`]: crate::repo::Object
+/// [`Error::InvalidObject`]: crate::Error::InvalidObject
+/// [`Commit::clean`]: crate::repo::Commit::clean
+#[derive(Debug)]
+pub struct ObjectStream {
+    /// The state for the object repository.
+    repo_state: Weak<RwLock<RepoState>>,
+
+    /// A copy of the object handle from when the stream was created.
+    handle: Arc<RwLock<ObjectHandle>>,
+
+    /// The state for reading the object.
+    object_state: ObjectState,
+}
+
+assert_impl_all!(ObjectStream: Send, Sync);
+
+impl ObjectStream {
+    fn store(&self) -> crate::Result<ObjectStore> {
+        ObjectStore::new(&self.repo_state, &Arc::downgrade(&self.handle))
+    }
+
+    /// Return the size of the stream in bytes.
+    ///
+    /// # Errors
+    /// - `Error::InvalidObject`: The repository has been dropped.
+    pub fn size(&self) -> crate::Result<u64> {
+        self.store()?.info_guard(&self.object_state).info().size()
+    }
+
+    /// Return a `ContentId` representing the contents of this stream.
+    ///
+    /// See [`Object::content_id`] for details.
+    ///
+    /// # Errors
+    /// - `Error::InvalidObject`: The repository has been dropped.
+    ///
+    /// [`Object::content_id`]: crate::repo::Object::content_id
+    pub fn content_id(&self) -> crate::Result<ContentId> {
+        self.store()?
+            .info_guard(&self.object_state)
+            .info()
+            .content_id()
+    }
+
+    /// Advise how this stream is going to be read from.
+    ///
+    /// See [`Object::advise`] for details.
+    ///
+    /// [`Object::advise`]: crate::repo::Object::advise
+    pub fn advise(&mut self, access: Access) {
+        self.object_state.access = access;
+        if access == Access::Random {
+            self.object_state.prefetched.clear();
+        }
+    }
+
+    /// Fetch the data in the given `range` of the stream from the data store ahead of time.
+    ///
+    /// See [`Object::prefetch`] for details.
+    ///
+    /// # Errors
+    /// - `Error::InvalidObject`: The repository has been dropped.
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    ///
+    /// [`Object::prefetch`]: crate::repo::Object::prefetch
+    pub fn prefetch(&mut self, range: Range<u64>) -> crate::Result<()> {
+        self.store()?
+            .reader_guard(&mut self.object_state)
+            .reader()
+            .prefetch(range)
+    }
+
+    /// Return whether this stream is valid.
+    pub fn is_valid(&self) -> bool {
+        self.repo_state.strong_count() > 0
+    }
+}
+
+impl Read for ObjectStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.store()?
+            .reader_guard(&mut self.object_state)
+            .reader()
+            .read(buf)
+    }
+}
+
+impl Seek for ObjectStream {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.store()?
+            .reader_guard(&mut self.object_state)
+            .reader()
+            .seek(pos)
+    }
+}
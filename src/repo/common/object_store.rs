@@ -1,16 +1,31 @@
 use std::cmp::{min, Ordering};
-use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::collections::HashSet;
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
 use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard, Weak};
+use std::thread;
 
-use rmp_serde::{from_read, to_vec};
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 
-use super::chunk_store::{ReadChunk, StoreReader, StoreWriter, WriteChunk};
-use super::handle::{chunk_hash, ContentId, Extent, ObjectHandle, ObjectStats};
+use super::chunk_store::{ReadChunk, StoreReader, StoreState, StoreWriter, WriteChunk};
+use super::chunking::IncrementalChunker;
+use super::format::Format;
+use super::handle::{chunk_hash, Chunk, ContentId, Extent, ObjectHandle, ObjectStats};
+use super::object::Access;
+use super::packing::Packing;
 use super::state::{ExtentLocation, ObjectState, RepoState, SeekPosition};
 use crate::repo::ObjectId;
 
+/// The size of the buffer of null bytes used when allocating space in an object.
+const ZERO_BUFFER_SIZE: usize = 1024 * 64;
+
+/// The size of the buffer used when streaming a serialized value to an object.
+const SERIALIZE_BUFFER_SIZE: usize = 1024 * 64;
+
+/// The number of chunks to fetch ahead of the seek position when reading sequentially.
+const READ_AHEAD_CHUNKS: usize = 4;
+
 pub struct ObjectStore {
     repo_state: Arc<RwLock<RepoState>>,
     handle: Arc<RwLock<ObjectHandle>>,
@@ -27,6 +42,10 @@ impl ObjectStore {
         })
     }
 
+    pub fn clone_handle(&self) -> ObjectHandle {
+        self.handle.read().unwrap().clone()
+    }
+
     pub fn info_guard<'a>(&'a self, object_state: &'a ObjectState) -> ObjectInfoGuard<'a> {
         ObjectInfoGuard {
             repo_state: self.repo_state.read().unwrap(),
@@ -84,7 +103,11 @@ pub struct ObjectWriterGuard<'a> {
 
 impl<'a> ObjectWriterGuard<'a> {
     pub fn writer(&mut self) -> ObjectWriter {
-        ObjectWriter::new(&mut self.repo_state, self.object_state, &mut self.handle)
+        // Objects accessed through an `Object` are always in the current instance.
+        let mut writer =
+            ObjectWriter::new(&mut self.repo_state, self.object_state, &mut self.handle);
+        writer.in_instance = true;
+        writer
     }
 }
 
@@ -141,11 +164,41 @@ impl<'a> ObjectInfo<'a> {
         let mut actual_size = 0u64;
         let mut apparent_size = 0u64;
         let mut holes = Vec::new();
+        let mut chunks = 0u64;
+        let mut compressed_size = Some(0u64);
+        let mut shared_chunks = HashSet::new();
+        let mut packs = HashSet::new();
 
         for extent in &self.handle.extents {
             match extent {
-                Extent::Chunk(_) => {
+                Extent::Chunk(chunk) => {
                     actual_size += extent.size();
+                    chunks += 1;
+
+                    let chunk_info = self.repo_state.chunks.get(chunk);
+                    let pack_indices = chunk_info
+                        .and_then(|chunk_info| self.repo_state.packs.get(&chunk_info.block_id));
+
+                    // Chunks written by older versions of this library don't record their
+                    // compressed size, but it can still be found from the pack map.
+                    let chunk_compressed_size = chunk_info
+                        .and_then(|chunk_info| chunk_info.compressed_size)
+                        .map(u64::from)
+                        .or_else(|| {
+                            pack_indices.map(|indices| {
+                                indices.iter().map(|index| u64::from(index.size)).sum()
+                            })
+                        });
+                    compressed_size = compressed_size
+                        .zip(chunk_compressed_size)
+                        .map(|(a, b)| a + b);
+
+                    if let Some(chunk_info) = chunk_info {
+                        if chunk_info.references.iter().any(|id| *id != self.handle.id) {
+                            shared_chunks.insert(*chunk);
+                        }
+                    }
+                    packs.extend(pack_indices.into_iter().flatten().map(|index| index.id));
                 }
                 Extent::Hole { .. } => {
                     holes.push(current_position..(current_position + extent.size()));
@@ -155,10 +208,19 @@ impl<'a> ObjectInfo<'a> {
             apparent_size += extent.size();
         }
 
+        let packs = match self.repo_state.metadata.config.packing {
+            Packing::None => None,
+            Packing::Fixed(_) => Some(packs.len() as u64),
+        };
+
         Ok(ObjectStats {
             apparent_size,
             actual_size,
             holes,
+            chunks,
+            compressed_size,
+            shared_chunks: shared_chunks.len() as u64,
+            packs,
         })
     }
 }
@@ -212,6 +274,75 @@ impl<'a> ObjectReader<'a> {
         Ok(true)
     }
 
+    /// Fetch the given `chunks` which haven't already been fetched and hold them in memory.
+    fn fetch_chunks(&mut self, chunks: impl IntoIterator<Item = Chunk>) -> crate::Result<()> {
+        let mut missing_chunks = Vec::new();
+        for chunk in chunks {
+            if Some(chunk) != self.object_state.buffered_chunk
+                && !self.object_state.prefetched.contains_key(&chunk)
+                && !missing_chunks.contains(&chunk)
+            {
+                missing_chunks.push(chunk);
+            }
+        }
+
+        let contents = read_chunks(
+            self.repo_state,
+            &mut self.object_state.store_state,
+            &missing_chunks,
+        )?;
+        self.object_state
+            .prefetched
+            .extend(missing_chunks.into_iter().zip(contents));
+
+        Ok(())
+    }
+
+    /// Fetch the chunks which contain the bytes in `range` ahead of time.
+    pub fn prefetch(&mut self, range: Range<u64>) -> crate::Result<()> {
+        if self.object_state.transaction_lock.is_some() {
+            return Err(crate::Error::TransactionInProgress);
+        }
+
+        let mut chunks = Vec::new();
+        let mut extent_start = 0u64;
+        for extent in &self.handle.extents {
+            let extent_end = extent_start + extent.size();
+            if extent_start >= range.end {
+                break;
+            }
+            if let Extent::Chunk(chunk) = extent {
+                if extent_end > range.start {
+                    chunks.push(*chunk);
+                }
+            }
+            extent_start = extent_end;
+        }
+
+        self.fetch_chunks(chunks)
+    }
+
+    /// Fetch the chunks following the extent at `index` if the next one hasn't been fetched yet.
+    fn read_ahead(&mut self, index: usize) -> crate::Result<()> {
+        let window_size = READ_AHEAD_CHUNKS.max(self.repo_state.metadata.config.read_threads);
+        let mut upcoming_chunks = self.handle.extents[index + 1..]
+            .iter()
+            .filter_map(|extent| match extent {
+                Extent::Chunk(chunk) => Some(*chunk),
+                Extent::Hole { .. } => None,
+            })
+            .take(window_size)
+            .peekable();
+
+        match upcoming_chunks.peek() {
+            Some(next_chunk) if !self.object_state.prefetched.contains_key(next_chunk) => {
+                let upcoming_chunks = upcoming_chunks.collect::<Vec<_>>();
+                self.fetch_chunks(upcoming_chunks)
+            }
+            _ => Ok(()),
+        }
+    }
+
     /// Return the current seek position in the object.
     fn current_position(&self) -> SeekPosition {
         if self.handle.extents.is_empty() {
@@ -261,8 +392,16 @@ impl<'a> ObjectReader<'a> {
                 // If we're reading from a new chunk, read the contents of that chunk into the read
                 // buffer.
                 if Some(chunk) != self.object_state.buffered_chunk {
+                    self.object_state.read_buffer =
+                        match self.object_state.prefetched.remove(&chunk) {
+                            Some(data) => data,
+                            None => self.store_reader().read_chunk(chunk)?,
+                        };
                     self.object_state.buffered_chunk = Some(chunk);
-                    self.object_state.read_buffer = self.store_reader().read_chunk(chunk)?;
+
+                    if self.object_state.access == Access::Sequential {
+                        self.read_ahead(current_location.index)?;
+                    }
                 }
 
                 let start = current_location.relative_position() as usize;
@@ -279,10 +418,10 @@ impl<'a> ObjectReader<'a> {
         }
     }
 
-    /// Deserialize a value serialized with `ObjectWriter::serialize`.
-    pub fn deserialize<T: DeserializeOwned>(&mut self) -> crate::Result<T> {
+    /// Deserialize a value serialized with `ObjectWriter::serialize` using the format `F`.
+    pub fn deserialize<F: Format, T: DeserializeOwned>(&mut self) -> crate::Result<T> {
         self.seek(SeekFrom::Start(0))?;
-        from_read(self).map_err(|_| crate::Error::Deserialize)
+        F::deserialize(self)
     }
 }
 
@@ -347,6 +486,9 @@ pub struct ObjectWriter<'a> {
     repo_state: &'a mut RepoState,
     object_state: &'a mut ObjectState,
     handle: &'a mut ObjectHandle,
+
+    /// Whether this object is in the current instance and counts toward its quota.
+    in_instance: bool,
 }
 
 impl<'a> ObjectWriter<'a> {
@@ -359,6 +501,32 @@ impl<'a> ObjectWriter<'a> {
             repo_state,
             object_state,
             handle,
+            in_instance: false,
+        }
+    }
+
+    /// Return an error if resizing the object to `size` would exceed the instance quota.
+    fn check_quota(&self, size: u64) -> crate::Result<()> {
+        let current_size = self.handle.size();
+        if !self.in_instance || size <= current_size {
+            return Ok(());
+        }
+
+        let new_instance_size = self
+            .repo_state
+            .instance_size
+            .saturating_add(size - current_size);
+        match self.repo_state.instance_quota {
+            Some(quota) if new_instance_size > quota => Err(crate::Error::QuotaExceeded),
+            _ => Ok(()),
+        }
+    }
+
+    /// Update the apparent size of the instance after the object changed from `previous_size`.
+    fn update_instance_size(&mut self, previous_size: u64) {
+        if self.in_instance {
+            let instance_size = &mut self.repo_state.instance_size;
+            *instance_size = (*instance_size + self.handle.size()).saturating_sub(previous_size);
         }
     }
 
@@ -432,6 +600,177 @@ impl<'a> ObjectWriter<'a> {
         self.handle.extents.push(hole);
     }
 
+    /// Ensure there is an extent boundary at `position` and return the index of the extent which
+    /// starts there.
+    ///
+    /// If `position` is in the middle of an extent, that extent is split in two.
+    fn split_at(&mut self, position: u64) -> crate::Result<usize> {
+        let mut start = 0;
+        for (index, extent) in self.handle.extents.iter().copied().enumerate() {
+            let end = start + extent.size();
+
+            if position == start {
+                return Ok(index);
+            }
+
+            if position < end {
+                let relative_position = position - start;
+                let (head, tail) = match extent {
+                    // Chunks can't be edited in-place, so we need to read the chunk, slice it, and
+                    // write both halves back.
+                    Extent::Chunk(chunk) => {
+                        let chunk_data = self.store_writer().read_chunk(chunk)?;
+                        let (head_data, tail_data) =
+                            chunk_data.split_at(relative_position as usize);
+                        let handle_id = self.handle.id;
+                        let mut store_writer = self.store_writer();
+                        (
+                            Extent::Chunk(store_writer.write_chunk(head_data, handle_id)?),
+                            Extent::Chunk(store_writer.write_chunk(tail_data, handle_id)?),
+                        )
+                    }
+                    Extent::Hole { size } => (
+                        Extent::Hole {
+                            size: relative_position,
+                        },
+                        Extent::Hole {
+                            size: size - relative_position,
+                        },
+                    ),
+                };
+                self.handle.extents.splice(index..=index, [head, tail]);
+                return Ok(index + 1);
+            }
+
+            start = end;
+        }
+
+        Ok(self.handle.extents.len())
+    }
+
+    /// Merge adjacent holes in the object into a single hole.
+    fn merge_holes(&mut self) {
+        let mut merged_extents: Vec<Extent> = Vec::with_capacity(self.handle.extents.len());
+        for extent in self.handle.extents.drain(..) {
+            match (merged_extents.last_mut(), extent) {
+                (Some(Extent::Hole { size }), Extent::Hole { size: next_size }) => {
+                    *size += next_size;
+                }
+                (_, Extent::Hole { size: 0 }) => {}
+                _ => merged_extents.push(extent),
+            }
+        }
+        self.handle.extents = merged_extents;
+    }
+
+    /// Write `size` null bytes to the repository and return the chunks they were written to.
+    fn write_zeroes(&mut self, size: u64) -> crate::Result<Vec<Extent>> {
+        let mut chunker =
+            IncrementalChunker::new(self.repo_state.metadata.config.chunking.to_chunker());
+        let zeroes = vec![0u8; min(size, ZERO_BUFFER_SIZE as u64) as usize];
+        let handle_id = self.handle.id;
+        let mut new_chunks = Vec::new();
+
+        let mut remaining = size;
+        while remaining > 0 {
+            let write_size = min(remaining, zeroes.len() as u64) as usize;
+            chunker.write_all(&zeroes[..write_size])?;
+            self.store_writer()
+                .write_chunks(&chunker.chunks(), handle_id, &mut new_chunks)?;
+            remaining -= write_size as u64;
+        }
+
+        chunker.flush()?;
+        self.store_writer()
+            .write_chunks(&chunker.chunks(), handle_id, &mut new_chunks)?;
+
+        Ok(new_chunks.into_iter().map(Extent::Chunk).collect())
+    }
+
+    /// Acquire a transaction lock for a modification which is committed immediately.
+    fn begin_modification(&mut self) -> crate::Result<()> {
+        match self.object_state.transaction_lock {
+            None => match self.repo_state.transactions.acquire_lock(self.handle.id) {
+                None => Err(crate::Error::TransactionInProgress),
+                Some(lock) => {
+                    self.object_state.transaction_lock = Some(lock);
+                    Ok(())
+                }
+            },
+            Some(_) => Err(crate::Error::TransactionInProgress),
+        }
+    }
+
+    /// Deallocate the bytes in the given `range`, replacing them with a sparse hole.
+    pub fn punch_hole(&mut self, range: Range<u64>) -> crate::Result<()> {
+        self.begin_modification()?;
+
+        let result = self.replace_with_hole(range);
+
+        self.object_state.transaction_lock = None;
+
+        result
+    }
+
+    fn replace_with_hole(&mut self, range: Range<u64>) -> crate::Result<()> {
+        let end = min(range.end, self.handle.size());
+        let start = min(range.start, end);
+        if start == end {
+            return Ok(());
+        }
+
+        let start_index = self.split_at(start)?;
+        let end_index = self.split_at(end)?;
+        self.handle
+            .extents
+            .splice(start_index..end_index, [Extent::Hole { size: end - start }]);
+        self.merge_holes();
+
+        Ok(())
+    }
+
+    /// Allocate space for the bytes in the given `range`, replacing any sparse holes with data.
+    pub fn allocate(&mut self, range: Range<u64>) -> crate::Result<()> {
+        self.begin_modification()?;
+
+        let result = self.check_quota(range.end).and_then(|_| {
+            let previous_size = self.handle.size();
+            self.extend(range.end);
+            let result = self.replace_holes(range);
+            self.update_instance_size(previous_size);
+            result
+        });
+
+        self.object_state.transaction_lock = None;
+
+        result
+    }
+
+    fn replace_holes(&mut self, range: Range<u64>) -> crate::Result<()> {
+        let end = min(range.end, self.handle.size());
+        let start = min(range.start, end);
+        if start == end {
+            return Ok(());
+        }
+
+        let start_index = self.split_at(start)?;
+        let end_index = self.split_at(end)?;
+
+        let mut new_extents = Vec::new();
+        for extent in self.handle.extents[start_index..end_index].to_vec() {
+            match extent {
+                Extent::Chunk(_) => new_extents.push(extent),
+                Extent::Hole { size } => new_extents.extend(self.write_zeroes(size)?),
+            }
+        }
+        self.handle
+            .extents
+            .splice(start_index..end_index, new_extents);
+        self.merge_holes();
+
+        Ok(())
+    }
+
     /// Set the length of the object.
     pub fn set_len(&mut self, size: u64) -> crate::Result<()> {
         // Because this modifies the object, we need to start a new transaction.
@@ -445,34 +784,68 @@ impl<'a> ObjectWriter<'a> {
             Some(_) => return Err(crate::Error::TransactionInProgress),
         }
 
-        match size.cmp(&self.handle.size()) {
+        if let Err(error) = self.check_quota(size) {
+            self.object_state.transaction_lock = None;
+            return Err(error);
+        }
+
+        let previous_size = self.handle.size();
+        match size.cmp(&previous_size) {
             Ordering::Less => self.truncate(size)?,
             Ordering::Greater => self.extend(size),
             _ => {}
         }
+        self.update_instance_size(previous_size);
 
         self.object_state.transaction_lock = None;
 
         Ok(())
     }
 
+    /// Pass the data in the write buffer to the chunker and write any complete chunks.
+    fn flush_write_buffer(&mut self) -> crate::Result<()> {
+        if self.object_state.write_buffer.is_empty() {
+            return Ok(());
+        }
+        let buffered_data = std::mem::take(&mut self.object_state.write_buffer);
+        self.object_state.chunker.write_all(&buffered_data)?;
+        self.write_chunks()
+    }
+
     /// Write chunks stored in the chunker to the repository.
     fn write_chunks(&mut self) -> crate::Result<()> {
-        for chunk_data in self.object_state.chunker.chunks() {
-            let handle_id = self.handle.id;
-            let chunk = self.store_writer().write_chunk(&chunk_data, handle_id)?;
-            self.object_state.new_chunks.push(chunk);
-        }
-        Ok(())
+        let chunks = self.object_state.chunker.chunks();
+        let mut store_writer =
+            StoreWriter::new(self.repo_state, &mut self.object_state.store_state);
+        store_writer.write_chunks(&chunks, self.handle.id, &mut self.object_state.new_chunks)
     }
 
-    /// Serialize the given `value` and write it to the object.
-    pub fn serialize<T: Serialize>(&mut self, value: &T) -> crate::Result<()> {
-        let serialized = to_vec(value).map_err(|_| crate::Error::Serialize)?;
+    /// Serialize the given `value` using the format `F` and write it to the object.
+    ///
+    /// The value is streamed to the object as it's serialized, so it's never buffered in memory
+    /// in its entirety.
+    pub fn serialize<F: Format, T: Serialize>(&mut self, value: &T) -> crate::Result<()> {
         self.seek(SeekFrom::Start(0))?;
-        self.write_all(serialized.as_slice())?;
+
+        let mut writer = BufWriter::with_capacity(
+            SERIALIZE_BUFFER_SIZE,
+            CaptureError {
+                inner: &mut *self,
+                error: None,
+            },
+        );
+        let result = F::serialize(value, &mut writer)
+            .and_then(|_| writer.flush().map_err(crate::Error::from));
+        let CaptureError { error, .. } = writer.into_parts().0;
+
+        // If serialization failed because the data couldn't be written, report the original error.
+        if let Err(serialize_error) = result {
+            return Err(error.map_or(serialize_error, crate::Error::from));
+        }
+
+        let size = self.object_state.position;
         self.commit()?;
-        self.set_len(serialized.len() as u64)?;
+        self.set_len(size)?;
         Ok(())
     }
 
@@ -483,6 +856,9 @@ impl<'a> ObjectWriter<'a> {
             return Ok(());
         }
 
+        // Any buffered data needs to be chunked before the data after the seek position.
+        self.flush_write_buffer()?;
+
         let current_position = self.object_reader().current_position();
 
         // If the start position was in a hole, we will need to prepend a new hole when we replace
@@ -547,9 +923,11 @@ impl<'a> ObjectWriter<'a> {
         }
 
         // Update extent references in the object handle to reflect changes.
+        let previous_size = self.handle.size();
         self.handle
             .extents
             .splice(start_index..end_index, new_extents);
+        self.update_instance_size(previous_size);
 
         // Release the current transaction.
         self.object_state.transaction_lock = None;
@@ -564,6 +942,10 @@ impl<'a> ObjectWriter<'a> {
 // the user needs to explicitly call `commit` when they're done writing data.
 impl<'a> Write for ObjectWriter<'a> {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // Check whether this write would extend the object past the instance quota.
+        let end_position = self.object_state.position + buf.len() as u64;
+        self.check_quota(end_position)?;
+
         // Attempt to acquire a transaction lock if one has not already been acquired.
         let first_write = match self.object_state.transaction_lock {
             None => match self.repo_state.transactions.acquire_lock(self.handle.id) {
@@ -595,9 +977,20 @@ impl<'a> Write for ObjectWriter<'a> {
             }
         }
 
-        // Chunk the data and write any complete chunks to the repository.
-        self.object_state.chunker.write_all(buf)?;
-        self.write_chunks()?;
+        // Chunk the data and write any complete chunks to the repository. If a write buffer is
+        // configured, small writes are accumulated first so they can be chunked and encoded
+        // together.
+        if self.object_state.write_buffer_size == 0 {
+            // The write buffer may have been disabled with data still in it.
+            self.flush_write_buffer()?;
+            self.object_state.chunker.write_all(buf)?;
+            self.write_chunks()?;
+        } else {
+            self.object_state.write_buffer.extend_from_slice(buf);
+            if self.object_state.write_buffer.len() >= self.object_state.write_buffer_size {
+                self.flush_write_buffer()?;
+            }
+        }
 
         // Advance the seek position.
         self.object_state.position += buf.len() as u64;
@@ -610,6 +1003,29 @@ impl<'a> Write for ObjectWriter<'a> {
     }
 }
 
+/// A writer which keeps the original error returned by the wrapped writer.
+///
+/// Serializers typically wrap I/O errors in their own error types, which would otherwise make it
+/// impossible to recover errors like `Error::QuotaExceeded` from a failed serialization.
+struct CaptureError<W> {
+    inner: W,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> Write for CaptureError<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf).map_err(|error| {
+            let kind = error.kind();
+            self.error = Some(error);
+            io::Error::from(kind)
+        })
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
 impl<'a> Seek for ObjectWriter<'a> {
     fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
         self.object_reader().seek(pos)
@@ -621,3 +1037,46 @@ impl<'a> Read for ObjectWriter<'a> {
         self.object_reader().read(buf)
     }
 }
+
+/// Read the contents of the given `chunks` from the data store.
+///
+/// If `RepoConfig::read_threads` is greater than one, the chunks are decoded concurrently.
+/// Otherwise, they are read using `store_state`.
+fn read_chunks(
+    repo_state: &RepoState,
+    store_state: &mut StoreState,
+    chunks: &[Chunk],
+) -> crate::Result<Vec<Vec<u8>>> {
+    let threads = repo_state.metadata.config.read_threads;
+
+    if threads <= 1 || chunks.len() <= 1 {
+        let mut store_reader = StoreReader::new(repo_state, store_state);
+        return chunks
+            .iter()
+            .map(|chunk| store_reader.read_chunk(*chunk))
+            .collect();
+    }
+
+    let group_size = (chunks.len() + threads - 1) / threads;
+    let groups = thread::scope(|scope| {
+        let workers = chunks
+            .chunks(group_size)
+            .map(|group| {
+                scope.spawn(move || {
+                    let mut store_state = StoreState::new();
+                    let mut store_reader = StoreReader::new(repo_state, &mut store_state);
+                    group
+                        .iter()
+                        .map(|chunk| store_reader.read_chunk(*chunk))
+                        .collect::<crate::Result<Vec<_>>>()
+                })
+            })
+            .collect::<Vec<_>>();
+        workers
+            .into_iter()
+            .map(|worker| worker.join().expect("A chunk reading thread panicked."))
+            .collect::<crate::Result<Vec<_>>>()
+    })?;
+
+    Ok(groups.into_iter().flatten().collect())
+}
@@ -1,20 +1,27 @@
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt::{Debug, Formatter};
 use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
 
 use rmp_serde::{from_read, to_vec};
 use secrecy::ExposeSecret;
 use uuid::{uuid, Uuid};
 
-use crate::store::{BlockKey, DataStore, OpenStore};
+use crate::store::{BlockKey, ContextStore, DataStore, OpenStore};
 
 use super::chunking::Chunking;
 use super::compression::Compression;
 use super::config::RepoConfig;
-use super::encryption::{Encryption, EncryptionKey, KeySalt, ResourceLimit};
+use super::encryption::{Encryption, EncryptionKey, KeyDerivation, KeySalt, ResourceLimit};
+use super::fingerprint::ChunkTree;
 use super::handle::HandleIdTable;
-use super::lock::{lock_store, LockTable};
-use super::metadata::{Header, RepoMetadata};
+use super::hooks::Hooks;
+use super::key_provider::KeyProvider;
+use super::lock::{lock_store, Heartbeat, LockInfo, LockTable};
+use super::metadata::{Header, RepoMetadata, SuperBlock};
+#[cfg(feature = "observability")]
+use super::metrics::{Metrics, MetricsStore};
 use super::open_repo::OpenRepo;
 use super::packing::Packing;
 use super::repository::KeyRepo;
@@ -32,7 +39,7 @@ pub const DEFAULT_INSTANCE: InstanceId =
 ///
 /// This must be changed any time a backwards-incompatible change is made to the repository
 /// format.
-const VERSION_ID: Uuid = uuid!("44253e72-f08f-11eb-a2a3-a701701f8601");
+pub(super) const VERSION_ID: Uuid = uuid!("44253e72-f08f-11eb-a2a3-a701701f8601");
 
 /// The mode to use to open a repository.
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
@@ -49,6 +56,8 @@ pub enum OpenMode {
 
 type BoxLockHandler<'a> = Box<dyn FnMut(&[u8]) -> bool + 'a>;
 
+type BoxPasswordCallback<'a> = Box<dyn FnMut(u32) -> Option<Vec<u8>> + 'a>;
+
 /// Open or create a repository.
 ///
 /// This type is a builder used to open or create repositories. Typically, when using `OpenOptions`,
@@ -107,9 +116,19 @@ pub struct OpenOptions<'a> {
     config: RepoConfig,
     mode: OpenMode,
     password: Option<&'a [u8]>,
+    password_callback: Option<BoxPasswordCallback<'a>>,
+    password_attempts: u32,
+    convergence_secret: Option<&'a [u8]>,
+    key_provider: Option<Arc<dyn KeyProvider>>,
     instance: InstanceId,
+    instance_name: Option<&'a str>,
     lock_context: &'a [u8],
+    lock_description: Option<&'a str>,
     lock_handler: BoxLockHandler<'a>,
+    lease: Option<Duration>,
+    health_check: bool,
+    #[cfg(feature = "observability")]
+    metrics: Option<Arc<dyn Metrics>>,
 }
 
 impl<'a> Default for OpenOptions<'a> {
@@ -125,9 +144,19 @@ impl<'a> OpenOptions<'a> {
             config: RepoConfig::default(),
             mode: OpenMode::Open,
             password: None,
+            password_callback: None,
+            password_attempts: 1,
+            convergence_secret: None,
+            key_provider: None,
             instance: DEFAULT_INSTANCE,
+            instance_name: None,
             lock_context: &[],
+            lock_description: None,
             lock_handler: Box::new(|_| false),
+            lease: None,
+            health_check: false,
+            #[cfg(feature = "observability")]
+            metrics: None,
         }
     }
 
@@ -214,11 +243,186 @@ impl<'a> OpenOptions<'a> {
         self
     }
 
+    /// Overwrite the key derivation function specified in [`RepoConfig::key_derivation`].
+    ///
+    /// This is only applicable when creating a new repository. This is ignored when opening an
+    /// existing repository.
+    ///
+    /// [`RepoConfig::key_derivation`]: crate::repo::RepoConfig::key_derivation
+    pub fn key_derivation(&mut self, key_derivation: Option<KeyDerivation>) -> &mut Self {
+        self.config.key_derivation = key_derivation;
+        self
+    }
+
+    /// Overwrite the quota specified in [`RepoConfig::quota`].
+    ///
+    /// This is only applicable when creating a new repository. This is ignored when opening an
+    /// existing repository.
+    ///
+    /// [`RepoConfig::quota`]: crate::repo::RepoConfig::quota
+    pub fn quota(&mut self, quota: Option<u64>) -> &mut Self {
+        self.config.quota = quota;
+        self
+    }
+
+    /// Overwrite the number of write threads specified in [`RepoConfig::write_threads`].
+    ///
+    /// Unlike other configuration options, this applies both when creating a new repository and
+    /// when opening an existing one.
+    ///
+    /// [`RepoConfig::write_threads`]: crate::repo::RepoConfig::write_threads
+    pub fn write_threads(&mut self, threads: usize) -> &mut Self {
+        self.config.write_threads = threads;
+        self
+    }
+
+    /// Overwrite the number of verify threads specified in [`RepoConfig::verify_threads`].
+    ///
+    /// Unlike other configuration options, this applies both when creating a new repository and
+    /// when opening an existing one.
+    ///
+    /// [`RepoConfig::verify_threads`]: crate::repo::RepoConfig::verify_threads
+    pub fn verify_threads(&mut self, threads: usize) -> &mut Self {
+        self.config.verify_threads = threads;
+        self
+    }
+
+    /// Overwrite the number of read threads specified in [`RepoConfig::read_threads`].
+    ///
+    /// Unlike other configuration options, this applies both when creating a new repository and
+    /// when opening an existing one.
+    ///
+    /// [`RepoConfig::read_threads`]: crate::repo::RepoConfig::read_threads
+    pub fn read_threads(&mut self, threads: usize) -> &mut Self {
+        self.config.read_threads = threads;
+        self
+    }
+
     /// Use the given `password`.
     ///
-    /// This is required when encryption is enabled for the repository.
+    /// This is required when encryption is enabled for the repository, unless a key provider is
+    /// used instead.
+    ///
+    /// This replaces any callback set with [`password_callback`].
+    ///
+    /// [`password_callback`]: crate::repo::OpenOptions::password_callback
     pub fn password(&mut self, password: &'a [u8]) -> &mut Self {
         self.password = Some(password);
+        self.password_callback = None;
+        self
+    }
+
+    /// Get the password from the given `callback` only when it's needed.
+    ///
+    /// This is an alternative to [`password`] for interactive applications which prompt the user
+    /// for a password or applications which fetch it lazily from a secrets agent. `callback` is
+    /// only invoked if the repository is encrypted and doesn't use a key provider. It is passed the
+    /// number of previous attempts which were rejected.
+    ///
+    /// When opening an existing repository, if the password returned by `callback` is wrong, it is
+    /// invoked again, up to `attempts` times in total. When creating a new repository, it is only
+    /// invoked once. If `callback` returns `None`, opening the repository fails with
+    /// `Error::Cancelled`.
+    ///
+    /// This replaces any password set with [`password`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use acid_store::repo::{OpenOptions, OpenMode, key::KeyRepo};
+    /// # use acid_store::store::MemoryConfig;
+    /// let mut repo: KeyRepo<String> = OpenOptions::new()
+    ///     .mode(OpenMode::Create)
+    ///     .password_callback(3, |attempt| {
+    ///         if attempt > 0 {
+    ///             eprintln!("The password was incorrect.");
+    ///         }
+    ///         Some(b"password".to_vec())
+    ///     })
+    ///     .open(&MemoryConfig::new())
+    ///     .unwrap();
+    /// ```
+    ///
+    /// [`password`]: crate::repo::OpenOptions::password
+    pub fn password_callback(
+        &mut self,
+        attempts: u32,
+        callback: impl FnMut(u32) -> Option<Vec<u8>> + 'a,
+    ) -> &mut Self {
+        self.password = None;
+        self.password_callback = Some(Box::new(callback));
+        self.password_attempts = attempts.max(1);
+        self
+    }
+
+    /// Use the given convergence `secret` for convergent encryption.
+    ///
+    /// This is required when creating a new repository with
+    /// [`RepoConfig::convergent_encryption`] enabled. Repositories created with the same secret
+    /// produce identical blocks for identical chunks of data. This is ignored when opening an
+    /// existing repository.
+    ///
+    /// [`RepoConfig::convergent_encryption`]: crate::repo::RepoConfig::convergent_encryption
+    pub fn convergence_secret(&mut self, secret: &'a [u8]) -> &mut Self {
+        self.convergence_secret = Some(secret);
+        self
+    }
+
+    /// Overwrite the convergent encryption setting specified in
+    /// [`RepoConfig::convergent_encryption`].
+    ///
+    /// This is only applicable when creating a new repository. This is ignored when opening an
+    /// existing repository.
+    ///
+    /// [`RepoConfig::convergent_encryption`]: crate::repo::RepoConfig::convergent_encryption
+    pub fn convergent_encryption(&mut self, enabled: bool) -> &mut Self {
+        self.config.convergent_encryption = enabled;
+        self
+    }
+
+    /// Overwrite the commit log setting specified in [`RepoConfig::commit_log`].
+    ///
+    /// This is only applicable when creating a new repository. This is ignored when opening an
+    /// existing repository.
+    ///
+    /// [`RepoConfig::commit_log`]: crate::repo::RepoConfig::commit_log
+    pub fn commit_log(&mut self, enabled: bool) -> &mut Self {
+        self.config.commit_log = enabled;
+        self
+    }
+
+    /// Overwrite the number of retained commits specified in [`RepoConfig::retained_commits`].
+    ///
+    /// This is only applicable when creating a new repository. This is ignored when opening an
+    /// existing repository.
+    ///
+    /// [`RepoConfig::retained_commits`]: crate::repo::RepoConfig::retained_commits
+    pub fn retained_commits(&mut self, commits: usize) -> &mut Self {
+        self.config.retained_commits = commits;
+        self
+    }
+
+    /// Overwrite the metadata encryption setting specified in [`RepoConfig::encrypt_metadata`].
+    ///
+    /// This is only applicable when creating a new repository. This is ignored when opening an
+    /// existing repository.
+    ///
+    /// [`RepoConfig::encrypt_metadata`]: crate::repo::RepoConfig::encrypt_metadata
+    pub fn encrypt_metadata(&mut self, enabled: bool) -> &mut Self {
+        self.config.encrypt_metadata = enabled;
+        self
+    }
+
+    /// Use the given `key_provider` to protect the master key instead of a password.
+    ///
+    /// When creating a new repository with encryption enabled, the master key is wrapped with
+    /// `key_provider` and any password is ignored. When opening an existing repository which was
+    /// created with a key provider, this is required to unwrap the master key. See
+    /// [`KeyProvider`] for details.
+    ///
+    /// [`KeyProvider`]: crate::repo::KeyProvider
+    pub fn key_provider(&mut self, key_provider: Arc<dyn KeyProvider>) -> &mut Self {
+        self.key_provider = Some(key_provider);
         self
     }
 
@@ -228,7 +432,8 @@ impl<'a> OpenOptions<'a> {
     /// lock is acquired. If a lock's context is not specified, the context value of the acquired
     /// lock will be empty. If encryption is enabled for the repository, the lock context is
     /// encrypted. You can change the context value of the held lock once the repository is open
-    /// using [`Unlock::update_context`].
+    /// using [`Unlock::update_context`]. To store information like the hostname and process ID in
+    /// the context instead, use [`lock_description`].
     ///
     /// This method also accepts a `handler` which is invoked if a lock is already held on the
     /// repository. This lock handler is passed the context value of the existing lock. If `handler`
@@ -236,6 +441,9 @@ impl<'a> OpenOptions<'a> {
     /// `handler` returns `false`, the existing lock will be respected and opening the repository
     /// will fail. If a lock handler is not specified, an existing lock will always be respected.
     ///
+    /// If the existing lock was acquired with a lease which has since expired, it is removed without
+    /// invoking `handler`. See [`lease`] for details.
+    ///
     /// Opening a repository can still fail due to lock conflicts even if `handler` returns `true`
     /// or is never called.
     ///
@@ -257,6 +465,8 @@ impl<'a> OpenOptions<'a> {
     /// ```
     ///
     /// [`Unlock::update_context`]: crate::repo::Unlock::update_context
+    /// [`lease`]: crate::repo::OpenOptions::lease
+    /// [`lock_description`]: crate::repo::OpenOptions::lock_description
     pub fn locking(
         &mut self,
         context: &'a [u8],
@@ -267,6 +477,44 @@ impl<'a> OpenOptions<'a> {
         self
     }
 
+    /// Store information about this client in the context of the lock on the repository.
+    ///
+    /// This sets the context value of the acquired lock to a [`LockInfo`] containing the hostname,
+    /// the process ID, the current time, and the given `description`, which should identify the
+    /// application which is opening the repository. Lock handlers set with [`locking`] can pass the
+    /// context of an existing lock to [`LockInfo::from_context`] to find out who holds it before
+    /// deciding whether to remove it.
+    ///
+    /// If this is set, the `context` passed to [`locking`] is ignored.
+    ///
+    /// [`LockInfo`]: crate::repo::LockInfo
+    /// [`LockInfo::from_context`]: crate::repo::LockInfo::from_context
+    /// [`locking`]: crate::repo::OpenOptions::locking
+    pub fn lock_description(&mut self, description: &'a str) -> &mut Self {
+        self.lock_description = Some(description);
+        self
+    }
+
+    /// Acquire the lock on the repository with a lease which expires after `ttl`.
+    ///
+    /// While the repository is open, a background thread refreshes the lease in the data store
+    /// several times per `ttl`. If the process crashes or otherwise stops refreshing the lease, the
+    /// lock expires, and the next client to open the repository removes it without invoking its
+    /// lock handler. This makes it possible to recover from stale locks without guessing whether
+    /// their holder is still alive.
+    ///
+    /// Whether a lease has expired is determined using the system clock of the client opening the
+    /// repository, so `ttl` should be long compared to any clock skew between clients. The lease
+    /// can only be refreshed while the data store is reachable, so `ttl` should also be long
+    /// compared to any expected network outage.
+    ///
+    /// Locks acquired without a lease never expire, which is the default. Older versions of this
+    /// library treat the context of a lock with a lease as opaque data.
+    pub fn lease(&mut self, ttl: Duration) -> &mut Self {
+        self.lease = Some(ttl);
+        self
+    }
+
     /// Open the instance of the repository with the given `id`.
     ///
     /// Opening a repository without specifying an instance ID will always open the same default
@@ -278,9 +526,79 @@ impl<'a> OpenOptions<'a> {
     /// [`DEFAULT_INSTANCE`]: crate::repo::DEFAULT_INSTANCE
     pub fn instance(&mut self, id: InstanceId) -> &mut Self {
         self.instance = id;
+        self.instance_name = None;
         self
     }
 
+    /// Open the instance of the repository with the given `name`.
+    ///
+    /// Unlike [`instance`], this does not create a new instance if there is no instance with the
+    /// given name. Instances are named with [`KeyRepo::name_instance`]. Opening a repository
+    /// returns [`Error::NotFound`] if there is no instance with this name, which is always the
+    /// case when creating a new repository.
+    ///
+    /// This overrides any instance ID passed to [`instance`].
+    ///
+    /// [`instance`]: crate::repo::OpenOptions::instance
+    /// [`KeyRepo::name_instance`]: crate::repo::key::KeyRepo::name_instance
+    /// [`Error::NotFound`]: crate::Error::NotFound
+    pub fn instance_name(&mut self, name: &'a str) -> &mut Self {
+        self.instance_name = Some(name);
+        self
+    }
+
+    /// Check the health of the data store before opening the repository.
+    ///
+    /// If this is enabled, [`DataStore::health_check`] is called on the data store before the
+    /// repository is opened or created, and opening the repository fails with a descriptive error
+    /// if any of the checks fail. This can turn a confusing I/O error partway through a commit into
+    /// an actionable error up front, at the cost of a few extra round trips to the data store.
+    ///
+    /// This is disabled by default.
+    ///
+    /// [`DataStore::health_check`]: crate::store::DataStore::health_check
+    pub fn health_check(&mut self, enabled: bool) -> &mut Self {
+        self.health_check = enabled;
+        self
+    }
+
+    /// Report metrics about the repository to the given `metrics` callback.
+    ///
+    /// This can be used to collect information like how many chunks are written, how well data
+    /// compresses, and how long operations on the data store take. See [`Metrics`] for details.
+    ///
+    /// [`Metrics`]: crate::repo::Metrics
+    #[cfg(feature = "observability")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "observability")))]
+    pub fn metrics(&mut self, metrics: Arc<dyn Metrics>) -> &mut Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Return the password for the repository, invoking the password callback if there is one.
+    ///
+    /// This accepts the number of previous `attempts` which were rejected.
+    fn request_password(&mut self, attempts: u32) -> crate::Result<Cow<'a, [u8]>> {
+        if let Some(password) = self.password {
+            return Ok(Cow::Borrowed(password));
+        }
+        let callback = self
+            .password_callback
+            .as_mut()
+            .ok_or(crate::Error::Password)?;
+        callback(attempts)
+            .map(Cow::Owned)
+            .ok_or(crate::Error::Cancelled)
+    }
+
+    /// Return the context value to store in the lock on the repository.
+    fn acquired_lock_context(&self) -> Cow<'a, [u8]> {
+        match self.lock_description {
+            Some(description) => Cow::Owned(LockInfo::new(description).to_context()),
+            None => Cow::Borrowed(self.lock_context),
+        }
+    }
+
     /// Open the repository, failing if it doesn't exist.
     fn open_repo<R: OpenRepo>(&mut self, mut store: impl DataStore + 'static) -> crate::Result<R> {
         // Read the repository version to see if this is a compatible repository.
@@ -294,35 +612,44 @@ impl<'a> OpenOptions<'a> {
             return Err(crate::Error::UnsupportedRepo);
         }
 
-        // Read the repository metadata from the super block.
+        // Read the parts of the repository metadata which are needed to decrypt the master key. The
+        // rest of the metadata may be encrypted.
         let serialized_metadata = store
             .read_block(BlockKey::Super)
             .map_err(crate::Error::Store)?
             .ok_or(crate::Error::Corrupt)?;
-        let metadata: RepoMetadata =
-            from_read(serialized_metadata.as_slice()).map_err(|_| crate::Error::Corrupt)?;
-
-        let password = match self.password {
-            Some(password) if metadata.config.encryption != Encryption::None => Some(password),
-            // Return an error if a password was required but not provided.
-            None if metadata.config.encryption != Encryption::None => {
-                return Err(crate::Error::Password)
-            }
-            _ => None,
-        };
+        let key_metadata = SuperBlock::from_bytes(&serialized_metadata)?.key_metadata();
 
-        // Decrypt the master key for the repository.
-        let master_key = match password {
-            Some(password_bytes) => metadata.decrypt_master_key(password_bytes)?,
-            None => EncryptionKey::new(Vec::new()),
+        // Decrypt the master key for the repository. Return an error if a password or key provider
+        // was required but not provided.
+        let master_key = if key_metadata.encryption == Encryption::None {
+            EncryptionKey::new(Vec::new())
+        } else if key_metadata.external_key {
+            let key_provider = self.key_provider.as_deref().ok_or(crate::Error::Password)?;
+            key_metadata.unwrap_master_key(key_provider)?
+        } else {
+            let mut attempts = 0;
+            loop {
+                let password = self.request_password(attempts)?;
+                match key_metadata.decrypt_master_key(&password) {
+                    // Ask the password callback again if the password was wrong.
+                    Err(crate::Error::Password)
+                        if self.password.is_none() && attempts + 1 < self.password_attempts =>
+                    {
+                        attempts += 1;
+                    }
+                    result => break result?,
+                }
+            }
         };
 
         // Attempt to acquire a lock on the repository.
         let lock_id = lock_store(
             &mut store,
-            &metadata.config.encryption,
+            &key_metadata.encryption,
             &master_key,
-            self.lock_context,
+            &self.acquired_lock_context(),
+            self.lease,
             &mut self.lock_handler,
         )?;
 
@@ -333,8 +660,14 @@ impl<'a> OpenOptions<'a> {
             .read_block(BlockKey::Super)
             .map_err(crate::Error::Store)?
             .ok_or(crate::Error::Corrupt)?;
-        let metadata: RepoMetadata =
-            from_read(serialized_metadata.as_slice()).map_err(|_| crate::Error::Corrupt)?;
+        let mut metadata = SuperBlock::from_bytes(&serialized_metadata)?.open(&master_key)?;
+
+        // The number of write, verify, and read threads is not stored in the repository.
+        metadata.config.write_threads = self.config.write_threads;
+        metadata.config.verify_threads = self.config.verify_threads;
+        metadata.config.read_threads = self.config.read_threads;
+
+        let convergence_key = metadata.decrypt_convergence_key(&master_key)?;
 
         // Read, decrypt, decompress, and deserialize the repository header.
         let encrypted_header = store
@@ -358,28 +691,63 @@ impl<'a> OpenOptions<'a> {
             packs,
             instances,
             handle_table,
+            checkpoints,
+            commit_log,
         } = header;
 
+        // The fingerprint is recomputed from scratch the next time changes are committed.
+        let chunk_tree = ChunkTree::new();
+
+        let store: Arc<Mutex<Box<dyn DataStore>>> = Arc::new(Mutex::new(Box::new(store)));
+        let heartbeat = self.lease.map(|ttl| {
+            Heartbeat::start(
+                Arc::downgrade(&store),
+                metadata.config.encryption.clone(),
+                &master_key,
+                lock_id,
+                ttl,
+            )
+        });
+
         let state = Arc::new(RwLock::new(RepoState {
-            store: Mutex::new(Box::new(store)),
+            store,
             metadata,
             chunks,
+            chunk_tree,
             packs,
             transactions: LockTable::new(),
             master_key,
+            convergence_key,
+            apparent_header_size: serialized_header.len() as u64,
+            header_size: encrypted_header.len() as u64,
+            instance_size: 0,
+            instance_quota: None,
             lock_id,
+            lease: self.lease,
+            heartbeat,
+            hooks: Mutex::new(Hooks::default()),
+            #[cfg(feature = "observability")]
+            metrics: self.metrics.clone(),
         }));
 
         let repo: KeyRepo<R::Key> = KeyRepo {
             state,
             instance_id: self.instance,
             objects: HashMap::new(),
+            handle_index: RwLock::new(HashMap::new()),
             instances,
             handle_table,
+            checkpoints,
+            commit_log,
             transaction_id: Arc::new(Uuid::new_v4()),
         };
 
-        repo.change_instance(self.instance)
+        let instance_id = match self.instance_name {
+            Some(name) => repo.find_instance(name).ok_or(crate::Error::NotFound)?,
+            None => self.instance,
+        };
+
+        repo.change_instance(instance_id)
     }
 
     /// Create a new repository, failing if one already exists.
@@ -387,14 +755,21 @@ impl<'a> OpenOptions<'a> {
         &mut self,
         mut store: impl DataStore + 'static,
     ) -> crate::Result<R> {
-        let password = match self.password {
-            Some(password) if self.config.encryption != Encryption::None => Some(password),
-            // Return an error if a password was required but not provided.
-            None if self.config.encryption != Encryption::None => {
-                return Err(crate::Error::Password)
-            }
-            _ => None,
-        };
+        // A new repository has no named instances.
+        if self.instance_name.is_some() {
+            return Err(crate::Error::NotFound);
+        }
+
+        let encrypted = self.config.encryption != Encryption::None;
+
+        // A key provider takes precedence over a password.
+        let key_provider = self.key_provider.as_deref().filter(|_| encrypted);
+        let needs_password = encrypted && key_provider.is_none();
+
+        // Return an error if a password or key provider was required but not provided.
+        if needs_password && self.password.is_none() && self.password_callback.is_none() {
+            return Err(crate::Error::Password);
+        }
 
         // Check if the repository already exists.
         if store
@@ -405,10 +780,50 @@ impl<'a> OpenOptions<'a> {
             return Err(crate::Error::AlreadyExists);
         }
 
+        // The password callback is only invoked once we know the repository will be created.
+        let password = if needs_password {
+            Some(self.request_password(0)?)
+        } else {
+            None
+        };
+
+        // Check the key derivation parameters before acquiring a lock.
+        if let (Some(..), Some(key_derivation)) = (&password, &self.config.key_derivation) {
+            key_derivation.validate(self.config.encryption.key_size())?;
+        }
+
+        // Convergent encryption requires a secret, and packs can't be deduplicated.
+        let convergence_key = if self.config.convergent_encryption {
+            if self.config.packing != Packing::None {
+                return Err(crate::Error::InvalidConfig);
+            }
+            let secret = self.convergence_secret.ok_or(crate::Error::InvalidConfig)?;
+            Some(EncryptionKey::convergent(secret))
+        } else {
+            None
+        };
+
+        // Metadata can only be encrypted if encryption is enabled.
+        if self.config.encrypt_metadata && !encrypted {
+            return Err(crate::Error::InvalidConfig);
+        }
+
+        // At least the most recent commit must be retained.
+        if self.config.retained_commits == 0 {
+            return Err(crate::Error::InvalidConfig);
+        }
+
         // Generate the master encryption key.
-        let master_key = match password {
-            Some(..) => EncryptionKey::generate(self.config.encryption.key_size()),
-            None => EncryptionKey::new(Vec::new()),
+        let master_key = if encrypted {
+            EncryptionKey::generate(self.config.encryption.key_size())
+        } else {
+            EncryptionKey::new(Vec::new())
+        };
+
+        // Wrap the master key before acquiring a lock in case the key provider fails.
+        let wrapped_master_key = match key_provider {
+            Some(key_provider) => Some(key_provider.wrap_key(master_key.expose_secret())?),
+            None => None,
         };
 
         // Attempt to acquire a lock on the data store.
@@ -416,7 +831,8 @@ impl<'a> OpenOptions<'a> {
             &mut store,
             &self.config.encryption,
             &master_key,
-            self.lock_context,
+            &self.acquired_lock_context(),
+            self.lease,
             &mut self.lock_handler,
         )?;
 
@@ -426,20 +842,15 @@ impl<'a> OpenOptions<'a> {
         };
 
         // Encrypt the master encryption key.
-        let encrypted_master_key = match password {
-            Some(password_bytes) => {
-                let user_key = EncryptionKey::derive(
-                    password_bytes,
-                    &salt,
-                    self.config.encryption.key_size(),
-                    self.config.memory_limit,
-                    self.config.operations_limit,
-                );
+        let encrypted_master_key = match (wrapped_master_key, password.as_deref()) {
+            (Some(wrapped_master_key), _) => wrapped_master_key,
+            (None, Some(password_bytes)) => {
+                let user_key = self.config.derive_key(password_bytes, &salt)?;
                 self.config
                     .encryption
                     .encrypt(master_key.expose_secret(), &user_key)
             }
-            None => Vec::new(),
+            (None, None) => Vec::new(),
         };
 
         // Generate the header.
@@ -448,6 +859,8 @@ impl<'a> OpenOptions<'a> {
             packs: HashMap::new(),
             instances: HashMap::new(),
             handle_table: HandleIdTable::new(),
+            checkpoints: BTreeMap::new(),
+            commit_log: Vec::new(),
         };
 
         // Serialize, encode, and write the header to the data store.
@@ -463,6 +876,10 @@ impl<'a> OpenOptions<'a> {
             .write_block(BlockKey::Header(header_id), &encrypted_header)
             .map_err(crate::Error::Store)?;
 
+        // Compute the fingerprint of the empty repository.
+        let mut chunk_tree = ChunkTree::new();
+        let fingerprint = chunk_tree.fingerprint(header.chunks.keys());
+
         // Create the repository metadata with the header block references.
         let metadata = RepoMetadata {
             id: Uuid::new_v4().into(),
@@ -470,10 +887,22 @@ impl<'a> OpenOptions<'a> {
             master_key: encrypted_master_key,
             salt,
             header_id,
+            flushed_header_id: None,
+            previous_header_ids: Vec::new(),
+            external_key: key_provider.is_some(),
+            convergence_key: match &convergence_key {
+                Some(convergence_key) => self
+                    .config
+                    .encryption
+                    .encrypt(convergence_key.expose_secret(), &master_key),
+                None => Vec::new(),
+            },
+            fingerprint: Some(fingerprint),
+            upgrade_backup_id: None,
         };
 
         // Write the repository metadata.
-        let serialized_metadata = to_vec(&metadata).expect("Could not serialize metadata.");
+        let serialized_metadata = metadata.to_super_block(&master_key);
         store
             .write_block(BlockKey::Super, &serialized_metadata)
             .map_err(crate::Error::Store)?;
@@ -489,24 +918,51 @@ impl<'a> OpenOptions<'a> {
             packs,
             instances,
             handle_table,
+            checkpoints,
+            commit_log,
         } = header;
 
+        let store: Arc<Mutex<Box<dyn DataStore>>> = Arc::new(Mutex::new(Box::new(store)));
+        let heartbeat = self.lease.map(|ttl| {
+            Heartbeat::start(
+                Arc::downgrade(&store),
+                metadata.config.encryption.clone(),
+                &master_key,
+                lock_id,
+                ttl,
+            )
+        });
+
         let state = Arc::new(RwLock::new(RepoState {
-            store: Mutex::new(Box::new(store)),
+            store,
             metadata,
             chunks,
+            chunk_tree,
             packs,
             transactions: LockTable::new(),
             master_key,
+            convergence_key,
+            apparent_header_size: serialized_header.len() as u64,
+            header_size: encrypted_header.len() as u64,
+            instance_size: 0,
+            instance_quota: None,
             lock_id,
+            lease: self.lease,
+            heartbeat,
+            hooks: Mutex::new(Hooks::default()),
+            #[cfg(feature = "observability")]
+            metrics: self.metrics.clone(),
         }));
 
         let repo: KeyRepo<R::Key> = KeyRepo {
             state,
             instance_id: self.instance,
             objects: HashMap::new(),
+            handle_index: RwLock::new(HashMap::new()),
             instances,
             handle_table,
+            checkpoints,
+            commit_log,
             transaction_id: Arc::new(Uuid::new_v4()),
         };
 
@@ -518,28 +974,51 @@ impl<'a> OpenOptions<'a> {
     /// # Errors
     /// - `Error::NotFound`: There is no repository in the data store and `OpenMode::Open` was
     /// specified.
+    /// - `Error::NotFound`: An instance name was specified and there is no instance with that name.
     /// - `Error::AlreadyExists`: A repository already exists in the data store and
     /// `OpenMode::CreateNew` was specified.
     /// - `Error::Corrupt`: The repository is corrupt. This is most likely unrecoverable.
     /// - `Error::Locked`: The repository is locked.
     /// - `Error::Password`: The password provided is invalid.
     /// - `Error::Password`: A password was required but not provided.
+    /// - `Error::Password`: The password callback returned an invalid password too many times.
+    /// - `Error::Cancelled`: The password callback did not return a password.
+    /// - `Error::Password`: A key provider was required but not provided, or it could not unwrap
+    /// the master key.
     /// - `Error::Deserialize`: Could not deserialize some data in the repository.
     /// - `Error::UnsupportedRepo`: The repository is an unsupported format. This can happen if the
     /// serialized data format changed or if the data store already contains a different type of
-    /// repository.
+    /// repository. Repositories which use a previous format version can be upgraded with
+    /// [`upgrade_repo`].
     /// - `Error::UnsupportedStore`: The data store is an unsupported format. This can happen if
     /// the serialized data format changed or if the storage represented by `config` does not
     /// contain a valid data store.
     /// - `Error::InvalidData`: Ciphertext verification failed.
     /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Store`: A health check was requested and the data store is not healthy.
+    /// - `Error::InvalidConfig`: The key derivation parameters in the configuration are invalid.
+    /// - `Error::InvalidConfig`: Convergent encryption is enabled and either packing is enabled or
+    /// a convergence secret was not provided.
+    /// - `Error::InvalidConfig`: The number of retained commits in the configuration is `0`.
+    /// - `Error::InvalidConfig`: Metadata encryption is enabled and encryption is disabled.
     /// - `Error::Io`: An I/O error occurred.
+    ///
+    /// [`upgrade_repo`]: crate::repo::upgrade_repo
     pub fn open<R, C>(&mut self, config: &C) -> crate::Result<R>
     where
         R: OpenRepo,
         C: OpenStore,
     {
-        let mut store = config.open()?;
+        #[cfg(not(feature = "observability"))]
+        let mut store = ContextStore::new(config.open()?);
+        #[cfg(feature = "observability")]
+        let mut store = MetricsStore::new(ContextStore::new(config.open()?), self.metrics.clone());
+
+        if self.health_check {
+            if let Some(error) = store.health_check().error() {
+                return Err(crate::Error::Store(error));
+            }
+        }
 
         match self.mode {
             OpenMode::Open => self.open_repo(store),
@@ -565,8 +1044,13 @@ impl<'a> Debug for OpenOptions<'a> {
             .field("config", &self.config)
             .field("mode", &self.mode)
             .field("password", &self.password)
+            .field("key_provider", &self.key_provider)
             .field("instance", &self.instance)
+            .field("instance_name", &self.instance_name)
             .field("lock_context", &self.lock_context)
+            .field("lock_description", &self.lock_description)
+            .field("lease", &self.lease)
+            .field("health_check", &self.health_check)
             .finish_non_exhaustive()
     }
 }
@@ -1,5 +1,7 @@
 use static_assertions::assert_obj_safe;
+use uuid::Uuid;
 
+use super::format::Format;
 use super::key::Key;
 use super::repository::KeyRepo;
 use super::state::InstanceId;
@@ -9,6 +11,23 @@ uuid_type! {
     VersionId
 }
 
+impl VersionId {
+    /// Return the version ID of a repository with this version ID which serializes values using
+    /// the format `F`.
+    ///
+    /// Repositories which accept a [`Format`] type parameter should use this to compute their
+    /// [`OpenRepo::VERSION_ID`], so that opening a repository with a different format than it was
+    /// created with returns `Error::UnsupportedRepo`. The version ID for [`MessagePack`] is the
+    /// same as this version ID.
+    ///
+    /// [`Format`]: crate::repo::Format
+    /// [`OpenRepo::VERSION_ID`]: crate::repo::OpenRepo::VERSION_ID
+    /// [`MessagePack`]: crate::repo::MessagePack
+    pub const fn with_format<F: Format>(self) -> Self {
+        Self(Uuid::from_u128(self.0.as_u128() ^ F::ID.as_u128()))
+    }
+}
+
 /// A repository which can be opened using [`OpenOptions`].
 ///
 /// This trait represents a repository type which can be converted to and from a [`KeyRepo`].
@@ -13,6 +13,12 @@ use serde::{Deserialize, Serialize};
 ///
 /// Choosing `Packing::Fixed` provides no additional security if encryption is disabled. If
 /// encryption is not needed, you should use `Packing::None`.
+///
+/// Because each pack is encrypted as a whole, reading a block from an encrypted repository requires
+/// reading every pack it's stored in. If encryption is disabled and the data store supports
+/// [`DataStore::read_block_range`], only the part of each pack containing the block is read.
+///
+/// [`DataStore::read_block_range`]: crate::store::DataStore::read_block_range
 #[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub enum Packing {
     /// Do not pack data into fixed-size blocks.
@@ -32,3 +38,31 @@ impl Packing {
     /// A reasonable default value of `Packing::Fixed`.
     pub const FIXED: Self = Packing::Fixed(1024 * 64);
 }
+
+/// Options for repacking a repository with [`KeyRepo::repack`].
+///
+/// This type implements `Default`, which returns options that leave the repository unchanged.
+///
+/// [`KeyRepo::repack`]: crate::repo::key::KeyRepo::repack
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+#[non_exhaustive]
+pub struct RepackOptions {
+    /// The packing method to switch the repository to.
+    ///
+    /// If this is `None`, the repository's current packing method is kept. If this is different
+    /// from the current packing method, all the data in the repository is rewritten using the new
+    /// packing method.
+    ///
+    /// The default value is `None`.
+    pub packing: Option<Packing>,
+
+    /// Whether to rewrite data stored in packs which are mostly empty.
+    ///
+    /// Over time, cleaning a repository can leave behind packs which contain mostly unreferenced
+    /// data. If this is `true`, data in packs which are less than half full is rewritten into new
+    /// packs. This has no effect if packing is disabled or if the packing method is changing, since
+    /// all data is rewritten in that case.
+    ///
+    /// The default value is `false`.
+    pub defragment: bool,
+}
@@ -1,32 +1,52 @@
 use std::borrow::Borrow;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, BinaryHeap, HashMap, HashSet};
 use std::hash::Hash;
+use std::io::{self, Read, Write};
+use std::iter;
 use std::mem;
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, SystemTime};
 
 use rmp_serde::{from_read, to_vec};
 use secrecy::ExposeSecret;
 use static_assertions::assert_impl_all;
 use uuid::{uuid, Uuid};
 
-use crate::store::{BlockKey, BlockType, DataStore};
+use crate::store::{BlockId, BlockKey, BlockType, DataStore};
 
+use super::check::{CheckReport, Inconsistencies};
+use super::checkpoint::Checkpoints;
 use super::chunk_store::{
-    EncodeBlock, ReadBlock, ReadChunk, StoreReader, StoreState, StoreWriter, WriteBlock,
+    repack_block, EncodeBlock, ReadBlock, ReadChunk, StoreReader, StoreState, StoreWriter,
+    WriteBlock, WriteChunk,
 };
+use super::chunking::{Chunking, IncrementalChunker};
 use super::commit::Commit;
-use super::encryption::{Encryption, EncryptionKey, KeySalt, ResourceLimit};
-use super::handle::{chunk_hash, HandleIdTable, ObjectHandle};
-use super::key::{Key, Keys};
-use super::lock::{unlock_store, Unlock};
-use super::metadata::{Header, RepoInfo, RepoStats};
+use super::commit_log::{verify_commit_log, CommitRecord};
+use super::encryption::{Encryption, KeySalt, ResourceLimit};
+use super::fingerprint::Fingerprint;
+use super::format::MessagePack;
+use super::handle::{
+    chunk_hash, Chunk, ChunkHash, ChunkId, ContentId, Extent, HandleId, HandleIdTable,
+    ObjectHandle, ObjectHandles, ObjectId,
+};
+use super::hooks::{HookId, RepoEvent};
+use super::key::{Key, Keys, KeysWithPrefix};
+use super::lock::{read_lock, unlock_store, write_lock, LockContents, Unlock};
+use super::merge::{MergeConflict, MergeReport};
+use super::metadata::{Header, RepoInfo, RepoStats, UsageStats};
 use super::object::Object;
 use super::object_store::{ObjectReader, ObjectWriter};
 use super::open_repo::OpenRepo;
 use super::open_repo::VersionId;
-use super::packing::Packing;
+use super::packing::{Packing, RepackOptions};
 use super::savepoint::{KeyRestore, RestoreSavepoint, Savepoint};
-use super::state::{InstanceId, InstanceInfo, ObjectState, RepoState};
+use super::shared::SharedKeyRepo;
+use super::state::{InstanceId, InstanceInfo, InstanceSummary, ObjectState, RepoState};
+
+/// The size of the buffer used when reading data to compute its content ID.
+const READ_BUFFER_SIZE: usize = 1024 * 64;
 
 /// An object store which maps keys to seekable binary blobs.
 ///
@@ -42,6 +62,12 @@ pub struct KeyRepo<K: Key> {
     /// A map of object keys to their object handles for the current instance.
     pub(super) objects: HashMap<K, Arc<RwLock<ObjectHandle>>>,
 
+    /// A possibly stale map of handle IDs to the keys of their objects in the current instance.
+    ///
+    /// This is built the first time an `ObjectId` is resolved and rebuilt whenever it's found to
+    /// be out of date, so it doesn't need to be updated each time `objects` changes.
+    pub(super) handle_index: RwLock<HashMap<HandleId, K>>,
+
     /// A map of instance IDs to information about those instances.
     pub(super) instances: HashMap<InstanceId, InstanceInfo>,
 
@@ -51,6 +77,12 @@ pub struct KeyRepo<K: Key> {
     /// storing it.
     pub(super) handle_table: HandleIdTable,
 
+    /// A map of checkpoint names to the IDs of the header blocks they were saved to.
+    pub(super) checkpoints: BTreeMap<String, BlockId>,
+
+    /// The log of commits to the repository.
+    pub(super) commit_log: Vec<CommitRecord>,
+
     /// The unique ID for the current transaction.
     ///
     /// This ID changes each time the repository is opened or committed. It is used to invalidate
@@ -103,6 +135,7 @@ impl<K: Key> KeyRepo<K> {
         let handle = ObjectHandle {
             id: handle_id,
             extents: Vec::new(),
+            attributes: BTreeMap::new(),
         };
         assert!(!self.objects.contains_key(&key));
         let handle = self
@@ -112,19 +145,44 @@ impl<K: Key> KeyRepo<K> {
         Object::new(&self.state, handle)
     }
 
+    /// Add a new object with the given `key` which expires after `ttl` and return it.
+    ///
+    /// This is the same as [`insert`], except the object is removed by [`expire`] once `ttl` has
+    /// elapsed. The expiration time can be changed later with [`set_expiry`].
+    ///
+    /// [`insert`]: crate::repo::key::KeyRepo::insert
+    /// [`expire`]: crate::repo::key::KeyRepo::expire
+    /// [`set_expiry`]: crate::repo::key::KeyRepo::set_expiry
+    pub fn insert_with_ttl(&mut self, key: K, ttl: Duration) -> Object {
+        let object = self.insert(key.clone());
+        let handle_id = self.objects[&key].read().unwrap().id;
+        self.instances
+            .get_mut(&self.instance_id)
+            .unwrap()
+            .expirations
+            .insert(handle_id, SystemTime::now() + ttl);
+        object
+    }
+
     /// Remove the given object `handle` from the repository.
-    fn remove_handle(&mut self, handle: &ObjectHandle) {
+    pub(super) fn remove_handle(&mut self, handle: &ObjectHandle) {
         let mut state = self.state.write().unwrap();
         for chunk in handle.chunks() {
-            let chunk_info = state
-                .chunks
-                .get_mut(&chunk)
-                .expect("This chunk was not found in the repository.");
+            // The chunk may be missing if the repository is damaged. See `KeyRepo::check`.
+            let chunk_info = match state.chunks.get_mut(&chunk) {
+                Some(chunk_info) => chunk_info,
+                None => continue,
+            };
             chunk_info.references.remove(&handle.id);
             if chunk_info.references.is_empty() {
                 state.chunks.remove(&chunk);
+                state.chunk_tree.invalidate(&chunk);
             }
         }
+        state.instance_size = state.instance_size.saturating_sub(handle.size());
+        if let Some(instance_info) = self.instances.get_mut(&self.instance_id) {
+            instance_info.expirations.remove(&handle.id);
+        }
         self.handle_table.recycle(handle.id);
     }
 
@@ -150,6 +208,319 @@ impl<K: Key> KeyRepo<K> {
         true
     }
 
+    /// Add several objects with the given keys and contents to the repository at once.
+    ///
+    /// This is equivalent to calling [`insert`] for each key and writing its data to the returned
+    /// object, but it amortizes the cost of writing to the data store. The chunks for all the
+    /// objects are encoded together, concurrently if [`RepoConfig::write_threads`] is greater than
+    /// one, and the repository state is only locked once.
+    ///
+    /// If another object with the same key already exists, it is replaced. If the same key
+    /// appears more than once in `objects`, the last one wins.
+    ///
+    /// If this returns `Err`, none of the objects are added to the repository.
+    ///
+    /// # Errors
+    /// - `Error::QuotaExceeded`: Adding the objects would exceed the quota for this instance.
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    ///
+    /// [`insert`]: crate::repo::key::KeyRepo::insert
+    /// [`RepoConfig::write_threads`]: crate::repo::RepoConfig::write_threads
+    pub fn insert_batch<D: AsRef<[u8]>>(
+        &mut self,
+        objects: impl IntoIterator<Item = (K, D)>,
+    ) -> crate::Result<()> {
+        let chunking = self.state.read().unwrap().metadata.config.chunking.clone();
+
+        // Split the data for each object into chunks, keeping track of which handle each chunk
+        // belongs to.
+        let mut new_handles = Vec::new();
+        let mut chunks = Vec::new();
+        let mut chunk_ids = Vec::new();
+        for (key, data) in objects {
+            let data = data.as_ref();
+            let mut chunker = IncrementalChunker::new(chunking.to_chunker());
+            chunker.write_all(data)?;
+            chunker.flush()?;
+            let object_chunks = chunker.chunks();
+
+            let handle_id = self.handle_table.next();
+            chunk_ids.extend(iter::repeat(handle_id).take(object_chunks.len()));
+            new_handles.push((key, handle_id, object_chunks.len(), data.len() as u64));
+            chunks.extend(object_chunks);
+        }
+
+        if let Err(error) = self.check_batch_quota(&new_handles) {
+            for (_, handle_id, _, _) in new_handles {
+                self.handle_table.recycle(handle_id);
+            }
+            return Err(error);
+        }
+
+        let mut new_chunks = Vec::with_capacity(chunks.len());
+        let mut state = self.state.write().unwrap();
+        let mut store_state = StoreState::new();
+        let result = StoreWriter::new(&mut state, &mut store_state).write_batch(
+            &chunks,
+            &chunk_ids,
+            &mut new_chunks,
+        );
+
+        if let Err(error) = result {
+            // Remove the references to any chunks which were written before the error occurred.
+            remove_references(&mut state, new_chunks.into_iter().zip(chunk_ids));
+            drop(state);
+            for (_, handle_id, _, _) in new_handles {
+                self.handle_table.recycle(handle_id);
+            }
+            return Err(error);
+        }
+        drop(state);
+
+        let mut new_chunks = new_chunks.into_iter();
+        for (key, handle_id, num_chunks, _) in new_handles {
+            self.remove(&key);
+            let handle = ObjectHandle {
+                id: handle_id,
+                extents: new_chunks
+                    .by_ref()
+                    .take(num_chunks)
+                    .map(Extent::Chunk)
+                    .collect(),
+                attributes: BTreeMap::new(),
+            };
+            self.state.write().unwrap().instance_size += handle.size();
+            self.objects.insert(key, Arc::new(RwLock::new(handle)));
+        }
+
+        Ok(())
+    }
+
+    /// Return an error if adding the batch of objects in `new_handles` would exceed the instance
+    /// quota.
+    ///
+    /// If a key appears more than once in `new_handles`, only its last object counts toward the
+    /// quota, since it replaces the others. The sizes of existing objects which are replaced by
+    /// the batch are subtracted.
+    fn check_batch_quota(&self, new_handles: &[(K, HandleId, usize, u64)]) -> crate::Result<()> {
+        let final_sizes = new_handles
+            .iter()
+            .map(|(key, _, _, size)| (key, *size))
+            .collect::<HashMap<_, _>>();
+        let batch_size = final_sizes.values().sum::<u64>();
+        let replaced_size = final_sizes
+            .keys()
+            .filter_map(|key| self.objects.get(*key))
+            .map(|handle| handle.read().unwrap().size())
+            .sum::<u64>();
+
+        self.check_quota(replaced_size, batch_size)
+    }
+
+    /// Return an error if removing `removed_size` bytes from the current instance and then adding
+    /// `added_size` bytes would exceed the instance quota.
+    fn check_quota(&self, removed_size: u64, added_size: u64) -> crate::Result<()> {
+        let state = self.state.read().unwrap();
+        let new_instance_size = state
+            .instance_size
+            .saturating_sub(removed_size)
+            .saturating_add(added_size);
+        match state.instance_quota {
+            Some(quota) if new_instance_size > quota => Err(crate::Error::QuotaExceeded),
+            _ => Ok(()),
+        }
+    }
+
+    /// Remove the objects with the given `keys` from the repository.
+    ///
+    /// This returns the number of objects which were removed. Keys which don't exist in the
+    /// repository are ignored.
+    ///
+    /// The space used by the given objects isn't reclaimed in the backing data store until changes
+    /// are committed and [`Commit::clean`] is called.
+    ///
+    /// [`Commit::clean`]: crate::repo::Commit::clean
+    pub fn remove_batch<'a, Q>(&mut self, keys: impl IntoIterator<Item = &'a Q>) -> usize
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized + 'a,
+    {
+        keys.into_iter().filter(|key| self.remove(*key)).count()
+    }
+
+    /// Set the time at which the object with the given `key` expires.
+    ///
+    /// Once the `expiry` time has passed, the object is removed by [`expire`]. If `expiry` is
+    /// `None`, the object never expires. This returns `true` if the expiration time was set or
+    /// `false` if there is no object with the given `key`.
+    ///
+    /// The expiration time stays with the object when it's renamed, but not when it's copied. It's
+    /// cleared when the object is replaced.
+    ///
+    /// This does not commit changes to the repository.
+    ///
+    /// [`expire`]: crate::repo::key::KeyRepo::expire
+    pub fn set_expiry<Q>(&mut self, key: &Q, expiry: Option<SystemTime>) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        let handle_id = match self.objects.get(key) {
+            Some(handle) => handle.read().unwrap().id,
+            None => return false,
+        };
+        let expirations = &mut self
+            .instances
+            .get_mut(&self.instance_id)
+            .unwrap()
+            .expirations;
+        match expiry {
+            Some(expiry) => expirations.insert(handle_id, expiry),
+            None => expirations.remove(&handle_id),
+        };
+        true
+    }
+
+    /// Return the time at which the object with the given `key` expires.
+    ///
+    /// This returns `None` if the object doesn't expire or there is no object with the given
+    /// `key`.
+    pub fn expiry<Q>(&self, key: &Q) -> Option<SystemTime>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        let handle_id = self.objects.get(key)?.read().unwrap().id;
+        self.instances
+            .get(&self.instance_id)?
+            .expirations
+            .get(&handle_id)
+            .copied()
+    }
+
+    /// Remove the objects in the current instance which have expired.
+    ///
+    /// Expired objects are not removed automatically; they can still be accessed until this is
+    /// called. This returns the number of objects which were removed.
+    ///
+    /// This does not commit changes to the repository. The space used by the removed objects isn't
+    /// reclaimed in the backing data store until changes are committed and [`Commit::clean`] is
+    /// called.
+    ///
+    /// [`Commit::clean`]: crate::repo::Commit::clean
+    pub fn expire(&mut self) -> usize {
+        let expirations = match self.instances.get(&self.instance_id) {
+            Some(instance_info) if !instance_info.expirations.is_empty() => {
+                &instance_info.expirations
+            }
+            _ => return 0,
+        };
+
+        let now = SystemTime::now();
+        let expired_keys = self
+            .objects
+            .iter()
+            .filter(|(_, handle)| {
+                matches!(
+                    expirations.get(&handle.read().unwrap().id),
+                    Some(expiry) if *expiry <= now
+                )
+            })
+            .map(|(key, _)| key.clone())
+            .collect::<Vec<_>>();
+
+        for key in &expired_keys {
+            self.remove(key);
+        }
+
+        expired_keys.len()
+    }
+
+    /// Set the attribute `name` of the object with the given `key` to `value`.
+    ///
+    /// Attributes are small pieces of user-defined metadata, such as a MIME type or the path the
+    /// object was imported from. They're stored alongside the object in the object map for the
+    /// current instance, so they're encrypted like the rest of the repository and are loaded into
+    /// memory when the repository is opened. Large values should be stored in objects instead.
+    ///
+    /// This returns `true` if the attribute was set or `false` if there is no object with the
+    /// given `key`. If the object already has an attribute named `name`, it is replaced.
+    ///
+    /// Attributes stay with the object when it's renamed or copied. They're cleared when the
+    /// object is replaced.
+    ///
+    /// This does not commit changes to the repository.
+    pub fn set_attr<Q>(
+        &mut self,
+        key: &Q,
+        name: impl Into<String>,
+        value: impl Into<Vec<u8>>,
+    ) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        match self.objects.get(key) {
+            Some(handle) => {
+                let mut handle = handle.write().unwrap();
+                handle.attributes.insert(name.into(), value.into());
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Return the value of the attribute `name` of the object with the given `key`.
+    ///
+    /// This returns `None` if the object doesn't have an attribute named `name` or there is no
+    /// object with the given `key`.
+    pub fn get_attr<Q>(&self, key: &Q, name: &str) -> Option<Vec<u8>>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.objects
+            .get(key)?
+            .read()
+            .unwrap()
+            .attributes
+            .get(name)
+            .cloned()
+    }
+
+    /// Remove the attribute `name` from the object with the given `key` and return its value.
+    ///
+    /// This returns `None` if the object doesn't have an attribute named `name` or there is no
+    /// object with the given `key`.
+    ///
+    /// This does not commit changes to the repository.
+    pub fn remove_attr<Q>(&mut self, key: &Q, name: &str) -> Option<Vec<u8>>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.objects
+            .get(key)?
+            .write()
+            .unwrap()
+            .attributes
+            .remove(name)
+    }
+
+    /// Return all the attributes of the object with the given `key`.
+    ///
+    /// This returns a map of attribute names to their values, or `None` if there is no object
+    /// with the given `key`.
+    pub fn attrs<Q>(&self, key: &Q) -> Option<BTreeMap<String, Vec<u8>>>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        Some(self.objects.get(key)?.read().unwrap().attributes.clone())
+    }
+
     /// Return an object for reading and writing the object with the given `key`.
     ///
     /// This returns `None` if there is no object with the given `key` in the repository.
@@ -167,6 +538,102 @@ impl<K: Key> KeyRepo<K> {
         Keys(self.objects.keys())
     }
 
+    /// Return an iterator over the keys of objects in this repository which start with `prefix`.
+    ///
+    /// This still visits every key in the repository, but it doesn't allocate.
+    pub fn keys_with_prefix<'a>(&'a self, prefix: &'a str) -> KeysWithPrefix<'a, K>
+    where
+        K: AsRef<str>,
+    {
+        KeysWithPrefix {
+            prefix,
+            inner: self.objects.keys(),
+        }
+    }
+
+    /// Return up to `limit` keys of objects in this repository which come after `cursor`.
+    ///
+    /// This allows for listing the keys in the repository incrementally without collecting all of
+    /// them at once. Keys are returned in sorted order. To get the first page, pass `None` as the
+    /// `cursor`. To get each subsequent page, pass the last key of the previous page. When this
+    /// returns fewer than `limit` keys, there are no more pages.
+    ///
+    /// Because pages are ordered by key, inserting or removing objects between calls never causes
+    /// a key to be skipped or returned twice. Keys inserted before the `cursor` won't be returned
+    /// until iteration starts over.
+    ///
+    /// Each call visits every key in the repository, but only holds `limit` keys in memory.
+    pub fn keys_page(&self, cursor: Option<&K>, limit: usize) -> Vec<&K>
+    where
+        K: Ord,
+    {
+        // Keep the smallest `limit` keys after the cursor in a max-heap.
+        let mut page = BinaryHeap::new();
+        for key in self.objects.keys() {
+            if cursor.is_some_and(|cursor| key <= cursor) {
+                continue;
+            }
+
+            if page.len() < limit {
+                page.push(key);
+            } else if page.peek().is_some_and(|largest| key < *largest) {
+                page.pop();
+                page.push(key);
+            }
+        }
+        page.into_sorted_vec()
+    }
+
+    /// Return the key of the object with the given `id` in the current instance.
+    ///
+    /// An [`ObjectId`] follows its object when it's renamed or swapped, so this can be used to
+    /// resolve references between objects which were stored by serializing an [`ObjectId`]. This
+    /// returns `None` if there is no object with the given `id` in the current instance.
+    ///
+    /// [`ObjectId`]: crate::repo::ObjectId
+    pub fn resolve(&self, id: ObjectId) -> Option<&K> {
+        if id.repo_id != self.state.read().unwrap().metadata.id {
+            return None;
+        }
+
+        if let Some(key) = self.indexed_key(id.handle_id) {
+            return Some(key);
+        }
+
+        // The index is either missing this handle or out of date, so we rebuild it.
+        let mut handle_index = self.handle_index.write().unwrap();
+        *handle_index = self
+            .objects
+            .iter()
+            .map(|(key, handle)| (handle.read().unwrap().id, key.clone()))
+            .collect();
+        drop(handle_index);
+
+        self.indexed_key(id.handle_id)
+    }
+
+    /// Return an `Object` for reading and writing the object with the given `id`.
+    ///
+    /// This returns `None` if there is no object with the given `id` in the current instance. See
+    /// [`resolve`] for details.
+    ///
+    /// [`resolve`]: crate::repo::key::KeyRepo::resolve
+    pub fn object_by_id(&self, id: ObjectId) -> Option<Object> {
+        let key = self.resolve(id)?;
+        self.object(key)
+    }
+
+    /// Look up the key for `handle_id` in the handle index and return it if it's up to date.
+    fn indexed_key(&self, handle_id: HandleId) -> Option<&K> {
+        let handle_index = self.handle_index.read().unwrap();
+        let (key, handle) = self.objects.get_key_value(handle_index.get(&handle_id)?)?;
+        if handle.read().unwrap().id == handle_id {
+            Some(key)
+        } else {
+            None
+        }
+    }
+
     /// Copy the object at `source` to `dest`.
     ///
     /// If another object already exists at `dest`, it is replaced.
@@ -179,8 +646,11 @@ impl<K: Key> KeyRepo<K> {
         K: Borrow<Q>,
         Q: Eq + Hash + ?Sized,
     {
-        let source_chunks = match self.objects.get(source) {
-            Some(handle) => handle.read().unwrap().extents.clone(),
+        let (source_chunks, source_attributes) = match self.objects.get(source) {
+            Some(handle) => {
+                let handle = handle.read().unwrap();
+                (handle.extents.clone(), handle.attributes.clone())
+            }
             None => return false,
         };
 
@@ -189,6 +659,7 @@ impl<K: Key> KeyRepo<K> {
         let dest_handle = ObjectHandle {
             id: self.handle_table.next(),
             extents: source_chunks,
+            attributes: source_attributes,
         };
 
         // Update the chunk map to include the new handle in the list of references for each chunk.
@@ -200,6 +671,7 @@ impl<K: Key> KeyRepo<K> {
                 .expect("This chunk was not found in the repository.");
             chunk_info.references.insert(dest_handle.id);
         }
+        state.instance_size += dest_handle.size();
 
         self.objects
             .insert(dest, Arc::new(RwLock::new(dest_handle)));
@@ -207,77 +679,532 @@ impl<K: Key> KeyRepo<K> {
         true
     }
 
-    /// Write the map of objects for the current instance to the data store.
-    pub(super) fn write_object_map(&mut self) -> crate::Result<()> {
-        let mut state = self.state.write().unwrap();
+    /// Move the object at `source` to `dest`.
+    ///
+    /// Unlike calling [`copy`] and then [`remove`], this doesn't create a new object. The object
+    /// keeps its identity, so any [`Object`] or [`ReadOnlyObject`] for it remains valid and refers
+    /// to the object at `dest`.
+    ///
+    /// # Errors
+    /// - `Error::NotFound`: There is no object at `source`.
+    /// - `Error::AlreadyExists`: There is already an object at `dest`.
+    ///
+    /// [`copy`]: crate::repo::key::KeyRepo::copy
+    /// [`remove`]: crate::repo::key::KeyRepo::remove
+    /// [`Object`]: crate::repo::Object
+    /// [`ReadOnlyObject`]: crate::repo::ReadOnlyObject
+    pub fn rename<Q>(&mut self, source: &Q, dest: K) -> crate::Result<()>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        if !self.objects.contains_key(source) {
+            return Err(crate::Error::NotFound);
+        }
+        if dest.borrow() == source {
+            return Ok(());
+        }
+        if self.objects.contains_key(dest.borrow()) {
+            return Err(crate::Error::AlreadyExists);
+        }
 
-        let handle = &mut self
-            .instances
-            .get_mut(&self.instance_id)
-            .expect("There is no instance with the given ID.")
-            .objects;
+        let handle = self.objects.remove(source).unwrap();
+        self.objects.insert(dest, handle);
 
-        let mut object_state = ObjectState::new(state.metadata.config.chunking.to_chunker());
-        let mut writer = ObjectWriter::new(&mut state, &mut object_state, handle);
-        writer.serialize(&self.objects)
+        Ok(())
     }
 
-    /// Read the object map for the current instance from the data store and return it.
+    /// Swap the objects at `first` and `second`.
     ///
-    /// This does not write the object map for the old instance first. To do that, use
-    /// `write_object_map`.
+    /// Like [`rename`], this doesn't copy or create any objects, so any [`Object`] or
+    /// [`ReadOnlyObject`] for either object remains valid and follows the object to its new key.
     ///
-    /// This does not commit or roll back changes.
-    pub(super) fn read_object_map(&self) -> crate::Result<HashMap<K, Arc<RwLock<ObjectHandle>>>> {
-        let state = self.state.read().unwrap();
-        match self.instances.get(&self.instance_id) {
-            Some(instance_info) => {
-                let mut object_state =
-                    ObjectState::new(state.metadata.config.chunking.to_chunker());
-                let mut reader =
-                    ObjectReader::new(&state, &mut object_state, &instance_info.objects);
-                reader.deserialize()
-            }
-            None => {
-                // If the current instance is not in the instance map, then this repository has not
-                // been committed since it was created and an object map has not been written for
-                // this instance.
-                Ok(HashMap::new())
-            }
+    /// # Errors
+    /// - `Error::NotFound`: There is no object at `first` or no object at `second`.
+    ///
+    /// [`rename`]: crate::repo::key::KeyRepo::rename
+    /// [`Object`]: crate::repo::Object
+    /// [`ReadOnlyObject`]: crate::repo::ReadOnlyObject
+    pub fn swap<Q>(&mut self, first: &Q, second: &Q) -> crate::Result<()>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        if !self.objects.contains_key(first) || !self.objects.contains_key(second) {
+            return Err(crate::Error::NotFound);
+        }
+        if first == second {
+            return Ok(());
         }
+
+        let (first_key, first_handle) = self.objects.remove_entry(first).unwrap();
+        let second_handle = mem::replace(self.objects.get_mut(second).unwrap(), first_handle);
+        self.objects.insert(first_key, second_handle);
+
+        Ok(())
     }
 
-    /// Set the current instance of the repository.
+    /// Compute the `ContentId` that `data` would have if it were written to this repository.
     ///
-    /// This does not write the object map for the current instance before switching to the new
-    /// instance.
-    pub(super) fn change_instance<R: OpenRepo>(
-        mut self,
-        instance_id: InstanceId,
-    ) -> crate::Result<R> {
-        let is_new_instance = !self.instances.contains_key(&instance_id);
-
-        let new_objects = if is_new_instance {
-            // Create the object handle for the object which will store the object map for the new
-            // instance.
-            let mut handle = ObjectHandle {
-                id: self.handle_table.next(),
-                extents: Vec::new(),
+    /// This splits `data` into chunks using the chunking configuration of this repository, but it
+    /// does not write anything to the data store. The returned content ID can be passed to
+    /// [`missing_chunks`] to find out which parts of `data` are not already in the repository.
+    ///
+    /// # Errors
+    /// - `Error::Io`: An I/O error occurred.
+    ///
+    /// [`missing_chunks`]: crate::repo::key::KeyRepo::missing_chunks
+    pub fn compute_content_id(&self, mut data: impl Read) -> crate::Result<ContentId> {
+        let state = self.state.read().unwrap();
+        let mut chunker = IncrementalChunker::new(state.metadata.config.chunking.to_chunker());
+        let mut buffer = vec![0u8; READ_BUFFER_SIZE];
+        let mut extents = Vec::new();
+
+        loop {
+            let bytes_read = match data.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(bytes_read) => bytes_read,
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                Err(error) => return Err(error.into()),
             };
+            chunker.write_all(&buffer[..bytes_read])?;
+            extents.extend(
+                chunker
+                    .chunks()
+                    .iter()
+                    .map(|chunk_data| new_extent(chunk_data)),
+            );
+        }
 
-            // Because this is a new instance, we return an empty object map.
-            let objects = HashMap::<R::Key, Arc<RwLock<ObjectHandle>>>::new();
+        chunker.flush()?;
+        extents.extend(
+            chunker
+                .chunks()
+                .iter()
+                .map(|chunk_data| new_extent(chunk_data)),
+        );
 
-            // Write an empty object map to the object.
+        Ok(ContentId {
+            repo_id: state.metadata.id,
+            extents,
+        })
+    }
+
+    /// Return whether the chunk with the given `id` is stored in this repository.
+    ///
+    /// Chunks are shared between all instances of the repository, so this may return `true` for
+    /// chunks which are only referenced by objects in other instances.
+    pub fn contains_chunk(&self, id: ChunkId) -> bool {
+        self.state.read().unwrap().chunks.contains_key(&id.0)
+    }
+
+    /// Return the IDs of the chunks in `content_id` which are not stored in this repository.
+    ///
+    /// The `content_id` may come from a different repository. This is useful for skipping data
+    /// which the repository already has when sending data to it over a network. The returned
+    /// chunks are in the order they appear in the contents, and each chunk is only returned once.
+    pub fn missing_chunks(&self, content_id: &ContentId) -> Vec<ChunkId> {
+        let state = self.state.read().unwrap();
+        let mut seen_chunks = HashSet::new();
+        content_id
+            .chunk_ids()
+            .into_iter()
+            .filter(|id| !state.chunks.contains_key(&id.0) && seen_chunks.insert(*id))
+            .collect()
+    }
+
+    /// Return the keys of the objects in the current instance whose contents match `content_id`.
+    ///
+    /// This compares the chunks which make up each object with the chunks in `content_id`, so it
+    /// doesn't read any data from the data store. Unlike comparing `ContentId` values, the
+    /// `content_id` may come from a different repository or be one returned by
+    /// [`compute_content_id`]. However, if it comes from a repository with a different chunking
+    /// configuration, objects with the same contents may not be found.
+    ///
+    /// This is useful for finding out whether some data is already stored under another key before
+    /// uploading it.
+    ///
+    /// [`compute_content_id`]: crate::repo::key::KeyRepo::compute_content_id
+    pub fn find_by_content_id(&self, content_id: &ContentId) -> HashSet<&K> {
+        self.objects
+            .iter()
+            .filter(|(_, handle)| handle.read().unwrap().extents == content_id.extents)
+            .map(|(key, _)| key)
+            .collect()
+    }
+
+    /// Return whether all the data in `content_id` is stored in this repository.
+    ///
+    /// This is equivalent to checking whether [`missing_chunks`] is empty.
+    ///
+    /// [`missing_chunks`]: crate::repo::key::KeyRepo::missing_chunks
+    pub fn contains_content(&self, content_id: &ContentId) -> bool {
+        let state = self.state.read().unwrap();
+        content_id
+            .chunk_ids()
+            .iter()
+            .all(|id| state.chunks.contains_key(&id.0))
+    }
+
+    /// Copy each object in the current instance of this repository to the current instance of
+    /// `dest`.
+    ///
+    /// Only the chunks which don't already exist in `dest` are read from this repository and
+    /// written to `dest`, so data is deduplicated across both repositories. This is much more
+    /// efficient than reading each object and writing it to `dest`, and it makes it possible to
+    /// cheaply keep a replica of a repository in another data store.
+    ///
+    /// If an object with the same key already exists in `dest`, it is replaced. Objects in `dest`
+    /// which don't exist in this repository are left untouched. The two repositories may use
+    /// different configurations; chunks are re-encoded using the configuration of `dest`, but
+    /// objects are not re-chunked.
+    ///
+    /// Changes to `dest` are not persisted until they are committed. If this returns `Err`, some
+    /// objects may have already been copied to `dest`.
+    ///
+    /// # Errors
+    /// - `Error::QuotaExceeded`: Copying an object would exceed the quota for the instance of
+    /// `dest`.
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    pub fn push_to(&self, dest: &mut KeyRepo<K>) -> crate::Result<()> {
+        let source_state = self.state.read().unwrap();
+        let mut source_store_state = StoreState::new();
+        let mut reader = StoreReader::new(&source_state, &mut source_store_state);
+
+        for (key, handle) in self.objects.iter() {
+            let source_handle = handle.read().unwrap();
+
+            if let Some(dest_handle) = dest.objects.get(key) {
+                let dest_handle = dest_handle.read().unwrap();
+                if dest_handle.extents == source_handle.extents
+                    && dest_handle.attributes == source_handle.attributes
+                {
+                    // This object is already up to date.
+                    continue;
+                }
+            }
+
+            dest.transfer_object(key, &source_handle, &mut reader)?;
+        }
+
+        Ok(())
+    }
+
+    /// Copy each object in the current instance of `source` to the current instance of this
+    /// repository.
+    ///
+    /// This is equivalent to calling [`push_to`] on `source`.
+    ///
+    /// # Errors
+    /// - `Error::QuotaExceeded`: Copying an object would exceed the quota for this instance.
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    ///
+    /// [`push_to`]: crate::repo::key::KeyRepo::push_to
+    pub fn pull_from(&mut self, source: &KeyRepo<K>) -> crate::Result<()> {
+        source.push_to(self)
+    }
+
+    /// Copy the object with the given `key` from the current instance of `source` to the current
+    /// instance of this repository.
+    ///
+    /// This returns `true` if the object was copied or `false` if it doesn't exist in `source`. If
+    /// an object with the same key already exists in this repository, it is replaced. Like
+    /// [`pull_from`], this only copies the chunks which don't already exist in this repository.
+    ///
+    /// This is useful for resolving conflicts reported by [`merge`] in favor of `source`.
+    ///
+    /// # Errors
+    /// - `Error::QuotaExceeded`: Copying the object would exceed the quota for this instance.
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    ///
+    /// [`pull_from`]: crate::repo::key::KeyRepo::pull_from
+    /// [`merge`]: crate::repo::key::KeyRepo::merge
+    pub fn pull_object<Q>(&mut self, source: &KeyRepo<K>, key: &Q) -> crate::Result<bool>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let (key, handle) = match source.objects.get_key_value(key) {
+            Some(entry) => entry,
+            None => return Ok(false),
+        };
+
+        let source_state = source.state.read().unwrap();
+        let mut source_store_state = StoreState::new();
+        let mut reader = StoreReader::new(&source_state, &mut source_store_state);
+
+        self.transfer_object(key, &handle.read().unwrap(), &mut reader)?;
+
+        Ok(true)
+    }
+
+    /// Merge the changes made to the current instance of `other` into the current instance of
+    /// this repository.
+    ///
+    /// This is a three-way merge. `ancestor` is a repository which both this repository and
+    /// `other` were derived from, such as a copy of the repository from before it was modified in
+    /// two places. Each object is compared between the three repositories:
+    ///
+    /// - If an object was only changed in `other`, the change is applied to this repository. This
+    /// includes objects which were added to or removed from `other`.
+    /// - If an object was only changed in this repository, or it was changed the same way in both
+    /// repositories, it is left as it is.
+    /// - If an object was changed differently in both repositories, it is a conflict. The object
+    /// is left as it is in this repository, and the conflict is included in the returned
+    /// [`MergeReport`] so it can be resolved by the caller, for example with [`pull_object`].
+    ///
+    /// Objects are compared by their chunks and attributes, so all three repositories should have
+    /// the same [`Chunking`] configuration; otherwise, identical objects may be reported as
+    /// conflicts. Objects are copied from `other` like they are with [`pull_from`], so the
+    /// repositories may otherwise have different configurations.
+    ///
+    /// Changes to this repository are not persisted until they are committed. If this returns
+    /// `Err`, some changes may have already been merged.
+    ///
+    /// This merges the objects in the repositories without knowing what they represent, so it
+    /// must not be used on the `KeyRepo` behind another repository type, like the one returned by
+    /// [`OpenRepo::into_repo`]. Repository types like [`ValueRepo`] and [`FileRepo`] keep an index
+    /// of their objects which this wouldn't merge, which would leave the repository inconsistent.
+    /// Use [`ValueRepo::merge`] to merge value repositories.
+    ///
+    /// # Errors
+    /// - `Error::QuotaExceeded`: Merging an object would exceed the quota for this instance.
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    ///
+    /// [`MergeReport`]: crate::repo::MergeReport
+    /// [`OpenRepo::into_repo`]: crate::repo::OpenRepo::into_repo
+    /// [`ValueRepo`]: crate::repo::value::ValueRepo
+    /// [`FileRepo`]: crate::repo::file::FileRepo
+    /// [`ValueRepo::merge`]: crate::repo::value::ValueRepo::merge
+    /// [`pull_object`]: crate::repo::key::KeyRepo::pull_object
+    /// [`Chunking`]: crate::repo::Chunking
+    /// [`pull_from`]: crate::repo::key::KeyRepo::pull_from
+    pub fn merge(
+        &mut self,
+        ancestor: &KeyRepo<K>,
+        other: &KeyRepo<K>,
+    ) -> crate::Result<MergeReport<K>> {
+        fn contents<K: Key>(
+            repo: &KeyRepo<K>,
+            key: &K,
+        ) -> Option<(Vec<Extent>, BTreeMap<String, Vec<u8>>)> {
+            repo.objects.get(key).map(|handle| {
+                let handle = handle.read().unwrap();
+                (handle.extents.clone(), handle.attributes.clone())
+            })
+        }
+
+        let keys = self
+            .objects
+            .keys()
+            .chain(ancestor.objects.keys())
+            .chain(other.objects.keys())
+            .cloned()
+            .collect::<HashSet<_>>();
+
+        let other_state = other.state.read().unwrap();
+        let mut other_store_state = StoreState::new();
+        let mut reader = StoreReader::new(&other_state, &mut other_store_state);
+        let mut report = MergeReport::default();
+
+        for key in keys {
+            let base = contents(ancestor, &key);
+            let ours = contents(self, &key);
+            let theirs = contents(other, &key);
+
+            if ours == theirs || theirs == base {
+                continue;
+            }
+
+            if ours != base {
+                let conflict =
+                    MergeConflict::from_presence(base.is_some(), ours.is_some(), theirs.is_some());
+                report.conflicts.insert(key, conflict);
+                continue;
+            }
+
+            match other.objects.get(&key) {
+                Some(handle) => {
+                    self.transfer_object(&key, &handle.read().unwrap(), &mut reader)?;
+                    report.updated.insert(key);
+                }
+                None => {
+                    self.remove(&key);
+                    report.removed.insert(key);
+                }
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Replace the object with the given `key` with a copy of `source_handle`, reading any chunks
+    /// which don't exist in this repository from `reader`.
+    ///
+    /// If this returns `Err`, this repository is unchanged.
+    fn transfer_object(
+        &mut self,
+        key: &K,
+        source_handle: &ObjectHandle,
+        reader: &mut impl ReadChunk,
+    ) -> crate::Result<()> {
+        let dest_size = self
+            .objects
+            .get(key)
+            .map_or(0, |handle| handle.read().unwrap().size());
+        self.check_quota(dest_size, source_handle.size())?;
+
+        let dest_handle = ObjectHandle {
+            id: self.handle_table.next(),
+            extents: source_handle.extents.clone(),
+            attributes: source_handle.attributes.clone(),
+        };
+        if let Err(error) = self.transfer_chunks(&dest_handle, reader) {
+            self.handle_table.recycle(dest_handle.id);
+            return Err(error);
+        }
+
+        self.remove(key);
+        self.state.write().unwrap().instance_size += dest_handle.size();
+        self.objects
+            .insert(key.clone(), Arc::new(RwLock::new(dest_handle)));
+
+        Ok(())
+    }
+
+    /// Add a reference from `handle` to each of its chunks, reading any chunks which don't exist
+    /// in this repository from `reader` and writing them to the data store.
+    ///
+    /// If this returns `Err`, no references to `handle` are added.
+    fn transfer_chunks(
+        &mut self,
+        handle: &ObjectHandle,
+        reader: &mut impl ReadChunk,
+    ) -> crate::Result<()> {
+        let mut state = self.state.write().unwrap();
+        let mut store_state = StoreState::new();
+        let mut transferred_chunks = Vec::new();
+
+        for chunk in handle.chunks() {
+            if let Some(chunk_info) = state.chunks.get_mut(&chunk) {
+                chunk_info.references.insert(handle.id);
+                transferred_chunks.push((chunk, handle.id));
+                continue;
+            }
+
+            let result = reader.read_chunk(chunk).and_then(|data| {
+                StoreWriter::new(&mut state, &mut store_state).write_chunk(&data, handle.id)
+            });
+            match result {
+                Ok(new_chunk) => {
+                    debug_assert_eq!(new_chunk, chunk);
+                    transferred_chunks.push((chunk, handle.id));
+                }
+                Err(error) => {
+                    remove_references(&mut state, transferred_chunks);
+                    return Err(error);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Convert this repository into a [`SharedKeyRepo`] which can be used from multiple threads.
+    ///
+    /// [`SharedKeyRepo`]: crate::repo::key::SharedKeyRepo
+    pub fn into_shared(self) -> SharedKeyRepo<K> {
+        SharedKeyRepo::new(self)
+    }
+
+    /// Write the map of objects for the current instance to the data store.
+    #[cfg_attr(
+        feature = "observability",
+        tracing::instrument(level = "debug", skip_all)
+    )]
+    pub(super) fn write_object_map(&mut self) -> crate::Result<()> {
+        let mut state = self.state.write().unwrap();
+
+        let handle = &mut self
+            .instances
+            .get_mut(&self.instance_id)
+            .expect("There is no instance with the given ID.")
+            .objects;
+
+        let mut object_state = ObjectState::new(state.metadata.config.chunking.to_chunker());
+        let mut writer = ObjectWriter::new(&mut state, &mut object_state, handle);
+        writer.serialize::<MessagePack, _>(&self.objects)
+    }
+
+    /// Read the object map for the current instance from the data store and return it.
+    ///
+    /// This does not write the object map for the old instance first. To do that, use
+    /// `write_object_map`.
+    ///
+    /// This does not commit or roll back changes.
+    pub(super) fn read_object_map(&self) -> crate::Result<HashMap<K, Arc<RwLock<ObjectHandle>>>> {
+        let state = self.state.read().unwrap();
+        match self.instances.get(&self.instance_id) {
+            Some(instance_info) => {
+                let mut object_state =
+                    ObjectState::new(state.metadata.config.chunking.to_chunker());
+                let mut reader =
+                    ObjectReader::new(&state, &mut object_state, &instance_info.objects);
+                reader.deserialize::<MessagePack, _>()
+            }
+            None => {
+                // If the current instance is not in the instance map, then this repository has not
+                // been committed since it was created and an object map has not been written for
+                // this instance.
+                Ok(HashMap::new())
+            }
+        }
+    }
+
+    /// Set the current instance of the repository.
+    ///
+    /// This does not write the object map for the current instance before switching to the new
+    /// instance.
+    pub(super) fn change_instance<R: OpenRepo>(
+        mut self,
+        instance_id: InstanceId,
+    ) -> crate::Result<R> {
+        let is_new_instance = !self.instances.contains_key(&instance_id);
+
+        let new_objects = if is_new_instance {
+            // Create the object handle for the object which will store the object map for the new
+            // instance.
+            let mut handle = ObjectHandle {
+                id: self.handle_table.next(),
+                extents: Vec::new(),
+                attributes: BTreeMap::new(),
+            };
+
+            // Because this is a new instance, we return an empty object map.
+            let objects = HashMap::<R::Key, Arc<RwLock<ObjectHandle>>>::new();
+
+            // Write an empty object map to the object.
             let mut state = self.state.write().unwrap();
             let mut object_state = ObjectState::new(state.metadata.config.chunking.to_chunker());
             let mut writer = ObjectWriter::new(&mut state, &mut object_state, &mut handle);
-            writer.serialize(&objects)?;
+            writer.serialize::<MessagePack, _>(&objects)?;
 
             // Insert the instance info into the instance map.
             let instance_info = InstanceInfo {
                 version_id: R::VERSION_ID,
                 objects: handle,
+                quota: None,
+                name: None,
+                created: Some(SystemTime::now()),
+                expirations: HashMap::new(),
             };
             self.instances.insert(instance_id, instance_info);
 
@@ -293,17 +1220,21 @@ impl<K: Key> KeyRepo<K> {
             let state = self.state.read().unwrap();
             let mut object_state = ObjectState::new(state.metadata.config.chunking.to_chunker());
             let mut reader = ObjectReader::new(&state, &mut object_state, &instance_info.objects);
-            reader.deserialize()?
+            reader.deserialize::<MessagePack, _>()?
         };
 
         let repo = KeyRepo {
             state: self.state,
             instance_id,
             objects: new_objects,
+            handle_index: RwLock::new(HashMap::new()),
             instances: self.instances,
             handle_table: self.handle_table,
+            checkpoints: self.checkpoints,
+            commit_log: self.commit_log,
             transaction_id: self.transaction_id,
         };
+        repo.update_instance_usage();
 
         if is_new_instance {
             R::create_repo(repo)
@@ -313,7 +1244,17 @@ impl<K: Key> KeyRepo<K> {
     }
 
     /// Atomically encode and write the given serialized `header` to the data store.
-    fn write_serialized_header(&mut self, serialized_header: &[u8]) -> crate::Result<()> {
+    ///
+    /// The given `fingerprint` of the chunks in the header is stored in the repository metadata.
+    #[cfg_attr(
+        feature = "observability",
+        tracing::instrument(level = "debug", skip_all)
+    )]
+    fn write_serialized_header(
+        &mut self,
+        serialized_header: &[u8],
+        fingerprint: Option<Fingerprint>,
+    ) -> crate::Result<()> {
         let mut state = self.state.write().unwrap();
         // Encode the serialized header.
         let encoded_header = state.encode_data(serialized_header)?;
@@ -328,15 +1269,24 @@ impl<K: Key> KeyRepo<K> {
             .map_err(crate::Error::Store)?;
         state.metadata.header_id = header_id;
 
+        // Any flushed changes are superseded by the new header.
+        state.metadata.flushed_header_id = None;
+
+        // Record the fingerprint of the data in the new header.
+        state.metadata.fingerprint = fingerprint;
+
         // Atomically write the new repository metadata containing the new header ID.
-        let serialized_metadata =
-            to_vec(&state.metadata).expect("Could not serialize repository metadata.");
+        let serialized_metadata = state.metadata.to_super_block(&state.master_key);
         state
             .store
             .lock()
             .unwrap()
             .write_block(BlockKey::Super, &serialized_metadata)
             .map_err(crate::Error::Store)?;
+
+        state.apparent_header_size = serialized_header.len() as u64;
+        state.header_size = encoded_header.len() as u64;
+
         Ok(())
     }
 
@@ -348,6 +1298,8 @@ impl<K: Key> KeyRepo<K> {
             packs: state.packs.clone(),
             instances: self.instances.clone(),
             handle_table: self.handle_table.clone(),
+            checkpoints: self.checkpoints.clone(),
+            commit_log: self.commit_log.clone(),
         }
     }
 
@@ -364,6 +1316,8 @@ impl<K: Key> KeyRepo<K> {
             packs: std::mem::take(&mut state.packs),
             instances: std::mem::take(&mut self.instances),
             handle_table: std::mem::take(&mut self.handle_table),
+            checkpoints: std::mem::take(&mut self.checkpoints),
+            commit_log: std::mem::take(&mut self.commit_log),
         };
 
         // Serialize the header so we can write it to the data store.
@@ -376,11 +1330,15 @@ impl<K: Key> KeyRepo<K> {
             packs,
             instances,
             handle_table,
+            checkpoints,
+            commit_log,
         } = header;
         state.chunks = chunks;
         state.packs = packs;
         self.instances = instances;
         self.handle_table = handle_table;
+        self.checkpoints = checkpoints;
+        self.commit_log = commit_log;
 
         serialized_header
     }
@@ -389,216 +1347,1574 @@ impl<K: Key> KeyRepo<K> {
     fn replace_header(&mut self, header: Header) -> Header {
         let mut state = self.state.write().unwrap();
         let old_chunks = mem::replace(&mut state.chunks, header.chunks);
+        state.chunk_tree.invalidate_all();
         let old_packs = mem::replace(&mut state.packs, header.packs);
         let old_instances = mem::replace(&mut self.instances, header.instances);
         let old_handle_table = mem::replace(&mut self.handle_table, header.handle_table);
+        let old_checkpoints = mem::replace(&mut self.checkpoints, header.checkpoints);
+        let old_commit_log = mem::replace(&mut self.commit_log, header.commit_log);
         Header {
             chunks: old_chunks,
             packs: old_packs,
             instances: old_instances,
             handle_table: old_handle_table,
+            checkpoints: old_checkpoints,
+            commit_log: old_commit_log,
+        }
+    }
+
+    /// Atomically restore the repository's state from the given `header`.
+    ///
+    /// This restores the state of the repository using the data in the given `header` and then
+    /// reads the object map for the current instance from the data store.
+    ///
+    /// If this returns `Ok`, the repository's state has been restored. If this returns `Err`, the
+    /// repository is unchanged.
+    fn restore_header(&mut self, header: Header) -> crate::Result<()> {
+        // We need to restore the repository state before we can read the object map.
+        let old_header = self.replace_header(header);
+
+        // Restore the object map from the old header.
+        match self.read_object_map() {
+            Ok(objects) => {
+                self.objects = objects;
+                self.update_instance_usage();
+                Ok(())
+            }
+            Err(error) => {
+                self.replace_header(old_header);
+                Err(error)
+            }
+        }
+    }
+
+    /// Verify the integrity of all the data in the current instance of the repository.
+    ///
+    /// This returns the set of keys of objects in the current instance which are corrupt.
+    ///
+    /// If you just need to verify the integrity of one object, [`Object::verify`] is faster. If you
+    /// need to verify the integrity of all the data in the repository, however, this can be more
+    /// efficient.
+    ///
+    /// If [`RepoConfig::verify_threads`] is greater than one, chunks are read, decoded, and hashed
+    /// concurrently. Chunks which are stored in the same pack are verified by the same thread so
+    /// that each pack is only read once. If the data store supports [`DataStore::try_clone`], each
+    /// thread reads from its own handle to the data store. Otherwise, the threads take turns
+    /// reading from the data store.
+    ///
+    /// # Errors
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    ///
+    /// [`Object::verify`]: crate::repo::Object::verify
+    /// [`RepoConfig::verify_threads`]: crate::repo::RepoConfig::verify_threads
+    /// [`DataStore::try_clone`]: crate::store::DataStore::try_clone
+    #[cfg_attr(
+        feature = "observability",
+        tracing::instrument(level = "debug", skip_all)
+    )]
+    pub fn verify(&self) -> crate::Result<HashSet<&K>> {
+        let state = self.state.read().unwrap();
+        let repo_state: &RepoState = &state;
+
+        let threads = state.metadata.config.verify_threads;
+
+        // Get the set of hashes of chunks which are corrupt.
+        let corrupt_chunks = if threads <= 1 || state.chunks.len() <= 1 {
+            let expected_chunks = state.chunks.keys().copied().collect::<Vec<_>>();
+            find_corrupt_chunks(repo_state, &repo_state.store, &expected_chunks)?
+        } else {
+            thread::scope(|scope| {
+                let workers = group_chunks(repo_state, threads)
+                    .into_iter()
+                    .map(|group| {
+                        scope.spawn(move || {
+                            // Use a separate handle to the data store if possible so that this
+                            // thread doesn't have to wait for the others to read from it.
+                            let store = repo_state.store.lock().unwrap().try_clone();
+                            match store {
+                                Some(store) => {
+                                    find_corrupt_chunks(repo_state, &Mutex::new(store), &group)
+                                }
+                                None => find_corrupt_chunks(repo_state, &repo_state.store, &group),
+                            }
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                workers
+                    .into_iter()
+                    .map(|worker| worker.join().expect("A verification thread panicked."))
+                    .collect::<crate::Result<Vec<_>>>()
+            })?
+            .into_iter()
+            .flatten()
+            .collect()
+        };
+
+        // If there are no corrupt chunks, there are no corrupt objects.
+        if corrupt_chunks.is_empty() {
+            return Ok(HashSet::new());
         }
+
+        let mut corrupt_keys = HashSet::new();
+        for (key, handle) in &self.objects {
+            for chunk in handle.read().unwrap().chunks() {
+                // If any one of the object's chunks is corrupt, the object is corrupt.
+                if corrupt_chunks.contains(&chunk.hash) {
+                    corrupt_keys.insert(key);
+                    break;
+                }
+            }
+        }
+
+        Ok(corrupt_keys)
+    }
+
+    /// Check the structure of the repository for inconsistencies.
+    ///
+    /// Unlike [`verify`], which checks the integrity of the data in the repository, this checks
+    /// that the repository's metadata is consistent with itself and with the data store. This
+    /// finds chunks which aren't used by any object, references between chunks and objects which
+    /// are missing or out of date, objects whose IDs aren't allocated, and chunks whose data is
+    /// missing from the data store. This checks the objects in every instance, so it reads the
+    /// object map of each instance other than the current one from the data store.
+    ///
+    /// This doesn't modify the repository. To fix the inconsistencies which can be fixed, use
+    /// [`repair`].
+    ///
+    /// Data which has been written to an object but not yet committed with [`Object::commit`]
+    /// may be reported as missing, so this should be called when no objects are being written to.
+    ///
+    /// # Errors
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    ///
+    /// [`verify`]: crate::repo::key::KeyRepo::verify
+    /// [`repair`]: crate::repo::key::KeyRepo::repair
+    /// [`Object::commit`]: crate::repo::Object::commit
+    pub fn check(&self) -> crate::Result<CheckReport<K>> {
+        Ok(self.find_inconsistencies()?.into_report(false))
+    }
+
+    /// Check the structure of the repository and repair the inconsistencies which can be fixed.
+    ///
+    /// This removes references to objects which no longer exist, adds missing references, removes
+    /// chunks which aren't used by any object, and allocates the IDs of existing objects. This
+    /// returns a report of the inconsistencies which were found before they were repaired.
+    ///
+    /// Inconsistencies which mean that data has been lost can't be repaired. The damaged objects
+    /// are left in the repository so that you can decide what to do with them; in the current
+    /// instance, they can be removed with [`remove`].
+    ///
+    /// This does not commit changes to the repository. The space used by orphaned chunks isn't
+    /// reclaimed in the backing data store until changes are committed and [`Commit::clean`] is
+    /// called.
+    ///
+    /// # Errors
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    ///
+    /// [`remove`]: crate::repo::key::KeyRepo::remove
+    /// [`Commit::clean`]: crate::repo::Commit::clean
+    pub fn repair(&mut self) -> crate::Result<CheckReport<K>> {
+        let inconsistencies = self.find_inconsistencies()?;
+
+        let mut state = self.state.write().unwrap();
+        for (chunk, handle_id) in &inconsistencies.stale_references {
+            if let Some(chunk_info) = state.chunks.get_mut(chunk) {
+                chunk_info.references.remove(handle_id);
+            }
+        }
+        for (chunk, handle_id) in &inconsistencies.missing_references {
+            if let Some(chunk_info) = state.chunks.get_mut(chunk) {
+                chunk_info.references.insert(*handle_id);
+            }
+        }
+        for chunk in &inconsistencies.orphaned_chunks {
+            state.chunks.remove(chunk);
+            state.chunk_tree.invalidate(chunk);
+        }
+        drop(state);
+
+        for handle_id in &inconsistencies.unallocated_handles {
+            self.handle_table.reserve(*handle_id);
+        }
+
+        Ok(inconsistencies.into_report(true))
+    }
+
+    /// Find the structural inconsistencies in the repository.
+    fn find_inconsistencies(&self) -> crate::Result<Inconsistencies<K>> {
+        let state = self.state.read().unwrap();
+        let mut inconsistencies = Inconsistencies::default();
+
+        // Find the chunks whose data is missing from the data store.
+        let stored_blocks = state
+            .store
+            .lock()
+            .unwrap()
+            .list_blocks(BlockType::Data)
+            .map_err(crate::Error::Store)?
+            .into_iter()
+            .collect::<HashSet<_>>();
+        let missing_data = state
+            .chunks
+            .iter()
+            .filter(|(_, chunk_info)| match &state.metadata.config.packing {
+                Packing::None => !stored_blocks.contains(&chunk_info.block_id),
+                Packing::Fixed(_) => match state.packs.get(&chunk_info.block_id) {
+                    Some(index_list) => index_list
+                        .iter()
+                        .any(|pack_index| !stored_blocks.contains(&pack_index.id)),
+                    None => true,
+                },
+            })
+            .map(|(chunk, _)| *chunk)
+            .collect::<HashSet<_>>();
+
+        // The IDs of the handles which exist in any instance and the chunks they use.
+        let mut live_handles = HashSet::new();
+        let mut used_chunks = HashSet::new();
+
+        for (key, handle) in &self.objects {
+            let handle = handle.read().unwrap();
+            live_handles.insert(handle.id);
+            if check_handle(
+                &state,
+                &handle,
+                &missing_data,
+                &mut used_chunks,
+                &mut inconsistencies,
+            ) {
+                inconsistencies.damaged_objects.insert(key.clone());
+            }
+        }
+
+        for (instance_id, instance_info) in &self.instances {
+            live_handles.insert(instance_info.objects.id);
+            let is_map_damaged = check_handle(
+                &state,
+                &instance_info.objects,
+                &missing_data,
+                &mut used_chunks,
+                &mut inconsistencies,
+            );
+
+            // The object map for the current instance is rewritten when changes are committed, so
+            // we check the objects in memory instead.
+            if *instance_id == self.instance_id {
+                continue;
+            }
+
+            if is_map_damaged {
+                inconsistencies.damaged_instances.insert(*instance_id);
+                continue;
+            }
+
+            let mut object_state = ObjectState::new(state.metadata.config.chunking.to_chunker());
+            let mut reader = ObjectReader::new(&state, &mut object_state, &instance_info.objects);
+            let handles = match reader.deserialize::<MessagePack, _>() {
+                Ok(ObjectHandles(handles)) => handles,
+                Err(crate::Error::Deserialize | crate::Error::InvalidData) => {
+                    inconsistencies.damaged_instances.insert(*instance_id);
+                    continue;
+                }
+                Err(error) => return Err(error),
+            };
+
+            for handle in &handles {
+                live_handles.insert(handle.id);
+                if check_handle(
+                    &state,
+                    handle,
+                    &missing_data,
+                    &mut used_chunks,
+                    &mut inconsistencies,
+                ) {
+                    inconsistencies.damaged_instances.insert(*instance_id);
+                }
+            }
+        }
+
+        inconsistencies.missing_blocks = used_chunks.intersection(&missing_data).count();
+
+        inconsistencies.unallocated_handles = live_handles
+            .iter()
+            .filter(|handle_id| !self.handle_table.contains(**handle_id))
+            .copied()
+            .collect();
+
+        for (chunk, chunk_info) in &state.chunks {
+            let mut is_used = used_chunks.contains(chunk);
+            for handle_id in &chunk_info.references {
+                if live_handles.contains(handle_id) {
+                    is_used = true;
+                } else {
+                    inconsistencies.stale_references.push((*chunk, *handle_id));
+                }
+            }
+            if !is_used {
+                inconsistencies.orphaned_chunks.push(*chunk);
+            }
+        }
+
+        Ok(inconsistencies)
+    }
+
+    /// Delete all data in the current instance of the repository.
+    ///
+    /// This does not delete data from other instances of the repository.
+    ///
+    /// This does not commit changes to the repository.
+    ///
+    /// No data is reclaimed in the backing data store until changes are committed and
+    /// [`Commit::clean`] is called.
+    ///
+    /// [`Commit::clean`]: crate::repo::Commit::clean
+    pub fn clear_instance(&mut self) {
+        let handles = self
+            .objects
+            .drain()
+            .map(|(_, handle)| handle)
+            .collect::<Vec<_>>();
+        for handle in handles {
+            self.remove_handle(&handle.read().unwrap());
+        }
+    }
+
+    /// Remove the instance of the repository with the given `id`.
+    ///
+    /// This removes all the objects in the instance along with its name and quota. This returns
+    /// `true` if the instance was removed or `false` if it didn't exist. The current instance can't
+    /// be removed; use [`clear_instance`] to delete its data instead.
+    ///
+    /// This does not commit changes to the repository.
+    ///
+    /// No data is reclaimed in the backing data store until changes are committed and
+    /// [`Commit::clean`] is called.
+    ///
+    /// # Errors
+    /// - `Error::Locked`: The instance with the given `id` is the current instance.
+    /// - `Error::Deserialize`: Could not deserialize the object map for the instance.
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    ///
+    /// [`clear_instance`]: crate::repo::key::KeyRepo::clear_instance
+    /// [`Commit::clean`]: crate::repo::Commit::clean
+    pub fn remove_instance(&mut self, id: InstanceId) -> crate::Result<bool> {
+        if id == self.instance_id {
+            return Err(crate::Error::Locked);
+        }
+
+        let instance_info = match self.instances.get(&id) {
+            Some(instance_info) => instance_info,
+            None => return Ok(false),
+        };
+
+        // Read the object map for the instance before modifying anything so that the instance is
+        // left intact if it can't be read.
+        let ObjectHandles(handles) = {
+            let state = self.state.read().unwrap();
+            let mut object_state = ObjectState::new(state.metadata.config.chunking.to_chunker());
+            let mut reader = ObjectReader::new(&state, &mut object_state, &instance_info.objects);
+            reader.deserialize::<MessagePack, _>()?
+        };
+
+        // Removing handles decreases the size of the current instance, so it must be restored.
+        let instance_size = self.state.read().unwrap().instance_size;
+
+        let instance_info = self.instances.remove(&id).unwrap();
+        for handle in handles {
+            self.remove_handle(&handle);
+        }
+        self.remove_handle(&instance_info.objects);
+
+        self.state.write().unwrap().instance_size = instance_size;
+
+        Ok(true)
+    }
+
+    /// Change the password for this repository.
+    ///
+    /// This replaces the existing password with `new_password`. This also accepts the
+    /// `memory_limit` and the `operations_limit`, which affect the amount of memory and the number
+    /// of computations respectively which will be used by the key derivation function. If the
+    /// repository was created with an explicit [`RepoConfig::key_derivation`], that key derivation
+    /// function is used instead and these limits are ignored.
+    ///
+    /// If the master key is protected by a [`KeyProvider`], this replaces the key provider with the
+    /// password. The key provider is no longer needed to open the repository.
+    ///
+    /// Changing the password does not require re-encrypting any data. The change does not take
+    /// effect until [`Commit::commit`] is called.
+    ///
+    /// If encryption is disabled, this method does nothing.
+    ///
+    /// [`Commit::commit`]: crate::repo::Commit::commit
+    /// [`RepoConfig::key_derivation`]: crate::repo::RepoConfig::key_derivation
+    /// [`KeyProvider`]: crate::repo::KeyProvider
+    pub fn change_password(
+        &mut self,
+        new_password: &[u8],
+        memory_limit: ResourceLimit,
+        operations_limit: ResourceLimit,
+    ) {
+        let mut state = self.state.write().unwrap();
+
+        if state.metadata.config.encryption == Encryption::None {
+            return;
+        }
+
+        state.metadata.config.memory_limit = memory_limit;
+        state.metadata.config.operations_limit = operations_limit;
+
+        let salt = KeySalt::generate();
+        let user_key = state
+            .metadata
+            .config
+            .derive_key(new_password, &salt)
+            .expect("The key derivation parameters were checked when the repository was opened.");
+
+        let encrypted_master_key = state
+            .metadata
+            .config
+            .encryption
+            .encrypt(state.master_key.expose_secret(), &user_key);
+
+        state.metadata.salt = salt;
+        state.metadata.master_key = encrypted_master_key;
+        state.metadata.external_key = false;
+    }
+
+    /// Return this repository's current instance ID.
+    pub fn instance(&self) -> InstanceId {
+        self.instance_id
+    }
+
+    /// Return information about each instance of this repository.
+    ///
+    /// Instances are returned in the order they were created. This includes instances which have
+    /// been created but not committed.
+    pub fn list_instances(&self) -> Vec<InstanceSummary> {
+        let mut instances = self
+            .instances
+            .iter()
+            .map(|(id, info)| InstanceSummary {
+                id: *id,
+                version_id: info.version_id,
+                name: info.name.clone(),
+                created: info.created,
+            })
+            .collect::<Vec<_>>();
+        instances.sort_by_key(|instance| (instance.created, *instance.id.as_ref()));
+        instances
+    }
+
+    /// Set the human-readable name of the instance with the given `id` to `name`.
+    ///
+    /// Names are unique within a repository, and an instance with a name can be opened by name
+    /// with [`OpenOptions::instance_name`]. If `name` is `None`, the name of the instance is
+    /// removed.
+    ///
+    /// This does not commit changes to the repository.
+    ///
+    /// # Errors
+    /// - `Error::NotFound`: There is no instance with the given `id`.
+    /// - `Error::AlreadyExists`: Another instance already has the given `name`.
+    ///
+    /// [`OpenOptions::instance_name`]: crate::repo::OpenOptions::instance_name
+    pub fn name_instance(&mut self, id: InstanceId, name: Option<&str>) -> crate::Result<()> {
+        if !self.instances.contains_key(&id) {
+            return Err(crate::Error::NotFound);
+        }
+        if let Some(name) = name {
+            if matches!(self.find_instance(name), Some(other_id) if other_id != id) {
+                return Err(crate::Error::AlreadyExists);
+            }
+        }
+        self.instances.get_mut(&id).unwrap().name = name.map(String::from);
+        Ok(())
+    }
+
+    /// Return the ID of the instance with the given `name`.
+    ///
+    /// This returns `None` if there is no instance with the given `name`.
+    pub fn find_instance(&self, name: &str) -> Option<InstanceId> {
+        self.instances
+            .iter()
+            .find(|(_, info)| info.name.as_deref() == Some(name))
+            .map(|(id, _)| *id)
+    }
+
+    /// Compute statistics about the repository.
+    ///
+    /// The returned `RepoStats` represents the contents of the repository at the time this method
+    /// was called. It is not updated when the repository is modified.
+    pub fn stats(&self) -> RepoStats {
+        let mut apparent_size = 0u64;
+        let mut actual_size = 0u64;
+        let mut repo_size = 0u64;
+
+        // The set of object handle IDs of objects in the current instance.
+        let mut current_instance_handles = HashSet::new();
+
+        // The set of object handle IDs of objects which store metadata and shouldn't count towards
+        // the `repo_size`.
+        let metadata_handles = self
+            .instances
+            .values()
+            .map(|info| info.objects.id)
+            .collect::<HashSet<_>>();
+
+        for handle_lock in self.objects.values() {
+            let handle = handle_lock.read().unwrap();
+            apparent_size += handle.size();
+            current_instance_handles.insert(handle.id);
+        }
+
+        let state = self.state.read().unwrap();
+        for (chunk, info) in state.chunks.iter() {
+            // Only count object inserted by the user in the `repo_size`.
+            if !info.references.is_subset(&metadata_handles) {
+                repo_size += chunk.size as u64;
+            }
+
+            if !info.references.is_disjoint(&current_instance_handles) {
+                actual_size += chunk.size as u64;
+            }
+        }
+
+        RepoStats {
+            apparent_size,
+            actual_size,
+            repo_size,
+            apparent_header_size: state.apparent_header_size,
+            header_size: state.header_size,
+        }
+    }
+
+    /// Compute statistics about the objects in each instance of the repository.
+    ///
+    /// This returns a map of the IDs of all the instances in the repository to statistics about the
+    /// objects in them. This can be used to find out how much space each instance is using. Data
+    /// which is deduplicated between instances is counted in the [`UsageStats::actual_size`] of
+    /// each instance which references it.
+    ///
+    /// This reads the object map of each instance other than the current one from the data store.
+    ///
+    /// # Errors
+    /// - `Error::Deserialize`: Could not deserialize the object map for an instance.
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    ///
+    /// [`UsageStats::actual_size`]: crate::repo::UsageStats::actual_size
+    pub fn instance_stats(&self) -> crate::Result<HashMap<InstanceId, UsageStats>> {
+        let mut instance_stats = HashMap::new();
+        instance_stats.insert(self.instance_id, self.stats_for_keys(|_| true));
+
+        let state = self.state.read().unwrap();
+        for (instance_id, instance_info) in &self.instances {
+            if *instance_id == self.instance_id {
+                continue;
+            }
+            let mut object_state = ObjectState::new(state.metadata.config.chunking.to_chunker());
+            let mut reader = ObjectReader::new(&state, &mut object_state, &instance_info.objects);
+            let ObjectHandles(handles) = reader.deserialize::<MessagePack, _>()?;
+            instance_stats.insert(*instance_id, UsageStats::from_handles(&handles));
+        }
+
+        Ok(instance_stats)
+    }
+
+    /// Compute statistics about the objects in the current instance whose keys match `predicate`.
+    ///
+    /// This can be used to find out how much space is used by a group of objects, like all the
+    /// objects whose keys start with a given prefix. Data which is deduplicated between objects
+    /// that match and objects that don't is counted in the [`UsageStats::actual_size`].
+    ///
+    /// [`UsageStats::actual_size`]: crate::repo::UsageStats::actual_size
+    pub fn stats_for_keys(&self, mut predicate: impl FnMut(&K) -> bool) -> UsageStats {
+        let handles = self
+            .objects
+            .iter()
+            .filter(|(key, _)| predicate(key))
+            .map(|(_, handle)| handle.read().unwrap())
+            .collect::<Vec<_>>();
+        UsageStats::from_handles(handles.iter().map(|handle| &**handle))
+    }
+
+    /// Return information about the repository.
+    pub fn info(&self) -> RepoInfo {
+        self.state.read().unwrap().metadata.to_info()
+    }
+
+    /// Return the number of bytes of free space available in the data store.
+    ///
+    /// This returns `None` if the data store doesn't report how much space is available.
+    ///
+    /// # Errors
+    /// - `Error::Store`: An error occurred with the data store.
+    pub(crate) fn available_space(&self) -> crate::Result<Option<u64>> {
+        let state = self.state.read().unwrap();
+        let mut store = state.store.lock().unwrap();
+        store.available_space().map_err(crate::Error::Store)
+    }
+
+    /// Return the quota for the current instance in bytes.
+    ///
+    /// This is the quota set with [`set_quota`] if there is one, or [`RepoConfig::quota`]
+    /// otherwise. This returns `None` if there is no limit.
+    ///
+    /// [`set_quota`]: crate::repo::key::KeyRepo::set_quota
+    /// [`RepoConfig::quota`]: crate::repo::RepoConfig::quota
+    pub fn quota(&self) -> Option<u64> {
+        self.state.read().unwrap().instance_quota
+    }
+
+    /// Set the quota for the current instance to `quota` bytes.
+    ///
+    /// This overrides [`RepoConfig::quota`] for the current instance. If `quota` is `None`, the
+    /// quota from the repository config is used instead. To remove the limit for an instance when
+    /// the repository config has a quota, set the quota to `u64::MAX`.
+    ///
+    /// Setting a quota lower than the current size of the instance does not remove any data, but
+    /// prevents the instance from growing.
+    ///
+    /// This does not commit changes to the repository.
+    ///
+    /// [`RepoConfig::quota`]: crate::repo::RepoConfig::quota
+    pub fn set_quota(&mut self, quota: Option<u64>) {
+        self.instances.get_mut(&self.instance_id).unwrap().quota = quota;
+        self.update_instance_usage();
+    }
+
+    /// Recompute the apparent size and the quota of the current instance.
+    ///
+    /// This must be called whenever the object map or instance map is replaced.
+    fn update_instance_usage(&self) {
+        let instance_size = self
+            .objects
+            .values()
+            .map(|handle| handle.read().unwrap().size())
+            .sum();
+        let mut state = self.state.write().unwrap();
+        state.instance_size = instance_size;
+        state.instance_quota = self
+            .instances
+            .get(&self.instance_id)
+            .and_then(|info| info.quota)
+            .or(state.metadata.config.quota)
+            .filter(|&quota| quota != u64::MAX);
+    }
+
+    /// Rewrite the data in the repository according to the given `options`.
+    ///
+    /// This can be used to change the [`Packing`] method of an existing repository, including
+    /// changing the pack size or enabling or disabling packing entirely. It can also be used to
+    /// defragment packs which have become mostly empty. See [`RepackOptions`] for details.
+    ///
+    /// Because changing the packing method affects how all existing data is read, this method
+    /// commits changes to the repository before rewriting any data. Like [`Commit::commit`], this
+    /// invalidates all savepoints. The data store is left in a consistent state if this method
+    /// returns early or panics, but unreferenced data may be left behind until [`Commit::clean`] is
+    /// called.
+    ///
+    /// This affects all instances of the repository.
+    ///
+    /// # Errors
+    /// - `Error::InvalidConfig`: Packing was requested and convergent encryption is enabled.
+    /// - `Error::Corrupt`: The repository is corrupt. This is most likely unrecoverable.
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    ///
+    /// [`Packing`]: crate::repo::Packing
+    /// [`RepackOptions`]: crate::repo::RepackOptions
+    /// [`Commit::commit`]: crate::repo::Commit::commit
+    /// [`Commit::clean`]: crate::repo::Commit::clean
+    #[cfg_attr(
+        feature = "observability",
+        tracing::instrument(level = "debug", skip_all)
+    )]
+    pub fn repack(&mut self, options: RepackOptions) -> crate::Result<()> {
+        // Packs can't be deduplicated across repositories.
+        let convergent_encryption = self
+            .state
+            .read()
+            .unwrap()
+            .metadata
+            .config
+            .convergent_encryption;
+        if convergent_encryption && matches!(options.packing, Some(Packing::Fixed(_))) {
+            return Err(crate::Error::InvalidConfig);
+        }
+
+        // Commit changes so that there is only one set of referenced blocks to rewrite. Blocks
+        // referenced by the previous commit would be unreadable once the packing method changes.
+        self.commit()?;
+
+        let mut state = self.state.write().unwrap();
+        let old_packing = state.metadata.config.packing.clone();
+        let new_packing = options.packing.unwrap_or_else(|| old_packing.clone());
+
+        // Blocks which are only referenced by checkpoints or retained commits need to be
+        // rewritten as well.
+        let mut referenced_blocks = state
+            .chunks
+            .values()
+            .map(|info| info.block_id)
+            .collect::<HashSet<_>>();
+        referenced_blocks.extend(checkpoint_blocks(
+            &state,
+            self.checkpoints
+                .values()
+                .chain(state.metadata.previous_header_ids.iter()),
+        )?);
+
+        // Get the list of blocks which need to be rewritten.
+        let blocks_to_rewrite = if new_packing != old_packing {
+            referenced_blocks.iter().copied().collect::<Vec<_>>()
+        } else {
+            match &old_packing {
+                Packing::Fixed(pack_size) if options.defragment => {
+                    // Find the number of bytes of referenced data in each pack.
+                    let mut pack_usage = HashMap::new();
+                    for block_id in &referenced_blocks {
+                        for pack_index in state.packs.get(block_id).into_iter().flatten() {
+                            *pack_usage.entry(pack_index.id).or_insert(0u64) +=
+                                u64::from(pack_index.size);
+                        }
+                    }
+
+                    // Rewrite every block which is at least partially stored in a sparse pack.
+                    referenced_blocks
+                        .iter()
+                        .filter(|block_id| {
+                            state
+                                .packs
+                                .get(block_id)
+                                .into_iter()
+                                .flatten()
+                                .any(|index| pack_usage[&index.id] < u64::from(*pack_size) / 2)
+                        })
+                        .copied()
+                        .collect::<Vec<_>>()
+                }
+                _ => Vec::new(),
+            }
+        };
+
+        if blocks_to_rewrite.is_empty() {
+            return Ok(());
+        }
+
+        // Write each block using the new packing method. Because block IDs don't change, the chunk
+        // map doesn't need to be updated.
+        {
+            let mut store_state = StoreState::new();
+            for block_id in blocks_to_rewrite {
+                repack_block(
+                    &mut state,
+                    &mut store_state,
+                    block_id,
+                    &old_packing,
+                    &new_packing,
+                )?;
+            }
+        }
+
+        // Update the packing method and atomically write the new header and metadata to the data
+        // store. Until this completes, the repository still uses the old packing method and the
+        // old data.
+        let previous_packing = mem::replace(&mut state.metadata.config.packing, new_packing);
+        let previous_packs = if state.metadata.config.packing == Packing::None {
+            mem::take(&mut state.packs)
+        } else {
+            HashMap::new()
+        };
+        let fingerprint = state.fingerprint();
+        drop(state);
+        let serialized_header = self.serialize_header();
+        if let Err(error) =
+            self.write_serialized_header(serialized_header.as_slice(), Some(fingerprint))
+        {
+            let mut state = self.state.write().unwrap();
+            state.metadata.config.packing = previous_packing;
+            state.packs.extend(previous_packs);
+            return Err(error);
+        }
+
+        // Savepoints reference the old pack map, so they must be invalidated.
+        self.transaction_id = Arc::new(Uuid::new_v4());
+
+        // Remove the data which is no longer referenced now that it's been rewritten.
+        let state = self.state.read().unwrap();
+        let live_blocks = match &state.metadata.config.packing {
+            Packing::None => referenced_blocks,
+            Packing::Fixed(_) => state
+                .packs
+                .values()
+                .flatten()
+                .map(|pack_index| pack_index.id)
+                .collect::<HashSet<_>>(),
+        };
+        let mut store = state.store.lock().unwrap();
+        let data_blocks = store
+            .list_blocks(BlockType::Data)
+            .map_err(crate::Error::Store)?;
+        for block_id in data_blocks {
+            if !live_blocks.contains(&block_id) {
+                store
+                    .remove_block(BlockKey::Data(block_id))
+                    .map_err(crate::Error::Store)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Change the chunking method used for new data in the repository.
+    ///
+    /// Data which is written after this is called is split into chunks using the new `chunking`
+    /// method. Existing data is left as it is, which means it will typically only be deduplicated
+    /// against new data if the chunking methods happen to produce the same chunks. Existing objects
+    /// can be rewritten using the new chunking method with [`rechunk`] or [`rechunk_all`].
+    ///
+    /// Objects which are open when this is called keep using the old chunking method.
+    ///
+    /// Because the chunking method is stored with the repository, this method commits changes to
+    /// the repository. Like [`Commit::commit`], this invalidates all savepoints.
+    ///
+    /// This affects all instances of the repository.
+    ///
+    /// # Errors
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    ///
+    /// [`rechunk`]: crate::repo::key::KeyRepo::rechunk
+    /// [`rechunk_all`]: crate::repo::key::KeyRepo::rechunk_all
+    /// [`Commit::commit`]: crate::repo::Commit::commit
+    pub fn set_chunking(&mut self, chunking: Chunking) -> crate::Result<()> {
+        let previous_chunking = mem::replace(
+            &mut self.state.write().unwrap().metadata.config.chunking,
+            chunking,
+        );
+        if let Err(error) = self.commit() {
+            self.state.write().unwrap().metadata.config.chunking = previous_chunking;
+            return Err(error);
+        }
+        Ok(())
+    }
+
+    /// Rewrite the object with the given `key` using the current chunking method.
+    ///
+    /// This returns `true` if the object was rewritten or `false` if it didn't exist. The contents
+    /// of the object, including any sparse holes, and its [`ObjectId`] don't change.
+    ///
+    /// This can be used to gradually move data to a new chunking method after calling
+    /// [`set_chunking`] by rewriting a few objects at a time. Rewriting an object which is already
+    /// chunked using the current chunking method doesn't write any new data to the data store.
+    ///
+    /// This does not commit changes to the repository. The space used by the old chunks isn't
+    /// reclaimed in the backing data store until changes are committed and [`Commit::clean`] is
+    /// called.
+    ///
+    /// # Errors
+    /// - `Error::TransactionInProgress`: The object is currently being written to.
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    ///
+    /// [`ObjectId`]: crate::repo::ObjectId
+    /// [`set_chunking`]: crate::repo::key::KeyRepo::set_chunking
+    /// [`Commit::clean`]: crate::repo::Commit::clean
+    pub fn rechunk<Q>(&mut self, key: &Q) -> crate::Result<bool>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        let handle = match self.objects.get(key) {
+            Some(handle) => Arc::clone(handle),
+            None => return Ok(false),
+        };
+        self.rechunk_handle(&handle)?;
+        Ok(true)
+    }
+
+    /// Rewrite every object in the current instance using the current chunking method.
+    ///
+    /// Objects are rewritten one at a time as if by [`rechunk`]. If this returns early, the objects
+    /// which were already rewritten keep their new chunks, so calling this again only writes new
+    /// data for the objects which weren't rewritten yet.
+    ///
+    /// This does not rewrite objects in other instances of the repository.
+    ///
+    /// This does not commit changes to the repository. The space used by the old chunks isn't
+    /// reclaimed in the backing data store until changes are committed and [`Commit::clean`] is
+    /// called.
+    ///
+    /// # Errors
+    /// - `Error::TransactionInProgress`: An object is currently being written to.
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    ///
+    /// [`rechunk`]: crate::repo::key::KeyRepo::rechunk
+    /// [`Commit::clean`]: crate::repo::Commit::clean
+    pub fn rechunk_all(&mut self) -> crate::Result<()> {
+        let handles = self.objects.values().cloned().collect::<Vec<_>>();
+        for handle in handles {
+            self.rechunk_handle(&handle)?;
+        }
+        Ok(())
+    }
+
+    /// Rewrite the object with the given `handle` using the current chunking method.
+    ///
+    /// The object keeps its handle ID, so chunks which are the same under both chunking methods
+    /// are left in place.
+    fn rechunk_handle(&self, handle: &RwLock<ObjectHandle>) -> crate::Result<()> {
+        let old_handle = handle.read().unwrap().clone();
+        let _transaction_lock = self
+            .state
+            .write()
+            .unwrap()
+            .transactions
+            .acquire_lock(old_handle.id)
+            .ok_or(crate::Error::TransactionInProgress)?;
+
+        let mut new_chunks = Vec::new();
+        let result = self.write_rechunked(&old_handle, &mut new_chunks);
+
+        let old_chunks = old_handle.chunks().collect::<HashSet<_>>();
+        let mut state = self.state.write().unwrap();
+
+        let new_extents = match result {
+            Ok(new_extents) => new_extents,
+            Err(error) => {
+                // Remove the references to the chunks which are only used by the new extents.
+                let unused_chunks = new_chunks
+                    .into_iter()
+                    .filter(|chunk| !old_chunks.contains(chunk));
+                remove_references(&mut state, unused_chunks.zip(iter::repeat(old_handle.id)));
+                return Err(error);
+            }
+        };
+
+        // Remove the references to the chunks which are only used by the old extents.
+        let new_chunks = new_chunks.into_iter().collect::<HashSet<_>>();
+        let unused_chunks = old_chunks
+            .into_iter()
+            .filter(|chunk| !new_chunks.contains(chunk));
+        remove_references(&mut state, unused_chunks.zip(iter::repeat(old_handle.id)));
+        handle.write().unwrap().extents = new_extents;
+
+        Ok(())
+    }
+
+    /// Write the contents of the object with the given `handle` using the current chunking method.
+    ///
+    /// This returns the new extents for the object. Each chunk which is written is added to
+    /// `new_chunks` as it's written, so that the references to them can be removed if this fails
+    /// partway through.
+    fn write_rechunked(
+        &self,
+        handle: &ObjectHandle,
+        new_chunks: &mut Vec<Chunk>,
+    ) -> crate::Result<Vec<Extent>> {
+        let chunking = self.state.read().unwrap().metadata.config.chunking.clone();
+        let mut chunker = IncrementalChunker::new(chunking.to_chunker());
+        let mut read_state = StoreState::new();
+        let mut write_state = StoreState::new();
+        let mut new_extents = Vec::new();
+
+        // Holes end the current chunk so that they can be kept as holes.
+        for extent in handle.extents.iter().map(Some).chain(iter::once(None)) {
+            match extent {
+                Some(Extent::Chunk(chunk)) => {
+                    let state = self.state.read().unwrap();
+                    let data = StoreReader::new(&state, &mut read_state).read_chunk(*chunk)?;
+                    chunker.write_all(&data)?;
+                }
+                Some(Extent::Hole { .. }) | None => chunker.flush()?,
+            }
+
+            let mut state = self.state.write().unwrap();
+            let mut store_writer = StoreWriter::new(&mut state, &mut write_state);
+            for data in chunker.chunks() {
+                let chunk = store_writer.write_chunk(&data, handle.id)?;
+                new_chunks.push(chunk);
+                new_extents.push(Extent::Chunk(chunk));
+            }
+
+            if let Some(hole @ Extent::Hole { .. }) = extent {
+                new_extents.push(*hole);
+            }
+        }
+
+        Ok(new_extents)
+    }
+
+    /// Persist uncommitted changes to the data store without committing them.
+    ///
+    /// This writes the current state of the repository to the data store so that uncommitted
+    /// changes are not lost if the process crashes, but it does not publish them. Opening the
+    /// repository still returns the state from the last commit, and [`Commit::rollback`] still
+    /// returns the repository to that state. The changes which were most recently flushed can be
+    /// recovered with [`restore_flushed`], such as after the process crashes. This gives
+    /// applications with long-running transactions an intermediate level of durability which is
+    /// cheaper than committing.
+    ///
+    /// Only data which has been written to objects is flushed. Data which is still buffered in an
+    /// [`Object`] must be flushed first with [`Object::commit`].
+    ///
+    /// Each call to this method replaces the previously flushed changes. Flushed changes are
+    /// discarded when changes are committed or when [`Commit::clean`] or [`repack`] is called.
+    ///
+    /// This method flushes changes for all instances of the repository.
+    ///
+    /// # Errors
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    ///
+    /// [`Commit::rollback`]: crate::repo::Commit::rollback
+    /// [`restore_flushed`]: crate::repo::key::KeyRepo::restore_flushed
+    /// [`Object`]: crate::repo::Object
+    /// [`Object::commit`]: crate::repo::Object::commit
+    /// [`Commit::clean`]: crate::repo::Commit::clean
+    /// [`repack`]: crate::repo::key::KeyRepo::repack
+    #[cfg_attr(
+        feature = "observability",
+        tracing::instrument(level = "debug", skip_all)
+    )]
+    pub fn flush(&mut self) -> crate::Result<()> {
+        // Write the map of objects for the current instance.
+        self.write_object_map()?;
+
+        let serialized_header = self.serialize_header();
+        let mut state = self.state.write().unwrap();
+        let encoded_header = state.encode_data(serialized_header.as_slice())?;
+
+        // Write the header to a new block, but don't replace the header from the last commit.
+        let header_id = Uuid::new_v4().into();
+        state
+            .store
+            .lock()
+            .unwrap()
+            .write_block(BlockKey::Header(header_id), encoded_header.as_slice())
+            .map_err(crate::Error::Store)?;
+
+        // Atomically write the new repository metadata containing the ID of the flushed header.
+        let previous_header_id = state.metadata.flushed_header_id.replace(header_id);
+        let serialized_metadata = state.metadata.to_super_block(&state.master_key);
+        let result = state
+            .store
+            .lock()
+            .unwrap()
+            .write_block(BlockKey::Super, &serialized_metadata)
+            .map_err(crate::Error::Store);
+        if let Err(error) = result {
+            state.metadata.flushed_header_id = previous_header_id;
+            return Err(error);
+        }
+
+        // The previously flushed header is no longer referenced. If this fails, it will be removed
+        // the next time the repository is cleaned.
+        if let Some(previous_header_id) = previous_header_id {
+            state
+                .store
+                .lock()
+                .unwrap()
+                .remove_block(BlockKey::Header(previous_header_id))
+                .ok();
+        }
+
+        Ok(())
+    }
+
+    /// Restore the repository to the state it was in when changes were last flushed.
+    ///
+    /// This returns `true` if the repository was restored or `false` if no changes have been
+    /// flushed since the last commit. This is typically used after re-opening a repository which
+    /// was not committed because the process crashed.
+    ///
+    /// If this method returns `Err`, the repository is unchanged.
+    ///
+    /// Restoring flushed changes invalidates all [`Object`] and [`ReadOnlyObject`] instances
+    /// associated with the repository.
+    ///
+    /// # Errors
+    /// - `Error::Corrupt`: The repository is corrupt. This is most likely unrecoverable.
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    ///
+    /// [`Object`]: crate::repo::Object
+    /// [`ReadOnlyObject`]: crate::repo::ReadOnlyObject
+    pub fn restore_flushed(&mut self) -> crate::Result<bool> {
+        let state = self.state.read().unwrap();
+        let header_id = match state.metadata.flushed_header_id {
+            Some(header_id) => header_id,
+            None => return Ok(false),
+        };
+
+        // Read the header from the most recent flush from the data store.
+        let header = read_header(&state, header_id)?;
+        drop(state);
+
+        // Atomically restore from the deserialized header.
+        self.restore_header(header)?;
+
+        Ok(true)
+    }
+
+    /// Save the current state of the repository as a checkpoint named `name`.
+    ///
+    /// Unlike a [`Savepoint`], a checkpoint is stored in the repository and is not invalidated
+    /// when changes are committed. Once the repository is committed, the checkpoint is persisted
+    /// to the data store and is available after the repository is closed and re-opened. The state
+    /// of the repository can be restored from the checkpoint with [`restore_checkpoint`].
+    ///
+    /// Creating a checkpoint does not copy any data. Data referenced by a checkpoint is not
+    /// reclaimed by [`Commit::clean`] until the checkpoint is removed.
+    ///
+    /// Only data which has been written to objects is saved in the checkpoint. Data which is still
+    /// buffered in an [`Object`] must be flushed first with [`Object::commit`].
+    ///
+    /// A checkpoint captures the state of all instances of the repository.
+    ///
+    /// # Errors
+    /// - `Error::AlreadyExists`: There is already a checkpoint named `name`.
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    ///
+    /// [`Savepoint`]: crate::repo::Savepoint
+    /// [`restore_checkpoint`]: crate::repo::key::KeyRepo::restore_checkpoint
+    /// [`Commit::clean`]: crate::repo::Commit::clean
+    /// [`Object`]: crate::repo::Object
+    /// [`Object::commit`]: crate::repo::Object::commit
+    pub fn create_checkpoint(&mut self, name: impl Into<String>) -> crate::Result<()> {
+        let name = name.into();
+        if self.checkpoints.contains_key(&name) {
+            return Err(crate::Error::AlreadyExists);
+        }
+
+        // Write the map of objects for the current instance.
+        self.write_object_map()?;
+
+        let serialized_header = self.serialize_header();
+        let state = self.state.read().unwrap();
+        let encoded_header = state.encode_data(serialized_header.as_slice())?;
+
+        // Write the header to a new block which is referenced by the checkpoint.
+        let header_id = Uuid::new_v4().into();
+        state
+            .store
+            .lock()
+            .unwrap()
+            .write_block(BlockKey::Header(header_id), encoded_header.as_slice())
+            .map_err(crate::Error::Store)?;
+        drop(state);
+
+        self.checkpoints.insert(name, header_id);
+
+        Ok(())
+    }
+
+    /// Remove the checkpoint named `name` from the repository.
+    ///
+    /// This returns `true` if the checkpoint was removed or `false` if it didn't exist.
+    ///
+    /// The space used by the checkpoint isn't reclaimed in the backing data store until changes
+    /// are committed and [`Commit::clean`] is called.
+    ///
+    /// [`Commit::clean`]: crate::repo::Commit::clean
+    pub fn remove_checkpoint(&mut self, name: &str) -> bool {
+        self.checkpoints.remove(name).is_some()
+    }
+
+    /// Return whether there is a checkpoint named `name`.
+    pub fn contains_checkpoint(&self, name: &str) -> bool {
+        self.checkpoints.contains_key(name)
+    }
+
+    /// Return an iterator over the names of the checkpoints in this repository.
+    ///
+    /// Checkpoints are returned in lexicographical order by name.
+    pub fn checkpoints(&self) -> Checkpoints {
+        Checkpoints(self.checkpoints.keys())
     }
-    /// Atomically restore the repository's state from the given `header`.
+
+    /// Register `callback` to be called each time `event` happens in this repository.
     ///
-    /// This restores the state of the repository using the data in the given `header` and then
-    /// reads the object map for the current instance from the data store.
+    /// The callback is passed information about the repository at the time of the event. Hooks are
+    /// called in the order they were registered. This returns an ID which can be passed to
+    /// [`remove_hook`] to remove the hook.
     ///
-    /// If this returns `Ok`, the repository's state has been restored. If this returns `Err`, the
-    /// repository is unchanged.
-    fn restore_header(&mut self, header: Header) -> crate::Result<()> {
-        // We need to restore the repository state before we can read the object map.
-        let old_header = self.replace_header(header);
+    /// Hooks are not persisted, so they must be registered again each time the repository is
+    /// opened. They are shared between all instances of the repository which were opened together,
+    /// so switching instances does not remove them.
+    ///
+    /// Hooks are called while the repository is in use, so the callback must not access this
+    /// repository or register or remove hooks, or it will deadlock. To do more work in response to
+    /// an event, send a message to another thread from the callback.
+    ///
+    /// [`remove_hook`]: crate::repo::key::KeyRepo::remove_hook
+    pub fn on_event(
+        &self,
+        event: RepoEvent,
+        callback: impl FnMut(&RepoInfo) + Send + 'static,
+    ) -> HookId {
+        let state = self.state.read().unwrap();
+        let mut hooks = state.hooks.lock().unwrap();
+        hooks.add(event, Box::new(callback))
+    }
 
-        // Restore the object map from the old header.
-        match self.read_object_map() {
-            Ok(objects) => {
-                self.objects = objects;
-                Ok(())
-            }
-            Err(error) => {
-                self.replace_header(old_header);
-                Err(error)
-            }
-        }
+    /// Remove the hook with the given `id` which was registered with [`on_event`].
+    ///
+    /// This returns `true` if the hook was removed or `false` if there is no hook with this ID.
+    ///
+    /// [`on_event`]: crate::repo::key::KeyRepo::on_event
+    pub fn remove_hook(&self, id: HookId) -> bool {
+        let state = self.state.read().unwrap();
+        let mut hooks = state.hooks.lock().unwrap();
+        hooks.remove(id)
     }
 
-    /// Verify the integrity of all the data in the current instance of the repository.
+    /// Call the hooks registered for `event`.
+    fn run_hooks(&self, event: RepoEvent) {
+        let state = self.state.read().unwrap();
+        let info = state.metadata.to_info();
+        let mut hooks = state.hooks.lock().unwrap();
+        hooks.run(event, &info);
+    }
+
+    /// Return the log of commits to this repository.
     ///
-    /// This returns the set of keys of objects in the current instance which are corrupt.
+    /// Records are returned in the order the commits were made. This is empty if
+    /// [`RepoConfig::commit_log`] is disabled. Changes which have not been committed do not appear
+    /// in the log.
     ///
-    /// If you just need to verify the integrity of one object, [`Object::verify`] is faster. If you
-    /// need to verify the integrity of all the data in the repository, however, this can be more
-    /// efficient.
+    /// [`RepoConfig::commit_log`]: crate::repo::RepoConfig::commit_log
+    pub fn commit_log(&self) -> &[CommitRecord] {
+        &self.commit_log
+    }
+
+    /// Verify the integrity of the commit log.
+    ///
+    /// This checks that every record in the log returned by [`commit_log`] is authentic and that
+    /// no records have been removed from the middle of the log. This can't detect whether the
+    /// repository has been rolled back to an earlier commit; see [`RepoConfig::commit_log`] for
+    /// details.
     ///
     /// # Errors
-    /// - `Error::InvalidData`: Ciphertext verification failed.
-    /// - `Error::Store`: An error occurred with the data store.
-    /// - `Error::Io`: An I/O error occurred.
+    /// - `Error::Corrupt`: The commit log has been tampered with.
     ///
-    /// [`Object::verify`]: crate::repo::Object::verify
-    pub fn verify(&self) -> crate::Result<HashSet<&K>> {
+    /// [`commit_log`]: crate::repo::key::KeyRepo::commit_log
+    /// [`RepoConfig::commit_log`]: crate::repo::RepoConfig::commit_log
+    pub fn verify_commit_log(&self) -> crate::Result<()> {
         let state = self.state.read().unwrap();
+        verify_commit_log(&state.master_key, state.metadata.id, &self.commit_log)
+    }
 
-        let mut corrupt_chunks = HashSet::new();
-        let expected_chunks = state.chunks.keys().copied().collect::<Vec<_>>();
-
-        // Get the set of hashes of chunks which are corrupt.
-        let mut store_state = StoreState::new();
-        let mut store_reader = StoreReader::new(&state, &mut store_state);
-        for chunk in expected_chunks {
-            match store_reader.read_chunk(chunk) {
-                Ok(data) => {
-                    if data.len() != chunk.size as usize || chunk_hash(&data) != chunk.hash {
-                        corrupt_chunks.insert(chunk.hash);
-                    }
-                }
-                Err(crate::Error::InvalidData) => {
-                    // Ciphertext verification failed. No need to check the hash.
-                    corrupt_chunks.insert(chunk.hash);
-                }
-                Err(error) => return Err(error),
-            };
-        }
-
-        // If there are no corrupt chunks, there are no corrupt objects.
-        if corrupt_chunks.is_empty() {
-            return Ok(HashSet::new());
-        }
+    /// Add the header of the most recent commit to the list of retained headers.
+    ///
+    /// Headers which are older than the retention policy allows are dropped from the list. This
+    /// returns the previous list of retained headers.
+    fn retain_previous_header(&mut self) -> Vec<BlockId> {
+        let mut state = self.state.write().unwrap();
+        let retained_headers = state.metadata.config.retained_commits.saturating_sub(1);
+        let current_header_id = state.metadata.header_id;
+        let old_header_ids = state.metadata.previous_header_ids.clone();
+        let header_ids = &mut state.metadata.previous_header_ids;
+        header_ids.insert(0, current_header_id);
+        header_ids.truncate(retained_headers);
+        old_header_ids
+    }
 
-        let mut corrupt_keys = HashSet::new();
-        for (key, handle) in &self.objects {
-            for chunk in handle.read().unwrap().chunks() {
-                // If any one of the object's chunks is corrupt, the object is corrupt.
-                if corrupt_chunks.contains(&chunk.hash) {
-                    corrupt_keys.insert(key);
-                    break;
-                }
-            }
+    /// Append a record of a commit with the given `fingerprint` to the commit log.
+    ///
+    /// This returns `false` without changing the log if the commit log is disabled.
+    fn log_commit(&mut self, fingerprint: Fingerprint) -> bool {
+        let state = self.state.read().unwrap();
+        if !state.metadata.config.commit_log {
+            return false;
         }
-
-        Ok(corrupt_keys)
+        let record = CommitRecord::new(
+            &state.master_key,
+            state.metadata.id,
+            self.commit_log.last(),
+            fingerprint,
+        );
+        drop(state);
+        self.commit_log.push(record);
+        true
     }
 
-    /// Delete all data in the current instance of the repository.
+    /// Restore the repository to the state it was in when the checkpoint `name` was created.
     ///
-    /// This does not delete data from other instances of the repository.
+    /// This restores all instances of the repository. The set of checkpoints in the repository and
+    /// the commit log are not affected. This does not commit changes to the repository.
     ///
-    /// This does not commit changes to the repository.
+    /// If this method returns `Err`, the repository is unchanged.
     ///
-    /// No data is reclaimed in the backing data store until changes are committed and
-    /// [`Commit::clean`] is called.
+    /// Restoring a checkpoint invalidates all [`Object`] and [`ReadOnlyObject`] instances
+    /// associated with the repository.
     ///
-    /// [`Commit::clean`]: crate::repo::Commit::clean
-    pub fn clear_instance(&mut self) {
-        let handles = self
-            .objects
-            .drain()
-            .map(|(_, handle)| handle)
-            .collect::<Vec<_>>();
-        for handle in handles {
-            self.remove_handle(&handle.read().unwrap());
-        }
+    /// # Errors
+    /// - `Error::NotFound`: There is no checkpoint named `name`.
+    /// - `Error::Corrupt`: The repository is corrupt. This is most likely unrecoverable.
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    ///
+    /// [`Object`]: crate::repo::Object
+    /// [`ReadOnlyObject`]: crate::repo::ReadOnlyObject
+    pub fn restore_checkpoint(&mut self, name: &str) -> crate::Result<()> {
+        let header_id = *self.checkpoints.get(name).ok_or(crate::Error::NotFound)?;
+        self.restore_saved_header(header_id)
     }
 
-    /// Change the password for this repository.
+    /// Return the number of previous commits which the repository can be restored to.
     ///
-    /// This replaces the existing password with `new_password`. This also accepts the
-    /// `memory_limit` and the `operations_limit`, which affect the amount of memory and the number
-    /// of computations respectively which will be used by the key derivation function.
+    /// This does not include the most recent commit. This is at most one less than
+    /// [`RepoConfig::retained_commits`].
     ///
-    /// Changing the password does not require re-encrypting any data. The change does not take
-    /// effect until [`Commit::commit`] is called.
+    /// [`RepoConfig::retained_commits`]: crate::repo::RepoConfig::retained_commits
+    pub fn previous_commits(&self) -> usize {
+        self.state
+            .read()
+            .unwrap()
+            .metadata
+            .previous_header_ids
+            .len()
+    }
+
+    /// Restore the repository to the state it was in `age` commits before the most recent one.
     ///
-    /// If encryption is disabled, this method does nothing.
+    /// An `age` of `0` restores the repository to the most recent commit, which is equivalent to
+    /// [`Commit::rollback`]. Older commits are only retained if [`RepoConfig::retained_commits`]
+    /// is greater than `1`, and [`previous_commits`] returns how many are available.
     ///
-    /// [`Commit::commit`]: crate::repo::Commit::commit
-    pub fn change_password(
-        &mut self,
-        new_password: &[u8],
-        memory_limit: ResourceLimit,
-        operations_limit: ResourceLimit,
-    ) {
-        let mut state = self.state.write().unwrap();
-
-        if state.metadata.config.encryption == Encryption::None {
-            return;
-        }
+    /// This restores all instances of the repository. The set of checkpoints in the repository and
+    /// the commit log are not affected. This does not commit changes to the repository.
+    ///
+    /// If this method returns `Err`, the repository is unchanged.
+    ///
+    /// Restoring a commit invalidates all [`Object`] and [`ReadOnlyObject`] instances associated
+    /// with the repository.
+    ///
+    /// # Errors
+    /// - `Error::NotFound`: There is no retained commit `age` commits before the most recent one.
+    /// - `Error::Corrupt`: The repository is corrupt. This is most likely unrecoverable.
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    ///
+    /// [`Commit::rollback`]: crate::repo::Commit::rollback
+    /// [`RepoConfig::retained_commits`]: crate::repo::RepoConfig::retained_commits
+    /// [`previous_commits`]: crate::repo::key::KeyRepo::previous_commits
+    /// [`Object`]: crate::repo::Object
+    /// [`ReadOnlyObject`]: crate::repo::ReadOnlyObject
+    pub fn restore_commit(&mut self, age: usize) -> crate::Result<()> {
+        let header_id = {
+            let state = self.state.read().unwrap();
+            match age {
+                0 => state.metadata.header_id,
+                _ => *state
+                    .metadata
+                    .previous_header_ids
+                    .get(age - 1)
+                    .ok_or(crate::Error::NotFound)?,
+            }
+        };
+        self.restore_saved_header(header_id)
+    }
 
-        let salt = KeySalt::generate();
-        let user_key = EncryptionKey::derive(
-            new_password,
-            &salt,
-            state.metadata.config.encryption.key_size(),
-            memory_limit,
-            operations_limit,
+    /// Restore the repository from the saved header with the given `header_id`.
+    ///
+    /// The current set of checkpoints and the commit log are preserved.
+    fn restore_saved_header(&mut self, header_id: BlockId) -> crate::Result<()> {
+        let state = self.state.read().unwrap();
+        let mut header = read_header(&state, header_id)?;
+
+        // Blocks may have been moved to different packs since the header was saved, so the
+        // current pack map takes precedence over the one in the saved header.
+        header.packs.extend(
+            state
+                .packs
+                .iter()
+                .map(|(block_id, pack_indices)| (*block_id, pack_indices.clone())),
         );
+        drop(state);
 
-        let encrypted_master_key = state
-            .metadata
-            .config
-            .encryption
-            .encrypt(state.master_key.expose_secret(), &user_key);
-
-        state.metadata.salt = salt;
-        state.metadata.master_key = encrypted_master_key;
-        state.metadata.config.memory_limit = memory_limit;
-        state.metadata.config.operations_limit = operations_limit;
-    }
+        header.checkpoints = self.checkpoints.clone();
+        header.commit_log = self.commit_log.clone();
 
-    /// Return this repository's current instance ID.
-    pub fn instance(&self) -> InstanceId {
-        self.instance_id
+        // Atomically restore from the deserialized header.
+        self.restore_header(header)
     }
+}
 
-    /// Compute statistics about the repository.
-    ///
-    /// The returned `RepoStats` represents the contents of the repository at the time this method
-    /// was called. It is not updated when the repository is modified.
-    pub fn stats(&self) -> RepoStats {
-        let mut apparent_size = 0u64;
-        let mut actual_size = 0u64;
-        let mut repo_size = 0u64;
+/// Read and decode the header with the given `header_id` from the data store.
+fn read_header(state: &RepoState, header_id: BlockId) -> crate::Result<Header> {
+    let encoded_header = state
+        .store
+        .lock()
+        .unwrap()
+        .read_block(BlockKey::Header(header_id))
+        .map_err(crate::Error::Store)?
+        .ok_or(crate::Error::Corrupt)?;
+    let serialized_header = state.decode_data(encoded_header.as_slice())?;
+    from_read(serialized_header.as_slice()).map_err(|_| crate::Error::Corrupt)
+}
 
-        // The set of object handle IDs of objects in the current instance.
-        let mut current_instance_handles = HashSet::new();
+/// Return the set of IDs of data blocks which are referenced by the checkpoint headers with the
+/// given `header_ids`.
+fn checkpoint_blocks<'a>(
+    state: &RepoState,
+    header_ids: impl IntoIterator<Item = &'a BlockId>,
+) -> crate::Result<HashSet<BlockId>> {
+    let mut referenced_blocks = HashSet::new();
+    for header_id in header_ids {
+        let header = read_header(state, *header_id)?;
+        referenced_blocks.extend(header.chunks.values().map(|info| info.block_id));
+    }
+    Ok(referenced_blocks)
+}
 
-        // The set of object handle IDs of objects which store metadata and shouldn't count towards
-        // the `repo_size`.
-        let metadata_handles = self
-            .instances
-            .values()
-            .map(|info| info.objects.id)
-            .collect::<HashSet<_>>();
+/// Split the chunks in the repository into at most `count` groups of roughly equal size.
+///
+/// Chunks which are stored in the same pack are always put in the same group, and they are ordered
+/// by their offset in the pack. This way, a reader which verifies a group in order only needs to
+/// read each pack from the data store once.
+fn group_chunks(repo_state: &RepoState, count: usize) -> Vec<Vec<Chunk>> {
+    // Group the chunks by the pack containing the start of their block. If packing is disabled,
+    // each chunk is stored in its own block.
+    let mut chunks_by_pack = HashMap::new();
+    for (chunk, chunk_info) in &repo_state.chunks {
+        let (pack_id, offset) = match repo_state
+            .packs
+            .get(&chunk_info.block_id)
+            .and_then(|index_list| index_list.first())
+        {
+            Some(pack_index) => (pack_index.id, pack_index.offset),
+            None => (chunk_info.block_id, 0),
+        };
+        chunks_by_pack
+            .entry(pack_id)
+            .or_insert_with(Vec::new)
+            .push((offset, *chunk));
+    }
 
-        for handle_lock in self.objects.values() {
-            let handle = handle_lock.read().unwrap();
-            apparent_size += handle.size();
-            current_instance_handles.insert(handle.id);
+    let group_size = (repo_state.chunks.len() + count - 1) / count;
+    let mut groups = Vec::with_capacity(count);
+    let mut current_group = Vec::with_capacity(group_size);
+    for mut pack_chunks in chunks_by_pack.into_values() {
+        pack_chunks.sort_unstable_by_key(|(offset, _)| *offset);
+        current_group.extend(pack_chunks.into_iter().map(|(_, chunk)| chunk));
+        if current_group.len() >= group_size {
+            groups.push(mem::replace(
+                &mut current_group,
+                Vec::with_capacity(group_size),
+            ));
         }
+    }
+    if !current_group.is_empty() {
+        groups.push(current_group);
+    }
 
-        let state = self.state.read().unwrap();
-        for (chunk, info) in state.chunks.iter() {
-            // Only count object inserted by the user in the `repo_size`.
-            if !info.references.is_subset(&metadata_handles) {
-                repo_size += chunk.size as u64;
-            }
+    groups
+}
 
-            if !info.references.is_disjoint(&current_instance_handles) {
-                actual_size += chunk.size as u64;
+/// Read each of the given `chunks` from `store` and return the set of hashes of those which are
+/// corrupt.
+fn find_corrupt_chunks(
+    repo_state: &RepoState,
+    store: &Mutex<Box<dyn DataStore>>,
+    chunks: &[Chunk],
+) -> crate::Result<HashSet<ChunkHash>> {
+    let mut corrupt_chunks = HashSet::new();
+    let mut store_state = StoreState::new();
+    let mut store_reader = StoreReader::with_store(repo_state, &mut store_state, store);
+    for chunk in chunks {
+        match store_reader.read_chunk(*chunk) {
+            Ok(data) => {
+                if data.len() != chunk.size as usize || chunk_hash(&data) != chunk.hash {
+                    corrupt_chunks.insert(chunk.hash);
+                }
             }
-        }
+            Err(crate::Error::InvalidData) => {
+                // Ciphertext verification failed. No need to check the hash.
+                corrupt_chunks.insert(chunk.hash);
+            }
+            Err(error) => return Err(error),
+        };
+    }
+    Ok(corrupt_chunks)
+}
 
-        RepoStats {
-            apparent_size,
-            actual_size,
-            repo_size,
+/// Check the chunks used by the existing object `handle`.
+///
+/// This records the chunks used by `handle` in `used_chunks` and any inconsistencies in
+/// `inconsistencies`. This returns whether any of the object's data is missing.
+fn check_handle<K>(
+    repo_state: &RepoState,
+    handle: &ObjectHandle,
+    missing_data: &HashSet<Chunk>,
+    used_chunks: &mut HashSet<Chunk>,
+    inconsistencies: &mut Inconsistencies<K>,
+) -> bool {
+    let mut is_damaged = false;
+    for chunk in handle.chunks().collect::<HashSet<_>>() {
+        used_chunks.insert(chunk);
+        match repo_state.chunks.get(&chunk) {
+            Some(chunk_info) => {
+                if !chunk_info.references.contains(&handle.id) {
+                    inconsistencies.missing_references.push((chunk, handle.id));
+                }
+                if missing_data.contains(&chunk) {
+                    is_damaged = true;
+                }
+            }
+            None => {
+                inconsistencies.missing_chunks.insert(chunk);
+                is_damaged = true;
+            }
         }
     }
+    is_damaged
+}
 
-    /// Return information about the repository.
-    pub fn info(&self) -> RepoInfo {
-        self.state.read().unwrap().metadata.to_info()
+/// Return the extent for a new chunk containing `data`.
+fn new_extent(data: &[u8]) -> Extent {
+    Extent::Chunk(Chunk {
+        hash: chunk_hash(data),
+        size: data.len() as u32,
+    })
+}
+
+/// Remove the reference from each handle to its chunk in `references`.
+///
+/// Chunks which are no longer referenced by any handle are removed from the repository.
+fn remove_references(
+    state: &mut RepoState,
+    references: impl IntoIterator<Item = (Chunk, HandleId)>,
+) {
+    for (chunk, handle_id) in references {
+        if let Some(chunk_info) = state.chunks.get_mut(&chunk) {
+            chunk_info.references.remove(&handle_id);
+            if chunk_info.references.is_empty() {
+                state.chunks.remove(&chunk);
+                state.chunk_tree.invalidate(&chunk);
+            }
+        }
     }
 }
 
@@ -652,30 +2968,58 @@ impl<K: Key> RestoreSavepoint for KeyRepo<K> {
 
         self.replace_header(restore.header);
         self.objects = restore.objects;
+        self.update_instance_usage();
 
         true
     }
 }
 
 impl<K: Key> Commit for KeyRepo<K> {
+    #[cfg_attr(
+        feature = "observability",
+        tracing::instrument(level = "debug", skip_all)
+    )]
     fn commit(&mut self) -> crate::Result<()> {
+        self.run_hooks(RepoEvent::PreCommit);
+
         // Write the map of objects for the current instance.
         self.write_object_map()?;
 
+        // Record the commit in the commit log if it's enabled.
+        let fingerprint = self.state.write().unwrap().fingerprint();
+        let logged = self.log_commit(fingerprint);
+
+        // Retain the header from the previous commit if the retention policy requires it.
+        let previous_header_ids = self.retain_previous_header();
+
         // Serialize the header.
         let serialized_header = self.serialize_header();
 
         // Write the serialized header to the data store, atomically completing the commit. If this
         // completes successfully, changes have been committed and this method MUST return `Ok`.
-        self.write_serialized_header(serialized_header.as_slice())?;
+        if let Err(error) =
+            self.write_serialized_header(serialized_header.as_slice(), Some(fingerprint))
+        {
+            if logged {
+                self.commit_log.pop();
+            }
+            self.state.write().unwrap().metadata.previous_header_ids = previous_header_ids;
+            return Err(error);
+        }
 
         // Now that the commit has succeeded, we must invalidate all savepoints associated with this
         // repository.
         self.transaction_id = Arc::new(Uuid::new_v4());
 
+        self.run_hooks(RepoEvent::PostCommit);
+
         Ok(())
     }
 
+    #[cfg_attr(
+        feature = "observability",
+        tracing::instrument(level = "debug", skip_all)
+    )]
     fn rollback(&mut self) -> crate::Result<()> {
         let state = self.state.read().unwrap();
         // Read the header from the previous commit from the data store.
@@ -695,20 +3039,31 @@ impl<K: Key> Commit for KeyRepo<K> {
         self.restore_header(header)
     }
 
+    #[cfg_attr(
+        feature = "observability",
+        tracing::instrument(level = "debug", skip_all)
+    )]
     fn clean(&mut self) -> crate::Result<()> {
         let mut state = self.state.write().unwrap();
 
+        // Cleaning may remove data which is only referenced by flushed changes, so we need to
+        // discard them first.
+        if let Some(flushed_header_id) = state.metadata.flushed_header_id.take() {
+            let serialized_metadata = state.metadata.to_super_block(&state.master_key);
+            let result = state
+                .store
+                .lock()
+                .unwrap()
+                .write_block(BlockKey::Super, &serialized_metadata)
+                .map_err(crate::Error::Store);
+            if let Err(error) = result {
+                state.metadata.flushed_header_id = Some(flushed_header_id);
+                return Err(error);
+            }
+        }
+
         // Read the header from the previous commit.
-        let encoded_header = state
-            .store
-            .lock()
-            .unwrap()
-            .read_block(BlockKey::Header(state.metadata.header_id))
-            .map_err(crate::Error::Store)?
-            .ok_or(crate::Error::Corrupt)?;
-        let serialized_header = state.decode_data(encoded_header.as_slice())?;
-        let previous_header: Header =
-            from_read(serialized_header.as_slice()).map_err(|_| crate::Error::Corrupt)?;
+        let previous_header = read_header(&state, state.metadata.header_id)?;
 
         // We need to find the set of blocks which are either currently referenced by the repository
         // or were referenced after the previous commit. It's important that we don't clean up
@@ -723,6 +3078,18 @@ impl<K: Key> Commit for KeyRepo<K> {
         let previous_referenced_blocks = previous_header.chunks.values().map(|info| info.block_id);
         referenced_blocks.extend(previous_referenced_blocks);
 
+        // Blocks which are referenced by checkpoints must also be preserved. This includes
+        // checkpoints which have been removed since the previous commit. The same goes for older
+        // commits which are being retained.
+        let checkpoint_headers = self
+            .checkpoints
+            .values()
+            .chain(previous_header.checkpoints.values())
+            .chain(state.metadata.previous_header_ids.iter())
+            .copied()
+            .collect::<HashSet<_>>();
+        referenced_blocks.extend(checkpoint_blocks(&state, &checkpoint_headers)?);
+
         // Remove all blocks from the data store which are unreferenced.
         match &state.metadata.config.packing {
             Packing::None => {
@@ -849,10 +3216,12 @@ impl<K: Key> Commit for KeyRepo<K> {
                     mem::swap(&mut previous_header.packs, &mut state.packs);
                     drop(previous_header);
 
-                    // Encode the serialized header and write it to the data store.
-                    let encoded_header = state.encode_data(serialized_header.as_slice())?;
+                    // Write the serialized header to the data store. This encodes the header, so
+                    // it must not be encoded here as well. The chunks in the previous header
+                    // haven't changed, so neither has its fingerprint.
+                    let fingerprint = state.metadata.fingerprint;
                     drop(state);
-                    self.write_serialized_header(encoded_header.as_slice())?;
+                    self.write_serialized_header(serialized_header.as_slice(), fingerprint)?;
                 }
             }
         }
@@ -865,7 +3234,11 @@ impl<K: Key> Commit for KeyRepo<K> {
                 .list_blocks(BlockType::Header)
                 .map_err(crate::Error::Store)?
                 .into_iter()
-                .filter(|&block_id| block_id != state.metadata.header_id);
+                .filter(|block_id| {
+                    *block_id != state.metadata.header_id
+                        && Some(*block_id) != state.metadata.upgrade_backup_id
+                        && !checkpoint_headers.contains(block_id)
+                });
             for block_id in unreferenced_headers {
                 store
                     .remove_block(BlockKey::Header(block_id))
@@ -873,6 +3246,8 @@ impl<K: Key> Commit for KeyRepo<K> {
             }
         }
 
+        self.run_hooks(RepoEvent::PostClean);
+
         Ok(())
     }
 }
@@ -896,27 +3271,25 @@ impl<K: Key> Unlock for KeyRepo<K> {
     fn context(&self) -> crate::Result<Vec<u8>> {
         let state = self.state.read().unwrap();
         let mut store = state.store.lock().unwrap();
-        let encrypted_context = store
-            .read_block(BlockKey::Lock(state.lock_id))
-            .map_err(crate::Error::Store)?
-            .ok_or(crate::Error::NotLocked)?;
-        state
-            .metadata
-            .config
-            .encryption
-            .decrypt(&encrypted_context, &state.master_key)
+        let contents = read_lock(
+            &mut *store,
+            &state.metadata.config.encryption,
+            &state.master_key,
+            state.lock_id,
+        )?
+        .ok_or(crate::Error::NotLocked)?;
+        Ok(contents.context)
     }
 
     fn update_context(&self, context: &[u8]) -> crate::Result<()> {
         let state = self.state.read().unwrap();
         let mut store = state.store.lock().unwrap();
-        let encrypted_context = state
-            .metadata
-            .config
-            .encryption
-            .encrypt(context, &state.master_key);
-        store
-            .write_block(BlockKey::Lock(state.lock_id), &encrypted_context)
-            .map_err(crate::Error::Store)
+        write_lock(
+            &mut *store,
+            &state.metadata.config.encryption,
+            &state.master_key,
+            state.lock_id,
+            &LockContents::new(context, state.lease),
+        )
     }
 }
@@ -0,0 +1,330 @@
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::sync::{Arc, Mutex, RwLock, RwLockWriteGuard};
+
+use static_assertions::assert_impl_all;
+
+use super::commit::Commit;
+use super::handle::ObjectHandle;
+use super::key::Key;
+use super::lock::Unlock;
+use super::metadata::{RepoInfo, RepoStats};
+use super::object::Object;
+use super::repository::KeyRepo;
+use super::savepoint::{RestoreSavepoint, Savepoint};
+use super::state::{InstanceId, RepoState};
+
+/// The number of shards the object map of a `SharedKeyRepo` is split into.
+const SHARD_COUNT: usize = 16;
+
+/// A shard of the object map of a `SharedKeyRepo`.
+type Shard<K> = HashMap<K, Arc<RwLock<ObjectHandle>>>;
+
+/// The state shared between all clones of a `SharedKeyRepo`.
+#[derive(Debug)]
+struct SharedState<K: Key> {
+    /// The state for the backing repository.
+    repo_state: Arc<RwLock<RepoState>>,
+
+    /// The backing repository.
+    ///
+    /// The object map of this repository is empty except while an operation which requires the
+    /// whole object map is in progress.
+    repo: Mutex<KeyRepo<K>>,
+
+    /// The object map for the current instance, split into shards by the hash of the key.
+    shards: Vec<RwLock<Shard<K>>>,
+
+    /// The hasher used to assign keys to shards.
+    hasher: RandomState,
+}
+
+/// A handle to a [`KeyRepo`] which can be shared between threads.
+///
+/// This value is created by [`KeyRepo::into_shared`]. It can be cheaply cloned, and each clone
+/// refers to the same repository. The object map is split into shards which are each protected by
+/// their own lock, so threads inserting, removing, and accessing objects with different keys
+/// rarely contend with each other.
+///
+/// Operations which affect the whole repository, like [`Commit::commit`] and
+/// [`RestoreSavepoint::savepoint`], lock every shard and block all other operations on the
+/// repository until they complete.
+///
+/// Once you're done using the repository from multiple threads, you can convert it back into a
+/// [`KeyRepo`] with [`try_unwrap`].
+///
+/// [`KeyRepo`]: crate::repo::key::KeyRepo
+/// [`KeyRepo::into_shared`]: crate::repo::key::KeyRepo::into_shared
+/// [`Commit::commit`]: crate::repo::Commit::commit
+/// [`RestoreSavepoint::savepoint`]: crate::repo::RestoreSavepoint::savepoint
+/// [`try_unwrap`]: crate::repo::key::SharedKeyRepo::try_unwrap
+#[derive(Debug)]
+pub struct SharedKeyRepo<K: Key>(Arc<SharedState<K>>);
+
+assert_impl_all!(SharedKeyRepo<()>: Send, Sync);
+
+impl<K: Key> Clone for SharedKeyRepo<K> {
+    fn clone(&self) -> Self {
+        Self(Arc::clone(&self.0))
+    }
+}
+
+impl<K: Key> SharedKeyRepo<K> {
+    /// Create a new `SharedKeyRepo` from the given `repo`.
+    pub(super) fn new(mut repo: KeyRepo<K>) -> Self {
+        let hasher = RandomState::new();
+        let mut shards = (0..SHARD_COUNT)
+            .map(|_| HashMap::new())
+            .collect::<Vec<Shard<K>>>();
+        for (key, handle) in repo.objects.drain() {
+            shards[shard_index(&hasher, &key)].insert(key, handle);
+        }
+
+        Self(Arc::new(SharedState {
+            repo_state: Arc::clone(&repo.state),
+            repo: Mutex::new(repo),
+            shards: shards.into_iter().map(RwLock::new).collect(),
+            hasher,
+        }))
+    }
+
+    /// Return the shard which contains the given `key`.
+    fn shard<Q>(&self, key: &Q) -> &RwLock<Shard<K>>
+    where
+        Q: Hash + ?Sized,
+    {
+        &self.0.shards[shard_index(&self.0.hasher, key)]
+    }
+
+    /// Call `block` with the backing repository with its full object map.
+    ///
+    /// This blocks all other operations on the repository until `block` returns.
+    fn with_repo<T>(&self, block: impl FnOnce(&mut KeyRepo<K>) -> T) -> T {
+        // Shards must always be locked before the repository to avoid deadlocks.
+        let mut shards = self
+            .0
+            .shards
+            .iter()
+            .map(|shard| shard.write().unwrap())
+            .collect::<Vec<RwLockWriteGuard<Shard<K>>>>();
+        let mut repo = self.0.repo.lock().unwrap();
+
+        repo.objects = shards.iter_mut().flat_map(|shard| shard.drain()).collect();
+        let result = block(&mut *repo);
+        for (key, handle) in repo.objects.drain() {
+            shards[shard_index(&self.0.hasher, &key)].insert(key, handle);
+        }
+
+        result
+    }
+
+    /// Return whether there is an object with the given `key` in this repository.
+    pub fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.shard(key).read().unwrap().contains_key(key)
+    }
+
+    /// Add a new object with the given `key` to the repository and return it.
+    ///
+    /// If another object with the same `key` already exists, it is replaced.
+    pub fn insert(&self, key: K) -> Object {
+        let mut shard = self.shard(&key).write().unwrap();
+        let mut repo = self.0.repo.lock().unwrap();
+        if let Some(old_handle) = shard.remove(&key) {
+            repo.remove_handle(&old_handle.read().unwrap());
+        }
+        let handle = ObjectHandle {
+            id: repo.handle_table.next(),
+            extents: Vec::new(),
+            attributes: BTreeMap::new(),
+        };
+        drop(repo);
+
+        let handle = shard
+            .entry(key)
+            .or_insert_with(|| Arc::new(RwLock::new(handle)));
+        Object::new(&self.0.repo_state, handle)
+    }
+
+    /// Remove the object with the given `key` from the repository.
+    ///
+    /// This returns `true` if the object was removed or `false` if it didn't exist.
+    ///
+    /// See [`KeyRepo::remove`] for details.
+    ///
+    /// [`KeyRepo::remove`]: crate::repo::key::KeyRepo::remove
+    pub fn remove<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        let mut shard = self.shard(key).write().unwrap();
+        let handle = match shard.remove(key) {
+            Some(handle) => handle,
+            None => return false,
+        };
+        let handle_guard = handle.read().unwrap();
+        self.0.repo.lock().unwrap().remove_handle(&handle_guard);
+        true
+    }
+
+    /// Return an object for reading and writing the object with the given `key`.
+    ///
+    /// This returns `None` if there is no object with the given `key` in the repository.
+    pub fn object<Q>(&self, key: &Q) -> Option<Object>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        let shard = self.shard(key).read().unwrap();
+        let handle = shard.get(key)?;
+        Some(Object::new(&self.0.repo_state, handle))
+    }
+
+    /// Return a list of the keys of all the objects in this repository.
+    ///
+    /// Because other threads may be modifying the repository concurrently, the returned list is a
+    /// snapshot of the keys at the time this method was called.
+    pub fn keys(&self) -> Vec<K> {
+        self.0
+            .shards
+            .iter()
+            .flat_map(|shard| shard.read().unwrap().keys().cloned().collect::<Vec<_>>())
+            .collect()
+    }
+
+    /// Copy the object at `source` to `dest`.
+    ///
+    /// See [`KeyRepo::copy`] for details.
+    ///
+    /// [`KeyRepo::copy`]: crate::repo::key::KeyRepo::copy
+    pub fn copy<Q>(&self, source: &Q, dest: K) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        // Copying between shards requires locking two shards at once, so we lock the whole
+        // repository instead to avoid deadlocks.
+        self.with_repo(|repo| repo.copy(source, dest))
+    }
+
+    /// Verify the integrity of all the data in the current instance of the repository.
+    ///
+    /// This returns the set of keys of objects in the current instance which are corrupt.
+    ///
+    /// See [`KeyRepo::verify`] for details.
+    ///
+    /// # Errors
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    ///
+    /// [`KeyRepo::verify`]: crate::repo::key::KeyRepo::verify
+    pub fn verify(&self) -> crate::Result<HashSet<K>> {
+        self.with_repo(|repo| Ok(repo.verify()?.into_iter().cloned().collect()))
+    }
+
+    /// Delete all data in the current instance of the repository.
+    ///
+    /// See [`KeyRepo::clear_instance`] for details.
+    ///
+    /// [`KeyRepo::clear_instance`]: crate::repo::key::KeyRepo::clear_instance
+    pub fn clear_instance(&self) {
+        self.with_repo(|repo| repo.clear_instance())
+    }
+
+    /// Return this repository's current instance ID.
+    pub fn instance(&self) -> InstanceId {
+        self.0.repo.lock().unwrap().instance()
+    }
+
+    /// Compute statistics about the repository.
+    ///
+    /// See [`KeyRepo::stats`] for details.
+    ///
+    /// [`KeyRepo::stats`]: crate::repo::key::KeyRepo::stats
+    pub fn stats(&self) -> RepoStats {
+        self.with_repo(|repo| repo.stats())
+    }
+
+    /// Return information about the repository.
+    pub fn info(&self) -> RepoInfo {
+        self.0.repo_state.read().unwrap().metadata.to_info()
+    }
+
+    /// Convert this handle back into a [`KeyRepo`].
+    ///
+    /// If there are other clones of this handle, this returns `Err` containing this handle.
+    ///
+    /// [`KeyRepo`]: crate::repo::key::KeyRepo
+    pub fn try_unwrap(self) -> Result<KeyRepo<K>, Self> {
+        let state = Arc::try_unwrap(self.0).map_err(Self)?;
+        let mut repo = state.repo.into_inner().unwrap();
+        repo.objects = state
+            .shards
+            .into_iter()
+            .flat_map(|shard| shard.into_inner().unwrap())
+            .collect();
+        Ok(repo)
+    }
+}
+
+/// Return the index of the shard which contains the given `key`.
+fn shard_index<Q: Hash + ?Sized>(hasher: &RandomState, key: &Q) -> usize {
+    let mut state = hasher.build_hasher();
+    key.hash(&mut state);
+    (state.finish() % SHARD_COUNT as u64) as usize
+}
+
+impl<K: Key> Commit for SharedKeyRepo<K> {
+    fn commit(&mut self) -> crate::Result<()> {
+        self.with_repo(|repo| repo.commit())
+    }
+
+    fn rollback(&mut self) -> crate::Result<()> {
+        self.with_repo(|repo| repo.rollback())
+    }
+
+    fn clean(&mut self) -> crate::Result<()> {
+        self.with_repo(|repo| repo.clean())
+    }
+}
+
+impl<K: Key> RestoreSavepoint for SharedKeyRepo<K> {
+    type Restore = <KeyRepo<K> as RestoreSavepoint>::Restore;
+
+    fn savepoint(&mut self) -> crate::Result<Savepoint> {
+        self.with_repo(|repo| repo.savepoint())
+    }
+
+    fn start_restore(&mut self, savepoint: &Savepoint) -> crate::Result<Self::Restore> {
+        self.with_repo(|repo| repo.start_restore(savepoint))
+    }
+
+    fn finish_restore(&mut self, restore: Self::Restore) -> bool {
+        self.with_repo(|repo| repo.finish_restore(restore))
+    }
+}
+
+impl<K: Key> Unlock for SharedKeyRepo<K> {
+    fn unlock(&self) -> crate::Result<()> {
+        self.0.repo.lock().unwrap().unlock()
+    }
+
+    fn is_locked(&self) -> crate::Result<bool> {
+        self.0.repo.lock().unwrap().is_locked()
+    }
+
+    fn context(&self) -> crate::Result<Vec<u8>> {
+        self.0.repo.lock().unwrap().context()
+    }
+
+    fn update_context(&self, context: &[u8]) -> crate::Result<()> {
+        self.0.repo.lock().unwrap().update_context(context)
+    }
+}
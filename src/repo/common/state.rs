@@ -1,7 +1,9 @@
 use std::collections::{HashMap, HashSet};
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
 
 use cdchunking::ChunkerImpl;
+use secrecy::ExposeSecret;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
@@ -10,11 +12,22 @@ use crate::store::{BlockId, DataStore};
 use super::chunk_store::StoreState;
 use super::chunking::IncrementalChunker;
 use super::encryption::EncryptionKey;
+use super::fingerprint::{ChunkTree, Fingerprint};
 use super::handle::{Chunk, Extent, HandleId, ObjectHandle};
-use super::lock::{unlock_store, Lock, LockTable};
+use super::hooks::Hooks;
+use super::lock::{unlock_store, Heartbeat, Lock, LockTable};
 use super::metadata::RepoMetadata;
+#[cfg(feature = "observability")]
+use super::metrics::Metrics;
+use super::object::Access;
 use super::open_repo::VersionId;
 
+/// The BLAKE3 context string for deriving the key used to compute the IDs of convergent blocks.
+const CONVERGENT_BLOCK_ID_CONTEXT: &str = "acid-store 2023-07-01 convergent block id";
+
+/// The BLAKE3 context string for deriving the key used to compute the keys of convergent blocks.
+const CONVERGENT_BLOCK_KEY_CONTEXT: &str = "acid-store 2023-07-01 convergent block key";
+
 /// Information about a chunk in a repository.
 #[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub struct ChunkInfo {
@@ -23,6 +36,11 @@ pub struct ChunkInfo {
 
     /// The IDs of objects which reference this chunk.
     pub references: HashSet<HandleId>,
+
+    /// The size of this chunk after it was compressed, or `None` if it was written by an older
+    /// version of this library.
+    #[serde(default)]
+    pub compressed_size: Option<u32>,
 }
 
 /// The location of a block in a pack.
@@ -88,13 +106,78 @@ pub struct InstanceInfo {
     /// This object handle contains a serialized map of object IDs to object handles for that
     /// instance.
     pub objects: ObjectHandle,
+
+    /// The quota for this instance, which overrides the quota in the repository config.
+    #[serde(default)]
+    pub quota: Option<u64>,
+
+    /// The human-readable name of this instance, if it has one.
+    #[serde(default)]
+    pub name: Option<String>,
+
+    /// The time this instance was created, or `None` if it was created by an older version of
+    /// this library.
+    #[serde(default)]
+    pub created: Option<SystemTime>,
+
+    /// A map of the IDs of objects in this instance to the times at which they expire.
+    #[serde(default)]
+    pub expirations: HashMap<HandleId, SystemTime>,
+}
+
+/// Information about an instance of a repository.
+///
+/// This is returned by [`KeyRepo::list_instances`].
+///
+/// [`KeyRepo::list_instances`]: crate::repo::key::KeyRepo::list_instances
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InstanceSummary {
+    pub(super) id: InstanceId,
+    pub(super) version_id: VersionId,
+    pub(super) name: Option<String>,
+    pub(super) created: Option<SystemTime>,
+}
+
+impl InstanceSummary {
+    /// The ID of this instance.
+    pub fn id(&self) -> InstanceId {
+        self.id
+    }
+
+    /// The version ID of the repository type stored in this instance.
+    ///
+    /// This corresponds to the [`OpenRepo::VERSION_ID`] of the repository which was created in
+    /// this instance.
+    ///
+    /// [`OpenRepo::VERSION_ID`]: crate::repo::OpenRepo::VERSION_ID
+    pub fn version_id(&self) -> VersionId {
+        self.version_id
+    }
+
+    /// The human-readable name of this instance, if it has one.
+    ///
+    /// Instances can be named with [`KeyRepo::name_instance`].
+    ///
+    /// [`KeyRepo::name_instance`]: crate::repo::key::KeyRepo::name_instance
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// The time this instance was created according to the system clock.
+    ///
+    /// This returns `None` if the instance was created by an older version of this library.
+    pub fn created(&self) -> Option<SystemTime> {
+        self.created
+    }
 }
 
 /// The state associated with a `KeyRepo`.
 #[derive(Debug)]
 pub struct RepoState {
     /// The data store which backs this repository.
-    pub store: Mutex<Box<dyn DataStore>>,
+    ///
+    /// This is shared with the thread which refreshes the lease on the lock, if there is one.
+    pub store: Arc<Mutex<Box<dyn DataStore>>>,
 
     /// The metadata for the repository.
     pub metadata: RepoMetadata,
@@ -102,6 +185,11 @@ pub struct RepoState {
     /// A map of chunk hashes to information about them.
     pub chunks: HashMap<Chunk, ChunkInfo>,
 
+    /// A Merkle tree over `chunks` used to compute the repository's fingerprint.
+    ///
+    /// This must be invalidated whenever a chunk is added to or removed from `chunks`.
+    pub chunk_tree: ChunkTree,
+
     /// A map of block IDs to their locations in packs.
     pub packs: HashMap<BlockId, Vec<PackIndex>>,
 
@@ -111,10 +199,107 @@ pub struct RepoState {
     /// The master encryption key for the repository.
     pub master_key: EncryptionKey,
 
+    /// The key used to derive block IDs and keys for convergent encryption, if it's enabled.
+    pub convergence_key: Option<EncryptionKey>,
+
+    /// The size of the header from the most recent commit before it was compressed and encrypted.
+    pub apparent_header_size: u64,
+
+    /// The size of the header from the most recent commit as it is stored in the data store.
+    pub header_size: u64,
+
+    /// The apparent size of the current instance.
+    pub instance_size: u64,
+
+    /// The quota for the current instance, or `None` if there is no limit.
+    pub instance_quota: Option<u64>,
+
     /// The `BlockId` of the key which stores the lock on the repository.
     ///
     /// This is used to release the lock when the repository is dropped.
     pub lock_id: BlockId,
+
+    /// The TTL of the lease on the lock, or `None` if the lock doesn't have a lease.
+    pub lease: Option<Duration>,
+
+    /// The thread which refreshes the lease on the lock, if there is one.
+    ///
+    /// This stops once the lock is released.
+    pub heartbeat: Option<Heartbeat>,
+
+    /// The hooks which are called when events happen in the repository.
+    pub hooks: Mutex<Hooks>,
+
+    /// The callback for collecting metrics about the repository, if there is one.
+    #[cfg(feature = "observability")]
+    pub metrics: Option<Arc<dyn Metrics>>,
+}
+
+impl RepoState {
+    /// Return a new ID for the block which stores the given `chunk`.
+    ///
+    /// If convergent encryption is enabled, this is derived from the contents of the chunk, so
+    /// repositories with the same convergence secret store the same chunk in the same block.
+    /// Otherwise, this is random.
+    ///
+    /// This uses a different key than `block_key`, so the key for a block can't be derived from
+    /// its ID.
+    pub fn chunk_block_id(&self, chunk: &Chunk) -> BlockId {
+        let mut hasher = match self.convergence_hasher(CONVERGENT_BLOCK_ID_CONTEXT) {
+            Some(hasher) => hasher,
+            None => return Uuid::new_v4().into(),
+        };
+        hasher.update(&chunk.size.to_le_bytes());
+        hasher.update(&chunk.hash);
+        let mut id_bytes = [0u8; 16];
+        hasher.finalize_xof().fill(&mut id_bytes);
+        uuid::Builder::from_random_bytes(id_bytes)
+            .into_uuid()
+            .into()
+    }
+
+    /// Return the key for encrypting the block which stores the given `chunk`.
+    ///
+    /// This is derived from the hash of the chunk, so it can only be computed by someone who knows
+    /// both the convergence secret and the contents of the chunk.
+    ///
+    /// This returns `None` if convergent encryption is disabled, in which case blocks are
+    /// encrypted with the master key.
+    pub fn block_key(&self, chunk: &Chunk) -> Option<EncryptionKey> {
+        let mut hasher = self.convergence_hasher(CONVERGENT_BLOCK_KEY_CONTEXT)?;
+        hasher.update(&chunk.hash);
+        let mut key_bytes = vec![0u8; self.metadata.config.encryption.key_size()];
+        hasher.finalize_xof().fill(&mut key_bytes);
+        Some(EncryptionKey::new(key_bytes))
+    }
+
+    /// Return the size of the compressed data in the given `encoded_block`.
+    pub fn compressed_size(&self, encoded_block: &[u8]) -> u32 {
+        (encoded_block.len() - self.metadata.config.encryption.overhead()) as u32
+    }
+
+    /// Return the fingerprint of the chunks currently in the repository.
+    pub fn fingerprint(&mut self) -> Fingerprint {
+        self.chunk_tree.fingerprint(self.chunks.keys())
+    }
+
+    /// Return a hasher keyed with a subkey of the convergence key for the given `context`, or
+    /// `None` if convergent encryption is disabled.
+    fn convergence_hasher(&self, context: &str) -> Option<blake3::Hasher> {
+        let convergence_key = self.convergence_key.as_ref()?;
+        let subkey = blake3::derive_key(context, convergence_key.expose_secret());
+        Some(blake3::Hasher::new_keyed(&subkey))
+    }
+}
+
+#[cfg(feature = "observability")]
+impl RepoState {
+    /// Call `report` with the metrics callback for the repository, if there is one.
+    pub fn report_metrics(&self, report: impl FnOnce(&dyn Metrics)) {
+        if let Some(metrics) = &self.metrics {
+            report(metrics.as_ref());
+        }
+    }
 }
 
 impl Drop for RepoState {
@@ -170,6 +355,12 @@ pub struct ObjectState {
     /// The list of chunks which have been written in the current transaction.
     pub new_chunks: Vec<Chunk>,
 
+    /// Data which has been written in the current transaction but not yet passed to the chunker.
+    pub write_buffer: Vec<u8>,
+
+    /// The number of bytes to accumulate in `write_buffer` before passing them to the chunker.
+    pub write_buffer_size: usize,
+
     /// The seek position when the transaction was started.
     pub start_position: SeekPosition,
 
@@ -187,6 +378,12 @@ pub struct ObjectState {
     /// A pre-allocated buffer of null bytes to read from when reading a hole.
     pub hole_buffer: Vec<u8>,
 
+    /// How the object is expected to be read from.
+    pub access: Access,
+
+    /// The contents of chunks which have been fetched ahead of time but not yet read from.
+    pub prefetched: HashMap<Chunk, Vec<u8>>,
+
     /// A lock representing the current transaction if there is one.
     pub transaction_lock: Option<Lock<HandleId>>,
 
@@ -200,13 +397,123 @@ impl ObjectState {
         Self {
             chunker: IncrementalChunker::new(chunker),
             new_chunks: Vec::new(),
+            write_buffer: Vec::new(),
+            write_buffer_size: 0,
             start_position: SeekPosition::Empty,
             position: 0,
             buffered_chunk: None,
             read_buffer: Vec::new(),
             hole_buffer: Vec::new(),
+            access: Access::default(),
+            prefetched: HashMap::new(),
             transaction_lock: None,
             store_state: StoreState::new(),
         }
     }
 }
+
+#[cfg(all(test, feature = "encryption"))]
+mod tests {
+    use std::io::Write;
+
+    use secrecy::ExposeSecret;
+    use spectral::prelude::*;
+
+    use super::super::handle::{chunk_hash, Chunk};
+    use crate::repo::key::KeyRepo;
+    use crate::repo::{Commit, Encryption, OpenMode, OpenOptions, Packing};
+    use crate::store::{BlockKey, DataStore, MemoryConfig, OpenStore};
+
+    const TEST_DATA: &[u8] = b"convergent test data";
+
+    /// Create a repository with convergent encryption and write `TEST_DATA` to it.
+    fn create_convergent_repo(
+        config: &MemoryConfig,
+        secret: &[u8],
+        password: &[u8],
+    ) -> KeyRepo<String> {
+        let mut repo: KeyRepo<String> = OpenOptions::new()
+            .encryption(Encryption::XChaCha20Poly1305)
+            .packing(Packing::None)
+            .convergent_encryption(true)
+            .convergence_secret(secret)
+            .password(password)
+            .mode(OpenMode::CreateNew)
+            .open(config)
+            .unwrap();
+        let mut object = repo.insert(String::from("test"));
+        object.write_all(TEST_DATA).unwrap();
+        object.commit().unwrap();
+        drop(object);
+        repo.commit().unwrap();
+        repo
+    }
+
+    fn test_chunk() -> Chunk {
+        Chunk {
+            size: TEST_DATA.len() as u32,
+            hash: chunk_hash(TEST_DATA),
+        }
+    }
+
+    #[test]
+    fn same_secret_stores_chunk_in_same_block() {
+        let first_config = MemoryConfig::new();
+        let second_config = MemoryConfig::new();
+        let first_repo = create_convergent_repo(&first_config, b"secret", b"first password");
+        let second_repo = create_convergent_repo(&second_config, b"secret", b"second password");
+        let first_state = first_repo.state.read().unwrap();
+        let second_state = second_repo.state.read().unwrap();
+        let chunk = test_chunk();
+
+        let first_id = first_state.chunks[&chunk].block_id;
+        let second_id = second_state.chunks[&chunk].block_id;
+        assert_that!(first_id).is_equal_to(second_id);
+        assert_that!(first_state.chunk_block_id(&chunk)).is_equal_to(first_id);
+
+        let first_block = first_config
+            .open()
+            .unwrap()
+            .read_block(BlockKey::Data(first_id))
+            .unwrap();
+        let second_block = second_config
+            .open()
+            .unwrap()
+            .read_block(BlockKey::Data(second_id))
+            .unwrap();
+        assert_that!(first_block).is_some();
+        assert_that!(first_block).is_equal_to(second_block);
+    }
+
+    #[test]
+    fn different_secret_stores_chunk_in_different_block() {
+        let first_repo = create_convergent_repo(&MemoryConfig::new(), b"first secret", b"password");
+        let second_repo =
+            create_convergent_repo(&MemoryConfig::new(), b"second secret", b"password");
+        let first_state = first_repo.state.read().unwrap();
+        let second_state = second_repo.state.read().unwrap();
+        let chunk = test_chunk();
+
+        assert_that!(first_state.chunks[&chunk].block_id)
+            .is_not_equal_to(second_state.chunks[&chunk].block_id);
+    }
+
+    #[test]
+    fn block_key_is_not_derived_from_block_id() {
+        let repo = create_convergent_repo(&MemoryConfig::new(), b"secret", b"password");
+        let state = repo.state.read().unwrap();
+        let chunk = test_chunk();
+        let other_chunk = Chunk {
+            size: chunk.size + 1,
+            hash: chunk.hash,
+        };
+
+        // Chunks with the same hash share a key but not a block ID, so the key is a function of
+        // the chunk's hash and not its block ID.
+        let block_key = state.block_key(&chunk).unwrap();
+        let other_block_key = state.block_key(&other_chunk).unwrap();
+        assert_that!(state.chunk_block_id(&chunk))
+            .is_not_equal_to(state.chunk_block_id(&other_chunk));
+        assert_that!(block_key.expose_secret()).is_equal_to(other_block_key.expose_secret());
+    }
+}
@@ -0,0 +1,220 @@
+use std::collections::VecDeque;
+
+use super::commit::Commit;
+use super::savepoint::{RestoreSavepoint, Savepoint};
+
+/// A wrapper over a repository which records a history of changes that can be undone and redone.
+///
+/// Each change to the wrapped repository is made through [`modify`], which automatically captures
+/// a [`Savepoint`] before the change is made. These savepoints are stored in a bounded history, and
+/// [`undo`] and [`redo`] can be used to move backwards and forwards through it. This is a building
+/// block for editor-like applications.
+///
+/// The history holds at most `capacity` changes. Once it's full, the oldest change is discarded
+/// and can no longer be undone.
+///
+/// Because committing or rolling back a repository invalidates all its savepoints, the history is
+/// cleared when changes are committed or rolled back through this wrapper.
+///
+/// # Examples
+/// ```
+/// # use std::io::Write;
+/// # use acid_store::store::MemoryConfig;
+/// # use acid_store::repo::{OpenOptions, OpenMode, UndoRepo, key::KeyRepo};
+/// #
+/// # let repo: KeyRepo<String> = OpenOptions::new()
+/// #     .mode(OpenMode::CreateNew)
+/// #     .open(&MemoryConfig::new())
+/// #     .unwrap();
+/// let mut repo = UndoRepo::new(repo, 100);
+///
+/// repo.modify(|repo| {
+///     repo.insert(String::from("test"));
+///     Ok(())
+/// }).unwrap();
+/// assert!(repo.repo().contains("test"));
+///
+/// assert!(repo.undo().unwrap());
+/// assert!(!repo.repo().contains("test"));
+///
+/// assert!(repo.redo().unwrap());
+/// assert!(repo.repo().contains("test"));
+/// ```
+///
+/// [`modify`]: crate::repo::UndoRepo::modify
+/// [`Savepoint`]: crate::repo::Savepoint
+/// [`undo`]: crate::repo::UndoRepo::undo
+/// [`redo`]: crate::repo::UndoRepo::redo
+#[derive(Debug)]
+pub struct UndoRepo<R: RestoreSavepoint> {
+    /// The wrapped repository.
+    repo: R,
+
+    /// The savepoints which can be restored by undoing changes, from oldest to newest.
+    undo_history: VecDeque<Savepoint>,
+
+    /// The savepoints which can be restored by redoing changes, from newest to oldest.
+    redo_history: Vec<Savepoint>,
+
+    /// The maximum number of changes which can be undone.
+    capacity: usize,
+}
+
+impl<R: RestoreSavepoint> UndoRepo<R> {
+    /// Wrap the given `repo`, keeping a history of at most `capacity` changes.
+    pub fn new(repo: R, capacity: usize) -> Self {
+        Self {
+            repo,
+            undo_history: VecDeque::with_capacity(capacity),
+            redo_history: Vec::new(),
+            capacity,
+        }
+    }
+
+    /// Return a reference to the wrapped repository.
+    pub fn repo(&self) -> &R {
+        &self.repo
+    }
+
+    /// Consume this wrapper and return the wrapped repository.
+    pub fn into_repo(self) -> R {
+        self.repo
+    }
+
+    /// Make a change to the repository which can later be undone.
+    ///
+    /// This captures a savepoint and then calls `change` with the wrapped repository. If `change`
+    /// returns `Ok`, the change is added to the history and any changes which were previously undone
+    /// can no longer be redone. If `change` returns `Err`, the repository is restored to the state
+    /// it was in before `change` was called and the history is unchanged.
+    ///
+    /// # Errors
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    ///
+    /// Any error returned by `change` is also returned.
+    pub fn modify<T>(
+        &mut self,
+        change: impl FnOnce(&mut R) -> crate::Result<T>,
+    ) -> crate::Result<T> {
+        let savepoint = self.repo.savepoint()?;
+        let restore = self.repo.start_restore(&savepoint)?;
+
+        match change(&mut self.repo) {
+            Ok(result) => {
+                if self.capacity == 0 {
+                    return Ok(result);
+                }
+                if self.undo_history.len() == self.capacity {
+                    self.undo_history.pop_front();
+                }
+                self.undo_history.push_back(savepoint);
+                self.redo_history.clear();
+                Ok(result)
+            }
+            Err(error) => {
+                self.repo.finish_restore(restore);
+                Err(error)
+            }
+        }
+    }
+
+    /// Restore `target` and return a savepoint representing the state before it was restored.
+    fn swap(&mut self, target: &Savepoint) -> crate::Result<Savepoint> {
+        let current = self.repo.savepoint()?;
+        let restore = self.repo.start_restore(target)?;
+        if !self.repo.finish_restore(restore) {
+            return Err(crate::Error::InvalidSavepoint);
+        }
+        Ok(current)
+    }
+
+    /// Undo the most recent change to the repository.
+    ///
+    /// This returns `true` if a change was undone or `false` if there were no changes to undo.
+    ///
+    /// Undoing a change invalidates all [`Object`] and [`ReadOnlyObject`] instances associated with
+    /// the repository.
+    ///
+    /// # Errors
+    /// - `Error::InvalidSavepoint`: The history has been invalidated, such as by committing changes
+    /// to the wrapped repository directly.
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    ///
+    /// [`Object`]: crate::repo::Object
+    /// [`ReadOnlyObject`]: crate::repo::ReadOnlyObject
+    pub fn undo(&mut self) -> crate::Result<bool> {
+        let target = match self.undo_history.back() {
+            Some(savepoint) => savepoint.clone(),
+            None => return Ok(false),
+        };
+        let current = self.swap(&target)?;
+        self.undo_history.pop_back();
+        self.redo_history.push(current);
+        Ok(true)
+    }
+
+    /// Redo the most recently undone change to the repository.
+    ///
+    /// This returns `true` if a change was redone or `false` if there were no changes to redo.
+    ///
+    /// Redoing a change invalidates all [`Object`] and [`ReadOnlyObject`] instances associated with
+    /// the repository.
+    ///
+    /// # Errors
+    /// - `Error::InvalidSavepoint`: The history has been invalidated, such as by committing changes
+    /// to the wrapped repository directly.
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    ///
+    /// [`Object`]: crate::repo::Object
+    /// [`ReadOnlyObject`]: crate::repo::ReadOnlyObject
+    pub fn redo(&mut self) -> crate::Result<bool> {
+        let target = match self.redo_history.last() {
+            Some(savepoint) => savepoint.clone(),
+            None => return Ok(false),
+        };
+        let current = self.swap(&target)?;
+        self.redo_history.pop();
+        self.undo_history.push_back(current);
+        Ok(true)
+    }
+
+    /// Return the number of changes which can currently be undone.
+    pub fn undo_len(&self) -> usize {
+        self.undo_history.len()
+    }
+
+    /// Return the number of changes which can currently be redone.
+    pub fn redo_len(&self) -> usize {
+        self.redo_history.len()
+    }
+
+    /// Discard the history of changes without modifying the repository.
+    pub fn clear_history(&mut self) {
+        self.undo_history.clear();
+        self.redo_history.clear();
+    }
+}
+
+impl<R: RestoreSavepoint + Commit> Commit for UndoRepo<R> {
+    fn commit(&mut self) -> crate::Result<()> {
+        self.repo.commit()?;
+        self.clear_history();
+        Ok(())
+    }
+
+    fn rollback(&mut self) -> crate::Result<()> {
+        self.repo.rollback()?;
+        self.clear_history();
+        Ok(())
+    }
+
+    fn clean(&mut self) -> crate::Result<()> {
+        self.repo.clean()
+    }
+}
@@ -0,0 +1,308 @@
+use rmp_serde::{from_read, to_vec};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::metadata::RepoMetadata;
+use super::open_options::VERSION_ID;
+use crate::store::{BlockId, BlockKey, BlockType, DataStore, OpenStore};
+
+/// A migration which upgrades the repository metadata from one format version to the next.
+#[derive(Debug, Clone, Copy)]
+pub struct Migration {
+    /// The format version this migration upgrades from.
+    pub from: Uuid,
+
+    /// The format version this migration upgrades to.
+    pub to: Uuid,
+
+    /// A function which accepts the serialized metadata in the `from` format and returns the
+    /// serialized metadata in the `to` format.
+    pub upgrade: fn(&[u8]) -> crate::Result<Vec<u8>>,
+}
+
+/// The migrations between previous format versions and the current one.
+///
+/// Every format change so far has been backwards-compatible, so there is nothing to migrate yet.
+/// When `VERSION_ID` changes, a migration from the previous version must be added here.
+pub const MIGRATIONS: &[Migration] = &[];
+
+/// A copy of the repository metadata from before it was upgraded.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct UpgradeBackup {
+    /// The format version of the repository before it was upgraded.
+    version: Uuid,
+
+    /// The serialized metadata from before the repository was upgraded.
+    metadata: Vec<u8>,
+}
+
+/// Convert the `serialized_metadata` from the format `version` to the current format.
+///
+/// # Errors
+/// - `Error::UnsupportedRepo`: There is no way to upgrade from `version`.
+/// - `Error::Corrupt`: The metadata could not be upgraded.
+pub fn upgrade_metadata(
+    migrations: &[Migration],
+    mut version: Uuid,
+    mut serialized_metadata: Vec<u8>,
+) -> crate::Result<Vec<u8>> {
+    // Each migration can only be applied once, which guarantees this terminates.
+    for _ in 0..=migrations.len() {
+        if version == VERSION_ID {
+            return Ok(serialized_metadata);
+        }
+        let migration = migrations
+            .iter()
+            .find(|migration| migration.from == version)
+            .ok_or(crate::Error::UnsupportedRepo)?;
+        serialized_metadata = (migration.upgrade)(&serialized_metadata)?;
+        version = migration.to;
+    }
+
+    Err(crate::Error::UnsupportedRepo)
+}
+
+/// Read the format version of the repository in `store`.
+///
+/// # Errors
+/// - `Error::NotFound`: There is no repository in the data store.
+/// - `Error::Corrupt`: The format version is invalid.
+/// - `Error::Store`: An error occurred with the data store.
+pub fn read_version(store: &mut impl DataStore) -> crate::Result<Uuid> {
+    let serialized_version = store
+        .read_block(BlockKey::Version)
+        .map_err(crate::Error::Store)?
+        .ok_or(crate::Error::NotFound)?;
+    Uuid::from_slice(serialized_version.as_slice()).map_err(|_| crate::Error::Corrupt)
+}
+
+/// Return whether the metadata in `serialized_metadata` was already upgraded from `version`.
+///
+/// This happens when an upgrade is interrupted after the super block is written but before the
+/// version block is written.
+fn is_partially_upgraded(
+    store: &mut impl DataStore,
+    version: Uuid,
+    serialized_metadata: &[u8],
+) -> crate::Result<bool> {
+    let backup_id = match from_read::<_, RepoMetadata>(serialized_metadata) {
+        Ok(RepoMetadata {
+            upgrade_backup_id: Some(backup_id),
+            ..
+        }) => backup_id,
+        _ => return Ok(false),
+    };
+    let backup = match store
+        .read_block(BlockKey::Header(backup_id))
+        .map_err(crate::Error::Store)?
+    {
+        Some(data) => data,
+        None => return Ok(false),
+    };
+    Ok(matches!(
+        from_read::<_, UpgradeBackup>(backup.as_slice()),
+        Ok(backup) if backup.version == version
+    ))
+}
+
+/// Upgrade the repository in `store` to the current format version using `migrations`.
+pub fn upgrade_store(store: &mut impl DataStore, migrations: &[Migration]) -> crate::Result<bool> {
+    let version = read_version(store)?;
+    if version == VERSION_ID {
+        return Ok(false);
+    }
+
+    if !store
+        .list_blocks(BlockType::Lock)
+        .map_err(crate::Error::Store)?
+        .is_empty()
+    {
+        return Err(crate::Error::Locked);
+    }
+
+    let serialized_metadata = store
+        .read_block(BlockKey::Super)
+        .map_err(crate::Error::Store)?
+        .ok_or(crate::Error::Corrupt)?;
+
+    if !is_partially_upgraded(store, version, &serialized_metadata)? {
+        let upgraded_metadata = upgrade_metadata(migrations, version, serialized_metadata.clone())?;
+        let mut metadata: RepoMetadata =
+            from_read(upgraded_metadata.as_slice()).map_err(|_| crate::Error::Corrupt)?;
+
+        // Back up the old metadata before overwriting it so the upgrade can be undone by hand.
+        let backup_id: BlockId = Uuid::new_v4().into();
+        let backup = UpgradeBackup {
+            version,
+            metadata: serialized_metadata,
+        };
+        store
+            .write_block(
+                BlockKey::Header(backup_id),
+                &to_vec(&backup).expect("Could not serialize the upgrade backup."),
+            )
+            .map_err(crate::Error::Store)?;
+
+        metadata.upgrade_backup_id = Some(backup_id);
+        store
+            .write_block(
+                BlockKey::Super,
+                &to_vec(&metadata).expect("Could not serialize the repository metadata."),
+            )
+            .map_err(crate::Error::Store)?;
+    }
+
+    // The version block is written last so that an interrupted upgrade can be resumed.
+    store
+        .write_block(BlockKey::Version, VERSION_ID.as_bytes())
+        .map_err(crate::Error::Store)?;
+
+    Ok(true)
+}
+
+/// Upgrade the repository in a data store to the current format version in place.
+///
+/// This accepts the `config` used to open the data store. This returns `true` if the repository
+/// was upgraded or `false` if it already used the current format version. You can check whether a
+/// repository needs to be upgraded without modifying it using [`RepoInfo::needs_upgrade`].
+///
+/// Before the repository metadata is overwritten, a copy of it is saved to a backup header in the
+/// data store, which is kept by [`clean`]. If the upgrade is interrupted, calling this function
+/// again completes it.
+///
+/// The repository must not be open while it is being upgraded. Older versions of this library
+/// will not be able to open the repository once it has been upgraded.
+///
+/// # Errors
+/// - `Error::NotFound`: There is no repository in the data store.
+/// - `Error::Locked`: The repository is locked.
+/// - `Error::UnsupportedRepo`: There is no way to upgrade from the repository's format version.
+/// - `Error::Corrupt`: The repository is corrupt. This is most likely unrecoverable.
+/// - `Error::UnsupportedStore`: The data store is an unsupported format. This can happen if
+/// the serialized data format changed or if the storage represented by this value does not
+/// contain a valid data store.
+/// - `Error::Store`: An error occurred with the data store.
+/// - `Error::Io`: An I/O error occurred.
+///
+/// [`RepoInfo::needs_upgrade`]: crate::repo::RepoInfo::needs_upgrade
+/// [`clean`]: crate::repo::Commit::clean
+pub fn upgrade_repo(config: &impl OpenStore) -> crate::Result<bool> {
+    let mut store = config.open()?;
+    upgrade_store(&mut store, MIGRATIONS)
+}
+
+#[cfg(test)]
+mod tests {
+    use spectral::prelude::*;
+    use uuid::{uuid, Uuid};
+
+    use super::{upgrade_store, Migration, UpgradeBackup, VERSION_ID};
+    use crate::repo::common::metadata::{peek_info_store, read_info, RepoMetadata};
+    use crate::repo::key::KeyRepo;
+    use crate::repo::{Commit, OpenMode, OpenOptions};
+    use crate::store::{BlockKey, DataStore, MemoryConfig, MemoryStore, OpenStore};
+
+    const OLD_VERSION: Uuid = uuid!("0d6b2f6e-4c1a-4b8e-9f3d-2a7c5e1b8d40");
+    const OLD_PREFIX: &[u8] = b"old";
+
+    fn strip_prefix(metadata: &[u8]) -> crate::Result<Vec<u8>> {
+        metadata
+            .strip_prefix(OLD_PREFIX)
+            .map(<[u8]>::to_vec)
+            .ok_or(crate::Error::Corrupt)
+    }
+
+    const MIGRATIONS: &[Migration] = &[Migration {
+        from: OLD_VERSION,
+        to: VERSION_ID,
+        upgrade: strip_prefix,
+    }];
+
+    /// Create a repository and rewrite its metadata in a fake old format.
+    fn create_old_repo() -> (MemoryConfig, MemoryStore) {
+        let config = MemoryConfig::new();
+        let repo: KeyRepo<String> = OpenOptions::new()
+            .mode(OpenMode::CreateNew)
+            .open(&config)
+            .unwrap();
+        drop(repo);
+
+        let mut store = config.open().unwrap();
+        let metadata = store.read_block(BlockKey::Super).unwrap().unwrap();
+        store
+            .write_block(BlockKey::Super, &[OLD_PREFIX, &metadata].concat())
+            .unwrap();
+        store
+            .write_block(BlockKey::Version, OLD_VERSION.as_bytes())
+            .unwrap();
+
+        (config, store)
+    }
+
+    #[test]
+    fn old_repo_needs_upgrade() {
+        let (config, mut store) = create_old_repo();
+        let info = read_info(&mut store, MIGRATIONS).unwrap();
+        assert_that!(info.format_version()).is_equal_to(OLD_VERSION);
+        assert_that!(info.needs_upgrade()).is_true();
+        assert!(matches!(
+            peek_info_store(&mut store),
+            Err(crate::Error::UnsupportedRepo)
+        ));
+        assert!(matches!(
+            OpenOptions::new().open::<KeyRepo<String>, _>(&config),
+            Err(crate::Error::UnsupportedRepo)
+        ));
+    }
+
+    #[test]
+    fn upgraded_repo_can_be_opened() {
+        let (config, mut store) = create_old_repo();
+
+        assert_that!(upgrade_store(&mut store, MIGRATIONS).unwrap()).is_true();
+        assert_that!(upgrade_store(&mut store, MIGRATIONS).unwrap()).is_false();
+
+        let info = peek_info_store(&mut store).unwrap();
+        assert_that!(info.format_version()).is_equal_to(VERSION_ID);
+        assert_that!(info.needs_upgrade()).is_false();
+
+        let repo: KeyRepo<String> = OpenOptions::new().open(&config).unwrap();
+        repo.clean().unwrap();
+        drop(repo);
+
+        // The backup header must survive cleaning the repository.
+        let metadata = store.read_block(BlockKey::Super).unwrap().unwrap();
+        let metadata: RepoMetadata = rmp_serde::from_read(metadata.as_slice()).unwrap();
+        let backup = store
+            .read_block(BlockKey::Header(metadata.upgrade_backup_id.unwrap()))
+            .unwrap()
+            .unwrap();
+        let backup: UpgradeBackup = rmp_serde::from_read(backup.as_slice()).unwrap();
+        assert_that!(backup.version).is_equal_to(OLD_VERSION);
+        assert_that!(backup.metadata.starts_with(OLD_PREFIX)).is_true();
+    }
+
+    #[test]
+    fn interrupted_upgrade_can_be_resumed() {
+        let (config, mut store) = create_old_repo();
+
+        upgrade_store(&mut store, MIGRATIONS).unwrap();
+        store
+            .write_block(BlockKey::Version, OLD_VERSION.as_bytes())
+            .unwrap();
+
+        assert_that!(upgrade_store(&mut store, MIGRATIONS).unwrap()).is_true();
+        assert!(OpenOptions::new()
+            .open::<KeyRepo<String>, _>(&config)
+            .is_ok());
+    }
+
+    #[test]
+    fn unknown_version_is_unsupported() {
+        let (_, mut store) = create_old_repo();
+        assert!(matches!(
+            upgrade_store(&mut store, &[]),
+            Err(crate::Error::UnsupportedRepo)
+        ));
+    }
+}
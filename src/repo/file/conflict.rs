@@ -0,0 +1,20 @@
+/// How to handle an ancestor of a path which exists but is not a directory.
+///
+/// This is used by [`FileRepo::create_parents_with`].
+///
+/// [`FileRepo::create_parents_with`]: crate::repo::file::FileRepo::create_parents_with
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ParentConflict {
+    /// Return `Error::NotDirectory`.
+    Error,
+
+    /// Remove the conflicting entry and replace it with a directory.
+    Replace,
+
+    /// Rename the conflicting entry and create a directory in its place.
+    ///
+    /// The entry is renamed by appending `~` followed by the smallest positive integer which
+    /// results in a path that does not already exist. For example, `file` would be renamed to
+    /// `file~1`.
+    Rename,
+}
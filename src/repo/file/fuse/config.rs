@@ -0,0 +1,95 @@
+use std::collections::HashSet;
+use std::time::Duration;
+
+use nix::libc;
+
+use super::acl::{ACCESS_ACL_XATTR, DEFAULT_ACL_XATTR};
+use super::options::{MountOption, XattrNamespace};
+
+/// The maximum length of an extended attribute name in bytes.
+const XATTR_NAME_MAX: usize = 255;
+
+/// The configuration for a FUSE file system which is handled by this library.
+#[derive(Debug, Clone)]
+pub struct AdapterConfig {
+    /// The interval at which to automatically commit changes, or `None` if disabled.
+    pub auto_commit: Option<Duration>,
+
+    /// The namespaces of extended attributes which are accepted.
+    pub xattr_namespaces: HashSet<XattrNamespace>,
+
+    /// The maximum size of extended attribute values, or `None` if there is no limit.
+    pub max_xattr_size: Option<usize>,
+
+    /// The declared capacity of the file system in bytes, or `None` if it is unknown.
+    pub capacity: Option<u64>,
+}
+
+impl Default for AdapterConfig {
+    fn default() -> Self {
+        Self {
+            auto_commit: None,
+            xattr_namespaces: XattrNamespace::ALL.into_iter().collect(),
+            max_xattr_size: None,
+            capacity: None,
+        }
+    }
+}
+
+impl AdapterConfig {
+    /// Create a new `AdapterConfig` from the given mount `options`.
+    ///
+    /// If an option is passed more than once, the last one takes precedence.
+    pub fn from_options(options: &[MountOption]) -> Self {
+        let mut config = Self::default();
+        for option in options {
+            match option {
+                MountOption::AutoCommit(interval) => config.auto_commit = Some(*interval),
+                MountOption::XattrNamespaces(namespaces) => {
+                    config.xattr_namespaces = namespaces.iter().copied().collect()
+                }
+                MountOption::MaxXattrSize(size) => config.max_xattr_size = Some(*size),
+                MountOption::Capacity(capacity) => config.capacity = Some(*capacity),
+                _ => {}
+            }
+        }
+        config
+    }
+
+    /// Return whether the extended attribute with the given `name` is accepted.
+    pub fn accepts_xattr(&self, name: &str) -> bool {
+        if name == ACCESS_ACL_XATTR || name == DEFAULT_ACL_XATTR {
+            return true;
+        }
+
+        match XattrNamespace::of(name) {
+            Some(namespace) => self.xattr_namespaces.contains(&namespace),
+            None => false,
+        }
+    }
+
+    /// Check whether the extended attribute with the given `name` can be accessed.
+    ///
+    /// If it can't, this returns the errno to reply with.
+    pub fn check_xattr_name(&self, name: &str) -> Result<(), libc::c_int> {
+        if name.len() > XATTR_NAME_MAX {
+            return Err(libc::ERANGE);
+        }
+
+        if !self.accepts_xattr(name) {
+            return Err(libc::EOPNOTSUPP);
+        }
+
+        Ok(())
+    }
+
+    /// Check whether an extended attribute can be set to the given `value`.
+    ///
+    /// If it can't, this returns the errno to reply with.
+    pub fn check_xattr_value(&self, value: &[u8]) -> Result<(), libc::c_int> {
+        match self.max_xattr_size {
+            Some(max_size) if value.len() > max_size => Err(libc::E2BIG),
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use std::collections::HashMap;
+
+use crate::repo::file::{Entry, UnixMetadata, UnixSpecial};
+use crate::repo::ContentId;
+
+/// The maximum number of entries to keep in an `EntryCache`.
+///
+/// This is large enough to hold every entry in a directory with tens of thousands of children.
+const MAX_CACHED_ENTRIES: usize = 1 << 16;
+
+/// A cache of deserialized entries in a virtual file system.
+///
+/// Each entry is stored along with the `ContentId` of the object it was deserialized from, so an
+/// entry which has been modified since it was cached is detected without deserializing it again.
+/// This also means that stale entries never need to be invalidated explicitly, even when an inode
+/// is reused for a different entry.
+#[derive(Debug, Default)]
+pub struct EntryCache {
+    /// A map of inodes to their cached entries and the versions they were deserialized from.
+    entries: HashMap<u64, (ContentId, Entry<UnixSpecial, UnixMetadata>)>,
+}
+
+impl EntryCache {
+    /// Return a new empty `EntryCache`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Return the cached entry for `inode` if it's still at the given `version`.
+    pub fn get(
+        &self,
+        inode: u64,
+        version: &ContentId,
+    ) -> Option<&Entry<UnixSpecial, UnixMetadata>> {
+        match self.entries.get(&inode) {
+            Some((cached_version, entry)) if cached_version == version => Some(entry),
+            _ => None,
+        }
+    }
+
+    /// Cache the given `entry` for `inode` at the given `version`.
+    ///
+    /// If the cache is full, every entry is evicted first.
+    pub fn insert(
+        &mut self,
+        inode: u64,
+        version: ContentId,
+        entry: Entry<UnixSpecial, UnixMetadata>,
+    ) {
+        if self.entries.len() >= MAX_CACHED_ENTRIES && !self.entries.contains_key(&inode) {
+            self.entries.clear();
+        }
+        self.entries.insert(inode, (version, entry));
+    }
+}
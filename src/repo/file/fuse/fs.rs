@@ -3,11 +3,14 @@ use std::ffi::OsStr;
 use std::io::{Read, Seek, SeekFrom, Write};
 use std::os::unix::ffi::OsStrExt;
 use std::path::Path;
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, SystemTime};
 
 use fuser::{
-    FileAttr, Filesystem, ReplyAttr, ReplyData, ReplyDirectory, ReplyEmpty, ReplyEntry, ReplyOpen,
-    ReplyWrite, ReplyXattr, Request, TimeOrNow,
+    consts::{FUSE_DO_READDIRPLUS, FUSE_POSIX_LOCKS, FUSE_READDIRPLUS_AUTO},
+    FileAttr, Filesystem, KernelConfig, ReplyAttr, ReplyData, ReplyDirectory, ReplyDirectoryPlus,
+    ReplyEmpty, ReplyEntry, ReplyLock, ReplyOpen, ReplyStatfs, ReplyWrite, ReplyXattr, Request,
+    TimeOrNow,
 };
 use nix::fcntl::OFlag;
 use nix::libc;
@@ -16,28 +19,36 @@ use once_cell::sync::Lazy;
 use relative_path::{RelativePath, RelativePathBuf};
 
 use super::acl::{Permissions, ACCESS_ACL_XATTR, DEFAULT_ACL_XATTR};
+use super::config::AdapterConfig;
+use super::entry_cache::EntryCache;
 use super::handle::{DirectoryEntry, DirectoryHandle, FileHandle, HandleState, HandleTable};
 use super::inode::InodeTable;
+use super::lock::{FileLock, LockTable, LockType};
 use super::object::ObjectTable;
 
 use crate::repo::file::{
-    repository::EMPTY_PATH, AclQualifier, Entry, EntryType, FileMode, FileRepo, UnixMetadata,
-    UnixSpecial, WalkPredicate,
+    repository::EMPTY_PATH, AclQualifier, Entry, EntryType, FileMode, FileRepo, SharedFileRepo,
+    UnixMetadata, UnixSpecial, WalkPredicate,
 };
-use crate::repo::{Commit, RestoreSavepoint};
+use crate::repo::{Access, Commit, RestoreSavepoint};
 
 /// The block size used to calculate `st_blocks`.
 const BLOCK_SIZE: u32 = 512;
 
 /// The default TTL value to use in FUSE replies.
 ///
-/// Because the backing `FileRepo` can only be safely modified through the FUSE file system, we can
-/// set this to an arbitrarily large value.
+/// Because the tree of entries and the contents of files in the backing `FileRepo` can only be
+/// modified through the FUSE file system, we can set this to an arbitrarily large value. This also
+/// holds when mounted through a `SharedFileRepo`, because it only allows read-only access to the
+/// repository.
 const DEFAULT_TTL: Duration = Duration::MAX;
 
 /// The set of `open` flags which are not supported by this file system.
 static UNSUPPORTED_OPEN_FLAGS: Lazy<OFlag> = Lazy::new(|| OFlag::O_DIRECT | OFlag::O_TMPFILE);
 
+/// The maximum length of a file name reported by `statfs`.
+const MAX_NAME_LEN: u32 = 255;
+
 /// The value of `st_rdev` value to use if the file is not a character or block device.
 const NON_SPECIAL_RDEV: u32 = 0;
 
@@ -54,6 +65,29 @@ macro_rules! try_result {
     };
 }
 
+/// Handle a `Result` containing an errno in a FUSE method.
+macro_rules! try_errno {
+    ($result:expr, $reply:expr) => {
+        if let Err(errno) = $result {
+            $reply.error(errno);
+            return;
+        }
+    };
+}
+
+/// Handle a `Result` containing an errno in a FUSE method and return the value on success.
+macro_rules! try_errno_result {
+    ($result:expr, $reply:expr) => {
+        match $result {
+            Ok(result) => result,
+            Err(errno) => {
+                $reply.error(errno);
+                return;
+            }
+        }
+    };
+}
+
 /// Handle an `Option` in a FUSE method.
 macro_rules! try_option {
     ($result:expr, $reply:expr, $error:expr) => {
@@ -96,13 +130,23 @@ pub struct FuseAdapter<'a> {
 
     /// A map of inodes to currently open file objects.
     objects: ObjectTable,
+
+    /// A table of advisory locks held on files.
+    locks: LockTable,
+
+    /// A cache of entries which have been deserialized.
+    entries: EntryCache,
+
+    /// The configuration for the file system.
+    config: AdapterConfig,
 }
 
 impl<'a> FuseAdapter<'a> {
-    /// Create a new `FuseAdapter` from the given `repo`.
+    /// Create a new `FuseAdapter` from the given `repo` with the given `config`.
     pub fn new(
         repo: &'a mut FileRepo<UnixSpecial, UnixMetadata>,
         root: &RelativePath,
+        config: AdapterConfig,
     ) -> crate::Result<Self> {
         if root == *EMPTY_PATH {
             return Err(crate::Error::InvalidPath);
@@ -121,9 +165,32 @@ impl<'a> FuseAdapter<'a> {
             inodes,
             handles: HandleTable::new(),
             objects: ObjectTable::new(),
+            locks: LockTable::new(),
+            entries: EntryCache::new(),
+            config,
         })
     }
 
+    /// Commit changes to all open objects and the repository.
+    fn commit_all(&mut self) -> crate::Result<()> {
+        self.objects.commit_all()?;
+        self.repo.commit()
+    }
+
+    /// Return the entry with the given `inode`.
+    ///
+    /// This only deserializes the entry if it has been modified since it was last cached.
+    fn cached_entry(&mut self, inode: u64) -> crate::Result<Entry<UnixSpecial, UnixMetadata>> {
+        let entry_path = self.inodes.path(inode).ok_or(crate::Error::NotFound)?;
+        let version = self.repo.entry_version(entry_path)?;
+        if let Some(entry) = self.entries.get(inode, &version) {
+            return Ok(entry.clone());
+        }
+        let entry = self.repo.entry(entry_path)?;
+        self.entries.insert(inode, version, entry.clone());
+        Ok(entry)
+    }
+
     /// Get the `FileAttr` for the `entry` with the given `inode`.
     fn entry_attr(
         &mut self,
@@ -136,12 +203,12 @@ impl<'a> FuseAdapter<'a> {
         let default_metadata = entry.default_metadata(req);
         let metadata = entry.metadata.as_ref().unwrap_or(&default_metadata);
 
+        // Sizes of files are cached so that listing large directories doesn't require opening the
+        // object for each file.
         let size = match &entry.kind {
             EntryType::File => self
                 .objects
-                .open_commit(inode, self.repo.open(entry_path).unwrap())?
-                .size()
-                .unwrap(),
+                .size(inode, || self.repo.open(entry_path).unwrap())?,
             EntryType::Directory => 0,
             EntryType::Special(special) => match special {
                 // The `st_size` of a symlink should be the length of the pathname it contains.
@@ -236,11 +303,13 @@ impl<'a> FuseAdapter<'a> {
                 Ok(()) => Ok(result),
                 Err(error) => {
                     self.repo.finish_restore(restore);
+                    self.objects.invalidate_sizes();
                     Err(error)
                 }
             },
             Err(error) => {
                 self.repo.finish_restore(restore);
+                self.objects.invalidate_sizes();
                 Err(error)
             }
         }
@@ -248,12 +317,30 @@ impl<'a> FuseAdapter<'a> {
 }
 
 impl<'a> Filesystem for FuseAdapter<'a> {
+    fn init(&mut self, _req: &Request, config: &mut KernelConfig) -> Result<(), libc::c_int> {
+        // Ask the kernel to forward POSIX locks to this file system. If the kernel doesn't support
+        // this, it handles locks itself.
+        let _ = config.add_capabilities(FUSE_POSIX_LOCKS);
+
+        // Ask the kernel to use `readdirplus` when listing large directories, so it doesn't need to
+        // send a separate `lookup` request for each entry.
+        let _ = config.add_capabilities(FUSE_DO_READDIRPLUS | FUSE_READDIRPLUS_AUTO);
+        Ok(())
+    }
+
+    fn destroy(&mut self) {
+        // There is no way to report an error when the file system is unmounted.
+        if self.config.auto_commit.is_some() {
+            let _ = self.commit_all();
+        }
+    }
+
     fn lookup(&mut self, req: &Request, parent: u64, name: &OsStr, reply: ReplyEntry) {
         let file_name = try_option!(name.to_str(), reply, libc::ENOENT);
         let entry_path = try_option!(self.inodes.path(parent), reply, libc::ENOENT).join(file_name);
         let entry_id = try_result!(self.repo.entry_id(&entry_path), reply);
         let entry_inode = try_option!(self.inodes.inode(entry_id), reply, libc::ENOENT);
-        let entry = try_result!(self.repo.entry(&entry_path), reply);
+        let entry = try_result!(self.cached_entry(entry_inode), reply);
 
         let attr = try_result!(self.entry_attr(&entry, entry_inode, req), reply);
 
@@ -263,8 +350,7 @@ impl<'a> Filesystem for FuseAdapter<'a> {
     }
 
     fn getattr(&mut self, req: &Request, ino: u64, reply: ReplyAttr) {
-        let entry_path = try_option!(self.inodes.path(ino), reply, libc::ENOENT);
-        let entry = try_result!(self.repo.entry(entry_path), reply);
+        let entry = try_result!(self.cached_entry(ino), reply);
         let attr = try_result!(self.entry_attr(&entry, ino, req), reply);
 
         reply.attr(&DEFAULT_TTL, &attr);
@@ -347,6 +433,7 @@ impl<'a> Filesystem for FuseAdapter<'a> {
 
                     if new_size != object.size().unwrap() {
                         object.set_len(new_size)?;
+                        fs.objects.invalidate_size(ino);
 
                         // Truncating the file should update its `mtime`, `atime`, and `ctime`.
                         metadata.modified = now;
@@ -503,6 +590,7 @@ impl<'a> Filesystem for FuseAdapter<'a> {
         );
 
         self.objects.close(entry_inode);
+        self.objects.invalidate_size(entry_inode);
         self.inodes.remove(entry_id, &entry_path);
 
         // Attempt to clean the repository to free unused space. We ignore any errors because this
@@ -658,6 +746,9 @@ impl<'a> Filesystem for FuseAdapter<'a> {
 
         // If the destination entry already existed, we need to remove it from the inode table.
         if let Some(entry_id) = existing_dest_id {
+            if let Some(dest_inode) = self.inodes.inode(entry_id) {
+                self.objects.invalidate_size(dest_inode);
+            }
             assert!(self.inodes.remove(entry_id, &dest_path));
         }
 
@@ -789,6 +880,14 @@ impl<'a> Filesystem for FuseAdapter<'a> {
             );
             try_result!(object.seek(SeekFrom::Start(offset as u64)), reply);
 
+            // If this read continues where the last one left off, the file is probably being read
+            // sequentially, so fetch upcoming chunks ahead of time.
+            object.advise(if offset as u64 == state.position {
+                Access::Sequential
+            } else {
+                Access::Random
+            });
+
             // `Filesystem::read` should read the exact number of bytes requested except on EOF or error.
             let mut bytes_read;
             loop {
@@ -856,6 +955,19 @@ impl<'a> Filesystem for FuseAdapter<'a> {
 
             flags = state.flags;
 
+            if self
+                .repo
+                .limits()
+                .check_file_size(offset as u64 + data.len() as u64)
+                .is_err()
+            {
+                reply.error(libc::EFBIG);
+                return;
+            }
+
+            // Writing to the file may change its size.
+            self.objects.invalidate_size(ino);
+
             let object = if offset as u64 == state.position {
                 // If the offset is the same as the previous offset, we don't need to seek and
                 // therefore don't need to commit changes to the object.
@@ -918,9 +1030,12 @@ impl<'a> Filesystem for FuseAdapter<'a> {
         reply.written(data.len() as u32);
     }
 
-    fn flush(&mut self, _req: &Request, ino: u64, _fh: u64, _lock_owner: u64, reply: ReplyEmpty) {
+    fn flush(&mut self, _req: &Request, ino: u64, _fh: u64, lock_owner: u64, reply: ReplyEmpty) {
+        // Closing any file descriptor for a file releases the POSIX locks the process holds on it.
+        self.locks.release(ino, lock_owner);
+
         try_result!(self.objects.commit(ino), reply);
-        reply.ok()
+        reply.ok();
     }
 
     fn release(
@@ -929,12 +1044,19 @@ impl<'a> Filesystem for FuseAdapter<'a> {
         ino: u64,
         fh: u64,
         _flags: i32,
-        _lock_owner: Option<u64>,
+        lock_owner: Option<u64>,
         _flush: bool,
         reply: ReplyEmpty,
     ) {
+        if let Some(lock_owner) = lock_owner {
+            self.locks.release(ino, lock_owner);
+        }
+
+        // Commit changes before closing the object so they aren't lost.
+        let result = self.objects.commit(ino);
         self.handles.close(fh);
         self.objects.close(ino);
+        try_result!(result, reply);
         reply.ok()
     }
 
@@ -944,6 +1066,76 @@ impl<'a> Filesystem for FuseAdapter<'a> {
         reply.ok();
     }
 
+    // The `fallocate` flags are specific to Linux.
+    #[cfg(target_os = "linux")]
+    fn fallocate(
+        &mut self,
+        _req: &Request,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        length: i64,
+        mode: i32,
+        reply: ReplyEmpty,
+    ) {
+        let entry_path = try_option!(self.inodes.path(ino), reply, libc::ENOENT).to_owned();
+
+        if !self.repo.is_file(&entry_path) {
+            reply.error(libc::ENODEV);
+            return;
+        }
+
+        if offset < 0 || length <= 0 {
+            reply.error(libc::EINVAL);
+            return;
+        }
+
+        let punch_hole = mode & libc::FALLOC_FL_PUNCH_HOLE != 0;
+        let keep_size = mode & libc::FALLOC_FL_KEEP_SIZE != 0;
+
+        // We only support allocating space and punching holes. Punching a hole requires that the
+        // size of the file be kept.
+        if mode & !(libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE) != 0
+            || (punch_hole && !keep_size)
+        {
+            reply.error(libc::EOPNOTSUPP);
+            return;
+        }
+
+        let start = offset as u64;
+        let end = start.saturating_add(length as u64);
+
+        try_result!(
+            self.transaction(|fs| {
+                let object = fs
+                    .objects
+                    .open_commit(ino, fs.repo.open(&entry_path).unwrap())?;
+
+                if punch_hole {
+                    object.punch_hole(start..end)?;
+                } else if keep_size {
+                    let size = object.size()?;
+                    object.allocate(start.min(size)..end.min(size))?;
+                } else {
+                    object.allocate(start..end)?;
+                }
+
+                fs.objects.invalidate_size(ino);
+
+                Ok(())
+            }),
+            reply
+        );
+
+        if punch_hole {
+            // Attempt to clean the repository to free unused space. We ignore any errors because this
+            // method must return successfully once the transaction is complete.
+            self.repo.clean().ok();
+        }
+
+        reply.ok();
+    }
+
     fn opendir(&mut self, _req: &Request, ino: u64, _flags: i32, reply: ReplyOpen) {
         let entry_path = try_option!(self.inodes.path(ino), reply, libc::ENOENT);
 
@@ -1022,11 +1214,207 @@ impl<'a> Filesystem for FuseAdapter<'a> {
         reply.ok();
     }
 
+    fn readdirplus(
+        &mut self,
+        req: &Request,
+        ino: u64,
+        fh: u64,
+        offset: i64,
+        mut reply: ReplyDirectoryPlus,
+    ) {
+        let directory_path = try_option!(self.inodes.path(ino), reply, libc::ENOENT).to_owned();
+
+        try_result!(
+            self.transaction(|fs| fs.repo.touch_accessed(&directory_path, req)),
+            reply
+        );
+
+        let mut index = offset as usize;
+        loop {
+            // We can't hold a reference to the handle while getting the attributes of each entry.
+            let dir_entry = match self.handles.state(fh) {
+                None => {
+                    reply.error(libc::EBADF);
+                    return;
+                }
+                Some(HandleState::File(_)) => {
+                    reply.error(libc::ENOTDIR);
+                    return;
+                }
+                Some(HandleState::Directory(DirectoryHandle { entries })) => {
+                    match entries.get(index) {
+                        Some(dir_entry) => dir_entry.clone(),
+                        None => break,
+                    }
+                }
+            };
+
+            let entry = try_result!(self.cached_entry(dir_entry.inode), reply);
+            let attr = try_result!(self.entry_attr(&entry, dir_entry.inode, req), reply);
+            let generation = self.inodes.generation(dir_entry.inode);
+
+            index += 1;
+            if reply.add(
+                dir_entry.inode,
+                index as i64,
+                &dir_entry.file_name,
+                &DEFAULT_TTL,
+                &attr,
+                generation,
+            ) {
+                break;
+            }
+        }
+
+        reply.ok();
+    }
+
     fn releasedir(&mut self, _req: &Request, _ino: u64, fh: u64, _flags: i32, reply: ReplyEmpty) {
         self.handles.close(fh);
         reply.ok()
     }
 
+    fn copy_file_range(
+        &mut self,
+        req: &Request,
+        ino_in: u64,
+        _fh_in: u64,
+        offset_in: i64,
+        ino_out: u64,
+        _fh_out: u64,
+        offset_out: i64,
+        len: u64,
+        flags: u32,
+        reply: ReplyWrite,
+    ) {
+        if flags != 0 {
+            reply.error(libc::EINVAL);
+            return;
+        }
+
+        let source_path = try_option!(self.inodes.path(ino_in), reply, libc::EBADF).to_owned();
+        let dest_path = try_option!(self.inodes.path(ino_out), reply, libc::EBADF).to_owned();
+
+        // We can only clone the contents of the whole file, since the repository has no way to
+        // share a range of bytes between objects. For anything else, we let the kernel or the
+        // caller fall back to copying the bytes.
+        if ino_in == ino_out || offset_in != 0 || offset_out != 0 {
+            reply.error(libc::EOPNOTSUPP);
+            return;
+        }
+
+        // Make sure the sizes include any uncommitted writes.
+        try_result!(self.objects.commit(ino_in), reply);
+        try_result!(self.objects.commit(ino_out), reply);
+        let source_size = try_result!(
+            self.objects
+                .size(ino_in, || self.repo.open(&source_path).unwrap()),
+            reply
+        );
+        let dest_size = try_result!(
+            self.objects
+                .size(ino_out, || self.repo.open(&dest_path).unwrap()),
+            reply
+        );
+
+        // The number of bytes copied must fit in the reply.
+        if len < source_size || dest_size > source_size || source_size > u64::from(u32::MAX) {
+            reply.error(libc::EOPNOTSUPP);
+            return;
+        }
+
+        try_result!(
+            self.transaction(|fs| {
+                fs.repo.clone_contents(&source_path, &dest_path)?;
+                fs.repo.touch_modified(&dest_path, req)
+            }),
+            reply
+        );
+
+        // The contents of the destination file have been replaced.
+        self.objects.close(ino_out);
+        self.objects.invalidate_size(ino_out);
+
+        reply.written(source_size as u32);
+    }
+
+    fn getlk(
+        &mut self,
+        _req: &Request,
+        ino: u64,
+        _fh: u64,
+        lock_owner: u64,
+        start: u64,
+        end: u64,
+        typ: i32,
+        pid: u32,
+        reply: ReplyLock,
+    ) {
+        let kind = match try_errno_result!(LockType::from_raw(typ), reply) {
+            Some(kind) => kind,
+            None => {
+                reply.error(libc::EINVAL);
+                return;
+            }
+        };
+        let lock = FileLock {
+            start,
+            end,
+            kind,
+            owner: lock_owner,
+            pid,
+        };
+
+        match self.locks.conflict(ino, &lock) {
+            Some(conflict) => reply.locked(
+                conflict.start,
+                conflict.end,
+                conflict.kind.to_raw(),
+                conflict.pid,
+            ),
+            None => reply.locked(start, end, libc::F_UNLCK, pid),
+        }
+    }
+
+    fn setlk(
+        &mut self,
+        _req: &Request,
+        ino: u64,
+        _fh: u64,
+        lock_owner: u64,
+        start: u64,
+        end: u64,
+        typ: i32,
+        pid: u32,
+        sleep: bool,
+        reply: ReplyEmpty,
+    ) {
+        let kind = match try_errno_result!(LockType::from_raw(typ), reply) {
+            Some(kind) => kind,
+            None => {
+                self.locks.unlock(ino, lock_owner, start, end);
+                reply.ok();
+                return;
+            }
+        };
+        let lock = FileLock {
+            start,
+            end,
+            kind,
+            owner: lock_owner,
+            pid,
+        };
+
+        if sleep {
+            // The reply is sent once the lock is acquired.
+            self.locks.lock_wait(ino, lock, reply);
+        } else if self.locks.lock(ino, lock) {
+            reply.ok();
+        } else {
+            reply.error(libc::EAGAIN);
+        }
+    }
+
     fn fsyncdir(
         &mut self,
         _req: &Request,
@@ -1039,6 +1427,45 @@ impl<'a> Filesystem for FuseAdapter<'a> {
         reply.ok();
     }
 
+    fn statfs(&mut self, _req: &Request, _ino: u64, reply: ReplyStatfs) {
+        let used_bytes = self.repo.stats().actual_size();
+        let store_free_bytes = try_result!(self.repo.available_space(), reply);
+
+        // Report the most restrictive limit on the amount of free space.
+        let free_bytes = [
+            self.config
+                .capacity
+                .map(|capacity| capacity.saturating_sub(used_bytes)),
+            self.repo
+                .quota()
+                .map(|quota| quota.saturating_sub(used_bytes)),
+            store_free_bytes,
+        ]
+        .into_iter()
+        .flatten()
+        .min()
+        .unwrap_or(0);
+
+        let block_size = u64::from(BLOCK_SIZE);
+        let used_blocks = (used_bytes + block_size - 1) / block_size;
+        let free_blocks = free_bytes / block_size;
+
+        // There is no limit on the number of files other than the number of available inodes.
+        let files = self.inodes.allocated();
+        let free_files = u64::MAX - files;
+
+        reply.statfs(
+            used_blocks + free_blocks,
+            free_blocks,
+            free_blocks,
+            files + free_files,
+            free_files,
+            BLOCK_SIZE,
+            MAX_NAME_LEN,
+            BLOCK_SIZE,
+        );
+    }
+
     fn setxattr(
         &mut self,
         req: &Request,
@@ -1050,6 +1477,8 @@ impl<'a> Filesystem for FuseAdapter<'a> {
         reply: ReplyEmpty,
     ) {
         let attr_name = try_option!(name.to_str(), reply, libc::EINVAL).to_owned();
+        try_errno!(self.config.check_xattr_name(&attr_name), reply);
+        try_errno!(self.config.check_xattr_value(value), reply);
 
         let entry_path = try_option!(self.inodes.path(ino), reply, libc::ENOENT).to_owned();
         let mut metadata =
@@ -1114,6 +1543,7 @@ impl<'a> Filesystem for FuseAdapter<'a> {
 
     fn getxattr(&mut self, req: &Request, ino: u64, name: &OsStr, size: u32, reply: ReplyXattr) {
         let attr_name = try_option!(name.to_str(), reply, libc::ENODATA).to_owned();
+        try_errno!(self.config.check_xattr_name(&attr_name), reply);
 
         let entry_path = try_option!(self.inodes.path(ino), reply, libc::ENOENT);
         let mut metadata = try_result!(self.repo.entry(entry_path), reply).metadata_or_default(req);
@@ -1159,7 +1589,11 @@ impl<'a> Filesystem for FuseAdapter<'a> {
 
         // Construct a byte string of null-terminated attribute names.
         let mut attr_names = Vec::new();
-        for attr_name in metadata.attributes.keys() {
+        for attr_name in metadata
+            .attributes
+            .keys()
+            .filter(|attr_name| self.config.accepts_xattr(attr_name))
+        {
             attr_names.extend_from_slice(attr_name.as_bytes());
             attr_names.push(0u8);
         }
@@ -1179,6 +1613,7 @@ impl<'a> Filesystem for FuseAdapter<'a> {
 
     fn removexattr(&mut self, req: &Request, ino: u64, name: &OsStr, reply: ReplyEmpty) {
         let attr_name = try_option!(name.to_str(), reply, libc::ENODATA).to_owned();
+        try_errno!(self.config.check_xattr_name(&attr_name), reply);
 
         let entry_path = try_option!(self.inodes.path(ino), reply, libc::ENOENT).to_owned();
         let mut metadata =
@@ -1208,3 +1643,321 @@ impl<'a> Filesystem for FuseAdapter<'a> {
         reply.ok();
     }
 }
+
+/// The state of a `SharedFuseAdapter` which persists between requests.
+#[derive(Debug)]
+struct AdapterState {
+    inodes: InodeTable,
+    handles: HandleTable,
+    objects: ObjectTable,
+    locks: LockTable,
+    entries: EntryCache,
+    config: AdapterConfig,
+}
+
+/// Forward each of the given `Filesystem` methods to a temporary `FuseAdapter`.
+macro_rules! forward_to_adapter {
+    ($(fn $name:ident(&mut self $(, $arg:ident: $arg_type:ty)*) $(-> $return_type:ty)?;)*) => {
+        $(
+            fn $name(&mut self $(, $arg: $arg_type)*) $(-> $return_type)? {
+                self.with_adapter(|adapter| adapter.$name($($arg),*))
+            }
+        )*
+    };
+}
+
+/// A handle to a `FileRepo` which can be locked while a FUSE request is handled.
+pub trait LockRepo {
+    /// Lock the repository and call `block` with it.
+    fn with_repo<T>(&self, block: impl FnOnce(&mut FileRepo<UnixSpecial, UnixMetadata>) -> T) -> T;
+}
+
+impl LockRepo for SharedFileRepo<UnixSpecial, UnixMetadata> {
+    fn with_repo<T>(&self, block: impl FnOnce(&mut FileRepo<UnixSpecial, UnixMetadata>) -> T) -> T {
+        SharedFileRepo::with_repo(self, block)
+    }
+}
+
+impl<'a> LockRepo for &Mutex<&'a mut FileRepo<UnixSpecial, UnixMetadata>> {
+    fn with_repo<T>(&self, block: impl FnOnce(&mut FileRepo<UnixSpecial, UnixMetadata>) -> T) -> T {
+        block(&mut self.lock().unwrap())
+    }
+}
+
+/// An adapter for implementing a FUSE file system backed by a repository which can be locked.
+///
+/// Unlike `FuseAdapter`, this only locks the repository while it's handling a request, so the
+/// repository can be accessed through other handles while it's mounted. Those handles can't modify
+/// the tree of entries, so the cached inodes and the TTL remain valid.
+///
+/// Clones of this value share the same state, which allows changes to be committed from another
+/// thread while the file system is running.
+#[derive(Debug, Clone)]
+pub struct SharedFuseAdapter<R> {
+    /// The repository which contains the virtual file system.
+    repo: R,
+
+    /// The state of the file system, or `None` if a request panicked.
+    state: Arc<Mutex<Option<AdapterState>>>,
+}
+
+impl<R: LockRepo> SharedFuseAdapter<R> {
+    /// Create a new `SharedFuseAdapter` from the given `repo` with the given `config`.
+    pub fn new(repo: R, root: &RelativePath, config: AdapterConfig) -> crate::Result<Self> {
+        let state = repo.with_repo(|repo| {
+            let adapter = FuseAdapter::new(repo, root, config)?;
+            Ok::<_, crate::Error>(AdapterState {
+                inodes: adapter.inodes,
+                handles: adapter.handles,
+                objects: adapter.objects,
+                locks: adapter.locks,
+                entries: adapter.entries,
+                config: adapter.config,
+            })
+        })?;
+        Ok(Self {
+            repo,
+            state: Arc::new(Mutex::new(Some(state))),
+        })
+    }
+
+    /// Lock the repository and call `block` with a `FuseAdapter` for it.
+    ///
+    /// This also blocks other clones of this adapter until `block` returns.
+    fn with_adapter<T>(&self, block: impl FnOnce(&mut FuseAdapter) -> T) -> T {
+        let mut state = self.state.lock().unwrap();
+        let AdapterState {
+            inodes,
+            handles,
+            objects,
+            locks,
+            entries,
+            config,
+        } = state.take().expect("A previous FUSE request panicked.");
+        self.repo.with_repo(|repo| {
+            let mut adapter = FuseAdapter {
+                repo,
+                inodes,
+                handles,
+                objects,
+                locks,
+                entries,
+                config,
+            };
+            let result = block(&mut adapter);
+            *state = Some(AdapterState {
+                inodes: adapter.inodes,
+                handles: adapter.handles,
+                objects: adapter.objects,
+                locks: adapter.locks,
+                entries: adapter.entries,
+                config: adapter.config,
+            });
+            result
+        })
+    }
+
+    /// Commit changes to all open files and the repository.
+    pub fn commit_all(&self) -> crate::Result<()> {
+        self.with_adapter(|adapter| adapter.commit_all())
+    }
+}
+
+impl<R: LockRepo> Filesystem for SharedFuseAdapter<R> {
+    forward_to_adapter! {
+        fn statfs(&mut self, req: &Request, ino: u64, reply: ReplyStatfs);
+        fn init(&mut self, req: &Request, config: &mut KernelConfig) -> Result<(), libc::c_int>;
+        fn destroy(&mut self);
+        fn lookup(&mut self, req: &Request, parent: u64, name: &OsStr, reply: ReplyEntry);
+        fn getattr(&mut self, req: &Request, ino: u64, reply: ReplyAttr);
+        fn setattr(
+            &mut self,
+            req: &Request,
+            ino: u64,
+            mode: Option<u32>,
+            uid: Option<u32>,
+            gid: Option<u32>,
+            size: Option<u64>,
+            atime: Option<TimeOrNow>,
+            mtime: Option<TimeOrNow>,
+            ctime: Option<SystemTime>,
+            fh: Option<u64>,
+            crtime: Option<SystemTime>,
+            chgtime: Option<SystemTime>,
+            bkuptime: Option<SystemTime>,
+            flags: Option<u32>,
+            reply: ReplyAttr
+        );
+        fn readlink(&mut self, req: &Request, ino: u64, reply: ReplyData);
+        fn mknod(
+            &mut self,
+            req: &Request,
+            parent: u64,
+            name: &OsStr,
+            mode: u32,
+            umask: u32,
+            rdev: u32,
+            reply: ReplyEntry
+        );
+        fn mkdir(
+            &mut self,
+            req: &Request,
+            parent: u64,
+            name: &OsStr,
+            mode: u32,
+            umask: u32,
+            reply: ReplyEntry
+        );
+        fn unlink(&mut self, req: &Request, parent: u64, name: &OsStr, reply: ReplyEmpty);
+        fn rmdir(&mut self, req: &Request, parent: u64, name: &OsStr, reply: ReplyEmpty);
+        fn symlink(
+            &mut self,
+            req: &Request,
+            parent: u64,
+            name: &OsStr,
+            link: &Path,
+            reply: ReplyEntry
+        );
+        fn rename(
+            &mut self,
+            req: &Request,
+            parent: u64,
+            name: &OsStr,
+            newparent: u64,
+            newname: &OsStr,
+            flags: u32,
+            reply: ReplyEmpty
+        );
+        fn link(
+            &mut self,
+            req: &Request,
+            ino: u64,
+            newparent: u64,
+            newname: &OsStr,
+            reply: ReplyEntry
+        );
+        fn open(&mut self, req: &Request, ino: u64, flags: i32, reply: ReplyOpen);
+        fn read(
+            &mut self,
+            req: &Request,
+            ino: u64,
+            fh: u64,
+            offset: i64,
+            size: u32,
+            flags: i32,
+            lock_owner: Option<u64>,
+            reply: ReplyData
+        );
+        fn write(
+            &mut self,
+            req: &Request,
+            ino: u64,
+            fh: u64,
+            offset: i64,
+            data: &[u8],
+            write_flags: u32,
+            flags: i32,
+            lock_owner: Option<u64>,
+            reply: ReplyWrite
+        );
+        fn flush(&mut self, req: &Request, ino: u64, fh: u64, lock_owner: u64, reply: ReplyEmpty);
+        fn release(
+            &mut self,
+            req: &Request,
+            ino: u64,
+            fh: u64,
+            flags: i32,
+            lock_owner: Option<u64>,
+            flush: bool,
+            reply: ReplyEmpty
+        );
+        fn fsync(&mut self, req: &Request, ino: u64, fh: u64, datasync: bool, reply: ReplyEmpty);
+        fn fallocate(
+            &mut self,
+            req: &Request,
+            ino: u64,
+            fh: u64,
+            offset: i64,
+            length: i64,
+            mode: i32,
+            reply: ReplyEmpty
+        );
+        fn opendir(&mut self, req: &Request, ino: u64, flags: i32, reply: ReplyOpen);
+        fn readdir(
+            &mut self,
+            req: &Request,
+            ino: u64,
+            fh: u64,
+            offset: i64,
+            reply: ReplyDirectory
+        );
+        fn readdirplus(
+            &mut self,
+            req: &Request,
+            ino: u64,
+            fh: u64,
+            offset: i64,
+            reply: ReplyDirectoryPlus
+        );
+        fn releasedir(&mut self, req: &Request, ino: u64, fh: u64, flags: i32, reply: ReplyEmpty);
+        fn copy_file_range(
+            &mut self,
+            req: &Request,
+            ino_in: u64,
+            fh_in: u64,
+            offset_in: i64,
+            ino_out: u64,
+            fh_out: u64,
+            offset_out: i64,
+            len: u64,
+            flags: u32,
+            reply: ReplyWrite
+        );
+        fn getlk(
+            &mut self,
+            req: &Request,
+            ino: u64,
+            fh: u64,
+            lock_owner: u64,
+            start: u64,
+            end: u64,
+            typ: i32,
+            pid: u32,
+            reply: ReplyLock
+        );
+        fn setlk(
+            &mut self,
+            req: &Request,
+            ino: u64,
+            fh: u64,
+            lock_owner: u64,
+            start: u64,
+            end: u64,
+            typ: i32,
+            pid: u32,
+            sleep: bool,
+            reply: ReplyEmpty
+        );
+        fn fsyncdir(
+            &mut self,
+            req: &Request,
+            ino: u64,
+            fh: u64,
+            datasync: bool,
+            reply: ReplyEmpty
+        );
+        fn setxattr(
+            &mut self,
+            req: &Request,
+            ino: u64,
+            name: &OsStr,
+            value: &[u8],
+            flags: i32,
+            position: u32,
+            reply: ReplyEmpty
+        );
+        fn getxattr(&mut self, req: &Request, ino: u64, name: &OsStr, size: u32, reply: ReplyXattr);
+        fn listxattr(&mut self, req: &Request, ino: u64, size: u32, reply: ReplyXattr);
+        fn removexattr(&mut self, req: &Request, ino: u64, name: &OsStr, reply: ReplyEmpty);
+    }
+}
@@ -120,6 +120,11 @@ impl InodeTable {
         self.entries.get_by_left(&id).copied()
     }
 
+    /// Return the number of inodes which are currently allocated.
+    pub fn allocated(&self) -> u64 {
+        self.paths.len() as u64
+    }
+
     /// Return the generation number associated with the given `inode`.
     pub fn generation(&self, inode: u64) -> u64 {
         self.generations.get(&inode).copied().unwrap_or(0)
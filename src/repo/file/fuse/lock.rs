@@ -0,0 +1,221 @@
+use std::collections::HashMap;
+use std::fmt::{self, Debug, Formatter};
+
+use fuser::ReplyEmpty;
+use nix::libc;
+
+/// The type of an advisory file lock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockType {
+    /// A shared lock, which can be held by multiple owners at once.
+    Read,
+
+    /// An exclusive lock, which can only be held by one owner at a time.
+    Write,
+}
+
+impl LockType {
+    /// Return the `LockType` for the given `typ` from `fcntl`, or `None` if it is `F_UNLCK`.
+    ///
+    /// # Errors
+    /// Returns `EINVAL` if `typ` is not a valid lock type.
+    pub fn from_raw(typ: i32) -> Result<Option<Self>, libc::c_int> {
+        match typ {
+            libc::F_RDLCK => Ok(Some(Self::Read)),
+            libc::F_WRLCK => Ok(Some(Self::Write)),
+            libc::F_UNLCK => Ok(None),
+            _ => Err(libc::EINVAL),
+        }
+    }
+
+    /// Return the lock type as it is represented by `fcntl`.
+    pub fn to_raw(self) -> i32 {
+        match self {
+            Self::Read => libc::F_RDLCK,
+            Self::Write => libc::F_WRLCK,
+        }
+    }
+}
+
+/// An advisory lock on a range of bytes in a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileLock {
+    /// The first byte in the locked range.
+    pub start: u64,
+
+    /// The last byte in the locked range, inclusive.
+    pub end: u64,
+
+    /// The type of lock.
+    pub kind: LockType,
+
+    /// The opaque ID of the owner of the lock provided by the kernel.
+    pub owner: u64,
+
+    /// The ID of the process which holds the lock.
+    pub pid: u32,
+}
+
+impl FileLock {
+    /// Return whether this lock overlaps the range from `start` to `end` inclusive.
+    fn overlaps(&self, start: u64, end: u64) -> bool {
+        self.start <= end && start <= self.end
+    }
+
+    /// Return whether this lock prevents `other` from being acquired.
+    fn conflicts(&self, other: &FileLock) -> bool {
+        self.owner != other.owner
+            && self.overlaps(other.start, other.end)
+            && (self.kind == LockType::Write || other.kind == LockType::Write)
+    }
+}
+
+/// A request for a lock which is waiting for a conflicting lock to be released.
+struct PendingLock {
+    /// The inode of the file to lock.
+    inode: u64,
+
+    /// The requested lock.
+    lock: FileLock,
+
+    /// The reply to send once the lock is acquired.
+    reply: ReplyEmpty,
+}
+
+impl Debug for PendingLock {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PendingLock")
+            .field("inode", &self.inode)
+            .field("lock", &self.lock)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A table of advisory file locks held on files in a virtual file system.
+///
+/// This implements the semantics of POSIX record locks. Locks are only tracked in memory, so they
+/// only apply to processes accessing the repository through the same mounted file system.
+#[derive(Debug, Default)]
+pub struct LockTable {
+    /// A map of inodes to the locks held on them.
+    locks: HashMap<u64, Vec<FileLock>>,
+
+    /// Requests for locks which are waiting for a conflicting lock to be released.
+    pending: Vec<PendingLock>,
+}
+
+impl LockTable {
+    /// Return a new empty `LockTable`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Return a lock which prevents `lock` from being acquired on the file at `inode`, if any.
+    pub fn conflict(&self, inode: u64, lock: &FileLock) -> Option<&FileLock> {
+        self.locks
+            .get(&inode)?
+            .iter()
+            .find(|existing| existing.conflicts(lock))
+    }
+
+    /// Acquire `lock` on the file at `inode`, replacing any locks its owner has on the same range.
+    ///
+    /// This returns `false` without acquiring the lock if a conflicting lock is held.
+    pub fn lock(&mut self, inode: u64, lock: FileLock) -> bool {
+        if self.conflict(inode, &lock).is_some() {
+            return false;
+        }
+        self.remove_range(inode, lock.owner, lock.start, lock.end);
+        self.locks.entry(inode).or_default().push(lock);
+
+        // Replacing a write lock with a read lock may allow other locks to be acquired.
+        self.grant_pending(inode);
+        true
+    }
+
+    /// Acquire `lock` on the file at `inode` once all conflicting locks are released.
+    ///
+    /// The given `reply` is sent once the lock is acquired.
+    pub fn lock_wait(&mut self, inode: u64, lock: FileLock, reply: ReplyEmpty) {
+        if self.lock(inode, lock.clone()) {
+            reply.ok();
+        } else {
+            self.pending.push(PendingLock { inode, lock, reply });
+        }
+    }
+
+    /// Release the locks held by `owner` on the range from `start` to `end` of the file at `inode`.
+    ///
+    /// Locks which only partially overlap the range are shrunk or split.
+    pub fn unlock(&mut self, inode: u64, owner: u64, start: u64, end: u64) {
+        self.remove_range(inode, owner, start, end);
+        self.grant_pending(inode);
+    }
+
+    /// Release all the locks held by `owner` on the file at `inode`.
+    ///
+    /// This also cancels any requests by `owner` which are waiting to acquire a lock.
+    pub fn release(&mut self, inode: u64, owner: u64) {
+        let (cancelled, pending): (Vec<_>, Vec<_>) = self
+            .pending
+            .drain(..)
+            .partition(|pending| pending.inode == inode && pending.lock.owner == owner);
+        self.pending = pending;
+        for pending in cancelled {
+            pending.reply.error(libc::EINTR);
+        }
+
+        self.unlock(inode, owner, 0, u64::MAX);
+    }
+
+    /// Remove the locks held by `owner` on the range from `start` to `end` of the file at `inode`.
+    fn remove_range(&mut self, inode: u64, owner: u64, start: u64, end: u64) {
+        let locks = match self.locks.get_mut(&inode) {
+            Some(locks) => locks,
+            None => return,
+        };
+
+        let mut remaining = Vec::with_capacity(locks.len());
+        for lock in locks.drain(..) {
+            if lock.owner != owner || !lock.overlaps(start, end) {
+                remaining.push(lock);
+                continue;
+            }
+            if lock.start < start {
+                remaining.push(FileLock {
+                    end: start - 1,
+                    ..lock.clone()
+                });
+            }
+            if lock.end > end {
+                remaining.push(FileLock {
+                    start: end + 1,
+                    ..lock
+                });
+            }
+        }
+
+        if remaining.is_empty() {
+            self.locks.remove(&inode);
+        } else {
+            *locks = remaining;
+        }
+    }
+
+    /// Acquire any pending locks on the file at `inode` which no longer conflict.
+    fn grant_pending(&mut self, inode: u64) {
+        let mut index = 0;
+        while index < self.pending.len() {
+            let pending = &self.pending[index];
+            if pending.inode == inode && self.conflict(inode, &pending.lock).is_none() {
+                let pending = self.pending.remove(index);
+                let lock = pending.lock;
+                self.remove_range(inode, lock.owner, lock.start, lock.end);
+                self.locks.entry(inode).or_default().push(lock);
+                pending.reply.ok();
+            } else {
+                index += 1;
+            }
+        }
+    }
+}
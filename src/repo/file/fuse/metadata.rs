@@ -28,6 +28,8 @@ impl crate::Error {
             crate::Error::NotEmpty => libc::ENOTEMPTY,
             crate::Error::NotDirectory => libc::ENOTDIR,
             crate::Error::NotFile => libc::EISDIR,
+            crate::Error::QuotaExceeded => libc::EDQUOT,
+            crate::Error::LimitExceeded => libc::EFBIG,
             crate::Error::Io(error) => match error.raw_os_error() {
                 Some(errno) => errno,
                 // Some third-party libraries use `std::io::Error` without there being an underlying
@@ -246,7 +248,7 @@ impl FileRepo<UnixSpecial, UnixMetadata> {
         let now = SystemTime::now();
         metadata.accessed = now;
         metadata.changed = now;
-        self.set_metadata(path, Some(metadata))
+        self.write_metadata(path, Some(metadata))
     }
 
     /// Update an entry's `ctime`.
@@ -1,13 +1,17 @@
 #![cfg(all(any(unix, doc), feature = "fuse-mount"))]
 
-pub use fs::FuseAdapter;
-pub use options::MountOption;
+pub use config::AdapterConfig;
+pub use fs::{LockRepo, SharedFuseAdapter};
+pub use options::{MountOption, XattrNamespace};
 
 mod acl;
+mod config;
+mod entry_cache;
 mod fs;
 mod handle;
 mod id_table;
 mod inode;
+mod lock;
 mod metadata;
 mod object;
 mod options;
@@ -6,13 +6,25 @@ use std::collections::hash_map::Entry;
 /// A table of open `Object` values representing open files.
 ///
 /// Objects in this table may be invalidated, in which case they are dropped lazily.
+///
+/// This also caches the sizes of files so that their attributes can be computed without opening
+/// their objects.
 #[derive(Debug)]
-pub struct ObjectTable(HashMap<u64, Object>);
+pub struct ObjectTable {
+    /// A map of inodes to open objects.
+    objects: HashMap<u64, Object>,
+
+    /// A map of inodes to the sizes of the files they represent.
+    sizes: HashMap<u64, u64>,
+}
 
 impl ObjectTable {
     /// Return a new empty `ObjectTable`.
     pub fn new() -> Self {
-        Self(HashMap::new())
+        Self {
+            objects: HashMap::new(),
+            sizes: HashMap::new(),
+        }
     }
 
     /// Return an `Object` for the file at the given `inode`.
@@ -22,7 +34,7 @@ impl ObjectTable {
     ///
     /// The returned object may have a transaction in progress.
     pub fn open(&mut self, inode: u64, default: Object) -> &mut Object {
-        match self.0.entry(inode) {
+        match self.objects.entry(inode) {
             Entry::Occupied(mut object_entry) => {
                 if !object_entry.get().is_valid() {
                     object_entry.insert(default);
@@ -37,7 +49,7 @@ impl ObjectTable {
     ///
     /// If the object is not open or has been invalidated, this returns `Ok`.
     pub fn commit(&mut self, inode: u64) -> crate::Result<()> {
-        if let HashMapEntry::Occupied(mut object_entry) = self.0.entry(inode) {
+        if let HashMapEntry::Occupied(mut object_entry) = self.objects.entry(inode) {
             if object_entry.get().is_valid() {
                 object_entry.get_mut().commit()?;
             } else {
@@ -49,7 +61,7 @@ impl ObjectTable {
 
     /// Commit changes to all objects in the table which have not been invalidated.
     pub fn commit_all(&mut self) -> crate::Result<()> {
-        let inodes = self.0.keys().copied().collect::<Vec<_>>();
+        let inodes = self.objects.keys().copied().collect::<Vec<_>>();
         for inode in inodes {
             self.commit(inode)?;
         }
@@ -65,7 +77,7 @@ impl ObjectTable {
     /// This commits changes if the object was already open to ensure there is not a transaction in
     /// progress when this method returns.
     pub fn open_commit(&mut self, inode: u64, default: Object) -> crate::Result<&mut Object> {
-        match self.0.entry(inode) {
+        match self.objects.entry(inode) {
             Entry::Occupied(mut object_entry) => {
                 if object_entry.get().is_valid() {
                     object_entry.get_mut().commit()?;
@@ -80,6 +92,34 @@ impl ObjectTable {
 
     /// Close the object for the file at the given `inode` if it is open.
     pub fn close(&mut self, inode: u64) -> bool {
-        self.0.remove(&inode).is_some()
+        self.objects.remove(&inode).is_some()
+    }
+
+    /// Return the size of the file at the given `inode`.
+    ///
+    /// If the size of the file is cached, this returns it without opening the object. Otherwise,
+    /// this opens the object with `default` as in `open_commit` and caches its size.
+    pub fn size(&mut self, inode: u64, default: impl FnOnce() -> Object) -> crate::Result<u64> {
+        if let Some(size) = self.sizes.get(&inode) {
+            return Ok(*size);
+        }
+
+        let size = self.open_commit(inode, default())?.size()?;
+        self.sizes.insert(inode, size);
+
+        Ok(size)
+    }
+
+    /// Remove the cached size of the file at the given `inode`.
+    ///
+    /// This must be called whenever the contents of the file are modified or the inode is
+    /// deallocated.
+    pub fn invalidate_size(&mut self, inode: u64) {
+        self.sizes.remove(&inode);
+    }
+
+    /// Remove the cached sizes of all files.
+    pub fn invalidate_sizes(&mut self) {
+        self.sizes.clear();
     }
 }
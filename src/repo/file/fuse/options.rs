@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 /// A mount option accepted when mounting a FUSE file system.
 ///
 /// See `man mount.fuse` for details.
@@ -72,13 +74,105 @@ pub enum MountOption {
 
     /// Pass an option which is not otherwise supported in this enum.
     Custom(String),
+
+    /// Periodically commit changes made through the file system.
+    ///
+    /// Changes to the directory tree are always committed immediately, but data written to files
+    /// is normally only committed when a file is synced with `fsync`. With this option, a
+    /// background thread commits changes to open files and to the repository each time the given
+    /// amount of time passes, whether or not the file system is handling any requests. Changes are
+    /// also committed when the file system is unmounted.
+    ///
+    /// This option is handled by this library and is not passed to libfuse.
+    AutoCommit(Duration),
+
+    /// Only accept extended attributes in the given namespaces.
+    ///
+    /// Attempting to get, set, or remove an extended attribute in any other namespace fails with
+    /// `EOPNOTSUPP`, and those attributes are not listed. The POSIX ACL attributes in the
+    /// `system.` namespace are always accepted because they're stored as file metadata. By default,
+    /// all namespaces are accepted.
+    ///
+    /// This option is handled by this library and is not passed to libfuse.
+    XattrNamespaces(Vec<XattrNamespace>),
+
+    /// Limit the size of extended attribute values to the given number of bytes.
+    ///
+    /// Attempting to set a larger value fails with `E2BIG`. By default, there is no limit beyond
+    /// the one imposed by the kernel.
+    ///
+    /// This option is handled by this library and is not passed to libfuse.
+    MaxXattrSize(usize),
+
+    /// Declare the total capacity of the file system in bytes.
+    ///
+    /// This is used to report how much space is free, such as with `df`. The free space reported
+    /// is the smallest of the remaining capacity, the remaining quota for the current instance,
+    /// and the free space reported by the data store. If none of these are known, no free space
+    /// is reported. Use this option if the data store can't report how much space is available.
+    ///
+    /// This option is handled by this library and is not passed to libfuse.
+    Capacity(u64),
+}
+
+/// A namespace for extended attributes.
+///
+/// The namespace of an extended attribute is determined by the prefix of its name.
+///
+/// See `man xattr` for details.
+#[cfg_attr(docsrs, doc(cfg(all(unix, feature = "fuse-mount"))))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum XattrNamespace {
+    /// The `user.` namespace, for arbitrary attributes set by users.
+    User,
+
+    /// The `security.` namespace, used by security modules like SELinux and for file capabilities.
+    Security,
+
+    /// The `trusted.` namespace, for attributes which are only visible to privileged processes.
+    Trusted,
+
+    /// The `system.` namespace, used by the kernel for objects like ACLs.
+    System,
+}
+
+impl XattrNamespace {
+    /// All the supported namespaces.
+    pub(crate) const ALL: [XattrNamespace; 4] = [
+        XattrNamespace::User,
+        XattrNamespace::Security,
+        XattrNamespace::Trusted,
+        XattrNamespace::System,
+    ];
+
+    /// Return the prefix of attribute names in this namespace, including the trailing dot.
+    pub fn prefix(self) -> &'static str {
+        match self {
+            XattrNamespace::User => "user.",
+            XattrNamespace::Security => "security.",
+            XattrNamespace::Trusted => "trusted.",
+            XattrNamespace::System => "system.",
+        }
+    }
+
+    /// Return the namespace of the attribute with the given `name`.
+    ///
+    /// This returns `None` if the name isn't in a known namespace.
+    pub fn of(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|namespace| name.starts_with(namespace.prefix()))
+    }
 }
 
 impl MountOption {
-    pub(crate) fn into_fuser(self) -> fuser::MountOption {
+    /// Convert this option to the equivalent `fuser` option.
+    ///
+    /// This returns `None` if the option is not passed to libfuse.
+    pub(crate) fn into_fuser(self) -> Option<fuser::MountOption> {
         use fuser::MountOption::*;
 
-        match self {
+        Some(match self {
             Self::FsName(name) => FSName(name),
             Self::Subtype(name) => Subtype(name),
             Self::AllowOther => AllowOther,
@@ -99,6 +193,10 @@ impl MountOption {
             Self::Sync => Sync,
             Self::Async => Async,
             Self::Custom(value) => CUSTOM(value),
-        }
+            Self::AutoCommit(_)
+            | Self::XattrNamespaces(_)
+            | Self::MaxXattrSize(_)
+            | Self::Capacity(_) => return None,
+        })
     }
 }
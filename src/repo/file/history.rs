@@ -0,0 +1,55 @@
+use std::time::SystemTime;
+
+use relative_path::RelativePath;
+use serde::{Deserialize, Serialize};
+
+use crate::repo::state::ObjectKey;
+
+/// The prefix of the names of the state segments which store the version history of each path.
+const HISTORY_SEGMENT_PREFIX: &str = "file-history/";
+
+/// A saved version of the contents of a file in a [`FileRepo`].
+///
+/// Versions are saved with [`FileRepo::save_version`].
+///
+/// [`FileRepo`]: crate::repo::file::FileRepo
+/// [`FileRepo::save_version`]: crate::repo::file::FileRepo::save_version
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileVersion {
+    /// The ID of this version, which is unique among the versions of its path.
+    pub(super) id: u32,
+
+    /// The time at which this version was saved.
+    pub(super) created: SystemTime,
+
+    /// The size of the file contents in bytes.
+    pub(super) size: u64,
+
+    /// A copy of the file contents as they were when this version was saved.
+    pub(super) contents: ObjectKey,
+}
+
+impl FileVersion {
+    /// The ID of this version.
+    ///
+    /// Version IDs increase each time a version of the same path is saved and are never reused
+    /// while the path has at least one saved version.
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// The time at which this version was saved according to the system clock.
+    pub fn created(&self) -> SystemTime {
+        self.created
+    }
+
+    /// The size of the file contents in this version in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+}
+
+/// Return the name of the state segment which stores the version history of `path`.
+pub fn history_segment(path: &RelativePath) -> String {
+    format!("{}{}", HISTORY_SEGMENT_PREFIX, path.normalize())
+}
@@ -4,7 +4,7 @@ use std::path::Path;
 
 use hole_punch::{ScanError, SegmentType, SparseFile};
 
-use crate::repo::Object;
+use crate::repo::{Access, Object};
 
 /// Copy the contents of the regular file at `path` to the given `object`.
 ///
@@ -62,6 +62,9 @@ pub fn archive_file(object: &mut Object, path: &Path) -> crate::Result<()> {
 pub fn extract_file(object: &mut Object, path: &Path) -> crate::Result<()> {
     assert!(matches!(object.stream_position(), Ok(0)));
 
+    // The object is copied from start to end, so fetch chunks ahead of time.
+    object.advise(Access::Sequential);
+
     let stats = object.stats()?;
     let mut file = OpenOptions::new().write(true).create_new(true).open(path)?;
 
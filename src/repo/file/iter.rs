@@ -8,7 +8,7 @@ use super::metadata::FileMetadata;
 use super::path_tree;
 use super::repository::FileRepo;
 use super::special::SpecialType;
-use crate::repo::Object;
+use crate::repo::{Format, Object};
 
 /// An iterator over the children of an entry in a [`FileRepo`].
 ///
@@ -80,32 +80,35 @@ pub enum WalkPredicate<R> {
 ///
 /// [`FileRepo`]: crate::repo::file::FileRepo
 /// [`FileRepo::walk`]: crate::repo::file::FileRepo::walk
-pub struct WalkEntry<'a, S, M>
+pub struct WalkEntry<'a, S, M, F>
 where
     S: SpecialType,
     M: FileMetadata,
+    F: Format,
 {
     pub(super) path: RelativePathBuf,
     pub(super) base: &'a RelativePath,
     pub(super) handle: EntryHandle,
     pub(super) depth: usize,
-    pub(super) repo: &'a FileRepo<S, M>,
+    pub(super) repo: &'a FileRepo<S, M, F>,
 }
 
-impl<'a, S, M> AsRef<RelativePath> for WalkEntry<'a, S, M>
+impl<'a, S, M, F> AsRef<RelativePath> for WalkEntry<'a, S, M, F>
 where
     S: SpecialType,
     M: FileMetadata,
+    F: Format,
 {
     fn as_ref(&self) -> &RelativePath {
         self.path.as_relative_path()
     }
 }
 
-impl<'a, S, M> WalkEntry<'a, S, M>
+impl<'a, S, M, F> WalkEntry<'a, S, M, F>
 where
     S: SpecialType,
     M: FileMetadata,
+    F: Format,
 {
     /// Return the path of this entry.
     pub fn path(&self) -> &RelativePath {
@@ -1,9 +1,12 @@
 use std::io;
 use std::path::Path;
+use std::time::SystemTime;
 
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 
+#[cfg(feature = "file-metadata")]
+use filetime::set_file_times;
 #[cfg(all(any(unix, doc), feature = "file-metadata"))]
 use {
     bitflags::bitflags,
@@ -16,8 +19,6 @@ use {
     std::time::{Duration, UNIX_EPOCH},
     users::{get_group_by_name, get_user_by_name},
 };
-#[cfg(feature = "file-metadata")]
-use {filetime::set_file_times, std::time::SystemTime};
 
 /// The metadata for a file in the file system.
 ///
@@ -32,6 +33,24 @@ pub trait FileMetadata: Serialize + DeserializeOwned {
 
     /// Write this metadata to the file at `path`.
     fn write_metadata(&self, path: &Path) -> io::Result<()>;
+
+    /// Record that the contents of the file were modified at `time`.
+    ///
+    /// This is called by [`FileRepo`] when [time tracking] is enabled. The default implementation
+    /// does nothing.
+    ///
+    /// [`FileRepo`]: crate::repo::file::FileRepo
+    /// [time tracking]: crate::repo::file::FileRepo::set_time_tracking
+    fn mark_modified(&mut self, _time: SystemTime) {}
+
+    /// Record that this metadata was changed at `time`.
+    ///
+    /// This is called by [`FileRepo`] when [time tracking] is enabled. The default implementation
+    /// does nothing.
+    ///
+    /// [`FileRepo`]: crate::repo::file::FileRepo
+    /// [time tracking]: crate::repo::file::FileRepo::set_time_tracking
+    fn mark_changed(&mut self, _time: SystemTime) {}
 }
 
 /// A `FileMetadata` which stores no metadata.
@@ -558,6 +577,14 @@ impl FileMetadata for UnixMetadata {
 
         Ok(())
     }
+
+    fn mark_modified(&mut self, time: SystemTime) {
+        self.modified = time;
+    }
+
+    fn mark_changed(&mut self, time: SystemTime) {
+        self.changed = time;
+    }
 }
 
 /// A `FileMetadata` for metadata that is common to most platforms.
@@ -585,4 +612,8 @@ impl FileMetadata for CommonMetadata {
     fn write_metadata(&self, path: &Path) -> io::Result<()> {
         set_file_times(path, self.accessed.into(), self.modified.into())
     }
+
+    fn mark_modified(&mut self, time: SystemTime) {
+        self.modified = time;
+    }
 }
@@ -16,7 +16,13 @@
 //! file types—are heavily platform-dependent, the behavior of [`FileRepo`] can be customized
 //! through the [`FileMetadata`] and [`SpecialType`] traits.
 //!
-//! A [`FileRepo`] can be mounted as a FUSE file system using [`FileRepo::mount`].
+//! A [`FileRepo`] can be mounted as a FUSE file system using [`FileRepo::mount`]. To access the
+//! repository from other threads while it's mounted, convert it into a [`SharedFileRepo`] and mount
+//! it using [`SharedFileRepo::mount`].
+//!
+//! Previous contents of a file can be saved using [`FileRepo::save_version`] and later restored
+//! using [`FileRepo::restore_version`]. Saved versions are kept by path, so they can be used to
+//! recover files which have since been removed or replaced.
 //!
 //! Like other repositories, changes made to the repository are not persisted to the data store
 //! until [`Commit::commit`] is called. For details about deduplication, compression, encryption,
@@ -33,6 +39,10 @@
 //! Instead, entry paths are relative paths relative to the root of the repository. A top-level
 //! directory `foo` containing a file `bar` is represented as `foo/bar`.
 //!
+//! By default, paths are case-sensitive and compared byte-for-byte. To make entries archived on
+//! platforms with case-insensitive or normalizing file systems behave as they did there, you can
+//! change how paths are compared using [`FileRepo::set_path_semantics`].
+//!
 //! # Metadata
 //!
 //! A [`FileRepo`] accepts a [`FileMetadata`] type parameter which determines how it handles file
@@ -50,16 +60,30 @@
 //! [`SpecialType`] implementation than it was stored with, it will fail to deserialize and return
 //! an error.
 //!
+//! # Serialization
+//!
+//! A [`FileRepo`] accepts a [`Format`] type parameter which determines how entries, including
+//! their metadata, are serialized. The default value is [`MessagePack`]. If you attempt to open a
+//! repository using a different [`Format`] than it was created with, it will return
+//! `Error::UnsupportedRepo`.
+//!
 //! [`FileRepo`]: crate::repo::file::FileRepo
+//! [`Format`]: crate::repo::Format
+//! [`MessagePack`]: crate::repo::MessagePack
 //! [`Entry`]: crate::repo::file::Entry
 //! [`FileRepo::archive`]: crate::repo::file::FileRepo::archive
 //! [`FileRepo::archive_tree`]: crate::repo::file::FileRepo::archive_tree
 //! [`FileRepo::extract`]: crate::repo::file::FileRepo::extract
 //! [`FileRepo::extract_tree`]: crate::repo::file::FileRepo::extract_tree
 //! [`RelativePath`]: crate::repo::file::RelativePath
+//! [`FileRepo::set_path_semantics`]: crate::repo::file::FileRepo::set_path_semantics
 //! [`FileMetadata`]: crate::repo::file::FileMetadata
 //! [`SpecialType`]: crate::repo::file::SpecialType
 //! [`FileRepo::mount`]: crate::repo::file::FileRepo::mount
+//! [`FileRepo::save_version`]: crate::repo::file::FileRepo::save_version
+//! [`FileRepo::restore_version`]: crate::repo::file::FileRepo::restore_version
+//! [`SharedFileRepo`]: crate::repo::file::SharedFileRepo
+//! [`SharedFileRepo::mount`]: crate::repo::file::SharedFileRepo::mount
 //! [`Commit::commit`]: crate::repo::Commit::commit
 //! [`NoMetadata`]: crate::repo::file::NoMetadata
 //! [`NoSpecial`]: crate::repo::file::NoSpecial
@@ -74,22 +98,40 @@ pub use {
     self::special::UnixSpecial,
 };
 
+pub use self::conflict::ParentConflict;
 pub use self::entry::{Entry, EntryId, EntryType};
+pub use self::history::FileVersion;
 pub use self::iter::{Children, Descendants, WalkEntry, WalkPredicate};
 #[cfg(feature = "file-metadata")]
 pub use self::metadata::CommonMetadata;
 pub use self::metadata::{FileMetadata, NoMetadata};
+pub use self::options::{ArchiveOptions, EntryLimits, ExtractOptions};
+pub use self::progress::{Progress, ProgressAction};
 pub use self::repository::FileRepo;
+pub use self::semantics::PathSemantics;
+pub use self::shared::SharedFileRepo;
 pub use self::special::{NoSpecial, SpecialType};
+pub use self::stats::TreeStats;
+pub use self::sync::{ChangeDetection, SyncOptions};
+pub use self::watch::EntryEvent;
 
 #[cfg(all(any(unix, doc), feature = "fuse-mount"))]
-pub use self::fuse::MountOption;
+pub use self::fuse::{MountOption, XattrNamespace};
 
+mod conflict;
 mod entry;
 mod fuse;
+mod history;
 mod holes;
 mod iter;
 mod metadata;
+mod options;
 mod path_tree;
+mod progress;
 mod repository;
+mod semantics;
+mod shared;
 mod special;
+mod stats;
+mod sync;
+mod watch;
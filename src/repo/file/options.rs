@@ -0,0 +1,247 @@
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use relative_path::RelativePath;
+
+/// Options for selecting which files are copied by [`FileRepo::archive_tree_with`].
+///
+/// This type implements `Default`, which returns options that archive every file in the tree.
+///
+/// Glob patterns are matched against the path of each file relative to the source directory,
+/// using `/` as the path separator. A `*` matches any sequence of characters except `/`, and `**`
+/// matches any number of directories. For example, `*.txt` only matches files directly inside the
+/// source directory, while `**/*.txt` matches files at any depth.
+///
+/// [`FileRepo::archive_tree_with`]: crate::repo::file::FileRepo::archive_tree_with
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+#[non_exhaustive]
+pub struct ArchiveOptions {
+    /// Glob patterns for files to archive.
+    ///
+    /// If this is not empty, only files and special files which match at least one of these
+    /// patterns are archived, and directories are only archived if they contain a file which is
+    /// archived. If this is empty, every file is archived.
+    ///
+    /// The default value is empty.
+    pub include: Vec<String>,
+
+    /// Glob patterns for files to skip.
+    ///
+    /// If a directory matches one of these patterns, none of its descendants are archived. This
+    /// takes precedence over `include`.
+    ///
+    /// The default value is empty.
+    pub exclude: Vec<String>,
+
+    /// The maximum depth of files to archive, or `None` if there is no limit.
+    ///
+    /// The source directory has a depth of `0`, its children have a depth of `1`, and so on.
+    ///
+    /// The default value is `None`.
+    pub max_depth: Option<usize>,
+
+    /// Whether to descend into symbolic links to directories.
+    ///
+    /// Symbolic links to regular files are always archived as the file they point to.
+    ///
+    /// The default value is `false`.
+    pub follow_symlinks: bool,
+
+    /// The maximum size of regular files to archive in bytes, or `None` if there is no limit.
+    ///
+    /// Files which are larger than this are skipped.
+    ///
+    /// The default value is `None`.
+    pub max_file_size: Option<u64>,
+
+    /// Whether to preserve hard links in the source tree.
+    ///
+    /// If this is `true`, files in the source tree which are hard links to the same file are
+    /// detected by their device and inode numbers. The first one is archived normally, and the
+    /// rest are added with [`FileRepo::link`] so that they share the same entry. Extracting the
+    /// tree with [`FileRepo::extract_tree`] then recreates them as hard links. If this is `false`,
+    /// each hard link is archived as a separate entry.
+    ///
+    /// Hard links are only detected on Unix-like platforms.
+    ///
+    /// The default value is `false`.
+    ///
+    /// [`FileRepo::link`]: crate::repo::file::FileRepo::link
+    /// [`FileRepo::extract_tree`]: crate::repo::file::FileRepo::extract_tree
+    pub detect_hard_links: bool,
+}
+
+/// Options for selecting which entries are copied by [`FileRepo::extract_tree_with`].
+///
+/// This type implements `Default`, which returns options that extract every entry in the tree.
+///
+/// Glob patterns are matched the same way as they are for [`ArchiveOptions`], against the path of
+/// each entry relative to the source entry.
+///
+/// [`FileRepo::extract_tree_with`]: crate::repo::file::FileRepo::extract_tree_with
+/// [`ArchiveOptions`]: crate::repo::file::ArchiveOptions
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+#[non_exhaustive]
+pub struct ExtractOptions {
+    /// Glob patterns for entries to extract.
+    ///
+    /// If this is not empty, only file entries and special file entries which match at least one
+    /// of these patterns are extracted, and directory entries are only extracted if they contain
+    /// an entry which is extracted. If this is empty, every entry is extracted.
+    ///
+    /// The default value is empty.
+    pub include: Vec<String>,
+
+    /// Glob patterns for entries to skip.
+    ///
+    /// If a directory entry matches one of these patterns, none of its descendants are extracted.
+    /// This takes precedence over `include`.
+    ///
+    /// The default value is empty.
+    pub exclude: Vec<String>,
+
+    /// The maximum depth of entries to extract, or `None` if there is no limit.
+    ///
+    /// The source entry has a depth of `0`, its children have a depth of `1`, and so on.
+    ///
+    /// The default value is `None`.
+    pub max_depth: Option<usize>,
+
+    /// The maximum size of file entries to extract in bytes, or `None` if there is no limit.
+    ///
+    /// File entries which are larger than this are skipped.
+    ///
+    /// The default value is `None`.
+    pub max_file_size: Option<u64>,
+}
+
+/// Size limits which are enforced when entries are added to a [`FileRepo`].
+///
+/// Limits are checked before an entry is added or modified, and operations which would exceed a
+/// limit return `Error::LimitExceeded` without changing the repository. This makes it possible to
+/// reject oversized entries up front instead of when the data store fails to write them.
+///
+/// This type implements `Default`, which returns limits that allow entries of any size.
+///
+/// These limits are set with [`FileRepo::set_limits`].
+///
+/// [`FileRepo`]: crate::repo::file::FileRepo
+/// [`FileRepo::set_limits`]: crate::repo::file::FileRepo::set_limits
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+#[non_exhaustive]
+pub struct EntryLimits {
+    /// The maximum length of an entry path in bytes, or `None` if there is no limit.
+    ///
+    /// The default value is `None`.
+    pub max_path_len: Option<usize>,
+
+    /// The maximum size of an entry's serialized metadata in bytes, or `None` if there is no
+    /// limit.
+    ///
+    /// This includes everything stored with the entry, like extended attributes and ACLs.
+    ///
+    /// The default value is `None`.
+    pub max_metadata_size: Option<usize>,
+
+    /// The maximum size of a regular file in bytes, or `None` if there is no limit.
+    ///
+    /// This is checked when files are archived and when they're written to through a FUSE file
+    /// system. It can't be checked when writing to an [`Object`] returned by [`FileRepo::open`].
+    ///
+    /// The default value is `None`.
+    ///
+    /// [`Object`]: crate::repo::Object
+    /// [`FileRepo::open`]: crate::repo::file::FileRepo::open
+    pub max_file_size: Option<u64>,
+}
+
+impl EntryLimits {
+    /// Return an error if `path` exceeds these limits.
+    ///
+    /// # Errors
+    /// - `Error::LimitExceeded`: The path is too long.
+    pub(super) fn check_path(&self, path: &RelativePath) -> crate::Result<()> {
+        match self.max_path_len {
+            Some(max_len) if path.as_str().len() > max_len => Err(crate::Error::LimitExceeded),
+            _ => Ok(()),
+        }
+    }
+
+    /// Return an error if serialized metadata of `size` bytes exceeds these limits.
+    ///
+    /// # Errors
+    /// - `Error::LimitExceeded`: The metadata is too large.
+    pub(super) fn check_metadata_size(&self, size: usize) -> crate::Result<()> {
+        match self.max_metadata_size {
+            Some(max_size) if size > max_size => Err(crate::Error::LimitExceeded),
+            _ => Ok(()),
+        }
+    }
+
+    /// Return an error if a file of `size` bytes exceeds these limits.
+    ///
+    /// # Errors
+    /// - `Error::LimitExceeded`: The file is too large.
+    pub(super) fn check_file_size(&self, size: u64) -> crate::Result<()> {
+        match self.max_file_size {
+            Some(max_size) if size > max_size => Err(crate::Error::LimitExceeded),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// A compiled set of include and exclude patterns.
+#[derive(Debug)]
+pub struct PathFilter {
+    /// The include patterns, or `None` if every path is included.
+    include: Option<GlobSet>,
+
+    /// The exclude patterns.
+    exclude: GlobSet,
+}
+
+impl PathFilter {
+    /// Compile the given `include` and `exclude` patterns.
+    ///
+    /// # Errors
+    /// - `Error::InvalidPath`: One of the patterns is not a valid glob pattern.
+    pub fn new(include: &[String], exclude: &[String]) -> crate::Result<Self> {
+        Ok(Self {
+            include: if include.is_empty() {
+                None
+            } else {
+                Some(compile_patterns(include)?)
+            },
+            exclude: compile_patterns(exclude)?,
+        })
+    }
+
+    /// Return whether every path is included unless it is excluded.
+    pub fn includes_all(&self) -> bool {
+        self.include.is_none()
+    }
+
+    /// Return whether the given `path` is excluded.
+    pub fn is_excluded(&self, path: &RelativePath) -> bool {
+        self.exclude.is_match(path.as_str())
+    }
+
+    /// Return whether the given `path` is included.
+    pub fn is_included(&self, path: &RelativePath) -> bool {
+        match &self.include {
+            Some(include) => include.is_match(path.as_str()),
+            None => true,
+        }
+    }
+}
+
+/// Compile the given glob `patterns` into a `GlobSet`.
+fn compile_patterns(patterns: &[String]) -> crate::Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = GlobBuilder::new(pattern)
+            .literal_separator(true)
+            .build()
+            .map_err(|_| crate::Error::InvalidPath)?;
+        builder.add(glob);
+    }
+    builder.build().map_err(|_| crate::Error::InvalidPath)
+}
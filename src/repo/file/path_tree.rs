@@ -1,20 +1,22 @@
-use std::collections::{hash_map, HashMap};
+use std::collections::{hash_map, HashMap, HashSet};
 use std::fmt::{Debug, Formatter};
 use std::iter::{self, ExactSizeIterator, FusedIterator};
+use std::mem;
 
 use relative_path::{RelativePath, RelativePathBuf};
 use serde::{Deserialize, Serialize};
 
 use super::iter::WalkPredicate;
+use super::semantics::PathSemantics;
 
 /// Recursively iterate through the tree of nodes.
 fn descendants<'a, V>(
     parent: impl AsRef<RelativePath> + 'a,
     children: &'a HashMap<String, PathNode<V>>,
 ) -> Box<dyn Iterator<Item = (RelativePathBuf, &'a V)> + 'a> {
-    Box::new(children.iter().flat_map(move |(name, node)| {
-        iter::once((parent.as_ref().join(name), &node.value))
-            .chain(descendants(parent.as_ref().join(name), &node.children))
+    Box::new(children.iter().flat_map(move |(key, node)| {
+        let path = parent.as_ref().join(node.name(key));
+        iter::once((path.clone(), &node.value)).chain(descendants(path, &node.children))
     }))
 }
 
@@ -23,10 +25,14 @@ fn drain_nodes<'a, V: 'a>(
     parent: impl AsRef<RelativePath> + 'a,
     children: HashMap<String, PathNode<V>>,
 ) -> Box<dyn Iterator<Item = (RelativePathBuf, V)> + 'a> {
-    Box::new(children.into_iter().flat_map(move |(name, node)| {
-        let PathNode { children, value } = node;
-        iter::once((parent.as_ref().join(&name), value))
-            .chain(drain_nodes(parent.as_ref().join(&name), children))
+    Box::new(children.into_iter().flat_map(move |(key, node)| {
+        let PathNode {
+            children,
+            value,
+            name,
+        } = node;
+        let path = parent.as_ref().join(name.unwrap_or(key));
+        iter::once((path.clone(), value)).chain(drain_nodes(path, children))
     }))
 }
 
@@ -43,7 +49,7 @@ impl<'a, V> Iterator for Children<'a, V> {
     fn next(&mut self) -> Option<Self::Item> {
         self.children
             .next()
-            .map(|(name, node)| (self.parent.join(name), &node.value))
+            .map(|(key, node)| (self.parent.join(node.name(key)), &node.value))
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
@@ -98,8 +104,13 @@ where
     let predicate: WalkPredicate<R> = visitor(walk_entry);
 
     if let WalkPredicate::Continue | WalkPredicate::SkipSiblings = predicate {
-        for (child_name, child_node) in &node.children {
-            match walk(parent.join(child_name), child_node, depth + 1, visitor) {
+        for (child_key, child_node) in &node.children {
+            match walk(
+                parent.join(child_node.name(child_key)),
+                child_node,
+                depth + 1,
+                visitor,
+            ) {
                 WalkPredicate::SkipSiblings => return predicate,
                 WalkPredicate::Stop(value) => return WalkPredicate::Stop(value),
                 _ => {}
@@ -121,32 +132,76 @@ impl<'a, V> Debug for Descendants<'a, V> {
 /// A node in a `PathTree`.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct PathNode<V> {
-    /// The file's children.
+    /// The file's children, keyed by the form of their names used for comparison.
     children: HashMap<String, PathNode<V>>,
 
     /// The associated value.
     value: V,
+
+    /// The file's name, or `None` if it's the same as its key.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
 }
 
 impl<V> PathNode<V> {
-    fn new(value: V) -> Self {
+    fn new(value: V, name: Option<String>) -> Self {
         PathNode {
             children: HashMap::new(),
             value,
+            name,
         }
     }
+
+    /// Return the file's name given the `key` it's stored under.
+    fn name<'a>(&'a self, key: &'a str) -> &'a str {
+        self.name.as_deref().unwrap_or(key)
+    }
+}
+
+/// Return whether any two sibling nodes in `nodes` or their descendants have names which would
+/// be the same under `semantics`.
+fn has_conflicts<V>(nodes: &HashMap<String, PathNode<V>>, semantics: PathSemantics) -> bool {
+    let mut keys = HashSet::with_capacity(nodes.len());
+    nodes.iter().any(|(key, node)| {
+        !keys.insert(semantics.key(node.name(key))) || has_conflicts(&node.children, semantics)
+    })
+}
+
+/// Recursively re-key `nodes` according to `semantics`.
+fn rekey_nodes<V>(
+    nodes: HashMap<String, PathNode<V>>,
+    semantics: PathSemantics,
+) -> HashMap<String, PathNode<V>> {
+    nodes
+        .into_iter()
+        .map(|(key, node)| {
+            let name = node.name.unwrap_or(key);
+            let new_key = semantics.key(&name).into_owned();
+            let new_node = PathNode {
+                children: rekey_nodes(node.children, semantics),
+                value: node.value,
+                name: if new_key == name { None } else { Some(name) },
+            };
+            (new_key, new_node)
+        })
+        .collect()
 }
 
 /// A tree that associates file paths with values of type `V`.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PathTree<V> {
     nodes: HashMap<String, PathNode<V>>,
+
+    /// How file names are compared when looking up paths.
+    #[serde(default)]
+    semantics: PathSemantics,
 }
 
 impl<V> Default for PathTree<V> {
     fn default() -> Self {
         Self {
             nodes: HashMap::new(),
+            semantics: PathSemantics::default(),
         }
     }
 }
@@ -156,14 +211,54 @@ impl<V> PathTree<V> {
     pub fn new() -> Self {
         PathTree {
             nodes: HashMap::new(),
+            semantics: PathSemantics::default(),
         }
     }
 
+    /// Return how file names are compared when looking up paths.
+    pub fn semantics(&self) -> PathSemantics {
+        self.semantics
+    }
+
+    /// Change how file names are compared when looking up paths.
+    ///
+    /// This returns `false` and leaves the tree unchanged if two paths in the tree would refer to
+    /// the same path under the new `semantics`.
+    pub fn set_semantics(&mut self, semantics: PathSemantics) -> bool {
+        if semantics == self.semantics {
+            return true;
+        }
+        if has_conflicts(&self.nodes, semantics) {
+            return false;
+        }
+        self.nodes = rekey_nodes(mem::take(&mut self.nodes), semantics);
+        self.semantics = semantics;
+        true
+    }
+
     /// Returns whether the given `path` is in the tree.
     pub fn contains(&self, path: impl AsRef<RelativePath>) -> bool {
         self.get(path).is_some()
     }
 
+    /// Return whether `base` is a prefix of `path` when file names are compared according to this
+    /// tree's semantics.
+    pub fn starts_with(
+        &self,
+        path: impl AsRef<RelativePath>,
+        base: impl AsRef<RelativePath>,
+    ) -> bool {
+        let mut path_segments = path.as_ref().iter();
+        base.as_ref()
+            .iter()
+            .all(|base_segment| match path_segments.next() {
+                Some(path_segment) => {
+                    self.semantics.key(path_segment) == self.semantics.key(base_segment)
+                }
+                None => false,
+            })
+    }
+
     /// Return the value associated with `path`.
     ///
     /// This returns `None` if `path` is not in the tree or does not have a value associated with
@@ -173,7 +268,7 @@ impl<V> PathTree<V> {
         let mut current_value = None;
 
         for segment in path.as_ref().iter() {
-            let node = current_nodes.get(segment)?;
+            let node = current_nodes.get(self.semantics.key(segment).as_ref())?;
             current_nodes = &node.children;
             current_value = Some(&node.value);
         }
@@ -186,11 +281,12 @@ impl<V> PathTree<V> {
     /// This returns `None` if `path` is not in the tree or does not have a value associated with
     /// it.
     pub fn get_mut(&mut self, path: impl AsRef<RelativePath>) -> Option<&mut V> {
+        let semantics = self.semantics;
         let mut current_nodes = &mut self.nodes;
         let mut current_value = None;
 
         for segment in path.as_ref().iter() {
-            let node = current_nodes.get_mut(segment)?;
+            let node = current_nodes.get_mut(semantics.key(segment).as_ref())?;
             current_nodes = &mut node.children;
             current_value = Some(&mut node.value);
         }
@@ -205,12 +301,13 @@ impl<V> PathTree<V> {
     /// # Panics
     /// - The parent path does not exist.
     pub fn insert(&mut self, path: impl AsRef<RelativePath>, value: V) -> Option<V> {
+        let semantics = self.semantics;
         let mut current_nodes = &mut self.nodes;
         let mut segments = path.as_ref().iter();
         let mut segment = segments.next()?;
 
         for next_segment in segments {
-            let node = match current_nodes.get_mut(segment) {
+            let node = match current_nodes.get_mut(semantics.key(segment).as_ref()) {
                 Some(node) => node,
                 None => panic!("The parent path does not exist."),
             };
@@ -220,8 +317,14 @@ impl<V> PathTree<V> {
             segment = next_segment;
         }
 
+        let key = semantics.key(segment).into_owned();
+        let name = if key == segment {
+            None
+        } else {
+            Some(segment.to_string())
+        };
         current_nodes
-            .insert(segment.to_string(), PathNode::new(value))
+            .insert(key, PathNode::new(value, name))
             .map(|node| node.value)
     }
 
@@ -229,17 +332,18 @@ impl<V> PathTree<V> {
     ///
     /// If the path is in the tree, this returns its value. Otherwise, this returns `None`.
     pub fn remove(&mut self, path: impl AsRef<RelativePath>) -> Option<V> {
+        let semantics = self.semantics;
         let mut current_nodes = &mut self.nodes;
         let mut segments = path.as_ref().iter();
         let mut segment = segments.next()?;
 
         for next_segment in segments {
-            let node = current_nodes.get_mut(segment)?;
+            let node = current_nodes.get_mut(semantics.key(segment).as_ref())?;
             current_nodes = &mut node.children;
             segment = next_segment;
         }
 
-        Some(current_nodes.remove(segment)?.value)
+        Some(current_nodes.remove(semantics.key(segment).as_ref())?.value)
     }
 
     /// Return an iterator of the children of `path` and their values.
@@ -251,7 +355,9 @@ impl<V> PathTree<V> {
         let mut current_nodes = &self.nodes;
 
         for segment in path.as_ref().iter() {
-            current_nodes = &current_nodes.get(segment)?.children;
+            current_nodes = &current_nodes
+                .get(self.semantics.key(segment).as_ref())?
+                .children;
         }
 
         Some(Children {
@@ -273,7 +379,9 @@ impl<V> PathTree<V> {
         let mut current_nodes = &self.nodes;
 
         for segment in path.as_ref().iter() {
-            current_nodes = &current_nodes.get(segment)?.children;
+            current_nodes = &current_nodes
+                .get(self.semantics.key(segment).as_ref())?
+                .children;
         }
 
         Some(Descendants {
@@ -297,15 +405,15 @@ impl<V> PathTree<V> {
         let mut current_nodes = &self.nodes;
 
         for segment in parent.as_ref().iter() {
-            current_nodes = match current_nodes.get(segment) {
+            current_nodes = match current_nodes.get(self.semantics.key(segment).as_ref()) {
                 Some(node) => &node.children,
                 None => return None,
             };
         }
 
-        for (child_name, child_node) in current_nodes {
+        for (child_key, child_node) in current_nodes {
             match walk(
-                parent.as_ref().join(child_name),
+                parent.as_ref().join(child_node.name(child_key)),
                 child_node,
                 1,
                 &mut visitor,
@@ -329,17 +437,20 @@ impl<V> PathTree<V> {
         &'a mut self,
         path: impl AsRef<RelativePath> + 'a,
     ) -> Option<Box<dyn Iterator<Item = (RelativePathBuf, V)> + 'a>> {
+        let semantics = self.semantics;
         let mut current_nodes = &mut self.nodes;
         let mut segments = path.as_ref().iter();
         let mut segment = segments.next()?;
 
         for next_segment in segments {
-            let node = current_nodes.get_mut(segment)?;
+            let node = current_nodes.get_mut(semantics.key(segment).as_ref())?;
             current_nodes = &mut node.children;
             segment = next_segment;
         }
 
-        let PathNode { value, children } = current_nodes.remove(segment)?;
+        let PathNode {
+            value, children, ..
+        } = current_nodes.remove(semantics.key(segment).as_ref())?;
         Some(Box::new(
             iter::once((path.as_ref().to_owned(), value)).chain(drain_nodes(path, children)),
         ))
@@ -360,7 +471,7 @@ mod tests {
     use spectral::prelude::*;
 
     use crate::repo::file::path_tree::PathTree;
-    use crate::repo::file::WalkPredicate;
+    use crate::repo::file::{PathSemantics, WalkPredicate};
 
     #[test]
     fn tree_contains_path() {
@@ -652,4 +763,42 @@ mod tests {
 
         assert_that!(actual).is_equal_to(expected);
     }
+
+    #[test]
+    fn case_insensitive_tree_preserves_names() {
+        let mut tree = PathTree::new();
+        tree.set_semantics(PathSemantics::CaseInsensitive);
+        tree.insert("Dir", 1);
+        tree.insert("DIR/File", 2);
+
+        let actual = tree.descendants("").unwrap().collect::<HashSet<_>>();
+        let expected = hashset![
+            (RelativePathBuf::from("Dir"), &1),
+            (RelativePathBuf::from("Dir/File"), &2),
+        ];
+
+        assert_that!(tree.get("dir/file")).contains_value(&2);
+        assert_that!(actual).is_equal_to(expected);
+    }
+
+    #[test]
+    fn normalized_tree_matches_equivalent_names() {
+        let mut tree = PathTree::new();
+        tree.set_semantics(PathSemantics::Normalized);
+        tree.insert("cafe\u{301}", 1);
+
+        assert_that!(tree.get("caf\u{e9}")).contains_value(&1);
+        assert_that!(tree.get("CAF\u{c9}")).is_none();
+    }
+
+    #[test]
+    fn changing_semantics_with_conflicting_names_fails() {
+        let mut tree = PathTree::new();
+        tree.insert("a", 1);
+        tree.insert("A", 2);
+
+        assert_that!(tree.set_semantics(PathSemantics::CaseInsensitive)).is_false();
+        assert_that!(tree.semantics()).is_equal_to(PathSemantics::CaseSensitive);
+        assert_that!(tree.get("A")).contains_value(&2);
+    }
 }
@@ -0,0 +1,33 @@
+use std::path::Path;
+
+/// The progress of a long-running operation on a [`FileRepo`].
+///
+/// This is passed to the callback given to methods like [`FileRepo::archive_tree_with_progress`]
+/// and [`FileRepo::extract_tree_with_progress`] after each file is processed.
+///
+/// [`FileRepo`]: crate::repo::file::FileRepo
+/// [`FileRepo::archive_tree_with_progress`]: crate::repo::file::FileRepo::archive_tree_with_progress
+/// [`FileRepo::extract_tree_with_progress`]: crate::repo::file::FileRepo::extract_tree_with_progress
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Progress<'a> {
+    /// The path of the file in the file system which was just processed.
+    pub path: &'a Path,
+
+    /// The number of files which have been processed so far, including this one.
+    pub files: u64,
+
+    /// The number of bytes of file contents which have been copied so far.
+    pub bytes: u64,
+}
+
+/// What to do after reporting the [`Progress`] of a long-running operation.
+///
+/// [`Progress`]: crate::repo::file::Progress
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ProgressAction {
+    /// Continue the operation.
+    Continue,
+
+    /// Stop the operation and return `Error::Cancelled`.
+    Cancel,
+}
@@ -1,9 +1,14 @@
+use std::collections::hash_map::Entry as HashMapEntry;
 use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
-use std::fs::{create_dir, create_dir_all, hard_link, metadata};
+use std::fs::{create_dir, create_dir_all, hard_link, metadata, File, Metadata};
 use std::io;
 use std::marker::PhantomData;
+use std::mem;
 use std::path::{Path, PathBuf};
+use std::sync::mpsc::Receiver;
+use std::sync::Mutex;
+use std::time::SystemTime;
 
 use once_cell::sync::Lazy;
 use relative_path::{RelativePath, RelativePathBuf};
@@ -12,22 +17,36 @@ use uuid::uuid;
 use walkdir::WalkDir;
 
 use crate::repo::{
-    key::KeyRepo, state::StateRepo, Commit, InstanceId, Object, OpenRepo, RepoInfo, RepoStats,
-    ResourceLimit, RestoreSavepoint, Savepoint, Unlock, VersionId,
+    key::KeyRepo,
+    state::{ObjectKey, StateRepo},
+    Checkpoints, Chunking, Commit, CommitRecord, ContentId, Format, HookId, InstanceId,
+    InstanceSummary, MessagePack, Object, OpenRepo, ReadOnlyObject, RepackOptions, RepoEvent,
+    RepoInfo, RepoStats, ResourceLimit, RestoreSavepoint, Savepoint, Unlock, UsageStats, VersionId,
 };
 
+use super::conflict::ParentConflict;
 use super::entry::{Entry, EntryHandle, EntryType, HandleType};
+use super::history::{history_segment, FileVersion};
 use super::holes::{archive_file, extract_file};
 use super::iter::{Children, Descendants, WalkEntry, WalkPredicate};
 use super::metadata::{FileMetadata, NoMetadata};
+use super::options::{ArchiveOptions, EntryLimits, ExtractOptions, PathFilter};
 use super::path_tree::PathTree;
+use super::progress::{Progress, ProgressAction};
+use super::semantics::PathSemantics;
+use super::shared::SharedFileRepo;
 use super::special::{NoSpecial, SpecialType};
+use super::stats::TreeStats;
+use super::sync::{ChangeDetection, SyncOptions};
+use super::watch::{EntryEvent, Watchers};
 use crate::repo::file::entry::EntryId;
 #[cfg(all(any(unix, doc), feature = "fuse-mount"))]
 use {
-    super::fuse::{FuseAdapter, MountOption},
+    super::fuse::{AdapterConfig, LockRepo, MountOption, SharedFuseAdapter},
     super::metadata::UnixMetadata,
     super::special::UnixSpecial,
+    std::sync::mpsc::{self, RecvTimeoutError},
+    std::thread,
 };
 
 /// The path of the root entry.
@@ -55,23 +74,34 @@ impl Default for RepoState {
 ///
 /// See [`crate::repo::file`] for more information.
 #[derive(Debug)]
-pub struct FileRepo<S = NoSpecial, M = NoMetadata>
+pub struct FileRepo<S = NoSpecial, M = NoMetadata, F = MessagePack>
 where
     S: SpecialType,
     M: FileMetadata,
+    F: Format,
 {
     pub(super) repo: StateRepo<RepoState>,
-    marker: PhantomData<(S, M)>,
+    watchers: Watchers,
+    limits: EntryLimits,
+    track_times: bool,
+    /// The files which have been opened since changes were last committed and their contents at
+    /// the time they were first opened.
+    ///
+    /// This is only populated when time tracking is enabled.
+    opened_files: Mutex<HashMap<EntryId, (EntryHandle, ContentId)>>,
+    marker: PhantomData<(S, M, F)>,
 }
 
-impl<S, M> OpenRepo for FileRepo<S, M>
+impl<S, M, F> OpenRepo for FileRepo<S, M, F>
 where
     S: SpecialType,
     M: FileMetadata,
+    F: Format,
 {
     type Key = <StateRepo<RepoState> as OpenRepo>::Key;
 
-    const VERSION_ID: VersionId = VersionId::new(uuid!("57ac9d00-fde6-11eb-82cd-1f2bdd384d98"));
+    const VERSION_ID: VersionId =
+        VersionId::new(uuid!("57ac9d00-fde6-11eb-82cd-1f2bdd384d98")).with_format::<F>();
 
     fn open_repo(repo: KeyRepo<Self::Key>) -> crate::Result<Self>
     where
@@ -79,6 +109,10 @@ where
     {
         Ok(Self {
             repo: StateRepo::open_repo(repo)?,
+            watchers: Watchers::default(),
+            limits: EntryLimits::default(),
+            track_times: false,
+            opened_files: Mutex::new(HashMap::new()),
             marker: PhantomData,
         })
     }
@@ -89,6 +123,10 @@ where
     {
         Ok(Self {
             repo: StateRepo::create_repo(repo)?,
+            watchers: Watchers::default(),
+            limits: EntryLimits::default(),
+            track_times: false,
+            opened_files: Mutex::new(HashMap::new()),
             marker: PhantomData,
         })
     }
@@ -98,10 +136,11 @@ where
     }
 }
 
-impl<S, M> FileRepo<S, M>
+impl<S, M, F> FileRepo<S, M, F>
 where
     S: SpecialType,
     M: FileMetadata,
+    F: Format,
 {
     /// Return whether there is an entry at `path`.
     pub fn exists(&self, path: impl AsRef<RelativePath>) -> bool {
@@ -208,6 +247,7 @@ where
     /// - `Error::InvalidData`: Ciphertext verification failed.
     /// - `Error::Store`: An error occurred with the data store.
     /// - `Error::Io`: An I/O error occurred.
+    /// - `Error::LimitExceeded`: The `path` or `entry` exceeds the configured size limits.
     pub fn create(
         &mut self,
         path: impl AsRef<RelativePath>,
@@ -219,9 +259,12 @@ where
             return Err(crate::Error::AlreadyExists);
         }
 
+        self.limits.check_path(path.as_ref())?;
+        self.check_entry_limits(entry)?;
+
         let entry_key = self.repo.create();
         let mut object = self.repo.object(entry_key).unwrap();
-        let result = object.serialize(entry);
+        let result = object.serialize_with::<F, _>(entry);
         drop(object);
         if let Err(error) = result {
             self.repo.remove(entry_key);
@@ -241,6 +284,8 @@ where
 
         self.repo.state_mut().links.insert(handle.id(), 1);
         self.repo.state_mut().tree.insert(path.as_ref(), handle);
+        self.watchers
+            .notify(EntryEvent::Created(path.as_ref().to_owned()));
 
         Ok(())
     }
@@ -249,18 +294,50 @@ where
     ///
     /// This also creates any missing parent directories.
     ///
+    /// This is equivalent to calling [`create_parents_with`] with [`ParentConflict::Error`].
+    ///
     /// # Errors
     /// - `Error::InvalidPath`: The given `path` is empty.
     /// - `Error::AlreadyExists`: There is already an entry at `path`.
+    /// - `Error::NotDirectory`: An ancestor of `path` exists but is not a directory entry.
     /// - `Error::InvalidData`: Ciphertext verification failed.
     /// - `Error::Serialize`: The new file metadata could not be serialized.
     /// - `Error::Deserialize`: The old file metadata could not be deserialized.
     /// - `Error::Store`: An error occurred with the data store.
     /// - `Error::Io`: An I/O error occurred.
+    /// - `Error::LimitExceeded`: The `path` or `entry` exceeds the configured size limits.
+    ///
+    /// [`create_parents_with`]: crate::repo::file::FileRepo::create_parents_with
+    /// [`ParentConflict::Error`]: crate::repo::file::ParentConflict::Error
     pub fn create_parents(
         &mut self,
         path: impl AsRef<RelativePath>,
         entry: &Entry<S, M>,
+    ) -> crate::Result<()> {
+        self.create_parents_with(path, entry, ParentConflict::Error)
+    }
+
+    /// Add a new empty file or directory entry to the repository at the given `path`.
+    ///
+    /// This also creates any missing parent directories. If an ancestor of `path` exists but is
+    /// not a directory, it is handled according to `conflict`.
+    ///
+    /// # Errors
+    /// - `Error::InvalidPath`: The given `path` is empty.
+    /// - `Error::AlreadyExists`: There is already an entry at `path`.
+    /// - `Error::NotDirectory`: An ancestor of `path` exists but is not a directory entry and
+    /// `conflict` is `ParentConflict::Error`.
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Serialize`: The new file metadata could not be serialized.
+    /// - `Error::Deserialize`: The old file metadata could not be deserialized.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    /// - `Error::LimitExceeded`: The `path` or `entry` exceeds the configured size limits.
+    pub fn create_parents_with(
+        &mut self,
+        path: impl AsRef<RelativePath>,
+        entry: &Entry<S, M>,
+        conflict: ParentConflict,
     ) -> crate::Result<()> {
         let parent = match path.as_ref().parent() {
             Some(parent) if parent != *EMPTY_PATH => parent,
@@ -270,6 +347,18 @@ where
         let mut ancestor = RelativePathBuf::new();
         for component in parent.iter() {
             ancestor.push(component);
+
+            if self.exists(&ancestor) && !self.is_directory(&ancestor) {
+                match conflict {
+                    ParentConflict::Error => return Err(crate::Error::NotDirectory),
+                    ParentConflict::Replace => self.remove(&ancestor)?,
+                    ParentConflict::Rename => {
+                        let new_path = self.unused_path(&ancestor);
+                        self.rename(&ancestor, new_path)?;
+                    }
+                }
+            }
+
             match self.create(&ancestor, &Entry::directory()) {
                 Err(crate::Error::AlreadyExists) => (),
                 Err(error) => return Err(error),
@@ -280,6 +369,15 @@ where
         self.create(path, entry)
     }
 
+    /// Return a path derived from `path` which does not exist in the repository.
+    fn unused_path(&self, path: &RelativePath) -> RelativePathBuf {
+        let file_name = path.file_name().unwrap_or_default();
+        (1u64..)
+            .map(|suffix| path.with_file_name(format!("{}~{}", file_name, suffix)))
+            .find(|new_path| !self.exists(new_path))
+            .unwrap()
+    }
+
     /// Remove the given `handle` from the repository.
     fn remove_handle(&mut self, handle: EntryHandle) {
         let num_links = {
@@ -294,6 +392,7 @@ where
             }
             self.repo.remove(handle.entry);
             self.repo.state_mut().links.remove(&handle.id());
+            self.opened_files.get_mut().unwrap().remove(&handle.id());
         }
     }
 
@@ -325,6 +424,8 @@ where
         let entry_handle = self.repo.state_mut().tree.remove(path.as_ref()).unwrap();
 
         self.remove_handle(entry_handle);
+        self.watchers
+            .notify(EntryEvent::Removed(path.as_ref().to_owned()));
 
         Ok(())
     }
@@ -344,17 +445,17 @@ where
             return Err(crate::Error::InvalidPath);
         }
 
-        let handles = self
+        let entries = self
             .repo
             .state_mut()
             .tree
             .drain(path.as_ref())
             .ok_or(crate::Error::NotFound)?
-            .map(|(_, handle)| handle)
             .collect::<Vec<_>>();
 
-        for handle in handles {
+        for (entry_path, handle) in entries {
             self.remove_handle(handle);
+            self.watchers.notify(EntryEvent::Removed(entry_path));
         }
 
         Ok(())
@@ -380,7 +481,7 @@ where
             .get(path.as_ref())
             .ok_or(crate::Error::NotFound)?;
         let mut object = self.repo.object(entry_handle.entry).unwrap();
-        object.deserialize()
+        object.deserialize_with::<F, _>()
     }
 
     /// Return the `EntryId` of the entry at `path`.
@@ -426,6 +527,8 @@ where
 
     /// Set the file `metadata` for the entry at `path`.
     ///
+    /// If [time tracking] is enabled, this also records that the metadata was changed.
+    ///
     /// # Errors
     /// - `Error::InvalidPath`: The given `path` is empty.
     /// - `Error::NotFound`: There is no entry at `path`.
@@ -434,12 +537,35 @@ where
     /// - `Error::InvalidData`: Ciphertext verification failed.
     /// - `Error::Store`: An error occurred with the data store.
     /// - `Error::Io`: An I/O error occurred.
+    /// - `Error::LimitExceeded`: The entry with the new `metadata` exceeds the configured size limits.
+    ///
+    /// [time tracking]: crate::repo::file::FileRepo::set_time_tracking
     pub fn set_metadata(
         &mut self,
         path: impl AsRef<RelativePath>,
+        mut metadata: Option<M>,
+    ) -> crate::Result<()> {
+        if self.track_times {
+            if let Some(metadata) = &mut metadata {
+                metadata.mark_changed(SystemTime::now());
+            }
+        }
+        self.write_metadata(path.as_ref(), metadata)?;
+        self.watchers
+            .notify(EntryEvent::Modified(path.as_ref().to_owned()));
+        Ok(())
+    }
+
+    /// Set the file `metadata` for the entry at `path` without notifying watchers.
+    ///
+    /// This is used for changes which shouldn't be reported as modifications, like updating the
+    /// access time of an entry.
+    pub(super) fn write_metadata(
+        &mut self,
+        path: &RelativePath,
         metadata: Option<M>,
     ) -> crate::Result<()> {
-        if path.as_ref() == *EMPTY_PATH {
+        if path == *EMPTY_PATH {
             return Err(crate::Error::InvalidPath);
         }
 
@@ -447,12 +573,41 @@ where
             .repo
             .state()
             .tree
-            .get(path.as_ref())
+            .get(path)
             .ok_or(crate::Error::NotFound)?;
         let mut object = self.repo.object(entry_handle.entry).unwrap();
-        let mut entry: Entry<S, M> = object.deserialize()?;
+        let mut entry: Entry<S, M> = object.deserialize_with::<F, _>()?;
         entry.metadata = metadata;
-        object.serialize(&entry)
+        self.check_entry_limits(&entry)?;
+        object.serialize_with::<F, _>(&entry)
+    }
+
+    /// Return an error if the serialized `entry` exceeds the configured size limits.
+    fn check_entry_limits(&self, entry: &Entry<S, M>) -> crate::Result<()> {
+        if self.limits.max_metadata_size.is_some() {
+            self.limits.check_metadata_size(F::to_vec(entry)?.len())?;
+        }
+        Ok(())
+    }
+
+    /// Return an error if copying or moving the tree at `source` to `dest` would create a path
+    /// which exceeds the configured size limits.
+    fn check_tree_limits(&self, source: &RelativePath, dest: &RelativePath) -> crate::Result<()> {
+        self.limits.check_path(dest)?;
+        if self.limits.max_path_len.is_some() {
+            for (path, _) in self
+                .repo
+                .state()
+                .tree
+                .descendants(source)
+                .into_iter()
+                .flatten()
+            {
+                self.limits
+                    .check_path(&dest.join(path.strip_prefix(source).unwrap()))?;
+            }
+        }
+        Ok(())
     }
 
     /// Return an `Object` for reading and writing the contents of the file at `path`.
@@ -474,7 +629,15 @@ where
             .ok_or(crate::Error::NotFound)?;
 
         if let HandleType::File(object_id) = entry_handle.kind {
-            Ok(self.repo.object(object_id).unwrap())
+            let object = self.repo.object(object_id).unwrap();
+            if self.track_times {
+                if let HashMapEntry::Vacant(vacant) =
+                    self.opened_files.lock().unwrap().entry(entry_handle.id())
+                {
+                    vacant.insert((entry_handle, object.content_id()?));
+                }
+            }
+            Ok(object)
         } else {
             Err(crate::Error::NotFile)
         }
@@ -511,6 +674,7 @@ where
     /// - `Error::NotDirectory`: The parent of `dest` is not a directory entry.
     /// - `Error::InvalidPath`: The given `source` or `dest` paths are empty.
     /// - `Error::AlreadyExists`: There is already an entry at `dest`.
+    /// - `Error::LimitExceeded`: The `dest` path exceeds the configured size limits.
     ///
     /// [`archive`]: crate::repo::file::FileRepo::archive
     /// [`extract`]: crate::repo::file::FileRepo::extract
@@ -529,6 +693,8 @@ where
             return Err(crate::Error::AlreadyExists);
         }
 
+        self.limits.check_path(dest.as_ref())?;
+
         let entry_handle = *self
             .repo
             .state()
@@ -538,10 +704,136 @@ where
 
         let new_handle = self.copy_entry_handle(entry_handle);
         self.repo.state_mut().tree.insert(dest.as_ref(), new_handle);
+        self.watchers
+            .notify(EntryEvent::Created(dest.as_ref().to_owned()));
 
         Ok(())
     }
 
+    /// Create a copy-on-write clone of the file at `source` at `dest`.
+    ///
+    /// This is like [`copy`], but `source` must be a regular file, and the clone is guaranteed to
+    /// share all of its data with `source` rather than relying on deduplication. Creating the clone
+    /// only copies metadata; it does not read or write any of the bytes in the file. Once one of
+    /// the files is modified, only the modified regions stop being shared, like `cp --reflink`.
+    ///
+    /// You can use [`shares_extents`] to check whether two files still share all their data.
+    ///
+    /// # Errors
+    /// - `Error::NotFound`: The parent of `dest` does not exist.
+    /// - `Error::NotFound`: There is no entry at `source`.
+    /// - `Error::NotFile`: The entry at `source` is not a regular file.
+    /// - `Error::NotDirectory`: The parent of `dest` is not a directory entry.
+    /// - `Error::InvalidPath`: The given `source` or `dest` paths are empty.
+    /// - `Error::AlreadyExists`: There is already an entry at `dest`.
+    /// - `Error::LimitExceeded`: The `dest` path exceeds the configured size limits.
+    ///
+    /// [`copy`]: crate::repo::file::FileRepo::copy
+    /// [`shares_extents`]: crate::repo::file::FileRepo::shares_extents
+    pub fn reflink(
+        &mut self,
+        source: impl AsRef<RelativePath>,
+        dest: impl AsRef<RelativePath>,
+    ) -> crate::Result<()> {
+        if source.as_ref() == *EMPTY_PATH || dest.as_ref() == *EMPTY_PATH {
+            return Err(crate::Error::InvalidPath);
+        }
+
+        let entry_handle = *self
+            .repo
+            .state()
+            .tree
+            .get(source.as_ref())
+            .ok_or(crate::Error::NotFound)?;
+
+        if !matches!(entry_handle.kind, HandleType::File(_)) {
+            return Err(crate::Error::NotFile);
+        }
+
+        self.copy(source, dest)
+    }
+
+    /// Replace the contents of the file at `dest` with a copy-on-write clone of the file at
+    /// `source`.
+    ///
+    /// Unlike [`reflink`], this keeps the entry at `dest` and its metadata, so other hard links to
+    /// it see the new contents.
+    ///
+    /// # Errors
+    /// - `Error::NotFound`: There is no entry at `source` or `dest`.
+    /// - `Error::NotFile`: The entry at `source` or `dest` is not a regular file.
+    /// - `Error::InvalidPath`: The given `source` or `dest` paths are empty.
+    ///
+    /// [`reflink`]: crate::repo::file::FileRepo::reflink
+    #[cfg(all(any(unix, doc), feature = "fuse-mount"))]
+    pub(super) fn clone_contents(
+        &mut self,
+        source: &RelativePath,
+        dest: &RelativePath,
+    ) -> crate::Result<()> {
+        if source == *EMPTY_PATH || dest == *EMPTY_PATH {
+            return Err(crate::Error::InvalidPath);
+        }
+
+        let tree = &self.repo.state().tree;
+        let source_handle = tree.get(source).ok_or(crate::Error::NotFound)?;
+        let dest_handle = tree.get(dest).ok_or(crate::Error::NotFound)?;
+        match (source_handle.kind, dest_handle.kind) {
+            (HandleType::File(source_id), HandleType::File(dest_id)) => {
+                assert!(self.repo.copy_to(source_id, dest_id));
+                self.watchers.notify(EntryEvent::Modified(dest.to_owned()));
+                Ok(())
+            }
+            _ => Err(crate::Error::NotFile),
+        }
+    }
+
+    /// Return a `ContentId` which changes whenever the entry at `path` is modified.
+    ///
+    /// This can be used to detect whether a cached copy of the entry is stale without
+    /// deserializing it.
+    ///
+    /// # Errors
+    /// - `Error::NotFound`: There is no entry at `path`.
+    /// - `Error::InvalidPath`: The given `path` is empty.
+    #[cfg(all(any(unix, doc), feature = "fuse-mount"))]
+    pub(super) fn entry_version(&self, path: &RelativePath) -> crate::Result<ContentId> {
+        if path == *EMPTY_PATH {
+            return Err(crate::Error::InvalidPath);
+        }
+        let entry_handle = self
+            .repo
+            .state()
+            .tree
+            .get(path)
+            .ok_or(crate::Error::NotFound)?;
+        self.repo.object(entry_handle.entry).unwrap().content_id()
+    }
+
+    /// Return whether the files at `first` and `second` currently share all their data.
+    ///
+    /// This returns `true` if the two files are made up of exactly the same chunks and sparse
+    /// holes, such as when one is a clone of the other created with [`reflink`] or [`copy`] and
+    /// neither has been modified since. This only compares metadata and does not read any data
+    /// from the data store.
+    ///
+    /// # Errors
+    /// - `Error::NotFound`: There is no entry at `first` or `second`.
+    /// - `Error::NotFile`: The entry at `first` or `second` is not a regular file.
+    /// - `Error::InvalidPath`: The given `first` or `second` paths are empty.
+    ///
+    /// [`reflink`]: crate::repo::file::FileRepo::reflink
+    /// [`copy`]: crate::repo::file::FileRepo::copy
+    pub fn shares_extents(
+        &self,
+        first: impl AsRef<RelativePath>,
+        second: impl AsRef<RelativePath>,
+    ) -> crate::Result<bool> {
+        let first_id = self.open(first)?.content_id()?;
+        let second_id = self.open(second)?.content_id()?;
+        Ok(first_id == second_id)
+    }
+
     /// Copy the tree of entries at `source` to `dest`.
     ///
     /// If `source` is a directory entry, this also copies its descendants.
@@ -558,6 +850,7 @@ where
     /// - `Error::NotDirectory`: The parent of `dest` is not a directory entry.
     /// - `Error::InvalidPath`: The given `source` or `dest` paths are empty.
     /// - `Error::AlreadyExists`: There is already an entry at `dest`.
+    /// - `Error::LimitExceeded`: A path in the `dest` tree exceeds the configured size limits.
     ///
     /// [`archive_tree`]: crate::repo::file::FileRepo::archive
     /// [`extract_tree`]: crate::repo::file::FileRepo::extract
@@ -576,6 +869,8 @@ where
             return Err(crate::Error::AlreadyExists);
         }
 
+        self.check_tree_limits(source.as_ref(), dest.as_ref())?;
+
         // Copy the root path.
         let source_root_handle = *self
             .repo
@@ -588,6 +883,8 @@ where
             .state_mut()
             .tree
             .insert(dest.as_ref(), dest_root_handle);
+        self.watchers
+            .notify(EntryEvent::Created(dest.as_ref().to_owned()));
 
         // Because we can't walk the path tree and insert into it at the same time, we need to
         // construct a tree of the destination paths before inserting them back into the path table.
@@ -614,6 +911,7 @@ where
             let relative_path = dest_tree_path.strip_prefix(dest_tree_root).unwrap();
             let dest_path = dest.as_ref().join(relative_path);
             self.repo.state_mut().tree.insert(&dest_path, dest_handle);
+            self.watchers.notify(EntryEvent::Created(dest_path));
         }
 
         Ok(())
@@ -638,6 +936,7 @@ where
     /// - `Error::InvalidPath`: The given `source` or `dest` paths are empty.
     /// - `Error::InvalidPath`: The given `dest` is a descendant of `source`.
     /// - `Error::AlreadyExists`: There is already an entry at `dest`.
+    /// - `Error::LimitExceeded`: A path in the `dest` tree exceeds the configured size limits.
     ///
     /// [`copy_tree`]: crate::repo::file::FileRepo::copy_tree
     /// [`remove_tree`]: crate::repo::file::FileRepo::remove_tree
@@ -652,7 +951,12 @@ where
             return Err(crate::Error::InvalidPath);
         }
 
-        if dest.as_ref().starts_with(source.as_ref()) {
+        if self
+            .repo
+            .state()
+            .tree
+            .starts_with(dest.as_ref(), source.as_ref())
+        {
             return Err(crate::Error::InvalidPath);
         }
 
@@ -662,6 +966,8 @@ where
             return Err(crate::Error::AlreadyExists);
         }
 
+        self.check_tree_limits(source.as_ref(), dest.as_ref())?;
+
         let source_tree = self
             .repo
             .state_mut()
@@ -676,6 +982,11 @@ where
             self.repo.state_mut().tree.insert(dest_path, handle);
         }
 
+        self.watchers.notify(EntryEvent::Renamed {
+            source: source.as_ref().to_owned(),
+            dest: dest.as_ref().to_owned(),
+        });
+
         Ok(())
     }
 
@@ -721,6 +1032,7 @@ where
     /// - `Error::NotDirectory`: The parent of `dest` is not a directory entry.
     /// - `Error::InvalidPath`: The given `source` or `dest` paths are empty.
     /// - `Error::AlreadyExists`: There is already an entry at `dest`.
+    /// - `Error::LimitExceeded`: The `dest` path exceeds the configured size limits.
     ///
     /// [`copy`]: crate::repo::file::FileRepo::copy
     /// [`entry_id`]: crate::repo::file::FileRepo::entry_id
@@ -740,6 +1052,8 @@ where
             return Err(crate::Error::AlreadyExists);
         }
 
+        self.limits.check_path(dest.as_ref())?;
+
         let entry_handle = *self
             .repo
             .state()
@@ -763,6 +1077,9 @@ where
             .get_mut(&entry_handle.id())
             .unwrap() += 1;
 
+        self.watchers
+            .notify(EntryEvent::Created(dest.as_ref().to_owned()));
+
         Ok(())
     }
 
@@ -903,10 +1220,10 @@ where
     /// # Errors
     /// - `Error::NotFound`: The given `parent` does not exist.
     /// - `Error::NotDirectory`: The given `parent` is not a directory.
-    pub fn walk<R, P, F>(&self, parent: P, mut visitor: F) -> crate::Result<Option<R>>
+    pub fn walk<R, P, V>(&self, parent: P, mut visitor: V) -> crate::Result<Option<R>>
     where
         P: AsRef<RelativePath>,
-        F: FnMut(WalkEntry<S, M>) -> WalkPredicate<R>,
+        V: FnMut(WalkEntry<S, M, F>) -> WalkPredicate<R>,
     {
         self.verify_has_descendants(parent.as_ref())?;
 
@@ -947,6 +1264,7 @@ where
     /// - `Error::InvalidData`: Ciphertext verification failed.
     /// - `Error::Store`: An error occurred with the data store.
     /// - `Error::Io`: An I/O error occurred.
+    /// - `Error::LimitExceeded`: The `dest` path or the file at `source` exceeds the configured size limits.
     ///
     /// [`FileMetadata`]: crate::repo::file::FileMetadata
     /// [`Object`]: crate::repo::Object
@@ -967,20 +1285,12 @@ where
             return Err(crate::Error::NotFound);
         }
 
-        let file_metadata = metadata(&source)?;
+        let entry = read_entry(source.as_ref())?;
 
-        let file_type = if file_metadata.is_file() {
-            EntryType::File
-        } else if file_metadata.is_dir() {
-            EntryType::Directory
-        } else {
-            EntryType::Special(S::from_file(source.as_ref())?.ok_or(crate::Error::FileType)?)
-        };
-
-        let entry = Entry {
-            kind: file_type,
-            metadata: M::from_file(source.as_ref())?,
-        };
+        if entry.is_file() {
+            self.limits
+                .check_file_size(metadata(source.as_ref())?.len())?;
+        }
 
         self.create(&dest, &entry)?;
 
@@ -1008,7 +1318,8 @@ where
     /// object.
     ///
     /// This method does not attempt to handle hard links in the `source` tree specially; if two
-    /// files in the `source` tree are hard links, they will be archived as separate entries.
+    /// files in the `source` tree are hard links, they will be archived as separate entries. To
+    /// preserve hard links, use [`archive_tree_with`] with [`ArchiveOptions::detect_hard_links`].
     ///
     /// # Errors
     /// - `Error::NotFound`: The given `source` file does not exist.
@@ -1022,77 +1333,374 @@ where
     ///
     /// [`archive`]: crate::repo::file::FileRepo::archive
     /// [`FileMetadata`]: crate::repo::file::FileMetadata
+    /// [`archive_tree_with`]: crate::repo::file::FileRepo::archive_tree_with
+    /// [`ArchiveOptions::detect_hard_links`]: crate::repo::file::ArchiveOptions::detect_hard_links
     pub fn archive_tree(
         &mut self,
         source: impl AsRef<Path>,
         dest: impl AsRef<RelativePath>,
+    ) -> crate::Result<()> {
+        self.archive_tree_with_progress(source, dest, |_| ProgressAction::Continue)
+    }
+
+    /// Copy a directory tree from the file system into the repository, reporting progress.
+    ///
+    /// This is like [`archive_tree`], except that `progress` is called with a [`Progress`] after
+    /// each file is archived. If `progress` returns [`ProgressAction::Cancel`], this method stops
+    /// and returns `Error::Cancelled`.
+    ///
+    /// If this method is cancelled or returns an error, the files which were archived before that
+    /// point remain in the repository, but no changes are committed. Each file is either archived
+    /// completely or not at all. You can discard the partially archived tree with
+    /// [`Commit::rollback`] or keep it with [`Commit::commit`].
+    ///
+    /// # Errors
+    /// - `Error::Cancelled`: The operation was cancelled by `progress`.
+    /// - `Error::NotFound`: The given `source` file does not exist.
+    /// - `Error::NotFound`: The parent of `dest` does not exist.
+    /// - `Error::NotDirectory`: The parent of `dest` is not a directory entry.
+    /// - `Error::InvalidPath`: The given `dest` path is empty.
+    /// - `Error::AlreadyExists`: There is already an entry at `dest`.
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    ///
+    /// [`archive_tree`]: crate::repo::file::FileRepo::archive_tree
+    /// [`Progress`]: crate::repo::file::Progress
+    /// [`ProgressAction::Cancel`]: crate::repo::file::ProgressAction::Cancel
+    /// [`Commit::rollback`]: crate::repo::Commit::rollback
+    /// [`Commit::commit`]: crate::repo::Commit::commit
+    pub fn archive_tree_with_progress(
+        &mut self,
+        source: impl AsRef<Path>,
+        dest: impl AsRef<RelativePath>,
+        progress: impl FnMut(Progress) -> ProgressAction,
+    ) -> crate::Result<()> {
+        self.archive_tree_with(source, dest, &ArchiveOptions::default(), progress)
+    }
+
+    /// Copy a selection of files in a directory tree from the file system into the repository.
+    ///
+    /// This is like [`archive_tree_with_progress`], except that only the files selected by
+    /// `options` are archived. See [`ArchiveOptions`] for details. The `source` file itself is
+    /// always archived.
+    ///
+    /// # Errors
+    /// - `Error::Cancelled`: The operation was cancelled by `progress`.
+    /// - `Error::NotFound`: The given `source` file does not exist.
+    /// - `Error::NotFound`: The parent of `dest` does not exist.
+    /// - `Error::NotDirectory`: The parent of `dest` is not a directory entry.
+    /// - `Error::InvalidPath`: The given `dest` path is empty.
+    /// - `Error::InvalidPath`: One of the patterns in `options` is not a valid glob pattern.
+    /// - `Error::AlreadyExists`: There is already an entry at `dest`.
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    ///
+    /// [`archive_tree_with_progress`]: crate::repo::file::FileRepo::archive_tree_with_progress
+    /// [`ArchiveOptions`]: crate::repo::file::ArchiveOptions
+    pub fn archive_tree_with(
+        &mut self,
+        source: impl AsRef<Path>,
+        dest: impl AsRef<RelativePath>,
+        options: &ArchiveOptions,
+        mut progress: impl FnMut(Progress) -> ProgressAction,
     ) -> crate::Result<()> {
         if !source.as_ref().exists() {
             return Err(crate::Error::NotFound);
         }
 
+        let filter = PathFilter::new(&options.include, &options.exclude)?;
+
+        // A map of the device and inode numbers of files with multiple hard links to the path of
+        // the first entry archived for them.
+        let mut link_map: HashMap<(u64, u64), RelativePathBuf> = HashMap::new();
+
+        let mut files = 0;
+        let mut bytes = 0;
+
         // `WalkDir` includes `source` in the paths it iterates over.
         // It does not error if `source` is not a directory.
-        let all_paths = WalkDir::new(&source).into_iter();
+        let mut walker = WalkDir::new(&source).follow_links(options.follow_symlinks);
+        if let Some(max_depth) = options.max_depth {
+            walker = walker.max_depth(max_depth);
+        }
+        let all_paths = walker.into_iter().filter_entry(|dir_entry| {
+            let relative_path =
+                RelativePath::from_path(dir_entry.path().strip_prefix(&source).unwrap())
+                    .expect("Not a valid relative path.");
+            dir_entry.depth() == 0 || !filter.is_excluded(relative_path)
+        });
 
         for result in all_paths {
             let dir_entry = result.map_err(io::Error::from)?;
             let relative_path =
                 RelativePath::from_path(dir_entry.path().strip_prefix(&source).unwrap())
                     .expect("Not a valid relative path.");
-            match self.archive(dir_entry.path(), dest.as_ref().join(relative_path)) {
-                Ok(_) => continue,
-                Err(crate::Error::FileType) => continue,
-                Err(error) => return Err(error),
+
+            if dir_entry.depth() > 0 {
+                if dir_entry.file_type().is_dir() {
+                    // When only some files are included, directories are archived as needed.
+                    if !filter.includes_all() {
+                        continue;
+                    }
+                } else {
+                    if !filter.is_included(relative_path) {
+                        continue;
+                    }
+
+                    if let Some(max_file_size) = options.max_file_size {
+                        let file_metadata = metadata(dir_entry.path())?;
+                        if file_metadata.is_file() && file_metadata.len() > max_file_size {
+                            continue;
+                        }
+                    }
+
+                    if !filter.includes_all() {
+                        self.archive_ancestors(source.as_ref(), dest.as_ref(), relative_path)?;
+                    }
+                }
+            }
+
+            let dest_path = dest.as_ref().join(relative_path);
+
+            let link_id = if options.detect_hard_links && !dir_entry.file_type().is_dir() {
+                hard_link_id(&dir_entry.path().symlink_metadata()?)
+            } else {
+                None
+            };
+
+            match link_id.and_then(|id| link_map.get(&id)) {
+                Some(original_path) => {
+                    self.link(original_path, &dest_path)?;
+                }
+                None => {
+                    match self.archive(dir_entry.path(), &dest_path) {
+                        Ok(_) => {}
+                        Err(crate::Error::FileType) => continue,
+                        Err(error) => return Err(error),
+                    }
+
+                    if let Some(id) = link_id {
+                        link_map.insert(id, dest_path);
+                    }
+
+                    let file_metadata = metadata(dir_entry.path())?;
+                    if file_metadata.is_file() {
+                        bytes += file_metadata.len();
+                    }
+                }
+            }
+
+            files += 1;
+
+            let current = Progress {
+                path: dir_entry.path(),
+                files,
+                bytes,
+            };
+            if progress(current) == ProgressAction::Cancel {
+                return Err(crate::Error::Cancelled);
             }
         }
 
         Ok(())
     }
 
-    /// Copy an entry from the repository into the file system.
+    /// Archive any ancestors of `relative_path` in `source` which don't exist in `dest` yet.
+    fn archive_ancestors(
+        &mut self,
+        source: &Path,
+        dest: &RelativePath,
+        relative_path: &RelativePath,
+    ) -> crate::Result<()> {
+        let mut ancestors = Vec::new();
+        let mut current = relative_path.parent();
+        while let Some(ancestor) = current {
+            if ancestor == *EMPTY_PATH {
+                break;
+            }
+            ancestors.push(ancestor);
+            current = ancestor.parent();
+        }
+
+        for ancestor in ancestors.into_iter().rev() {
+            let dest_path = dest.join(ancestor);
+            if !self.exists(&dest_path) {
+                self.archive(ancestor.to_path(source), &dest_path)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Update a directory tree in the repository to match a directory tree in the file system.
     ///
-    /// If `source` is a directory, its descendants are not copied.
+    /// This is like [`archive_tree`], except that `dest` may already exist. Files in the `source`
+    /// tree which don't have an entry in the `dest` tree are archived, and files which have changed
+    /// since they were archived are archived again. Whether a file has changed is determined by
+    /// [`SyncOptions::detection`]. Entries which have only changed metadata have their metadata
+    /// updated without archiving the file contents again. If [`SyncOptions::remove_deleted`] is
+    /// `true`, entries in the `dest` tree which no longer exist in the `source` tree are removed.
     ///
-    /// The `source` entry's metadata will be copied to the `dest` file according to the selected
-    /// [`FileMetadata`] implementation.
+    /// If an entry for a changed file is linked via [`link`], it is replaced with a new entry which
+    /// is not linked to any others.
     ///
-    /// If `source` is a sparse object, this method will attempt to efficiently copy any sparse
-    /// holes in the [`Object`] to the file in the file system, creating a sparse file.
+    /// If one of the files in the tree is not a regular file, directory, or supported special file,
+    /// it is skipped.
     ///
     /// # Errors
-    /// - `Error::InvalidPath`: The given `source` path is empty.
-    /// - `Error::NotFound`: The `source` entry does not exist.
-    /// - `Error::AlreadyExists`: The `dest` file already exists.
+    /// - `Error::NotFound`: The given `source` file does not exist.
+    /// - `Error::NotFound`: The parent of `dest` does not exist.
+    /// - `Error::NotDirectory`: The parent of `dest` is not a directory entry.
+    /// - `Error::InvalidPath`: The given `dest` path is empty.
     /// - `Error::Deserialize`: The file metadata could not be deserialized.
     /// - `Error::InvalidData`: Ciphertext verification failed.
     /// - `Error::Store`: An error occurred with the data store.
     /// - `Error::Io`: An I/O error occurred.
     ///
-    /// [`FileMetadata`]: crate::repo::file::FileMetadata
-    /// [`Object`]: crate::repo::Object
-    pub fn extract(
-        &self,
-        source: impl AsRef<RelativePath>,
-        dest: impl AsRef<Path>,
-    ) -> crate::Result<()> {
-        if source.as_ref() == *EMPTY_PATH {
+    /// [`archive_tree`]: crate::repo::file::FileRepo::archive_tree
+    /// [`SyncOptions::detection`]: crate::repo::file::SyncOptions::detection
+    /// [`SyncOptions::remove_deleted`]: crate::repo::file::SyncOptions::remove_deleted
+    /// [`link`]: crate::repo::file::FileRepo::link
+    pub fn sync_tree(
+        &mut self,
+        source: impl AsRef<Path>,
+        dest: impl AsRef<RelativePath>,
+        options: SyncOptions,
+    ) -> crate::Result<()>
+    where
+        S: PartialEq,
+        M: PartialEq,
+    {
+        if dest.as_ref() == *EMPTY_PATH {
             return Err(crate::Error::InvalidPath);
         }
 
-        if dest.as_ref().exists() {
-            return Err(crate::Error::AlreadyExists);
+        if !source.as_ref().exists() {
+            return Err(crate::Error::NotFound);
         }
 
-        let entry = self.entry(&source)?;
-
-        // Create any necessary parent directories.
-        if let Some(parent) = dest.as_ref().parent() {
-            create_dir_all(parent)?
-        }
+        // The paths of entries which correspond to files in the `source` tree.
+        let mut synced_paths = HashSet::new();
 
-        // Create the file or directory.
-        match entry.kind {
+        // `WalkDir` includes `source` in the paths it iterates over.
+        // It does not error if `source` is not a directory.
+        for result in WalkDir::new(&source) {
+            let dir_entry = result.map_err(io::Error::from)?;
+            let relative_path =
+                RelativePath::from_path(dir_entry.path().strip_prefix(&source).unwrap())
+                    .expect("Not a valid relative path.");
+            let dest_path = dest.as_ref().join(relative_path);
+            match self.sync_file(dir_entry.path(), &dest_path, options.detection) {
+                Ok(_) => {
+                    synced_paths.insert(dest_path);
+                }
+                Err(crate::Error::FileType) => continue,
+                Err(error) => return Err(error),
+            }
+        }
+
+        if options.remove_deleted && self.is_directory(&dest) {
+            let deleted_paths = self
+                .descendants(&dest)?
+                .filter(|path| !synced_paths.contains(path))
+                .collect::<Vec<_>>();
+            for path in deleted_paths {
+                // The entry may have already been removed along with one of its ancestors.
+                if self.exists(&path) {
+                    self.remove_tree(&path)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Update the entry at `dest` to match the file at `source`, creating it if it doesn't exist.
+    fn sync_file(
+        &mut self,
+        source: &Path,
+        dest: &RelativePath,
+        detection: ChangeDetection,
+    ) -> crate::Result<()>
+    where
+        S: PartialEq,
+        M: PartialEq,
+    {
+        if !self.exists(dest) {
+            return self.archive(source, dest);
+        }
+
+        let new_entry: Entry<S, M> = read_entry(source)?;
+        let old_entry = self.entry(dest)?;
+
+        let contents_changed = match (&old_entry.kind, &new_entry.kind) {
+            (EntryType::File, EntryType::File) => {
+                let object = self.open(dest)?;
+                match detection {
+                    ChangeDetection::Metadata => object.size()? != metadata(source)?.len(),
+                    ChangeDetection::Contents => {
+                        !object.content_id()?.compare_contents(File::open(source)?)?
+                    }
+                }
+            }
+            (old_kind, new_kind) => old_kind != new_kind,
+        };
+
+        if contents_changed {
+            self.remove_tree(dest)?;
+            self.archive(source, dest)
+        } else if old_entry.metadata != new_entry.metadata {
+            self.set_metadata(dest, new_entry.metadata)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Copy an entry from the repository into the file system.
+    ///
+    /// If `source` is a directory, its descendants are not copied.
+    ///
+    /// The `source` entry's metadata will be copied to the `dest` file according to the selected
+    /// [`FileMetadata`] implementation.
+    ///
+    /// If `source` is a sparse object, this method will attempt to efficiently copy any sparse
+    /// holes in the [`Object`] to the file in the file system, creating a sparse file.
+    ///
+    /// # Errors
+    /// - `Error::InvalidPath`: The given `source` path is empty.
+    /// - `Error::NotFound`: The `source` entry does not exist.
+    /// - `Error::AlreadyExists`: The `dest` file already exists.
+    /// - `Error::Deserialize`: The file metadata could not be deserialized.
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    ///
+    /// [`FileMetadata`]: crate::repo::file::FileMetadata
+    /// [`Object`]: crate::repo::Object
+    pub fn extract(
+        &self,
+        source: impl AsRef<RelativePath>,
+        dest: impl AsRef<Path>,
+    ) -> crate::Result<()> {
+        if source.as_ref() == *EMPTY_PATH {
+            return Err(crate::Error::InvalidPath);
+        }
+
+        if dest.as_ref().exists() {
+            return Err(crate::Error::AlreadyExists);
+        }
+
+        let entry = self.entry(&source)?;
+
+        // Create any necessary parent directories.
+        if let Some(parent) = dest.as_ref().parent() {
+            create_dir_all(parent)?
+        }
+
+        // Create the file or directory.
+        match entry.kind {
             EntryType::File => {
                 let mut object = self.open(source.as_ref()).unwrap();
                 extract_file(&mut object, dest.as_ref())?;
@@ -1146,14 +1754,128 @@ where
         source: impl AsRef<RelativePath>,
         dest: impl AsRef<Path>,
     ) -> crate::Result<()> {
+        self.extract_tree_with_progress(source, dest, |_| ProgressAction::Continue)
+    }
+
+    /// Copy a tree of entries from the repository into the file system, reporting progress.
+    ///
+    /// This is like [`extract_tree`], except that `progress` is called with a [`Progress`] after
+    /// each file is extracted. If `progress` returns [`ProgressAction::Cancel`], this method stops
+    /// and returns `Error::Cancelled`.
+    ///
+    /// This method does not modify the repository. If it is cancelled or returns an error, the
+    /// files which were extracted before that point are left in the file system.
+    ///
+    /// # Errors
+    /// - `Error::Cancelled`: The operation was cancelled by `progress`.
+    /// - `Error::InvalidPath`: The given `source` path is empty.
+    /// - `Error::NotFound`: The `source` entry does not exist.
+    /// - `Error::AlreadyExists`: The `dest` file already exists.
+    /// - `Error::Deserialize`: The file metadata could not be deserialized.
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    ///
+    /// [`extract_tree`]: crate::repo::file::FileRepo::extract_tree
+    /// [`Progress`]: crate::repo::file::Progress
+    /// [`ProgressAction::Cancel`]: crate::repo::file::ProgressAction::Cancel
+    pub fn extract_tree_with_progress(
+        &self,
+        source: impl AsRef<RelativePath>,
+        dest: impl AsRef<Path>,
+        progress: impl FnMut(Progress) -> ProgressAction,
+    ) -> crate::Result<()> {
+        self.extract_tree_with(source, dest, &ExtractOptions::default(), progress)
+    }
+
+    /// Copy a selection of entries in a tree from the repository into the file system.
+    ///
+    /// This is like [`extract_tree_with_progress`], except that only the entries selected by
+    /// `options` are extracted. See [`ExtractOptions`] for details. The `source` entry itself is
+    /// always extracted.
+    ///
+    /// # Errors
+    /// - `Error::Cancelled`: The operation was cancelled by `progress`.
+    /// - `Error::InvalidPath`: The given `source` path is empty.
+    /// - `Error::InvalidPath`: One of the patterns in `options` is not a valid glob pattern.
+    /// - `Error::NotFound`: The `source` entry does not exist.
+    /// - `Error::AlreadyExists`: The `dest` file already exists.
+    /// - `Error::Deserialize`: The file metadata could not be deserialized.
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    ///
+    /// [`extract_tree_with_progress`]: crate::repo::file::FileRepo::extract_tree_with_progress
+    /// [`ExtractOptions`]: crate::repo::file::ExtractOptions
+    pub fn extract_tree_with(
+        &self,
+        source: impl AsRef<RelativePath>,
+        dest: impl AsRef<Path>,
+        options: &ExtractOptions,
+        mut progress: impl FnMut(Progress) -> ProgressAction,
+    ) -> crate::Result<()> {
+        let filter = PathFilter::new(&options.include, &options.exclude)?;
+
         self.extract(&source, &dest)?;
 
+        let mut files = 1;
+        let mut bytes = self.file_size(source.as_ref())?;
+
+        let current = Progress {
+            path: dest.as_ref(),
+            files,
+            bytes,
+        };
+        if progress(current) == ProgressAction::Cancel {
+            return Err(crate::Error::Cancelled);
+        }
+
         let mut link_map: HashMap<EntryId, PathBuf> = HashMap::new();
 
         let walk_result: crate::Result<Option<crate::Error>> = self.walk(&source, |entry| {
-            let relative_path = entry.path().strip_prefix(&source).unwrap();
+            let relative_path = entry.relative_path();
             let dest_path = relative_path.to_path(dest.as_ref());
 
+            if filter.is_excluded(relative_path) {
+                return WalkPredicate::SkipDescendants;
+            }
+
+            if let Some(max_depth) = options.max_depth {
+                if entry.depth() > max_depth {
+                    return WalkPredicate::SkipDescendants;
+                }
+            }
+
+            let size = match self.file_size(entry.path()) {
+                Ok(size) => size,
+                Err(error) => return WalkPredicate::Stop(error),
+            };
+
+            if entry.is_directory() {
+                // When only some entries are included, directories are extracted as needed.
+                if !filter.includes_all() {
+                    return WalkPredicate::Continue;
+                }
+            } else {
+                if !filter.is_included(relative_path) {
+                    return WalkPredicate::Continue;
+                }
+
+                if let Some(max_file_size) = options.max_file_size {
+                    if size > max_file_size {
+                        return WalkPredicate::Continue;
+                    }
+                }
+
+                if !filter.includes_all() {
+                    let result =
+                        self.extract_ancestors(source.as_ref(), dest.as_ref(), relative_path);
+                    if let Err(error) = result {
+                        return WalkPredicate::Stop(error);
+                    }
+                }
+            }
+
             match link_map.get(&entry.entry_id()) {
                 Some(original_path) => {
                     if let Err(error) = hard_link(original_path, &dest_path) {
@@ -1165,12 +1887,24 @@ where
                         return WalkPredicate::Stop(error);
                     }
 
+                    bytes += size;
+
                     if !entry.is_directory() {
-                        link_map.insert(entry.entry_id(), dest_path);
+                        link_map.insert(entry.entry_id(), dest_path.clone());
                     }
                 }
             }
 
+            files += 1;
+            let current = Progress {
+                path: &dest_path,
+                files,
+                bytes,
+            };
+            if progress(current) == ProgressAction::Cancel {
+                return WalkPredicate::Stop(crate::Error::Cancelled);
+            }
+
             WalkPredicate::Continue
         });
 
@@ -1182,6 +1916,230 @@ where
         }
     }
 
+    /// Extract any ancestors of `relative_path` in `source` which don't exist in `dest` yet.
+    fn extract_ancestors(
+        &self,
+        source: &RelativePath,
+        dest: &Path,
+        relative_path: &RelativePath,
+    ) -> crate::Result<()> {
+        let mut ancestors = Vec::new();
+        let mut current = relative_path.parent();
+        while let Some(ancestor) = current {
+            if ancestor == *EMPTY_PATH {
+                break;
+            }
+            ancestors.push(ancestor);
+            current = ancestor.parent();
+        }
+
+        for ancestor in ancestors.into_iter().rev() {
+            let dest_path = ancestor.to_path(dest);
+            if !dest_path.exists() {
+                self.extract(source.join(ancestor), &dest_path)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Return the size of the file entry at `path`, or `0` if it's not a file.
+    fn file_size(&self, path: &RelativePath) -> crate::Result<u64> {
+        if self.is_file(path) {
+            self.open(path)?.size()
+        } else {
+            Ok(0)
+        }
+    }
+
+    /// Save a version of the current contents of the file at `path`.
+    ///
+    /// The version history of each path is kept until its versions are removed with
+    /// [`remove_version`], even if the file at `path` is modified, renamed, or removed. This makes
+    /// it possible to recover previous contents of a file with [`restore_version`].
+    ///
+    /// This is a cheap operation which does not require copying the bytes in the file. Only the
+    /// contents of the file are saved; its metadata is not.
+    ///
+    /// # Errors
+    /// - `Error::InvalidPath`: The given `path` is empty.
+    /// - `Error::NotFound`: There is no entry at `path`.
+    /// - `Error::NotFile`: The entry at `path` is not a regular file.
+    /// - `Error::Serialize`: The version history could not be serialized.
+    /// - `Error::Deserialize`: The version history could not be deserialized.
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    ///
+    /// [`remove_version`]: crate::repo::file::FileRepo::remove_version
+    /// [`restore_version`]: crate::repo::file::FileRepo::restore_version
+    pub fn save_version(&mut self, path: impl AsRef<RelativePath>) -> crate::Result<FileVersion> {
+        let file_id = self.file_key(path.as_ref())?;
+        let size = self.repo.object(file_id).unwrap().size()?;
+
+        let segment = history_segment(path.as_ref());
+        let mut versions = self
+            .repo
+            .segment::<Vec<FileVersion>>(&segment)?
+            .unwrap_or_default();
+        let version = FileVersion {
+            id: versions.last().map_or(0, |version| version.id + 1),
+            created: SystemTime::now(),
+            size,
+            contents: self.repo.copy(file_id).unwrap(),
+        };
+        versions.push(version.clone());
+
+        if let Err(error) = self.repo.set_segment(segment, &versions) {
+            self.repo.remove(version.contents);
+            return Err(error);
+        }
+
+        Ok(version)
+    }
+
+    /// Return the saved versions of the file at `path`.
+    ///
+    /// Versions are returned in the order they were saved, oldest first. If no versions of `path`
+    /// have been saved, this returns an empty list. There does not need to be an entry at `path`.
+    ///
+    /// # Errors
+    /// - `Error::Deserialize`: The version history could not be deserialized.
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    pub fn versions(&self, path: impl AsRef<RelativePath>) -> crate::Result<Vec<FileVersion>> {
+        Ok(self
+            .repo
+            .segment(&history_segment(path.as_ref()))?
+            .unwrap_or_default())
+    }
+
+    /// Return an object for reading the contents of the version of `path` with the given `id`.
+    ///
+    /// # Errors
+    /// - `Error::NotFound`: There is no version of `path` with the given `id`.
+    /// - `Error::Deserialize`: The version history could not be deserialized.
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    pub fn open_version(
+        &self,
+        path: impl AsRef<RelativePath>,
+        id: u32,
+    ) -> crate::Result<ReadOnlyObject> {
+        let version = self.find_version(path.as_ref(), id)?;
+        ReadOnlyObject::try_from(self.repo.object(version.contents).unwrap())
+    }
+
+    /// Replace the contents of the file at `path` with the version of `path` with the given `id`.
+    ///
+    /// If there is no entry at `path`, a new file entry with no metadata is created. Otherwise,
+    /// this keeps the entry at `path` and its metadata, so other hard links to it see the restored
+    /// contents. The version itself is not modified or removed.
+    ///
+    /// Restoring a version invalidates all [`Object`] instances for the file at `path`.
+    ///
+    /// # Errors
+    /// - `Error::NotFound`: There is no version of `path` with the given `id`.
+    /// - `Error::NotFound`: The parent of `path` does not exist.
+    /// - `Error::NotFile`: The entry at `path` is not a regular file.
+    /// - `Error::NotDirectory`: The parent of `path` is not a directory entry.
+    /// - `Error::InvalidPath`: The given `path` is empty.
+    /// - `Error::Serialize`: The new file metadata could not be serialized.
+    /// - `Error::Deserialize`: The version history could not be deserialized.
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    ///
+    /// [`Object`]: crate::repo::Object
+    pub fn restore_version(
+        &mut self,
+        path: impl AsRef<RelativePath>,
+        id: u32,
+    ) -> crate::Result<()> {
+        let version = self.find_version(path.as_ref(), id)?;
+
+        if !self.exists(path.as_ref()) {
+            self.create(path.as_ref(), &Entry::file())?;
+        }
+
+        let file_id = self.file_key(path.as_ref())?;
+        assert!(self.repo.copy_to(version.contents, file_id));
+        self.watchers
+            .notify(EntryEvent::Modified(path.as_ref().to_owned()));
+
+        Ok(())
+    }
+
+    /// Remove the version of `path` with the given `id`.
+    ///
+    /// This returns `true` if the version was removed or `false` if it didn't exist.
+    ///
+    /// The space used by data which is only referenced by this version isn't reclaimed in the
+    /// backing data store until changes are committed and [`Commit::clean`] is called.
+    ///
+    /// # Errors
+    /// - `Error::Serialize`: The version history could not be serialized.
+    /// - `Error::Deserialize`: The version history could not be deserialized.
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    ///
+    /// [`Commit::clean`]: crate::repo::Commit::clean
+    pub fn remove_version(
+        &mut self,
+        path: impl AsRef<RelativePath>,
+        id: u32,
+    ) -> crate::Result<bool> {
+        let segment = history_segment(path.as_ref());
+        let mut versions = match self.repo.segment::<Vec<FileVersion>>(&segment)? {
+            Some(versions) => versions,
+            None => return Ok(false),
+        };
+        let index = match versions.iter().position(|version| version.id == id) {
+            Some(index) => index,
+            None => return Ok(false),
+        };
+
+        let version = versions.remove(index);
+        if versions.is_empty() {
+            self.repo.remove_segment(&segment);
+        } else {
+            self.repo.set_segment(segment, &versions)?;
+        }
+        self.repo.remove(version.contents);
+
+        Ok(true)
+    }
+
+    /// Return the version of `path` with the given `id`.
+    fn find_version(&self, path: &RelativePath, id: u32) -> crate::Result<FileVersion> {
+        self.versions(path)?
+            .into_iter()
+            .find(|version| version.id == id)
+            .ok_or(crate::Error::NotFound)
+    }
+
+    /// Return the key of the object which stores the contents of the file at `path`.
+    fn file_key(&self, path: &RelativePath) -> crate::Result<ObjectKey> {
+        if path == *EMPTY_PATH {
+            return Err(crate::Error::InvalidPath);
+        }
+
+        let entry_handle = self
+            .repo
+            .state()
+            .tree
+            .get(path)
+            .ok_or(crate::Error::NotFound)?;
+
+        match entry_handle.kind {
+            HandleType::File(file_id) => Ok(file_id),
+            _ => Err(crate::Error::NotFile),
+        }
+    }
+
     /// Verify the integrity of all the data in the repository.
     ///
     /// This returns the set of paths of files with corrupt data or metadata.
@@ -1222,6 +2180,7 @@ where
     ///
     /// [`KeyRepo::clear_instance`]: crate::repo::key::KeyRepo::clear_instance
     pub fn clear_instance(&mut self) {
+        self.forget_opened_files();
         self.repo.clear_instance()
     }
 
@@ -1245,6 +2204,42 @@ where
         self.repo.instance()
     }
 
+    /// Return information about each instance of this repository.
+    ///
+    /// See [`KeyRepo::list_instances`] for details.
+    ///
+    /// [`KeyRepo::list_instances`]: crate::repo::key::KeyRepo::list_instances
+    pub fn list_instances(&self) -> Vec<InstanceSummary> {
+        self.repo.list_instances()
+    }
+
+    /// Set the human-readable name of the instance with the given `id` to `name`.
+    ///
+    /// See [`KeyRepo::name_instance`] for details.
+    ///
+    /// [`KeyRepo::name_instance`]: crate::repo::key::KeyRepo::name_instance
+    pub fn name_instance(&mut self, id: InstanceId, name: Option<&str>) -> crate::Result<()> {
+        self.repo.name_instance(id, name)
+    }
+
+    /// Return the ID of the instance with the given `name`.
+    ///
+    /// See [`KeyRepo::find_instance`] for details.
+    ///
+    /// [`KeyRepo::find_instance`]: crate::repo::key::KeyRepo::find_instance
+    pub fn find_instance(&self, name: &str) -> Option<InstanceId> {
+        self.repo.find_instance(name)
+    }
+
+    /// Remove the instance of the repository with the given `id`.
+    ///
+    /// See [`KeyRepo::remove_instance`] for details.
+    ///
+    /// [`KeyRepo::remove_instance`]: crate::repo::key::KeyRepo::remove_instance
+    pub fn remove_instance(&mut self, id: InstanceId) -> crate::Result<bool> {
+        self.repo.remove_instance(id)
+    }
+
     /// Compute statistics about the repository.
     ///
     /// See [`KeyRepo::stats`] for details.
@@ -1254,22 +2249,424 @@ where
         self.repo.stats()
     }
 
+    /// Compute statistics about the objects in each instance of the repository.
+    ///
+    /// See [`KeyRepo::instance_stats`] for details.
+    ///
+    /// [`KeyRepo::instance_stats`]: crate::repo::key::KeyRepo::instance_stats
+    pub fn instance_stats(&self) -> crate::Result<HashMap<InstanceId, UsageStats>> {
+        self.repo.instance_stats()
+    }
+
+    /// Compute statistics about the tree of entries at `path`.
+    ///
+    /// If `path` is a directory, this describes all of its descendants, but not the directory
+    /// itself. The given `path` may be an empty path, in which case this describes every entry in
+    /// the repository. If `path` is not a directory, this describes just that entry.
+    ///
+    /// Entries with more than one path in the tree, which can be created with [`link`], are only
+    /// counted once. This is computed from the repository's in-memory object map, so it doesn't
+    /// need to read any files.
+    ///
+    /// # Errors
+    /// - `Error::NotFound`: There is no entry at `path`.
+    ///
+    /// [`link`]: crate::repo::file::FileRepo::link
+    pub fn tree_stats(&self, path: impl AsRef<RelativePath>) -> crate::Result<TreeStats> {
+        let path = path.as_ref();
+        let tree = &self.repo.state().tree;
+
+        let mut stats = TreeStats::default();
+        let mut visited_entries = HashSet::new();
+        let mut file_objects = Vec::new();
+        let mut visit = |entry_handle: &EntryHandle| {
+            if !visited_entries.insert(entry_handle.id()) {
+                return;
+            }
+            match entry_handle.kind {
+                HandleType::File(object_id) => {
+                    stats.files += 1;
+                    file_objects.push(object_id);
+                }
+                HandleType::Directory => stats.directories += 1,
+                HandleType::Special => stats.special_files += 1,
+            }
+        };
+
+        match tree.get(path) {
+            Some(entry_handle) if !matches!(entry_handle.kind, HandleType::Directory) => {
+                visit(entry_handle)
+            }
+            None if path != *EMPTY_PATH => return Err(crate::Error::NotFound),
+            _ => {
+                for (_, entry_handle) in tree.descendants(path).unwrap() {
+                    visit(entry_handle);
+                }
+            }
+        }
+
+        let usage = self.repo.stats_for_objects(file_objects);
+        stats.apparent_size = usage.apparent_size();
+        stats.actual_size = usage.actual_size();
+
+        Ok(stats)
+    }
+
     /// Return information about the repository.
     pub fn info(&self) -> RepoInfo {
         self.repo.info()
     }
+
+    /// Return how file names are compared when looking up paths in this repository.
+    ///
+    /// See [`set_path_semantics`] for details.
+    ///
+    /// [`set_path_semantics`]: crate::repo::file::FileRepo::set_path_semantics
+    pub fn path_semantics(&self) -> PathSemantics {
+        self.repo.state().tree.semantics()
+    }
+
+    /// Set how file names are compared when looking up paths in this repository.
+    ///
+    /// This affects how entries are looked up by path, so with [`PathSemantics::CaseInsensitive`],
+    /// `README.TXT` refers to the same entry as `Readme.txt`. Entries keep the file names they
+    /// were created with, so they're listed and extracted with their original names.
+    ///
+    /// Unlike most other settings, this is stored in the repository, so it's kept when changes are
+    /// committed and the repository is reopened. The default is [`PathSemantics::CaseSensitive`].
+    ///
+    /// # Errors
+    /// - `Error::AlreadyExists`: There are two entries in the repository whose paths would be the
+    /// same under the new `semantics`. The path semantics are left unchanged.
+    ///
+    /// [`PathSemantics::CaseInsensitive`]: crate::repo::file::PathSemantics::CaseInsensitive
+    /// [`PathSemantics::CaseSensitive`]: crate::repo::file::PathSemantics::CaseSensitive
+    pub fn set_path_semantics(&mut self, semantics: PathSemantics) -> crate::Result<()> {
+        if self.path_semantics() == semantics {
+            return Ok(());
+        }
+
+        if self.repo.state_mut().tree.set_semantics(semantics) {
+            Ok(())
+        } else {
+            Err(crate::Error::AlreadyExists)
+        }
+    }
+
+    /// Return the size limits which are enforced when entries are added to this repository.
+    pub fn limits(&self) -> EntryLimits {
+        self.limits
+    }
+
+    /// Set the size limits which are enforced when entries are added to this repository.
+    ///
+    /// See [`EntryLimits`] for details. This does not affect entries which are already in the
+    /// repository. This setting is not persisted, so it must be set each time the repository is
+    /// opened.
+    ///
+    /// [`EntryLimits`]: crate::repo::file::EntryLimits
+    pub fn set_limits(&mut self, limits: EntryLimits) {
+        self.limits = limits;
+    }
+
+    /// Return whether this repository updates the timestamps in entry metadata automatically.
+    ///
+    /// See [`set_time_tracking`] for details.
+    ///
+    /// [`set_time_tracking`]: crate::repo::file::FileRepo::set_time_tracking
+    pub fn time_tracking(&self) -> bool {
+        self.track_times
+    }
+
+    /// Set whether this repository updates the timestamps in entry metadata automatically.
+    ///
+    /// When this is enabled, the repository records when file contents and metadata change using
+    /// [`FileMetadata::mark_modified`] and [`FileMetadata::mark_changed`], so that timestamps stay
+    /// consistent without mounting the repository as a FUSE file system. This has no effect on
+    /// entries which have no metadata.
+    ///
+    /// Metadata is marked as changed when it's set with [`set_metadata`]. Files opened with
+    /// [`open`] are marked as modified when changes are committed if their contents changed since
+    /// they were opened, so their modification time is the time of the commit.
+    ///
+    /// This is disabled by default. This setting is not persisted, so it must be set each time the
+    /// repository is opened.
+    ///
+    /// [`FileMetadata::mark_modified`]: crate::repo::file::FileMetadata::mark_modified
+    /// [`FileMetadata::mark_changed`]: crate::repo::file::FileMetadata::mark_changed
+    /// [`set_metadata`]: crate::repo::file::FileRepo::set_metadata
+    /// [`open`]: crate::repo::file::FileRepo::open
+    pub fn set_time_tracking(&mut self, enabled: bool) {
+        self.track_times = enabled;
+        if !enabled {
+            self.forget_opened_files();
+        }
+    }
+
+    /// Mark the metadata of each file whose contents changed since it was opened as modified.
+    fn mark_opened_files(&mut self) -> crate::Result<()> {
+        let now = SystemTime::now();
+        let opened_files = mem::take(self.opened_files.get_mut().unwrap());
+
+        for (handle, original_content) in opened_files.into_values() {
+            let file_id = match handle.kind {
+                HandleType::File(file_id) => file_id,
+                _ => continue,
+            };
+            let current_content = match self.repo.object(file_id) {
+                Some(object) => object.content_id()?,
+                None => continue,
+            };
+            if current_content == original_content {
+                continue;
+            }
+
+            let mut object = self.repo.object(handle.entry).unwrap();
+            let mut entry: Entry<S, M> = object.deserialize_with::<F, _>()?;
+            if let Some(metadata) = &mut entry.metadata {
+                metadata.mark_modified(now);
+                metadata.mark_changed(now);
+                object.serialize_with::<F, _>(&entry)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Forget which files have been opened, such as when changes are rolled back.
+    fn forget_opened_files(&mut self) {
+        self.opened_files.get_mut().unwrap().clear();
+    }
+
+    /// Subscribe to changes to entries at or below `prefix`.
+    ///
+    /// This returns a receiver which is sent an [`EntryEvent`] each time an entry at or below
+    /// `prefix` is created, modified, removed, or renamed, whether through this repository or
+    /// through a FUSE file system it's mounted as. Pass an empty path to watch every entry. The
+    /// subscription ends when the receiver is dropped.
+    ///
+    /// Writing to the contents of a file through an [`Object`] returned by [`open`] is not
+    /// reported, because the repository can't observe those writes; the FUSE file system reports
+    /// them as [`EntryEvent::Modified`]. Changes made by rolling back or restoring the repository,
+    /// like with [`Commit::rollback`] or [`restore_checkpoint`], are not reported either.
+    ///
+    /// Subscriptions are not persisted, so they don't survive reopening the repository.
+    ///
+    /// [`EntryEvent`]: crate::repo::file::EntryEvent
+    /// [`EntryEvent::Modified`]: crate::repo::file::EntryEvent::Modified
+    /// [`Object`]: crate::repo::Object
+    /// [`open`]: crate::repo::file::FileRepo::open
+    /// [`Commit::rollback`]: crate::repo::Commit::rollback
+    /// [`restore_checkpoint`]: crate::repo::file::FileRepo::restore_checkpoint
+    pub fn watch(&self, prefix: impl AsRef<RelativePath>) -> Receiver<EntryEvent> {
+        self.watchers.add(prefix.as_ref().to_owned())
+    }
+
+    /// Convert this repository into a [`SharedFileRepo`] which can be used from multiple threads.
+    ///
+    /// [`SharedFileRepo`]: crate::repo::file::SharedFileRepo
+    pub fn into_shared(self) -> SharedFileRepo<S, M, F> {
+        SharedFileRepo::new(self)
+    }
+
+    /// Return the quota for the current instance in bytes.
+    ///
+    /// See [`KeyRepo::quota`] for details.
+    ///
+    /// [`KeyRepo::quota`]: crate::repo::key::KeyRepo::quota
+    pub fn quota(&self) -> Option<u64> {
+        self.repo.quota()
+    }
+
+    /// Return the number of bytes of free space available in the data store.
+    ///
+    /// This returns `None` if the data store doesn't report how much space is available.
+    ///
+    /// # Errors
+    /// - `Error::Store`: An error occurred with the data store.
+    pub(super) fn available_space(&self) -> crate::Result<Option<u64>> {
+        self.repo.available_space()
+    }
+
+    /// Set the quota for the current instance to `quota` bytes.
+    ///
+    /// See [`KeyRepo::set_quota`] for details.
+    ///
+    /// [`KeyRepo::set_quota`]: crate::repo::key::KeyRepo::set_quota
+    pub fn set_quota(&mut self, quota: Option<u64>) {
+        self.repo.set_quota(quota)
+    }
+
+    /// Register `callback` to be called each time `event` happens in this repository.
+    ///
+    /// See [`KeyRepo::on_event`] for details.
+    ///
+    /// [`KeyRepo::on_event`]: crate::repo::key::KeyRepo::on_event
+    pub fn on_event(
+        &self,
+        event: RepoEvent,
+        callback: impl FnMut(&RepoInfo) + Send + 'static,
+    ) -> HookId {
+        self.repo.on_event(event, callback)
+    }
+
+    /// Remove the hook with the given `id`.
+    ///
+    /// See [`KeyRepo::remove_hook`] for details.
+    ///
+    /// [`KeyRepo::remove_hook`]: crate::repo::key::KeyRepo::remove_hook
+    pub fn remove_hook(&self, id: HookId) -> bool {
+        self.repo.remove_hook(id)
+    }
+
+    /// Return the log of commits to this repository.
+    ///
+    /// See [`KeyRepo::commit_log`] for details.
+    ///
+    /// [`KeyRepo::commit_log`]: crate::repo::key::KeyRepo::commit_log
+    pub fn commit_log(&self) -> &[CommitRecord] {
+        self.repo.commit_log()
+    }
+
+    /// Verify the integrity of the commit log.
+    ///
+    /// See [`KeyRepo::verify_commit_log`] for details.
+    ///
+    /// # Errors
+    /// - `Error::Corrupt`: The commit log has been tampered with.
+    ///
+    /// [`KeyRepo::verify_commit_log`]: crate::repo::key::KeyRepo::verify_commit_log
+    pub fn verify_commit_log(&self) -> crate::Result<()> {
+        self.repo.verify_commit_log()
+    }
+
+    /// Rewrite the data in the repository according to the given `options`.
+    ///
+    /// See [`KeyRepo::repack`] for details.
+    ///
+    /// [`KeyRepo::repack`]: crate::repo::key::KeyRepo::repack
+    pub fn repack(&mut self, options: RepackOptions) -> crate::Result<()> {
+        self.repo.repack(options)
+    }
+
+    /// Change the chunking method used for new data in the repository.
+    ///
+    /// See [`KeyRepo::set_chunking`] for details.
+    ///
+    /// [`KeyRepo::set_chunking`]: crate::repo::key::KeyRepo::set_chunking
+    pub fn set_chunking(&mut self, chunking: Chunking) -> crate::Result<()> {
+        self.mark_opened_files()?;
+        self.repo.set_chunking(chunking)
+    }
+
+    /// Rewrite every object in the current instance using the current chunking method.
+    ///
+    /// See [`KeyRepo::rechunk_all`] for details.
+    ///
+    /// [`KeyRepo::rechunk_all`]: crate::repo::key::KeyRepo::rechunk_all
+    pub fn rechunk_all(&mut self) -> crate::Result<()> {
+        self.repo.rechunk_all()
+    }
+
+    /// Persist uncommitted changes to the data store without committing them.
+    ///
+    /// See [`KeyRepo::flush`] for details.
+    ///
+    /// [`KeyRepo::flush`]: crate::repo::key::KeyRepo::flush
+    pub fn flush(&mut self) -> crate::Result<()> {
+        self.repo.flush()
+    }
+
+    /// Restore the repository to the state it was in when changes were last flushed.
+    ///
+    /// See [`KeyRepo::restore_flushed`] for details.
+    ///
+    /// [`KeyRepo::restore_flushed`]: crate::repo::key::KeyRepo::restore_flushed
+    pub fn restore_flushed(&mut self) -> crate::Result<bool> {
+        let restored = self.repo.restore_flushed()?;
+        if restored {
+            self.forget_opened_files();
+        }
+        Ok(restored)
+    }
+
+    /// Save the current state of the repository as a checkpoint named `name`.
+    ///
+    /// See [`KeyRepo::create_checkpoint`] for details.
+    ///
+    /// [`KeyRepo::create_checkpoint`]: crate::repo::key::KeyRepo::create_checkpoint
+    pub fn create_checkpoint(&mut self, name: impl Into<String>) -> crate::Result<()> {
+        self.repo.create_checkpoint(name)
+    }
+
+    /// Remove the checkpoint named `name` from the repository.
+    ///
+    /// See [`KeyRepo::remove_checkpoint`] for details.
+    ///
+    /// [`KeyRepo::remove_checkpoint`]: crate::repo::key::KeyRepo::remove_checkpoint
+    pub fn remove_checkpoint(&mut self, name: &str) -> bool {
+        self.repo.remove_checkpoint(name)
+    }
+
+    /// Return whether there is a checkpoint named `name`.
+    pub fn contains_checkpoint(&self, name: &str) -> bool {
+        self.repo.contains_checkpoint(name)
+    }
+
+    /// Return an iterator over the names of the checkpoints in this repository.
+    ///
+    /// See [`KeyRepo::checkpoints`] for details.
+    ///
+    /// [`KeyRepo::checkpoints`]: crate::repo::key::KeyRepo::checkpoints
+    pub fn checkpoints(&self) -> Checkpoints {
+        self.repo.checkpoints()
+    }
+
+    /// Restore the repository to the state it was in when the checkpoint `name` was created.
+    ///
+    /// See [`KeyRepo::restore_checkpoint`] for details.
+    ///
+    /// [`KeyRepo::restore_checkpoint`]: crate::repo::key::KeyRepo::restore_checkpoint
+    pub fn restore_checkpoint(&mut self, name: &str) -> crate::Result<()> {
+        self.repo.restore_checkpoint(name)?;
+        self.forget_opened_files();
+        Ok(())
+    }
+
+    /// Return the number of previous commits which the repository can be restored to.
+    ///
+    /// See [`KeyRepo::previous_commits`] for details.
+    ///
+    /// [`KeyRepo::previous_commits`]: crate::repo::key::KeyRepo::previous_commits
+    pub fn previous_commits(&self) -> usize {
+        self.repo.previous_commits()
+    }
+
+    /// Restore the repository to the state it was in `age` commits before the most recent one.
+    ///
+    /// See [`KeyRepo::restore_commit`] for details.
+    ///
+    /// [`KeyRepo::restore_commit`]: crate::repo::key::KeyRepo::restore_commit
+    pub fn restore_commit(&mut self, age: usize) -> crate::Result<()> {
+        self.repo.restore_commit(age)?;
+        self.forget_opened_files();
+        Ok(())
+    }
 }
 
-impl<S, M> Commit for FileRepo<S, M>
+impl<S, M, F> Commit for FileRepo<S, M, F>
 where
     S: SpecialType,
     M: FileMetadata,
+    F: Format,
 {
     fn commit(&mut self) -> crate::Result<()> {
+        self.mark_opened_files()?;
         self.repo.commit()
     }
 
     fn rollback(&mut self) -> crate::Result<()> {
+        self.forget_opened_files();
         self.repo.rollback()
     }
 
@@ -1277,10 +2674,11 @@ where
         self.repo.clean()
     }
 }
-impl<S, M> RestoreSavepoint for FileRepo<S, M>
+impl<S, M, F> RestoreSavepoint for FileRepo<S, M, F>
 where
     S: SpecialType,
     M: FileMetadata,
+    F: Format,
 {
     type Restore = <StateRepo<RepoState> as RestoreSavepoint>::Restore;
 
@@ -1293,10 +2691,56 @@ where
     }
 
     fn finish_restore(&mut self, restore: Self::Restore) -> bool {
-        self.repo.finish_restore(restore)
+        let restored = self.repo.finish_restore(restore);
+        if restored {
+            self.forget_opened_files();
+        }
+        restored
+    }
+}
+
+/// Read the file at `path` and return an `Entry` which represents it.
+///
+/// # Errors
+/// - `Error::FileType`: The file is not a regular file, directory, or supported special file.
+/// - `Error::Io`: An I/O error occurred.
+fn read_entry<S: SpecialType, M: FileMetadata>(path: &Path) -> crate::Result<Entry<S, M>> {
+    let file_metadata = metadata(path)?;
+
+    let file_type = if file_metadata.is_file() {
+        EntryType::File
+    } else if file_metadata.is_dir() {
+        EntryType::Directory
+    } else {
+        EntryType::Special(S::from_file(path)?.ok_or(crate::Error::FileType)?)
+    };
+
+    Ok(Entry {
+        kind: file_type,
+        metadata: M::from_file(path)?,
+    })
+}
+
+/// Return a unique identifier for the file with the given `metadata` if it has multiple hard links.
+#[cfg(unix)]
+fn hard_link_id(metadata: &Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+
+    if metadata.nlink() > 1 {
+        Some((metadata.dev(), metadata.ino()))
+    } else {
+        None
     }
 }
 
+/// Return a unique identifier for the file with the given `metadata` if it has multiple hard links.
+///
+/// Hard links are not detected on this platform.
+#[cfg(not(unix))]
+fn hard_link_id(_metadata: &Metadata) -> Option<(u64, u64)> {
+    None
+}
+
 /// The default mount options which are always passed to libfuse.
 #[cfg(all(any(unix, doc), feature = "fuse-mount"))]
 const DEFAULT_FUSE_MOUNT_OPTS: &[MountOption] = &[MountOption::DefaultPermissions];
@@ -1310,6 +2754,13 @@ impl FileRepo<UnixSpecial, UnixMetadata> {
     /// file system at `mountpoint`. This also accepts an array of mount `options` to pass to
     /// libfuse. This method enables the [`DefaultPermissions`] mount option by default.
     ///
+    /// Advisory file locks set with `fcntl` are supported. They are only tracked in memory, so they
+    /// only apply to processes accessing the repository through this file system. Locks set with
+    /// `flock` are handled by the kernel.
+    ///
+    /// Copying the whole contents of a file with `copy_file_range` creates a copy-on-write clone
+    /// like [`reflink`] instead of copying the bytes.
+    ///
     /// This method does not return until the file system is unmounted.
     ///
     /// # Errors
@@ -1319,31 +2770,71 @@ impl FileRepo<UnixSpecial, UnixMetadata> {
     /// - `Error::Io`: An I/O error occurred.
     ///
     /// [`DefaultPermissions`]: crate::repo::file::MountOption::DefaultPermissions
+    /// [`reflink`]: crate::repo::file::FileRepo::reflink
     pub fn mount(
         &mut self,
         mountpoint: impl AsRef<Path>,
         root: impl AsRef<RelativePath>,
         options: &[MountOption],
     ) -> crate::Result<()> {
-        let adapter = FuseAdapter::new(self, root.as_ref())?;
+        mount_repo(
+            &Mutex::new(self),
+            mountpoint.as_ref(),
+            root.as_ref(),
+            options,
+        )
+    }
+}
 
-        // These need to be deduplicated.
-        let all_opts = [DEFAULT_FUSE_MOUNT_OPTS, options]
-            .concat()
-            .into_iter()
-            .map(|opt| opt.into_fuser())
-            .collect::<HashSet<_>>()
-            .into_iter()
-            .collect::<Vec<_>>();
+/// Mount the repository behind `repo` at `mountpoint` with the given mount `options`.
+///
+/// If the `AutoCommit` option was passed, this commits changes from another thread at the given
+/// interval until the file system is unmounted.
+#[cfg(all(any(unix, doc), feature = "fuse-mount"))]
+pub(super) fn mount_repo(
+    repo: impl LockRepo + Clone + Send,
+    mountpoint: &Path,
+    root: &RelativePath,
+    options: &[MountOption],
+) -> crate::Result<()> {
+    let config = AdapterConfig::from_options(options);
+    let auto_commit = config.auto_commit;
+    let adapter = SharedFuseAdapter::new(repo, root, config)?;
+
+    // These need to be deduplicated.
+    let all_opts = [DEFAULT_FUSE_MOUNT_OPTS, options]
+        .concat()
+        .into_iter()
+        .filter_map(|opt| opt.into_fuser())
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect::<Vec<_>>();
+
+    thread::scope(|scope| {
+        // Nothing is ever sent on this channel. Dropping the sender stops the timer.
+        let (stop_sender, stop_receiver) = mpsc::channel::<()>();
+
+        if let Some(interval) = auto_commit {
+            let timer_adapter = adapter.clone();
+            scope.spawn(move || {
+                while stop_receiver.recv_timeout(interval) == Err(RecvTimeoutError::Timeout) {
+                    // If committing fails, changes will be committed at the next interval instead.
+                    let _ = timer_adapter.commit_all();
+                }
+            });
+        }
 
-        Ok(fuser::mount2(adapter, &mountpoint, &all_opts)?)
-    }
+        let result = fuser::mount2(adapter, mountpoint, &all_opts);
+        drop(stop_sender);
+        Ok(result?)
+    })
 }
 
-impl<S, M> Unlock for FileRepo<S, M>
+impl<S, M, F> Unlock for FileRepo<S, M, F>
 where
     S: SpecialType,
     M: FileMetadata,
+    F: Format,
 {
     fn unlock(&self) -> crate::Result<()> {
         self.repo.unlock()
@@ -0,0 +1,49 @@
+use std::borrow::Cow;
+
+use serde::{Deserialize, Serialize};
+use unicode_normalization::{is_nfc, UnicodeNormalization};
+
+/// How the file names in a [`FileRepo`] are compared when looking up paths.
+///
+/// In every mode, entries keep the file names they were created with. This only determines which
+/// file names are considered to be the same, so that, for example, a file created as `Readme.txt`
+/// can be opened as `README.TXT` in a case-insensitive repository, but still has the name
+/// `Readme.txt` when it's listed or extracted.
+///
+/// This is set with [`FileRepo::set_path_semantics`].
+///
+/// [`FileRepo`]: crate::repo::file::FileRepo
+/// [`FileRepo::set_path_semantics`]: crate::repo::file::FileRepo::set_path_semantics
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Default, Serialize, Deserialize)]
+pub enum PathSemantics {
+    /// File names are compared byte-for-byte.
+    ///
+    /// This is the default, and it matches the behavior of most file systems on Linux.
+    #[default]
+    CaseSensitive,
+
+    /// File names are compared after Unicode normalization and case folding.
+    ///
+    /// This matches the behavior of the default file systems on macOS and Windows.
+    CaseInsensitive,
+
+    /// File names are compared after converting them to Unicode Normalization Form C (NFC).
+    ///
+    /// This makes file names which are canonically equivalent, like those with precomposed and
+    /// decomposed accented characters, refer to the same entry.
+    Normalized,
+}
+
+impl PathSemantics {
+    /// Return the form of the file `name` which is used to compare it to other file names.
+    pub(super) fn key<'a>(&self, name: &'a str) -> Cow<'a, str> {
+        match self {
+            PathSemantics::CaseSensitive => Cow::Borrowed(name),
+            PathSemantics::CaseInsensitive => {
+                Cow::Owned(name.nfc().collect::<String>().to_lowercase())
+            }
+            PathSemantics::Normalized if is_nfc(name) => Cow::Borrowed(name),
+            PathSemantics::Normalized => Cow::Owned(name.nfc().collect()),
+        }
+    }
+}
@@ -0,0 +1,292 @@
+use std::collections::HashSet;
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, Mutex};
+
+use relative_path::{RelativePath, RelativePathBuf};
+use static_assertions::assert_impl_all;
+
+use crate::repo::{
+    Commit, Format, InstanceId, MessagePack, ReadOnlyObject, RepoInfo, RepoStats, Unlock,
+};
+
+use super::entry::{Entry, EntryId};
+use super::metadata::{FileMetadata, NoMetadata};
+use super::repository::FileRepo;
+use super::special::{NoSpecial, SpecialType};
+use super::watch::EntryEvent;
+#[cfg(all(any(unix, doc), feature = "fuse-mount"))]
+use {
+    super::fuse::MountOption, super::metadata::UnixMetadata, super::special::UnixSpecial,
+    std::path::Path,
+};
+
+/// A handle to a [`FileRepo`] which can be shared between threads.
+///
+/// This value is created by [`FileRepo::into_shared`]. It can be cheaply cloned, and each clone
+/// refers to the same repository. Each operation locks the whole repository until it completes.
+///
+/// This handle only provides operations which don't modify the tree of entries or the contents of
+/// files, so it's safe to use while the repository is mounted as a FUSE file system with [`mount`].
+/// Files can only be opened for reading with [`open`]. This allows an application to query entries,
+/// read files, compute statistics, and commit changes while the file system is in use. Changes to
+/// files which are open in the file system are not committed until they are flushed or closed, or
+/// until they are committed automatically with [`MountOption::AutoCommit`].
+///
+/// Once you're done using the repository from multiple threads, you can convert it back into a
+/// [`FileRepo`] with [`try_unwrap`].
+///
+/// [`FileRepo`]: crate::repo::file::FileRepo
+/// [`FileRepo::into_shared`]: crate::repo::file::FileRepo::into_shared
+/// [`mount`]: crate::repo::file::SharedFileRepo::mount
+/// [`MountOption::AutoCommit`]: crate::repo::file::MountOption::AutoCommit
+/// [`open`]: crate::repo::file::SharedFileRepo::open
+/// [`try_unwrap`]: crate::repo::file::SharedFileRepo::try_unwrap
+#[derive(Debug)]
+pub struct SharedFileRepo<S = NoSpecial, M = NoMetadata, F = MessagePack>(
+    Arc<Mutex<FileRepo<S, M, F>>>,
+)
+where
+    S: SpecialType,
+    M: FileMetadata,
+    F: Format;
+
+assert_impl_all!(SharedFileRepo: Send, Sync);
+
+impl<S, M, F> Clone for SharedFileRepo<S, M, F>
+where
+    S: SpecialType,
+    M: FileMetadata,
+    F: Format,
+{
+    fn clone(&self) -> Self {
+        Self(Arc::clone(&self.0))
+    }
+}
+
+impl<S, M, F> SharedFileRepo<S, M, F>
+where
+    S: SpecialType,
+    M: FileMetadata,
+    F: Format,
+{
+    /// Create a new `SharedFileRepo` from the given `repo`.
+    pub(super) fn new(repo: FileRepo<S, M, F>) -> Self {
+        Self(Arc::new(Mutex::new(repo)))
+    }
+
+    /// Call `block` with the backing repository.
+    ///
+    /// This blocks all other operations on the repository until `block` returns.
+    pub(super) fn with_repo<T>(&self, block: impl FnOnce(&mut FileRepo<S, M, F>) -> T) -> T {
+        block(&mut self.0.lock().unwrap())
+    }
+
+    /// Call `block` with a reference to the backing repository and return its result.
+    ///
+    /// This must not be exposed publicly, because some methods which take `&FileRepo` can still
+    /// modify files, which the FUSE file system wouldn't know about.
+    fn inspect<T>(&self, block: impl FnOnce(&FileRepo<S, M, F>) -> T) -> T {
+        block(&self.0.lock().unwrap())
+    }
+
+    /// Return whether there is an entry at `path`.
+    pub fn exists(&self, path: impl AsRef<RelativePath>) -> bool {
+        self.inspect(|repo| repo.exists(path))
+    }
+
+    /// Return whether `path` is a regular file entry.
+    ///
+    /// If there is no entry at `path`, this returns `false`.
+    pub fn is_file(&self, path: impl AsRef<RelativePath>) -> bool {
+        self.inspect(|repo| repo.is_file(path))
+    }
+
+    /// Return whether `path` is a directory entry.
+    ///
+    /// If there is no entry at `path`, this returns `false`.
+    pub fn is_directory(&self, path: impl AsRef<RelativePath>) -> bool {
+        self.inspect(|repo| repo.is_directory(path))
+    }
+
+    /// Return the entry at `path`.
+    ///
+    /// See [`FileRepo::entry`] for details.
+    ///
+    /// [`FileRepo::entry`]: crate::repo::file::FileRepo::entry
+    pub fn entry(&self, path: impl AsRef<RelativePath>) -> crate::Result<Entry<S, M>> {
+        self.inspect(|repo| repo.entry(path))
+    }
+
+    /// Return the `EntryId` of the entry at `path`.
+    ///
+    /// See [`FileRepo::entry_id`] for details.
+    ///
+    /// [`FileRepo::entry_id`]: crate::repo::file::FileRepo::entry_id
+    pub fn entry_id(&self, path: impl AsRef<RelativePath>) -> crate::Result<EntryId> {
+        self.inspect(|repo| repo.entry_id(path))
+    }
+
+    /// Return a `ReadOnlyObject` for reading the contents of the file at `path`.
+    ///
+    /// Unlike [`FileRepo::open`], the returned object can't be used to modify the file.
+    ///
+    /// # Errors
+    /// - `Error::InvalidPath`: The given `path` is empty.
+    /// - `Error::NotFound`: There is no entry with the given `path`.
+    /// - `Error::NotFile`: The entry does not represent a regular file.
+    ///
+    /// [`FileRepo::open`]: crate::repo::file::FileRepo::open
+    pub fn open(&self, path: impl AsRef<RelativePath>) -> crate::Result<ReadOnlyObject> {
+        self.inspect(|repo| ReadOnlyObject::try_from(repo.open(path)?))
+    }
+
+    /// Return a list of paths which are immediate children of `parent`.
+    ///
+    /// Because other threads may be modifying the repository concurrently, the returned list is a
+    /// snapshot of the children at the time this method was called.
+    ///
+    /// See [`FileRepo::children`] for details.
+    ///
+    /// [`FileRepo::children`]: crate::repo::file::FileRepo::children
+    pub fn children(
+        &self,
+        parent: impl AsRef<RelativePath>,
+    ) -> crate::Result<Vec<RelativePathBuf>> {
+        self.inspect(|repo| Ok(repo.children(parent)?.collect()))
+    }
+
+    /// Return a list of paths which are descendants of `parent`.
+    ///
+    /// Because other threads may be modifying the repository concurrently, the returned list is a
+    /// snapshot of the descendants at the time this method was called.
+    ///
+    /// See [`FileRepo::descendants`] for details.
+    ///
+    /// [`FileRepo::descendants`]: crate::repo::file::FileRepo::descendants
+    pub fn descendants(
+        &self,
+        parent: impl AsRef<RelativePath>,
+    ) -> crate::Result<Vec<RelativePathBuf>> {
+        self.inspect(|repo| Ok(repo.descendants(parent)?.collect()))
+    }
+
+    /// Verify the integrity of all the data in the repository.
+    ///
+    /// This returns the set of paths of files with corrupt data or metadata.
+    ///
+    /// See [`FileRepo::verify`] for details.
+    ///
+    /// # Errors
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    ///
+    /// [`FileRepo::verify`]: crate::repo::file::FileRepo::verify
+    pub fn verify(&self) -> crate::Result<HashSet<RelativePathBuf>> {
+        self.inspect(|repo| repo.verify())
+    }
+
+    /// Return this repository's instance ID.
+    pub fn instance(&self) -> InstanceId {
+        self.inspect(|repo| repo.instance())
+    }
+
+    /// Compute statistics about the repository.
+    ///
+    /// See [`KeyRepo::stats`] for details.
+    ///
+    /// [`KeyRepo::stats`]: crate::repo::key::KeyRepo::stats
+    pub fn stats(&self) -> RepoStats {
+        self.inspect(|repo| repo.stats())
+    }
+
+    /// Return information about the repository.
+    pub fn info(&self) -> RepoInfo {
+        self.inspect(|repo| repo.info())
+    }
+
+    /// Subscribe to changes to entries at or below `prefix`.
+    ///
+    /// See [`FileRepo::watch`] for details.
+    ///
+    /// [`FileRepo::watch`]: crate::repo::file::FileRepo::watch
+    pub fn watch(&self, prefix: impl AsRef<RelativePath>) -> Receiver<EntryEvent> {
+        self.inspect(|repo| repo.watch(prefix))
+    }
+
+    /// Commit changes which have been made to the repository.
+    ///
+    /// See [`Commit::commit`] for details.
+    ///
+    /// # Errors
+    /// - `Error::Corrupt`: The repository is corrupt. This is most likely unrecoverable.
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    ///
+    /// [`Commit::commit`]: crate::repo::Commit::commit
+    pub fn commit(&self) -> crate::Result<()> {
+        self.with_repo(|repo| repo.commit())
+    }
+
+    /// Convert this handle back into a [`FileRepo`].
+    ///
+    /// If there are other clones of this handle, this returns `Err` containing this handle.
+    ///
+    /// [`FileRepo`]: crate::repo::file::FileRepo
+    pub fn try_unwrap(self) -> Result<FileRepo<S, M, F>, Self> {
+        let repo = Arc::try_unwrap(self.0).map_err(Self)?;
+        Ok(repo.into_inner().unwrap())
+    }
+}
+
+#[cfg(all(any(unix, doc), feature = "fuse-mount"))]
+#[cfg_attr(docsrs, doc(cfg(all(unix, feature = "fuse-mount"))))]
+impl SharedFileRepo<UnixSpecial, UnixMetadata> {
+    /// Mount the repository as a FUSE file system.
+    ///
+    /// This is like [`FileRepo::mount`], except the repository is only locked while the file
+    /// system is handling a request. Other clones of this handle can be used to access the
+    /// repository from other threads while it's mounted.
+    ///
+    /// This method does not return until the file system is unmounted.
+    ///
+    /// # Errors
+    /// - `Error::InvalidPath`: The given `root` path is empty.
+    /// - `Error::NotFound`: There is no entry at `root`.
+    /// - `Error::NotDirectory`: The given `root` entry is not a directory.
+    /// - `Error::Io`: An I/O error occurred.
+    ///
+    /// [`FileRepo::mount`]: crate::repo::file::FileRepo::mount
+    pub fn mount(
+        &self,
+        mountpoint: impl AsRef<Path>,
+        root: impl AsRef<RelativePath>,
+        options: &[MountOption],
+    ) -> crate::Result<()> {
+        super::repository::mount_repo(self.clone(), mountpoint.as_ref(), root.as_ref(), options)
+    }
+}
+
+impl<S, M, F> Unlock for SharedFileRepo<S, M, F>
+where
+    S: SpecialType,
+    M: FileMetadata,
+    F: Format,
+{
+    fn unlock(&self) -> crate::Result<()> {
+        self.inspect(|repo| repo.unlock())
+    }
+
+    fn is_locked(&self) -> crate::Result<bool> {
+        self.inspect(|repo| repo.is_locked())
+    }
+
+    fn context(&self) -> crate::Result<Vec<u8>> {
+        self.inspect(|repo| repo.context())
+    }
+
+    fn update_context(&self, context: &[u8]) -> crate::Result<()> {
+        self.inspect(|repo| repo.update_context(context))
+    }
+}
@@ -0,0 +1,52 @@
+/// Statistics about a tree of entries in a [`FileRepo`].
+///
+/// This is returned by [`FileRepo::tree_stats`].
+///
+/// [`FileRepo`]: crate::repo::file::FileRepo
+/// [`FileRepo::tree_stats`]: crate::repo::file::FileRepo::tree_stats
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TreeStats {
+    pub(super) files: u64,
+    pub(super) directories: u64,
+    pub(super) special_files: u64,
+    pub(super) apparent_size: u64,
+    pub(super) actual_size: u64,
+}
+
+impl TreeStats {
+    /// The number of regular files in the tree.
+    pub fn files(&self) -> u64 {
+        self.files
+    }
+
+    /// The number of directories in the tree.
+    pub fn directories(&self) -> u64 {
+        self.directories
+    }
+
+    /// The number of special files in the tree.
+    pub fn special_files(&self) -> u64 {
+        self.special_files
+    }
+
+    /// The total number of entries in the tree.
+    pub fn entries(&self) -> u64 {
+        self.files + self.directories + self.special_files
+    }
+
+    /// The sum of the apparent sizes of the regular files in the tree.
+    ///
+    /// This includes any sparse holes in the files.
+    pub fn apparent_size(&self) -> u64 {
+        self.apparent_size
+    }
+
+    /// The actual number of bytes stored in the regular files in the tree.
+    ///
+    /// See [`UsageStats::actual_size`] for details.
+    ///
+    /// [`UsageStats::actual_size`]: crate::repo::UsageStats::actual_size
+    pub fn actual_size(&self) -> u64 {
+        self.actual_size
+    }
+}
@@ -0,0 +1,52 @@
+/// A method for determining whether a file has changed since it was archived.
+///
+/// This is used by [`FileRepo::sync_tree`].
+///
+/// [`FileRepo::sync_tree`]: crate::repo::file::FileRepo::sync_tree
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ChangeDetection {
+    /// Compare the size and metadata of the file with those of the entry.
+    ///
+    /// This is fast, because it does not require reading the contents of any files. However, it
+    /// relies on the selected [`FileMetadata`] implementation to detect changes. If no metadata is
+    /// stored, such as with [`NoMetadata`], only the size of the file is compared, and changes
+    /// which don't affect the size of the file will not be detected.
+    ///
+    /// [`FileMetadata`]: crate::repo::file::FileMetadata
+    /// [`NoMetadata`]: crate::repo::file::NoMetadata
+    Metadata,
+
+    /// Compare the contents and metadata of the file with those of the entry.
+    ///
+    /// This detects all changes, but it requires reading the contents of every file in the tree.
+    Contents,
+}
+
+/// Options for synchronizing a directory tree with [`FileRepo::sync_tree`].
+///
+/// This type implements `Default`, which returns options that compare files by their metadata and
+/// do not remove entries.
+///
+/// [`FileRepo::sync_tree`]: crate::repo::file::FileRepo::sync_tree
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[non_exhaustive]
+pub struct SyncOptions {
+    /// The method used to determine whether a file has changed.
+    ///
+    /// The default value is `ChangeDetection::Metadata`.
+    pub detection: ChangeDetection,
+
+    /// Whether to remove entries for files which no longer exist in the file system.
+    ///
+    /// The default value is `false`.
+    pub remove_deleted: bool,
+}
+
+impl Default for SyncOptions {
+    fn default() -> Self {
+        Self {
+            detection: ChangeDetection::Metadata,
+            remove_deleted: false,
+        }
+    }
+}
@@ -0,0 +1,77 @@
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Mutex;
+
+use relative_path::{RelativePath, RelativePathBuf};
+
+/// A change to an entry in a [`FileRepo`].
+///
+/// These events are sent to the receivers returned by [`FileRepo::watch`].
+///
+/// [`FileRepo`]: crate::repo::file::FileRepo
+/// [`FileRepo::watch`]: crate::repo::file::FileRepo::watch
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum EntryEvent {
+    /// An entry was created at the given path.
+    Created(RelativePathBuf),
+
+    /// The entry at the given path was modified.
+    Modified(RelativePathBuf),
+
+    /// The entry at the given path was removed.
+    Removed(RelativePathBuf),
+
+    /// The entry at `source` was moved to `dest`.
+    Renamed {
+        /// The old path of the entry.
+        source: RelativePathBuf,
+
+        /// The new path of the entry.
+        dest: RelativePathBuf,
+    },
+}
+
+impl EntryEvent {
+    /// Return whether this event affects an entry at or below `prefix`.
+    fn matches(&self, prefix: &RelativePath) -> bool {
+        match self {
+            EntryEvent::Created(path) | EntryEvent::Modified(path) | EntryEvent::Removed(path) => {
+                path.starts_with(prefix)
+            }
+            EntryEvent::Renamed { source, dest } => {
+                source.starts_with(prefix) || dest.starts_with(prefix)
+            }
+        }
+    }
+}
+
+/// A subscriber which receives events for entries at or below `prefix`.
+#[derive(Debug)]
+struct Watcher {
+    prefix: RelativePathBuf,
+    sender: Sender<EntryEvent>,
+}
+
+/// The set of subscribers which are notified of changes to entries in a `FileRepo`.
+///
+/// This is not persisted and does not survive reopening the repository.
+#[derive(Debug, Default)]
+pub struct Watchers(Mutex<Vec<Watcher>>);
+
+impl Watchers {
+    /// Add a subscriber for entries at or below `prefix` and return its receiver.
+    pub fn add(&self, prefix: RelativePathBuf) -> Receiver<EntryEvent> {
+        let (sender, receiver) = channel();
+        self.0.lock().unwrap().push(Watcher { prefix, sender });
+        receiver
+    }
+
+    /// Send `event` to each subscriber whose prefix it matches.
+    ///
+    /// Subscribers whose receivers have been dropped are removed.
+    pub fn notify(&self, event: EntryEvent) {
+        let mut watchers = self.0.lock().unwrap();
+        watchers.retain(|watcher| {
+            !event.matches(&watcher.prefix) || watcher.sender.send(event.clone()).is_ok()
+        });
+    }
+}
@@ -6,7 +6,8 @@
 //!
 //! This module contains types which are common to most repositories. The most important of these
 //! are [`Object`] and [`ReadOnlyObject`], which provide views of data in a repository and are used
-//! to read data from them and write data to them.
+//! to read data from them and write data to them. An [`ObjectStream`] is a detached view of the
+//! contents of an object which is not affected by later changes to the repository.
 //!
 //! Each sub-module of this module contains a different repository type.
 //!
@@ -55,7 +56,10 @@
 //!
 //! # Encryption
 //! If encryption is enabled, the Argon2id key derivation function is used to derive a key from a
-//! user-supplied password. This key is used to encrypt the repository's randomly generated master
+//! user-supplied password. Other key derivation functions can be configured with
+//! [`RepoConfig::key_derivation`] for environments which can't use Argon2id, or a [`KeyProvider`]
+//! can be used to protect the master key with an external mechanism instead of a password. This
+//! key is used to encrypt the repository's randomly generated master
 //! key, which is used to encrypt all data in the repository. This setup means that the repository's
 //! password can be changed without re-encrypting any data.
 //!
@@ -66,7 +70,16 @@
 //! it to the data store at the cost of performance. See [`Packing`] for details.
 //!
 //! The information in [`RepoInfo`] is never encrypted, and can be read without decrypting the
-//! repository using [`peek_info`].
+//! repository using [`peek_info`]. This includes a [`Fingerprint`] of the data in the repository,
+//! which can be used to verify that two replicas of a repository are identical.
+//!
+//! The metadata of a repository can be detached from its data store using [`export_repo`], saved
+//! to a file, and later re-attached using [`RepoExport::attach`]. This is useful for recovering a
+//! repository whose metadata has been damaged.
+//!
+//! Repositories created by older versions of this library may use a previous format version,
+//! which causes opening them to return `Error::UnsupportedRepo`. You can detect this ahead of time
+//! with [`RepoInfo::needs_upgrade`] and upgrade the repository in place with [`upgrade_repo`].
 //!
 //! # Instances
 //! A repository can consist of multiple instances, each identified by an [`InstanceId`]. Each
@@ -95,9 +108,14 @@
 //! repository commits changes for all instances of that repository; it is not possible to commit
 //! changes to only a single instance. The same goes for rolling back changes.
 //!
+//! Instances are not a security boundary. Anyone who can open one instance of a repository can
+//! list the IDs of all its instances and open any of them, so instances can't be used to hide data
+//! from someone who knows the password.
+//!
 //! [`DataStore`]: crate::store::DataStore
 //! [`Object`]: crate::repo::Object
 //! [`ReadOnlyObject`]: crate::repo::ReadOnlyObject
+//! [`ObjectStream`]: crate::repo::ObjectStream
 //! [`KeyRepo`]: crate::repo::key::KeyRepo
 //! [`OpenOptions`]: crate::repo::OpenOptions
 //! [`Chunking`]: crate::repo::Chunking
@@ -109,17 +127,32 @@
 //! [`Packing`]: crate::repo::Packing
 //! [`RepoInfo`]: crate::repo::RepoInfo
 //! [`peek_info`]: crate::repo::peek_info
+//! [`Fingerprint`]: crate::repo::Fingerprint
+//! [`export_repo`]: crate::repo::export_repo
+//! [`RepoInfo::needs_upgrade`]: crate::repo::RepoInfo::needs_upgrade
+//! [`upgrade_repo`]: crate::repo::upgrade_repo
+//! [`RepoExport::attach`]: crate::repo::RepoExport::attach
 //! [`InstanceId`]: crate::repo::InstanceId
 //! [`SwitchInstance::switch_instance`]: crate::repo::SwitchInstance::switch_instance
 //! [`FileRepo`]: crate::repo::file::FileRepo
 
 pub use self::common::{
-    peek_info, Chunking, Commit, Compression, ContentId, Encryption, InstanceId, Object, ObjectId,
-    ObjectStats, OpenMode, OpenOptions, OpenRepo, Packing, ReadOnlyObject, RepoConfig, RepoId,
+    export_repo, peek_info, upgrade_repo, Access, CheckReport, Checkpoints, ChunkId, Chunking,
+    Commit, CommitRecord, Compression, ConfigPreset, ContentId, Encryption, Fingerprint, Format,
+    HookId, InstanceId, InstanceSummary, KeyDerivation, KeyProvider, LockInfo, MergeConflict,
+    MergeReport, MessagePack, Object, ObjectId, ObjectStats, ObjectStream, OpenMode, OpenOptions,
+    OpenRepo, Packing, ReadOnlyObject, RepackOptions, RepoConfig, RepoEvent, RepoExport, RepoId,
     RepoInfo, RepoStats, ResourceLimit, Restore, RestoreSavepoint, Savepoint, SwitchInstance,
-    Unlock, VersionId, DEFAULT_INSTANCE,
+    UndoRepo, Unlock, UsageStats, VersionId, DEFAULT_INSTANCE,
 };
 
+#[cfg(feature = "format-cbor")]
+pub use self::common::Cbor;
+#[cfg(feature = "format-json")]
+pub use self::common::Json;
+#[cfg(feature = "observability")]
+pub use self::common::Metrics;
+
 /// An object store which maps keys to seekable binary blobs.
 ///
 /// This module contains the [`KeyRepo`] repository type.
@@ -136,7 +169,7 @@ pub use self::common::{
 /// [`Key`]: crate::repo::key::Key
 /// [`Commit::commit`]: crate::repo::Commit::commit
 pub mod key {
-    pub use super::common::{Key, KeyRepo, Keys};
+    pub use super::common::{Key, KeyRepo, Keys, KeysWithPrefix, SharedKeyRepo};
 }
 
 mod common;
@@ -145,6 +178,18 @@ mod common;
 #[cfg_attr(docsrs, doc(cfg(feature = "repo-file")))]
 pub mod file;
 
+#[cfg(feature = "repo-session")]
+#[cfg_attr(docsrs, doc(cfg(feature = "repo-session")))]
+pub mod session;
+
+#[cfg(feature = "repo-single")]
+#[cfg_attr(docsrs, doc(cfg(feature = "repo-single")))]
+pub mod single;
+
+#[cfg(feature = "repo-snapshot")]
+#[cfg_attr(docsrs, doc(cfg(feature = "repo-snapshot")))]
+pub mod snapshot;
+
 pub mod state;
 
 #[cfg(feature = "repo-value")]
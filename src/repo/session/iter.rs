@@ -0,0 +1,35 @@
+use std::collections::hash_map;
+use std::iter::FusedIterator;
+use std::time::SystemTime;
+
+use super::repository::SessionEntry;
+
+/// An iterator over the keys of unexpired values in a [`SessionRepo`].
+///
+/// This value is created by [`SessionRepo::keys`].
+///
+/// [`SessionRepo`]: crate::repo::session::SessionRepo
+/// [`SessionRepo::keys`]: crate::repo::session::SessionRepo::keys
+#[derive(Debug, Clone)]
+pub struct Keys<'a, K> {
+    pub(super) now: SystemTime,
+    pub(super) inner: hash_map::Iter<'a, K, SessionEntry>,
+}
+
+impl<'a, K> Iterator for Keys<'a, K> {
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let now = self.now;
+        self.inner
+            .by_ref()
+            .find(|(_, entry)| !entry.is_expired(now))
+            .map(|(key, _)| key)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.inner.size_hint().1)
+    }
+}
+
+impl<'a, K> FusedIterator for Keys<'a, K> {}
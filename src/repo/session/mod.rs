@@ -0,0 +1,52 @@
+//! A persistent key-value store where values expire after a period of time.
+//!
+//! This module contains the [`SessionRepo`] repository type.
+//!
+//! This is a repository which maps keys to concrete values like a [`ValueRepo`], except each value
+//! is stored with a time-to-live. Once a value's time-to-live elapses, it is considered expired and
+//! behaves as if it was removed from the repository. This is useful for implementing things like
+//! persistent session stores.
+//!
+//! Expired values are not automatically removed from the repository and continue to use space in
+//! the data store until [`SessionRepo::purge_expired`] is called. This method is designed to be
+//! called periodically from an application's scheduler, followed by [`Commit::commit`] and
+//! [`Commit::clean`] to reclaim space in the data store.
+//!
+//! Expiration times are based on the system clock of the machine accessing the repository.
+//!
+//! Like other repositories, changes made to the repository are not persisted to the data store
+//! until [`Commit::commit`] is called. For details about deduplication, compression, encryption,
+//! and locking, see the module-level documentation for [`crate::repo`].
+//!
+//! # Examples
+//! ```
+//! use std::time::Duration;
+//!
+//! use acid_store::repo::{Commit, OpenMode, OpenOptions, session::SessionRepo};
+//! use acid_store::store::MemoryConfig;
+//!
+//! let mut repo: SessionRepo<String> = OpenOptions::new()
+//!     .mode(OpenMode::CreateNew)
+//!     .open(&MemoryConfig::new())
+//!     .unwrap();
+//!
+//! repo.insert("session-id".to_string(), &42u64, Duration::from_secs(3600)).unwrap();
+//! assert_eq!(repo.get::<_, u64>("session-id").unwrap(), 42);
+//!
+//! // This would typically be called periodically from a scheduled task.
+//! repo.purge_expired();
+//! repo.commit().unwrap();
+//! repo.clean().unwrap();
+//! ```
+//!
+//! [`SessionRepo`]: crate::repo::session::SessionRepo
+//! [`ValueRepo`]: crate::repo::value::ValueRepo
+//! [`SessionRepo::purge_expired`]: crate::repo::session::SessionRepo::purge_expired
+//! [`Commit::commit`]: crate::repo::Commit::commit
+//! [`Commit::clean`]: crate::repo::Commit::clean
+
+pub use self::iter::Keys;
+pub use self::repository::SessionRepo;
+
+mod iter;
+mod repository;
@@ -0,0 +1,549 @@
+use std::borrow::Borrow;
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+use std::time::{Duration, SystemTime};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use uuid::uuid;
+
+use super::iter::Keys;
+use crate::repo::{
+    key::{Key, KeyRepo},
+    state::{ObjectKey, StateRepo},
+    Checkpoints, Chunking, Commit, CommitRecord, HookId, InstanceId, InstanceSummary, OpenRepo,
+    RepackOptions, RepoEvent, RepoInfo, RepoStats, ResourceLimit, RestoreSavepoint, Savepoint,
+    Unlock, UsageStats, VersionId,
+};
+
+/// A value stored in a `SessionRepo` along with its expiration time.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct SessionEntry {
+    /// The key of the object which stores the serialized value.
+    object: ObjectKey,
+
+    /// The time at which this value expires.
+    expires: SystemTime,
+}
+
+impl SessionEntry {
+    /// Return whether this value is expired at the given time `now`.
+    pub fn is_expired(&self, now: SystemTime) -> bool {
+        self.expires <= now
+    }
+}
+
+type RepoState<K> = HashMap<K, SessionEntry>;
+
+/// A persistent key-value store where values expire after a period of time.
+///
+/// See [`crate::repo::session`] for more information.
+#[derive(Debug)]
+pub struct SessionRepo<K: Key>(StateRepo<RepoState<K>>);
+
+impl<K: Key> OpenRepo for SessionRepo<K> {
+    type Key = <StateRepo<RepoState<K>> as OpenRepo>::Key;
+
+    const VERSION_ID: VersionId = VersionId::new(uuid!("8a3f6e52-1d4b-4c7e-b2a9-5e0d3c7f91a6"));
+
+    fn open_repo(repo: KeyRepo<Self::Key>) -> crate::Result<Self>
+    where
+        Self: Sized,
+    {
+        Ok(Self(StateRepo::open_repo(repo)?))
+    }
+
+    fn create_repo(repo: KeyRepo<Self::Key>) -> crate::Result<Self>
+    where
+        Self: Sized,
+    {
+        Ok(Self(StateRepo::create_repo(repo)?))
+    }
+
+    fn into_repo(self) -> crate::Result<KeyRepo<Self::Key>> {
+        self.0.into_repo()
+    }
+}
+
+impl<K: Key> SessionRepo<K> {
+    /// Return the entry for `key` if it exists and is not expired.
+    fn live_entry<Q>(&self, key: &Q) -> Option<&SessionEntry>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.0
+            .state()
+            .get(key)
+            .filter(|entry| !entry.is_expired(SystemTime::now()))
+    }
+
+    /// Return whether the given `key` exists in this repository and is not expired.
+    pub fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.live_entry(key).is_some()
+    }
+
+    /// Insert a new key-value pair which expires after `ttl` has elapsed.
+    ///
+    /// If `key` is already in the repository, its value and expiration time are replaced.
+    ///
+    /// # Errors
+    /// - `Error::Serialize`: The `value` could not be serialized.
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    pub fn insert<V: Serialize>(&mut self, key: K, value: &V, ttl: Duration) -> crate::Result<()> {
+        let object_id = self.0.create();
+        let mut object = self.0.object(object_id).unwrap();
+        let result = object.serialize(value);
+        drop(object);
+        if let Err(error) = result {
+            self.0.remove(object_id);
+            return Err(error);
+        }
+
+        let entry = SessionEntry {
+            object: object_id,
+            expires: SystemTime::now() + ttl,
+        };
+
+        if let Some(prev_entry) = self.0.state_mut().insert(key, entry) {
+            self.0.remove(prev_entry.object);
+        }
+
+        Ok(())
+    }
+
+    /// Remove the value associated with `key` from the repository.
+    ///
+    /// This returns `true` if the value was removed or `false` if it didn't exist or was expired.
+    ///
+    /// The space used by the given value isn't reclaimed in the backing data store until changes
+    /// are committed and [`Commit::clean`] is called.
+    ///
+    /// [`Commit::clean`]: crate::repo::Commit::clean
+    pub fn remove<Q>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match self.0.state_mut().remove(key) {
+            Some(entry) => {
+                self.0.remove(entry.object);
+                !entry.is_expired(SystemTime::now())
+            }
+            None => false,
+        }
+    }
+
+    /// Return the value associated with `key`.
+    ///
+    /// # Errors
+    /// - `Error::NotFound`: There is no value associated with `key` or it has expired.
+    /// - `Error::Deserialize`: The value could not be deserialized.
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    pub fn get<Q, V>(&self, key: &Q) -> crate::Result<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        V: DeserializeOwned,
+    {
+        let entry = self.live_entry(key).ok_or(crate::Error::NotFound)?;
+        let mut object = self.0.object(entry.object).unwrap();
+        object.deserialize()
+    }
+
+    /// Return the time at which the value associated with `key` expires.
+    ///
+    /// This returns `None` if there is no value associated with `key` or it has expired.
+    pub fn expires_at<Q>(&self, key: &Q) -> Option<SystemTime>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.live_entry(key).map(|entry| entry.expires)
+    }
+
+    /// Reset the expiration time of the value associated with `key` to expire after `ttl`.
+    ///
+    /// This does not modify the value itself.
+    ///
+    /// # Errors
+    /// - `Error::NotFound`: There is no value associated with `key` or it has expired.
+    pub fn touch<Q>(&mut self, key: &Q, ttl: Duration) -> crate::Result<()>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let now = SystemTime::now();
+        let entry = self
+            .0
+            .state_mut()
+            .get_mut(key)
+            .filter(|entry| !entry.is_expired(now))
+            .ok_or(crate::Error::NotFound)?;
+        entry.expires = now + ttl;
+        Ok(())
+    }
+
+    /// Remove all expired values from the repository.
+    ///
+    /// This returns the number of values which were removed.
+    ///
+    /// This is designed to be called periodically from an application's scheduler. The space used
+    /// by the removed values isn't reclaimed in the backing data store until changes are committed
+    /// and [`Commit::clean`] is called.
+    ///
+    /// [`Commit::clean`]: crate::repo::Commit::clean
+    pub fn purge_expired(&mut self) -> usize {
+        let now = SystemTime::now();
+        let expired_keys = self
+            .0
+            .state()
+            .iter()
+            .filter(|(_, entry)| entry.is_expired(now))
+            .map(|(key, _)| key.clone())
+            .collect::<Vec<_>>();
+
+        for key in &expired_keys {
+            let entry = self.0.state_mut().remove(key).unwrap();
+            self.0.remove(entry.object);
+        }
+
+        expired_keys.len()
+    }
+
+    /// Return an iterator of the keys of all the unexpired values in this repository.
+    pub fn keys(&self) -> Keys<K> {
+        Keys {
+            now: SystemTime::now(),
+            inner: self.0.state().iter(),
+        }
+    }
+
+    /// Verify the integrity of all the data in the repository.
+    ///
+    /// This returns the set of keys of values which are corrupt, including expired values.
+    ///
+    /// # Errors
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    pub fn verify(&self) -> crate::Result<HashSet<&K>> {
+        let corrupt_keys = self.0.verify()?;
+        Ok(self
+            .0
+            .state()
+            .iter()
+            .filter(|(_, entry)| corrupt_keys.contains(&entry.object))
+            .map(|(key, _)| key)
+            .collect())
+    }
+
+    /// Delete all data in the current instance of the repository.
+    ///
+    /// See [`KeyRepo::clear_instance`] for details.
+    ///
+    /// [`KeyRepo::clear_instance`]: crate::repo::key::KeyRepo::clear_instance
+    pub fn clear_instance(&mut self) {
+        self.0.clear_instance()
+    }
+
+    /// Change the password for this repository.
+    ///
+    /// See [`KeyRepo::change_password`] for details.
+    ///
+    /// [`KeyRepo::change_password`]: crate::repo::key::KeyRepo::change_password
+    pub fn change_password(
+        &mut self,
+        new_password: &[u8],
+        memory_limit: ResourceLimit,
+        operations_limit: ResourceLimit,
+    ) {
+        self.0
+            .change_password(new_password, memory_limit, operations_limit);
+    }
+
+    /// Return this repository's instance ID.
+    pub fn instance(&self) -> InstanceId {
+        self.0.instance()
+    }
+
+    /// Return information about each instance of this repository.
+    ///
+    /// See [`KeyRepo::list_instances`] for details.
+    ///
+    /// [`KeyRepo::list_instances`]: crate::repo::key::KeyRepo::list_instances
+    pub fn list_instances(&self) -> Vec<InstanceSummary> {
+        self.0.list_instances()
+    }
+
+    /// Set the human-readable name of the instance with the given `id` to `name`.
+    ///
+    /// See [`KeyRepo::name_instance`] for details.
+    ///
+    /// [`KeyRepo::name_instance`]: crate::repo::key::KeyRepo::name_instance
+    pub fn name_instance(&mut self, id: InstanceId, name: Option<&str>) -> crate::Result<()> {
+        self.0.name_instance(id, name)
+    }
+
+    /// Return the ID of the instance with the given `name`.
+    ///
+    /// See [`KeyRepo::find_instance`] for details.
+    ///
+    /// [`KeyRepo::find_instance`]: crate::repo::key::KeyRepo::find_instance
+    pub fn find_instance(&self, name: &str) -> Option<InstanceId> {
+        self.0.find_instance(name)
+    }
+
+    /// Remove the instance of the repository with the given `id`.
+    ///
+    /// See [`KeyRepo::remove_instance`] for details.
+    ///
+    /// [`KeyRepo::remove_instance`]: crate::repo::key::KeyRepo::remove_instance
+    pub fn remove_instance(&mut self, id: InstanceId) -> crate::Result<bool> {
+        self.0.remove_instance(id)
+    }
+
+    /// Compute statistics about the repository.
+    ///
+    /// See [`KeyRepo::stats`] for details.
+    ///
+    /// [`KeyRepo::stats`]: crate::repo::key::KeyRepo::stats
+    pub fn stats(&self) -> RepoStats {
+        self.0.stats()
+    }
+
+    /// Compute statistics about the objects in each instance of the repository.
+    ///
+    /// See [`KeyRepo::instance_stats`] for details.
+    ///
+    /// [`KeyRepo::instance_stats`]: crate::repo::key::KeyRepo::instance_stats
+    pub fn instance_stats(&self) -> crate::Result<HashMap<InstanceId, UsageStats>> {
+        self.0.instance_stats()
+    }
+
+    /// Return information about the repository.
+    pub fn info(&self) -> RepoInfo {
+        self.0.info()
+    }
+
+    /// Return the quota for the current instance in bytes.
+    ///
+    /// See [`KeyRepo::quota`] for details.
+    ///
+    /// [`KeyRepo::quota`]: crate::repo::key::KeyRepo::quota
+    pub fn quota(&self) -> Option<u64> {
+        self.0.quota()
+    }
+
+    /// Set the quota for the current instance to `quota` bytes.
+    ///
+    /// See [`KeyRepo::set_quota`] for details.
+    ///
+    /// [`KeyRepo::set_quota`]: crate::repo::key::KeyRepo::set_quota
+    pub fn set_quota(&mut self, quota: Option<u64>) {
+        self.0.set_quota(quota)
+    }
+
+    /// Register `callback` to be called each time `event` happens in this repository.
+    ///
+    /// See [`KeyRepo::on_event`] for details.
+    ///
+    /// [`KeyRepo::on_event`]: crate::repo::key::KeyRepo::on_event
+    pub fn on_event(
+        &self,
+        event: RepoEvent,
+        callback: impl FnMut(&RepoInfo) + Send + 'static,
+    ) -> HookId {
+        self.0.on_event(event, callback)
+    }
+
+    /// Remove the hook with the given `id`.
+    ///
+    /// See [`KeyRepo::remove_hook`] for details.
+    ///
+    /// [`KeyRepo::remove_hook`]: crate::repo::key::KeyRepo::remove_hook
+    pub fn remove_hook(&self, id: HookId) -> bool {
+        self.0.remove_hook(id)
+    }
+
+    /// Return the log of commits to this repository.
+    ///
+    /// See [`KeyRepo::commit_log`] for details.
+    ///
+    /// [`KeyRepo::commit_log`]: crate::repo::key::KeyRepo::commit_log
+    pub fn commit_log(&self) -> &[CommitRecord] {
+        self.0.commit_log()
+    }
+
+    /// Verify the integrity of the commit log.
+    ///
+    /// See [`KeyRepo::verify_commit_log`] for details.
+    ///
+    /// # Errors
+    /// - `Error::Corrupt`: The commit log has been tampered with.
+    ///
+    /// [`KeyRepo::verify_commit_log`]: crate::repo::key::KeyRepo::verify_commit_log
+    pub fn verify_commit_log(&self) -> crate::Result<()> {
+        self.0.verify_commit_log()
+    }
+
+    /// Rewrite the data in the repository according to the given `options`.
+    ///
+    /// See [`KeyRepo::repack`] for details.
+    ///
+    /// [`KeyRepo::repack`]: crate::repo::key::KeyRepo::repack
+    pub fn repack(&mut self, options: RepackOptions) -> crate::Result<()> {
+        self.0.repack(options)
+    }
+
+    /// Change the chunking method used for new data in the repository.
+    ///
+    /// See [`KeyRepo::set_chunking`] for details.
+    ///
+    /// [`KeyRepo::set_chunking`]: crate::repo::key::KeyRepo::set_chunking
+    pub fn set_chunking(&mut self, chunking: Chunking) -> crate::Result<()> {
+        self.0.set_chunking(chunking)
+    }
+
+    /// Rewrite every object in the current instance using the current chunking method.
+    ///
+    /// See [`KeyRepo::rechunk_all`] for details.
+    ///
+    /// [`KeyRepo::rechunk_all`]: crate::repo::key::KeyRepo::rechunk_all
+    pub fn rechunk_all(&mut self) -> crate::Result<()> {
+        self.0.rechunk_all()
+    }
+
+    /// Persist uncommitted changes to the data store without committing them.
+    ///
+    /// See [`KeyRepo::flush`] for details.
+    ///
+    /// [`KeyRepo::flush`]: crate::repo::key::KeyRepo::flush
+    pub fn flush(&mut self) -> crate::Result<()> {
+        self.0.flush()
+    }
+
+    /// Restore the repository to the state it was in when changes were last flushed.
+    ///
+    /// See [`KeyRepo::restore_flushed`] for details.
+    ///
+    /// [`KeyRepo::restore_flushed`]: crate::repo::key::KeyRepo::restore_flushed
+    pub fn restore_flushed(&mut self) -> crate::Result<bool> {
+        self.0.restore_flushed()
+    }
+
+    /// Save the current state of the repository as a checkpoint named `name`.
+    ///
+    /// See [`KeyRepo::create_checkpoint`] for details.
+    ///
+    /// [`KeyRepo::create_checkpoint`]: crate::repo::key::KeyRepo::create_checkpoint
+    pub fn create_checkpoint(&mut self, name: impl Into<String>) -> crate::Result<()> {
+        self.0.create_checkpoint(name)
+    }
+
+    /// Remove the checkpoint named `name` from the repository.
+    ///
+    /// See [`KeyRepo::remove_checkpoint`] for details.
+    ///
+    /// [`KeyRepo::remove_checkpoint`]: crate::repo::key::KeyRepo::remove_checkpoint
+    pub fn remove_checkpoint(&mut self, name: &str) -> bool {
+        self.0.remove_checkpoint(name)
+    }
+
+    /// Return whether there is a checkpoint named `name`.
+    pub fn contains_checkpoint(&self, name: &str) -> bool {
+        self.0.contains_checkpoint(name)
+    }
+
+    /// Return an iterator over the names of the checkpoints in this repository.
+    ///
+    /// See [`KeyRepo::checkpoints`] for details.
+    ///
+    /// [`KeyRepo::checkpoints`]: crate::repo::key::KeyRepo::checkpoints
+    pub fn checkpoints(&self) -> Checkpoints {
+        self.0.checkpoints()
+    }
+
+    /// Restore the repository to the state it was in when the checkpoint `name` was created.
+    ///
+    /// See [`KeyRepo::restore_checkpoint`] for details.
+    ///
+    /// [`KeyRepo::restore_checkpoint`]: crate::repo::key::KeyRepo::restore_checkpoint
+    pub fn restore_checkpoint(&mut self, name: &str) -> crate::Result<()> {
+        self.0.restore_checkpoint(name)
+    }
+
+    /// Return the number of previous commits which the repository can be restored to.
+    ///
+    /// See [`KeyRepo::previous_commits`] for details.
+    ///
+    /// [`KeyRepo::previous_commits`]: crate::repo::key::KeyRepo::previous_commits
+    pub fn previous_commits(&self) -> usize {
+        self.0.previous_commits()
+    }
+
+    /// Restore the repository to the state it was in `age` commits before the most recent one.
+    ///
+    /// See [`KeyRepo::restore_commit`] for details.
+    ///
+    /// [`KeyRepo::restore_commit`]: crate::repo::key::KeyRepo::restore_commit
+    pub fn restore_commit(&mut self, age: usize) -> crate::Result<()> {
+        self.0.restore_commit(age)
+    }
+}
+
+impl<K: Key> Commit for SessionRepo<K> {
+    fn commit(&mut self) -> crate::Result<()> {
+        self.0.commit()
+    }
+
+    fn rollback(&mut self) -> crate::Result<()> {
+        self.0.rollback()
+    }
+
+    fn clean(&mut self) -> crate::Result<()> {
+        self.0.clean()
+    }
+}
+
+impl<K: Key> RestoreSavepoint for SessionRepo<K> {
+    type Restore = <StateRepo<RepoState<K>> as RestoreSavepoint>::Restore;
+
+    fn savepoint(&mut self) -> crate::Result<Savepoint> {
+        self.0.savepoint()
+    }
+
+    fn start_restore(&mut self, savepoint: &Savepoint) -> crate::Result<Self::Restore> {
+        self.0.start_restore(savepoint)
+    }
+
+    fn finish_restore(&mut self, restore: Self::Restore) -> bool {
+        self.0.finish_restore(restore)
+    }
+}
+
+impl<K: Key> Unlock for SessionRepo<K> {
+    fn unlock(&self) -> crate::Result<()> {
+        self.0.unlock()
+    }
+
+    fn is_locked(&self) -> crate::Result<bool> {
+        self.0.is_locked()
+    }
+
+    fn context(&self) -> crate::Result<Vec<u8>> {
+        self.0.context()
+    }
+
+    fn update_context(&self, context: &[u8]) -> crate::Result<()> {
+        self.0.update_context(context)
+    }
+}
@@ -0,0 +1,20 @@
+//! A repository which stores a single binary blob.
+//!
+//! This module contains the [`SingleObjectRepo`] repository type.
+//!
+//! This is a repository for the common case where a data store only needs to hold one blob of
+//! data, such as an application's database file. It hides keys and instances entirely and exposes
+//! a single implicit object which can be read and written as a whole or accessed as a seekable
+//! [`Object`].
+//!
+//! Like other repositories, changes made to the repository are not persisted to the data store
+//! until [`Commit::commit`] is called. For details about deduplication, compression, encryption,
+//! and locking, see the module-level documentation for [`crate::repo`].
+//!
+//! [`SingleObjectRepo`]: crate::repo::single::SingleObjectRepo
+//! [`Object`]: crate::repo::Object
+//! [`Commit::commit`]: crate::repo::Commit::commit
+
+pub use self::repository::SingleObjectRepo;
+
+mod repository;
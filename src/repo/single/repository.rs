@@ -0,0 +1,416 @@
+use std::collections::HashMap;
+use std::io::{Read, Write};
+
+use uuid::uuid;
+
+use crate::repo::{
+    key::KeyRepo, Checkpoints, Chunking, Commit, CommitRecord, HookId, InstanceId, InstanceSummary,
+    Object, OpenRepo, RepackOptions, RepoEvent, RepoInfo, RepoStats, ResourceLimit,
+    RestoreSavepoint, Savepoint, Unlock, UsageStats, VersionId,
+};
+
+/// A repository which stores a single binary blob.
+///
+/// See [`crate::repo::single`] for more information.
+#[derive(Debug)]
+pub struct SingleObjectRepo(KeyRepo<()>);
+
+impl OpenRepo for SingleObjectRepo {
+    type Key = ();
+
+    const VERSION_ID: VersionId = VersionId::new(uuid!("0f7b5a1e-6c2d-4f3a-9e8b-2d4c6a8e1b3f"));
+
+    fn open_repo(repo: KeyRepo<Self::Key>) -> crate::Result<Self>
+    where
+        Self: Sized,
+    {
+        Ok(Self(repo))
+    }
+
+    fn create_repo(mut repo: KeyRepo<Self::Key>) -> crate::Result<Self>
+    where
+        Self: Sized,
+    {
+        repo.insert(());
+        Ok(Self(repo))
+    }
+
+    fn into_repo(self) -> crate::Result<KeyRepo<Self::Key>> {
+        Ok(self.0)
+    }
+}
+
+impl SingleObjectRepo {
+    /// Return the object which stores the data in this repository.
+    ///
+    /// The returned object can be used to read and write the data incrementally. Changes made
+    /// through the object must be committed with [`Object::commit`] before they are visible through
+    /// [`read`] and before they can be committed to the repository.
+    ///
+    /// [`Object::commit`]: crate::repo::Object::commit
+    /// [`read`]: crate::repo::single::SingleObjectRepo::read
+    pub fn object(&mut self) -> Object {
+        match self.0.object(&()) {
+            Some(object) => object,
+            None => self.0.insert(()),
+        }
+    }
+
+    /// Return the size of the data in this repository in bytes.
+    ///
+    /// # Errors
+    /// - `Error::TransactionInProgress`: An [`Object`] returned by [`object`] has uncommitted
+    /// changes.
+    ///
+    /// [`Object`]: crate::repo::Object
+    /// [`object`]: crate::repo::single::SingleObjectRepo::object
+    pub fn size(&self) -> crate::Result<u64> {
+        match self.0.object(&()) {
+            Some(object) => object.size(),
+            None => Ok(0),
+        }
+    }
+
+    /// Read all the data in this repository.
+    ///
+    /// # Errors
+    /// - `Error::TransactionInProgress`: An [`Object`] returned by [`object`] has uncommitted
+    /// changes.
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    ///
+    /// [`Object`]: crate::repo::Object
+    /// [`object`]: crate::repo::single::SingleObjectRepo::object
+    pub fn read(&self) -> crate::Result<Vec<u8>> {
+        let mut object = match self.0.object(&()) {
+            Some(object) => object,
+            None => return Ok(Vec::new()),
+        };
+        let mut data = Vec::with_capacity(object.size()? as usize);
+        object.read_to_end(&mut data)?;
+        Ok(data)
+    }
+
+    /// Replace the data in this repository with `data`.
+    ///
+    /// # Errors
+    /// - `Error::TransactionInProgress`: An [`Object`] returned by [`object`] has uncommitted
+    /// changes.
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    ///
+    /// [`Object`]: crate::repo::Object
+    /// [`object`]: crate::repo::single::SingleObjectRepo::object
+    pub fn write(&mut self, data: &[u8]) -> crate::Result<()> {
+        let mut object = self.object();
+        object.set_len(0)?;
+        object.write_all(data)?;
+        object.commit()
+    }
+
+    /// Verify the integrity of the data in this repository.
+    ///
+    /// This returns `true` if the data is valid and `false` if it is corrupt.
+    ///
+    /// # Errors
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    pub fn verify(&self) -> crate::Result<bool> {
+        Ok(self.0.verify()?.is_empty())
+    }
+
+    /// Change the password for this repository.
+    ///
+    /// See [`KeyRepo::change_password`] for details.
+    ///
+    /// [`KeyRepo::change_password`]: crate::repo::key::KeyRepo::change_password
+    pub fn change_password(
+        &mut self,
+        new_password: &[u8],
+        memory_limit: ResourceLimit,
+        operations_limit: ResourceLimit,
+    ) {
+        self.0
+            .change_password(new_password, memory_limit, operations_limit);
+    }
+
+    /// Return this repository's instance ID.
+    pub fn instance(&self) -> InstanceId {
+        self.0.instance()
+    }
+
+    /// Return information about each instance of this repository.
+    ///
+    /// See [`KeyRepo::list_instances`] for details.
+    ///
+    /// [`KeyRepo::list_instances`]: crate::repo::key::KeyRepo::list_instances
+    pub fn list_instances(&self) -> Vec<InstanceSummary> {
+        self.0.list_instances()
+    }
+
+    /// Set the human-readable name of the instance with the given `id` to `name`.
+    ///
+    /// See [`KeyRepo::name_instance`] for details.
+    ///
+    /// [`KeyRepo::name_instance`]: crate::repo::key::KeyRepo::name_instance
+    pub fn name_instance(&mut self, id: InstanceId, name: Option<&str>) -> crate::Result<()> {
+        self.0.name_instance(id, name)
+    }
+
+    /// Return the ID of the instance with the given `name`.
+    ///
+    /// See [`KeyRepo::find_instance`] for details.
+    ///
+    /// [`KeyRepo::find_instance`]: crate::repo::key::KeyRepo::find_instance
+    pub fn find_instance(&self, name: &str) -> Option<InstanceId> {
+        self.0.find_instance(name)
+    }
+
+    /// Remove the instance of the repository with the given `id`.
+    ///
+    /// See [`KeyRepo::remove_instance`] for details.
+    ///
+    /// [`KeyRepo::remove_instance`]: crate::repo::key::KeyRepo::remove_instance
+    pub fn remove_instance(&mut self, id: InstanceId) -> crate::Result<bool> {
+        self.0.remove_instance(id)
+    }
+
+    /// Compute statistics about the repository.
+    ///
+    /// See [`KeyRepo::stats`] for details.
+    ///
+    /// [`KeyRepo::stats`]: crate::repo::key::KeyRepo::stats
+    pub fn stats(&self) -> RepoStats {
+        self.0.stats()
+    }
+
+    /// Compute statistics about the objects in each instance of the repository.
+    ///
+    /// See [`KeyRepo::instance_stats`] for details.
+    ///
+    /// [`KeyRepo::instance_stats`]: crate::repo::key::KeyRepo::instance_stats
+    pub fn instance_stats(&self) -> crate::Result<HashMap<InstanceId, UsageStats>> {
+        self.0.instance_stats()
+    }
+
+    /// Return information about the repository.
+    pub fn info(&self) -> RepoInfo {
+        self.0.info()
+    }
+
+    /// Return the quota for the current instance in bytes.
+    ///
+    /// See [`KeyRepo::quota`] for details.
+    ///
+    /// [`KeyRepo::quota`]: crate::repo::key::KeyRepo::quota
+    pub fn quota(&self) -> Option<u64> {
+        self.0.quota()
+    }
+
+    /// Set the quota for the current instance to `quota` bytes.
+    ///
+    /// See [`KeyRepo::set_quota`] for details.
+    ///
+    /// [`KeyRepo::set_quota`]: crate::repo::key::KeyRepo::set_quota
+    pub fn set_quota(&mut self, quota: Option<u64>) {
+        self.0.set_quota(quota)
+    }
+
+    /// Register `callback` to be called each time `event` happens in this repository.
+    ///
+    /// See [`KeyRepo::on_event`] for details.
+    ///
+    /// [`KeyRepo::on_event`]: crate::repo::key::KeyRepo::on_event
+    pub fn on_event(
+        &self,
+        event: RepoEvent,
+        callback: impl FnMut(&RepoInfo) + Send + 'static,
+    ) -> HookId {
+        self.0.on_event(event, callback)
+    }
+
+    /// Remove the hook with the given `id`.
+    ///
+    /// See [`KeyRepo::remove_hook`] for details.
+    ///
+    /// [`KeyRepo::remove_hook`]: crate::repo::key::KeyRepo::remove_hook
+    pub fn remove_hook(&self, id: HookId) -> bool {
+        self.0.remove_hook(id)
+    }
+
+    /// Return the log of commits to this repository.
+    ///
+    /// See [`KeyRepo::commit_log`] for details.
+    ///
+    /// [`KeyRepo::commit_log`]: crate::repo::key::KeyRepo::commit_log
+    pub fn commit_log(&self) -> &[CommitRecord] {
+        self.0.commit_log()
+    }
+
+    /// Verify the integrity of the commit log.
+    ///
+    /// See [`KeyRepo::verify_commit_log`] for details.
+    ///
+    /// # Errors
+    /// - `Error::Corrupt`: The commit log has been tampered with.
+    ///
+    /// [`KeyRepo::verify_commit_log`]: crate::repo::key::KeyRepo::verify_commit_log
+    pub fn verify_commit_log(&self) -> crate::Result<()> {
+        self.0.verify_commit_log()
+    }
+
+    /// Rewrite the data in the repository according to the given `options`.
+    ///
+    /// See [`KeyRepo::repack`] for details.
+    ///
+    /// [`KeyRepo::repack`]: crate::repo::key::KeyRepo::repack
+    pub fn repack(&mut self, options: RepackOptions) -> crate::Result<()> {
+        self.0.repack(options)
+    }
+
+    /// Change the chunking method used for new data in the repository.
+    ///
+    /// See [`KeyRepo::set_chunking`] for details.
+    ///
+    /// [`KeyRepo::set_chunking`]: crate::repo::key::KeyRepo::set_chunking
+    pub fn set_chunking(&mut self, chunking: Chunking) -> crate::Result<()> {
+        self.0.set_chunking(chunking)
+    }
+
+    /// Rewrite every object in the current instance using the current chunking method.
+    ///
+    /// See [`KeyRepo::rechunk_all`] for details.
+    ///
+    /// [`KeyRepo::rechunk_all`]: crate::repo::key::KeyRepo::rechunk_all
+    pub fn rechunk_all(&mut self) -> crate::Result<()> {
+        self.0.rechunk_all()
+    }
+
+    /// Persist uncommitted changes to the data store without committing them.
+    ///
+    /// See [`KeyRepo::flush`] for details.
+    ///
+    /// [`KeyRepo::flush`]: crate::repo::key::KeyRepo::flush
+    pub fn flush(&mut self) -> crate::Result<()> {
+        self.0.flush()
+    }
+
+    /// Restore the repository to the state it was in when changes were last flushed.
+    ///
+    /// See [`KeyRepo::restore_flushed`] for details.
+    ///
+    /// [`KeyRepo::restore_flushed`]: crate::repo::key::KeyRepo::restore_flushed
+    pub fn restore_flushed(&mut self) -> crate::Result<bool> {
+        self.0.restore_flushed()
+    }
+
+    /// Save the current state of the repository as a checkpoint named `name`.
+    ///
+    /// See [`KeyRepo::create_checkpoint`] for details.
+    ///
+    /// [`KeyRepo::create_checkpoint`]: crate::repo::key::KeyRepo::create_checkpoint
+    pub fn create_checkpoint(&mut self, name: impl Into<String>) -> crate::Result<()> {
+        self.0.create_checkpoint(name)
+    }
+
+    /// Remove the checkpoint named `name` from the repository.
+    ///
+    /// See [`KeyRepo::remove_checkpoint`] for details.
+    ///
+    /// [`KeyRepo::remove_checkpoint`]: crate::repo::key::KeyRepo::remove_checkpoint
+    pub fn remove_checkpoint(&mut self, name: &str) -> bool {
+        self.0.remove_checkpoint(name)
+    }
+
+    /// Return whether there is a checkpoint named `name`.
+    pub fn contains_checkpoint(&self, name: &str) -> bool {
+        self.0.contains_checkpoint(name)
+    }
+
+    /// Return an iterator over the names of the checkpoints in this repository.
+    ///
+    /// See [`KeyRepo::checkpoints`] for details.
+    ///
+    /// [`KeyRepo::checkpoints`]: crate::repo::key::KeyRepo::checkpoints
+    pub fn checkpoints(&self) -> Checkpoints {
+        self.0.checkpoints()
+    }
+
+    /// Restore the repository to the state it was in when the checkpoint `name` was created.
+    ///
+    /// See [`KeyRepo::restore_checkpoint`] for details.
+    ///
+    /// [`KeyRepo::restore_checkpoint`]: crate::repo::key::KeyRepo::restore_checkpoint
+    pub fn restore_checkpoint(&mut self, name: &str) -> crate::Result<()> {
+        self.0.restore_checkpoint(name)
+    }
+
+    /// Return the number of previous commits which the repository can be restored to.
+    ///
+    /// See [`KeyRepo::previous_commits`] for details.
+    ///
+    /// [`KeyRepo::previous_commits`]: crate::repo::key::KeyRepo::previous_commits
+    pub fn previous_commits(&self) -> usize {
+        self.0.previous_commits()
+    }
+
+    /// Restore the repository to the state it was in `age` commits before the most recent one.
+    ///
+    /// See [`KeyRepo::restore_commit`] for details.
+    ///
+    /// [`KeyRepo::restore_commit`]: crate::repo::key::KeyRepo::restore_commit
+    pub fn restore_commit(&mut self, age: usize) -> crate::Result<()> {
+        self.0.restore_commit(age)
+    }
+}
+
+impl Commit for SingleObjectRepo {
+    fn commit(&mut self) -> crate::Result<()> {
+        self.0.commit()
+    }
+
+    fn rollback(&mut self) -> crate::Result<()> {
+        self.0.rollback()
+    }
+
+    fn clean(&mut self) -> crate::Result<()> {
+        self.0.clean()
+    }
+}
+
+impl RestoreSavepoint for SingleObjectRepo {
+    type Restore = <KeyRepo<()> as RestoreSavepoint>::Restore;
+
+    fn savepoint(&mut self) -> crate::Result<Savepoint> {
+        self.0.savepoint()
+    }
+
+    fn start_restore(&mut self, savepoint: &Savepoint) -> crate::Result<Self::Restore> {
+        self.0.start_restore(savepoint)
+    }
+
+    fn finish_restore(&mut self, restore: Self::Restore) -> bool {
+        self.0.finish_restore(restore)
+    }
+}
+
+impl Unlock for SingleObjectRepo {
+    fn unlock(&self) -> crate::Result<()> {
+        self.0.unlock()
+    }
+
+    fn is_locked(&self) -> crate::Result<bool> {
+        self.0.is_locked()
+    }
+
+    fn context(&self) -> crate::Result<Vec<u8>> {
+        self.0.context()
+    }
+
+    fn update_context(&self, context: &[u8]) -> crate::Result<()> {
+        self.0.update_context(context)
+    }
+}
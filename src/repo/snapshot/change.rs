@@ -0,0 +1,17 @@
+/// A difference between two snapshots in a [`SnapshotRepo`].
+///
+/// This value is returned by [`SnapshotRepo::diff`].
+///
+/// [`SnapshotRepo`]: crate::repo::snapshot::SnapshotRepo
+/// [`SnapshotRepo::diff`]: crate::repo::snapshot::SnapshotRepo::diff
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub enum Change<K> {
+    /// The key exists in the new snapshot but not the old one.
+    Added(K),
+
+    /// The key exists in the old snapshot but not the new one.
+    Removed(K),
+
+    /// The key exists in both snapshots, but the contents of the object are different.
+    Modified(K),
+}
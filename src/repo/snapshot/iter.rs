@@ -0,0 +1,62 @@
+use std::collections::{btree_map, hash_map};
+use std::iter::{ExactSizeIterator, FusedIterator};
+
+use super::repository::Snapshot;
+use crate::repo::state::ObjectKey;
+
+/// An iterator over the keys in a [`SnapshotRepo`] or one of its snapshots.
+///
+/// This value is created by [`SnapshotRepo::keys`] and [`SnapshotRepo::snapshot_keys`].
+///
+/// [`SnapshotRepo`]: crate::repo::snapshot::SnapshotRepo
+/// [`SnapshotRepo::keys`]: crate::repo::snapshot::SnapshotRepo::keys
+/// [`SnapshotRepo::snapshot_keys`]: crate::repo::snapshot::SnapshotRepo::snapshot_keys
+#[derive(Debug, Clone)]
+pub struct Keys<'a, K>(pub(super) hash_map::Keys<'a, K, ObjectKey>);
+
+impl<'a, K> Iterator for Keys<'a, K> {
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl<'a, K> FusedIterator for Keys<'a, K> {}
+
+impl<'a, K> ExactSizeIterator for Keys<'a, K> {}
+
+/// An iterator over the names of the snapshots in a [`SnapshotRepo`].
+///
+/// This value is created by [`SnapshotRepo::snapshots`].
+///
+/// [`SnapshotRepo`]: crate::repo::snapshot::SnapshotRepo
+/// [`SnapshotRepo::snapshots`]: crate::repo::snapshot::SnapshotRepo::snapshots
+#[derive(Debug, Clone)]
+pub struct Snapshots<'a, K>(pub(super) btree_map::Keys<'a, String, Snapshot<K>>);
+
+impl<'a, K> Iterator for Snapshots<'a, K> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(String::as_str)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl<'a, K> DoubleEndedIterator for Snapshots<'a, K> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.0.next_back().map(String::as_str)
+    }
+}
+
+impl<'a, K> FusedIterator for Snapshots<'a, K> {}
+
+impl<'a, K> ExactSizeIterator for Snapshots<'a, K> {}
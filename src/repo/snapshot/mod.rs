@@ -0,0 +1,64 @@
+//! An object store with named, repository-wide snapshots.
+//!
+//! This module contains the [`SnapshotRepo`] repository type.
+//!
+//! This is a repository which maps keys to seekable binary blobs like a [`KeyRepo`], but also
+//! allows you to capture named snapshots of all the objects in the repository. Snapshots can be
+//! listed, read from, compared with each other, and restored. This is useful for implementing
+//! things like backup tools, where each backup is a snapshot of the repository.
+//!
+//! Creating a snapshot is a cheap operation which does not require copying the bytes in each
+//! object. Data is deduplicated between snapshots, so a snapshot only uses additional space in the
+//! data store for data which has changed since it was created.
+//!
+//! Like other repositories, changes made to the repository, including creating and removing
+//! snapshots, are not persisted to the data store until [`Commit::commit`] is called. For details
+//! about deduplication, compression, encryption, and locking, see the module-level documentation
+//! for [`crate::repo`].
+//!
+//! # Examples
+//! ```
+//! use std::io::{Read, Write};
+//!
+//! use acid_store::repo::{Commit, OpenMode, OpenOptions, snapshot::{Change, SnapshotRepo}};
+//! use acid_store::store::MemoryConfig;
+//!
+//! let mut repo: SnapshotRepo<String> = OpenOptions::new()
+//!     .mode(OpenMode::CreateNew)
+//!     .open(&MemoryConfig::new())
+//!     .unwrap();
+//!
+//! let mut object = repo.insert("file".to_string());
+//! object.write_all(b"first version").unwrap();
+//! object.commit().unwrap();
+//! drop(object);
+//! repo.create_snapshot("first").unwrap();
+//!
+//! let mut object = repo.insert("file".to_string());
+//! object.write_all(b"second version").unwrap();
+//! object.commit().unwrap();
+//! drop(object);
+//! repo.create_snapshot("second").unwrap();
+//!
+//! let changes = repo.diff("first", "second").unwrap();
+//! assert_eq!(changes, vec![Change::Modified("file".to_string())]);
+//!
+//! repo.restore_snapshot("first").unwrap();
+//! let mut contents = Vec::new();
+//! repo.object("file").unwrap().read_to_end(&mut contents).unwrap();
+//! assert_eq!(contents, b"first version");
+//!
+//! repo.commit().unwrap();
+//! ```
+//!
+//! [`SnapshotRepo`]: crate::repo::snapshot::SnapshotRepo
+//! [`KeyRepo`]: crate::repo::key::KeyRepo
+//! [`Commit::commit`]: crate::repo::Commit::commit
+
+pub use self::change::Change;
+pub use self::iter::{Keys, Snapshots};
+pub use self::repository::SnapshotRepo;
+
+mod change;
+mod iter;
+mod repository;
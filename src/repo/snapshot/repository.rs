@@ -0,0 +1,678 @@
+use std::borrow::Borrow;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::hash::Hash;
+use std::mem;
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+use uuid::uuid;
+
+use super::change::Change;
+use super::iter::{Keys, Snapshots};
+use crate::repo::{
+    key::{Key, KeyRepo},
+    state::{ObjectKey, StateRepo},
+    Checkpoints, Chunking, Commit, CommitRecord, HookId, InstanceId, InstanceSummary, Object,
+    OpenRepo, ReadOnlyObject, RepackOptions, RepoEvent, RepoInfo, RepoStats, ResourceLimit,
+    RestoreSavepoint, Savepoint, Unlock, UsageStats, VersionId,
+};
+
+/// A named snapshot of all the objects in a `SnapshotRepo`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot<K: Eq + Hash> {
+    /// The time at which this snapshot was created.
+    created: SystemTime,
+
+    /// A map of keys to copies of the objects as they were when the snapshot was created.
+    objects: HashMap<K, ObjectKey>,
+}
+
+/// The state for a `SnapshotRepo`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RepoState<K: Eq + Hash> {
+    /// A map of keys to the current objects in the repository.
+    objects: HashMap<K, ObjectKey>,
+
+    /// A map of snapshot names to snapshots.
+    snapshots: BTreeMap<String, Snapshot<K>>,
+}
+
+impl<K: Eq + Hash> Default for RepoState<K> {
+    fn default() -> Self {
+        Self {
+            objects: HashMap::new(),
+            snapshots: BTreeMap::new(),
+        }
+    }
+}
+
+/// An object store with named, repository-wide snapshots.
+///
+/// See [`crate::repo::snapshot`] for more information.
+#[derive(Debug)]
+pub struct SnapshotRepo<K: Key>(StateRepo<RepoState<K>>);
+
+impl<K: Key> OpenRepo for SnapshotRepo<K> {
+    type Key = <StateRepo<RepoState<K>> as OpenRepo>::Key;
+
+    const VERSION_ID: VersionId = VersionId::new(uuid!("c5e2a7d4-3b9f-4e61-8a0c-7f1d2e9b4a63"));
+
+    fn open_repo(repo: KeyRepo<Self::Key>) -> crate::Result<Self>
+    where
+        Self: Sized,
+    {
+        Ok(Self(StateRepo::open_repo(repo)?))
+    }
+
+    fn create_repo(repo: KeyRepo<Self::Key>) -> crate::Result<Self>
+    where
+        Self: Sized,
+    {
+        Ok(Self(StateRepo::create_repo(repo)?))
+    }
+
+    fn into_repo(self) -> crate::Result<KeyRepo<Self::Key>> {
+        self.0.into_repo()
+    }
+}
+
+impl<K: Key> SnapshotRepo<K> {
+    /// Return whether there is an object with the given `key` in this repository.
+    pub fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.0.state().objects.contains_key(key)
+    }
+
+    /// Add a new object with the given `key` to the repository and return it.
+    ///
+    /// If another object with the same `key` already exists, it is replaced. This does not affect
+    /// any snapshots which contain the old object.
+    pub fn insert(&mut self, key: K) -> Object {
+        let object_id = self.0.create();
+        if let Some(prev_object_id) = self.0.state_mut().objects.insert(key, object_id) {
+            self.0.remove(prev_object_id);
+        }
+        self.0.object(object_id).unwrap()
+    }
+
+    /// Remove the object with the given `key` from the repository.
+    ///
+    /// This returns `true` if the object was removed or `false` if it didn't exist. This does not
+    /// affect any snapshots which contain the object.
+    ///
+    /// The space used by the given object isn't reclaimed in the backing data store until changes
+    /// are committed and [`Commit::clean`] is called.
+    ///
+    /// [`Commit::clean`]: crate::repo::Commit::clean
+    pub fn remove<Q>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match self.0.state_mut().objects.remove(key) {
+            Some(object_id) => {
+                self.0.remove(object_id);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Return an object for reading and writing the object with the given `key`.
+    ///
+    /// This returns `None` if there is no object with the given `key` in the repository.
+    pub fn object<Q>(&self, key: &Q) -> Option<Object>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let object_id = self.0.state().objects.get(key)?;
+        self.0.object(*object_id)
+    }
+
+    /// Return an iterator over all the keys of objects in this repository.
+    pub fn keys(&self) -> Keys<K> {
+        Keys(self.0.state().objects.keys())
+    }
+
+    /// Copy the object at `source` to `dest`.
+    ///
+    /// If another object already exists at `dest`, it is replaced.
+    ///
+    /// This returns `true` if the object was copied or `false` if there was no object at `source`.
+    ///
+    /// This is a cheap operation which does not require copying the bytes in the object.
+    pub fn copy<Q>(&mut self, source: &Q, dest: K) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let object_id = match self.0.state().objects.get(source) {
+            Some(object_id) => *object_id,
+            None => return false,
+        };
+        let new_object_id = self.0.copy(object_id).unwrap();
+        if let Some(prev_object_id) = self.0.state_mut().objects.insert(dest, new_object_id) {
+            self.0.remove(prev_object_id);
+        }
+        true
+    }
+
+    /// Create a new snapshot of all the objects in the repository with the given `name`.
+    ///
+    /// This is a cheap operation which does not require copying the bytes in each object. Objects
+    /// which have a transaction in progress are captured as they were before the transaction
+    /// started.
+    ///
+    /// # Errors
+    /// - `Error::AlreadyExists`: There is already a snapshot with the given `name`.
+    pub fn create_snapshot(&mut self, name: impl Into<String>) -> crate::Result<()> {
+        let name = name.into();
+        if self.0.state().snapshots.contains_key(&name) {
+            return Err(crate::Error::AlreadyExists);
+        }
+
+        let current_objects = self
+            .0
+            .state()
+            .objects
+            .iter()
+            .map(|(key, object_id)| (key.clone(), *object_id))
+            .collect::<Vec<_>>();
+        let mut snapshot_objects = HashMap::with_capacity(current_objects.len());
+        for (key, object_id) in current_objects {
+            snapshot_objects.insert(key, self.0.copy(object_id).unwrap());
+        }
+
+        let snapshot = Snapshot {
+            created: SystemTime::now(),
+            objects: snapshot_objects,
+        };
+        self.0.state_mut().snapshots.insert(name, snapshot);
+
+        Ok(())
+    }
+
+    /// Remove the snapshot with the given `name`.
+    ///
+    /// This returns `true` if the snapshot was removed or `false` if it didn't exist.
+    ///
+    /// The space used by data which is only referenced by this snapshot isn't reclaimed in the
+    /// backing data store until changes are committed and [`Commit::clean`] is called.
+    ///
+    /// [`Commit::clean`]: crate::repo::Commit::clean
+    pub fn remove_snapshot(&mut self, name: &str) -> bool {
+        match self.0.state_mut().snapshots.remove(name) {
+            Some(snapshot) => {
+                for object_id in snapshot.objects.into_values() {
+                    self.0.remove(object_id);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Return whether there is a snapshot with the given `name`.
+    pub fn contains_snapshot(&self, name: &str) -> bool {
+        self.0.state().snapshots.contains_key(name)
+    }
+
+    /// Return an iterator over the names of all the snapshots in this repository.
+    ///
+    /// Snapshots are returned in lexicographical order by name.
+    pub fn snapshots(&self) -> Snapshots<K> {
+        Snapshots(self.0.state().snapshots.keys())
+    }
+
+    /// Return the time at which the snapshot with the given `name` was created.
+    ///
+    /// This returns `None` if there is no snapshot with the given `name`.
+    pub fn snapshot_time(&self, name: &str) -> Option<SystemTime> {
+        self.0
+            .state()
+            .snapshots
+            .get(name)
+            .map(|snapshot| snapshot.created)
+    }
+
+    /// Return an iterator over the keys of all the objects in the snapshot with the given `name`.
+    ///
+    /// # Errors
+    /// - `Error::NotFound`: There is no snapshot with the given `name`.
+    pub fn snapshot_keys(&self, name: &str) -> crate::Result<Keys<K>> {
+        let snapshot = self
+            .0
+            .state()
+            .snapshots
+            .get(name)
+            .ok_or(crate::Error::NotFound)?;
+        Ok(Keys(snapshot.objects.keys()))
+    }
+
+    /// Return an object for reading the object with the given `key` in the snapshot `name`.
+    ///
+    /// # Errors
+    /// - `Error::NotFound`: There is no snapshot with the given `name`.
+    /// - `Error::NotFound`: There is no object with the given `key` in the snapshot.
+    pub fn snapshot_object<Q>(&self, name: &str, key: &Q) -> crate::Result<ReadOnlyObject>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let object_id = self
+            .0
+            .state()
+            .snapshots
+            .get(name)
+            .and_then(|snapshot| snapshot.objects.get(key))
+            .ok_or(crate::Error::NotFound)?;
+        ReadOnlyObject::try_from(self.0.object(*object_id).unwrap())
+    }
+
+    /// Return the differences between the snapshot `old` and the snapshot `new`.
+    ///
+    /// The returned changes describe how to get from `old` to `new`. They are not returned in any
+    /// particular order.
+    ///
+    /// Objects are compared using their [`ContentId`], so this does not require reading the
+    /// contents of any objects.
+    ///
+    /// # Errors
+    /// - `Error::NotFound`: There is no snapshot named `old` or `new`.
+    ///
+    /// [`ContentId`]: crate::repo::ContentId
+    pub fn diff(&self, old: &str, new: &str) -> crate::Result<Vec<Change<K>>> {
+        let snapshots = &self.0.state().snapshots;
+        let old_objects = &snapshots.get(old).ok_or(crate::Error::NotFound)?.objects;
+        let new_objects = &snapshots.get(new).ok_or(crate::Error::NotFound)?.objects;
+
+        let mut changes = Vec::new();
+
+        for (key, new_object_id) in new_objects {
+            match old_objects.get(key) {
+                Some(old_object_id) => {
+                    let old_content = self.0.object(*old_object_id).unwrap().content_id()?;
+                    let new_content = self.0.object(*new_object_id).unwrap().content_id()?;
+                    if old_content != new_content {
+                        changes.push(Change::Modified(key.clone()));
+                    }
+                }
+                None => changes.push(Change::Added(key.clone())),
+            }
+        }
+
+        for key in old_objects.keys() {
+            if !new_objects.contains_key(key) {
+                changes.push(Change::Removed(key.clone()));
+            }
+        }
+
+        Ok(changes)
+    }
+
+    /// Restore the objects in the repository to the snapshot with the given `name`.
+    ///
+    /// This replaces all the objects in the repository with the objects in the snapshot. The
+    /// snapshot itself is not modified or removed.
+    ///
+    /// Restoring a snapshot invalidates all [`Object`] instances for objects which were in the
+    /// repository before it was restored.
+    ///
+    /// # Errors
+    /// - `Error::NotFound`: There is no snapshot with the given `name`.
+    ///
+    /// [`Object`]: crate::repo::Object
+    pub fn restore_snapshot(&mut self, name: &str) -> crate::Result<()> {
+        let snapshot_objects = self
+            .0
+            .state()
+            .snapshots
+            .get(name)
+            .ok_or(crate::Error::NotFound)?
+            .objects
+            .iter()
+            .map(|(key, object_id)| (key.clone(), *object_id))
+            .collect::<Vec<_>>();
+
+        let old_objects = mem::take(&mut self.0.state_mut().objects);
+        for object_id in old_objects.into_values() {
+            self.0.remove(object_id);
+        }
+
+        for (key, object_id) in snapshot_objects {
+            let new_object_id = self.0.copy(object_id).unwrap();
+            self.0.state_mut().objects.insert(key, new_object_id);
+        }
+
+        Ok(())
+    }
+
+    /// Verify the integrity of all the data in the repository.
+    ///
+    /// This returns the set of keys of objects which are corrupt. This only includes objects which
+    /// are currently in the repository, but because data is shared between objects and snapshots,
+    /// snapshots may contain corrupt data as well.
+    ///
+    /// # Errors
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    pub fn verify(&self) -> crate::Result<HashSet<&K>> {
+        let corrupt_keys = self.0.verify()?;
+        Ok(self
+            .0
+            .state()
+            .objects
+            .iter()
+            .filter(|(_, object_id)| corrupt_keys.contains(*object_id))
+            .map(|(key, _)| key)
+            .collect())
+    }
+
+    /// Delete all data in the current instance of the repository.
+    ///
+    /// This also removes all snapshots in the current instance.
+    ///
+    /// See [`KeyRepo::clear_instance`] for details.
+    ///
+    /// [`KeyRepo::clear_instance`]: crate::repo::key::KeyRepo::clear_instance
+    pub fn clear_instance(&mut self) {
+        self.0.clear_instance()
+    }
+
+    /// Change the password for this repository.
+    ///
+    /// See [`KeyRepo::change_password`] for details.
+    ///
+    /// [`KeyRepo::change_password`]: crate::repo::key::KeyRepo::change_password
+    pub fn change_password(
+        &mut self,
+        new_password: &[u8],
+        memory_limit: ResourceLimit,
+        operations_limit: ResourceLimit,
+    ) {
+        self.0
+            .change_password(new_password, memory_limit, operations_limit);
+    }
+
+    /// Return this repository's instance ID.
+    pub fn instance(&self) -> InstanceId {
+        self.0.instance()
+    }
+
+    /// Return information about each instance of this repository.
+    ///
+    /// See [`KeyRepo::list_instances`] for details.
+    ///
+    /// [`KeyRepo::list_instances`]: crate::repo::key::KeyRepo::list_instances
+    pub fn list_instances(&self) -> Vec<InstanceSummary> {
+        self.0.list_instances()
+    }
+
+    /// Set the human-readable name of the instance with the given `id` to `name`.
+    ///
+    /// See [`KeyRepo::name_instance`] for details.
+    ///
+    /// [`KeyRepo::name_instance`]: crate::repo::key::KeyRepo::name_instance
+    pub fn name_instance(&mut self, id: InstanceId, name: Option<&str>) -> crate::Result<()> {
+        self.0.name_instance(id, name)
+    }
+
+    /// Return the ID of the instance with the given `name`.
+    ///
+    /// See [`KeyRepo::find_instance`] for details.
+    ///
+    /// [`KeyRepo::find_instance`]: crate::repo::key::KeyRepo::find_instance
+    pub fn find_instance(&self, name: &str) -> Option<InstanceId> {
+        self.0.find_instance(name)
+    }
+
+    /// Remove the instance of the repository with the given `id`.
+    ///
+    /// See [`KeyRepo::remove_instance`] for details.
+    ///
+    /// [`KeyRepo::remove_instance`]: crate::repo::key::KeyRepo::remove_instance
+    pub fn remove_instance(&mut self, id: InstanceId) -> crate::Result<bool> {
+        self.0.remove_instance(id)
+    }
+
+    /// Compute statistics about the repository.
+    ///
+    /// See [`KeyRepo::stats`] for details.
+    ///
+    /// [`KeyRepo::stats`]: crate::repo::key::KeyRepo::stats
+    pub fn stats(&self) -> RepoStats {
+        self.0.stats()
+    }
+
+    /// Compute statistics about the objects in each instance of the repository.
+    ///
+    /// See [`KeyRepo::instance_stats`] for details.
+    ///
+    /// [`KeyRepo::instance_stats`]: crate::repo::key::KeyRepo::instance_stats
+    pub fn instance_stats(&self) -> crate::Result<HashMap<InstanceId, UsageStats>> {
+        self.0.instance_stats()
+    }
+
+    /// Return information about the repository.
+    pub fn info(&self) -> RepoInfo {
+        self.0.info()
+    }
+
+    /// Return the quota for the current instance in bytes.
+    ///
+    /// See [`KeyRepo::quota`] for details.
+    ///
+    /// [`KeyRepo::quota`]: crate::repo::key::KeyRepo::quota
+    pub fn quota(&self) -> Option<u64> {
+        self.0.quota()
+    }
+
+    /// Set the quota for the current instance to `quota` bytes.
+    ///
+    /// See [`KeyRepo::set_quota`] for details.
+    ///
+    /// [`KeyRepo::set_quota`]: crate::repo::key::KeyRepo::set_quota
+    pub fn set_quota(&mut self, quota: Option<u64>) {
+        self.0.set_quota(quota)
+    }
+
+    /// Register `callback` to be called each time `event` happens in this repository.
+    ///
+    /// See [`KeyRepo::on_event`] for details.
+    ///
+    /// [`KeyRepo::on_event`]: crate::repo::key::KeyRepo::on_event
+    pub fn on_event(
+        &self,
+        event: RepoEvent,
+        callback: impl FnMut(&RepoInfo) + Send + 'static,
+    ) -> HookId {
+        self.0.on_event(event, callback)
+    }
+
+    /// Remove the hook with the given `id`.
+    ///
+    /// See [`KeyRepo::remove_hook`] for details.
+    ///
+    /// [`KeyRepo::remove_hook`]: crate::repo::key::KeyRepo::remove_hook
+    pub fn remove_hook(&self, id: HookId) -> bool {
+        self.0.remove_hook(id)
+    }
+
+    /// Return the log of commits to this repository.
+    ///
+    /// See [`KeyRepo::commit_log`] for details.
+    ///
+    /// [`KeyRepo::commit_log`]: crate::repo::key::KeyRepo::commit_log
+    pub fn commit_log(&self) -> &[CommitRecord] {
+        self.0.commit_log()
+    }
+
+    /// Verify the integrity of the commit log.
+    ///
+    /// See [`KeyRepo::verify_commit_log`] for details.
+    ///
+    /// # Errors
+    /// - `Error::Corrupt`: The commit log has been tampered with.
+    ///
+    /// [`KeyRepo::verify_commit_log`]: crate::repo::key::KeyRepo::verify_commit_log
+    pub fn verify_commit_log(&self) -> crate::Result<()> {
+        self.0.verify_commit_log()
+    }
+
+    /// Rewrite the data in the repository according to the given `options`.
+    ///
+    /// See [`KeyRepo::repack`] for details.
+    ///
+    /// [`KeyRepo::repack`]: crate::repo::key::KeyRepo::repack
+    pub fn repack(&mut self, options: RepackOptions) -> crate::Result<()> {
+        self.0.repack(options)
+    }
+
+    /// Change the chunking method used for new data in the repository.
+    ///
+    /// See [`KeyRepo::set_chunking`] for details.
+    ///
+    /// [`KeyRepo::set_chunking`]: crate::repo::key::KeyRepo::set_chunking
+    pub fn set_chunking(&mut self, chunking: Chunking) -> crate::Result<()> {
+        self.0.set_chunking(chunking)
+    }
+
+    /// Rewrite every object in the current instance using the current chunking method.
+    ///
+    /// See [`KeyRepo::rechunk_all`] for details.
+    ///
+    /// [`KeyRepo::rechunk_all`]: crate::repo::key::KeyRepo::rechunk_all
+    pub fn rechunk_all(&mut self) -> crate::Result<()> {
+        self.0.rechunk_all()
+    }
+
+    /// Persist uncommitted changes to the data store without committing them.
+    ///
+    /// See [`KeyRepo::flush`] for details.
+    ///
+    /// [`KeyRepo::flush`]: crate::repo::key::KeyRepo::flush
+    pub fn flush(&mut self) -> crate::Result<()> {
+        self.0.flush()
+    }
+
+    /// Restore the repository to the state it was in when changes were last flushed.
+    ///
+    /// See [`KeyRepo::restore_flushed`] for details.
+    ///
+    /// [`KeyRepo::restore_flushed`]: crate::repo::key::KeyRepo::restore_flushed
+    pub fn restore_flushed(&mut self) -> crate::Result<bool> {
+        self.0.restore_flushed()
+    }
+
+    /// Save the current state of the repository as a checkpoint named `name`.
+    ///
+    /// See [`KeyRepo::create_checkpoint`] for details.
+    ///
+    /// [`KeyRepo::create_checkpoint`]: crate::repo::key::KeyRepo::create_checkpoint
+    pub fn create_checkpoint(&mut self, name: impl Into<String>) -> crate::Result<()> {
+        self.0.create_checkpoint(name)
+    }
+
+    /// Remove the checkpoint named `name` from the repository.
+    ///
+    /// See [`KeyRepo::remove_checkpoint`] for details.
+    ///
+    /// [`KeyRepo::remove_checkpoint`]: crate::repo::key::KeyRepo::remove_checkpoint
+    pub fn remove_checkpoint(&mut self, name: &str) -> bool {
+        self.0.remove_checkpoint(name)
+    }
+
+    /// Return whether there is a checkpoint named `name`.
+    pub fn contains_checkpoint(&self, name: &str) -> bool {
+        self.0.contains_checkpoint(name)
+    }
+
+    /// Return an iterator over the names of the checkpoints in this repository.
+    ///
+    /// See [`KeyRepo::checkpoints`] for details.
+    ///
+    /// [`KeyRepo::checkpoints`]: crate::repo::key::KeyRepo::checkpoints
+    pub fn checkpoints(&self) -> Checkpoints {
+        self.0.checkpoints()
+    }
+
+    /// Restore the repository to the state it was in when the checkpoint `name` was created.
+    ///
+    /// See [`KeyRepo::restore_checkpoint`] for details.
+    ///
+    /// [`KeyRepo::restore_checkpoint`]: crate::repo::key::KeyRepo::restore_checkpoint
+    pub fn restore_checkpoint(&mut self, name: &str) -> crate::Result<()> {
+        self.0.restore_checkpoint(name)
+    }
+
+    /// Return the number of previous commits which the repository can be restored to.
+    ///
+    /// See [`KeyRepo::previous_commits`] for details.
+    ///
+    /// [`KeyRepo::previous_commits`]: crate::repo::key::KeyRepo::previous_commits
+    pub fn previous_commits(&self) -> usize {
+        self.0.previous_commits()
+    }
+
+    /// Restore the repository to the state it was in `age` commits before the most recent one.
+    ///
+    /// See [`KeyRepo::restore_commit`] for details.
+    ///
+    /// [`KeyRepo::restore_commit`]: crate::repo::key::KeyRepo::restore_commit
+    pub fn restore_commit(&mut self, age: usize) -> crate::Result<()> {
+        self.0.restore_commit(age)
+    }
+}
+
+impl<K: Key> Commit for SnapshotRepo<K> {
+    fn commit(&mut self) -> crate::Result<()> {
+        self.0.commit()
+    }
+
+    fn rollback(&mut self) -> crate::Result<()> {
+        self.0.rollback()
+    }
+
+    fn clean(&mut self) -> crate::Result<()> {
+        self.0.clean()
+    }
+}
+
+impl<K: Key> RestoreSavepoint for SnapshotRepo<K> {
+    type Restore = <StateRepo<RepoState<K>> as RestoreSavepoint>::Restore;
+
+    fn savepoint(&mut self) -> crate::Result<Savepoint> {
+        self.0.savepoint()
+    }
+
+    fn start_restore(&mut self, savepoint: &Savepoint) -> crate::Result<Self::Restore> {
+        self.0.start_restore(savepoint)
+    }
+
+    fn finish_restore(&mut self, restore: Self::Restore) -> bool {
+        self.0.finish_restore(restore)
+    }
+}
+
+impl<K: Key> Unlock for SnapshotRepo<K> {
+    fn unlock(&self) -> crate::Result<()> {
+        self.0.unlock()
+    }
+
+    fn is_locked(&self) -> crate::Result<bool> {
+        self.0.is_locked()
+    }
+
+    fn context(&self) -> crate::Result<Vec<u8>> {
+        self.0.context()
+    }
+
+    fn update_context(&self, context: &[u8]) -> crate::Result<()> {
+        self.0.update_context(context)
+    }
+}
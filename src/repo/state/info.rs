@@ -11,12 +11,13 @@ id_table! {
     KeyIdTable
 }
 
-#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Serialize, Deserialize)]
 pub enum RepoKey {
     Object(KeyId),
     State,
     IdTable,
     Stage,
+    Segment(String),
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
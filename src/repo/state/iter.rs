@@ -43,3 +43,32 @@ impl<'a> Iterator for Keys<'a> {
 impl<'a> FusedIterator for Keys<'a> {}
 
 impl<'a> ExactSizeIterator for Keys<'a> {}
+
+/// An iterator over the names of the state segments in a [`StateRepo`].
+///
+/// This value is created by [`StateRepo::segments`].
+///
+/// [`StateRepo`]: crate::repo::state::StateRepo
+/// [`StateRepo::segments`]: crate::repo::state::StateRepo::segments
+#[derive(Debug, Clone)]
+pub struct Segments<'a>(pub(super) key::Keys<'a, RepoKey>);
+
+impl<'a> Iterator for Segments<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.0.next() {
+                None => return None,
+                Some(RepoKey::Segment(name)) => return Some(name.as_str()),
+                _ => continue,
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.0.size_hint().1)
+    }
+}
+
+impl<'a> FusedIterator for Segments<'a> {}
@@ -18,15 +18,25 @@
 //! values are opaque, but they're serializable, meaning that an [`ObjectKey`] can be written to
 //! another object or stored in the repository state.
 //!
+//! Because the `State` value is serialized in its entirety whenever it's written, it's only
+//! rewritten when changes are committed if it was accessed mutably via [`state_mut`] since it was
+//! last written. Repository types with large indices can also store parts of their state in named
+//! segments using [`set_segment`]. Unlike the `State` value, segments are not kept in memory; each
+//! segment is only deserialized when it's read with [`segment`], and writing a segment does not
+//! require serializing any other part of the state. Segments are committed, rolled back, and
+//! restored along with the rest of the repository.
+//!
 //! [`StateRepo`]: crate::repo::state::StateRepo
 //! [`KeyRepo`]: crate::repo::key::KeyRepo
 //! [`state`]: crate::repo::state::StateRepo::state
 //! [`state_mut`]: crate::repo::state::StateRepo::state_mut
 //! [`clear_instance`]: crate::repo::state::StateRepo::clear_instance
+//! [`set_segment`]: crate::repo::state::StateRepo::set_segment
+//! [`segment`]: crate::repo::state::StateRepo::segment
 //! [`ObjectKey`]: crate::repo::state::ObjectKey
 
 pub use self::info::ObjectKey;
-pub use self::iter::Keys;
+pub use self::iter::{Keys, Segments};
 pub use self::repository::StateRepo;
 
 mod info;
@@ -1,4 +1,4 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
 use serde::de::DeserializeOwned;
 use serde::Serialize;
@@ -6,10 +6,11 @@ use static_assertions::assert_impl_all;
 use uuid::uuid;
 
 use super::info::{KeyId, KeyIdTable, ObjectKey, RepoKey, RepoState, StateRestore};
-use super::iter::Keys;
+use super::iter::{Keys, Segments};
 use crate::repo::{
-    key::KeyRepo, Commit, InstanceId, Object, OpenRepo, RepoInfo, RepoStats, ResourceLimit,
-    RestoreSavepoint, Savepoint, Unlock, VersionId,
+    key::KeyRepo, Checkpoints, Chunking, Commit, CommitRecord, HookId, InstanceId, InstanceSummary,
+    Object, OpenRepo, RepackOptions, RepoEvent, RepoInfo, RepoStats, ResourceLimit,
+    RestoreSavepoint, Savepoint, Unlock, UsageStats, VersionId,
 };
 
 /// A low-level repository type which can be used to implement higher-level repository types
@@ -23,6 +24,10 @@ where
     repo: KeyRepo<RepoKey>,
     id_table: KeyIdTable,
     state: State,
+    /// Whether the state may have changed since it was last written to the backing repository.
+    state_changed: bool,
+    /// Whether the ID table has changed since it was last written to the backing repository.
+    id_table_changed: bool,
 }
 
 assert_impl_all!(StateRepo<()>: Send, Sync);
@@ -43,10 +48,11 @@ where
             repo,
             id_table: KeyIdTable::new(),
             state: State::default(),
+            state_changed: false,
+            id_table_changed: false,
         };
-        let RepoState { state, id_table } = state_repo.read_state()?;
-        state_repo.state = state;
-        state_repo.id_table = id_table;
+        let repo_state = state_repo.read_state()?;
+        state_repo.set_state(repo_state);
         Ok(state_repo)
     }
 
@@ -58,6 +64,8 @@ where
             repo,
             id_table: KeyIdTable::new(),
             state: State::default(),
+            state_changed: true,
+            id_table_changed: true,
         };
         state_repo.write_state()?;
         Ok(state_repo)
@@ -86,19 +94,26 @@ where
         Ok(RepoState { state, id_table })
     }
 
-    /// Write the state and ID table to the backing repository.
+    /// Replace the state and ID table with ones read from the backing repository.
+    fn set_state(&mut self, repo_state: RepoState<State>) {
+        let RepoState { state, id_table } = repo_state;
+        self.state = state;
+        self.id_table = id_table;
+        self.state_changed = false;
+        self.id_table_changed = false;
+    }
+
+    /// Write the state and ID table to the backing repository if they've changed.
     fn write_state(&mut self) -> crate::Result<()> {
-        // We write to a temporary object before copying to the final destination to make the write
-        // atomic.
-        let mut object = self.repo.insert(RepoKey::Stage);
-        object.serialize(&self.state)?;
-        drop(object);
-        self.repo.copy(&RepoKey::Stage, RepoKey::State);
-
-        let mut object = self.repo.insert(RepoKey::Stage);
-        object.serialize(&self.id_table)?;
-        drop(object);
-        self.repo.copy(&RepoKey::Stage, RepoKey::IdTable);
+        if self.state_changed {
+            write_staged(&mut self.repo, RepoKey::State, &self.state)?;
+            self.state_changed = false;
+        }
+
+        if self.id_table_changed {
+            write_staged(&mut self.repo, RepoKey::IdTable, &self.id_table)?;
+            self.id_table_changed = false;
+        }
 
         Ok(())
     }
@@ -123,10 +138,81 @@ where
     }
 
     /// Return a mutable reference to the encapsulated state.
+    ///
+    /// Calling this marks the state as modified, so it will be serialized and written to the data
+    /// store the next time changes are committed. Use [`state`] if you don't need to modify it.
+    ///
+    /// [`state`]: crate::repo::state::StateRepo::state
     pub fn state_mut(&mut self) -> &mut State {
+        self.state_changed = true;
         &mut self.state
     }
 
+    /// Replace the encapsulated state with `state` and return the previous state.
+    ///
+    /// Unlike [`state_mut`], this only marks the state as modified if `changed` is `true`.
+    ///
+    /// [`state_mut`]: crate::repo::state::StateRepo::state_mut
+    pub(crate) fn replace_state(&mut self, state: State, changed: bool) -> State {
+        self.state_changed |= changed;
+        std::mem::replace(&mut self.state, state)
+    }
+
+    /// Return whether there is a state segment named `name`.
+    pub fn contains_segment(&self, name: &str) -> bool {
+        self.repo.contains(&RepoKey::Segment(name.to_string()))
+    }
+
+    /// Read and deserialize the state segment named `name`.
+    ///
+    /// Segments are not kept in memory, so this deserializes the segment each time it's called.
+    /// This returns `None` if there is no segment named `name`.
+    ///
+    /// # Errors
+    /// - `Error::Deserialize`: The segment could not be deserialized as a value of type `T`.
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    pub fn segment<T: DeserializeOwned>(&self, name: &str) -> crate::Result<Option<T>> {
+        match self.repo.object(&RepoKey::Segment(name.to_string())) {
+            Some(mut object) => Ok(Some(object.deserialize()?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Serialize `value` and write it to the state segment named `name`.
+    ///
+    /// If there is already a segment named `name`, it is replaced. This only serializes `value`,
+    /// so it's cheaper than modifying a large `State` value when only a small part of it changes.
+    ///
+    /// If this returns `Err`, the segment is unchanged.
+    ///
+    /// # Errors
+    /// - `Error::Serialize`: The `value` could not be serialized.
+    /// - `Error::QuotaExceeded`: Writing the segment would exceed the quota for this instance.
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    pub fn set_segment<T: Serialize>(
+        &mut self,
+        name: impl Into<String>,
+        value: &T,
+    ) -> crate::Result<()> {
+        write_staged(&mut self.repo, RepoKey::Segment(name.into()), value)
+    }
+
+    /// Remove the state segment named `name`.
+    ///
+    /// This returns `true` if the segment was removed or `false` if it didn't exist.
+    pub fn remove_segment(&mut self, name: &str) -> bool {
+        self.repo.remove(&RepoKey::Segment(name.to_string()))
+    }
+
+    /// Return an iterator over the names of the state segments in this repository.
+    pub fn segments(&self) -> Segments {
+        Segments(self.repo.keys())
+    }
+
     /// Return whether there is an object with the given `key` in this repository.
     pub fn contains(&self, key: ObjectKey) -> bool {
         self.check_key(key) && self.repo.contains(&RepoKey::Object(key.key_id))
@@ -135,10 +221,56 @@ where
     /// Create a new object in the repository and returns its `ObjectKey`.
     pub fn create(&mut self) -> ObjectKey {
         let object_id = self.id_table.next();
+        self.id_table_changed = true;
         self.repo.insert(RepoKey::Object(object_id));
         self.new_id(object_id)
     }
 
+    /// Create a new object in the repository for each item in `data` and return their keys.
+    ///
+    /// Each new object contains the corresponding bytes from `data`, and the returned keys are in
+    /// the same order as `data`. See [`KeyRepo::insert_batch`] for details.
+    ///
+    /// If this returns `Err`, none of the objects are created.
+    ///
+    /// # Errors
+    /// - `Error::QuotaExceeded`: Adding the objects would exceed the quota for this instance.
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    ///
+    /// [`KeyRepo::insert_batch`]: crate::repo::key::KeyRepo::insert_batch
+    pub fn create_batch<D: AsRef<[u8]>>(
+        &mut self,
+        data: impl IntoIterator<Item = D>,
+    ) -> crate::Result<Vec<ObjectKey>> {
+        let objects = data
+            .into_iter()
+            .map(|data| (self.id_table.next(), data))
+            .collect::<Vec<_>>();
+        self.id_table_changed = true;
+        let object_ids = objects.iter().map(|(id, _)| *id).collect::<Vec<_>>();
+
+        let result = self.repo.insert_batch(
+            objects
+                .into_iter()
+                .map(|(object_id, data)| (RepoKey::Object(object_id), data)),
+        );
+
+        match result {
+            Ok(()) => Ok(object_ids
+                .into_iter()
+                .map(|object_id| self.new_id(object_id))
+                .collect()),
+            Err(error) => {
+                for object_id in object_ids {
+                    self.id_table.recycle(object_id);
+                }
+                Err(error)
+            }
+        }
+    }
+
     /// Remove the object with the given `key` from the repository.
     ///
     /// This returns `true` if the object was removed or `false` if it didn't exist.
@@ -155,6 +287,7 @@ where
         if !self.id_table.recycle(key.key_id) {
             return false;
         }
+        self.id_table_changed = true;
 
         assert!(self.repo.remove(&RepoKey::Object(key.key_id)));
 
@@ -191,6 +324,7 @@ where
             return None;
         }
         let dest_id = self.id_table.next();
+        self.id_table_changed = true;
 
         assert!(self
             .repo
@@ -199,6 +333,26 @@ where
         Some(self.new_id(dest_id))
     }
 
+    /// Replace the object at `dest` with a copy of the object at `source`.
+    ///
+    /// This returns `true` if the object was copied or `false` if there was no object at `source`
+    /// or `dest`.
+    ///
+    /// This is a cheap operation which does not require copying the bytes in the object.
+    pub fn copy_to(&mut self, source: ObjectKey, dest: ObjectKey) -> bool {
+        if !self.check_key(source) || !self.repo.contains(&RepoKey::Object(source.key_id)) {
+            return false;
+        }
+        if !self.check_key(dest) || !self.repo.contains(&RepoKey::Object(dest.key_id)) {
+            return false;
+        }
+
+        self.repo.copy(
+            &RepoKey::Object(source.key_id),
+            RepoKey::Object(dest.key_id),
+        )
+    }
+
     /// Verify the integrity of all the data in the repository.
     ///
     /// This returns the set of keys of objects which are corrupt.
@@ -227,6 +381,8 @@ where
     pub fn clear_instance(&mut self) {
         self.state = State::default();
         self.id_table = KeyIdTable::new();
+        self.state_changed = true;
+        self.id_table_changed = true;
         self.repo.clear_instance();
     }
 
@@ -250,6 +406,42 @@ where
         self.repo.instance()
     }
 
+    /// Return information about each instance of this repository.
+    ///
+    /// See [`KeyRepo::list_instances`] for details.
+    ///
+    /// [`KeyRepo::list_instances`]: crate::repo::key::KeyRepo::list_instances
+    pub fn list_instances(&self) -> Vec<InstanceSummary> {
+        self.repo.list_instances()
+    }
+
+    /// Set the human-readable name of the instance with the given `id` to `name`.
+    ///
+    /// See [`KeyRepo::name_instance`] for details.
+    ///
+    /// [`KeyRepo::name_instance`]: crate::repo::key::KeyRepo::name_instance
+    pub fn name_instance(&mut self, id: InstanceId, name: Option<&str>) -> crate::Result<()> {
+        self.repo.name_instance(id, name)
+    }
+
+    /// Return the ID of the instance with the given `name`.
+    ///
+    /// See [`KeyRepo::find_instance`] for details.
+    ///
+    /// [`KeyRepo::find_instance`]: crate::repo::key::KeyRepo::find_instance
+    pub fn find_instance(&self, name: &str) -> Option<InstanceId> {
+        self.repo.find_instance(name)
+    }
+
+    /// Remove the instance of the repository with the given `id`.
+    ///
+    /// See [`KeyRepo::remove_instance`] for details.
+    ///
+    /// [`KeyRepo::remove_instance`]: crate::repo::key::KeyRepo::remove_instance
+    pub fn remove_instance(&mut self, id: InstanceId) -> crate::Result<bool> {
+        self.repo.remove_instance(id)
+    }
+
     /// Compute statistics about the repository.
     ///
     /// See [`KeyRepo::stats`] for details.
@@ -259,10 +451,259 @@ where
         self.repo.stats()
     }
 
+    /// Compute statistics about the objects in each instance of the repository.
+    ///
+    /// See [`KeyRepo::instance_stats`] for details.
+    ///
+    /// [`KeyRepo::instance_stats`]: crate::repo::key::KeyRepo::instance_stats
+    pub fn instance_stats(&self) -> crate::Result<HashMap<InstanceId, UsageStats>> {
+        self.repo.instance_stats()
+    }
+
+    /// Compute statistics about the objects with the given `keys`.
+    ///
+    /// Keys which don't exist in the repository are ignored. See [`KeyRepo::stats_for_keys`] for
+    /// details.
+    ///
+    /// [`KeyRepo::stats_for_keys`]: crate::repo::key::KeyRepo::stats_for_keys
+    pub fn stats_for_objects(&self, keys: impl IntoIterator<Item = ObjectKey>) -> UsageStats {
+        let key_ids = keys
+            .into_iter()
+            .filter(|key| self.check_key(*key))
+            .map(|key| key.key_id)
+            .collect::<HashSet<_>>();
+        self.repo.stats_for_keys(
+            |key| matches!(key, RepoKey::Object(key_id) if key_ids.contains(key_id)),
+        )
+    }
+
     /// Return information about the repository.
     pub fn info(&self) -> RepoInfo {
         self.repo.info()
     }
+
+    /// Return the number of bytes of free space available in the data store.
+    ///
+    /// This returns `None` if the data store doesn't report how much space is available.
+    ///
+    /// # Errors
+    /// - `Error::Store`: An error occurred with the data store.
+    pub(crate) fn available_space(&self) -> crate::Result<Option<u64>> {
+        self.repo.available_space()
+    }
+
+    /// Return the quota for the current instance in bytes.
+    ///
+    /// See [`KeyRepo::quota`] for details.
+    ///
+    /// [`KeyRepo::quota`]: crate::repo::key::KeyRepo::quota
+    pub fn quota(&self) -> Option<u64> {
+        self.repo.quota()
+    }
+
+    /// Set the quota for the current instance to `quota` bytes.
+    ///
+    /// See [`KeyRepo::set_quota`] for details.
+    ///
+    /// [`KeyRepo::set_quota`]: crate::repo::key::KeyRepo::set_quota
+    pub fn set_quota(&mut self, quota: Option<u64>) {
+        self.repo.set_quota(quota)
+    }
+
+    /// Register `callback` to be called each time `event` happens in this repository.
+    ///
+    /// See [`KeyRepo::on_event`] for details.
+    ///
+    /// [`KeyRepo::on_event`]: crate::repo::key::KeyRepo::on_event
+    pub fn on_event(
+        &self,
+        event: RepoEvent,
+        callback: impl FnMut(&RepoInfo) + Send + 'static,
+    ) -> HookId {
+        self.repo.on_event(event, callback)
+    }
+
+    /// Remove the hook with the given `id`.
+    ///
+    /// See [`KeyRepo::remove_hook`] for details.
+    ///
+    /// [`KeyRepo::remove_hook`]: crate::repo::key::KeyRepo::remove_hook
+    pub fn remove_hook(&self, id: HookId) -> bool {
+        self.repo.remove_hook(id)
+    }
+
+    /// Return the log of commits to this repository.
+    ///
+    /// See [`KeyRepo::commit_log`] for details.
+    ///
+    /// [`KeyRepo::commit_log`]: crate::repo::key::KeyRepo::commit_log
+    pub fn commit_log(&self) -> &[CommitRecord] {
+        self.repo.commit_log()
+    }
+
+    /// Verify the integrity of the commit log.
+    ///
+    /// See [`KeyRepo::verify_commit_log`] for details.
+    ///
+    /// # Errors
+    /// - `Error::Corrupt`: The commit log has been tampered with.
+    ///
+    /// [`KeyRepo::verify_commit_log`]: crate::repo::key::KeyRepo::verify_commit_log
+    pub fn verify_commit_log(&self) -> crate::Result<()> {
+        self.repo.verify_commit_log()
+    }
+
+    /// Rewrite the data in the repository according to the given `options`.
+    ///
+    /// See [`KeyRepo::repack`] for details.
+    ///
+    /// [`KeyRepo::repack`]: crate::repo::key::KeyRepo::repack
+    pub fn repack(&mut self, options: RepackOptions) -> crate::Result<()> {
+        self.write_state()?;
+        self.repo.repack(options)
+    }
+
+    /// Change the chunking method used for new data in the repository.
+    ///
+    /// See [`KeyRepo::set_chunking`] for details.
+    ///
+    /// [`KeyRepo::set_chunking`]: crate::repo::key::KeyRepo::set_chunking
+    pub fn set_chunking(&mut self, chunking: Chunking) -> crate::Result<()> {
+        self.write_state()?;
+        self.repo.set_chunking(chunking)
+    }
+
+    /// Rewrite every object in the current instance using the current chunking method.
+    ///
+    /// See [`KeyRepo::rechunk_all`] for details.
+    ///
+    /// [`KeyRepo::rechunk_all`]: crate::repo::key::KeyRepo::rechunk_all
+    pub fn rechunk_all(&mut self) -> crate::Result<()> {
+        self.repo.rechunk_all()
+    }
+
+    /// Persist uncommitted changes to the data store without committing them.
+    ///
+    /// See [`KeyRepo::flush`] for details.
+    ///
+    /// [`KeyRepo::flush`]: crate::repo::key::KeyRepo::flush
+    pub fn flush(&mut self) -> crate::Result<()> {
+        self.write_state()?;
+        self.repo.flush()
+    }
+
+    /// Restore the repository to the state it was in when changes were last flushed.
+    ///
+    /// See [`KeyRepo::restore_flushed`] for details.
+    ///
+    /// [`KeyRepo::restore_flushed`]: crate::repo::key::KeyRepo::restore_flushed
+    pub fn restore_flushed(&mut self) -> crate::Result<bool> {
+        // Create a savepoint on the backing repository so that we can undo restoring the backing
+        // repository if reading the state fails.
+        let backup_savepoint = self.repo.savepoint()?;
+        let backup_restore = self.repo.start_restore(&backup_savepoint)?;
+
+        if !self.repo.restore_flushed()? {
+            return Ok(false);
+        }
+
+        match self.read_state() {
+            Ok(repo_state) => {
+                self.set_state(repo_state);
+                Ok(true)
+            }
+            Err(error) => {
+                self.repo.finish_restore(backup_restore);
+                Err(error)
+            }
+        }
+    }
+
+    /// Save the current state of the repository as a checkpoint named `name`.
+    ///
+    /// See [`KeyRepo::create_checkpoint`] for details.
+    ///
+    /// [`KeyRepo::create_checkpoint`]: crate::repo::key::KeyRepo::create_checkpoint
+    pub fn create_checkpoint(&mut self, name: impl Into<String>) -> crate::Result<()> {
+        self.write_state()?;
+        self.repo.create_checkpoint(name)
+    }
+
+    /// Remove the checkpoint named `name` from the repository.
+    ///
+    /// See [`KeyRepo::remove_checkpoint`] for details.
+    ///
+    /// [`KeyRepo::remove_checkpoint`]: crate::repo::key::KeyRepo::remove_checkpoint
+    pub fn remove_checkpoint(&mut self, name: &str) -> bool {
+        self.repo.remove_checkpoint(name)
+    }
+
+    /// Return whether there is a checkpoint named `name`.
+    pub fn contains_checkpoint(&self, name: &str) -> bool {
+        self.repo.contains_checkpoint(name)
+    }
+
+    /// Return an iterator over the names of the checkpoints in this repository.
+    ///
+    /// See [`KeyRepo::checkpoints`] for details.
+    ///
+    /// [`KeyRepo::checkpoints`]: crate::repo::key::KeyRepo::checkpoints
+    pub fn checkpoints(&self) -> Checkpoints {
+        self.repo.checkpoints()
+    }
+
+    /// Restore the repository to the state it was in when the checkpoint `name` was created.
+    ///
+    /// See [`KeyRepo::restore_checkpoint`] for details.
+    ///
+    /// [`KeyRepo::restore_checkpoint`]: crate::repo::key::KeyRepo::restore_checkpoint
+    pub fn restore_checkpoint(&mut self, name: &str) -> crate::Result<()> {
+        self.restore_backing_repo(|repo| repo.restore_checkpoint(name))
+    }
+
+    /// Return the number of previous commits which the repository can be restored to.
+    ///
+    /// See [`KeyRepo::previous_commits`] for details.
+    ///
+    /// [`KeyRepo::previous_commits`]: crate::repo::key::KeyRepo::previous_commits
+    pub fn previous_commits(&self) -> usize {
+        self.repo.previous_commits()
+    }
+
+    /// Restore the repository to the state it was in `age` commits before the most recent one.
+    ///
+    /// See [`KeyRepo::restore_commit`] for details.
+    ///
+    /// [`KeyRepo::restore_commit`]: crate::repo::key::KeyRepo::restore_commit
+    pub fn restore_commit(&mut self, age: usize) -> crate::Result<()> {
+        self.restore_backing_repo(|repo| repo.restore_commit(age))
+    }
+
+    /// Restore the backing repository with `restore` and then re-read the state from it.
+    ///
+    /// If this returns `Err`, the repository is unchanged.
+    fn restore_backing_repo(
+        &mut self,
+        restore: impl FnOnce(&mut KeyRepo<RepoKey>) -> crate::Result<()>,
+    ) -> crate::Result<()> {
+        // Create a savepoint on the backing repository so that we can undo restoring the backing
+        // repository if reading the state fails.
+        let backup_savepoint = self.repo.savepoint()?;
+        let backup_restore = self.repo.start_restore(&backup_savepoint)?;
+
+        restore(&mut self.repo)?;
+
+        match self.read_state() {
+            Ok(repo_state) => {
+                self.set_state(repo_state);
+                Ok(())
+            }
+            Err(error) => {
+                self.repo.finish_restore(backup_restore);
+                Err(error)
+            }
+        }
+    }
 }
 
 impl<State> Commit for StateRepo<State>
@@ -287,9 +728,8 @@ where
 
         // Roll back this repository's state to the previous commit.
         match self.read_state() {
-            Ok(RepoState { state, id_table }) => {
-                self.state = state;
-                self.id_table = id_table;
+            Ok(repo_state) => {
+                self.set_state(repo_state);
                 Ok(())
             }
             Err(error) => {
@@ -353,9 +793,7 @@ where
         if !self.repo.finish_restore(restore.restore) {
             return false;
         }
-        let RepoState { state, id_table } = restore.state;
-        self.state = state;
-        self.id_table = id_table;
+        self.set_state(restore.state);
         true
     }
 }
@@ -380,3 +818,18 @@ where
         self.repo.update_context(context)
     }
 }
+
+/// Serialize `value` and write it to the object at `key` in the backing `repo`.
+fn write_staged<T: Serialize>(
+    repo: &mut KeyRepo<RepoKey>,
+    key: RepoKey,
+    value: &T,
+) -> crate::Result<()> {
+    // We write to a temporary object before copying to the final destination to make the write
+    // atomic.
+    let mut object = repo.insert(RepoKey::Stage);
+    object.serialize(value)?;
+    drop(object);
+    repo.copy(&RepoKey::Stage, key);
+    Ok(())
+}
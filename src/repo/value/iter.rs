@@ -1,4 +1,4 @@
-use std::collections::hash_map;
+use std::collections::{btree_map, hash_map};
 use std::iter::{ExactSizeIterator, FusedIterator};
 
 use crate::repo::state::ObjectKey;
@@ -27,3 +27,63 @@ impl<'a, K> Iterator for Keys<'a, K> {
 impl<'a, K> FusedIterator for Keys<'a, K> {}
 
 impl<'a, K> ExactSizeIterator for Keys<'a, K> {}
+
+/// An iterator over the keys in an [`OrderedValueRepo`] in order.
+///
+/// This value is created by [`OrderedValueRepo::keys`].
+///
+/// [`OrderedValueRepo`]: crate::repo::value::OrderedValueRepo
+/// [`OrderedValueRepo::keys`]: crate::repo::value::OrderedValueRepo::keys
+#[derive(Debug, Clone)]
+pub struct OrderedKeys<'a, K>(pub(super) btree_map::Keys<'a, K, ObjectKey>);
+
+impl<'a, K> Iterator for OrderedKeys<'a, K> {
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl<'a, K> DoubleEndedIterator for OrderedKeys<'a, K> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.0.next_back()
+    }
+}
+
+impl<'a, K> FusedIterator for OrderedKeys<'a, K> {}
+
+impl<'a, K> ExactSizeIterator for OrderedKeys<'a, K> {}
+
+/// An iterator over a range of keys in an [`OrderedValueRepo`] in order.
+///
+/// This value is created by [`OrderedValueRepo::range`].
+///
+/// [`OrderedValueRepo`]: crate::repo::value::OrderedValueRepo
+/// [`OrderedValueRepo::range`]: crate::repo::value::OrderedValueRepo::range
+#[derive(Debug, Clone)]
+pub struct Range<'a, K>(pub(super) btree_map::Range<'a, K, ObjectKey>);
+
+impl<'a, K> Iterator for Range<'a, K> {
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(|(key, _)| key)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl<'a, K> DoubleEndedIterator for Range<'a, K> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.0.next_back().map(|(key, _)| key)
+    }
+}
+
+impl<'a, K> FusedIterator for Range<'a, K> {}
@@ -1,9 +1,15 @@
 //! A persistent, heterogeneous, map-like collection.
 //!
-//! This module contains the [`ValueRepo`] repository type.
+//! This module contains the [`ValueRepo`] and [`OrderedValueRepo`] repository types.
 //!
 //! This is a repository which maps keys to concrete values instead of binary blobs. Values are
-//! serialized and deserialized automatically using a space-efficient binary format.
+//! serialized and deserialized automatically using a space-efficient binary format. A
+//! [`ValueRepo`] accepts a [`Format`] type parameter which can be used to serialize values in a
+//! different format instead, like JSON.
+//!
+//! An [`OrderedValueRepo`] keeps its keys sorted. This allows for iterating over keys in order and
+//! querying ranges of keys without deserializing any values, which makes it useful as a small
+//! embedded sorted key-value store.
 //!
 //! Like other repositories, changes made to the repository are not persisted to the data store
 //! until [`Commit::commit`] is called. For details about deduplication, compression, encryption,
@@ -11,9 +17,17 @@
 //!
 //! [`ValueRepo`]: crate::repo::value::ValueRepo
 //! [`Commit::commit`]: crate::repo::Commit::commit
+//! [`OrderedValueRepo`]: crate::repo::value::OrderedValueRepo
+//! [`Format`]: crate::repo::Format
 
-pub use self::iter::Keys;
+pub use self::iter::{Keys, OrderedKeys, Range};
+pub use self::ordered::OrderedValueRepo;
 pub use self::repository::ValueRepo;
+pub use self::serialized::SerializedValue;
+pub use self::shared::{SharedOrderedValueRepo, SharedValueRepo};
 
 mod iter;
+mod ordered;
 mod repository;
+mod serialized;
+mod shared;
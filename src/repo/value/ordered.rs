@@ -0,0 +1,617 @@
+use std::borrow::Borrow;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Debug;
+use std::io::Read;
+use std::ops::RangeBounds;
+
+use rmp_serde::to_vec;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use uuid::uuid;
+
+use super::iter::{OrderedKeys, Range};
+use super::serialized::SerializedValue;
+use super::shared::{SharedOrderedValueRepo, ValueStore};
+use crate::repo::{
+    key::{Key, KeyRepo},
+    state::{ObjectKey, StateRepo},
+    Checkpoints, Chunking, Commit, CommitRecord, HookId, InstanceId, InstanceSummary, MessagePack,
+    OpenRepo, RepackOptions, RepoEvent, RepoInfo, RepoStats, ResourceLimit, RestoreSavepoint,
+    Savepoint, Unlock, UsageStats, VersionId,
+};
+
+type RepoState<K> = BTreeMap<K, ObjectKey>;
+
+/// A persistent, heterogeneous, map-like collection with ordered keys.
+///
+/// This is like a [`ValueRepo`], except keys are kept sorted, which allows for iterating over
+/// keys in order and querying ranges of keys.
+///
+/// See [`crate::repo::value`] for more information.
+///
+/// [`ValueRepo`]: crate::repo::value::ValueRepo
+#[derive(Debug)]
+pub struct OrderedValueRepo<K: Key + Ord>(StateRepo<RepoState<K>>);
+
+impl<K: Key + Ord> OpenRepo for OrderedValueRepo<K> {
+    type Key = <StateRepo<RepoState<K>> as OpenRepo>::Key;
+
+    const VERSION_ID: VersionId = VersionId::new(uuid!("751547bc-3887-4665-b7c3-f41befd0f0ec"));
+
+    fn open_repo(repo: KeyRepo<Self::Key>) -> crate::Result<Self>
+    where
+        Self: Sized,
+    {
+        Ok(Self(StateRepo::open_repo(repo)?))
+    }
+
+    fn create_repo(repo: KeyRepo<Self::Key>) -> crate::Result<Self>
+    where
+        Self: Sized,
+    {
+        Ok(Self(StateRepo::create_repo(repo)?))
+    }
+
+    fn into_repo(self) -> crate::Result<KeyRepo<Self::Key>> {
+        self.0.into_repo()
+    }
+}
+
+impl<K: Key + Ord> OrderedValueRepo<K> {
+    /// Return whether the given `key` exists in this repository.
+    pub fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.0.state().contains_key(key)
+    }
+
+    /// Insert a new key-value pair.
+    ///
+    /// If `key` is already in the repository, its value is replaced.
+    ///
+    /// The value is written to the repository as it's serialized, so its serialized form is never
+    /// buffered in memory in its entirety.
+    ///
+    /// # Errors
+    /// - `Error::Serialize`: The `value` could not be serialized.
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    pub fn insert<V: Serialize>(&mut self, key: K, value: &V) -> crate::Result<()> {
+        let object_id = self.0.create();
+        let mut object = self.0.object(object_id).unwrap();
+        let result = object.serialize(value);
+        drop(object);
+        if let Err(error) = result {
+            self.0.remove(object_id);
+            return Err(error);
+        }
+
+        if let Some(prev_object_id) = self.0.state_mut().insert(key, object_id) {
+            self.0.remove(prev_object_id);
+        }
+
+        Ok(())
+    }
+
+    /// Insert several key-value pairs at once.
+    ///
+    /// This is equivalent to calling [`insert`] for each pair, but it amortizes the cost of
+    /// writing the values to the data store. See [`KeyRepo::insert_batch`] for details.
+    ///
+    /// If a key is already in the repository, its value is replaced. If this returns `Err`, none
+    /// of the values are inserted.
+    ///
+    /// # Errors
+    /// - `Error::Serialize`: One of the values could not be serialized.
+    /// - `Error::QuotaExceeded`: Inserting the values would exceed the quota for this instance.
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    ///
+    /// [`insert`]: crate::repo::value::OrderedValueRepo::insert
+    /// [`KeyRepo::insert_batch`]: crate::repo::key::KeyRepo::insert_batch
+    pub fn insert_batch<V: Serialize>(
+        &mut self,
+        values: impl IntoIterator<Item = (K, V)>,
+    ) -> crate::Result<()> {
+        let mut keys = Vec::new();
+        let mut serialized_values = Vec::new();
+        for (key, value) in values {
+            serialized_values.push(to_vec(&value).map_err(|_| crate::Error::Serialize)?);
+            keys.push(key);
+        }
+
+        let object_ids = self.0.create_batch(serialized_values)?;
+
+        for (key, object_id) in keys.into_iter().zip(object_ids) {
+            if let Some(prev_object_id) = self.0.state_mut().insert(key, object_id) {
+                self.0.remove(prev_object_id);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Remove the values associated with each of the given `keys` from the repository.
+    ///
+    /// This returns the number of values which were removed. Keys which don't exist in the
+    /// repository are ignored.
+    ///
+    /// The space used by the given values isn't reclaimed in the backing data store until changes
+    /// are committed and [`Commit::clean`] is called.
+    ///
+    /// [`Commit::clean`]: crate::repo::Commit::clean
+    pub fn remove_batch<'a, Q>(&mut self, keys: impl IntoIterator<Item = &'a Q>) -> usize
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized + 'a,
+    {
+        keys.into_iter().filter(|key| self.remove(*key)).count()
+    }
+
+    /// Remove the value associated with `key` from the repository.
+    ///
+    /// This returns `true` if the value was removed or `false` if it didn't exist.
+    ///
+    /// The space used by the given value isn't reclaimed in the backing data store until changes
+    /// are committed and [`Commit::clean`] is called.
+    ///
+    /// [`Commit::clean`]: crate::repo::Commit::clean
+    pub fn remove<Q>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        match self.0.state_mut().remove(key) {
+            Some(object_id) => {
+                self.0.remove(object_id);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Return the value associated with `key`.
+    ///
+    /// The value is deserialized as it's read from the repository, so its serialized form is never
+    /// buffered in memory in its entirety.
+    ///
+    /// # Errors
+    /// - `Error::NotFound`: There is no value associated with `key`.
+    /// - `Error::Deserialize`: The value could not be deserialized.
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    pub fn get<Q, V>(&self, key: &Q) -> crate::Result<V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+        V: DeserializeOwned,
+    {
+        let object_id = self.0.state().get(key).ok_or(crate::Error::NotFound)?;
+        let mut object = self.0.object(*object_id).unwrap();
+        object.deserialize()
+    }
+
+    /// Return the serialized value associated with `key`.
+    ///
+    /// Unlike [`get`], this reads the entire serialized value into memory, which allows it to be
+    /// deserialized into types which borrow from it. See [`SerializedValue`] for details.
+    ///
+    /// # Errors
+    /// - `Error::NotFound`: There is no value associated with `key`.
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    ///
+    /// [`get`]: crate::repo::value::OrderedValueRepo::get
+    /// [`SerializedValue`]: crate::repo::value::SerializedValue
+    pub fn get_serialized<Q>(&self, key: &Q) -> crate::Result<SerializedValue>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let object_id = self.0.state().get(key).ok_or(crate::Error::NotFound)?;
+        let mut object = self.0.object(*object_id).unwrap();
+        let mut serialized_value = Vec::new();
+        object.read_to_end(&mut serialized_value)?;
+        Ok(SerializedValue(serialized_value))
+    }
+
+    /// Return an iterator of all the keys in this repository in order.
+    pub fn keys(&self) -> OrderedKeys<K> {
+        OrderedKeys(self.0.state().keys())
+    }
+
+    /// Return an iterator of the keys in this repository which are in the given `range` in order.
+    ///
+    /// This does not deserialize any values.
+    ///
+    /// # Panics
+    /// - The start of `range` is greater than its end.
+    /// - The start and end of `range` are equal and both are excluded.
+    pub fn range<Q, R>(&self, range: R) -> Range<K>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+        R: RangeBounds<Q>,
+    {
+        Range(self.0.state().range(range))
+    }
+
+    /// Return the smallest key in this repository, or `None` if it is empty.
+    pub fn first_key(&self) -> Option<&K> {
+        self.0.state().keys().next()
+    }
+
+    /// Return the largest key in this repository, or `None` if it is empty.
+    pub fn last_key(&self) -> Option<&K> {
+        self.0.state().keys().next_back()
+    }
+
+    /// Copy the value at `source` to `dest`.
+    ///
+    /// This is a cheap operation which does not require copying the object itself.
+    ///
+    /// # Errors
+    /// - `Error::NotFound`: There is no value at `source`.
+    /// - `Error::AlreadyExists`: There is already a value at `dest`.
+    pub fn copy<Q>(&mut self, source: &Q, dest: K) -> crate::Result<()>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        if self.0.state().contains_key(dest.borrow()) {
+            return Err(crate::Error::AlreadyExists);
+        }
+        let object_id = *self.0.state().get(source).ok_or(crate::Error::NotFound)?;
+        let new_object_id = self.0.copy(object_id).unwrap();
+        self.0.state_mut().insert(dest, new_object_id);
+        Ok(())
+    }
+
+    /// Verify the integrity of all the data in the repository.
+    ///
+    /// This returns the set of keys of values which are corrupt.
+    ///
+    /// # Errors
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    pub fn verify(&self) -> crate::Result<HashSet<&K>> {
+        let corrupt_keys = self.0.verify()?;
+        Ok(self
+            .0
+            .state()
+            .iter()
+            .filter(|(_, object_id)| corrupt_keys.contains(*object_id))
+            .map(|(key, _)| key)
+            .collect())
+    }
+
+    /// Delete all data in the current instance of the repository.
+    ///
+    /// See [`KeyRepo::clear_instance`] for details.
+    ///
+    /// [`KeyRepo::clear_instance`]: crate::repo::key::KeyRepo::clear_instance
+    pub fn clear_instance(&mut self) {
+        self.0.clear_instance()
+    }
+
+    /// Change the password for this repository.
+    ///
+    /// See [`KeyRepo::change_password`] for details.
+    ///
+    /// [`KeyRepo::change_password`]: crate::repo::key::KeyRepo::change_password
+    pub fn change_password(
+        &mut self,
+        new_password: &[u8],
+        memory_limit: ResourceLimit,
+        operations_limit: ResourceLimit,
+    ) {
+        self.0
+            .change_password(new_password, memory_limit, operations_limit);
+    }
+
+    /// Return this repository's instance ID.
+    pub fn instance(&self) -> InstanceId {
+        self.0.instance()
+    }
+
+    /// Return information about each instance of this repository.
+    ///
+    /// See [`KeyRepo::list_instances`] for details.
+    ///
+    /// [`KeyRepo::list_instances`]: crate::repo::key::KeyRepo::list_instances
+    pub fn list_instances(&self) -> Vec<InstanceSummary> {
+        self.0.list_instances()
+    }
+
+    /// Set the human-readable name of the instance with the given `id` to `name`.
+    ///
+    /// See [`KeyRepo::name_instance`] for details.
+    ///
+    /// [`KeyRepo::name_instance`]: crate::repo::key::KeyRepo::name_instance
+    pub fn name_instance(&mut self, id: InstanceId, name: Option<&str>) -> crate::Result<()> {
+        self.0.name_instance(id, name)
+    }
+
+    /// Return the ID of the instance with the given `name`.
+    ///
+    /// See [`KeyRepo::find_instance`] for details.
+    ///
+    /// [`KeyRepo::find_instance`]: crate::repo::key::KeyRepo::find_instance
+    pub fn find_instance(&self, name: &str) -> Option<InstanceId> {
+        self.0.find_instance(name)
+    }
+
+    /// Remove the instance of the repository with the given `id`.
+    ///
+    /// See [`KeyRepo::remove_instance`] for details.
+    ///
+    /// [`KeyRepo::remove_instance`]: crate::repo::key::KeyRepo::remove_instance
+    pub fn remove_instance(&mut self, id: InstanceId) -> crate::Result<bool> {
+        self.0.remove_instance(id)
+    }
+
+    /// Compute statistics about the repository.
+    ///
+    /// See [`KeyRepo::stats`] for details.
+    ///
+    /// [`KeyRepo::stats`]: crate::repo::key::KeyRepo::stats
+    pub fn stats(&self) -> RepoStats {
+        self.0.stats()
+    }
+
+    /// Compute statistics about the objects in each instance of the repository.
+    ///
+    /// See [`KeyRepo::instance_stats`] for details.
+    ///
+    /// [`KeyRepo::instance_stats`]: crate::repo::key::KeyRepo::instance_stats
+    pub fn instance_stats(&self) -> crate::Result<HashMap<InstanceId, UsageStats>> {
+        self.0.instance_stats()
+    }
+
+    /// Return information about the repository.
+    pub fn info(&self) -> RepoInfo {
+        self.0.info()
+    }
+
+    /// Convert this repository into a [`SharedOrderedValueRepo`] which can be used from multiple
+    /// threads.
+    ///
+    /// [`SharedOrderedValueRepo`]: crate::repo::value::SharedOrderedValueRepo
+    pub fn into_shared(self) -> SharedOrderedValueRepo<K> {
+        SharedOrderedValueRepo::new(self)
+    }
+
+    /// Return the quota for the current instance in bytes.
+    ///
+    /// See [`KeyRepo::quota`] for details.
+    ///
+    /// [`KeyRepo::quota`]: crate::repo::key::KeyRepo::quota
+    pub fn quota(&self) -> Option<u64> {
+        self.0.quota()
+    }
+
+    /// Set the quota for the current instance to `quota` bytes.
+    ///
+    /// See [`KeyRepo::set_quota`] for details.
+    ///
+    /// [`KeyRepo::set_quota`]: crate::repo::key::KeyRepo::set_quota
+    pub fn set_quota(&mut self, quota: Option<u64>) {
+        self.0.set_quota(quota)
+    }
+
+    /// Register `callback` to be called each time `event` happens in this repository.
+    ///
+    /// See [`KeyRepo::on_event`] for details.
+    ///
+    /// [`KeyRepo::on_event`]: crate::repo::key::KeyRepo::on_event
+    pub fn on_event(
+        &self,
+        event: RepoEvent,
+        callback: impl FnMut(&RepoInfo) + Send + 'static,
+    ) -> HookId {
+        self.0.on_event(event, callback)
+    }
+
+    /// Remove the hook with the given `id`.
+    ///
+    /// See [`KeyRepo::remove_hook`] for details.
+    ///
+    /// [`KeyRepo::remove_hook`]: crate::repo::key::KeyRepo::remove_hook
+    pub fn remove_hook(&self, id: HookId) -> bool {
+        self.0.remove_hook(id)
+    }
+
+    /// Return the log of commits to this repository.
+    ///
+    /// See [`KeyRepo::commit_log`] for details.
+    ///
+    /// [`KeyRepo::commit_log`]: crate::repo::key::KeyRepo::commit_log
+    pub fn commit_log(&self) -> &[CommitRecord] {
+        self.0.commit_log()
+    }
+
+    /// Verify the integrity of the commit log.
+    ///
+    /// See [`KeyRepo::verify_commit_log`] for details.
+    ///
+    /// # Errors
+    /// - `Error::Corrupt`: The commit log has been tampered with.
+    ///
+    /// [`KeyRepo::verify_commit_log`]: crate::repo::key::KeyRepo::verify_commit_log
+    pub fn verify_commit_log(&self) -> crate::Result<()> {
+        self.0.verify_commit_log()
+    }
+
+    /// Rewrite the data in the repository according to the given `options`.
+    ///
+    /// See [`KeyRepo::repack`] for details.
+    ///
+    /// [`KeyRepo::repack`]: crate::repo::key::KeyRepo::repack
+    pub fn repack(&mut self, options: RepackOptions) -> crate::Result<()> {
+        self.0.repack(options)
+    }
+
+    /// Change the chunking method used for new data in the repository.
+    ///
+    /// See [`KeyRepo::set_chunking`] for details.
+    ///
+    /// [`KeyRepo::set_chunking`]: crate::repo::key::KeyRepo::set_chunking
+    pub fn set_chunking(&mut self, chunking: Chunking) -> crate::Result<()> {
+        self.0.set_chunking(chunking)
+    }
+
+    /// Rewrite every object in the current instance using the current chunking method.
+    ///
+    /// See [`KeyRepo::rechunk_all`] for details.
+    ///
+    /// [`KeyRepo::rechunk_all`]: crate::repo::key::KeyRepo::rechunk_all
+    pub fn rechunk_all(&mut self) -> crate::Result<()> {
+        self.0.rechunk_all()
+    }
+
+    /// Persist uncommitted changes to the data store without committing them.
+    ///
+    /// See [`KeyRepo::flush`] for details.
+    ///
+    /// [`KeyRepo::flush`]: crate::repo::key::KeyRepo::flush
+    pub fn flush(&mut self) -> crate::Result<()> {
+        self.0.flush()
+    }
+
+    /// Restore the repository to the state it was in when changes were last flushed.
+    ///
+    /// See [`KeyRepo::restore_flushed`] for details.
+    ///
+    /// [`KeyRepo::restore_flushed`]: crate::repo::key::KeyRepo::restore_flushed
+    pub fn restore_flushed(&mut self) -> crate::Result<bool> {
+        self.0.restore_flushed()
+    }
+
+    /// Save the current state of the repository as a checkpoint named `name`.
+    ///
+    /// See [`KeyRepo::create_checkpoint`] for details.
+    ///
+    /// [`KeyRepo::create_checkpoint`]: crate::repo::key::KeyRepo::create_checkpoint
+    pub fn create_checkpoint(&mut self, name: impl Into<String>) -> crate::Result<()> {
+        self.0.create_checkpoint(name)
+    }
+
+    /// Remove the checkpoint named `name` from the repository.
+    ///
+    /// See [`KeyRepo::remove_checkpoint`] for details.
+    ///
+    /// [`KeyRepo::remove_checkpoint`]: crate::repo::key::KeyRepo::remove_checkpoint
+    pub fn remove_checkpoint(&mut self, name: &str) -> bool {
+        self.0.remove_checkpoint(name)
+    }
+
+    /// Return whether there is a checkpoint named `name`.
+    pub fn contains_checkpoint(&self, name: &str) -> bool {
+        self.0.contains_checkpoint(name)
+    }
+
+    /// Return an iterator over the names of the checkpoints in this repository.
+    ///
+    /// See [`KeyRepo::checkpoints`] for details.
+    ///
+    /// [`KeyRepo::checkpoints`]: crate::repo::key::KeyRepo::checkpoints
+    pub fn checkpoints(&self) -> Checkpoints {
+        self.0.checkpoints()
+    }
+
+    /// Restore the repository to the state it was in when the checkpoint `name` was created.
+    ///
+    /// See [`KeyRepo::restore_checkpoint`] for details.
+    ///
+    /// [`KeyRepo::restore_checkpoint`]: crate::repo::key::KeyRepo::restore_checkpoint
+    pub fn restore_checkpoint(&mut self, name: &str) -> crate::Result<()> {
+        self.0.restore_checkpoint(name)
+    }
+
+    /// Return the number of previous commits which the repository can be restored to.
+    ///
+    /// See [`KeyRepo::previous_commits`] for details.
+    ///
+    /// [`KeyRepo::previous_commits`]: crate::repo::key::KeyRepo::previous_commits
+    pub fn previous_commits(&self) -> usize {
+        self.0.previous_commits()
+    }
+
+    /// Restore the repository to the state it was in `age` commits before the most recent one.
+    ///
+    /// See [`KeyRepo::restore_commit`] for details.
+    ///
+    /// [`KeyRepo::restore_commit`]: crate::repo::key::KeyRepo::restore_commit
+    pub fn restore_commit(&mut self, age: usize) -> crate::Result<()> {
+        self.0.restore_commit(age)
+    }
+}
+
+impl<K: Key + Ord> Commit for OrderedValueRepo<K> {
+    fn commit(&mut self) -> crate::Result<()> {
+        self.0.commit()
+    }
+
+    fn rollback(&mut self) -> crate::Result<()> {
+        self.0.rollback()
+    }
+
+    fn clean(&mut self) -> crate::Result<()> {
+        self.0.clean()
+    }
+}
+
+impl<K: Key + Ord> RestoreSavepoint for OrderedValueRepo<K> {
+    type Restore = <StateRepo<RepoState<K>> as RestoreSavepoint>::Restore;
+
+    fn savepoint(&mut self) -> crate::Result<Savepoint> {
+        self.0.savepoint()
+    }
+
+    fn start_restore(&mut self, savepoint: &Savepoint) -> crate::Result<Self::Restore> {
+        self.0.start_restore(savepoint)
+    }
+
+    fn finish_restore(&mut self, restore: Self::Restore) -> bool {
+        self.0.finish_restore(restore)
+    }
+}
+
+impl<K: Key + Ord> ValueStore for OrderedValueRepo<K> {
+    type ValueKey = K;
+    type Format = MessagePack;
+    type State = RepoState<K>;
+
+    fn state_repo(&self) -> &StateRepo<Self::State> {
+        &self.0
+    }
+
+    fn state_repo_mut(&mut self) -> &mut StateRepo<Self::State> {
+        &mut self.0
+    }
+}
+
+impl<K: Key + Ord> Unlock for OrderedValueRepo<K> {
+    fn unlock(&self) -> crate::Result<()> {
+        self.0.unlock()
+    }
+
+    fn is_locked(&self) -> crate::Result<bool> {
+        self.0.is_locked()
+    }
+
+    fn context(&self) -> crate::Result<Vec<u8>> {
+        self.0.context()
+    }
+
+    fn update_context(&self, context: &[u8]) -> crate::Result<()> {
+        self.0.update_context(context)
+    }
+}
@@ -2,44 +2,55 @@ use std::borrow::Borrow;
 use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
 use std::hash::Hash;
+use std::io::Read;
+use std::marker::PhantomData;
 
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 use uuid::uuid;
 
 use super::iter::Keys;
+use super::serialized::SerializedValue;
+use super::shared::{SharedValueRepo, ValueStore};
 use crate::repo::{
     key::{Key, KeyRepo},
     state::{ObjectKey, StateRepo},
-    Commit, InstanceId, OpenRepo, RepoInfo, RepoStats, ResourceLimit, RestoreSavepoint, Savepoint,
-    Unlock, VersionId,
+    Checkpoints, ChunkId, Chunking, Commit, CommitRecord, Format, HookId, InstanceId,
+    InstanceSummary, MergeConflict, MergeReport, MessagePack, OpenRepo, RepackOptions, RepoEvent,
+    RepoInfo, RepoStats, ResourceLimit, RestoreSavepoint, Savepoint, Unlock, UsageStats, VersionId,
 };
 
 type RepoState<K> = HashMap<K, ObjectKey>;
 
 /// A persistent, heterogeneous, map-like collection.
 ///
+/// Values are serialized using the [`Format`] `F`, which defaults to [`MessagePack`].
+///
 /// See [`crate::repo::value`] for more information.
+///
+/// [`Format`]: crate::repo::Format
+/// [`MessagePack`]: crate::repo::MessagePack
 #[derive(Debug)]
-pub struct ValueRepo<K: Key>(StateRepo<RepoState<K>>);
+pub struct ValueRepo<K: Key, F: Format = MessagePack>(StateRepo<RepoState<K>>, PhantomData<F>);
 
-impl<K: Key> OpenRepo for ValueRepo<K> {
+impl<K: Key, F: Format> OpenRepo for ValueRepo<K, F> {
     type Key = <StateRepo<RepoState<K>> as OpenRepo>::Key;
 
-    const VERSION_ID: VersionId = VersionId::new(uuid!("4db4c84c-cfc7-11eb-9e06-77121c3277f7"));
+    const VERSION_ID: VersionId =
+        VersionId::new(uuid!("4db4c84c-cfc7-11eb-9e06-77121c3277f7")).with_format::<F>();
 
     fn open_repo(repo: KeyRepo<Self::Key>) -> crate::Result<Self>
     where
         Self: Sized,
     {
-        Ok(Self(StateRepo::open_repo(repo)?))
+        Ok(Self(StateRepo::open_repo(repo)?, PhantomData))
     }
 
     fn create_repo(repo: KeyRepo<Self::Key>) -> crate::Result<Self>
     where
         Self: Sized,
     {
-        Ok(Self(StateRepo::create_repo(repo)?))
+        Ok(Self(StateRepo::create_repo(repo)?, PhantomData))
     }
 
     fn into_repo(self) -> crate::Result<KeyRepo<Self::Key>> {
@@ -47,7 +58,7 @@ impl<K: Key> OpenRepo for ValueRepo<K> {
     }
 }
 
-impl<K: Key> ValueRepo<K> {
+impl<K: Key, F: Format> ValueRepo<K, F> {
     /// Return whether the given `key` exists in this repository.
     pub fn contains<Q>(&self, key: &Q) -> bool
     where
@@ -61,28 +72,169 @@ impl<K: Key> ValueRepo<K> {
     ///
     /// If `key` is already in the repository, its value is replaced.
     ///
+    /// The value is written to the repository as it's serialized, so its serialized form is never
+    /// buffered in memory in its entirety.
+    ///
     /// # Errors
     /// - `Error::Serialize`: The `value` could not be serialized.
     /// - `Error::InvalidData`: Ciphertext verification failed.
     /// - `Error::Store`: An error occurred with the data store.
     /// - `Error::Io`: An I/O error occurred.
     pub fn insert<V: Serialize>(&mut self, key: K, value: &V) -> crate::Result<()> {
+        let object_id = self.write_value(value)?;
+
+        if let Some(prev_object_id) = self.0.state_mut().insert(key, object_id) {
+            self.0.remove(prev_object_id);
+        }
+
+        Ok(())
+    }
+
+    /// Insert a new key-value pair only if `key` is not already in the repository.
+    ///
+    /// This returns `true` if the value was inserted or `false` if `key` already had a value, in
+    /// which case the existing value is left unchanged.
+    ///
+    /// # Errors
+    /// - `Error::Serialize`: The `value` could not be serialized.
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    pub fn insert_if_absent<V: Serialize>(&mut self, key: K, value: &V) -> crate::Result<bool> {
+        if self.0.state().contains_key(&key) {
+            return Ok(false);
+        }
+
+        let object_id = self.write_value(value)?;
+        self.0.state_mut().insert(key, object_id);
+
+        Ok(true)
+    }
+
+    /// Replace the value associated with `key` with `new` only if its current value is `expected`.
+    ///
+    /// This returns `true` if the value was replaced or `false` if `key` has no value or its value
+    /// is not `expected`. Values are compared by their serialized form, so `expected` must
+    /// serialize to the same bytes as the current value for them to be considered equal.
+    ///
+    /// This is only atomic with respect to this `ValueRepo` value, which can't be changed by
+    /// anything else between the comparison and the replacement. To do this from multiple threads,
+    /// use [`SharedValueRepo::compare_and_swap`]. This doesn't detect changes made through other
+    /// `ValueRepo` values or other processes which share the same data store, and it doesn't stop
+    /// those changes from being overwritten when this repository is committed.
+    ///
+    /// # Errors
+    /// - `Error::Serialize`: The `expected` or `new` value could not be serialized.
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    ///
+    /// [`SharedValueRepo::compare_and_swap`]: crate::repo::value::SharedValueRepo::compare_and_swap
+    pub fn compare_and_swap<Q, V>(&mut self, key: &Q, expected: &V, new: &V) -> crate::Result<bool>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        V: Serialize,
+    {
+        let object_id = match self.0.state().get(key) {
+            Some(object_id) => *object_id,
+            None => return Ok(false),
+        };
+
+        let expected_value = F::to_vec(expected)?;
+        if !self.value_equals(object_id, &expected_value)? {
+            return Ok(false);
+        }
+
+        let new_object_id = self.write_value(new)?;
+        *self.0.state_mut().get_mut(key).unwrap() = new_object_id;
+        self.0.remove(object_id);
+
+        Ok(true)
+    }
+
+    /// Serialize `value` to a new object and return its key.
+    ///
+    /// If this returns `Err`, the new object is removed.
+    fn write_value<V: Serialize>(&mut self, value: &V) -> crate::Result<ObjectKey> {
         let object_id = self.0.create();
         let mut object = self.0.object(object_id).unwrap();
-        let result = object.serialize(value);
+        let result = object.serialize_with::<F, V>(value);
         drop(object);
         if let Err(error) = result {
             self.0.remove(object_id);
             return Err(error);
         }
+        Ok(object_id)
+    }
 
-        if let Some(prev_object_id) = self.0.state_mut().insert(key, object_id) {
-            self.0.remove(prev_object_id);
+    /// Return whether the contents of the object `object_id` are equal to `serialized_value`.
+    fn value_equals(&self, object_id: ObjectKey, serialized_value: &[u8]) -> crate::Result<bool> {
+        let mut object = self.0.object(object_id).unwrap();
+        if object.size()? != serialized_value.len() as u64 {
+            return Ok(false);
+        }
+        let mut current_value = Vec::with_capacity(serialized_value.len());
+        object.read_to_end(&mut current_value)?;
+        Ok(current_value == serialized_value)
+    }
+
+    /// Insert several key-value pairs at once.
+    ///
+    /// This is equivalent to calling [`insert`] for each pair, but it amortizes the cost of
+    /// writing the values to the data store. See [`KeyRepo::insert_batch`] for details.
+    ///
+    /// If a key is already in the repository, its value is replaced. If this returns `Err`, none
+    /// of the values are inserted.
+    ///
+    /// # Errors
+    /// - `Error::Serialize`: One of the values could not be serialized.
+    /// - `Error::QuotaExceeded`: Inserting the values would exceed the quota for this instance.
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    ///
+    /// [`insert`]: crate::repo::value::ValueRepo::insert
+    /// [`KeyRepo::insert_batch`]: crate::repo::key::KeyRepo::insert_batch
+    pub fn insert_batch<V: Serialize>(
+        &mut self,
+        values: impl IntoIterator<Item = (K, V)>,
+    ) -> crate::Result<()> {
+        let mut keys = Vec::new();
+        let mut serialized_values = Vec::new();
+        for (key, value) in values {
+            serialized_values.push(F::to_vec(&value)?);
+            keys.push(key);
+        }
+
+        let object_ids = self.0.create_batch(serialized_values)?;
+
+        for (key, object_id) in keys.into_iter().zip(object_ids) {
+            if let Some(prev_object_id) = self.0.state_mut().insert(key, object_id) {
+                self.0.remove(prev_object_id);
+            }
         }
 
         Ok(())
     }
 
+    /// Remove the values associated with each of the given `keys` from the repository.
+    ///
+    /// This returns the number of values which were removed. Keys which don't exist in the
+    /// repository are ignored.
+    ///
+    /// The space used by the given values isn't reclaimed in the backing data store until changes
+    /// are committed and [`Commit::clean`] is called.
+    ///
+    /// [`Commit::clean`]: crate::repo::Commit::clean
+    pub fn remove_batch<'a, Q>(&mut self, keys: impl IntoIterator<Item = &'a Q>) -> usize
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized + 'a,
+    {
+        keys.into_iter().filter(|key| self.remove(*key)).count()
+    }
+
     /// Remove the value associated with `key` from the repository.
     ///
     /// This returns `true` if the value was removed or `false` if it didn't exist.
@@ -107,6 +259,9 @@ impl<K: Key> ValueRepo<K> {
 
     /// Return the value associated with `key`.
     ///
+    /// The value is deserialized as it's read from the repository, so its serialized form is never
+    /// buffered in memory in its entirety.
+    ///
     /// # Errors
     /// - `Error::NotFound`: There is no value associated with `key`.
     /// - `Error::Deserialize`: The value could not be deserialized.
@@ -121,7 +276,7 @@ impl<K: Key> ValueRepo<K> {
     {
         let object_id = self.0.state().get(key).ok_or(crate::Error::NotFound)?;
         let mut object = self.0.object(*object_id).unwrap();
-        object.deserialize()
+        object.deserialize_with::<F, V>()
     }
 
     /// Return an iterator of all the keys in this repository.
@@ -150,6 +305,110 @@ impl<K: Key> ValueRepo<K> {
         Ok(())
     }
 
+    /// Merge the changes made to the current instance of `other` into the current instance of
+    /// this repository.
+    ///
+    /// This is a three-way merge like [`KeyRepo::merge`], except that values are compared by
+    /// their keys in this repository rather than by the keys of the objects which store them.
+    /// `ancestor` is a repository which both this repository and `other` were derived from. Each
+    /// value is compared between the three repositories:
+    ///
+    /// - If a value was only changed in `other`, the change is applied to this repository. This
+    /// includes values which were inserted into or removed from `other`.
+    /// - If a value was only changed in this repository, or it was changed the same way in both
+    /// repositories, it is left as it is.
+    /// - If a value was changed differently in both repositories, it is a conflict. The value is
+    /// left as it is in this repository, and the conflict is included in the returned
+    /// [`MergeReport`] so it can be resolved by the caller.
+    ///
+    /// Values are compared by their chunks without reading them, so all three repositories should
+    /// have the same [`Chunking`] configuration; otherwise, identical values may be reported as
+    /// conflicts.
+    ///
+    /// Changes to this repository are not persisted until they are committed. If this returns
+    /// `Err`, none of the changes are merged.
+    ///
+    /// # Errors
+    /// - `Error::QuotaExceeded`: Merging the values would exceed the quota for this instance.
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    ///
+    /// [`KeyRepo::merge`]: crate::repo::key::KeyRepo::merge
+    /// [`MergeReport`]: crate::repo::MergeReport
+    /// [`Chunking`]: crate::repo::Chunking
+    pub fn merge(
+        &mut self,
+        ancestor: &ValueRepo<K, F>,
+        other: &ValueRepo<K, F>,
+    ) -> crate::Result<MergeReport<K>> {
+        let keys = self
+            .0
+            .state()
+            .keys()
+            .chain(ancestor.0.state().keys())
+            .chain(other.0.state().keys())
+            .cloned()
+            .collect::<HashSet<_>>();
+
+        let mut report = MergeReport::default();
+        let mut updated_keys = Vec::new();
+        let mut updated_values = Vec::new();
+
+        for key in keys {
+            let base = ancestor.value_contents(&key)?;
+            let ours = self.value_contents(&key)?;
+            let theirs = other.value_contents(&key)?;
+
+            if ours == theirs || theirs == base {
+                continue;
+            }
+
+            if ours != base {
+                let conflict =
+                    MergeConflict::from_presence(base.is_some(), ours.is_some(), theirs.is_some());
+                report.conflicts.insert(key, conflict);
+                continue;
+            }
+
+            if theirs.is_some() {
+                updated_values.push(other.get_serialized(&key)?.0);
+                updated_keys.push(key);
+            } else {
+                report.removed.insert(key);
+            }
+        }
+
+        let object_ids = self.0.create_batch(updated_values)?;
+
+        for (key, object_id) in updated_keys.into_iter().zip(object_ids) {
+            if let Some(prev_object_id) = self.0.state_mut().insert(key.clone(), object_id) {
+                self.0.remove(prev_object_id);
+            }
+            report.updated.insert(key);
+        }
+
+        for key in &report.removed {
+            self.remove(key);
+        }
+
+        Ok(report)
+    }
+
+    /// Return the size and chunks of the value associated with `key`, or `None` if there is no
+    /// such value.
+    ///
+    /// Unlike content IDs, these can be compared between repositories.
+    fn value_contents(&self, key: &K) -> crate::Result<Option<(u64, Vec<ChunkId>)>> {
+        match self.0.state().get(key) {
+            Some(object_id) => {
+                let content_id = self.0.object(*object_id).unwrap().content_id()?;
+                Ok(Some((content_id.size(), content_id.chunk_ids())))
+            }
+            None => Ok(None),
+        }
+    }
+
     /// Verify the integrity of all the data in the repository.
     ///
     /// This returns the set of keys of values which are corrupt.
@@ -198,6 +457,42 @@ impl<K: Key> ValueRepo<K> {
         self.0.instance()
     }
 
+    /// Return information about each instance of this repository.
+    ///
+    /// See [`KeyRepo::list_instances`] for details.
+    ///
+    /// [`KeyRepo::list_instances`]: crate::repo::key::KeyRepo::list_instances
+    pub fn list_instances(&self) -> Vec<InstanceSummary> {
+        self.0.list_instances()
+    }
+
+    /// Set the human-readable name of the instance with the given `id` to `name`.
+    ///
+    /// See [`KeyRepo::name_instance`] for details.
+    ///
+    /// [`KeyRepo::name_instance`]: crate::repo::key::KeyRepo::name_instance
+    pub fn name_instance(&mut self, id: InstanceId, name: Option<&str>) -> crate::Result<()> {
+        self.0.name_instance(id, name)
+    }
+
+    /// Return the ID of the instance with the given `name`.
+    ///
+    /// See [`KeyRepo::find_instance`] for details.
+    ///
+    /// [`KeyRepo::find_instance`]: crate::repo::key::KeyRepo::find_instance
+    pub fn find_instance(&self, name: &str) -> Option<InstanceId> {
+        self.0.find_instance(name)
+    }
+
+    /// Remove the instance of the repository with the given `id`.
+    ///
+    /// See [`KeyRepo::remove_instance`] for details.
+    ///
+    /// [`KeyRepo::remove_instance`]: crate::repo::key::KeyRepo::remove_instance
+    pub fn remove_instance(&mut self, id: InstanceId) -> crate::Result<bool> {
+        self.0.remove_instance(id)
+    }
+
     /// Compute statistics about the repository.
     ///
     /// See [`KeyRepo::stats`] for details.
@@ -207,13 +502,224 @@ impl<K: Key> ValueRepo<K> {
         self.0.stats()
     }
 
+    /// Compute statistics about the objects in each instance of the repository.
+    ///
+    /// See [`KeyRepo::instance_stats`] for details.
+    ///
+    /// [`KeyRepo::instance_stats`]: crate::repo::key::KeyRepo::instance_stats
+    pub fn instance_stats(&self) -> crate::Result<HashMap<InstanceId, UsageStats>> {
+        self.0.instance_stats()
+    }
+
     /// Return information about the repository.
     pub fn info(&self) -> RepoInfo {
         self.0.info()
     }
+
+    /// Convert this repository into a [`SharedValueRepo`] which can be used from multiple threads.
+    ///
+    /// [`SharedValueRepo`]: crate::repo::value::SharedValueRepo
+    pub fn into_shared(self) -> SharedValueRepo<K, F> {
+        SharedValueRepo::new(self)
+    }
+
+    /// Return the quota for the current instance in bytes.
+    ///
+    /// See [`KeyRepo::quota`] for details.
+    ///
+    /// [`KeyRepo::quota`]: crate::repo::key::KeyRepo::quota
+    pub fn quota(&self) -> Option<u64> {
+        self.0.quota()
+    }
+
+    /// Set the quota for the current instance to `quota` bytes.
+    ///
+    /// See [`KeyRepo::set_quota`] for details.
+    ///
+    /// [`KeyRepo::set_quota`]: crate::repo::key::KeyRepo::set_quota
+    pub fn set_quota(&mut self, quota: Option<u64>) {
+        self.0.set_quota(quota)
+    }
+
+    /// Register `callback` to be called each time `event` happens in this repository.
+    ///
+    /// See [`KeyRepo::on_event`] for details.
+    ///
+    /// [`KeyRepo::on_event`]: crate::repo::key::KeyRepo::on_event
+    pub fn on_event(
+        &self,
+        event: RepoEvent,
+        callback: impl FnMut(&RepoInfo) + Send + 'static,
+    ) -> HookId {
+        self.0.on_event(event, callback)
+    }
+
+    /// Remove the hook with the given `id`.
+    ///
+    /// See [`KeyRepo::remove_hook`] for details.
+    ///
+    /// [`KeyRepo::remove_hook`]: crate::repo::key::KeyRepo::remove_hook
+    pub fn remove_hook(&self, id: HookId) -> bool {
+        self.0.remove_hook(id)
+    }
+
+    /// Return the log of commits to this repository.
+    ///
+    /// See [`KeyRepo::commit_log`] for details.
+    ///
+    /// [`KeyRepo::commit_log`]: crate::repo::key::KeyRepo::commit_log
+    pub fn commit_log(&self) -> &[CommitRecord] {
+        self.0.commit_log()
+    }
+
+    /// Verify the integrity of the commit log.
+    ///
+    /// See [`KeyRepo::verify_commit_log`] for details.
+    ///
+    /// # Errors
+    /// - `Error::Corrupt`: The commit log has been tampered with.
+    ///
+    /// [`KeyRepo::verify_commit_log`]: crate::repo::key::KeyRepo::verify_commit_log
+    pub fn verify_commit_log(&self) -> crate::Result<()> {
+        self.0.verify_commit_log()
+    }
+
+    /// Rewrite the data in the repository according to the given `options`.
+    ///
+    /// See [`KeyRepo::repack`] for details.
+    ///
+    /// [`KeyRepo::repack`]: crate::repo::key::KeyRepo::repack
+    pub fn repack(&mut self, options: RepackOptions) -> crate::Result<()> {
+        self.0.repack(options)
+    }
+
+    /// Change the chunking method used for new data in the repository.
+    ///
+    /// See [`KeyRepo::set_chunking`] for details.
+    ///
+    /// [`KeyRepo::set_chunking`]: crate::repo::key::KeyRepo::set_chunking
+    pub fn set_chunking(&mut self, chunking: Chunking) -> crate::Result<()> {
+        self.0.set_chunking(chunking)
+    }
+
+    /// Rewrite every object in the current instance using the current chunking method.
+    ///
+    /// See [`KeyRepo::rechunk_all`] for details.
+    ///
+    /// [`KeyRepo::rechunk_all`]: crate::repo::key::KeyRepo::rechunk_all
+    pub fn rechunk_all(&mut self) -> crate::Result<()> {
+        self.0.rechunk_all()
+    }
+
+    /// Persist uncommitted changes to the data store without committing them.
+    ///
+    /// See [`KeyRepo::flush`] for details.
+    ///
+    /// [`KeyRepo::flush`]: crate::repo::key::KeyRepo::flush
+    pub fn flush(&mut self) -> crate::Result<()> {
+        self.0.flush()
+    }
+
+    /// Restore the repository to the state it was in when changes were last flushed.
+    ///
+    /// See [`KeyRepo::restore_flushed`] for details.
+    ///
+    /// [`KeyRepo::restore_flushed`]: crate::repo::key::KeyRepo::restore_flushed
+    pub fn restore_flushed(&mut self) -> crate::Result<bool> {
+        self.0.restore_flushed()
+    }
+
+    /// Save the current state of the repository as a checkpoint named `name`.
+    ///
+    /// See [`KeyRepo::create_checkpoint`] for details.
+    ///
+    /// [`KeyRepo::create_checkpoint`]: crate::repo::key::KeyRepo::create_checkpoint
+    pub fn create_checkpoint(&mut self, name: impl Into<String>) -> crate::Result<()> {
+        self.0.create_checkpoint(name)
+    }
+
+    /// Remove the checkpoint named `name` from the repository.
+    ///
+    /// See [`KeyRepo::remove_checkpoint`] for details.
+    ///
+    /// [`KeyRepo::remove_checkpoint`]: crate::repo::key::KeyRepo::remove_checkpoint
+    pub fn remove_checkpoint(&mut self, name: &str) -> bool {
+        self.0.remove_checkpoint(name)
+    }
+
+    /// Return whether there is a checkpoint named `name`.
+    pub fn contains_checkpoint(&self, name: &str) -> bool {
+        self.0.contains_checkpoint(name)
+    }
+
+    /// Return an iterator over the names of the checkpoints in this repository.
+    ///
+    /// See [`KeyRepo::checkpoints`] for details.
+    ///
+    /// [`KeyRepo::checkpoints`]: crate::repo::key::KeyRepo::checkpoints
+    pub fn checkpoints(&self) -> Checkpoints {
+        self.0.checkpoints()
+    }
+
+    /// Restore the repository to the state it was in when the checkpoint `name` was created.
+    ///
+    /// See [`KeyRepo::restore_checkpoint`] for details.
+    ///
+    /// [`KeyRepo::restore_checkpoint`]: crate::repo::key::KeyRepo::restore_checkpoint
+    pub fn restore_checkpoint(&mut self, name: &str) -> crate::Result<()> {
+        self.0.restore_checkpoint(name)
+    }
+
+    /// Return the number of previous commits which the repository can be restored to.
+    ///
+    /// See [`KeyRepo::previous_commits`] for details.
+    ///
+    /// [`KeyRepo::previous_commits`]: crate::repo::key::KeyRepo::previous_commits
+    pub fn previous_commits(&self) -> usize {
+        self.0.previous_commits()
+    }
+
+    /// Restore the repository to the state it was in `age` commits before the most recent one.
+    ///
+    /// See [`KeyRepo::restore_commit`] for details.
+    ///
+    /// [`KeyRepo::restore_commit`]: crate::repo::key::KeyRepo::restore_commit
+    pub fn restore_commit(&mut self, age: usize) -> crate::Result<()> {
+        self.0.restore_commit(age)
+    }
+}
+
+impl<K: Key> ValueRepo<K, MessagePack> {
+    /// Return the serialized value associated with `key`.
+    ///
+    /// Unlike [`get`], this reads the entire serialized value into memory, which allows it to be
+    /// deserialized into types which borrow from it. See [`SerializedValue`] for details.
+    ///
+    /// This is only available for repositories which serialize values with [`MessagePack`].
+    ///
+    /// # Errors
+    /// - `Error::NotFound`: There is no value associated with `key`.
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    ///
+    /// [`get`]: crate::repo::value::ValueRepo::get
+    /// [`SerializedValue`]: crate::repo::value::SerializedValue
+    /// [`MessagePack`]: crate::repo::MessagePack
+    pub fn get_serialized<Q>(&self, key: &Q) -> crate::Result<SerializedValue>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let object_id = self.0.state().get(key).ok_or(crate::Error::NotFound)?;
+        let mut object = self.0.object(*object_id).unwrap();
+        let mut serialized_value = Vec::new();
+        object.read_to_end(&mut serialized_value)?;
+        Ok(SerializedValue(serialized_value))
+    }
 }
 
-impl<K: Key> Commit for ValueRepo<K> {
+impl<K: Key, F: Format> Commit for ValueRepo<K, F> {
     fn commit(&mut self) -> crate::Result<()> {
         self.0.commit()
     }
@@ -227,7 +733,7 @@ impl<K: Key> Commit for ValueRepo<K> {
     }
 }
 
-impl<K: Key> RestoreSavepoint for ValueRepo<K> {
+impl<K: Key, F: Format> RestoreSavepoint for ValueRepo<K, F> {
     type Restore = <StateRepo<RepoState<K>> as RestoreSavepoint>::Restore;
 
     fn savepoint(&mut self) -> crate::Result<Savepoint> {
@@ -243,7 +749,21 @@ impl<K: Key> RestoreSavepoint for ValueRepo<K> {
     }
 }
 
-impl<K: Key> Unlock for ValueRepo<K> {
+impl<K: Key, F: Format> ValueStore for ValueRepo<K, F> {
+    type ValueKey = K;
+    type Format = F;
+    type State = RepoState<K>;
+
+    fn state_repo(&self) -> &StateRepo<Self::State> {
+        &self.0
+    }
+
+    fn state_repo_mut(&mut self) -> &mut StateRepo<Self::State> {
+        &mut self.0
+    }
+}
+
+impl<K: Key, F: Format> Unlock for ValueRepo<K, F> {
     fn unlock(&self) -> crate::Result<()> {
         self.0.unlock()
     }
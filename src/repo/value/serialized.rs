@@ -0,0 +1,38 @@
+use serde::Deserialize;
+
+/// A serialized value read from a [`ValueRepo`] or [`OrderedValueRepo`].
+///
+/// This value holds the serialized bytes of a value in memory so that it can be deserialized
+/// into types which borrow from it. Deserializing a type which contains `&str` or `&[u8]` fields
+/// from a `SerializedValue` doesn't copy those fields, which avoids a second allocation for large
+/// strings and byte buffers.
+///
+/// [`ValueRepo`]: crate::repo::value::ValueRepo
+/// [`OrderedValueRepo`]: crate::repo::value::OrderedValueRepo
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SerializedValue(pub(super) Vec<u8>);
+
+impl SerializedValue {
+    /// Deserialize a value which may borrow from this serialized value.
+    ///
+    /// # Errors
+    /// - `Error::Deserialize`: The value could not be deserialized.
+    pub fn deserialize<'de, V: Deserialize<'de>>(&'de self) -> crate::Result<V> {
+        rmp_serde::from_slice(&self.0).map_err(|_| crate::Error::Deserialize)
+    }
+
+    /// Return the serialized bytes of this value.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Return the size of this serialized value in bytes.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Return whether this serialized value is empty.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
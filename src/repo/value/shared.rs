@@ -0,0 +1,890 @@
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, HashSet};
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::io::Read;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock, RwLockWriteGuard};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use static_assertions::assert_impl_all;
+
+use super::ordered::OrderedValueRepo;
+use super::repository::ValueRepo;
+use super::serialized::SerializedValue;
+use crate::repo::{
+    key::Key,
+    state::{ObjectKey, StateRepo},
+    Commit, Format, InstanceId, MessagePack, RepoInfo, RepoStats, RestoreSavepoint, Savepoint,
+    Unlock,
+};
+
+/// The number of shards the key map of a shared value repository is split into.
+const SHARD_COUNT: usize = 16;
+
+/// A shard of the key map of a shared value repository.
+type Shard<K> = HashMap<K, ObjectKey>;
+
+/// A repository which stores each of its values in a separate object in a `StateRepo`.
+///
+/// This is implemented by the repository types which can be wrapped by a `SharedValues`.
+pub(super) trait ValueStore: Commit + RestoreSavepoint + Unlock {
+    /// The type of the keys in the repository.
+    type ValueKey: Key;
+
+    /// The format used to serialize values.
+    type Format: Format;
+
+    /// The map of keys to the objects which store their values.
+    type State: Serialize
+        + DeserializeOwned
+        + Default
+        + IntoIterator<Item = (Self::ValueKey, ObjectKey)>
+        + FromIterator<(Self::ValueKey, ObjectKey)>;
+
+    /// Return a reference to the backing `StateRepo`.
+    fn state_repo(&self) -> &StateRepo<Self::State>;
+
+    /// Return a mutable reference to the backing `StateRepo`.
+    fn state_repo_mut(&mut self) -> &mut StateRepo<Self::State>;
+}
+
+/// The state shared between all clones of a `SharedValues`.
+#[derive(Debug)]
+struct SharedState<R: ValueStore> {
+    /// The backing repository.
+    ///
+    /// The key map of this repository is empty except while an operation which requires the whole
+    /// key map is in progress.
+    repo: Mutex<R>,
+
+    /// The key map, split into shards by the hash of the key.
+    shards: Vec<RwLock<Shard<R::ValueKey>>>,
+
+    /// The hasher used to assign keys to shards.
+    hasher: RandomState,
+
+    /// Whether the key map has changed since it was last moved into `repo`.
+    changed: AtomicBool,
+}
+
+/// The implementation of the shared handles for the value repository types.
+///
+/// This works like a `SharedKeyRepo`. The key map is split into shards which are each protected by
+/// their own lock. Values are serialized and written to the data store without holding the lock on
+/// the backing repository, which is only locked to create and remove objects.
+///
+/// Locks must always be acquired in this order to avoid deadlocks: shards, then the backing
+/// repository.
+#[derive(Debug)]
+pub(super) struct SharedValues<R: ValueStore>(Arc<SharedState<R>>);
+
+impl<R: ValueStore> Clone for SharedValues<R> {
+    fn clone(&self) -> Self {
+        Self(Arc::clone(&self.0))
+    }
+}
+
+impl<R: ValueStore> SharedValues<R> {
+    /// Create a new `SharedValues` from the given `repo`.
+    fn new(mut repo: R) -> Self {
+        let hasher = RandomState::new();
+        let mut shards = (0..SHARD_COUNT)
+            .map(|_| HashMap::new())
+            .collect::<Vec<Shard<R::ValueKey>>>();
+        let state = repo
+            .state_repo_mut()
+            .replace_state(R::State::default(), false);
+        for (key, object_id) in state {
+            shards[shard_index(&hasher, &key)].insert(key, object_id);
+        }
+
+        Self(Arc::new(SharedState {
+            repo: Mutex::new(repo),
+            shards: shards.into_iter().map(RwLock::new).collect(),
+            hasher,
+            changed: AtomicBool::new(false),
+        }))
+    }
+
+    /// Return the shard which contains the given `key`.
+    fn shard<Q>(&self, key: &Q) -> &RwLock<Shard<R::ValueKey>>
+    where
+        Q: Hash + ?Sized,
+    {
+        &self.0.shards[shard_index(&self.0.hasher, key)]
+    }
+
+    /// Call `block` with the backing repository with its full key map.
+    ///
+    /// This blocks all other operations on the repository until `block` returns.
+    fn with_repo<T>(&self, block: impl FnOnce(&mut R) -> T) -> T {
+        let mut shards = self
+            .0
+            .shards
+            .iter()
+            .map(|shard| shard.write().unwrap())
+            .collect::<Vec<RwLockWriteGuard<Shard<R::ValueKey>>>>();
+        let mut repo = self.0.repo.lock().unwrap();
+
+        let state = shards.iter_mut().flat_map(|shard| shard.drain()).collect();
+        let changed = self.0.changed.swap(false, Ordering::SeqCst);
+        repo.state_repo_mut().replace_state(state, changed);
+        let result = block(&mut *repo);
+        let state = repo
+            .state_repo_mut()
+            .replace_state(R::State::default(), false);
+        for (key, object_id) in state {
+            shards[shard_index(&self.0.hasher, &key)].insert(key, object_id);
+        }
+
+        result
+    }
+
+    /// Serialize `value` to a new object and return its key.
+    ///
+    /// The backing repository is only locked while the object is created, not while the value is
+    /// written. If this returns `Err`, the new object is removed.
+    fn write_value<V: Serialize>(&self, value: &V) -> crate::Result<ObjectKey> {
+        let (object_id, mut object) = {
+            let mut repo = self.0.repo.lock().unwrap();
+            let object_id = repo.state_repo_mut().create();
+            (object_id, repo.state_repo().object(object_id).unwrap())
+        };
+        let result = object.serialize_with::<R::Format, V>(value);
+        drop(object);
+        if let Err(error) = result {
+            self.remove_object(object_id);
+            return Err(error);
+        }
+        Ok(object_id)
+    }
+
+    /// Remove the object with the given `object_id` from the backing repository.
+    fn remove_object(&self, object_id: ObjectKey) {
+        self.0
+            .repo
+            .lock()
+            .unwrap()
+            .state_repo_mut()
+            .remove(object_id);
+    }
+
+    /// Read the contents of the object with the given `object_id`.
+    fn read_object(&self, object_id: ObjectKey) -> crate::Result<Vec<u8>> {
+        let mut object = self
+            .0
+            .repo
+            .lock()
+            .unwrap()
+            .state_repo()
+            .object(object_id)
+            .unwrap();
+        let mut contents = Vec::new();
+        object.read_to_end(&mut contents)?;
+        Ok(contents)
+    }
+
+    fn contains<Q>(&self, key: &Q) -> bool
+    where
+        R::ValueKey: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.shard(key).read().unwrap().contains_key(key)
+    }
+
+    fn insert<V: Serialize>(&self, key: R::ValueKey, value: &V) -> crate::Result<()> {
+        let object_id = self.write_value(value)?;
+
+        let mut shard = self.shard(&key).write().unwrap();
+        self.0.changed.store(true, Ordering::SeqCst);
+        if let Some(prev_object_id) = shard.insert(key, object_id) {
+            self.remove_object(prev_object_id);
+        }
+
+        Ok(())
+    }
+
+    fn insert_if_absent<V: Serialize>(&self, key: R::ValueKey, value: &V) -> crate::Result<bool> {
+        if self.contains(&key) {
+            return Ok(false);
+        }
+
+        let object_id = self.write_value(value)?;
+
+        // Another thread may have inserted a value while this one was being written.
+        let mut shard = self.shard(&key).write().unwrap();
+        if shard.contains_key(&key) {
+            self.remove_object(object_id);
+            return Ok(false);
+        }
+        self.0.changed.store(true, Ordering::SeqCst);
+        shard.insert(key, object_id);
+
+        Ok(true)
+    }
+
+    fn compare_and_swap<Q, V>(&self, key: &Q, expected: &[u8], new: &V) -> crate::Result<bool>
+    where
+        R::ValueKey: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        V: Serialize,
+    {
+        // The shard stays locked until the value is replaced so that no other thread can change it
+        // in between.
+        let mut shard = self.shard(key).write().unwrap();
+        let object_id = match shard.get(key) {
+            Some(object_id) => *object_id,
+            None => return Ok(false),
+        };
+
+        if self.read_object(object_id)? != expected {
+            return Ok(false);
+        }
+
+        let new_object_id = self.write_value(new)?;
+        self.0.changed.store(true, Ordering::SeqCst);
+        *shard.get_mut(key).unwrap() = new_object_id;
+        self.remove_object(object_id);
+
+        Ok(true)
+    }
+
+    fn remove<Q>(&self, key: &Q) -> bool
+    where
+        R::ValueKey: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let mut shard = self.shard(key).write().unwrap();
+        match shard.remove(key) {
+            Some(object_id) => {
+                self.0.changed.store(true, Ordering::SeqCst);
+                self.remove_object(object_id);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn get<Q, V>(&self, key: &Q) -> crate::Result<V>
+    where
+        R::ValueKey: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        V: DeserializeOwned,
+    {
+        // The shard stays locked while the value is read so that it can't be removed in between.
+        let shard = self.shard(key).read().unwrap();
+        let object_id = *shard.get(key).ok_or(crate::Error::NotFound)?;
+        let mut object = self
+            .0
+            .repo
+            .lock()
+            .unwrap()
+            .state_repo()
+            .object(object_id)
+            .unwrap();
+        object.deserialize_with::<R::Format, V>()
+    }
+
+    fn get_serialized<Q>(&self, key: &Q) -> crate::Result<SerializedValue>
+    where
+        R::ValueKey: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let shard = self.shard(key).read().unwrap();
+        let object_id = *shard.get(key).ok_or(crate::Error::NotFound)?;
+        Ok(SerializedValue(self.read_object(object_id)?))
+    }
+
+    fn keys(&self) -> Vec<R::ValueKey> {
+        self.0
+            .shards
+            .iter()
+            .flat_map(|shard| shard.read().unwrap().keys().cloned().collect::<Vec<_>>())
+            .collect()
+    }
+
+    fn instance(&self) -> InstanceId {
+        self.0.repo.lock().unwrap().state_repo().instance()
+    }
+
+    fn stats(&self) -> RepoStats {
+        self.0.repo.lock().unwrap().state_repo().stats()
+    }
+
+    fn info(&self) -> RepoInfo {
+        self.0.repo.lock().unwrap().state_repo().info()
+    }
+
+    fn try_unwrap(self) -> Result<R, Self> {
+        let state = Arc::try_unwrap(self.0).map_err(Self)?;
+        let mut repo = state.repo.into_inner().unwrap();
+        let key_map = state
+            .shards
+            .into_iter()
+            .flat_map(|shard| shard.into_inner().unwrap())
+            .collect();
+        repo.state_repo_mut()
+            .replace_state(key_map, state.changed.into_inner());
+        Ok(repo)
+    }
+}
+
+/// Return the index of the shard which contains the given `key`.
+fn shard_index<Q: Hash + ?Sized>(hasher: &RandomState, key: &Q) -> usize {
+    let mut state = hasher.build_hasher();
+    key.hash(&mut state);
+    (state.finish() % SHARD_COUNT as u64) as usize
+}
+
+impl<R: ValueStore> Commit for SharedValues<R> {
+    fn commit(&mut self) -> crate::Result<()> {
+        self.with_repo(|repo| repo.commit())
+    }
+
+    fn rollback(&mut self) -> crate::Result<()> {
+        self.with_repo(|repo| repo.rollback())
+    }
+
+    fn clean(&mut self) -> crate::Result<()> {
+        self.with_repo(|repo| repo.clean())
+    }
+}
+
+impl<R: ValueStore> RestoreSavepoint for SharedValues<R> {
+    type Restore = R::Restore;
+
+    fn savepoint(&mut self) -> crate::Result<Savepoint> {
+        self.with_repo(|repo| repo.savepoint())
+    }
+
+    fn start_restore(&mut self, savepoint: &Savepoint) -> crate::Result<Self::Restore> {
+        self.with_repo(|repo| repo.start_restore(savepoint))
+    }
+
+    fn finish_restore(&mut self, restore: Self::Restore) -> bool {
+        self.with_repo(|repo| repo.finish_restore(restore))
+    }
+}
+
+impl<R: ValueStore> Unlock for SharedValues<R> {
+    fn unlock(&self) -> crate::Result<()> {
+        self.0.repo.lock().unwrap().unlock()
+    }
+
+    fn is_locked(&self) -> crate::Result<bool> {
+        self.0.repo.lock().unwrap().is_locked()
+    }
+
+    fn context(&self) -> crate::Result<Vec<u8>> {
+        self.0.repo.lock().unwrap().context()
+    }
+
+    fn update_context(&self, context: &[u8]) -> crate::Result<()> {
+        self.0.repo.lock().unwrap().update_context(context)
+    }
+}
+
+/// A handle to a [`ValueRepo`] which can be shared between threads.
+///
+/// This value is created by [`ValueRepo::into_shared`]. It can be cheaply cloned, and each clone
+/// refers to the same repository. The key map is split into shards which are each protected by
+/// their own lock, and values are serialized and written to the data store without locking the
+/// whole repository, so threads inserting, removing, and reading values with different keys
+/// rarely contend with each other.
+///
+/// Operations which affect the whole repository, like [`Commit::commit`] and
+/// [`RestoreSavepoint::savepoint`], lock every shard and block all other operations on the
+/// repository until they complete.
+///
+/// Once you're done using the repository from multiple threads, you can convert it back into a
+/// [`ValueRepo`] with [`try_unwrap`].
+///
+/// [`ValueRepo`]: crate::repo::value::ValueRepo
+/// [`ValueRepo::into_shared`]: crate::repo::value::ValueRepo::into_shared
+/// [`Commit::commit`]: crate::repo::Commit::commit
+/// [`RestoreSavepoint::savepoint`]: crate::repo::RestoreSavepoint::savepoint
+/// [`try_unwrap`]: crate::repo::value::SharedValueRepo::try_unwrap
+#[derive(Debug)]
+pub struct SharedValueRepo<K: Key, F: Format = MessagePack>(SharedValues<ValueRepo<K, F>>);
+
+assert_impl_all!(SharedValueRepo<String>: Send, Sync);
+
+impl<K: Key, F: Format> Clone for SharedValueRepo<K, F> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<K: Key, F: Format> SharedValueRepo<K, F> {
+    /// Create a new `SharedValueRepo` from the given `repo`.
+    pub(super) fn new(repo: ValueRepo<K, F>) -> Self {
+        Self(SharedValues::new(repo))
+    }
+
+    /// Return whether the given `key` exists in this repository.
+    pub fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.0.contains(key)
+    }
+
+    /// Insert a new key-value pair.
+    ///
+    /// See [`ValueRepo::insert`] for details.
+    ///
+    /// # Errors
+    /// - `Error::Serialize`: The `value` could not be serialized.
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    ///
+    /// [`ValueRepo::insert`]: crate::repo::value::ValueRepo::insert
+    pub fn insert<V: Serialize>(&self, key: K, value: &V) -> crate::Result<()> {
+        self.0.insert(key, value)
+    }
+
+    /// Insert a new key-value pair only if `key` is not already in the repository.
+    ///
+    /// See [`ValueRepo::insert_if_absent`] for details.
+    ///
+    /// # Errors
+    /// - `Error::Serialize`: The `value` could not be serialized.
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    ///
+    /// [`ValueRepo::insert_if_absent`]: crate::repo::value::ValueRepo::insert_if_absent
+    pub fn insert_if_absent<V: Serialize>(&self, key: K, value: &V) -> crate::Result<bool> {
+        self.0.insert_if_absent(key, value)
+    }
+
+    /// Replace the value associated with `key` with `new` only if its current value is `expected`.
+    ///
+    /// No other thread can change the value associated with `key` between the comparison and the
+    /// replacement. See [`ValueRepo::compare_and_swap`] for details.
+    ///
+    /// # Errors
+    /// - `Error::Serialize`: The `expected` or `new` value could not be serialized.
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    ///
+    /// [`ValueRepo::compare_and_swap`]: crate::repo::value::ValueRepo::compare_and_swap
+    pub fn compare_and_swap<Q, V>(&self, key: &Q, expected: &V, new: &V) -> crate::Result<bool>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        V: Serialize,
+    {
+        self.0.compare_and_swap(key, &F::to_vec(expected)?, new)
+    }
+
+    /// Remove the value associated with `key` from the repository.
+    ///
+    /// See [`ValueRepo::remove`] for details.
+    ///
+    /// [`ValueRepo::remove`]: crate::repo::value::ValueRepo::remove
+    pub fn remove<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.0.remove(key)
+    }
+
+    /// Return the value associated with `key`.
+    ///
+    /// # Errors
+    /// - `Error::NotFound`: There is no value associated with `key`.
+    /// - `Error::Deserialize`: The value could not be deserialized.
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    pub fn get<Q, V>(&self, key: &Q) -> crate::Result<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        V: DeserializeOwned,
+    {
+        self.0.get(key)
+    }
+
+    /// Return the serialized value associated with `key`.
+    ///
+    /// See [`ValueRepo::get_serialized`] for details.
+    ///
+    /// # Errors
+    /// - `Error::NotFound`: There is no value associated with `key`.
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    ///
+    /// [`ValueRepo::get_serialized`]: crate::repo::value::ValueRepo::get_serialized
+    pub fn get_serialized<Q>(&self, key: &Q) -> crate::Result<SerializedValue>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.0.get_serialized(key)
+    }
+
+    /// Return a list of all the keys in this repository.
+    ///
+    /// Because other threads may be modifying the repository concurrently, the returned list is a
+    /// snapshot of the keys at the time this method was called.
+    pub fn keys(&self) -> Vec<K> {
+        self.0.keys()
+    }
+
+    /// Copy the value at `source` to `dest`.
+    ///
+    /// See [`ValueRepo::copy`] for details.
+    ///
+    /// # Errors
+    /// - `Error::NotFound`: There is no value at `source`.
+    /// - `Error::AlreadyExists`: There is already a value at `dest`.
+    ///
+    /// [`ValueRepo::copy`]: crate::repo::value::ValueRepo::copy
+    pub fn copy<Q>(&self, source: &Q, dest: K) -> crate::Result<()>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        // Copying between shards requires locking two shards at once, so we lock the whole
+        // repository instead to avoid deadlocks.
+        self.0.with_repo(|repo| repo.copy(source, dest))
+    }
+
+    /// Verify the integrity of all the data in the repository.
+    ///
+    /// This returns the set of keys of values which are corrupt.
+    ///
+    /// # Errors
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    pub fn verify(&self) -> crate::Result<HashSet<K>> {
+        self.0
+            .with_repo(|repo| Ok(repo.verify()?.into_iter().cloned().collect()))
+    }
+
+    /// Delete all data in the current instance of the repository.
+    ///
+    /// See [`KeyRepo::clear_instance`] for details.
+    ///
+    /// [`KeyRepo::clear_instance`]: crate::repo::key::KeyRepo::clear_instance
+    pub fn clear_instance(&self) {
+        self.0.with_repo(|repo| repo.clear_instance())
+    }
+
+    /// Return this repository's current instance ID.
+    pub fn instance(&self) -> InstanceId {
+        self.0.instance()
+    }
+
+    /// Compute statistics about the repository.
+    ///
+    /// See [`KeyRepo::stats`] for details.
+    ///
+    /// [`KeyRepo::stats`]: crate::repo::key::KeyRepo::stats
+    pub fn stats(&self) -> RepoStats {
+        self.0.stats()
+    }
+
+    /// Return information about the repository.
+    pub fn info(&self) -> RepoInfo {
+        self.0.info()
+    }
+
+    /// Convert this handle back into a [`ValueRepo`].
+    ///
+    /// If there are other clones of this handle, this returns `Err` containing this handle.
+    ///
+    /// [`ValueRepo`]: crate::repo::value::ValueRepo
+    pub fn try_unwrap(self) -> Result<ValueRepo<K, F>, Self> {
+        self.0.try_unwrap().map_err(Self)
+    }
+}
+
+impl<K: Key, F: Format> Commit for SharedValueRepo<K, F> {
+    fn commit(&mut self) -> crate::Result<()> {
+        self.0.commit()
+    }
+
+    fn rollback(&mut self) -> crate::Result<()> {
+        self.0.rollback()
+    }
+
+    fn clean(&mut self) -> crate::Result<()> {
+        self.0.clean()
+    }
+}
+
+impl<K: Key, F: Format> RestoreSavepoint for SharedValueRepo<K, F> {
+    type Restore = <ValueRepo<K, F> as RestoreSavepoint>::Restore;
+
+    fn savepoint(&mut self) -> crate::Result<Savepoint> {
+        self.0.savepoint()
+    }
+
+    fn start_restore(&mut self, savepoint: &Savepoint) -> crate::Result<Self::Restore> {
+        self.0.start_restore(savepoint)
+    }
+
+    fn finish_restore(&mut self, restore: Self::Restore) -> bool {
+        self.0.finish_restore(restore)
+    }
+}
+
+impl<K: Key, F: Format> Unlock for SharedValueRepo<K, F> {
+    fn unlock(&self) -> crate::Result<()> {
+        self.0.unlock()
+    }
+
+    fn is_locked(&self) -> crate::Result<bool> {
+        self.0.is_locked()
+    }
+
+    fn context(&self) -> crate::Result<Vec<u8>> {
+        self.0.context()
+    }
+
+    fn update_context(&self, context: &[u8]) -> crate::Result<()> {
+        self.0.update_context(context)
+    }
+}
+
+/// A handle to an [`OrderedValueRepo`] which can be shared between threads.
+///
+/// This value is created by [`OrderedValueRepo::into_shared`]. It works like a
+/// [`SharedValueRepo`]. While the repository is shared, its keys are not kept in order, so methods
+/// which return keys sort them first.
+///
+/// Once you're done using the repository from multiple threads, you can convert it back into an
+/// [`OrderedValueRepo`] with [`try_unwrap`].
+///
+/// [`OrderedValueRepo`]: crate::repo::value::OrderedValueRepo
+/// [`OrderedValueRepo::into_shared`]: crate::repo::value::OrderedValueRepo::into_shared
+/// [`SharedValueRepo`]: crate::repo::value::SharedValueRepo
+/// [`try_unwrap`]: crate::repo::value::SharedOrderedValueRepo::try_unwrap
+#[derive(Debug)]
+pub struct SharedOrderedValueRepo<K: Key + Ord>(SharedValues<OrderedValueRepo<K>>);
+
+assert_impl_all!(SharedOrderedValueRepo<String>: Send, Sync);
+
+impl<K: Key + Ord> Clone for SharedOrderedValueRepo<K> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<K: Key + Ord> SharedOrderedValueRepo<K> {
+    /// Create a new `SharedOrderedValueRepo` from the given `repo`.
+    pub(super) fn new(repo: OrderedValueRepo<K>) -> Self {
+        Self(SharedValues::new(repo))
+    }
+
+    /// Return whether the given `key` exists in this repository.
+    pub fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.0.contains(key)
+    }
+
+    /// Insert a new key-value pair.
+    ///
+    /// See [`OrderedValueRepo::insert`] for details.
+    ///
+    /// # Errors
+    /// - `Error::Serialize`: The `value` could not be serialized.
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    ///
+    /// [`OrderedValueRepo::insert`]: crate::repo::value::OrderedValueRepo::insert
+    pub fn insert<V: Serialize>(&self, key: K, value: &V) -> crate::Result<()> {
+        self.0.insert(key, value)
+    }
+
+    /// Remove the value associated with `key` from the repository.
+    ///
+    /// See [`OrderedValueRepo::remove`] for details.
+    ///
+    /// [`OrderedValueRepo::remove`]: crate::repo::value::OrderedValueRepo::remove
+    pub fn remove<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.0.remove(key)
+    }
+
+    /// Return the value associated with `key`.
+    ///
+    /// # Errors
+    /// - `Error::NotFound`: There is no value associated with `key`.
+    /// - `Error::Deserialize`: The value could not be deserialized.
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    pub fn get<Q, V>(&self, key: &Q) -> crate::Result<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        V: DeserializeOwned,
+    {
+        self.0.get(key)
+    }
+
+    /// Return the serialized value associated with `key`.
+    ///
+    /// See [`OrderedValueRepo::get_serialized`] for details.
+    ///
+    /// # Errors
+    /// - `Error::NotFound`: There is no value associated with `key`.
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    ///
+    /// [`OrderedValueRepo::get_serialized`]: crate::repo::value::OrderedValueRepo::get_serialized
+    pub fn get_serialized<Q>(&self, key: &Q) -> crate::Result<SerializedValue>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.0.get_serialized(key)
+    }
+
+    /// Return a list of all the keys in this repository in order.
+    ///
+    /// Because other threads may be modifying the repository concurrently, the returned list is a
+    /// snapshot of the keys at the time this method was called.
+    pub fn keys(&self) -> Vec<K> {
+        let mut keys = self.0.keys();
+        keys.sort_unstable();
+        keys
+    }
+
+    /// Copy the value at `source` to `dest`.
+    ///
+    /// See [`OrderedValueRepo::copy`] for details.
+    ///
+    /// # Errors
+    /// - `Error::NotFound`: There is no value at `source`.
+    /// - `Error::AlreadyExists`: There is already a value at `dest`.
+    ///
+    /// [`OrderedValueRepo::copy`]: crate::repo::value::OrderedValueRepo::copy
+    pub fn copy<Q>(&self, source: &Q, dest: K) -> crate::Result<()>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        // Copying between shards requires locking two shards at once, so we lock the whole
+        // repository instead to avoid deadlocks.
+        self.0.with_repo(|repo| repo.copy(source, dest))
+    }
+
+    /// Verify the integrity of all the data in the repository.
+    ///
+    /// This returns the set of keys of values which are corrupt.
+    ///
+    /// # Errors
+    /// - `Error::InvalidData`: Ciphertext verification failed.
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    pub fn verify(&self) -> crate::Result<HashSet<K>> {
+        self.0
+            .with_repo(|repo| Ok(repo.verify()?.into_iter().cloned().collect()))
+    }
+
+    /// Delete all data in the current instance of the repository.
+    ///
+    /// See [`KeyRepo::clear_instance`] for details.
+    ///
+    /// [`KeyRepo::clear_instance`]: crate::repo::key::KeyRepo::clear_instance
+    pub fn clear_instance(&self) {
+        self.0.with_repo(|repo| repo.clear_instance())
+    }
+
+    /// Return this repository's current instance ID.
+    pub fn instance(&self) -> InstanceId {
+        self.0.instance()
+    }
+
+    /// Compute statistics about the repository.
+    ///
+    /// See [`KeyRepo::stats`] for details.
+    ///
+    /// [`KeyRepo::stats`]: crate::repo::key::KeyRepo::stats
+    pub fn stats(&self) -> RepoStats {
+        self.0.stats()
+    }
+
+    /// Return information about the repository.
+    pub fn info(&self) -> RepoInfo {
+        self.0.info()
+    }
+
+    /// Convert this handle back into an [`OrderedValueRepo`].
+    ///
+    /// If there are other clones of this handle, this returns `Err` containing this handle.
+    ///
+    /// [`OrderedValueRepo`]: crate::repo::value::OrderedValueRepo
+    pub fn try_unwrap(self) -> Result<OrderedValueRepo<K>, Self> {
+        self.0.try_unwrap().map_err(Self)
+    }
+}
+
+impl<K: Key + Ord> Commit for SharedOrderedValueRepo<K> {
+    fn commit(&mut self) -> crate::Result<()> {
+        self.0.commit()
+    }
+
+    fn rollback(&mut self) -> crate::Result<()> {
+        self.0.rollback()
+    }
+
+    fn clean(&mut self) -> crate::Result<()> {
+        self.0.clean()
+    }
+}
+
+impl<K: Key + Ord> RestoreSavepoint for SharedOrderedValueRepo<K> {
+    type Restore = <OrderedValueRepo<K> as RestoreSavepoint>::Restore;
+
+    fn savepoint(&mut self) -> crate::Result<Savepoint> {
+        self.0.savepoint()
+    }
+
+    fn start_restore(&mut self, savepoint: &Savepoint) -> crate::Result<Self::Restore> {
+        self.0.start_restore(savepoint)
+    }
+
+    fn finish_restore(&mut self, restore: Self::Restore) -> bool {
+        self.0.finish_restore(restore)
+    }
+}
+
+impl<K: Key + Ord> Unlock for SharedOrderedValueRepo<K> {
+    fn unlock(&self) -> crate::Result<()> {
+        self.0.unlock()
+    }
+
+    fn is_locked(&self) -> crate::Result<bool> {
+        self.0.is_locked()
+    }
+
+    fn context(&self) -> crate::Result<Vec<u8>> {
+        self.0.context()
+    }
+
+    fn update_context(&self, context: &[u8]) -> crate::Result<()> {
+        self.0.update_context(context)
+    }
+}
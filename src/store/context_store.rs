@@ -0,0 +1,85 @@
+use std::io::Read;
+
+use super::data_store::{BlockId, BlockKey, BlockType, DataStore};
+use super::error::{ErrorContext, StoreOperation};
+use super::health::HealthReport;
+
+/// A `DataStore` which attaches an `ErrorContext` to the errors returned by another data store.
+///
+/// Repositories wrap their data store in this so that errors which surface through the repository
+/// identify the operation and block which failed.
+#[derive(Debug)]
+pub(crate) struct ContextStore<S>(S);
+
+impl<S: DataStore> ContextStore<S> {
+    /// Wrap the given `store`.
+    pub fn new(store: S) -> Self {
+        ContextStore(store)
+    }
+}
+
+impl<S: DataStore> DataStore for ContextStore<S> {
+    fn write_block(&mut self, key: BlockKey, data: &[u8]) -> super::Result<()> {
+        self.0.write_block(key, data).map_err(|error| {
+            error.with_context(ErrorContext::block(StoreOperation::WriteBlock, key))
+        })
+    }
+
+    fn read_block(&mut self, key: BlockKey) -> super::Result<Option<Vec<u8>>> {
+        self.0.read_block(key).map_err(|error| {
+            error.with_context(ErrorContext::block(StoreOperation::ReadBlock, key))
+        })
+    }
+
+    fn write_block_stream(&mut self, key: BlockKey, reader: &mut dyn Read) -> super::Result<()> {
+        self.0.write_block_stream(key, reader).map_err(|error| {
+            error.with_context(ErrorContext::block(StoreOperation::WriteBlock, key))
+        })
+    }
+
+    fn read_block_stream(&mut self, key: BlockKey) -> super::Result<Option<Box<dyn Read + '_>>> {
+        self.0.read_block_stream(key).map_err(|error| {
+            error.with_context(ErrorContext::block(StoreOperation::ReadBlock, key))
+        })
+    }
+
+    fn supports_range_reads(&self) -> bool {
+        self.0.supports_range_reads()
+    }
+
+    fn read_block_range(
+        &mut self,
+        key: BlockKey,
+        offset: u64,
+        len: u64,
+    ) -> super::Result<Option<Vec<u8>>> {
+        self.0.read_block_range(key, offset, len).map_err(|error| {
+            error.with_context(ErrorContext::block(StoreOperation::ReadBlock, key))
+        })
+    }
+
+    fn remove_block(&mut self, key: BlockKey) -> super::Result<()> {
+        self.0.remove_block(key).map_err(|error| {
+            error.with_context(ErrorContext::block(StoreOperation::RemoveBlock, key))
+        })
+    }
+
+    fn list_blocks(&mut self, kind: BlockType) -> super::Result<Vec<BlockId>> {
+        self.0
+            .list_blocks(kind)
+            .map_err(|error| error.with_context(ErrorContext::list(kind)))
+    }
+
+    fn health_check(&mut self) -> HealthReport {
+        self.0.health_check()
+    }
+
+    fn available_space(&mut self) -> super::Result<Option<u64>> {
+        self.0.available_space()
+    }
+
+    fn try_clone(&self) -> Option<Box<dyn DataStore>> {
+        let store = self.0.try_clone()?;
+        Some(Box::new(ContextStore::new(store)))
+    }
+}
@@ -1,7 +1,10 @@
 use std::fmt::{self, Debug, Formatter};
+use std::io::{Cursor, Read};
 
 use static_assertions::assert_obj_safe;
 
+use super::health::{check_store, HealthReport};
+
 uuid_type! {
     /// The UUID of a block in a [`DataStore`].
     ///
@@ -55,6 +58,67 @@ pub trait DataStore: Send {
     /// If there is no block with the given `key`, return `None`.
     fn read_block(&mut self, key: BlockKey) -> super::Result<Option<Vec<u8>>>;
 
+    /// Write the bytes read from `reader` as a new block with the given `key`.
+    ///
+    /// This has the same guarantees as [`write_block`], but it allows data stores to write a block
+    /// without holding all of its data in memory at once.
+    ///
+    /// The default implementation reads all the data from `reader` into memory and then calls
+    /// [`write_block`]. Implementations which can write data incrementally should override this.
+    ///
+    /// [`write_block`]: crate::store::DataStore::write_block
+    fn write_block_stream(&mut self, key: BlockKey, reader: &mut dyn Read) -> super::Result<()> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        self.write_block(key, &data)
+    }
+
+    /// Return a reader for the bytes of the block with the given `key`.
+    ///
+    /// If there is no block with the given `key`, return `None`. This allows data stores to return
+    /// a block without holding all of its data in memory at once.
+    ///
+    /// The default implementation reads the whole block with [`read_block`] and returns a reader
+    /// over it. Implementations which can read data incrementally should override this.
+    ///
+    /// [`read_block`]: crate::store::DataStore::read_block
+    fn read_block_stream(&mut self, key: BlockKey) -> super::Result<Option<Box<dyn Read + '_>>> {
+        Ok(self
+            .read_block(key)?
+            .map(|data| Box::new(Cursor::new(data)) as Box<dyn Read>))
+    }
+
+    /// Return whether this data store can read part of a block without reading the whole block.
+    ///
+    /// If this returns `true`, repositories use [`read_block_range`] to read only the data they
+    /// need from large blocks, like packs. The default implementation returns `false`.
+    ///
+    /// [`read_block_range`]: crate::store::DataStore::read_block_range
+    fn supports_range_reads(&self) -> bool {
+        false
+    }
+
+    /// Return `len` bytes of the block with the given `key` starting at `offset`.
+    ///
+    /// If there is no block with the given `key`, return `None`. If the range extends past the end
+    /// of the block, only the bytes up to the end of the block are returned.
+    ///
+    /// The default implementation reads the whole block with `read_block` and returns the
+    /// requested range. Implementations which return `true` from [`supports_range_reads`] should
+    /// override this to read only the requested range from the backend.
+    ///
+    /// [`supports_range_reads`]: crate::store::DataStore::supports_range_reads
+    fn read_block_range(
+        &mut self,
+        key: BlockKey,
+        offset: u64,
+        len: u64,
+    ) -> super::Result<Option<Vec<u8>>> {
+        Ok(self
+            .read_block(key)?
+            .map(|data| block_range(&data, offset, len).to_vec()))
+    }
+
     /// Remove the block with the given `key` from the store.
     ///
     /// If this method returns `Ok`, the given `key` is no longer stored persistently and any space
@@ -68,6 +132,44 @@ pub trait DataStore: Send {
 
     /// Return a list of IDs of blocks of the given `kind` in the store.
     fn list_blocks(&mut self, kind: BlockType) -> super::Result<Vec<BlockId>>;
+
+    /// Check whether this data store is reachable and can be read from and written to.
+    ///
+    /// This returns a [`HealthReport`] describing which basic operations succeeded and how long
+    /// they took. This is useful for diagnosing connectivity and permission problems before they
+    /// cause an operation on a repository to fail partway through.
+    ///
+    /// The default implementation lists the data blocks in the store and then writes, reads, and
+    /// removes a small temporary data block. Implementations can override this to perform checks
+    /// which are specific to the backend.
+    ///
+    /// [`HealthReport`]: crate::store::HealthReport
+    fn health_check(&mut self) -> HealthReport {
+        check_store(self)
+    }
+
+    /// Return the number of bytes of free space available in the data store.
+    ///
+    /// This is a hint which is used to report how much space is available, such as when a
+    /// repository is mounted as a FUSE file system. It returns `None` if the amount of free space
+    /// is unknown, which is what the default implementation does.
+    ///
+    /// # Errors
+    /// - `Error::Io`: An I/O error occurred.
+    fn available_space(&mut self) -> super::Result<Option<u64>> {
+        Ok(None)
+    }
+
+    /// Return a new handle to this data store which can be used independently of this one.
+    ///
+    /// Repositories use this to read from the data store from several threads at once, such as
+    /// when verifying data with multiple threads. The new handle must see every block which was
+    /// written through this one. This returns `None` if the data store doesn't support multiple
+    /// handles, which is what the default implementation does. In that case, the threads share this
+    /// handle and take turns reading from it.
+    fn try_clone(&self) -> Option<Box<dyn DataStore>> {
+        None
+    }
 }
 
 assert_obj_safe!(DataStore);
@@ -81,6 +183,27 @@ impl DataStore for Box<dyn DataStore> {
         self.as_mut().read_block(key)
     }
 
+    fn write_block_stream(&mut self, key: BlockKey, reader: &mut dyn Read) -> super::Result<()> {
+        self.as_mut().write_block_stream(key, reader)
+    }
+
+    fn read_block_stream(&mut self, key: BlockKey) -> super::Result<Option<Box<dyn Read + '_>>> {
+        self.as_mut().read_block_stream(key)
+    }
+
+    fn supports_range_reads(&self) -> bool {
+        self.as_ref().supports_range_reads()
+    }
+
+    fn read_block_range(
+        &mut self,
+        key: BlockKey,
+        offset: u64,
+        len: u64,
+    ) -> super::Result<Option<Vec<u8>>> {
+        self.as_mut().read_block_range(key, offset, len)
+    }
+
     fn remove_block(&mut self, key: BlockKey) -> super::Result<()> {
         self.as_mut().remove_block(key)
     }
@@ -88,6 +211,18 @@ impl DataStore for Box<dyn DataStore> {
     fn list_blocks(&mut self, kind: BlockType) -> super::Result<Vec<BlockId>> {
         self.as_mut().list_blocks(kind)
     }
+
+    fn health_check(&mut self) -> HealthReport {
+        self.as_mut().health_check()
+    }
+
+    fn available_space(&mut self) -> super::Result<Option<u64>> {
+        self.as_mut().available_space()
+    }
+
+    fn try_clone(&self) -> Option<Box<dyn DataStore>> {
+        self.as_ref().try_clone()
+    }
 }
 
 impl Debug for dyn DataStore {
@@ -95,3 +230,12 @@ impl Debug for dyn DataStore {
         f.write_str("DataStore")
     }
 }
+
+/// Return the slice of `data` which is `len` bytes long and starts at `offset`.
+///
+/// The slice is truncated if it extends past the end of `data`.
+pub(super) fn block_range(data: &[u8], offset: u64, len: u64) -> &[u8] {
+    let start = offset.min(data.len() as u64) as usize;
+    let end = offset.saturating_add(len).min(data.len() as u64) as usize;
+    &data[start..end]
+}
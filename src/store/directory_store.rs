@@ -1,8 +1,9 @@
 #![cfg(feature = "store-directory")]
 
+use std::collections::HashSet;
 use std::fs::{create_dir_all, read_dir, remove_file, rename, File};
-use std::io::{Read, Write};
-use std::path::PathBuf;
+use std::io::{self, copy, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
 
 use uuid::Uuid;
 
@@ -46,6 +47,50 @@ fn block_path(key: BlockKey) -> PathBuf {
     }
 }
 
+/// Flush the directory at `path` to disk so that changes to its entries are durable.
+///
+/// Directories can't be flushed on Windows, where this does nothing.
+fn sync_directory(path: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    File::open(path)?.sync_all()?;
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
+/// When a [`DirectoryStore`] waits for blocks to be written to disk.
+///
+/// Blocks are always written to a staging file and then atomically renamed into place, so a block
+/// is never left partially written. This determines whether a block which was written
+/// successfully is guaranteed to survive a crash or power loss.
+///
+/// This type implements `Default`, which returns `DirectoryDurability::Commit`.
+///
+/// [`DirectoryStore`]: crate::store::DirectoryStore
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+#[cfg_attr(docsrs, doc(cfg(feature = "store-directory")))]
+pub enum DirectoryDurability {
+    /// Never wait for blocks to be written to disk.
+    ///
+    /// This is the fastest, but the operating system may lose recently written blocks if the
+    /// system crashes or loses power, which can leave the repository in an inconsistent state.
+    None,
+
+    /// Wait for blocks to be written to disk before writing a block which commits changes.
+    ///
+    /// Data blocks are not flushed individually. Instead, every data block which was written since
+    /// the last flush is flushed right before a header, lock, or metadata block is written. This
+    /// guarantees that a committed repository never references data which was lost, while avoiding
+    /// the cost of flushing every data block.
+    #[default]
+    Commit,
+
+    /// Wait for each block to be written to disk before the write completes.
+    ///
+    /// This is the slowest, but it guarantees that every block which was written is durable.
+    Full,
+}
+
 /// The configuration for opening a [`DirectoryStore`].
 ///
 /// [`DirectoryStore`]: crate::store::DirectoryStore
@@ -54,6 +99,9 @@ fn block_path(key: BlockKey) -> PathBuf {
 pub struct DirectoryConfig {
     /// The path of the directory store.
     pub path: PathBuf,
+
+    /// When to wait for blocks to be written to disk.
+    pub durability: DirectoryDurability,
 }
 
 impl OpenStore for DirectoryConfig {
@@ -87,14 +135,28 @@ impl OpenStore for DirectoryConfig {
                 return Err(crate::Error::UnsupportedStore);
             }
         } else {
-            // Write the version ID file.
-            let mut version_file = File::create(&version_path)
+            // Write the version ID file to a staging file and then atomically move it into place so
+            // that a crash can't leave an empty version file behind.
+            let staging_path = self
+                .path
+                .join(STAGING_DIRECTORY)
+                .join(Uuid::new_v4().as_hyphenated().to_string());
+            let mut version_file = File::create(&staging_path)
                 .map_err(|error| crate::Error::Store(super::Error::from(error)))?;
             version_file.write_all(CURRENT_VERSION.as_bytes())?;
+            if self.durability != DirectoryDurability::None {
+                version_file.sync_all()?;
+            }
+            rename(&staging_path, &version_path)?;
+            if self.durability != DirectoryDurability::None {
+                sync_directory(&self.path)?;
+            }
         }
 
         Ok(DirectoryStore {
             path: self.path.clone(),
+            durability: self.durability,
+            unsynced_blocks: Vec::new(),
         })
     }
 }
@@ -109,6 +171,12 @@ impl OpenStore for DirectoryConfig {
 pub struct DirectoryStore {
     /// The path of the store's root directory.
     path: PathBuf,
+
+    /// When to wait for blocks to be written to disk.
+    durability: DirectoryDurability,
+
+    /// The paths of the data blocks which have been written but not flushed to disk.
+    unsynced_blocks: Vec<PathBuf>,
 }
 
 impl DirectoryStore {
@@ -117,6 +185,58 @@ impl DirectoryStore {
         self.path.join(block_path(key))
     }
 
+    /// Open the file containing the block with the given `key`, or `None` if there is no block.
+    pub(super) fn open_block(&self, key: BlockKey) -> super::Result<Option<File>> {
+        let block_path = self.block_path(key);
+
+        if block_path.exists() {
+            Ok(Some(File::open(block_path)?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Flush every data block which was written since the last flush to disk.
+    pub(super) fn sync_blocks(&mut self) -> super::Result<()> {
+        let mut directories = HashSet::new();
+
+        for block_path in &self.unsynced_blocks {
+            match File::open(block_path) {
+                Ok(file) => file.sync_all()?,
+                // The block may have been removed since it was written.
+                Err(error) if error.kind() == io::ErrorKind::NotFound => continue,
+                Err(error) => return Err(error.into()),
+            }
+            directories.insert(block_path.parent().unwrap().to_owned());
+        }
+
+        for directory in directories {
+            sync_directory(&directory)?;
+        }
+
+        self.unsynced_blocks.clear();
+
+        Ok(())
+    }
+
+    /// Return whether the block with the given `key` must be flushed to disk when it's written.
+    fn must_sync(&self, key: BlockKey) -> bool {
+        match self.durability {
+            DirectoryDurability::None => false,
+            DirectoryDurability::Commit => !matches!(key, BlockKey::Data(_)),
+            DirectoryDurability::Full => true,
+        }
+    }
+
+    /// Return a new handle to this store which has no unflushed blocks of its own.
+    pub(super) fn new_handle(&self) -> DirectoryStore {
+        DirectoryStore {
+            path: self.path.clone(),
+            durability: self.durability,
+            unsynced_blocks: Vec::new(),
+        }
+    }
+
     /// Return a new staging path.
     fn staging_path(&self) -> PathBuf {
         let uuid_str = Uuid::new_v4().as_hyphenated().to_string();
@@ -125,18 +245,49 @@ impl DirectoryStore {
 }
 
 impl DataStore for DirectoryStore {
-    fn write_block(&mut self, key: BlockKey, data: &[u8]) -> super::Result<()> {
+    fn write_block(&mut self, key: BlockKey, mut data: &[u8]) -> super::Result<()> {
+        self.write_block_stream(key, &mut data)
+    }
+
+    fn read_block(&mut self, key: BlockKey) -> super::Result<Option<Vec<u8>>> {
+        match self.open_block(key)? {
+            Some(mut file) => {
+                let mut buffer = Vec::with_capacity(file.metadata()?.len() as usize);
+                file.read_to_end(&mut buffer)?;
+                Ok(Some(buffer))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn write_block_stream(&mut self, key: BlockKey, reader: &mut dyn Read) -> super::Result<()> {
         let staging_path = self.staging_path();
         let block_path = self.block_path(key);
 
+        let must_sync = self.must_sync(key);
+
+        // Blocks which commit changes must not reach the disk before the data they reference.
+        if must_sync {
+            self.sync_blocks()?;
+        }
+
         // If this is the first block its sub-directory, the directory needs to be created.
         create_dir_all(block_path.parent().unwrap())?;
 
         // Write to a staging file and then atomically move it to its final destination.
         let mut staging_file = File::create(&staging_path)?;
-        staging_file.write_all(data)?;
+        copy(reader, &mut staging_file)?;
+        if must_sync {
+            staging_file.sync_all()?;
+        }
         rename(&staging_path, &block_path)?;
 
+        if must_sync {
+            sync_directory(block_path.parent().unwrap())?;
+        } else if self.durability == DirectoryDurability::Commit {
+            self.unsynced_blocks.push(block_path);
+        }
+
         // Remove any unused staging files.
         for entry in read_dir(self.path.join(STAGING_DIRECTORY))? {
             remove_file(entry?.path())?;
@@ -145,13 +296,31 @@ impl DataStore for DirectoryStore {
         Ok(())
     }
 
-    fn read_block(&mut self, key: BlockKey) -> super::Result<Option<Vec<u8>>> {
+    fn read_block_stream(&mut self, key: BlockKey) -> super::Result<Option<Box<dyn Read + '_>>> {
+        Ok(self
+            .open_block(key)?
+            .map(|file| Box::new(file) as Box<dyn Read>))
+    }
+
+    fn supports_range_reads(&self) -> bool {
+        true
+    }
+
+    fn read_block_range(
+        &mut self,
+        key: BlockKey,
+        offset: u64,
+        len: u64,
+    ) -> super::Result<Option<Vec<u8>>> {
         let block_path = self.block_path(key);
 
         if block_path.exists() {
             let mut file = File::open(block_path)?;
-            let mut buffer = Vec::with_capacity(file.metadata()?.len() as usize);
-            file.read_to_end(&mut buffer)?;
+            let block_len = file.metadata()?.len();
+            let range_len = len.min(block_len.saturating_sub(offset));
+            let mut buffer = Vec::with_capacity(range_len as usize);
+            file.seek(SeekFrom::Start(offset))?;
+            file.take(range_len).read_to_end(&mut buffer)?;
             Ok(Some(buffer))
         } else {
             Ok(None)
@@ -204,4 +373,8 @@ impl DataStore for DirectoryStore {
 
         Ok(block_ids)
     }
+
+    fn try_clone(&self) -> Option<Box<dyn DataStore>> {
+        Some(Box::new(self.new_handle()))
+    }
 }
@@ -3,14 +3,83 @@ use std::fmt;
 use std::ops::Deref;
 use std::result;
 
+use super::data_store::{BlockKey, BlockType};
+
+/// An operation on a [`DataStore`].
+///
+/// [`DataStore`]: crate::store::DataStore
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StoreOperation {
+    /// [`DataStore::write_block`](crate::store::DataStore::write_block)
+    WriteBlock,
+
+    /// [`DataStore::read_block`](crate::store::DataStore::read_block)
+    ReadBlock,
+
+    /// [`DataStore::remove_block`](crate::store::DataStore::remove_block)
+    RemoveBlock,
+
+    /// [`DataStore::list_blocks`](crate::store::DataStore::list_blocks)
+    ListBlocks,
+}
+
+/// Information about the data store operation which caused an [`Error`].
+///
+/// [`Error`]: crate::store::Error
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ErrorContext {
+    operation: StoreOperation,
+    block: Option<BlockKey>,
+    block_type: Option<BlockType>,
+}
+
+impl ErrorContext {
+    /// Create a context for an `operation` on the block with the given `key`.
+    pub fn block(operation: StoreOperation, key: BlockKey) -> Self {
+        Self {
+            operation,
+            block: Some(key),
+            block_type: None,
+        }
+    }
+
+    /// Create a context for listing the blocks of the given `kind`.
+    pub fn list(kind: BlockType) -> Self {
+        Self {
+            operation: StoreOperation::ListBlocks,
+            block: None,
+            block_type: Some(kind),
+        }
+    }
+
+    /// The operation which failed.
+    pub fn operation(&self) -> StoreOperation {
+        self.operation
+    }
+
+    /// The key of the block the operation was accessing, if any.
+    pub fn block_key(&self) -> Option<BlockKey> {
+        self.block
+    }
+
+    /// The type of blocks being listed, if the operation was listing blocks.
+    pub fn block_type(&self) -> Option<BlockType> {
+        self.block_type
+    }
+}
+
 /// An error that occurs in a [`DataStore`].
 ///
-/// This wraps a dynamic error type.
+/// This wraps a dynamic error type. Errors returned by repositories also carry an
+/// [`ErrorContext`] describing which operation failed, which is available via [`context`].
 ///
 /// [`DataStore`]: crate::store::DataStore
+/// [`ErrorContext`]: crate::store::ErrorContext
+/// [`context`]: crate::store::Error::context
 #[derive(Debug)]
 pub struct Error {
     inner: anyhow::Error,
+    context: Option<ErrorContext>,
 }
 
 impl Error {
@@ -21,6 +90,7 @@ impl Error {
     {
         Self {
             inner: anyhow::Error::new(error),
+            context: None,
         }
     }
 
@@ -35,8 +105,20 @@ impl Error {
     {
         Self {
             inner: anyhow::Error::msg(message),
+            context: None,
         }
     }
+
+    /// Attach the given `context` to this error, replacing any existing context.
+    pub fn with_context(mut self, context: ErrorContext) -> Self {
+        self.context = Some(context);
+        self
+    }
+
+    /// Information about the data store operation which caused this error, if available.
+    pub fn context(&self) -> Option<&ErrorContext> {
+        self.context.as_ref()
+    }
 }
 
 impl<E> From<E> for Error
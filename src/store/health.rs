@@ -0,0 +1,194 @@
+use std::fmt::{self, Display, Formatter};
+use std::time::{Duration, Instant};
+
+use uuid::Uuid;
+
+use super::data_store::{BlockKey, BlockType, DataStore};
+
+/// The size of the block written to the data store during a health check.
+const TEST_BLOCK_SIZE: usize = 4096;
+
+/// The outcome of a single check in a [`HealthReport`].
+///
+/// [`HealthReport`]: crate::store::HealthReport
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum CheckStatus {
+    /// The check passed, and the operation took the given amount of time.
+    Passed(Duration),
+
+    /// The check failed with the given error message.
+    Failed(String),
+
+    /// The check was not performed because an earlier check failed.
+    Skipped,
+}
+
+impl CheckStatus {
+    /// Return whether the check passed.
+    pub fn is_passed(&self) -> bool {
+        matches!(self, CheckStatus::Passed(_))
+    }
+
+    /// Return the amount of time the operation took, or `None` if the check didn't pass.
+    pub fn latency(&self) -> Option<Duration> {
+        match self {
+            CheckStatus::Passed(latency) => Some(*latency),
+            _ => None,
+        }
+    }
+}
+
+/// Diagnostic information about the health of a [`DataStore`].
+///
+/// This is returned by [`DataStore::health_check`]. Each check tests whether the data store can
+/// perform a basic operation and measures how long it takes.
+///
+/// [`DataStore`]: crate::store::DataStore
+/// [`DataStore::health_check`]: crate::store::DataStore::health_check
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct HealthReport {
+    /// Whether the data store can list its blocks.
+    ///
+    /// This is the first check performed, and it's a good measure of the round-trip latency of
+    /// the data store.
+    pub list: CheckStatus,
+
+    /// Whether the data store can write a new block.
+    ///
+    /// This fails if the data store is read-only or the user doesn't have permission to write to
+    /// it.
+    pub write: CheckStatus,
+
+    /// Whether the data store can read back the block it wrote with the same contents.
+    pub read: CheckStatus,
+
+    /// Whether the data store can remove the block it wrote.
+    pub remove: CheckStatus,
+}
+
+impl HealthReport {
+    /// Return whether every check passed.
+    pub fn is_healthy(&self) -> bool {
+        self.list.is_passed()
+            && self.write.is_passed()
+            && self.read.is_passed()
+            && self.remove.is_passed()
+    }
+
+    /// Return the round-trip latency of the data store, or `None` if it couldn't be reached.
+    pub fn latency(&self) -> Option<Duration> {
+        self.list.latency()
+    }
+
+    /// Return an error describing the first check that failed, or `None` if every check passed.
+    pub fn error(&self) -> Option<super::Error> {
+        [
+            ("list blocks", &self.list),
+            ("write a block", &self.write),
+            ("read a block", &self.read),
+            ("remove a block", &self.remove),
+        ]
+        .into_iter()
+        .find_map(|(operation, status)| match status {
+            CheckStatus::Failed(message) => Some(super::Error::msg(format!(
+                "The health check failed: could not {}: {}",
+                operation, message
+            ))),
+            _ => None,
+        })
+    }
+}
+
+impl Display for HealthReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for (name, status) in [
+            ("list", &self.list),
+            ("write", &self.write),
+            ("read", &self.read),
+            ("remove", &self.remove),
+        ] {
+            match status {
+                CheckStatus::Passed(latency) => writeln!(f, "{}: passed ({:?})", name, latency)?,
+                CheckStatus::Failed(message) => writeln!(f, "{}: failed ({})", name, message)?,
+                CheckStatus::Skipped => writeln!(f, "{}: skipped", name)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Time the given `operation` and return the outcome as a `CheckStatus`.
+fn time_check<T>(
+    operation: impl FnOnce() -> super::Result<T>,
+    validate: impl FnOnce(T) -> Result<(), String>,
+) -> CheckStatus {
+    let start = Instant::now();
+    let result = operation();
+    let latency = start.elapsed();
+    match result.map_err(|error| error.to_string()).and_then(validate) {
+        Ok(()) => CheckStatus::Passed(latency),
+        Err(message) => CheckStatus::Failed(message),
+    }
+}
+
+/// Run a health check on the given `store`.
+///
+/// This writes a temporary data block with a random ID and then removes it. If the process is
+/// interrupted before it can be removed, the block is unreferenced and will be removed the next
+/// time a repository in the data store is cleaned.
+pub(super) fn check_store<S: DataStore + ?Sized>(store: &mut S) -> HealthReport {
+    let list = time_check(|| store.list_blocks(BlockType::Data), |_| Ok(()));
+    if !list.is_passed() {
+        return HealthReport {
+            list,
+            write: CheckStatus::Skipped,
+            read: CheckStatus::Skipped,
+            remove: CheckStatus::Skipped,
+        };
+    }
+
+    let key = BlockKey::Data(Uuid::new_v4().into());
+    let data = (0..TEST_BLOCK_SIZE)
+        .map(|index| index as u8)
+        .collect::<Vec<_>>();
+
+    let write = time_check(|| store.write_block(key, &data), |_| Ok(()));
+    if !write.is_passed() {
+        return HealthReport {
+            list,
+            write,
+            read: CheckStatus::Skipped,
+            remove: CheckStatus::Skipped,
+        };
+    }
+
+    let read = time_check(
+        || store.read_block(key),
+        |actual| match actual {
+            Some(actual) if actual == data => Ok(()),
+            Some(_) => Err(String::from("The block read back was different.")),
+            None => Err(String::from("The block written was not found.")),
+        },
+    );
+
+    // Try to clean up the block even if it couldn't be read back.
+    let remove = time_check(
+        || {
+            store.remove_block(key)?;
+            store.read_block(key)
+        },
+        |actual| match actual {
+            Some(_) => Err(String::from(
+                "The block was still present after removing it.",
+            )),
+            None => Ok(()),
+        },
+    );
+
+    HealthReport {
+        list,
+        write,
+        read,
+        remove,
+    }
+}
@@ -1,9 +1,24 @@
 use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
 use std::sync::{Arc, Mutex};
 
-use super::data_store::{BlockId, BlockKey, BlockType, DataStore};
+use uuid::{uuid, Uuid};
+
+use super::data_store::{block_range, BlockId, BlockKey, BlockType, DataStore};
 use super::open_store::OpenStore;
 
+/// A UUID which acts as the version ID of the snapshot format.
+const SNAPSHOT_VERSION: Uuid = uuid!("9b8e6f0c-2d4a-4c71-8e3f-5a1b7d9c0e26");
+
+// The tags which identify the kind of each block in a snapshot.
+const DATA_TAG: u8 = 0;
+const LOCK_TAG: u8 = 1;
+const HEADER_TAG: u8 = 2;
+const SUPER_TAG: u8 = 3;
+const VERSION_TAG: u8 = 4;
+
 #[derive(Debug, Clone, Default)]
 struct BlockMap {
     data: HashMap<BlockId, Vec<u8>>,
@@ -13,6 +28,135 @@ struct BlockMap {
     version: Option<Vec<u8>>,
 }
 
+/// Write a single block to a snapshot.
+fn write_entry(
+    writer: &mut impl Write,
+    tag: u8,
+    id: Option<BlockId>,
+    data: &[u8],
+) -> io::Result<()> {
+    writer.write_all(&[tag])?;
+    if let Some(id) = id {
+        writer.write_all(id.as_ref().as_bytes())?;
+    }
+    writer.write_all(&(data.len() as u64).to_le_bytes())?;
+    writer.write_all(data)
+}
+
+impl BlockMap {
+    /// Insert the block with the given `key`, replacing it if it already exists.
+    fn insert(&mut self, key: BlockKey, data: Vec<u8>) {
+        match key {
+            BlockKey::Data(id) => {
+                self.data.insert(id, data);
+            }
+            BlockKey::Lock(id) => {
+                self.locks.insert(id, data);
+            }
+            BlockKey::Header(id) => {
+                self.headers.insert(id, data);
+            }
+            BlockKey::Super => {
+                self.superblock = Some(data);
+            }
+            BlockKey::Version => {
+                self.version = Some(data);
+            }
+        }
+    }
+
+    /// Write a snapshot of every block to `writer`.
+    ///
+    /// A snapshot consists of the snapshot version ID followed by each block. Each block is a tag
+    /// identifying its kind, the block ID if it has one, its length as a little-endian `u64`, and
+    /// its contents.
+    fn write_snapshot(&self, writer: &mut impl Write) -> io::Result<()> {
+        writer.write_all(SNAPSHOT_VERSION.as_bytes())?;
+        for (tag, blocks) in [
+            (DATA_TAG, &self.data),
+            (LOCK_TAG, &self.locks),
+            (HEADER_TAG, &self.headers),
+        ] {
+            for (id, data) in blocks {
+                write_entry(writer, tag, Some(*id), data)?;
+            }
+        }
+        if let Some(data) = &self.superblock {
+            write_entry(writer, SUPER_TAG, None, data)?;
+        }
+        if let Some(data) = &self.version {
+            write_entry(writer, VERSION_TAG, None, data)?;
+        }
+        Ok(())
+    }
+
+    /// Read a snapshot which was written with `write_snapshot` from `reader`.
+    ///
+    /// # Errors
+    /// - `Error::UnsupportedStore`: The data is not a snapshot or is an unsupported version.
+    /// - `Error::Deserialize`: The snapshot is corrupt.
+    /// - `Error::Io`: An I/O error occurred.
+    fn read_snapshot(reader: &mut impl Read) -> crate::Result<Self> {
+        let mut version = [0u8; 16];
+        match reader.read_exact(&mut version) {
+            Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => {
+                return Err(crate::Error::UnsupportedStore)
+            }
+            result => result?,
+        }
+        if Uuid::from_bytes(version) != SNAPSHOT_VERSION {
+            return Err(crate::Error::UnsupportedStore);
+        }
+
+        let mut block_map = BlockMap::default();
+
+        loop {
+            let mut tag = [0u8; 1];
+            if reader.read(&mut tag)? == 0 {
+                break;
+            }
+
+            let key = match tag[0] {
+                DATA_TAG => BlockKey::Data(read_block_id(reader)?),
+                LOCK_TAG => BlockKey::Lock(read_block_id(reader)?),
+                HEADER_TAG => BlockKey::Header(read_block_id(reader)?),
+                SUPER_TAG => BlockKey::Super,
+                VERSION_TAG => BlockKey::Version,
+                _ => return Err(crate::Error::Deserialize),
+            };
+
+            let mut len_bytes = [0u8; 8];
+            read_entry_bytes(reader, &mut len_bytes)?;
+            let len = u64::from_le_bytes(len_bytes);
+            let mut data = Vec::new();
+            if reader.take(len).read_to_end(&mut data)? as u64 != len {
+                return Err(crate::Error::Deserialize);
+            }
+
+            block_map.insert(key, data);
+        }
+
+        Ok(block_map)
+    }
+}
+
+/// Fill `buffer` from `reader`, treating the end of the stream as a corrupt snapshot.
+fn read_entry_bytes(reader: &mut impl Read, buffer: &mut [u8]) -> crate::Result<()> {
+    match reader.read_exact(buffer) {
+        Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => {
+            Err(crate::Error::Deserialize)
+        }
+        result => Ok(result?),
+    }
+}
+
+/// Read a block ID from a snapshot.
+fn read_block_id(reader: &mut impl Read) -> crate::Result<BlockId> {
+    let mut id_bytes = [0u8; 16];
+    read_entry_bytes(reader, &mut id_bytes)?;
+    Ok(BlockId::new(Uuid::from_bytes(id_bytes)))
+}
+
 /// The configuration for opening a [`MemoryStore`].
 ///
 /// [`MemoryStore`]: crate::store::MemoryStore
@@ -24,6 +168,34 @@ impl MemoryConfig {
     pub fn new() -> Self {
         MemoryConfig(Arc::new(Mutex::new(BlockMap::default())))
     }
+
+    /// Return a snapshot of the contents of the stores opened with this config.
+    ///
+    /// See [`MemoryStore::to_bytes`].
+    ///
+    /// [`MemoryStore::to_bytes`]: crate::store::MemoryStore::to_bytes
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut snapshot = Vec::new();
+        self.0
+            .lock()
+            .unwrap()
+            .write_snapshot(&mut snapshot)
+            .expect("Writing to a buffer cannot fail.");
+        snapshot
+    }
+
+    /// Create a new `MemoryConfig` from a `snapshot` returned by [`to_bytes`].
+    ///
+    /// # Errors
+    /// - `Error::UnsupportedStore`: The data is not a snapshot or is an unsupported version.
+    /// - `Error::Deserialize`: The snapshot is corrupt.
+    ///
+    /// [`to_bytes`]: crate::store::MemoryConfig::to_bytes
+    pub fn from_bytes(snapshot: &[u8]) -> crate::Result<Self> {
+        let mut reader = snapshot;
+        let block_map = BlockMap::read_snapshot(&mut reader)?;
+        Ok(MemoryConfig(Arc::new(Mutex::new(block_map))))
+    }
 }
 
 impl OpenStore for MemoryConfig {
@@ -43,34 +215,84 @@ impl OpenStore for MemoryConfig {
 ///
 /// None of the methods in this data store will ever return `Err`.
 ///
+/// The contents of a `MemoryStore` can be saved to a byte buffer with [`to_bytes`] or to a file
+/// with [`save`] and restored later, which allows small repositories to be persisted in test
+/// fixtures or in environments where no other data store is available.
+///
 /// You can use [`MemoryConfig`] to open a data store of this type.
 ///
 /// [`MemoryConfig`]: crate::store::MemoryConfig
+/// [`to_bytes`]: crate::store::MemoryStore::to_bytes
+/// [`save`]: crate::store::MemoryStore::save
 #[derive(Debug)]
 pub struct MemoryStore {
     blocks: Arc<Mutex<BlockMap>>,
 }
 
+impl MemoryStore {
+    /// Return a snapshot of the contents of this store.
+    ///
+    /// The snapshot contains every block in the store, and it can be restored with
+    /// [`from_bytes`]. If a repository is open on this store, changes which have not been committed
+    /// are not included.
+    ///
+    /// [`from_bytes`]: crate::store::MemoryStore::from_bytes
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut snapshot = Vec::new();
+        self.blocks
+            .lock()
+            .unwrap()
+            .write_snapshot(&mut snapshot)
+            .expect("Writing to a buffer cannot fail.");
+        snapshot
+    }
+
+    /// Create a new `MemoryStore` from a `snapshot` returned by [`to_bytes`].
+    ///
+    /// # Errors
+    /// - `Error::UnsupportedStore`: The data is not a snapshot or is an unsupported version.
+    /// - `Error::Deserialize`: The snapshot is corrupt.
+    ///
+    /// [`to_bytes`]: crate::store::MemoryStore::to_bytes
+    pub fn from_bytes(snapshot: &[u8]) -> crate::Result<Self> {
+        let mut reader = snapshot;
+        let block_map = BlockMap::read_snapshot(&mut reader)?;
+        Ok(MemoryStore {
+            blocks: Arc::new(Mutex::new(block_map)),
+        })
+    }
+
+    /// Write a snapshot of the contents of this store to the file at `path`.
+    ///
+    /// This creates the file if it doesn't exist and replaces it if it does.
+    ///
+    /// # Errors
+    /// - `Error::Io`: An I/O error occurred.
+    pub fn save(&self, path: impl AsRef<Path>) -> crate::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.blocks.lock().unwrap().write_snapshot(&mut writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Create a new `MemoryStore` from a snapshot in the file at `path`.
+    ///
+    /// # Errors
+    /// - `Error::UnsupportedStore`: The file is not a snapshot or is an unsupported version.
+    /// - `Error::Deserialize`: The snapshot is corrupt.
+    /// - `Error::Io`: An I/O error occurred.
+    pub fn load(path: impl AsRef<Path>) -> crate::Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let block_map = BlockMap::read_snapshot(&mut reader)?;
+        Ok(MemoryStore {
+            blocks: Arc::new(Mutex::new(block_map)),
+        })
+    }
+}
+
 impl DataStore for MemoryStore {
     fn write_block(&mut self, key: BlockKey, data: &[u8]) -> super::Result<()> {
-        let mut block_map = self.blocks.lock().unwrap();
-        match key {
-            BlockKey::Data(id) => {
-                block_map.data.insert(id, data.to_owned());
-            }
-            BlockKey::Lock(id) => {
-                block_map.locks.insert(id, data.to_owned());
-            }
-            BlockKey::Header(id) => {
-                block_map.headers.insert(id, data.to_owned());
-            }
-            BlockKey::Super => {
-                block_map.superblock = Some(data.to_owned());
-            }
-            BlockKey::Version => {
-                block_map.version = Some(data.to_owned());
-            }
-        }
+        self.blocks.lock().unwrap().insert(key, data.to_owned());
         Ok(())
     }
 
@@ -85,6 +307,27 @@ impl DataStore for MemoryStore {
         })
     }
 
+    fn supports_range_reads(&self) -> bool {
+        true
+    }
+
+    fn read_block_range(
+        &mut self,
+        key: BlockKey,
+        offset: u64,
+        len: u64,
+    ) -> super::Result<Option<Vec<u8>>> {
+        let block_map = self.blocks.lock().unwrap();
+        let data = match key {
+            BlockKey::Data(id) => block_map.data.get(&id),
+            BlockKey::Lock(id) => block_map.locks.get(&id),
+            BlockKey::Header(id) => block_map.headers.get(&id),
+            BlockKey::Super => block_map.superblock.as_ref(),
+            BlockKey::Version => block_map.version.as_ref(),
+        };
+        Ok(data.map(|data| block_range(data, offset, len).to_vec()))
+    }
+
     fn remove_block(&mut self, key: BlockKey) -> super::Result<()> {
         let mut block_map = self.blocks.lock().unwrap();
         match key {
@@ -115,4 +358,10 @@ impl DataStore for MemoryStore {
             BlockType::Header => block_map.headers.keys().copied().collect(),
         })
     }
+
+    fn try_clone(&self) -> Option<Box<dyn DataStore>> {
+        Some(Box::new(MemoryStore {
+            blocks: Arc::clone(&self.blocks),
+        }))
+    }
 }
@@ -0,0 +1,184 @@
+use std::collections::HashSet;
+
+use super::data_store::{BlockId, BlockKey, BlockType, DataStore};
+use super::error::{ErrorContext, StoreOperation};
+use super::open_store::OpenStore;
+
+/// Options for copying a repository between data stores with [`migrate`].
+///
+/// This type implements `Default`, which returns options that copy every block without verifying
+/// it.
+///
+/// [`migrate`]: crate::store::migrate
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+#[non_exhaustive]
+pub struct MigrateOptions {
+    /// Whether to read back each block from the destination and compare it to the source.
+    ///
+    /// This roughly doubles the number of operations on the destination data store, but it
+    /// detects blocks which were corrupted in transit or by the destination.
+    ///
+    /// The default value is `false`.
+    pub verify: bool,
+
+    /// Whether to resume a migration which was interrupted.
+    ///
+    /// Normally, migrating to a data store which already contains blocks fails. If this is `true`,
+    /// data blocks which already exist in the destination are assumed to have been copied by a
+    /// previous migration and are skipped. The other blocks are always copied.
+    ///
+    /// The default value is `false`.
+    pub resume: bool,
+}
+
+/// The progress of a [`migrate_with_progress`] call.
+///
+/// [`migrate_with_progress`]: crate::store::migrate_with_progress
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct MigrateProgress {
+    /// The key of the block which was just copied or skipped.
+    pub key: BlockKey,
+
+    /// The number of blocks which have been copied or skipped so far, including this one.
+    pub blocks: u64,
+
+    /// The total number of blocks to copy.
+    pub total_blocks: u64,
+
+    /// The number of bytes which have been copied so far.
+    pub bytes: u64,
+}
+
+/// Copy a repository from the data store opened by `source` to the data store opened by `dest`.
+///
+/// This copies every block from one data store to another without opening the repository, so it
+/// doesn't require the password of an encrypted repository and works with any repository type.
+/// This can be used to move a repository to a different kind of data store, like from a
+/// [`DirectoryStore`] to an [`S3Store`].
+///
+/// Data blocks are copied first and the blocks which identify the repository are copied last, so
+/// if the migration is interrupted, the destination won't contain a repository which is only
+/// partially copied. An interrupted migration can be continued with [`MigrateOptions::resume`].
+///
+/// The repository must not be open while it is being migrated.
+///
+/// # Errors
+/// - `Error::AlreadyExists`: The destination data store already contains blocks and
+/// [`MigrateOptions::resume`] is `false`.
+/// - `Error::NotFound`: There is no repository in the source data store.
+/// - `Error::Locked`: The repository in the source data store is locked.
+/// - `Error::InvalidData`: A block read back from the destination did not match the source.
+/// - `Error::UnsupportedStore`: Either data store is an unsupported format.
+/// - `Error::Store`: An error occurred with either data store.
+/// - `Error::Io`: An I/O error occurred.
+///
+/// [`DirectoryStore`]: crate::store::DirectoryStore
+/// [`S3Store`]: crate::store::S3Store
+/// [`MigrateOptions::resume`]: crate::store::MigrateOptions::resume
+pub fn migrate(
+    source: &impl OpenStore,
+    dest: &impl OpenStore,
+    options: MigrateOptions,
+) -> crate::Result<()> {
+    migrate_with_progress(source, dest, options, |_| {})
+}
+
+/// Copy a repository between data stores, reporting progress.
+///
+/// This is like [`migrate`], except that `progress` is called with a [`MigrateProgress`] after
+/// each block is copied or skipped.
+///
+/// # Errors
+/// See [`migrate`].
+///
+/// [`migrate`]: crate::store::migrate
+/// [`MigrateProgress`]: crate::store::MigrateProgress
+pub fn migrate_with_progress(
+    source: &impl OpenStore,
+    dest: &impl OpenStore,
+    options: MigrateOptions,
+    mut progress: impl FnMut(MigrateProgress),
+) -> crate::Result<()> {
+    let mut source = source.open()?;
+    let mut dest = dest.open()?;
+
+    if read_block(&mut source, BlockKey::Version)?.is_none() {
+        return Err(crate::Error::NotFound);
+    }
+    if !list_blocks(&mut source, BlockType::Lock)?.is_empty() {
+        return Err(crate::Error::Locked);
+    }
+
+    let existing_data = list_blocks(&mut dest, BlockType::Data)?
+        .into_iter()
+        .collect::<HashSet<_>>();
+    if !options.resume {
+        let dest_is_empty = existing_data.is_empty()
+            && list_blocks(&mut dest, BlockType::Header)?.is_empty()
+            && read_block(&mut dest, BlockKey::Super)?.is_none()
+            && read_block(&mut dest, BlockKey::Version)?.is_none();
+        if !dest_is_empty {
+            return Err(crate::Error::AlreadyExists);
+        }
+    }
+
+    // The blocks which identify the repository are copied last.
+    let mut keys = list_blocks(&mut source, BlockType::Data)?
+        .into_iter()
+        .map(BlockKey::Data)
+        .collect::<Vec<_>>();
+    keys.extend(
+        list_blocks(&mut source, BlockType::Header)?
+            .into_iter()
+            .map(BlockKey::Header),
+    );
+    keys.push(BlockKey::Super);
+    keys.push(BlockKey::Version);
+
+    let total_blocks = keys.len() as u64;
+    let mut bytes = 0;
+
+    for (index, key) in keys.into_iter().enumerate() {
+        let skip = matches!(key, BlockKey::Data(id) if existing_data.contains(&id));
+
+        if !skip {
+            // A block may have been removed since the blocks were listed.
+            if let Some(data) = read_block(&mut source, key)? {
+                dest.write_block(key, &data).map_err(|error| {
+                    crate::Error::Store(
+                        error.with_context(ErrorContext::block(StoreOperation::WriteBlock, key)),
+                    )
+                })?;
+
+                if options.verify && read_block(&mut dest, key)?.as_ref() != Some(&data) {
+                    return Err(crate::Error::InvalidData);
+                }
+
+                bytes += data.len() as u64;
+            }
+        }
+
+        progress(MigrateProgress {
+            key,
+            blocks: index as u64 + 1,
+            total_blocks,
+            bytes,
+        });
+    }
+
+    Ok(())
+}
+
+/// Read the block with the given `key` from `store`, attaching context to any error.
+fn read_block(store: &mut impl DataStore, key: BlockKey) -> crate::Result<Option<Vec<u8>>> {
+    store.read_block(key).map_err(|error| {
+        crate::Error::Store(error.with_context(ErrorContext::block(StoreOperation::ReadBlock, key)))
+    })
+}
+
+/// List the blocks of the given `kind` in `store`, attaching context to any error.
+fn list_blocks(store: &mut impl DataStore, kind: BlockType) -> crate::Result<Vec<BlockId>> {
+    store
+        .list_blocks(kind)
+        .map_err(|error| crate::Error::Store(error.with_context(ErrorContext::list(kind))))
+}
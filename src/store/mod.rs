@@ -13,34 +13,62 @@
 //! config types with [`OpenOptions`] to open repositories. You'll almost never need to use the
 //! [`OpenStore`] or [`DataStore`] traits directly.
 //!
+//! Some data stores wrap other data stores to add behavior to them. [`RetryStore`] retries failed
+//! operations with exponential backoff, and [`ThrottleStore`] limits the bandwidth used by a data
+//! store. These can be combined with any other data store.
+//!
+//! A repository can be moved from one data store to another with [`migrate`].
+//!
 //! [`DataStore`]: crate::store::DataStore
 //! [`OpenStore`]: crate::store::OpenStore
 //! [`OpenOptions`]: crate::repo::OpenOptions
+//! [`RetryStore`]: crate::store::RetryStore
+//! [`ThrottleStore`]: crate::store::ThrottleStore
+//! [`migrate`]: crate::store::migrate
 
 pub use self::data_store::{BlockId, BlockKey, BlockType, DataStore};
 #[cfg(feature = "store-directory")]
-pub use self::directory_store::{DirectoryConfig, DirectoryStore};
-pub use self::error::{Error, Result};
+pub use self::directory_store::{DirectoryConfig, DirectoryDurability, DirectoryStore};
+pub use self::error::{Error, ErrorContext, Result, StoreOperation};
+pub use self::health::{CheckStatus, HealthReport};
 pub use self::memory_store::{MemoryConfig, MemoryStore};
+pub use self::migrate::{migrate, migrate_with_progress, MigrateOptions, MigrateProgress};
 pub use self::open_store::OpenStore;
 #[cfg(feature = "store-rclone")]
-pub use self::rclone_store::{RcloneConfig, RcloneStore};
+pub use self::rclone_store::{RcloneConfig, RcloneError, RcloneMode, RcloneStore};
 #[cfg(feature = "store-redis")]
 pub use self::redis_store::{RedisAddr, RedisConfig, RedisStore};
+pub use self::retry_store::{RetryConfig, RetryPolicy, RetryStore};
 #[cfg(feature = "store-s3")]
-pub use self::s3_store::{S3Config, S3Credentials, S3Region, S3Store};
+pub use self::s3_store::{S3Config, S3Credentials, S3Multipart, S3Region, S3Store};
 #[cfg(feature = "store-sftp")]
 pub use self::sftp_store::{SftpAuth, SftpConfig, SftpStore};
+#[cfg(feature = "store-directory")]
+pub use self::sharded_directory_store::{
+    ShardPlacement, ShardedDirectoryConfig, ShardedDirectoryStore,
+};
 #[cfg(feature = "store-sqlite")]
-pub use self::sqlite_store::{SqliteConfig, SqliteStore};
+pub use self::sqlite_store::{SqliteConfig, SqliteJournalMode, SqliteStore, SqliteSynchronous};
+pub use self::throttle_store::{ThrottleConfig, ThrottlePolicy, ThrottleStore};
+#[cfg(feature = "store-webdav")]
+pub use self::webdav_store::{WebDavAuth, WebDavConfig, WebDavStore};
+
+pub(crate) use self::context_store::ContextStore;
 
+mod context_store;
 mod data_store;
 mod directory_store;
 mod error;
+mod health;
 mod memory_store;
+mod migrate;
 mod open_store;
 mod rclone_store;
 mod redis_store;
+mod retry_store;
 mod s3_store;
 mod sftp_store;
+mod sharded_directory_store;
 mod sqlite_store;
+mod throttle_store;
+mod webdav_store;
@@ -1,4 +1,4 @@
-use crate::store::DataStore;
+use crate::store::{DataStore, HealthReport};
 
 /// A value which can be used to open a `DataStore`.
 pub trait OpenStore {
@@ -16,4 +16,19 @@ pub trait OpenStore {
     /// - `Error::Store`: An error occurred with the data store.
     /// - `Error::Io`: An I/O error occurred.
     fn open(&self) -> crate::Result<Self::Store>;
+
+    /// Open the data store and check whether it is healthy.
+    ///
+    /// This opens the data store with [`open`] and then calls [`DataStore::health_check`] on it.
+    ///
+    /// # Errors
+    /// - `Error::UnsupportedStore`: The data store is an unsupported format.
+    /// - `Error::Store`: An error occurred opening the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    ///
+    /// [`open`]: crate::store::OpenStore::open
+    /// [`DataStore::health_check`]: crate::store::DataStore::health_check
+    fn health_check(&self) -> crate::Result<HealthReport> {
+        Ok(self.open()?.health_check())
+    }
 }
@@ -1,17 +1,28 @@
 #![cfg(feature = "store-rclone")]
 
-use std::io;
+use std::collections::BTreeMap;
+use std::fmt::{self, Display, Formatter};
+use std::io::{self, Read};
 use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, TcpStream, UdpSocket};
-use std::path::Path;
+use std::path::{Component, Path};
 use std::process::{Child, Command, Stdio};
 use std::thread::sleep;
 use std::time::Duration;
 
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
 use rand::distributions::{Alphanumeric, DistString};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use ureq::{Agent, AgentBuilder, Request, Response};
+use uuid::Uuid;
 
 use super::data_store::{BlockId, BlockKey, BlockType, DataStore};
 use super::open_store::OpenStore;
-use super::sftp_store::{SftpAuth, SftpConfig, SftpStore};
+use super::sftp_store::{
+    block_path, type_path, SftpAuth, SftpConfig, SftpStore, CURRENT_VERSION, STAGING_DIRECTORY,
+    STORE_DIRECTORY, VERSION_FILE,
+};
 
 /// Generate a random secure password for the SFTP server.
 fn generate_password(length: usize) -> String {
@@ -32,6 +43,15 @@ const PASSWORD_LENGTH: usize = 32;
 /// The username for authenticating the SSH connection.
 const SSH_USERNAME: &str = "rclone";
 
+/// The username for authenticating with a remote control daemon started by the store.
+const RC_USERNAME: &str = "rclone";
+
+/// The HTTP status code for a file which does not exist.
+const NOT_FOUND_CODE: u16 = 404;
+
+/// The boundary between the parts of a multipart upload.
+const MULTIPART_BOUNDARY: &str = "acid-store-block-boundary";
+
 /// The amount of time to wait between attempts to connect to the SFTP server.
 const CONNECT_WAIT_TIME: Duration = Duration::from_millis(100);
 
@@ -57,6 +77,25 @@ fn serve(port: u16, password: &str, config: &str) -> io::Result<Child> {
         .spawn()
 }
 
+/// Start a remote control daemon and return the daemon process.
+fn serve_rc(port: u16, password: &str) -> io::Result<Child> {
+    Command::new("rclone")
+        .args([
+            "rcd",
+            "--rc-serve",
+            "--rc-addr",
+            &format!("localhost:{}", port),
+            "--rc-user",
+            RC_USERNAME,
+            "--rc-pass",
+            password,
+        ])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::inherit())
+        .spawn()
+}
+
 /// Wait for a local TCP connection on the given `port` to connect and then drop the connection.
 fn wait_for_connection(port: u16) -> io::Result<()> {
     loop {
@@ -73,6 +112,64 @@ fn wait_for_connection(port: u16) -> io::Result<()> {
     Ok(())
 }
 
+/// Return the given relative `path` with its components separated by forward slashes.
+fn remote_path(path: &Path) -> String {
+    path.components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => name.to_str(),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Percent-encode `value` so it can be used as a segment of a URL path.
+fn encode_segment(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// How an [`RcloneStore`] communicates with rclone.
+///
+/// [`RcloneStore`]: crate::store::RcloneStore
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+#[cfg_attr(docsrs, doc(cfg(feature = "store-rclone")))]
+pub enum RcloneMode {
+    /// Serve the remote over SFTP with `rclone serve sftp` and access it over SFTP.
+    ///
+    /// This is the default.
+    #[default]
+    Sftp,
+
+    /// Start an `rclone rcd` daemon and access the remote through its HTTP API.
+    ///
+    /// The daemon only listens on the loopback interface and is stopped when the store is dropped.
+    Daemon,
+
+    /// Access the remote through the HTTP API of an `rclone rcd` daemon which is already running.
+    ///
+    /// This allows a single long-lived daemon to be shared between stores and processes. The
+    /// daemon must be started with the `--rc-serve` flag.
+    Connect {
+        /// The URL of the daemon, like `http://localhost:5572`.
+        url: String,
+
+        /// The username to authenticate with, as set with `--rc-user`.
+        username: String,
+
+        /// The password to authenticate with, as set with `--rc-pass`.
+        password: String,
+    },
+}
+
 /// The configuration for opening an [`RcloneStore`].
 ///
 /// [`RcloneStore`]: crate::store::RcloneStore
@@ -84,17 +181,65 @@ pub struct RcloneConfig {
     /// This is a string with the format `<remote>:<path>`, where `<remote>` is the name of the
     /// remote as configured using `rclone config` and `<path>` is the path of the directory on the
     /// remote to use.
+    ///
+    /// The remote doesn't need to be configured ahead of time. A remote of the form `:<backend>`,
+    /// like `:s3`, creates a remote of that type on the fly, which can be configured with
+    /// [`options`].
+    ///
+    /// [`options`]: crate::store::RcloneConfig::options
     pub config: String,
+
+    /// Options for the remote, like credentials, which override its configuration.
+    ///
+    /// These are the same options which can be set with `rclone config`, like `access_key_id` for
+    /// an S3 remote, and they are passed to rclone programmatically as part of the remote rather
+    /// than being read from the rclone config file. Passing options requires rclone version 1.55.0
+    /// or higher.
+    pub options: BTreeMap<String, String>,
+
+    /// How to communicate with rclone.
+    pub mode: RcloneMode,
 }
 
-impl OpenStore for RcloneConfig {
-    type Store = RcloneStore;
+impl RcloneConfig {
+    /// Create a new `RcloneConfig` for the given `<remote>:<path>` string.
+    ///
+    /// This serves the remote over SFTP and doesn't pass any options.
+    pub fn new(config: impl Into<String>) -> Self {
+        RcloneConfig {
+            config: config.into(),
+            options: BTreeMap::new(),
+            mode: RcloneMode::Sftp,
+        }
+    }
 
-    fn open(&self) -> crate::Result<Self::Store> {
+    /// Return the remote and path as an rclone connection string including the options.
+    fn remote(&self) -> String {
+        if self.options.is_empty() {
+            return self.config.clone();
+        }
+
+        // On-the-fly remotes start with a colon, so the separator is the next colon.
+        let search_start = usize::from(self.config.starts_with(':'));
+        let separator = match self.config[search_start..].find(':') {
+            Some(index) => search_start + index,
+            None => return self.config.clone(),
+        };
+
+        let mut remote = self.config[..separator].to_owned();
+        for (name, value) in &self.options {
+            remote.push_str(&format!(",{}=\"{}\"", name, value.replace('"', "\"\"")));
+        }
+        remote.push_str(&self.config[separator..]);
+        remote
+    }
+
+    /// Open a store which accesses the remote over SFTP.
+    fn open_sftp(&self) -> crate::Result<RcloneStore> {
         // Serve the rclone remote over SFTP and wait for the server to start.
         let port = ephemeral_port()?;
         let password = generate_password(PASSWORD_LENGTH);
-        let server_process = serve(port, &password, &self.config)?;
+        let server_process = serve(port, &password, &self.remote())?;
         wait_for_connection(port)?;
 
         let sftp_config = SftpConfig {
@@ -104,55 +249,426 @@ impl OpenStore for RcloneConfig {
                 password,
             },
             path: Path::new("").to_owned(),
+            // The server runs locally, so the connection won't be dropped while idle.
+            keepalive: None,
+            max_reconnects: 0,
         };
 
         let sftp_store = sftp_config.open()?;
 
         Ok(RcloneStore {
-            sftp_store,
+            backend: RcloneBackend::Sftp(sftp_store),
+            server_process: Some(server_process),
+        })
+    }
+
+    /// Open a store which accesses the remote through the HTTP API of a daemon at `url`.
+    fn open_rc(
+        &self,
+        url: &str,
+        username: &str,
+        password: &str,
+        server_process: Option<Child>,
+    ) -> crate::Result<RcloneStore> {
+        let client = RcClient {
+            agent: AgentBuilder::new().build(),
+            url: url.trim_end_matches('/').to_owned(),
+            auth: format!(
+                "Basic {}",
+                BASE64.encode(format!("{}:{}", username, password))
+            ),
+            fs: self.remote(),
+        };
+        if let Err(error) = client.initialize() {
+            if let Some(mut server_process) = server_process {
+                server_process.kill().ok();
+            }
+            return Err(error);
+        }
+
+        Ok(RcloneStore {
+            backend: RcloneBackend::Rc(client),
             server_process,
         })
     }
 }
 
+impl OpenStore for RcloneConfig {
+    type Store = RcloneStore;
+
+    fn open(&self) -> crate::Result<Self::Store> {
+        match &self.mode {
+            RcloneMode::Sftp => self.open_sftp(),
+            RcloneMode::Daemon => {
+                // Start the daemon and wait for it to start listening.
+                let port = ephemeral_port()?;
+                let password = generate_password(PASSWORD_LENGTH);
+                let server_process = serve_rc(port, &password)?;
+                wait_for_connection(port)?;
+
+                self.open_rc(
+                    &format!("http://localhost:{}", port),
+                    RC_USERNAME,
+                    &password,
+                    Some(server_process),
+                )
+            }
+            RcloneMode::Connect {
+                url,
+                username,
+                password,
+            } => self.open_rc(url, username, password, None),
+        }
+    }
+}
+
+/// An error returned by the rclone remote control API.
+///
+/// When an [`RcloneStore`] accesses a remote through an `rclone rcd` daemon, errors returned by
+/// the daemon can be recovered from a [`store::Error`] by downcasting it to this type.
+///
+/// [`RcloneStore`]: crate::store::RcloneStore
+/// [`store::Error`]: crate::store::Error
+#[derive(Debug, PartialEq, Eq, Clone, Deserialize)]
+#[cfg_attr(docsrs, doc(cfg(feature = "store-rclone")))]
+pub struct RcloneError {
+    #[serde(rename = "error")]
+    message: String,
+    status: u16,
+    #[serde(default)]
+    path: String,
+}
+
+impl RcloneError {
+    /// The error message returned by rclone.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// The HTTP status code of the response.
+    ///
+    /// This is `404` when a file or directory does not exist.
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    /// The API method which returned the error, like `operations/deletefile`.
+    pub fn method(&self) -> &str {
+        &self.path
+    }
+}
+
+impl Display for RcloneError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "rclone returned an error: {}", self.message)
+    }
+}
+
+impl std::error::Error for RcloneError {}
+
+/// An entry in the response to an `operations/list` call.
+#[derive(Debug, Deserialize)]
+struct ListItem {
+    #[serde(rename = "Name")]
+    name: String,
+}
+
+/// The response to an `operations/list` call.
+#[derive(Debug, Deserialize)]
+struct ListResponse {
+    list: Vec<ListItem>,
+}
+
+/// A client for the HTTP API of an `rclone rcd` daemon.
+#[derive(Debug)]
+struct RcClient {
+    agent: Agent,
+    url: String,
+    auth: String,
+
+    /// The remote and path as an rclone connection string.
+    fs: String,
+}
+
+impl RcClient {
+    /// Create a new request with the given `method` for the given `url`.
+    fn request(&self, method: &str, url: &str) -> Request {
+        self.agent
+            .request(method, url)
+            .set("Authorization", &self.auth)
+    }
+
+    /// Convert the error from a request to the daemon into a store error.
+    fn error(error: ureq::Error) -> super::Error {
+        match error {
+            ureq::Error::Status(status, response) => {
+                let body = response.into_string().unwrap_or_default();
+                match serde_json::from_str::<RcloneError>(&body) {
+                    Ok(error) => error.into(),
+                    Err(_) => RcloneError {
+                        message: body,
+                        status,
+                        path: String::new(),
+                    }
+                    .into(),
+                }
+            }
+            error => error.into(),
+        }
+    }
+
+    /// Create the directories for the store if necessary and check its version.
+    fn initialize(&self) -> crate::Result<()> {
+        // Object storage doesn't have directories, so these are only needed on some remotes.
+        for directory in [
+            Path::new(STORE_DIRECTORY).to_owned(),
+            Path::new(STAGING_DIRECTORY).to_owned(),
+            type_path(BlockType::Data),
+            type_path(BlockType::Lock),
+            type_path(BlockType::Header),
+        ] {
+            self.call(
+                "operations/mkdir",
+                json!({ "fs": self.fs, "remote": remote_path(&directory) }),
+            )
+            .map_err(crate::Error::Store)?;
+        }
+
+        match self.read_file(VERSION_FILE).map_err(crate::Error::Store)? {
+            Some(version_id) => {
+                if version_id != CURRENT_VERSION.as_bytes() {
+                    return Err(crate::Error::UnsupportedStore);
+                }
+            }
+            None => {
+                self.write_file(VERSION_FILE, CURRENT_VERSION.as_bytes())
+                    .map_err(crate::Error::Store)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Call the API `method` with the given JSON `params` and return the JSON response.
+    fn call(&self, method: &str, params: Value) -> super::Result<Value> {
+        let response = self
+            .request("POST", &format!("{}/{}", self.url, method))
+            .set("Content-Type", "application/json")
+            .send_string(&params.to_string())
+            .map_err(Self::error)?;
+        Ok(serde_json::from_reader(response.into_reader())?)
+    }
+
+    /// Read the file at the given `remote` path, returning `None` if it doesn't exist.
+    fn read_file(&self, remote: &str) -> super::Result<Option<Vec<u8>>> {
+        let url = format!(
+            "{}/{}/{}",
+            self.url,
+            encode_segment(&format!("[{}]", self.fs)),
+            remote
+                .split('/')
+                .map(encode_segment)
+                .collect::<Vec<_>>()
+                .join("/")
+        );
+        let response = match self.request("GET", &url).call() {
+            Ok(response) => response,
+            Err(ureq::Error::Status(NOT_FOUND_CODE, _)) => return Ok(None),
+            Err(error) => return Err(Self::error(error)),
+        };
+        Ok(Some(read_body(response)?))
+    }
+
+    /// Write `data` to a staging file and move it to the given `remote` path.
+    fn write_file(&self, remote: &str, data: &[u8]) -> super::Result<()> {
+        let staging_name = Uuid::new_v4().as_hyphenated().to_string();
+
+        let mut body = format!(
+            "--{boundary}\r\nContent-Disposition: form-data; name=\"file0\"; filename=\"{name}\"\r\n\
+            Content-Type: application/octet-stream\r\n\r\n",
+            boundary = MULTIPART_BOUNDARY,
+            name = staging_name,
+        )
+        .into_bytes();
+        body.extend_from_slice(data);
+        body.extend_from_slice(format!("\r\n--{}--\r\n", MULTIPART_BOUNDARY).as_bytes());
+
+        self.request("POST", &format!("{}/operations/uploadfile", self.url))
+            .query("fs", &self.fs)
+            .query("remote", STAGING_DIRECTORY)
+            .set(
+                "Content-Type",
+                &format!("multipart/form-data; boundary={}", MULTIPART_BOUNDARY),
+            )
+            .send_bytes(&body)
+            .map_err(Self::error)?;
+
+        // Move the block into place so that it is never partially written.
+        let result = self.call(
+            "operations/movefile",
+            json!({
+                "srcFs": self.fs,
+                "srcRemote": format!("{}/{}", STAGING_DIRECTORY, staging_name),
+                "dstFs": self.fs,
+                "dstRemote": remote,
+            }),
+        );
+        if result.is_err() {
+            // Attempt to clean up the staged block.
+            self.remove_file(&format!("{}/{}", STAGING_DIRECTORY, staging_name))
+                .ok();
+        }
+
+        result.map(|_| ())
+    }
+
+    /// Remove the file at the given `remote` path if it exists.
+    fn remove_file(&self, remote: &str) -> super::Result<()> {
+        match self.call(
+            "operations/deletefile",
+            json!({ "fs": self.fs, "remote": remote }),
+        ) {
+            Ok(_) => Ok(()),
+            Err(error) => match error.downcast_ref::<RcloneError>() {
+                Some(rclone_error) if rclone_error.status == NOT_FOUND_CODE => Ok(()),
+                _ => Err(error),
+            },
+        }
+    }
+
+    /// Return the names of the files in the given `remote` directory and its descendants.
+    fn list_files(&self, remote: &str) -> super::Result<Vec<String>> {
+        let response = match self.call(
+            "operations/list",
+            json!({
+                "fs": self.fs,
+                "remote": remote,
+                "opt": { "recurse": true, "filesOnly": true, "noModTime": true, "noMimeType": true },
+            }),
+        ) {
+            Ok(response) => response,
+            Err(error) => {
+                return match error.downcast_ref::<RcloneError>() {
+                    Some(rclone_error) if rclone_error.status == NOT_FOUND_CODE => Ok(Vec::new()),
+                    _ => Err(error),
+                }
+            }
+        };
+
+        let response: ListResponse = serde_json::from_value(response)?;
+        Ok(response.list.into_iter().map(|item| item.name).collect())
+    }
+}
+
+/// Read the body of the given `response`.
+fn read_body(response: Response) -> io::Result<Vec<u8>> {
+    let mut body = Vec::new();
+    response.into_reader().read_to_end(&mut body)?;
+    Ok(body)
+}
+
+/// How an `RcloneStore` accesses the remote.
+#[derive(Debug)]
+enum RcloneBackend {
+    Sftp(SftpStore),
+    Rc(RcClient),
+}
+
 /// A `DataStore` which stores data in cloud storage using rclone.
 ///
 /// This is a data store which is backed by [rclone](https://rclone.org/), allowing access to a wide
 /// variety of cloud storage providers.
 ///
 /// To use this data store, rclone must be installed and available on the `PATH`. Rclone version
-/// 1.48.0 or higher is required.
+/// 1.48.0 or higher is required. Accessing the remote through an `rclone rcd` daemon, which avoids
+/// the overhead of SFTP and returns errors as [`RcloneError`] values, requires version 1.55.0 or
+/// higher. See [`RcloneMode`].
+///
+/// The layout of the files on the remote is the same in every mode, so a store can be opened in a
+/// different mode than it was created with.
 ///
 /// You can use [`RcloneConfig`] to open a data store of this type.
 ///
 /// [`RcloneConfig`]: crate::store::RcloneConfig
+/// [`RcloneError`]: crate::store::RcloneError
+/// [`RcloneMode`]: crate::store::RcloneMode
 #[derive(Debug)]
 #[cfg_attr(docsrs, doc(cfg(feature = "store-rclone")))]
 pub struct RcloneStore {
-    sftp_store: SftpStore,
-    server_process: Child,
+    backend: RcloneBackend,
+
+    /// The rclone process which was started by this store, if any.
+    server_process: Option<Child>,
 }
 
 impl DataStore for RcloneStore {
     fn write_block(&mut self, key: BlockKey, data: &[u8]) -> super::Result<()> {
-        self.sftp_store.write_block(key, data)
+        match &mut self.backend {
+            RcloneBackend::Sftp(store) => store.write_block(key, data),
+            RcloneBackend::Rc(client) => client.write_file(&remote_path(&block_path(key)), data),
+        }
     }
 
     fn read_block(&mut self, key: BlockKey) -> super::Result<Option<Vec<u8>>> {
-        self.sftp_store.read_block(key)
+        match &mut self.backend {
+            RcloneBackend::Sftp(store) => store.read_block(key),
+            RcloneBackend::Rc(client) => client.read_file(&remote_path(&block_path(key))),
+        }
     }
 
     fn remove_block(&mut self, key: BlockKey) -> super::Result<()> {
-        self.sftp_store.remove_block(key)
+        match &mut self.backend {
+            RcloneBackend::Sftp(store) => store.remove_block(key),
+            RcloneBackend::Rc(client) => client.remove_file(&remote_path(&block_path(key))),
+        }
     }
 
     fn list_blocks(&mut self, kind: BlockType) -> super::Result<Vec<BlockId>> {
-        self.sftp_store.list_blocks(kind)
+        match &mut self.backend {
+            RcloneBackend::Sftp(store) => store.list_blocks(kind),
+            RcloneBackend::Rc(client) => client
+                .list_files(&remote_path(&type_path(kind)))?
+                .into_iter()
+                .map(|name| {
+                    Uuid::parse_str(&name)
+                        .map(BlockId::from)
+                        .map_err(|_| super::Error::msg("Block file name is invalid."))
+                })
+                .collect(),
+        }
     }
 }
 
 impl Drop for RcloneStore {
     fn drop(&mut self) {
-        self.server_process.kill().ok();
+        if let Some(server_process) = &mut self.server_process {
+            server_process.kill().ok();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use spectral::prelude::*;
+
+    use super::RcloneConfig;
+
+    #[test]
+    fn options_are_added_to_remote() {
+        let mut config = RcloneConfig::new(":s3:bucket/path");
+        config.options.insert("provider".into(), "AWS".into());
+        config
+            .options
+            .insert("secret_access_key".into(), "a\"b,c:d".into());
+
+        assert_that!(config.remote().as_str())
+            .is_equal_to(r#":s3,provider="AWS",secret_access_key="a""b,c:d":bucket/path"#);
+    }
+
+    #[test]
+    fn remote_without_options_is_unchanged() {
+        let config = RcloneConfig::new("remote:path");
+
+        assert_that!(config.remote().as_str()).is_equal_to("remote:path");
     }
 }
@@ -3,8 +3,9 @@
 use std::fmt::{self, Debug, Formatter};
 use std::path::PathBuf;
 
+use redis::cluster::ClusterClient;
 use redis::{
-    Client, Commands, Connection, ConnectionAddr, ConnectionInfo, IntoConnectionInfo,
+    cmd, Client, ConnectionAddr, ConnectionInfo, ConnectionLike, IntoConnectionInfo,
     RedisConnectionInfo,
 };
 use uuid::Uuid;
@@ -22,6 +23,12 @@ const SUPER_KEY: &str = "store:super";
 const REPO_VERSION_KEY: &str = "store:version";
 const STORE_VERSION_KEY: &str = "version";
 
+/// The prefix of every key in a store on a Redis Cluster.
+///
+/// This is a hash tag, which makes Redis Cluster store every key in the store on the same node.
+/// Otherwise, we would need to query every node to list the blocks in the store.
+const CLUSTER_KEY_PREFIX: &str = "{acid-store}:";
+
 fn block_key(key: BlockKey) -> String {
     match key {
         BlockKey::Data(id) => format!("{}:{}", DATA_KEY, id.as_ref().as_hyphenated()),
@@ -41,6 +48,13 @@ pub enum RedisAddr {
 
     /// The path of a Unix socket.
     Unix(PathBuf),
+
+    /// The hostnames and ports of one or more nodes in a Redis Cluster.
+    ///
+    /// The rest of the nodes in the cluster are discovered automatically. Every key in the store is
+    /// stored on the same node in the cluster, so the size of the store is limited by the memory
+    /// of that node.
+    Cluster(Vec<(String, u16)>),
 }
 
 /// The configuration for opening a [`RedisStore`].
@@ -60,6 +74,12 @@ pub struct RedisConfig {
 
     /// The optional password to use for the connection.
     pub password: Option<String>,
+
+    /// Whether to connect to the server using TLS.
+    ///
+    /// This is required by most managed Redis offerings. This is ignored for Unix sockets and is
+    /// not supported for Redis Cluster.
+    pub tls: bool,
 }
 
 impl RedisConfig {
@@ -70,10 +90,14 @@ impl RedisConfig {
     /// For a TCP connection, the URL format is:
     /// `redis://[<username>][:<passwd>@]<hostname>[:port][/<db>]`.
     ///
+    /// For a TCP connection using TLS, the URL format is:
+    /// `rediss://[<username>][:<passwd>@]<hostname>[:port][/<db>]`.
+    ///
     /// For a Unix socket connection, the URL format is:
     /// `redis+unix:///<path>[?db=<db>[&pass=<password>][&user=<username>]]`.
     pub fn from_url(url: &str) -> Option<Self> {
         let connection_info = url.into_connection_info().ok()?;
+        let tls = matches!(connection_info.addr, ConnectionAddr::TcpTls { .. });
         Some(RedisConfig {
             addr: match connection_info.addr {
                 ConnectionAddr::Tcp(host, port) => RedisAddr::Tcp(host, port),
@@ -83,26 +107,72 @@ impl RedisConfig {
             db: connection_info.redis.db,
             username: connection_info.redis.username,
             password: connection_info.redis.password,
+            tls,
         })
     }
-}
 
-impl OpenStore for RedisConfig {
-    type Store = RedisStore;
-
-    fn open(&self) -> crate::Result<Self::Store> {
-        let info = ConnectionInfo {
-            addr: match self.addr.clone() {
-                RedisAddr::Tcp(host, port) => ConnectionAddr::Tcp(host, port),
-                RedisAddr::Unix(path) => ConnectionAddr::Unix(path),
-            },
+    /// Return the information for connecting to the server at `addr`.
+    fn connection_info(&self, addr: ConnectionAddr) -> ConnectionInfo {
+        ConnectionInfo {
+            addr,
             redis: RedisConnectionInfo {
                 db: self.db,
                 username: self.username.clone(),
                 password: self.password.clone(),
             },
+        }
+    }
+
+    /// Return the address of the server at `host` and `port`.
+    fn tcp_addr(&self, host: String, port: u16) -> ConnectionAddr {
+        if self.tls {
+            ConnectionAddr::TcpTls {
+                host,
+                port,
+                insecure: false,
+            }
+        } else {
+            ConnectionAddr::Tcp(host, port)
+        }
+    }
+
+    /// Connect to the server or cluster.
+    fn connect(&self) -> super::Result<Box<dyn ConnectionLike + Send>> {
+        Ok(match self.addr.clone() {
+            RedisAddr::Tcp(host, port) => {
+                let info = self.connection_info(self.tcp_addr(host, port));
+                Box::new(Client::open(info)?.get_connection()?)
+            }
+            RedisAddr::Unix(path) => {
+                let info = self.connection_info(ConnectionAddr::Unix(path));
+                Box::new(Client::open(info)?.get_connection()?)
+            }
+            RedisAddr::Cluster(nodes) => {
+                if self.tls {
+                    return Err(super::Error::msg(
+                        "TLS is not supported with Redis Cluster.",
+                    ));
+                }
+                let nodes = nodes
+                    .into_iter()
+                    .map(|(host, port)| self.connection_info(ConnectionAddr::Tcp(host, port)))
+                    .collect::<Vec<_>>();
+                Box::new(ClusterClient::open(nodes)?.get_connection()?)
+            }
+        })
+    }
+}
+
+impl OpenStore for RedisConfig {
+    type Store = RedisStore;
+
+    fn open(&self) -> crate::Result<Self::Store> {
+        let connection = self.connect().map_err(crate::Error::Store)?;
+        let key_prefix = match self.addr {
+            RedisAddr::Cluster(_) => CLUSTER_KEY_PREFIX,
+            _ => "",
         };
-        RedisStore::from_connection_info(info)
+        RedisStore::new(connection, key_prefix)
     }
 }
 
@@ -113,7 +183,8 @@ impl OpenStore for RedisConfig {
 /// [`RedisConfig`]: crate::store::RedisConfig
 #[cfg_attr(docsrs, doc(cfg(feature = "store-redis")))]
 pub struct RedisStore {
-    connection: Connection,
+    connection: Box<dyn ConnectionLike + Send>,
+    key_prefix: &'static str,
 }
 
 impl Debug for RedisStore {
@@ -123,14 +194,14 @@ impl Debug for RedisStore {
 }
 
 impl RedisStore {
-    fn from_connection_info(info: ConnectionInfo) -> crate::Result<Self> {
-        let mut connection = Client::open(info)
-            .map_err(|error| crate::Error::Store(super::Error::from(error)))?
-            .get_connection()
-            .map_err(|error| crate::Error::Store(super::Error::from(error)))?;
-
-        let version_response: Option<String> = connection
-            .get(STORE_VERSION_KEY)
+    fn new(
+        mut connection: Box<dyn ConnectionLike + Send>,
+        key_prefix: &'static str,
+    ) -> crate::Result<Self> {
+        let version_key = format!("{}{}", key_prefix, STORE_VERSION_KEY);
+        let version_response: Option<String> = cmd("GET")
+            .arg(&version_key)
+            .query(connection.as_mut())
             .map_err(|error| crate::Error::Store(super::Error::from(error)))?;
 
         match version_response {
@@ -139,41 +210,58 @@ impl RedisStore {
                     return Err(crate::Error::UnsupportedStore);
                 }
             }
-            None => connection
-                .set(STORE_VERSION_KEY, CURRENT_VERSION)
+            None => cmd("SET")
+                .arg(&version_key)
+                .arg(CURRENT_VERSION)
+                .query::<()>(connection.as_mut())
                 .map_err(|error| crate::Error::Store(super::Error::from(error)))?,
         }
 
-        Ok(RedisStore { connection })
+        Ok(RedisStore {
+            connection,
+            key_prefix,
+        })
+    }
+
+    /// Return the Redis key of the block with the given `key`.
+    fn block_key(&self, key: BlockKey) -> String {
+        format!("{}{}", self.key_prefix, block_key(key))
     }
 }
 
 impl DataStore for RedisStore {
     fn write_block(&mut self, key: BlockKey, data: &[u8]) -> super::Result<()> {
-        self.connection.set(block_key(key), data)?;
+        cmd("SET")
+            .arg(self.block_key(key))
+            .arg(data)
+            .query::<()>(self.connection.as_mut())?;
         Ok(())
     }
 
     fn read_block(&mut self, key: BlockKey) -> super::Result<Option<Vec<u8>>> {
-        Ok(self.connection.get(block_key(key))?)
+        Ok(cmd("GET")
+            .arg(self.block_key(key))
+            .query(self.connection.as_mut())?)
     }
 
     fn remove_block(&mut self, key: BlockKey) -> super::Result<()> {
-        self.connection.del(block_key(key))?;
+        cmd("DEL")
+            .arg(self.block_key(key))
+            .query::<()>(self.connection.as_mut())?;
         Ok(())
     }
 
     fn list_blocks(&mut self, kind: BlockType) -> super::Result<Vec<BlockId>> {
         let key_prefix = match kind {
-            BlockType::Data => format!("{}:", DATA_KEY),
-            BlockType::Lock => format!("{}:", LOCKS_KEY),
-            BlockType::Header => format!("{}:", HEADERS_KEY),
+            BlockType::Data => format!("{}{}:", self.key_prefix, DATA_KEY),
+            BlockType::Lock => format!("{}{}:", self.key_prefix, LOCKS_KEY),
+            BlockType::Header => format!("{}{}:", self.key_prefix, HEADERS_KEY),
         };
         let search_key = format!("{}*", key_prefix);
 
-        let blocks = self
-            .connection
-            .keys::<_, Vec<String>>(search_key)?
+        let blocks = cmd("KEYS")
+            .arg(search_key)
+            .query::<Vec<String>>(self.connection.as_mut())?
             .iter()
             .map(|key| {
                 let uuid = key.trim_start_matches(&key_prefix);
@@ -0,0 +1,191 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::data_store::{BlockId, BlockKey, BlockType, DataStore};
+use super::open_store::OpenStore;
+
+/// A policy for retrying failed operations in a [`RetryStore`].
+///
+/// Failed operations are retried with exponential backoff. After each failed attempt, the delay
+/// before the next attempt is multiplied by `multiplier`, up to a maximum of `max_delay`.
+///
+/// [`RetryStore`]: crate::store::RetryStore
+#[derive(Debug, PartialEq, Clone)]
+pub struct RetryPolicy {
+    /// The maximum number of times to retry an operation before returning the error.
+    ///
+    /// The default value is `5`.
+    pub max_retries: u32,
+
+    /// The delay before the first retry.
+    ///
+    /// The default value is 100 milliseconds.
+    pub initial_delay: Duration,
+
+    /// The maximum delay between retries.
+    ///
+    /// The default value is 10 seconds.
+    pub max_delay: Duration,
+
+    /// The factor to multiply the delay by after each retry.
+    ///
+    /// The default value is `2.0`.
+    pub multiplier: f64,
+
+    /// The maximum amount of time to spend on an operation, including retries.
+    ///
+    /// If an attempt fails after this much time has elapsed, the error is returned instead of
+    /// retrying again. An attempt which is already in progress is never interrupted; use the
+    /// options provided by the data store to limit how long a single attempt can take. If this is
+    /// `None`, there is no limit.
+    ///
+    /// The default value is `None`.
+    pub timeout: Option<Duration>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 5,
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(10),
+            multiplier: 2.0,
+            timeout: None,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Call `operation` until it succeeds or this policy says to stop retrying.
+    pub(super) fn retry<T>(
+        &self,
+        mut operation: impl FnMut() -> super::Result<T>,
+    ) -> super::Result<T> {
+        let start = Instant::now();
+        let mut delay = self.initial_delay;
+        let mut retries = 0;
+
+        loop {
+            let error = match operation() {
+                Ok(value) => return Ok(value),
+                Err(error) => error,
+            };
+
+            let timed_out = match self.timeout {
+                Some(timeout) => start.elapsed() + delay >= timeout,
+                None => false,
+            };
+            if retries >= self.max_retries || timed_out {
+                return Err(error);
+            }
+
+            thread::sleep(delay);
+            delay = Duration::try_from_secs_f64(delay.as_secs_f64() * self.multiplier)
+                .unwrap_or(self.max_delay)
+                .min(self.max_delay);
+            retries += 1;
+        }
+    }
+}
+
+/// The configuration for opening a [`RetryStore`].
+///
+/// This wraps the configuration for another data store.
+///
+/// [`RetryStore`]: crate::store::RetryStore
+#[derive(Debug, PartialEq, Clone)]
+pub struct RetryConfig<C> {
+    /// The configuration for the data store to wrap.
+    pub config: C,
+
+    /// The policy for retrying failed operations.
+    pub policy: RetryPolicy,
+}
+
+impl<C: OpenStore> OpenStore for RetryConfig<C> {
+    type Store = RetryStore<C::Store>;
+
+    fn open(&self) -> crate::Result<Self::Store> {
+        Ok(RetryStore::new(self.config.open()?, self.policy.clone()))
+    }
+}
+
+/// A `DataStore` which retries failed operations on another data store.
+///
+/// Data stores which access the network can fail transiently. This wraps any data store and
+/// retries each failed operation according to a [`RetryPolicy`]. This is safe because each
+/// operation on a data store is atomic and idempotent.
+///
+/// A stream can't be read again after an operation fails, so blocks which are read or written as
+/// streams are buffered in memory so that the operation can be retried.
+///
+/// You can use [`RetryConfig`] to open a data store of this type, or you can wrap an existing data
+/// store with [`RetryStore::new`].
+///
+/// [`RetryPolicy`]: crate::store::RetryPolicy
+/// [`RetryConfig`]: crate::store::RetryConfig
+/// [`RetryStore::new`]: crate::store::RetryStore::new
+#[derive(Debug)]
+pub struct RetryStore<S> {
+    store: S,
+    policy: RetryPolicy,
+}
+
+impl<S: DataStore> RetryStore<S> {
+    /// Wrap the given `store`, retrying failed operations according to `policy`.
+    pub fn new(store: S, policy: RetryPolicy) -> Self {
+        RetryStore { store, policy }
+    }
+
+    /// Return the wrapped data store.
+    pub fn into_inner(self) -> S {
+        self.store
+    }
+}
+
+impl<S: DataStore> DataStore for RetryStore<S> {
+    fn write_block(&mut self, key: BlockKey, data: &[u8]) -> super::Result<()> {
+        let store = &mut self.store;
+        self.policy.retry(|| store.write_block(key, data))
+    }
+
+    fn read_block(&mut self, key: BlockKey) -> super::Result<Option<Vec<u8>>> {
+        let store = &mut self.store;
+        self.policy.retry(|| store.read_block(key))
+    }
+
+    fn supports_range_reads(&self) -> bool {
+        self.store.supports_range_reads()
+    }
+
+    fn read_block_range(
+        &mut self,
+        key: BlockKey,
+        offset: u64,
+        len: u64,
+    ) -> super::Result<Option<Vec<u8>>> {
+        let store = &mut self.store;
+        self.policy
+            .retry(|| store.read_block_range(key, offset, len))
+    }
+
+    fn remove_block(&mut self, key: BlockKey) -> super::Result<()> {
+        let store = &mut self.store;
+        self.policy.retry(|| store.remove_block(key))
+    }
+
+    fn list_blocks(&mut self, kind: BlockType) -> super::Result<Vec<BlockId>> {
+        let store = &mut self.store;
+        self.policy.retry(|| store.list_blocks(kind))
+    }
+
+    fn available_space(&mut self) -> super::Result<Option<u64>> {
+        let store = &mut self.store;
+        self.policy.retry(|| store.available_space())
+    }
+
+    fn try_clone(&self) -> Option<Box<dyn DataStore>> {
+        let store = self.store.try_clone()?;
+        Some(Box::new(RetryStore::new(store, self.policy.clone())))
+    }
+}
@@ -1,14 +1,19 @@
 #![cfg(feature = "store-s3")]
 
 use std::env;
+use std::fs;
+use std::thread;
+use std::time::{Duration, Instant};
 
 use s3::bucket::Bucket;
 use s3::creds::Credentials;
 use s3::region::Region;
+use s3::serde_types::Part;
 use uuid::{uuid, Uuid};
 
 use super::data_store::{BlockId, BlockKey, BlockType, DataStore};
 use super::open_store::OpenStore;
+use super::retry_store::RetryPolicy;
 
 /// The separator to use in S3 object keys.
 const SEPARATOR: &str = "/";
@@ -43,6 +48,17 @@ const CURRENT_VERSION: Uuid = uuid!("f0511da2-f90d-11eb-be71-13f36b8156e4");
 /// The HTTP status code for an object which does not exist.
 const NOT_FOUND_CODE: u16 = 404;
 
+/// The HTTP status code returned when a requested range starts past the end of an object.
+const RANGE_NOT_SATISFIABLE_CODE: u16 = 416;
+
+/// The content type of the objects in the data store.
+const CONTENT_TYPE: &str = "application/octet-stream";
+
+/// How often to refresh temporary credentials.
+///
+/// This is shorter than the minimum lifetime of STS credentials, which is 15 minutes.
+const CREDENTIALS_REFRESH_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
 /// The environment variable for the AWS access key.
 const ACCESS_KEY_ENV: &str = "AWS_ACCESS_KEY_ID";
 
@@ -52,6 +68,12 @@ const SECRET_KEY_ENV: &str = "AWS_SECRET_ACCESS_KEY";
 /// The environment variable for the AWS session token.
 const SESSION_TOKEN_ENV: &str = "AWS_SESSION_TOKEN";
 
+/// The environment variable for the ARN of the role to assume with a web identity token.
+const ROLE_ARN_ENV: &str = "AWS_ROLE_ARN";
+
+/// The environment variable for the path of the file containing a web identity token.
+const WEB_IDENTITY_TOKEN_FILE_ENV: &str = "AWS_WEB_IDENTITY_TOKEN_FILE";
+
 /// An AWS region.
 #[non_exhaustive]
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -252,6 +274,27 @@ pub enum S3Credentials {
         /// Session token.
         session_token: String,
     },
+
+    /// Temporary credentials from STS for a role assumed with a web identity token.
+    ///
+    /// This is used for OpenID Connect providers, like the ones used by Kubernetes service
+    /// accounts. The credentials are refreshed automatically before they expire.
+    WebIdentity {
+        /// The ARN of the role to assume.
+        role_arn: String,
+
+        /// An identifier for the assumed role session.
+        session_name: String,
+
+        /// The OAuth 2.0 access token or OpenID Connect ID token from the identity provider.
+        web_identity_token: String,
+    },
+
+    /// Temporary credentials for the IAM role of the current EC2 instance.
+    ///
+    /// The credentials are fetched from the instance metadata service and are refreshed
+    /// automatically before they expire.
+    InstanceMetadata,
 }
 
 impl S3Credentials {
@@ -279,6 +322,26 @@ impl S3Credentials {
         })
     }
 
+    /// Get `S3Credentials` for assuming a role with a web identity token from environment
+    /// variables.
+    ///
+    /// This checks the following environment variables:
+    /// - `AWS_ROLE_ARN`
+    /// - `AWS_WEB_IDENTITY_TOKEN_FILE`
+    ///
+    /// The token is read from the file when this is called. This returns `None` if the environment
+    /// variables were unset or the token file could not be read.
+    pub fn from_web_identity_env(session_name: &str) -> Option<Self> {
+        let role_arn = env::var(ROLE_ARN_ENV).ok()?;
+        let token_path = env::var(WEB_IDENTITY_TOKEN_FILE_ENV).ok()?;
+        let web_identity_token = fs::read_to_string(token_path).ok()?;
+        Some(S3Credentials::WebIdentity {
+            role_arn,
+            session_name: session_name.to_owned(),
+            web_identity_token: web_identity_token.trim().to_owned(),
+        })
+    }
+
     /// Get `S3Credentials` from the profile with the given `name`.
     ///
     /// This returns `None` if the profile was not found or could not be read.
@@ -298,6 +361,88 @@ impl S3Credentials {
             },
         })
     }
+
+    /// Return whether these credentials expire and need to be refreshed.
+    fn is_temporary(&self) -> bool {
+        matches!(
+            self,
+            S3Credentials::WebIdentity { .. } | S3Credentials::InstanceMetadata
+        )
+    }
+
+    /// Get the credentials to connect with, requesting temporary credentials if necessary.
+    fn to_credentials(&self) -> super::Result<Credentials> {
+        Ok(match self {
+            S3Credentials::Anonymous => Credentials::anonymous()?,
+            S3Credentials::Basic {
+                access_key,
+                secret_key,
+            } => Credentials {
+                access_key: Some(access_key.clone()),
+                secret_key: Some(secret_key.clone()),
+                security_token: None,
+                session_token: None,
+                expiration: None,
+            },
+            S3Credentials::Session {
+                access_key,
+                secret_key,
+                session_token,
+            } => Credentials {
+                access_key: Some(access_key.clone()),
+                secret_key: Some(secret_key.clone()),
+                security_token: None,
+                session_token: Some(session_token.clone()),
+                expiration: None,
+            },
+            S3Credentials::WebIdentity {
+                role_arn,
+                session_name,
+                web_identity_token,
+            } => Credentials::from_sts(role_arn, session_name, web_identity_token)?,
+            S3Credentials::InstanceMetadata => Credentials::from_instance_metadata()?,
+        })
+    }
+}
+
+/// The configuration for multipart uploads in an [`S3Store`].
+///
+/// Blocks which are larger than `part_size` are split into parts which are uploaded concurrently
+/// and retried individually if they fail. This makes writing large packs faster and more reliable
+/// over high-latency connections.
+///
+/// This type implements `Default`, which returns reasonable values for most connections.
+///
+/// [`S3Store`]: crate::store::S3Store
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(docsrs, doc(cfg(feature = "store-s3")))]
+pub struct S3Multipart {
+    /// The size of each part in bytes.
+    ///
+    /// S3 requires every part except the last to be at least 5 MiB.
+    ///
+    /// The default value is 8 MiB.
+    pub part_size: usize,
+
+    /// The maximum number of parts of a block to upload at once.
+    ///
+    /// The default value is `4`.
+    pub concurrency: usize,
+
+    /// The number of times to retry uploading a part before the upload fails.
+    ///
+    /// The default value is `3`.
+    pub max_retries: u32,
+}
+
+impl Default for S3Multipart {
+    fn default() -> Self {
+        S3Multipart {
+            part_size: 8 * 1024 * 1024,
+            concurrency: 4,
+            max_retries: 3,
+        }
+    }
 }
 
 /// The configuration for opening an [`S3Store`].
@@ -320,42 +465,57 @@ pub struct S3Config {
     /// While keys in S3 are a flat namespace, you can think of this like the directory of the
     /// bucket to create the store in. To create the store in the bucket root, use an empty string.
     pub prefix: String,
+
+    /// The configuration for multipart uploads.
+    ///
+    /// If this is `None`, each block is uploaded in a single request regardless of its size.
+    pub multipart: Option<S3Multipart>,
+
+    /// Whether to use path-style addressing instead of virtual-hosted-style addressing.
+    ///
+    /// Path-style addressing puts the bucket name in the path of the URL instead of the host
+    /// name. Many S3-compatible services, like MinIO and Ceph, require this.
+    pub path_style: bool,
 }
 
 impl S3Config {
-    fn into_bucket(self) -> Bucket {
-        Bucket::new(
-            self.bucket.as_str(),
-            Region::Custom {
-                region: self.region.name().to_string(),
-                endpoint: self.region.endpoint().to_string(),
-            },
-            match self.credentials {
-                S3Credentials::Anonymous => Credentials::anonymous().unwrap(),
-                S3Credentials::Basic {
-                    access_key,
-                    secret_key,
-                } => Credentials {
-                    access_key: Some(access_key),
-                    secret_key: Some(secret_key),
-                    security_token: None,
-                    session_token: None,
-                    expiration: None,
-                },
-                S3Credentials::Session {
-                    access_key,
-                    secret_key,
-                    session_token,
-                } => Credentials {
-                    access_key: Some(access_key),
-                    secret_key: Some(secret_key),
-                    security_token: None,
-                    session_token: Some(session_token),
-                    expiration: None,
-                },
-            },
-        )
-        .unwrap()
+    /// Create a new `S3Config` for the given `bucket` in the given `region`.
+    ///
+    /// This uses the default [`S3Multipart`] configuration.
+    ///
+    /// [`S3Multipart`]: crate::store::S3Multipart
+    pub fn new(
+        bucket: impl Into<String>,
+        region: S3Region,
+        credentials: S3Credentials,
+        prefix: impl Into<String>,
+    ) -> Self {
+        S3Config {
+            bucket: bucket.into(),
+            region,
+            credentials,
+            prefix: prefix.into(),
+            multipart: Some(S3Multipart::default()),
+            path_style: false,
+        }
+    }
+
+    /// Connect to the bucket, requesting temporary credentials if necessary.
+    fn bucket(&self) -> super::Result<Bucket> {
+        let region = Region::Custom {
+            region: self.region.name().to_string(),
+            endpoint: self.region.endpoint().to_string(),
+        };
+        let credentials = self.credentials.to_credentials()?;
+        if self.path_style {
+            Ok(Bucket::new_with_path_style(
+                self.bucket.as_str(),
+                region,
+                credentials,
+            )?)
+        } else {
+            Ok(Bucket::new(self.bucket.as_str(), region, credentials)?)
+        }
     }
 }
 
@@ -363,7 +523,7 @@ impl OpenStore for S3Config {
     type Store = S3Store;
 
     fn open(&self) -> crate::Result<Self::Store> {
-        let bucket = self.clone().into_bucket();
+        let bucket = self.bucket().map_err(crate::Error::Store)?;
         let prefix = self.prefix.trim_end_matches(SEPARATOR).to_owned();
         let version_key = join_key!(prefix, STORE_VERSION_KEY);
 
@@ -383,7 +543,13 @@ impl OpenStore for S3Config {
             Err(error) => return Err(crate::Error::Store(super::Error::from(error))),
         };
 
-        Ok(S3Store { bucket, prefix })
+        Ok(S3Store {
+            bucket,
+            prefix,
+            multipart: self.multipart,
+            config: self.clone(),
+            connected: Instant::now(),
+        })
     }
 }
 
@@ -397,9 +563,23 @@ impl OpenStore for S3Config {
 pub struct S3Store {
     bucket: Bucket,
     prefix: String,
+    multipart: Option<S3Multipart>,
+    config: S3Config,
+    connected: Instant,
 }
 
 impl S3Store {
+    /// Reconnect to the bucket with new credentials if the current ones may expire soon.
+    fn refresh_credentials(&mut self) -> super::Result<()> {
+        if self.config.credentials.is_temporary()
+            && self.connected.elapsed() >= CREDENTIALS_REFRESH_INTERVAL
+        {
+            self.bucket = self.config.bucket()?;
+            self.connected = Instant::now();
+        }
+        Ok(())
+    }
+
     /// Return the key of the block with the given `id`.
     fn block_path(&self, key: BlockKey) -> String {
         match key {
@@ -427,16 +607,103 @@ impl S3Store {
             BlockKey::Version => join_key!(self.prefix, STORE_KEY, REPO_VERSION_KEY),
         }
     }
+
+    /// Upload `data` to the object at `path` in multiple parts.
+    ///
+    /// If the upload fails, it is aborted so that the parts which were uploaded are removed.
+    fn put_multipart(&self, path: &str, data: &[u8], multipart: S3Multipart) -> super::Result<()> {
+        let upload_id = self
+            .bucket
+            .initiate_multipart_upload(path, CONTENT_TYPE)?
+            .upload_id;
+
+        let result = self
+            .put_parts(path, &upload_id, data, multipart)
+            .and_then(|parts| {
+                self.bucket
+                    .complete_multipart_upload(path, &upload_id, parts)?;
+                Ok(())
+            });
+
+        if result.is_err() {
+            // The upload already failed, so there's nothing useful to do if this fails too.
+            self.bucket.abort_upload(path, &upload_id).ok();
+        }
+
+        result
+    }
+
+    /// Upload each part of `data` concurrently and return the uploaded parts in order.
+    fn put_parts(
+        &self,
+        path: &str,
+        upload_id: &str,
+        data: &[u8],
+        multipart: S3Multipart,
+    ) -> super::Result<Vec<Part>> {
+        let policy = RetryPolicy {
+            max_retries: multipart.max_retries,
+            ..RetryPolicy::default()
+        };
+
+        // S3 part numbers start at 1.
+        let parts = data
+            .chunks(multipart.part_size.max(1))
+            .zip(1u32..)
+            .collect::<Vec<_>>();
+        let concurrency = multipart.concurrency.max(1);
+        let group_size = (parts.len() + concurrency - 1) / concurrency;
+
+        let uploaded_parts = thread::scope(|scope| {
+            let workers = parts
+                .chunks(group_size)
+                .map(|group| {
+                    let policy = &policy;
+                    scope.spawn(move || {
+                        group
+                            .iter()
+                            .map(|&(chunk, part_number)| {
+                                policy.retry(|| {
+                                    Ok(self.bucket.put_multipart_chunk(
+                                        chunk.to_vec(),
+                                        path,
+                                        part_number,
+                                        upload_id,
+                                        CONTENT_TYPE,
+                                    )?)
+                                })
+                            })
+                            .collect::<super::Result<Vec<_>>>()
+                    })
+                })
+                .collect::<Vec<_>>();
+            workers
+                .into_iter()
+                .map(|worker| worker.join().expect("A part upload thread panicked."))
+                .collect::<super::Result<Vec<_>>>()
+        })?;
+
+        Ok(uploaded_parts.into_iter().flatten().collect())
+    }
 }
 
 impl DataStore for S3Store {
     fn write_block(&mut self, key: BlockKey, data: &[u8]) -> super::Result<()> {
+        self.refresh_credentials()?;
         let block_path = self.block_path(key);
-        self.bucket.put_object(block_path, data)?;
+        match self.multipart {
+            Some(multipart) if data.len() > multipart.part_size => {
+                self.put_multipart(&block_path, data, multipart)?;
+            }
+            _ => {
+                self.bucket.put_object(block_path, data)?;
+            }
+        }
         Ok(())
     }
 
     fn read_block(&mut self, key: BlockKey) -> super::Result<Option<Vec<u8>>> {
+        self.refresh_credentials()?;
         let block_path = self.block_path(key);
         let response = self.bucket.get_object(block_path)?;
         if response.status_code() == NOT_FOUND_CODE {
@@ -446,13 +713,44 @@ impl DataStore for S3Store {
         }
     }
 
+    fn supports_range_reads(&self) -> bool {
+        true
+    }
+
+    fn read_block_range(
+        &mut self,
+        key: BlockKey,
+        offset: u64,
+        len: u64,
+    ) -> super::Result<Option<Vec<u8>>> {
+        // An HTTP range can't be empty, so we need to fetch the whole object to find out whether
+        // it exists.
+        if len == 0 {
+            return Ok(self.read_block(key)?.map(|_| Vec::new()));
+        }
+
+        self.refresh_credentials()?;
+        let block_path = self.block_path(key);
+        let end = offset.saturating_add(len - 1);
+        let response = self
+            .bucket
+            .get_object_range(block_path, offset, Some(end))?;
+        match response.status_code() {
+            NOT_FOUND_CODE => Ok(None),
+            RANGE_NOT_SATISFIABLE_CODE => Ok(Some(Vec::new())),
+            _ => Ok(Some(response.bytes().into())),
+        }
+    }
+
     fn remove_block(&mut self, key: BlockKey) -> super::Result<()> {
+        self.refresh_credentials()?;
         let block_path = self.block_path(key);
         self.bucket.delete_object(block_path)?;
         Ok(())
     }
 
     fn list_blocks(&mut self, kind: BlockType) -> super::Result<Vec<BlockId>> {
+        self.refresh_credentials()?;
         let blocks_key = match kind {
             BlockType::Data => join_key!(self.prefix, STORE_KEY, DATA_KEY) + SEPARATOR,
             BlockType::Lock => join_key!(self.prefix, STORE_KEY, LOCKS_KEY) + SEPARATOR,
@@ -1,25 +1,29 @@
 #![cfg(feature = "store-sftp")]
 
 use std::fmt::{self, Debug, Formatter};
-use std::io::{Read, Write};
+use std::io::{copy, Read, Write};
 use std::net::{SocketAddr, TcpStream};
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
-use ssh2::{self, RenameFlags, Session, Sftp};
+use ssh2::{self, ErrorCode, RenameFlags, Session, Sftp};
 use uuid::Uuid;
 
 use super::data_store::{BlockId, BlockKey, BlockType, DataStore};
 use super::open_store::OpenStore;
 
 // A UUID which acts as the version ID of the directory store format.
-const CURRENT_VERSION: &str = "dcfb91ae-f8ab-11eb-964b-5b439fc44271";
+pub(super) const CURRENT_VERSION: &str = "dcfb91ae-f8ab-11eb-964b-5b439fc44271";
 
 // The names of top-level files in the data store.
-const STORE_DIRECTORY: &str = "store";
-const STAGING_DIRECTORY: &str = "stage";
-const VERSION_FILE: &str = "version";
+pub(super) const STORE_DIRECTORY: &str = "store";
+pub(super) const STAGING_DIRECTORY: &str = "stage";
+pub(super) const VERSION_FILE: &str = "version";
 
-fn type_path(kind: BlockType) -> PathBuf {
+/// The SFTP status code for a file which does not exist.
+const NO_SUCH_FILE_CODE: i32 = 2;
+
+pub(super) fn type_path(kind: BlockType) -> PathBuf {
     match kind {
         BlockType::Data => [STORE_DIRECTORY, "data"].iter().collect(),
         BlockType::Lock => [STORE_DIRECTORY, "locks"].iter().collect(),
@@ -27,7 +31,7 @@ fn type_path(kind: BlockType) -> PathBuf {
     }
 }
 
-fn block_path(key: BlockKey) -> PathBuf {
+pub(super) fn block_path(key: BlockKey) -> PathBuf {
     match key {
         BlockKey::Data(id) => {
             let uuid_str = id.as_ref().as_hyphenated().to_string();
@@ -103,28 +107,62 @@ pub struct SftpConfig {
 
     /// The path of the store on the server.
     pub path: PathBuf,
+
+    /// How often to send keepalive messages to the server, or `None` to not send them.
+    ///
+    /// Keepalive messages are sent before each operation once the connection has been idle for
+    /// this long. This keeps firewalls and NAT devices from dropping idle connections and detects
+    /// dropped connections before an operation is attempted.
+    pub keepalive: Option<Duration>,
+
+    /// The maximum number of times to reconnect to the server during a single operation.
+    ///
+    /// If an operation fails because the connection to the server was lost, the store reconnects,
+    /// authenticates again, and retries the operation. Blocks which are written from a stream
+    /// with [`DataStore::write_block_stream`] are not retried, since the stream can't be read
+    /// again, but the store still reconnects so that later operations succeed.
+    ///
+    /// [`DataStore::write_block_stream`]: crate::store::DataStore::write_block_stream
+    pub max_reconnects: u32,
 }
 
-impl OpenStore for SftpConfig {
-    type Store = SftpStore;
+impl SftpConfig {
+    /// Create a new `SftpConfig` for the store at `path` on the server at `addr`.
+    ///
+    /// This sends keepalive messages every 30 seconds and reconnects up to 3 times per operation.
+    pub fn new(addr: SocketAddr, auth: SftpAuth, path: impl Into<PathBuf>) -> Self {
+        SftpConfig {
+            addr,
+            auth,
+            path: path.into(),
+            keepalive: Some(Duration::from_secs(30)),
+            max_reconnects: 3,
+        }
+    }
 
-    fn open(&self) -> crate::Result<Self::Store> {
-        // Connect to the SSH server.
-        let stream = TcpStream::connect(self.addr)
-            .map_err(|error| crate::Error::Store(super::Error::from(error)))?;
-        let mut session =
-            Session::new().map_err(|error| crate::Error::Store(super::Error::from(error)))?;
+    /// Connect and authenticate to the SSH server and start an SFTP session.
+    fn connect(&self) -> super::Result<(Session, Sftp)> {
+        let stream = TcpStream::connect(self.addr)?;
+        let mut session = Session::new()?;
         session.set_tcp_stream(stream);
-        session
-            .handshake()
-            .map_err(|error| crate::Error::Store(super::Error::from(error)))?;
+        session.handshake()?;
 
-        // Perform authentication.
+        if let Some(keepalive) = self.keepalive {
+            let interval = keepalive.as_secs().clamp(1, u32::MAX as u64) as u32;
+            session.set_keepalive(true, interval);
+        }
+
+        self.authenticate(&session)?;
+
+        let sftp = session.sftp()?;
+        Ok((session, sftp))
+    }
+
+    /// Authenticate the given `session` using the configured authentication method.
+    fn authenticate(&self, session: &Session) -> super::Result<()> {
         match &self.auth {
             SftpAuth::Password { username, password } => {
-                session
-                    .userauth_password(username, password)
-                    .map_err(|error| crate::Error::Store(super::Error::from(error)))?;
+                session.userauth_password(username, password)?;
             }
             SftpAuth::Key {
                 username,
@@ -132,51 +170,42 @@ impl OpenStore for SftpConfig {
                 private_key,
                 password,
             } => {
-                session
-                    .userauth_pubkey_file(
-                        username,
-                        public_key.as_ref().map(|path| path.as_path()),
-                        private_key,
-                        password.as_ref().map(|str| str.as_str()),
-                    )
-                    .map_err(|error| crate::Error::Store(super::Error::from(error)))?;
+                session.userauth_pubkey_file(
+                    username,
+                    public_key.as_ref().map(|path| path.as_path()),
+                    private_key,
+                    password.as_ref().map(|str| str.as_str()),
+                )?;
             }
             SftpAuth::Agent { username, comment } => match comment {
                 Some(comment) => {
-                    let mut agent = session
-                        .agent()
-                        .map_err(|error| crate::Error::Store(super::Error::from(error)))?;
-                    agent
-                        .connect()
-                        .map_err(|error| crate::Error::Store(super::Error::from(error)))?;
-                    agent
-                        .list_identities()
-                        .map_err(|error| crate::Error::Store(super::Error::from(error)))?;
-                    let identities = agent
-                        .identities()
-                        .map_err(|error| crate::Error::Store(super::Error::from(error)))?;
+                    let mut agent = session.agent()?;
+                    agent.connect()?;
+                    agent.list_identities()?;
+                    let identities = agent.identities()?;
                     let key = identities
                         .iter()
                         .find(|key| key.comment() == comment)
                         .ok_or_else(|| {
                             super::Error::msg("No key with matching comment found in agent.")
-                        })
-                        .map_err(crate::Error::Store)?;
-                    agent
-                        .userauth(username, key)
-                        .map_err(|error| crate::Error::Store(super::Error::from(error)))?;
+                        })?;
+                    agent.userauth(username, key)?;
                 }
                 None => {
-                    session
-                        .userauth_agent(username)
-                        .map_err(|error| crate::Error::Store(super::Error::from(error)))?;
+                    session.userauth_agent(username)?;
                 }
             },
         }
 
-        let sftp = session
-            .sftp()
-            .map_err(|error| crate::Error::Store(super::Error::from(error)))?;
+        Ok(())
+    }
+}
+
+impl OpenStore for SftpConfig {
+    type Store = SftpStore;
+
+    fn open(&self) -> crate::Result<Self::Store> {
+        let (session, sftp) = self.connect().map_err(crate::Error::Store)?;
 
         // Create the directories if they don't exist.
         let directories = &[
@@ -217,8 +246,10 @@ impl OpenStore for SftpConfig {
         }
 
         Ok(SftpStore {
+            session,
             sftp,
             path: self.path.clone(),
+            config: self.clone(),
         })
     }
 }
@@ -230,8 +261,10 @@ impl OpenStore for SftpConfig {
 /// [`SftpConfig`]: crate::store::SftpConfig
 #[cfg_attr(docsrs, doc(cfg(feature = "store-sftp")))]
 pub struct SftpStore {
+    session: Session,
     sftp: Sftp,
     path: PathBuf,
+    config: SftpConfig,
 }
 
 impl SftpStore {
@@ -247,29 +280,88 @@ impl SftpStore {
     }
 
     /// Return whether the given remote `path` exists.
-    fn exists(&self, path: &Path) -> bool {
-        self.sftp.stat(path).is_ok()
+    fn exists(&self, path: &Path) -> super::Result<bool> {
+        match self.sftp.stat(path) {
+            Ok(_) => Ok(true),
+            Err(error) if error.code() == ErrorCode::SFTP(NO_SUCH_FILE_CODE) => Ok(false),
+            Err(error) => Err(error.into()),
+        }
     }
-}
 
-impl DataStore for SftpStore {
-    fn write_block(&mut self, key: BlockKey, data: &[u8]) -> super::Result<()> {
+    /// Return whether the connection to the server is still usable.
+    fn is_connected(&self) -> bool {
+        self.sftp.stat(&self.path).is_ok()
+    }
+
+    /// Connect to the server again, replacing the current session.
+    fn reconnect(&mut self) -> super::Result<()> {
+        let (session, sftp) = self.config.connect()?;
+        self.session = session;
+        self.sftp = sftp;
+        Ok(())
+    }
+
+    /// Send a keepalive message if one is due, reconnecting if the connection was lost.
+    fn keep_alive(&mut self) -> super::Result<()> {
+        if self.config.keepalive.is_some() && self.session.keepalive_send().is_err() {
+            self.reconnect()?;
+        }
+        Ok(())
+    }
+
+    /// Call `operation`, reconnecting and trying again if the connection to the server was lost.
+    fn reconnecting<T>(
+        &mut self,
+        mut operation: impl FnMut(&Self) -> super::Result<T>,
+    ) -> super::Result<T> {
+        self.keep_alive()?;
+
+        let mut reconnects = 0;
+        loop {
+            let error = match operation(self) {
+                Ok(value) => return Ok(value),
+                Err(error) => error,
+            };
+
+            if reconnects >= self.config.max_reconnects || self.is_connected() {
+                return Err(error);
+            }
+
+            self.reconnect()?;
+            reconnects += 1;
+        }
+    }
+
+    /// Read the block at the given remote `path`.
+    fn read_file(&self, path: &Path) -> super::Result<Option<Vec<u8>>> {
+        if !self.exists(path)? {
+            return Ok(None);
+        }
+
+        let mut file = self.sftp.open(path)?;
+
+        let mut buffer = Vec::with_capacity(file.stat()?.size.unwrap_or(0) as usize);
+        file.read_to_end(&mut buffer)?;
+        Ok(Some(buffer))
+    }
+
+    /// Write the data from `reader` to a staging file and move it to the given remote `path`.
+    fn write_file(&self, path: &Path, reader: &mut dyn Read) -> super::Result<()> {
         let staging_path = self.staging_path();
-        let block_path = self.block_path(key);
 
         // If this is the first block its sub-directory, the directory needs to be created.
-        let parent = block_path.parent().unwrap();
-        if !self.exists(parent) {
+        let parent = path.parent().unwrap();
+        if !self.exists(parent)? {
             self.sftp.mkdir(parent, 0o755)?;
         }
 
         // Write to a staging file and then atomically move it to its final destination.
         let mut staging_file = self.sftp.create(&staging_path)?;
-        staging_file.write_all(data)?;
+        copy(reader, &mut staging_file)?;
         staging_file.flush()?;
         self.sftp.rename(
             &staging_path,
-            &block_path,
+            path,
             Some(RenameFlags::ATOMIC | RenameFlags::OVERWRITE),
         )?;
 
@@ -281,33 +373,16 @@ impl DataStore for SftpStore {
         Ok(())
     }
 
-    fn read_block(&mut self, key: BlockKey) -> super::Result<Option<Vec<u8>>> {
-        let block_path = self.block_path(key);
-
-        if !self.exists(&block_path) {
-            return Ok(None);
-        }
-
-        let mut file = self.sftp.open(&block_path)?;
-
-        let mut buffer = Vec::with_capacity(file.stat()?.size.unwrap_or(0) as usize);
-        file.read_to_end(&mut buffer)?;
-        Ok(Some(buffer))
-    }
-
-    fn remove_block(&mut self, key: BlockKey) -> super::Result<()> {
-        let block_path = self.block_path(key);
-
-        if !self.exists(&block_path) {
-            return Ok(());
+    /// Remove the block at the given remote `path` if it exists.
+    fn remove_file(&self, path: &Path) -> super::Result<()> {
+        if self.exists(path)? {
+            self.sftp.unlink(path)?;
         }
-
-        self.sftp.unlink(&block_path)?;
-
         Ok(())
     }
 
-    fn list_blocks(&mut self, kind: BlockType) -> super::Result<Vec<BlockId>> {
+    /// Return the IDs of the blocks of the given `kind`.
+    fn list_files(&self, kind: BlockType) -> super::Result<Vec<BlockId>> {
         let mut block_ids = Vec::new();
 
         match kind {
@@ -346,6 +421,52 @@ impl DataStore for SftpStore {
     }
 }
 
+impl DataStore for SftpStore {
+    fn write_block(&mut self, key: BlockKey, data: &[u8]) -> super::Result<()> {
+        let block_path = self.block_path(key);
+        self.reconnecting(|store| {
+            let mut reader = data;
+            store.write_file(&block_path, &mut reader)
+        })
+    }
+
+    fn read_block(&mut self, key: BlockKey) -> super::Result<Option<Vec<u8>>> {
+        let block_path = self.block_path(key);
+        self.reconnecting(|store| store.read_file(&block_path))
+    }
+
+    fn write_block_stream(&mut self, key: BlockKey, reader: &mut dyn Read) -> super::Result<()> {
+        self.keep_alive()?;
+        let block_path = self.block_path(key);
+        let result = self.write_file(&block_path, reader);
+        if result.is_err() && !self.is_connected() {
+            // The stream can't be read again, so we can't retry, but we can make sure the next
+            // operation has a working connection.
+            self.reconnect()?;
+        }
+        result
+    }
+
+    fn read_block_stream(&mut self, key: BlockKey) -> super::Result<Option<Box<dyn Read + '_>>> {
+        let block_path = self.block_path(key);
+
+        if !self.reconnecting(|store| store.exists(&block_path))? {
+            return Ok(None);
+        }
+
+        Ok(Some(Box::new(self.sftp.open(&block_path)?)))
+    }
+
+    fn remove_block(&mut self, key: BlockKey) -> super::Result<()> {
+        let block_path = self.block_path(key);
+        self.reconnecting(|store| store.remove_file(&block_path))
+    }
+
+    fn list_blocks(&mut self, kind: BlockType) -> super::Result<Vec<BlockId>> {
+        self.reconnecting(|store| store.list_files(kind))
+    }
+}
+
 impl Debug for SftpStore {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         f.debug_struct("SftpStore")
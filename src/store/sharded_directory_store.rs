@@ -0,0 +1,283 @@
+#![cfg(feature = "store-directory")]
+
+use std::collections::HashSet;
+use std::io::Read;
+use std::path::PathBuf;
+
+use super::data_store::{BlockId, BlockKey, BlockType, DataStore};
+use super::directory_store::{DirectoryConfig, DirectoryDurability, DirectoryStore};
+use super::open_store::OpenStore;
+
+/// The index of the shard which stores all blocks other than data blocks.
+const PRIMARY_SHARD: usize = 0;
+
+/// A policy for choosing which directory in a [`ShardedDirectoryStore`] stores each data block.
+///
+/// [`ShardedDirectoryStore`]: crate::store::ShardedDirectoryStore
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(docsrs, doc(cfg(feature = "store-directory")))]
+pub enum ShardPlacement {
+    /// Spread data blocks evenly between all the directories.
+    Uniform,
+
+    /// Spread data blocks between the directories in proportion to the given weights.
+    ///
+    /// There must be one weight for each directory. This is useful when the directories are on
+    /// disks with different capacities. A directory with a weight of `0` will not receive any new
+    /// data blocks.
+    Weighted(Vec<u32>),
+}
+
+impl ShardPlacement {
+    /// Return the index of the shard out of `num_shards` which should store the block with the
+    /// given `id`.
+    fn shard_index(&self, id: BlockId, num_shards: usize) -> usize {
+        let hash = blake3::hash(id.as_ref().as_bytes());
+        let mut hash_bytes = [0u8; 8];
+        hash_bytes.copy_from_slice(&hash.as_bytes()[..8]);
+        let position = u64::from_le_bytes(hash_bytes);
+
+        match self {
+            ShardPlacement::Uniform => (position % num_shards as u64) as usize,
+            ShardPlacement::Weighted(weights) => {
+                let total_weight = weights.iter().map(|&weight| weight as u64).sum::<u64>();
+                let mut remaining = position % total_weight;
+                for (index, &weight) in weights.iter().enumerate() {
+                    if remaining < weight as u64 {
+                        return index;
+                    }
+                    remaining -= weight as u64;
+                }
+                unreachable!("The position is always less than the total weight.")
+            }
+        }
+    }
+}
+
+/// The configuration for opening a [`ShardedDirectoryStore`].
+///
+/// [`ShardedDirectoryStore`]: crate::store::ShardedDirectoryStore
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(docsrs, doc(cfg(feature = "store-directory")))]
+pub struct ShardedDirectoryConfig {
+    /// The paths of the directories to spread the data store across.
+    ///
+    /// The first directory stores the repository metadata in addition to its share of the data
+    /// blocks. The order of the directories matters, so they should always be given in the same
+    /// order.
+    pub paths: Vec<PathBuf>,
+
+    /// The policy for choosing which directory stores each data block.
+    pub placement: ShardPlacement,
+
+    /// When to wait for blocks to be written to disk.
+    pub durability: DirectoryDurability,
+}
+
+impl OpenStore for ShardedDirectoryConfig {
+    type Store = ShardedDirectoryStore;
+
+    fn open(&self) -> crate::Result<Self::Store> {
+        if self.paths.is_empty() {
+            return Err(crate::Error::Store(super::Error::msg(
+                "A sharded directory store must have at least one directory.",
+            )));
+        }
+
+        if let ShardPlacement::Weighted(weights) = &self.placement {
+            if weights.len() != self.paths.len() {
+                return Err(crate::Error::Store(super::Error::msg(
+                    "There must be exactly one weight for each directory.",
+                )));
+            }
+            if weights.iter().all(|&weight| weight == 0) {
+                return Err(crate::Error::Store(super::Error::msg(
+                    "At least one directory must have a non-zero weight.",
+                )));
+            }
+        }
+
+        let shards = self
+            .paths
+            .iter()
+            .map(|path| {
+                DirectoryConfig {
+                    path: path.clone(),
+                    durability: self.durability,
+                }
+                .open()
+            })
+            .collect::<crate::Result<Vec<_>>>()?;
+
+        Ok(ShardedDirectoryStore {
+            shards,
+            placement: self.placement.clone(),
+        })
+    }
+}
+
+/// A `DataStore` which spreads data across directories in the local file system.
+///
+/// This allows a repository to span several disks or mount points. Each directory is laid out
+/// like a [`DirectoryStore`]. Data blocks are distributed between the directories according to a
+/// [`ShardPlacement`] policy based on a hash of their ID, and all other blocks are stored in the
+/// first directory.
+///
+/// If a data block is not found in the directory chosen by the placement policy, the other
+/// directories are searched as well. This means it's safe to add directories to the end of the
+/// list or change the placement policy, although existing blocks are not moved.
+///
+/// You can use [`ShardedDirectoryConfig`] to open a data store of this type.
+///
+/// [`DirectoryStore`]: crate::store::DirectoryStore
+/// [`ShardPlacement`]: crate::store::ShardPlacement
+/// [`ShardedDirectoryConfig`]: crate::store::ShardedDirectoryConfig
+#[derive(Debug)]
+#[cfg_attr(docsrs, doc(cfg(feature = "store-directory")))]
+pub struct ShardedDirectoryStore {
+    /// The stores for each directory.
+    shards: Vec<DirectoryStore>,
+
+    /// The policy for choosing which directory stores each data block.
+    placement: ShardPlacement,
+}
+
+impl ShardedDirectoryStore {
+    /// Return the index of the shard where a block with the given `key` will be written.
+    fn shard_index(&self, key: BlockKey) -> usize {
+        match key {
+            BlockKey::Data(id) => self.placement.shard_index(id, self.shards.len()),
+            _ => PRIMARY_SHARD,
+        }
+    }
+
+    /// Prepare to write the block with the given `key` and return the index of its shard.
+    ///
+    /// Each shard only flushes the data blocks it stores itself, so data blocks in the other shards
+    /// must be flushed before a block which commits changes is written to the primary shard.
+    fn prepare_write(&mut self, key: BlockKey) -> super::Result<usize> {
+        let index = self.shard_index(key);
+        if index == PRIMARY_SHARD && !matches!(key, BlockKey::Data(_)) {
+            for shard in &mut self.shards[PRIMARY_SHARD + 1..] {
+                shard.sync_blocks()?;
+            }
+        }
+        Ok(index)
+    }
+}
+
+impl DataStore for ShardedDirectoryStore {
+    fn write_block(&mut self, key: BlockKey, data: &[u8]) -> super::Result<()> {
+        let index = self.prepare_write(key)?;
+        self.shards[index].write_block(key, data)
+    }
+
+    fn read_block(&mut self, key: BlockKey) -> super::Result<Option<Vec<u8>>> {
+        let index = self.shard_index(key);
+        if let Some(data) = self.shards[index].read_block(key)? {
+            return Ok(Some(data));
+        }
+
+        if let BlockKey::Data(_) = key {
+            // The block may have been written before the placement policy changed.
+            for (other_index, shard) in self.shards.iter_mut().enumerate() {
+                if other_index == index {
+                    continue;
+                }
+                if let Some(data) = shard.read_block(key)? {
+                    return Ok(Some(data));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn write_block_stream(&mut self, key: BlockKey, reader: &mut dyn Read) -> super::Result<()> {
+        let index = self.prepare_write(key)?;
+        self.shards[index].write_block_stream(key, reader)
+    }
+
+    fn read_block_stream(&mut self, key: BlockKey) -> super::Result<Option<Box<dyn Read + '_>>> {
+        let index = self.shard_index(key);
+        if let Some(file) = self.shards[index].open_block(key)? {
+            return Ok(Some(Box::new(file)));
+        }
+
+        if let BlockKey::Data(_) = key {
+            // The block may have been written before the placement policy changed.
+            for (other_index, shard) in self.shards.iter().enumerate() {
+                if other_index == index {
+                    continue;
+                }
+                if let Some(file) = shard.open_block(key)? {
+                    return Ok(Some(Box::new(file)));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn supports_range_reads(&self) -> bool {
+        true
+    }
+
+    fn read_block_range(
+        &mut self,
+        key: BlockKey,
+        offset: u64,
+        len: u64,
+    ) -> super::Result<Option<Vec<u8>>> {
+        let index = self.shard_index(key);
+        if let Some(data) = self.shards[index].read_block_range(key, offset, len)? {
+            return Ok(Some(data));
+        }
+
+        if let BlockKey::Data(_) = key {
+            // The block may have been written before the placement policy changed.
+            for (other_index, shard) in self.shards.iter_mut().enumerate() {
+                if other_index == index {
+                    continue;
+                }
+                if let Some(data) = shard.read_block_range(key, offset, len)? {
+                    return Ok(Some(data));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn remove_block(&mut self, key: BlockKey) -> super::Result<()> {
+        match key {
+            BlockKey::Data(_) => {
+                for shard in self.shards.iter_mut() {
+                    shard.remove_block(key)?;
+                }
+                Ok(())
+            }
+            _ => self.shards[PRIMARY_SHARD].remove_block(key),
+        }
+    }
+
+    fn list_blocks(&mut self, kind: BlockType) -> super::Result<Vec<BlockId>> {
+        match kind {
+            BlockType::Data => {
+                let mut block_ids = HashSet::new();
+                for shard in self.shards.iter_mut() {
+                    block_ids.extend(shard.list_blocks(kind)?);
+                }
+                Ok(block_ids.into_iter().collect())
+            }
+            BlockType::Lock | BlockType::Header => self.shards[PRIMARY_SHARD].list_blocks(kind),
+        }
+    }
+
+    fn try_clone(&self) -> Option<Box<dyn DataStore>> {
+        Some(Box::new(ShardedDirectoryStore {
+            shards: self.shards.iter().map(DirectoryStore::new_handle).collect(),
+            placement: self.placement.clone(),
+        }))
+    }
+}
@@ -1,6 +1,7 @@
 #![cfg(feature = "store-sqlite")]
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use rusqlite::{params, Connection, OptionalExtension, NO_PARAMS};
 use uuid::{uuid, Uuid};
@@ -11,6 +12,71 @@ use super::open_store::OpenStore;
 /// A UUID which acts as the version ID of the store format.
 const CURRENT_VERSION: Uuid = uuid!("42efde7c-f927-11eb-bb01-d70e242b02af");
 
+/// The journal mode of a SQLite database.
+///
+/// See the [SQLite documentation](https://www.sqlite.org/pragma.html#pragma_journal_mode) for
+/// details.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(docsrs, doc(cfg(feature = "store-sqlite")))]
+pub enum SqliteJournalMode {
+    /// Use a rollback journal which is deleted at the end of each transaction.
+    ///
+    /// Readers block writers and writers block readers.
+    Delete,
+
+    /// Use a write-ahead log.
+    ///
+    /// Readers don't block writers and writers don't block readers, so other processes can inspect
+    /// the database while the store is being written to. This doesn't work when the database is on
+    /// a network file system.
+    Wal,
+}
+
+impl SqliteJournalMode {
+    fn pragma_value(self) -> &'static str {
+        match self {
+            SqliteJournalMode::Delete => "DELETE",
+            SqliteJournalMode::Wal => "WAL",
+        }
+    }
+}
+
+/// How often a SQLite database waits for data to be written to disk.
+///
+/// See the [SQLite documentation](https://www.sqlite.org/pragma.html#pragma_synchronous) for
+/// details.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(docsrs, doc(cfg(feature = "store-sqlite")))]
+pub enum SqliteSynchronous {
+    /// Don't wait for data to be written to disk.
+    ///
+    /// This is the fastest, but the database may be corrupted if the system loses power.
+    Off,
+
+    /// Wait for data to be written to disk at the most critical moments.
+    ///
+    /// In WAL mode, the database can't be corrupted, but the most recent writes may be lost if the
+    /// system loses power.
+    Normal,
+
+    /// Wait for data to be written to disk before each transaction completes.
+    Full,
+
+    /// Like `Full`, but also wait for the directory containing the journal to be written to disk.
+    Extra,
+}
+
+impl SqliteSynchronous {
+    fn pragma_value(self) -> &'static str {
+        match self {
+            SqliteSynchronous::Off => "OFF",
+            SqliteSynchronous::Normal => "NORMAL",
+            SqliteSynchronous::Full => "FULL",
+            SqliteSynchronous::Extra => "EXTRA",
+        }
+    }
+}
+
 /// The configuration for opening a [`SqliteStore`].
 ///
 /// [`SqliteStore`]: crate::store::SqliteStore
@@ -19,6 +85,35 @@ const CURRENT_VERSION: Uuid = uuid!("42efde7c-f927-11eb-bb01-d70e242b02af");
 pub struct SqliteConfig {
     /// The path of the SQLite database.
     pub path: PathBuf,
+
+    /// The journal mode of the database.
+    ///
+    /// The journal mode is stored in the database, so changing it affects every connection.
+    pub journal_mode: SqliteJournalMode,
+
+    /// How often to wait for data to be written to disk.
+    pub synchronous: SqliteSynchronous,
+
+    /// How long to wait for a lock held by another connection before failing.
+    ///
+    /// If this is `None`, operations fail immediately with `SQLITE_BUSY` when the database is
+    /// locked by another connection.
+    pub busy_timeout: Option<Duration>,
+}
+
+impl SqliteConfig {
+    /// Create a new `SqliteConfig` for the database at `path`.
+    ///
+    /// This uses a write-ahead log, waits for data to be written to disk before each transaction
+    /// completes, and waits up to 5 seconds for locks held by other connections.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        SqliteConfig {
+            path: path.into(),
+            journal_mode: SqliteJournalMode::Wal,
+            synchronous: SqliteSynchronous::Full,
+            busy_timeout: Some(Duration::from_secs(5)),
+        }
+    }
 }
 
 impl OpenStore for SqliteConfig {
@@ -28,6 +123,30 @@ impl OpenStore for SqliteConfig {
         let connection = Connection::open(&self.path)
             .map_err(|error| crate::Error::Store(super::Error::from(error)))?;
 
+        // Set the busy timeout first so that the other pragmas wait for locks too.
+        connection
+            .busy_timeout(self.busy_timeout.unwrap_or_default())
+            .map_err(|error| crate::Error::Store(super::Error::from(error)))?;
+
+        // Setting the journal mode returns the new journal mode as a row.
+        connection
+            .query_row(
+                &format!(
+                    "PRAGMA journal_mode = {};",
+                    self.journal_mode.pragma_value()
+                ),
+                NO_PARAMS,
+                |row| row.get::<_, String>(0),
+            )
+            .map_err(|error| crate::Error::Store(super::Error::from(error)))?;
+
+        connection
+            .execute_batch(&format!(
+                "PRAGMA synchronous = {};",
+                self.synchronous.pragma_value()
+            ))
+            .map_err(|error| crate::Error::Store(super::Error::from(error)))?;
+
         connection
             .execute_batch(
                 r#"
@@ -108,6 +227,28 @@ pub struct SqliteStore {
     connection: Connection,
 }
 
+impl SqliteStore {
+    /// Write a compacted copy of the database to a new file at `path`.
+    ///
+    /// The copy is a single self-contained file, even if the store uses a write-ahead log, so it
+    /// can be copied to another machine and opened with a new [`SqliteConfig`]. The copy is
+    /// consistent even if other connections write to the database while it's being made.
+    ///
+    /// # Errors
+    /// - `Error::Store`: There is already a file at `path`, or an error occurred with the database.
+    ///
+    /// [`SqliteConfig`]: crate::store::SqliteConfig
+    pub fn vacuum_into(&self, path: impl AsRef<Path>) -> crate::Result<()> {
+        let path = path.as_ref().to_str().ok_or_else(|| {
+            crate::Error::Store(super::Error::msg("The path is not valid Unicode."))
+        })?;
+        self.connection
+            .execute("VACUUM INTO ?1;", params![path])
+            .map_err(|error| crate::Error::Store(super::Error::from(error)))?;
+        Ok(())
+    }
+}
+
 impl DataStore for SqliteStore {
     fn write_block(&mut self, key: BlockKey, data: &[u8]) -> super::Result<()> {
         match key {
@@ -0,0 +1,169 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::data_store::{BlockId, BlockKey, BlockType, DataStore};
+use super::open_store::OpenStore;
+
+/// A policy for limiting the bandwidth used by a [`ThrottleStore`].
+///
+/// [`ThrottleStore`]: crate::store::ThrottleStore
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct ThrottlePolicy {
+    /// The maximum average number of bytes per second to read from the data store.
+    ///
+    /// If this is `None`, reads are not limited.
+    ///
+    /// The default value is `None`.
+    pub read_rate: Option<u64>,
+
+    /// The maximum average number of bytes per second to write to the data store.
+    ///
+    /// If this is `None`, writes are not limited.
+    ///
+    /// The default value is `None`.
+    pub write_rate: Option<u64>,
+}
+
+/// A limit on the rate at which bytes are transferred.
+#[derive(Debug)]
+struct RateLimit {
+    /// The maximum average number of bytes per second, or `None` if there is no limit.
+    rate: Option<u64>,
+
+    /// The time at which the next transfer can start.
+    next_available: Instant,
+}
+
+impl RateLimit {
+    /// Create a new limit of `rate` bytes per second.
+    fn new(rate: Option<u64>) -> Self {
+        RateLimit {
+            rate,
+            next_available: Instant::now(),
+        }
+    }
+
+    /// Block until a new transfer can start.
+    fn wait(&self) {
+        let now = Instant::now();
+        if self.next_available > now {
+            thread::sleep(self.next_available - now);
+        }
+    }
+
+    /// Record that `size` bytes were transferred.
+    fn consume(&mut self, size: usize) {
+        let rate = match self.rate {
+            Some(rate) if rate > 0 => rate,
+            _ => return,
+        };
+        let transfer_time = Duration::from_secs_f64(size as f64 / rate as f64);
+        self.next_available = self.next_available.max(Instant::now()) + transfer_time;
+    }
+}
+
+/// The configuration for opening a [`ThrottleStore`].
+///
+/// This wraps the configuration for another data store.
+///
+/// [`ThrottleStore`]: crate::store::ThrottleStore
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ThrottleConfig<C> {
+    /// The configuration for the data store to wrap.
+    pub config: C,
+
+    /// The policy for limiting bandwidth.
+    pub policy: ThrottlePolicy,
+}
+
+impl<C: OpenStore> OpenStore for ThrottleConfig<C> {
+    type Store = ThrottleStore<C::Store>;
+
+    fn open(&self) -> crate::Result<Self::Store> {
+        Ok(ThrottleStore::new(self.config.open()?, self.policy.clone()))
+    }
+}
+
+/// A `DataStore` which limits the bandwidth used by another data store.
+///
+/// This wraps any data store and delays operations so that the average rate at which block data
+/// is read and written doesn't exceed the limits in a [`ThrottlePolicy`]. This is useful for
+/// keeping a backup from saturating a network link. Individual blocks are not split up, so the
+/// rate may briefly exceed the limit while transferring a large block.
+///
+/// You can use [`ThrottleConfig`] to open a data store of this type, or you can wrap an existing
+/// data store with [`ThrottleStore::new`].
+///
+/// [`ThrottlePolicy`]: crate::store::ThrottlePolicy
+/// [`ThrottleConfig`]: crate::store::ThrottleConfig
+/// [`ThrottleStore::new`]: crate::store::ThrottleStore::new
+#[derive(Debug)]
+pub struct ThrottleStore<S> {
+    store: S,
+    read_limit: RateLimit,
+    write_limit: RateLimit,
+}
+
+impl<S: DataStore> ThrottleStore<S> {
+    /// Wrap the given `store`, limiting its bandwidth according to `policy`.
+    pub fn new(store: S, policy: ThrottlePolicy) -> Self {
+        ThrottleStore {
+            store,
+            read_limit: RateLimit::new(policy.read_rate),
+            write_limit: RateLimit::new(policy.write_rate),
+        }
+    }
+
+    /// Return the wrapped data store.
+    pub fn into_inner(self) -> S {
+        self.store
+    }
+}
+
+impl<S: DataStore> DataStore for ThrottleStore<S> {
+    fn write_block(&mut self, key: BlockKey, data: &[u8]) -> super::Result<()> {
+        self.write_limit.wait();
+        let result = self.store.write_block(key, data);
+        self.write_limit.consume(data.len());
+        result
+    }
+
+    fn read_block(&mut self, key: BlockKey) -> super::Result<Option<Vec<u8>>> {
+        self.read_limit.wait();
+        let result = self.store.read_block(key);
+        if let Ok(Some(data)) = &result {
+            self.read_limit.consume(data.len());
+        }
+        result
+    }
+
+    fn supports_range_reads(&self) -> bool {
+        self.store.supports_range_reads()
+    }
+
+    fn read_block_range(
+        &mut self,
+        key: BlockKey,
+        offset: u64,
+        len: u64,
+    ) -> super::Result<Option<Vec<u8>>> {
+        self.read_limit.wait();
+        let result = self.store.read_block_range(key, offset, len);
+        if let Ok(Some(data)) = &result {
+            self.read_limit.consume(data.len());
+        }
+        result
+    }
+
+    fn remove_block(&mut self, key: BlockKey) -> super::Result<()> {
+        self.store.remove_block(key)
+    }
+
+    fn list_blocks(&mut self, kind: BlockType) -> super::Result<Vec<BlockId>> {
+        self.store.list_blocks(kind)
+    }
+
+    fn available_space(&mut self) -> super::Result<Option<u64>> {
+        self.store.available_space()
+    }
+}
@@ -0,0 +1,434 @@
+#![cfg(feature = "store-webdav")]
+
+use std::collections::HashMap;
+use std::io::{Cursor, Read};
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use ureq::{Agent, AgentBuilder, Request, Response};
+use uuid::{uuid, Uuid};
+
+use super::data_store::{BlockId, BlockKey, BlockType, DataStore};
+use super::open_store::OpenStore;
+
+/// The separator to use in URL paths.
+const SEPARATOR: &str = "/";
+
+// The paths of resources in the data store.
+const STORE_PATH: &str = "store";
+const DATA_PATH: &str = "data";
+const LOCKS_PATH: &str = "locks";
+const HEADERS_PATH: &str = "headers";
+const STAGE_PATH: &str = "stage";
+const SUPER_PATH: &str = "super";
+const REPO_VERSION_PATH: &str = "version";
+const STORE_VERSION_PATH: &str = "version";
+
+/// A UUID which acts as the version ID of the store format.
+const CURRENT_VERSION: Uuid = uuid!("3c0a5d5e-7f2b-4a3e-9d8b-52e1f0c6a7d4");
+
+/// The HTTP status code for a resource which does not exist.
+const NOT_FOUND_CODE: u16 = 404;
+
+/// The HTTP status code returned by `MKCOL` when the collection already exists.
+const METHOD_NOT_ALLOWED_CODE: u16 = 405;
+
+/// The HTTP status code for a request whose conditional headers were not satisfied.
+const PRECONDITION_FAILED_CODE: u16 = 412;
+
+/// The body of a `PROPFIND` request which only requests the resource type.
+const PROPFIND_BODY: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<propfind xmlns="DAV:"><prop><resourcetype/></prop></propfind>"#;
+
+/// Join the given segments into a URL.
+macro_rules! join_url {
+    ($($segment:expr),*) => {
+        {
+            let mut url = String::new();
+            $(
+                url.push_str(&$segment);
+                url.push_str(SEPARATOR);
+            )*
+            url.truncate(url.len() - SEPARATOR.len());
+            url
+        }
+    }
+}
+
+/// The credentials for a WebDAV server.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(docsrs, doc(cfg(feature = "store-webdav")))]
+pub enum WebDavAuth {
+    /// Connect without authenticating.
+    Anonymous,
+
+    /// Authenticate with HTTP basic authentication.
+    Basic {
+        /// The username to authenticate with.
+        username: String,
+
+        /// The password to authenticate with.
+        password: String,
+    },
+
+    /// Authenticate with a bearer token, like an app password or OAuth token.
+    Bearer {
+        /// The token to authenticate with.
+        token: String,
+    },
+}
+
+impl WebDavAuth {
+    /// Return the value of the `Authorization` header for these credentials.
+    fn header(&self) -> Option<String> {
+        match self {
+            WebDavAuth::Anonymous => None,
+            WebDavAuth::Basic { username, password } => Some(format!(
+                "Basic {}",
+                BASE64.encode(format!("{}:{}", username, password))
+            )),
+            WebDavAuth::Bearer { token } => Some(format!("Bearer {}", token)),
+        }
+    }
+}
+
+/// The configuration for opening a [`WebDavStore`].
+///
+/// [`WebDavStore`]: crate::store::WebDavStore
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(docsrs, doc(cfg(feature = "store-webdav")))]
+pub struct WebDavConfig {
+    /// The URL of the collection on the WebDAV server to store data in.
+    ///
+    /// This collection must already exist. For Nextcloud and ownCloud, this is typically a URL
+    /// like `https://example.com/remote.php/dav/files/<username>/<path>`.
+    pub url: String,
+
+    /// The credentials to connect with.
+    pub auth: WebDavAuth,
+
+    /// Whether to upload blocks using chunked transfer encoding.
+    ///
+    /// This allows uploads to start before the size of the block is sent to the server, which
+    /// some reverse proxies require for large uploads. Not all servers support chunked transfer
+    /// encoding, so this is disabled by default.
+    pub chunked_upload: bool,
+}
+
+impl WebDavConfig {
+    /// Create a new `WebDavConfig` for the collection at `url` with the given `auth`.
+    pub fn new(url: impl Into<String>, auth: WebDavAuth) -> Self {
+        WebDavConfig {
+            url: url.into(),
+            auth,
+            chunked_upload: false,
+        }
+    }
+}
+
+impl OpenStore for WebDavConfig {
+    type Store = WebDavStore;
+
+    fn open(&self) -> crate::Result<Self::Store> {
+        let store = WebDavStore {
+            agent: AgentBuilder::new().build(),
+            url: self.url.trim_end_matches(SEPARATOR).to_owned(),
+            auth: self.auth.header(),
+            chunked_upload: self.chunked_upload,
+            lock_tags: HashMap::new(),
+        };
+        let version_url = join_url!(store.url, STORE_VERSION_PATH);
+
+        match store.request("GET", &version_url).call() {
+            Err(ureq::Error::Status(NOT_FOUND_CODE, _)) => {
+                for collection_url in [
+                    join_url!(store.url, STORE_PATH),
+                    join_url!(store.url, STORE_PATH, DATA_PATH),
+                    join_url!(store.url, STORE_PATH, LOCKS_PATH),
+                    join_url!(store.url, STORE_PATH, HEADERS_PATH),
+                    join_url!(store.url, STORE_PATH, STAGE_PATH),
+                ] {
+                    store
+                        .make_collection(&collection_url)
+                        .map_err(crate::Error::Store)?;
+                }
+                store
+                    .request("PUT", &version_url)
+                    .send_bytes(CURRENT_VERSION.as_bytes())
+                    .map_err(|error| crate::Error::Store(super::Error::from(error)))?;
+            }
+            Ok(response) => {
+                let version_bytes = read_body(response)
+                    .map_err(|error| crate::Error::Store(super::Error::from(error)))?;
+                let version =
+                    Uuid::from_slice(&version_bytes).map_err(|_| crate::Error::UnsupportedStore)?;
+                if version != CURRENT_VERSION {
+                    return Err(crate::Error::UnsupportedStore);
+                }
+            }
+            Err(error) => return Err(crate::Error::Store(super::Error::from(error))),
+        }
+
+        Ok(store)
+    }
+}
+
+/// A `DataStore` which stores data on a WebDAV server.
+///
+/// This works with generic WebDAV servers as well as Nextcloud and ownCloud. You can use
+/// [`WebDavConfig`] to open a data store of this type.
+///
+/// Blocks are first uploaded to a staging collection and then moved into place, so a block is
+/// never partially written. Lock blocks are written using conditional requests with `If-Match`
+/// and `If-None-Match` headers, so writing a lock block fails instead of silently overwriting a
+/// lock which was modified by another client.
+///
+/// [`WebDavConfig`]: crate::store::WebDavConfig
+#[derive(Debug)]
+#[cfg_attr(docsrs, doc(cfg(feature = "store-webdav")))]
+pub struct WebDavStore {
+    agent: Agent,
+    url: String,
+    auth: Option<String>,
+    chunked_upload: bool,
+
+    /// The entity tag of each lock block the last time it was read or written by this store.
+    ///
+    /// This is `None` if the server did not return an entity tag.
+    lock_tags: HashMap<BlockId, Option<String>>,
+}
+
+impl WebDavStore {
+    /// Create a new request with the given `method` for the given `url`.
+    fn request(&self, method: &str, url: &str) -> Request {
+        let request = self.agent.request(method, url);
+        match &self.auth {
+            Some(auth) => request.set("Authorization", auth),
+            None => request,
+        }
+    }
+
+    /// Create a collection at `url` if it doesn't already exist.
+    fn make_collection(&self, url: &str) -> super::Result<()> {
+        match self.request("MKCOL", url).call() {
+            Ok(_) | Err(ureq::Error::Status(METHOD_NOT_ALLOWED_CODE, _)) => Ok(()),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    /// Upload `data` to the server using the given `request`.
+    fn upload(&self, request: Request, data: &[u8]) -> super::Result<Response> {
+        let response = if self.chunked_upload {
+            request.send(Cursor::new(data))?
+        } else {
+            request.send_bytes(data)?
+        };
+        Ok(response)
+    }
+
+    /// Return the URL of the collection containing blocks of the given `kind`.
+    fn type_url(&self, kind: BlockType) -> String {
+        match kind {
+            BlockType::Data => join_url!(self.url, STORE_PATH, DATA_PATH),
+            BlockType::Lock => join_url!(self.url, STORE_PATH, LOCKS_PATH),
+            BlockType::Header => join_url!(self.url, STORE_PATH, HEADERS_PATH),
+        }
+    }
+
+    /// Return the URL of the block with the given `key`.
+    fn block_url(&self, key: BlockKey) -> String {
+        match key {
+            BlockKey::Data(id) => join_url!(
+                self.type_url(BlockType::Data),
+                id.as_ref().as_hyphenated().to_string()
+            ),
+            BlockKey::Lock(id) => join_url!(
+                self.type_url(BlockType::Lock),
+                id.as_ref().as_hyphenated().to_string()
+            ),
+            BlockKey::Header(id) => join_url!(
+                self.type_url(BlockType::Header),
+                id.as_ref().as_hyphenated().to_string()
+            ),
+            BlockKey::Super => join_url!(self.url, STORE_PATH, SUPER_PATH),
+            BlockKey::Version => join_url!(self.url, STORE_PATH, REPO_VERSION_PATH),
+        }
+    }
+
+    /// Write the lock block with the given `id`, failing if it was modified by another client.
+    fn write_lock(&mut self, id: BlockId, data: &[u8]) -> super::Result<()> {
+        let lock_url = self.block_url(BlockKey::Lock(id));
+        let request = match self.lock_tags.get(&id) {
+            // We've seen this lock before. Make sure it hasn't changed since then.
+            Some(Some(tag)) => self.request("PUT", &lock_url).set("If-Match", tag),
+            // We've seen this lock before, but the server didn't give us an entity tag.
+            Some(None) => self.request("PUT", &lock_url),
+            // This is a new lock. Make sure it doesn't already exist.
+            None => self.request("PUT", &lock_url).set("If-None-Match", "*"),
+        };
+
+        let result = if self.chunked_upload {
+            request.send(Cursor::new(data))
+        } else {
+            request.send_bytes(data)
+        };
+
+        match result {
+            Ok(response) => {
+                self.lock_tags.insert(id, entity_tag(&response));
+                Ok(())
+            }
+            Err(ureq::Error::Status(PRECONDITION_FAILED_CODE, _)) => Err(super::Error::msg(
+                "The lock block was modified by another client.",
+            )),
+            Err(error) => Err(error.into()),
+        }
+    }
+}
+
+impl DataStore for WebDavStore {
+    fn write_block(&mut self, key: BlockKey, data: &[u8]) -> super::Result<()> {
+        if let BlockKey::Lock(id) = key {
+            return self.write_lock(id, data);
+        }
+
+        let block_url = self.block_url(key);
+        let stage_url = join_url!(
+            self.url,
+            STORE_PATH,
+            STAGE_PATH,
+            Uuid::new_v4().as_hyphenated().to_string()
+        );
+
+        self.upload(self.request("PUT", &stage_url), data)?;
+
+        // Move the block into place so that it is never partially written.
+        let result = self
+            .request("MOVE", &stage_url)
+            .set("Destination", &block_url)
+            .set("Overwrite", "T")
+            .call();
+        if let Err(error) = result {
+            // Attempt to clean up the staged block.
+            self.request("DELETE", &stage_url).call().ok();
+            return Err(error.into());
+        }
+
+        Ok(())
+    }
+
+    fn read_block(&mut self, key: BlockKey) -> super::Result<Option<Vec<u8>>> {
+        let block_url = self.block_url(key);
+        let response = match self.request("GET", &block_url).call() {
+            Ok(response) => response,
+            Err(ureq::Error::Status(NOT_FOUND_CODE, _)) => return Ok(None),
+            Err(error) => return Err(error.into()),
+        };
+
+        if let BlockKey::Lock(id) = key {
+            self.lock_tags.insert(id, entity_tag(&response));
+        }
+
+        Ok(Some(read_body(response)?))
+    }
+
+    fn remove_block(&mut self, key: BlockKey) -> super::Result<()> {
+        let block_url = self.block_url(key);
+        match self.request("DELETE", &block_url).call() {
+            Ok(_) | Err(ureq::Error::Status(NOT_FOUND_CODE, _)) => {}
+            Err(error) => return Err(error.into()),
+        }
+
+        if let BlockKey::Lock(id) = key {
+            self.lock_tags.remove(&id);
+        }
+
+        Ok(())
+    }
+
+    fn list_blocks(&mut self, kind: BlockType) -> super::Result<Vec<BlockId>> {
+        let type_url = self.type_url(kind) + SEPARATOR;
+        let response = self
+            .request("PROPFIND", &type_url)
+            .set("Depth", "1")
+            .set("Content-Type", "application/xml")
+            .send_string(PROPFIND_BODY)?;
+        let body = String::from_utf8(read_body(response)?)?;
+
+        // The response includes the collection itself, which won't parse as a UUID.
+        let block_ids = parse_hrefs(&body)
+            .into_iter()
+            .filter_map(|href| {
+                let name = href.trim_end_matches(SEPARATOR).rsplit(SEPARATOR).next()?;
+                Uuid::parse_str(name).ok().map(BlockId::from)
+            })
+            .collect();
+
+        Ok(block_ids)
+    }
+}
+
+/// Read the body of the given `response`.
+fn read_body(response: Response) -> std::io::Result<Vec<u8>> {
+    let mut body = Vec::new();
+    response.into_reader().read_to_end(&mut body)?;
+    Ok(body)
+}
+
+/// Return the entity tag of the given `response` if it has one.
+fn entity_tag(response: &Response) -> Option<String> {
+    response.header("ETag").map(String::from)
+}
+
+/// Return the contents of each `href` element in the given `PROPFIND` response `body`.
+///
+/// WebDAV servers use different namespace prefixes, so this matches elements by their local name.
+fn parse_hrefs(body: &str) -> Vec<String> {
+    let mut hrefs = Vec::new();
+    let mut remaining = body;
+
+    while let Some(tag_start) = remaining.find('<') {
+        remaining = &remaining[tag_start + 1..];
+        let tag_end = match remaining.find('>') {
+            Some(index) => index,
+            None => break,
+        };
+        let tag = &remaining[..tag_end];
+        remaining = &remaining[tag_end + 1..];
+
+        let name = tag.split_whitespace().next().unwrap_or_default();
+        let local_name = name.rsplit(':').next().unwrap_or(name);
+        if local_name != "href" {
+            continue;
+        }
+
+        let text_end = remaining.find('<').unwrap_or(remaining.len());
+        hrefs.push(remaining[..text_end].trim().to_owned());
+        remaining = &remaining[text_end..];
+    }
+
+    hrefs
+}
+
+#[cfg(test)]
+mod tests {
+    use spectral::prelude::*;
+
+    use super::parse_hrefs;
+
+    #[test]
+    fn parse_hrefs_with_any_namespace_prefix() {
+        let body = r#"<?xml version="1.0"?>
+<d:multistatus xmlns:d="DAV:">
+  <d:response><d:href>/dav/store/data/</d:href></d:response>
+  <D:response><D:href>/dav/store/data/a</D:href></D:response>
+  <response><href> /dav/store/data/b </href></response>
+</d:multistatus>"#;
+
+        assert_that!(parse_hrefs(body)).is_equal_to(vec![
+            String::from("/dav/store/data/"),
+            String::from("/dav/store/data/a"),
+            String::from("/dav/store/data/b"),
+        ]);
+    }
+}
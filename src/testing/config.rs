@@ -0,0 +1,65 @@
+use crate::repo::{Chunking, Compression, Encryption, Packing, RepoConfig};
+
+/// The chunk size used by [`fixed_config`] and the configs derived from it.
+///
+/// [`fixed_config`]: crate::testing::fixed_config
+pub const TEST_CHUNK_SIZE: u32 = 256;
+
+/// The repository config used for testing fixed-size chunking.
+pub fn fixed_config() -> RepoConfig {
+    let mut config = RepoConfig::default();
+    config.chunking = Chunking::Fixed {
+        size: TEST_CHUNK_SIZE,
+    };
+    config.packing = Packing::None;
+    config.encryption = Encryption::None;
+    config.compression = Compression::None;
+    config
+}
+
+/// The repository config used for testing encryption and compression.
+pub fn encoding_config() -> RepoConfig {
+    let mut config = fixed_config();
+    config.encryption = Encryption::XChaCha20Poly1305;
+    config.compression = Compression::Lz4 { level: 1 };
+    config
+}
+
+/// The repository config used for testing ZPAQ chunking.
+pub fn zpaq_config() -> RepoConfig {
+    let mut config = fixed_config();
+    config.chunking = Chunking::Zpaq { bits: 8 };
+    config
+}
+
+/// The repository config used for testing packing with a size smaller than the chunk size.
+pub fn fixed_packing_small_config() -> RepoConfig {
+    let mut config = fixed_config();
+    // Smaller than the chunk size and not a factor of it.
+    config.packing = Packing::Fixed(100);
+    config
+}
+
+/// The repository config used for testing packing with a size larger than the chunk size.
+pub fn fixed_packing_large_config() -> RepoConfig {
+    let mut config = fixed_config();
+    // Larger than the chunk size and not a multiple of it.
+    config.packing = Packing::Fixed(300);
+    config
+}
+
+/// The repository config used for testing packing with ZPAQ chunking.
+pub fn zpaq_packing_config() -> RepoConfig {
+    let mut config = fixed_config();
+    config.packing = Packing::Fixed(TEST_CHUNK_SIZE);
+    config
+}
+
+/// The repository config used for testing concurrent chunk encoding, decoding, and verification.
+pub fn parallel_write_config() -> RepoConfig {
+    let mut config = encoding_config();
+    config.write_threads = 4;
+    config.verify_threads = 4;
+    config.read_threads = 4;
+    config
+}
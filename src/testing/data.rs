@@ -0,0 +1,78 @@
+use rand::{thread_rng, Rng, RngCore};
+
+/// The minimum size of the buffers returned by [`random_buffer`].
+///
+/// [`random_buffer`]: crate::testing::random_buffer
+pub const MIN_BUFFER_SIZE: usize = 2048;
+
+/// The maximum size of the buffers returned by [`random_buffer`].
+///
+/// [`random_buffer`]: crate::testing::random_buffer
+pub const MAX_BUFFER_SIZE: usize = 4096;
+
+/// Return a buffer containing `size` random bytes.
+pub fn random_bytes(size: usize) -> Vec<u8> {
+    let mut buffer = vec![0u8; size];
+    thread_rng().fill_bytes(&mut buffer);
+    buffer
+}
+
+/// Return a randomly sized buffer of random bytes.
+///
+/// The size of the buffer is between [`MIN_BUFFER_SIZE`] and [`MAX_BUFFER_SIZE`].
+///
+/// [`MIN_BUFFER_SIZE`]: crate::testing::MIN_BUFFER_SIZE
+/// [`MAX_BUFFER_SIZE`]: crate::testing::MAX_BUFFER_SIZE
+pub fn random_buffer() -> Vec<u8> {
+    random_bytes(thread_rng().gen_range(MIN_BUFFER_SIZE..MAX_BUFFER_SIZE))
+}
+
+/// Return a randomly sized buffer of random bytes.
+///
+/// The returned buffer is guaranteed to be smaller than the one returned by [`random_buffer`].
+///
+/// [`random_buffer`]: crate::testing::random_buffer
+pub fn smaller_buffer() -> Vec<u8> {
+    random_bytes(thread_rng().gen_range((MIN_BUFFER_SIZE / 2)..MIN_BUFFER_SIZE))
+}
+
+/// Return a randomly sized buffer of random bytes.
+///
+/// The returned buffer is guaranteed to be larger than the one returned by [`random_buffer`].
+///
+/// [`random_buffer`]: crate::testing::random_buffer
+pub fn larger_buffer() -> Vec<u8> {
+    random_bytes(thread_rng().gen_range(MAX_BUFFER_SIZE..(MAX_BUFFER_SIZE * 2)))
+}
+
+/// Return a buffer of `size` bytes which alternates between random data and runs of zeroes.
+///
+/// Each run of random data and each run of zeroes is `hole_size` bytes long, starting with random
+/// data. This is useful for testing sparse files and holes in objects.
+///
+/// # Panics
+/// - `hole_size` is zero.
+pub fn sparse_bytes(size: usize, hole_size: usize) -> Vec<u8> {
+    assert!(hole_size > 0, "The hole size must be greater than zero.");
+
+    let mut buffer = random_bytes(size);
+    for hole in buffer.chunks_mut(hole_size).skip(1).step_by(2) {
+        hole.fill(0);
+    }
+    buffer
+}
+
+/// Return a list of buffer sizes around the boundaries of chunks of `chunk_size` bytes.
+///
+/// This returns sizes which are zero, one byte, and one byte less than, equal to, and one byte
+/// more than one and two chunks. These are the sizes most likely to expose off-by-one errors in
+/// chunking and packing.
+pub fn chunk_boundary_sizes(chunk_size: usize) -> Vec<usize> {
+    let mut sizes = vec![0, 1];
+    for multiple in [chunk_size, chunk_size * 2] {
+        sizes.extend([multiple.saturating_sub(1), multiple, multiple + 1]);
+    }
+    sizes.sort_unstable();
+    sizes.dedup();
+    sizes
+}
@@ -0,0 +1,362 @@
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::store::{BlockId, BlockKey, BlockType, DataStore, OpenStore, StoreOperation};
+
+/// A fault which a [`FaultInjectingStore`] can inject into an operation.
+///
+/// [`FaultInjectingStore`]: crate::testing::FaultInjectingStore
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Fault {
+    /// Return an error without performing the operation.
+    Fail,
+
+    /// Perform the operation and then return an error anyway.
+    ///
+    /// This simulates a connection which is lost after the data store processes a request but
+    /// before the client receives the response.
+    FailAfter,
+
+    /// Wait for the given amount of time before performing the operation.
+    Delay(Duration),
+
+    /// Corrupt the data being written or read.
+    ///
+    /// For writes, the block is stored with corrupted data. For reads, the block is returned with
+    /// corrupted data, but the data in the wrapped store is unchanged. This has no effect on other
+    /// operations.
+    Corrupt,
+}
+
+/// A rule which determines which operations a [`FaultInjectingStore`] injects a fault into.
+///
+/// A rule matches operations of a given kind, optionally only those which access a given block or
+/// type of block. It can be configured to let a number of matching operations through before it
+/// starts injecting faults and to stop after injecting a number of faults. Rules are deterministic,
+/// so the same sequence of operations always triggers the same faults.
+///
+/// [`FaultInjectingStore`]: crate::testing::FaultInjectingStore
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FaultRule {
+    operation: StoreOperation,
+    fault: Fault,
+    key: Option<BlockKey>,
+    block_type: Option<BlockType>,
+    skip: u32,
+    times: Option<u32>,
+}
+
+impl FaultRule {
+    /// Create a rule which injects `fault` into every operation of the given kind.
+    pub fn new(operation: StoreOperation, fault: Fault) -> Self {
+        Self {
+            operation,
+            fault,
+            key: None,
+            block_type: None,
+            skip: 0,
+            times: None,
+        }
+    }
+
+    /// Only match operations which access the block with the given `key`.
+    pub fn key(mut self, key: BlockKey) -> Self {
+        self.key = Some(key);
+        self
+    }
+
+    /// Only match operations which access or list blocks of the given `kind`.
+    pub fn block_type(mut self, kind: BlockType) -> Self {
+        self.block_type = Some(kind);
+        self
+    }
+
+    /// Let the first `count` matching operations through before injecting faults.
+    pub fn skip(mut self, count: u32) -> Self {
+        self.skip = count;
+        self
+    }
+
+    /// Stop injecting faults after `count` faults have been injected.
+    ///
+    /// By default, faults are injected into every matching operation.
+    pub fn times(mut self, count: u32) -> Self {
+        self.times = Some(count);
+        self
+    }
+
+    /// Return whether this rule matches an `operation` on the block with the given `key` or on the
+    /// blocks of the given `kind`.
+    fn matches(
+        &self,
+        operation: StoreOperation,
+        key: Option<BlockKey>,
+        kind: Option<BlockType>,
+    ) -> bool {
+        let kind = kind.or_else(|| match key {
+            Some(BlockKey::Data(_)) => Some(BlockType::Data),
+            Some(BlockKey::Lock(_)) => Some(BlockType::Lock),
+            Some(BlockKey::Header(_)) => Some(BlockType::Header),
+            _ => None,
+        });
+        self.operation == operation
+            && (self.key.is_none() || self.key == key)
+            && (self.block_type.is_none() || self.block_type == kind)
+    }
+}
+
+/// A rule and how many operations it has matched.
+#[derive(Debug)]
+struct RuleState {
+    rule: FaultRule,
+    matched: u32,
+    injected: u32,
+}
+
+/// The rules shared between a [`Faults`] and the stores it controls.
+#[derive(Debug, Default)]
+struct FaultState {
+    rules: Vec<RuleState>,
+    injected: u32,
+}
+
+/// A handle for scripting the faults injected by a [`FaultInjectingStore`].
+///
+/// This can be cloned, and all clones control the same stores. This allows tests to add rules
+/// after the store has been passed to a repository.
+///
+/// [`FaultInjectingStore`]: crate::testing::FaultInjectingStore
+#[derive(Debug, Clone, Default)]
+pub struct Faults(Arc<Mutex<FaultState>>);
+
+impl Faults {
+    /// Create a new `Faults` with no rules.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the given `rule`.
+    ///
+    /// If more than one rule matches an operation, the rule which was added first is used, but
+    /// every matching rule counts the operation.
+    pub fn inject(&self, rule: FaultRule) {
+        self.0.lock().unwrap().rules.push(RuleState {
+            rule,
+            matched: 0,
+            injected: 0,
+        });
+    }
+
+    /// Remove all rules.
+    pub fn clear(&self) {
+        self.0.lock().unwrap().rules.clear();
+    }
+
+    /// Return the total number of faults which have been injected.
+    pub fn injected(&self) -> u32 {
+        self.0.lock().unwrap().injected
+    }
+
+    /// Return the fault to inject into an `operation` on `key` or `kind`, if any.
+    fn next(
+        &self,
+        operation: StoreOperation,
+        key: Option<BlockKey>,
+        kind: Option<BlockType>,
+    ) -> Option<Fault> {
+        let mut state = self.0.lock().unwrap();
+        let mut fault = None;
+
+        for rule_state in state.rules.iter_mut() {
+            if !rule_state.rule.matches(operation, key, kind) {
+                continue;
+            }
+            rule_state.matched += 1;
+
+            let exhausted =
+                matches!(rule_state.rule.times, Some(times) if rule_state.injected >= times);
+            if fault.is_none() && rule_state.matched > rule_state.rule.skip && !exhausted {
+                rule_state.injected += 1;
+                fault = Some(rule_state.rule.fault);
+            }
+        }
+
+        if fault.is_some() {
+            state.injected += 1;
+        }
+
+        fault
+    }
+}
+
+/// Return a copy of `data` with the bits of its first byte flipped.
+fn corrupt(data: &[u8]) -> Vec<u8> {
+    let mut corrupted = data.to_vec();
+    match corrupted.first_mut() {
+        Some(byte) => *byte = !*byte,
+        None => corrupted.push(0),
+    }
+    corrupted
+}
+
+/// Return the error returned for injected failures.
+fn injected_error() -> crate::store::Error {
+    crate::store::Error::msg("Injected fault.")
+}
+
+/// The configuration for opening a [`FaultInjectingStore`].
+///
+/// This wraps the configuration for another data store. Every store opened with this config is
+/// controlled by the same [`Faults`].
+///
+/// [`FaultInjectingStore`]: crate::testing::FaultInjectingStore
+/// [`Faults`]: crate::testing::Faults
+#[derive(Debug, Clone)]
+pub struct FaultInjectingConfig<C> {
+    /// The configuration for the data store to wrap.
+    pub config: C,
+
+    /// The handle which controls the faults injected into the data store.
+    pub faults: Faults,
+}
+
+impl<C: OpenStore> OpenStore for FaultInjectingConfig<C> {
+    type Store = FaultInjectingStore<C::Store>;
+
+    fn open(&self) -> crate::Result<Self::Store> {
+        Ok(FaultInjectingStore::new(
+            self.config.open()?,
+            self.faults.clone(),
+        ))
+    }
+}
+
+/// A `DataStore` which injects faults into the operations of another data store.
+///
+/// This wraps any data store and fails, delays, or corrupts operations according to the rules
+/// added to its [`Faults`]. This is useful for testing how code which uses a repository recovers
+/// from errors in the data store.
+///
+/// You can use [`FaultInjectingConfig`] to open a data store of this type, or you can wrap an
+/// existing data store with [`FaultInjectingStore::new`].
+///
+/// # Examples
+/// ```
+/// use acid_store::repo::{key::KeyRepo, Commit, OpenMode, OpenOptions};
+/// use acid_store::store::{BlockKey, MemoryConfig, StoreOperation};
+/// use acid_store::testing::{Fault, FaultInjectingConfig, FaultRule, Faults};
+///
+/// let faults = Faults::new();
+/// let config = FaultInjectingConfig {
+///     config: MemoryConfig::new(),
+///     faults: faults.clone(),
+/// };
+/// let mut repo: KeyRepo<String> = OpenOptions::new()
+///     .mode(OpenMode::CreateNew)
+///     .open(&config)
+///     .unwrap();
+///
+/// // Fail the next write to the super block, which happens when changes are committed.
+/// faults.inject(FaultRule::new(StoreOperation::WriteBlock, Fault::Fail).key(BlockKey::Super));
+/// repo.insert(String::from("test"));
+/// assert!(repo.commit().is_err());
+/// ```
+///
+/// [`Faults`]: crate::testing::Faults
+/// [`FaultInjectingConfig`]: crate::testing::FaultInjectingConfig
+/// [`FaultInjectingStore::new`]: crate::testing::FaultInjectingStore::new
+#[derive(Debug)]
+pub struct FaultInjectingStore<S> {
+    store: S,
+    faults: Faults,
+}
+
+impl<S: DataStore> FaultInjectingStore<S> {
+    /// Wrap the given `store`, injecting faults according to the rules in `faults`.
+    pub fn new(store: S, faults: Faults) -> Self {
+        FaultInjectingStore { store, faults }
+    }
+
+    /// Return the wrapped data store.
+    pub fn into_inner(self) -> S {
+        self.store
+    }
+}
+
+impl<S: DataStore> DataStore for FaultInjectingStore<S> {
+    fn write_block(&mut self, key: BlockKey, data: &[u8]) -> crate::store::Result<()> {
+        match self
+            .faults
+            .next(StoreOperation::WriteBlock, Some(key), None)
+        {
+            None => self.store.write_block(key, data),
+            Some(Fault::Fail) => Err(injected_error()),
+            Some(Fault::FailAfter) => {
+                self.store.write_block(key, data)?;
+                Err(injected_error())
+            }
+            Some(Fault::Delay(duration)) => {
+                thread::sleep(duration);
+                self.store.write_block(key, data)
+            }
+            Some(Fault::Corrupt) => self.store.write_block(key, &corrupt(data)),
+        }
+    }
+
+    fn read_block(&mut self, key: BlockKey) -> crate::store::Result<Option<Vec<u8>>> {
+        match self.faults.next(StoreOperation::ReadBlock, Some(key), None) {
+            None => self.store.read_block(key),
+            Some(Fault::Fail) => Err(injected_error()),
+            Some(Fault::FailAfter) => {
+                self.store.read_block(key)?;
+                Err(injected_error())
+            }
+            Some(Fault::Delay(duration)) => {
+                thread::sleep(duration);
+                self.store.read_block(key)
+            }
+            Some(Fault::Corrupt) => Ok(self.store.read_block(key)?.map(|data| corrupt(&data))),
+        }
+    }
+
+    fn remove_block(&mut self, key: BlockKey) -> crate::store::Result<()> {
+        match self
+            .faults
+            .next(StoreOperation::RemoveBlock, Some(key), None)
+        {
+            None | Some(Fault::Corrupt) => self.store.remove_block(key),
+            Some(Fault::Fail) => Err(injected_error()),
+            Some(Fault::FailAfter) => {
+                self.store.remove_block(key)?;
+                Err(injected_error())
+            }
+            Some(Fault::Delay(duration)) => {
+                thread::sleep(duration);
+                self.store.remove_block(key)
+            }
+        }
+    }
+
+    fn list_blocks(&mut self, kind: BlockType) -> crate::store::Result<Vec<BlockId>> {
+        match self
+            .faults
+            .next(StoreOperation::ListBlocks, None, Some(kind))
+        {
+            None | Some(Fault::Corrupt) => self.store.list_blocks(kind),
+            Some(Fault::Fail) => Err(injected_error()),
+            Some(Fault::FailAfter) => {
+                self.store.list_blocks(kind)?;
+                Err(injected_error())
+            }
+            Some(Fault::Delay(duration)) => {
+                thread::sleep(duration);
+                self.store.list_blocks(kind)
+            }
+        }
+    }
+
+    fn available_space(&mut self) -> crate::store::Result<Option<u64>> {
+        self.store.available_space()
+    }
+}
@@ -0,0 +1,76 @@
+//! Utilities for testing code which uses this library.
+//!
+//! This module provides the helpers used by this crate's own test suite so that downstream crates
+//! don't need to rewrite them. It contains:
+//!
+//! - Repository configs which exercise different chunking, packing, encryption, and compression
+//! settings, like [`fixed_config`] and [`encoding_config`].
+//! - Helpers for creating repositories backed by a [`MemoryConfig`], like [`create_repo`],
+//! [`RepoStore`], and [`RepoObject`].
+//! - Generators for test data, including sparse data and data sized around chunk boundaries, like
+//! [`random_buffer`], [`sparse_bytes`], and [`chunk_boundary_sizes`].
+//! - Helpers for creating empty data stores, like [`memory_config`] and [`memory_store`].
+//! - A [`FaultInjectingStore`] which can be scripted to fail, delay, or corrupt operations on
+//! another data store, for testing how code recovers from errors in the data store.
+//!
+//! The helpers in this module are plain functions so that they can be used with any test
+//! framework. To use them as `rstest` fixtures, wrap them in a function annotated with
+//! `#[fixture]`.
+//!
+//! This module is only available with the `testing` feature, which also enables the `encryption`
+//! and `compression` features.
+//!
+//! # Examples
+//! ```
+//! use std::io::Write;
+//!
+//! use acid_store::repo::{key::KeyRepo, Commit};
+//! use acid_store::testing::{create_repo, encoding_config, random_buffer};
+//!
+//! let mut repo: KeyRepo<String> = create_repo(encoding_config()).unwrap();
+//! let data = random_buffer();
+//!
+//! let mut object = repo.insert(String::from("test"));
+//! object.write_all(&data).unwrap();
+//! object.commit().unwrap();
+//! drop(object);
+//!
+//! repo.commit().unwrap();
+//! ```
+//!
+//! [`fixed_config`]: crate::testing::fixed_config
+//! [`encoding_config`]: crate::testing::encoding_config
+//! [`MemoryConfig`]: crate::store::MemoryConfig
+//! [`create_repo`]: crate::testing::create_repo
+//! [`RepoStore`]: crate::testing::RepoStore
+//! [`RepoObject`]: crate::testing::RepoObject
+//! [`random_buffer`]: crate::testing::random_buffer
+//! [`sparse_bytes`]: crate::testing::sparse_bytes
+//! [`chunk_boundary_sizes`]: crate::testing::chunk_boundary_sizes
+//! [`memory_config`]: crate::testing::memory_config
+//! [`memory_store`]: crate::testing::memory_store
+//! [`FaultInjectingStore`]: crate::testing::FaultInjectingStore
+
+pub use self::config::{
+    encoding_config, fixed_config, fixed_packing_large_config, fixed_packing_small_config,
+    parallel_write_config, zpaq_config, zpaq_packing_config, TEST_CHUNK_SIZE,
+};
+pub use self::data::{
+    chunk_boundary_sizes, larger_buffer, random_buffer, random_bytes, smaller_buffer, sparse_bytes,
+    MAX_BUFFER_SIZE, MIN_BUFFER_SIZE,
+};
+pub use self::fault::{Fault, FaultInjectingConfig, FaultInjectingStore, FaultRule, Faults};
+pub use self::repository::{create_repo, BoxLockHandler, RepoObject, RepoStore};
+#[cfg(feature = "store-directory")]
+pub use self::store::{
+    directory_config, directory_store, sharded_directory_config, sharded_directory_store,
+};
+pub use self::store::{memory_config, memory_store, truncate_store};
+#[cfg(feature = "store-sqlite")]
+pub use self::store::{sqlite_config, sqlite_store};
+
+mod config;
+mod data;
+mod fault;
+mod repository;
+mod store;
@@ -0,0 +1,126 @@
+use rand::distributions::{Alphanumeric, DistString};
+use rand::thread_rng;
+
+use crate::repo::{
+    key::KeyRepo, InstanceId, Object, OpenMode, OpenOptions, OpenRepo, RepoConfig, DEFAULT_INSTANCE,
+};
+use crate::store::MemoryConfig;
+
+/// The length of the random keys generated by [`RepoObject`].
+const KEY_LEN: usize = 16;
+
+/// The length of the random passwords generated by [`RepoStore`].
+const PASSWORD_LEN: usize = 16;
+
+/// A helper which encapsulates a repository and an object in it.
+pub struct RepoObject {
+    /// The repository containing the object.
+    pub repo: KeyRepo<String>,
+
+    /// The object.
+    pub object: Object,
+
+    /// The randomly generated key of the object.
+    pub key: String,
+}
+
+impl RepoObject {
+    /// Create a new repository with the given `config` and insert an empty object into it.
+    ///
+    /// # Errors
+    /// - `Error::Store`: An error occurred with the data store.
+    /// - `Error::Io`: An I/O error occurred.
+    pub fn new(config: RepoConfig) -> crate::Result<Self> {
+        let mut repo: KeyRepo<String> = create_repo(config)?;
+        let key = Alphanumeric.sample_string(&mut thread_rng(), KEY_LEN);
+        let object = repo.insert(key.clone());
+        Ok(RepoObject { repo, object, key })
+    }
+}
+
+/// A function which handles existing locks in a [`RepoStore`].
+pub type BoxLockHandler = Box<dyn Fn(&[u8]) -> bool>;
+
+/// A helper for opening multiple repositories backed by the same data store.
+pub struct RepoStore {
+    /// The config for the data store.
+    pub store: MemoryConfig,
+
+    /// The config used when creating the repository.
+    pub config: RepoConfig,
+
+    /// The randomly generated password for the repository.
+    pub password: String,
+
+    /// The instance to open.
+    pub instance: InstanceId,
+
+    /// The context value to store in the lock.
+    pub context: Vec<u8>,
+
+    /// The handler which is called when the repository is already locked.
+    pub handler: BoxLockHandler,
+}
+
+impl RepoStore {
+    /// Create a new `RepoStore` backed by an empty data store.
+    ///
+    /// The repository is not created until [`create`] is called.
+    ///
+    /// [`create`]: crate::testing::RepoStore::create
+    pub fn new(config: RepoConfig) -> Self {
+        let password = Alphanumeric.sample_string(&mut thread_rng(), PASSWORD_LEN);
+        RepoStore {
+            store: MemoryConfig::new(),
+            config,
+            password,
+            instance: DEFAULT_INSTANCE,
+            context: Vec::new(),
+            handler: Box::new(|_| false),
+        }
+    }
+
+    /// Create a new repository.
+    ///
+    /// See [`OpenOptions::open`] for details.
+    ///
+    /// [`OpenOptions::open`]: crate::repo::OpenOptions::open
+    pub fn create<R: OpenRepo>(&self) -> crate::Result<R> {
+        OpenOptions::new()
+            .config(self.config.clone())
+            .password(self.password.as_bytes())
+            .instance(self.instance)
+            .locking(&self.context, |context| (self.handler)(context))
+            .mode(OpenMode::CreateNew)
+            .open(&self.store)
+    }
+
+    /// Open an existing repository.
+    ///
+    /// See [`OpenOptions::open`] for details.
+    ///
+    /// [`OpenOptions::open`]: crate::repo::OpenOptions::open
+    pub fn open<R: OpenRepo>(&self) -> crate::Result<R> {
+        OpenOptions::new()
+            .config(self.config.clone())
+            .password(self.password.as_bytes())
+            .instance(self.instance)
+            .locking(&self.context, |context| (self.handler)(context))
+            .mode(OpenMode::Open)
+            .open(&self.store)
+    }
+}
+
+/// Create a new repository with the given `config` backed by an empty [`MemoryConfig`].
+///
+/// See [`OpenOptions::open`] for details.
+///
+/// [`MemoryConfig`]: crate::store::MemoryConfig
+/// [`OpenOptions::open`]: crate::repo::OpenOptions::open
+pub fn create_repo<R: OpenRepo>(config: RepoConfig) -> crate::Result<R> {
+    OpenOptions::new()
+        .config(config)
+        .password(b"Password")
+        .mode(OpenMode::CreateNew)
+        .open(&MemoryConfig::new())
+}
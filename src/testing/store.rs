@@ -0,0 +1,213 @@
+#[cfg(feature = "store-directory")]
+use std::path::Path;
+
+#[cfg(any(feature = "store-directory", feature = "store-sqlite"))]
+use tempfile::TempDir;
+
+#[cfg(any(feature = "store-directory", feature = "store-sqlite"))]
+use crate::store::BlockId;
+use crate::store::{BlockKey, BlockType, DataStore, MemoryConfig, MemoryStore, OpenStore};
+#[cfg(feature = "store-directory")]
+use crate::store::{
+    DirectoryConfig, DirectoryDurability, DirectoryStore, ShardPlacement, ShardedDirectoryConfig,
+    ShardedDirectoryStore,
+};
+#[cfg(feature = "store-sqlite")]
+use crate::store::{SqliteConfig, SqliteStore};
+
+/// Remove all blocks in the given `store`.
+///
+/// # Errors
+/// - `Error::Io`: An I/O error occurred.
+/// - `Error::Store`: An error specific to the data store occurred.
+pub fn truncate_store(store: &mut impl DataStore) -> crate::store::Result<()> {
+    for block_id in store.list_blocks(BlockType::Data)? {
+        store.remove_block(BlockKey::Data(block_id))?;
+    }
+    for block_id in store.list_blocks(BlockType::Lock)? {
+        store.remove_block(BlockKey::Lock(block_id))?;
+    }
+    for block_id in store.list_blocks(BlockType::Header)? {
+        store.remove_block(BlockKey::Header(block_id))?;
+    }
+    store.remove_block(BlockKey::Super)?;
+    store.remove_block(BlockKey::Version)?;
+
+    Ok(())
+}
+
+/// A value which is tied to the lifetime of a temporary directory.
+#[cfg(any(feature = "store-directory", feature = "store-sqlite"))]
+struct WithTempDir<T> {
+    #[allow(dead_code)]
+    directory: TempDir,
+    value: T,
+}
+
+#[cfg(any(feature = "store-directory", feature = "store-sqlite"))]
+impl<T: DataStore> DataStore for WithTempDir<T> {
+    fn write_block(&mut self, key: BlockKey, data: &[u8]) -> crate::store::Result<()> {
+        self.value.write_block(key, data)
+    }
+
+    fn read_block(&mut self, key: BlockKey) -> crate::store::Result<Option<Vec<u8>>> {
+        self.value.read_block(key)
+    }
+
+    fn remove_block(&mut self, key: BlockKey) -> crate::store::Result<()> {
+        self.value.remove_block(key)
+    }
+
+    fn list_blocks(&mut self, kind: BlockType) -> crate::store::Result<Vec<BlockId>> {
+        self.value.list_blocks(kind)
+    }
+
+    fn available_space(&mut self) -> crate::store::Result<Option<u64>> {
+        self.value.available_space()
+    }
+
+    fn try_clone(&self) -> Option<Box<dyn DataStore>> {
+        self.value.try_clone()
+    }
+}
+
+#[cfg(any(feature = "store-directory", feature = "store-sqlite"))]
+impl<T: OpenStore> OpenStore for WithTempDir<T> {
+    type Store = T::Store;
+
+    fn open(&self) -> crate::Result<Self::Store> {
+        self.value.open()
+    }
+}
+
+/// Return the config for a new empty [`MemoryStore`].
+///
+/// [`MemoryStore`]: crate::store::MemoryStore
+pub fn memory_config() -> Box<dyn OpenStore<Store = MemoryStore>> {
+    Box::new(MemoryConfig::new())
+}
+
+/// Return a new empty [`MemoryStore`].
+///
+/// [`MemoryStore`]: crate::store::MemoryStore
+pub fn memory_store() -> Box<dyn DataStore> {
+    Box::new(memory_config().open().unwrap())
+}
+
+/// Return the config for a new [`DirectoryStore`] in a temporary directory.
+///
+/// The temporary directory is deleted when the returned value is dropped.
+///
+/// [`DirectoryStore`]: crate::store::DirectoryStore
+#[cfg(feature = "store-directory")]
+pub fn directory_config() -> Box<dyn OpenStore<Store = DirectoryStore>> {
+    let directory = tempfile::tempdir().unwrap();
+    let config = DirectoryConfig {
+        path: directory.as_ref().join("store"),
+        durability: DirectoryDurability::default(),
+    };
+    Box::new(WithTempDir {
+        directory,
+        value: config,
+    })
+}
+
+/// Return a new empty [`DirectoryStore`] in a temporary directory.
+///
+/// The temporary directory is deleted when the returned value is dropped.
+///
+/// [`DirectoryStore`]: crate::store::DirectoryStore
+#[cfg(feature = "store-directory")]
+pub fn directory_store() -> Box<dyn DataStore> {
+    let directory = tempfile::tempdir().unwrap();
+    let config = DirectoryConfig {
+        path: directory.as_ref().join("store"),
+        durability: DirectoryDurability::default(),
+    };
+    let mut store = config.open().unwrap();
+    truncate_store(&mut store).unwrap();
+    Box::new(WithTempDir {
+        directory,
+        value: store,
+    })
+}
+
+/// Return the config for a [`ShardedDirectoryStore`] spread across three directories in `path`.
+///
+/// [`ShardedDirectoryStore`]: crate::store::ShardedDirectoryStore
+#[cfg(feature = "store-directory")]
+fn new_sharded_directory_config(path: &Path) -> ShardedDirectoryConfig {
+    ShardedDirectoryConfig {
+        paths: ["first", "second", "third"]
+            .iter()
+            .map(|name| path.join(name))
+            .collect(),
+        placement: ShardPlacement::Weighted(vec![1, 2, 1]),
+        durability: DirectoryDurability::default(),
+    }
+}
+
+/// Return the config for a new [`ShardedDirectoryStore`] spread across three temporary
+/// directories.
+///
+/// The temporary directories are deleted when the returned value is dropped.
+///
+/// [`ShardedDirectoryStore`]: crate::store::ShardedDirectoryStore
+#[cfg(feature = "store-directory")]
+pub fn sharded_directory_config() -> Box<dyn OpenStore<Store = ShardedDirectoryStore>> {
+    let directory = tempfile::tempdir().unwrap();
+    let config = new_sharded_directory_config(directory.as_ref());
+    Box::new(WithTempDir {
+        directory,
+        value: config,
+    })
+}
+
+/// Return a new empty [`ShardedDirectoryStore`] spread across three temporary directories.
+///
+/// The temporary directories are deleted when the returned value is dropped.
+///
+/// [`ShardedDirectoryStore`]: crate::store::ShardedDirectoryStore
+#[cfg(feature = "store-directory")]
+pub fn sharded_directory_store() -> Box<dyn DataStore> {
+    let directory = tempfile::tempdir().unwrap();
+    let config = new_sharded_directory_config(directory.as_ref());
+    let mut store = config.open().unwrap();
+    truncate_store(&mut store).unwrap();
+    Box::new(WithTempDir {
+        directory,
+        value: store,
+    })
+}
+
+/// Return the config for a new [`SqliteStore`] in a temporary directory.
+///
+/// The temporary directory is deleted when the returned value is dropped.
+///
+/// [`SqliteStore`]: crate::store::SqliteStore
+#[cfg(feature = "store-sqlite")]
+pub fn sqlite_config() -> Box<dyn OpenStore<Store = SqliteStore>> {
+    let directory = tempfile::tempdir().unwrap();
+    let config = SqliteConfig::new(directory.as_ref().join("store.db"));
+    Box::new(WithTempDir {
+        directory,
+        value: config,
+    })
+}
+
+/// Return a new empty [`SqliteStore`] in a temporary directory.
+///
+/// The temporary directory is deleted when the returned value is dropped.
+///
+/// [`SqliteStore`]: crate::store::SqliteStore
+#[cfg(feature = "store-sqlite")]
+pub fn sqlite_store() -> Box<dyn DataStore> {
+    let directory = tempfile::tempdir().unwrap();
+    let config = SqliteConfig::new(directory.as_ref().join("store.db"));
+    let mut store = config.open().unwrap();
+    truncate_store(&mut store).unwrap();
+    Box::new(WithTempDir {
+        directory,
+        value: store,
+    })
+}
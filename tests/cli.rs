@@ -0,0 +1,56 @@
+#![cfg(all(feature = "cli", feature = "testing"))]
+
+use std::fs;
+use std::process::{Command, Output};
+
+use tempfile::TempDir;
+
+use common::*;
+
+mod common;
+
+/// Run the command-line tool with the given `args` on the store in `temp_dir`.
+fn run(temp_dir: &TempDir, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_acid-store"))
+        .arg("--store")
+        .arg(temp_dir.path().join("store"))
+        .args(args)
+        .env("ACID_STORE_PASSWORD", "password")
+        .output()
+        .unwrap()
+}
+
+#[rstest]
+fn archive_list_and_extract_tree(temp_dir: TempDir) {
+    let source = temp_dir.path().join("source");
+    fs::create_dir_all(source.join("directory")).unwrap();
+    fs::write(source.join("directory/file"), b"data").unwrap();
+
+    assert_that!(run(&temp_dir, &["create", "--encrypt"]).status.success()).is_true();
+    assert_that!(
+        run(&temp_dir, &["archive", source.to_str().unwrap(), "tree"])
+            .status
+            .success()
+    )
+    .is_true();
+
+    let output = String::from_utf8(run(&temp_dir, &["list"]).stdout).unwrap();
+    let mut entries = output.lines().collect::<Vec<_>>();
+    entries.sort();
+    assert_that!(entries).is_equal_to(vec!["tree/", "tree/directory/", "tree/directory/file"]);
+
+    let dest = temp_dir.path().join("dest");
+    assert_that!(run(&temp_dir, &["extract", "tree", dest.to_str().unwrap()])
+        .status
+        .success())
+    .is_true();
+    assert_that!(fs::read(dest.join("directory/file")).unwrap()).is_equal_to(b"data".to_vec());
+}
+
+#[rstest]
+fn unlock_requires_force(temp_dir: TempDir) {
+    run(&temp_dir, &["create"]);
+
+    assert_that!(run(&temp_dir, &["unlock"]).status.success()).is_false();
+    assert_that!(run(&temp_dir, &["unlock", "--force"]).status.success()).is_true();
+}
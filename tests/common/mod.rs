@@ -1,24 +1,32 @@
 #![allow(dead_code)]
-#![cfg(all(feature = "encryption", feature = "compression"))]
+#![cfg(feature = "testing")]
 
 mod assertions;
 mod config;
 mod data;
+#[cfg(all(target_os = "linux", feature = "fuse-mount"))]
+mod mount;
 mod repository;
 mod store;
 
-pub use assertions::ErrorVariantAssertions;
-pub use config::{
-    encoding_config, fixed_config, fixed_packing_large_config, fixed_packing_small_config,
-    zpaq_config, zpaq_packing_config,
+pub use acid_store::testing::{
+    create_repo, encoding_config, fixed_config, fixed_packing_large_config,
+    fixed_packing_small_config, memory_config, memory_store, parallel_write_config, zpaq_config,
+    zpaq_packing_config, RepoObject, RepoStore,
+};
+#[cfg(feature = "store-directory")]
+pub use acid_store::testing::{
+    directory_config, directory_store, sharded_directory_config, sharded_directory_store,
 };
+#[cfg(feature = "store-sqlite")]
+pub use acid_store::testing::{sqlite_config, sqlite_store};
+pub use assertions::ErrorVariantAssertions;
 pub use data::{buffer, fixed_buffer, larger_buffer, smaller_buffer, temp_dir};
-pub use repository::{create_repo, repo, repo_object, repo_store, RepoObject, RepoStore};
+#[cfg(all(target_os = "linux", feature = "fuse-mount"))]
+pub use mount::MountedRepo;
+pub use repository::{repo, repo_object, repo_store};
 pub use rstest::*;
 pub use spectral::prelude::*;
-#[cfg(feature = "store-directory")]
-pub use store::{directory_config, directory_store};
-pub use store::{memory_config, memory_store};
 #[cfg(feature = "store-rclone")]
 pub use store::{rclone_config, rclone_store};
 #[cfg(feature = "store-redis")]
@@ -27,5 +35,5 @@ pub use store::{redis_config, redis_store};
 pub use store::{s3_config, s3_store};
 #[cfg(feature = "store-sftp")]
 pub use store::{sftp_config, sftp_store};
-#[cfg(feature = "store-sqlite")]
-pub use store::{sqlite_config, sqlite_store};
+#[cfg(feature = "store-webdav")]
+pub use store::{webdav_config, webdav_store};
@@ -0,0 +1,73 @@
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::process::Command;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use acid_store::repo::file::{MountOption, SharedFileRepo, UnixMetadata, UnixSpecial};
+use tempfile::TempDir;
+
+/// How long to wait for a file system to be mounted.
+const MOUNT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A `SharedFileRepo` which is mounted as a FUSE file system in a background thread.
+///
+/// The file system is unmounted when this value is dropped.
+pub struct MountedRepo {
+    mountpoint: TempDir,
+    thread: Option<JoinHandle<acid_store::Result<()>>>,
+}
+
+impl MountedRepo {
+    /// Mount the entry at `root` in the given `repo` in a temporary directory with `options`.
+    ///
+    /// This doesn't return until the file system is mounted.
+    pub fn new(
+        repo: &SharedFileRepo<UnixSpecial, UnixMetadata>,
+        root: &str,
+        options: Vec<MountOption>,
+    ) -> anyhow::Result<Self> {
+        let mountpoint = tempfile::tempdir()?;
+        let unmounted_dev = mountpoint.path().metadata()?.dev();
+
+        let thread = thread::spawn({
+            let repo = repo.clone();
+            let root = root.to_owned();
+            let path = mountpoint.path().to_owned();
+            move || repo.mount(path, root, &options)
+        });
+
+        // The mountpoint is on a different device once the file system is mounted.
+        let mut waited = Duration::ZERO;
+        while mountpoint.path().metadata()?.dev() == unmounted_dev {
+            if waited >= MOUNT_TIMEOUT || thread.is_finished() {
+                anyhow::bail!("The file system could not be mounted.");
+            }
+            thread::sleep(Duration::from_millis(10));
+            waited += Duration::from_millis(10);
+        }
+
+        Ok(Self {
+            mountpoint,
+            thread: Some(thread),
+        })
+    }
+
+    /// The path of the mountpoint.
+    pub fn path(&self) -> &Path {
+        self.mountpoint.path()
+    }
+}
+
+impl Drop for MountedRepo {
+    fn drop(&mut self) {
+        Command::new("fusermount")
+            .arg("-u")
+            .arg(self.mountpoint.path())
+            .status()
+            .ok();
+        if let Some(thread) = self.thread.take() {
+            thread.join().ok();
+        }
+    }
+}
@@ -1,10 +1,27 @@
-#![cfg(all(feature = "encryption", feature = "compression"))]
+#![cfg(feature = "testing")]
 
 use std::fmt::Debug;
-
-use acid_store::store::{BlockKey, BlockType, DataStore, OpenStore};
+use std::io::{Read, Write};
+use std::time::{Duration, Instant};
+
+use acid_store::repo::key::KeyRepo;
+use acid_store::repo::{Commit, OpenMode, OpenOptions};
+#[cfg(feature = "store-sqlite")]
+use acid_store::store::SqliteConfig;
+use acid_store::store::{
+    migrate, migrate_with_progress, BlockId, BlockKey, BlockType, CheckStatus, DataStore,
+    ErrorContext, MemoryConfig, MemoryStore, MigrateOptions, OpenStore, RetryPolicy, RetryStore,
+    StoreOperation, ThrottlePolicy, ThrottleStore,
+};
+#[cfg(feature = "store-directory")]
+use acid_store::store::{
+    DirectoryConfig, DirectoryDurability, ShardPlacement, ShardedDirectoryConfig,
+};
+use acid_store::testing::{Fault, FaultInjectingConfig, FaultInjectingStore, FaultRule, Faults};
 use rstest_reuse::{self, *};
 use serial_test::serial;
+#[cfg(any(feature = "store-directory", feature = "store-sqlite"))]
+use tempfile::TempDir;
 use uuid::Uuid;
 
 use common::*;
@@ -30,6 +47,57 @@ fn read_data_block(#[case] mut store: Box<dyn DataStore>, buffer: Vec<u8>) {
     assert_that!(store.read_block(BlockKey::Data(id))).is_ok_containing(Some(buffer));
 }
 
+#[apply(data_stores)]
+#[serial(data_store)]
+fn read_data_block_stream(
+    #[case] mut store: Box<dyn DataStore>,
+    buffer: Vec<u8>,
+) -> anyhow::Result<()> {
+    let id = Uuid::new_v4().into();
+
+    assert_that!(store.read_block_stream(BlockKey::Data(id))?.is_none()).is_true();
+    store.write_block_stream(BlockKey::Data(id), &mut buffer.as_slice())?;
+
+    let mut actual_data = Vec::new();
+    store
+        .read_block_stream(BlockKey::Data(id))?
+        .expect("The block was not written.")
+        .read_to_end(&mut actual_data)?;
+    assert_that!(actual_data).is_equal_to(buffer);
+
+    Ok(())
+}
+
+#[apply(data_stores)]
+#[serial(data_store)]
+fn read_data_block_range(#[case] mut store: Box<dyn DataStore>, buffer: Vec<u8>) {
+    let id = Uuid::new_v4().into();
+    let len = buffer.len() as u64;
+
+    assert_that!(store.read_block_range(BlockKey::Data(id), 0, 10)).is_ok_containing(None);
+    assert_that!(store.write_block(BlockKey::Data(id), &buffer)).is_ok();
+    assert_that!(store.read_block_range(BlockKey::Data(id), 10, 20))
+        .is_ok_containing(Some(buffer[10..30].to_vec()));
+    assert_that!(store.read_block_range(BlockKey::Data(id), len - 5, 20))
+        .is_ok_containing(Some(buffer[buffer.len() - 5..].to_vec()));
+    assert_that!(store.read_block_range(BlockKey::Data(id), len + 5, 20))
+        .is_ok_containing(Some(Vec::new()));
+}
+
+#[apply(data_stores)]
+#[serial(data_store)]
+fn health_check_passes(#[case] mut store: Box<dyn DataStore>) -> anyhow::Result<()> {
+    let blocks_before = store.list_blocks(BlockType::Data)?.len();
+    let report = store.health_check();
+
+    assert_that!(report.is_healthy()).is_true();
+    assert_that!(report.error().is_none()).is_true();
+    assert_that!(report.latency()).is_some();
+    assert_that!(store.list_blocks(BlockType::Data)?).has_length(blocks_before);
+
+    Ok(())
+}
+
 #[apply(data_stores)]
 #[serial(data_store)]
 fn read_lock_block(#[case] mut store: Box<dyn DataStore>, buffer: Vec<u8>) {
@@ -255,3 +323,423 @@ fn list_header_blocks(#[case] mut store: Box<dyn DataStore>, buffer: Vec<u8>) {
         .is_ok()
         .contains_all_of(&[&id1, &id2, &id3]);
 }
+
+#[cfg(feature = "store-sqlite")]
+#[rstest]
+fn sqlite_vacuum_into_copies_blocks(temp_dir: TempDir, buffer: Vec<u8>) -> anyhow::Result<()> {
+    let id = Uuid::new_v4().into();
+    let mut store = SqliteConfig::new(temp_dir.path().join("store.db")).open()?;
+    store.write_block(BlockKey::Data(id), &buffer)?;
+
+    let copy_path = temp_dir.path().join("copy.db");
+    store.vacuum_into(&copy_path)?;
+    drop(store);
+
+    let mut copy = SqliteConfig::new(copy_path).open()?;
+    assert_that!(copy.read_block(BlockKey::Data(id))).is_ok_containing(Some(buffer));
+
+    Ok(())
+}
+
+#[rstest]
+fn memory_store_snapshot_round_trips(buffer: Vec<u8>) -> anyhow::Result<()> {
+    let data_id = Uuid::new_v4().into();
+    let lock_id = Uuid::new_v4().into();
+    let mut store = MemoryConfig::new().open()?;
+    store.write_block(BlockKey::Data(data_id), &buffer)?;
+    store.write_block(BlockKey::Lock(lock_id), &buffer)?;
+    store.write_block(BlockKey::Super, &buffer)?;
+
+    let mut restored = MemoryStore::from_bytes(&store.to_bytes())?;
+
+    assert_that!(restored.read_block(BlockKey::Data(data_id)))
+        .is_ok_containing(Some(buffer.clone()));
+    assert_that!(restored.read_block(BlockKey::Lock(lock_id)))
+        .is_ok_containing(Some(buffer.clone()));
+    assert_that!(restored.read_block(BlockKey::Super)).is_ok_containing(Some(buffer));
+    assert_that!(restored.read_block(BlockKey::Version)).is_ok_containing(None);
+
+    Ok(())
+}
+
+#[rstest]
+fn memory_store_snapshot_with_invalid_data_errs(buffer: Vec<u8>) -> anyhow::Result<()> {
+    let mut store = MemoryConfig::new().open()?;
+    store.write_block(BlockKey::Data(Uuid::new_v4().into()), &buffer)?;
+    let snapshot = store.to_bytes();
+
+    assert_that!(MemoryStore::from_bytes(&buffer))
+        .is_err_variant(acid_store::Error::UnsupportedStore);
+    assert_that!(MemoryStore::from_bytes(&snapshot[..snapshot.len() - 1]))
+        .is_err_variant(acid_store::Error::Deserialize);
+
+    Ok(())
+}
+
+#[rstest]
+fn migrate_copies_repository(buffer: Vec<u8>) -> anyhow::Result<()> {
+    let source = MemoryConfig::new();
+    let dest = MemoryConfig::new();
+    let mut repo: KeyRepo<String> = OpenOptions::new().mode(OpenMode::CreateNew).open(&source)?;
+    let mut object = repo.insert(String::from("test"));
+    object.write_all(&buffer)?;
+    object.commit()?;
+    drop(object);
+    repo.commit()?;
+    drop(repo);
+
+    let mut options = MigrateOptions::default();
+    options.verify = true;
+    let mut blocks = 0;
+    migrate_with_progress(&source, &dest, options, |progress| {
+        blocks = progress.blocks;
+    })?;
+
+    let repo: KeyRepo<String> = OpenOptions::new().open(&dest)?;
+    let mut contents = Vec::new();
+    repo.object("test").unwrap().read_to_end(&mut contents)?;
+
+    assert_that!(contents).is_equal_to(buffer);
+    assert_that!(blocks).is_greater_than(2);
+
+    Ok(())
+}
+
+#[rstest]
+fn migrate_to_nonempty_store_errs() -> anyhow::Result<()> {
+    let source = MemoryConfig::new();
+    let dest = MemoryConfig::new();
+    let _: KeyRepo<String> = OpenOptions::new().mode(OpenMode::CreateNew).open(&source)?;
+    let _: KeyRepo<String> = OpenOptions::new().mode(OpenMode::CreateNew).open(&dest)?;
+
+    assert_that!(migrate(&source, &dest, MigrateOptions::default()))
+        .is_err_variant(acid_store::Error::AlreadyExists);
+
+    Ok(())
+}
+
+#[cfg(feature = "store-directory")]
+#[rstest]
+fn directory_blocks_persist_with_each_durability(
+    temp_dir: TempDir,
+    buffer: Vec<u8>,
+    #[values(
+        DirectoryDurability::None,
+        DirectoryDurability::Commit,
+        DirectoryDurability::Full
+    )]
+    durability: DirectoryDurability,
+) {
+    let config = DirectoryConfig {
+        path: temp_dir.path().join("store"),
+        durability,
+    };
+    let mut store = config.open().unwrap();
+    let id = Uuid::new_v4().into();
+    assert_that!(store.write_block(BlockKey::Data(id), &buffer)).is_ok();
+    assert_that!(store.write_block(BlockKey::Super, &buffer)).is_ok();
+    drop(store);
+
+    let mut store = config.open().unwrap();
+
+    assert_that!(store.read_block(BlockKey::Data(id))).is_ok_containing(Some(buffer.clone()));
+    assert_that!(store.read_block(BlockKey::Super)).is_ok_containing(Some(buffer));
+}
+
+#[cfg(feature = "store-directory")]
+#[rstest]
+fn sharded_blocks_are_found_after_placement_changes(temp_dir: TempDir, buffer: Vec<u8>) {
+    let mut config = ShardedDirectoryConfig {
+        paths: vec![
+            temp_dir.path().join("first"),
+            temp_dir.path().join("second"),
+        ],
+        placement: ShardPlacement::Weighted(vec![1, 0]),
+        durability: DirectoryDurability::default(),
+    };
+    let mut store = config.open().unwrap();
+    let ids = (0..8).map(|_| Uuid::new_v4().into()).collect::<Vec<_>>();
+    for id in &ids {
+        assert_that!(store.write_block(BlockKey::Data(*id), &buffer)).is_ok();
+    }
+    drop(store);
+
+    config.placement = ShardPlacement::Weighted(vec![0, 1]);
+    let mut store = config.open().unwrap();
+
+    for id in &ids {
+        assert_that!(store.read_block(BlockKey::Data(*id))).is_ok_containing(Some(buffer.clone()));
+    }
+    assert_that!(store.list_blocks(BlockType::Data))
+        .is_ok()
+        .has_length(ids.len());
+
+    for id in &ids {
+        assert_that!(store.remove_block(BlockKey::Data(*id))).is_ok();
+    }
+    assert_that!(store.list_blocks(BlockType::Data)?).has_length(blocks_before);
+
+    Ok(())
+}
+
+/// A data store which fails a given number of times before each successful operation.
+#[derive(Debug)]
+struct FlakyStore<S> {
+    store: S,
+    failures: u32,
+    remaining_failures: u32,
+}
+
+impl<S: DataStore> FlakyStore<S> {
+    fn new(store: S, failures: u32) -> Self {
+        FlakyStore {
+            store,
+            failures,
+            remaining_failures: failures,
+        }
+    }
+
+    fn check(&mut self) -> acid_store::store::Result<()> {
+        if self.remaining_failures > 0 {
+            self.remaining_failures -= 1;
+            Err(acid_store::store::Error::msg("Transient failure."))
+        } else {
+            self.remaining_failures = self.failures;
+            Ok(())
+        }
+    }
+}
+
+impl<S: DataStore> DataStore for FlakyStore<S> {
+    fn write_block(&mut self, key: BlockKey, data: &[u8]) -> acid_store::store::Result<()> {
+        self.check()?;
+        self.store.write_block(key, data)
+    }
+
+    fn read_block(&mut self, key: BlockKey) -> acid_store::store::Result<Option<Vec<u8>>> {
+        self.check()?;
+        self.store.read_block(key)
+    }
+
+    fn remove_block(&mut self, key: BlockKey) -> acid_store::store::Result<()> {
+        self.check()?;
+        self.store.remove_block(key)
+    }
+
+    fn list_blocks(&mut self, kind: BlockType) -> acid_store::store::Result<Vec<BlockId>> {
+        self.check()?;
+        self.store.list_blocks(kind)
+    }
+}
+
+fn fast_retry_policy(max_retries: u32) -> RetryPolicy {
+    RetryPolicy {
+        max_retries,
+        initial_delay: Duration::from_millis(1),
+        ..RetryPolicy::default()
+    }
+}
+
+#[rstest]
+fn retry_store_retries_transient_failures(buffer: Vec<u8>) -> anyhow::Result<()> {
+    let flaky_store = FlakyStore::new(MemoryConfig::new().open()?, 2);
+    let mut store = RetryStore::new(flaky_store, fast_retry_policy(2));
+    let id = Uuid::new_v4().into();
+
+    assert_that!(store.write_block(BlockKey::Data(id), &buffer)).is_ok();
+    assert_that!(store.read_block(BlockKey::Data(id))).is_ok_containing(Some(buffer));
+
+    Ok(())
+}
+
+#[rstest]
+fn retry_store_gives_up_after_max_retries(buffer: Vec<u8>) -> anyhow::Result<()> {
+    let flaky_store = FlakyStore::new(MemoryConfig::new().open()?, 3);
+    let mut store = RetryStore::new(flaky_store, fast_retry_policy(2));
+
+    assert_that!(store.write_block(BlockKey::Data(Uuid::new_v4().into()), &buffer)).is_err();
+
+    Ok(())
+}
+
+#[rstest]
+fn throttle_store_limits_write_rate() -> anyhow::Result<()> {
+    let policy = ThrottlePolicy {
+        write_rate: Some(10_000),
+        ..ThrottlePolicy::default()
+    };
+    let mut store = ThrottleStore::new(MemoryConfig::new().open()?, policy);
+    let data = vec![0u8; 1000];
+
+    let start = Instant::now();
+    for _ in 0..3 {
+        store.write_block(BlockKey::Data(Uuid::new_v4().into()), &data)?;
+    }
+
+    // The third write must wait for the first two to be paid for.
+    assert_that!(start.elapsed()).is_greater_than_or_equal_to(Duration::from_millis(200));
+    assert_that!(store.list_blocks(BlockType::Data)?).has_length(3);
+
+    Ok(())
+}
+
+#[rstest]
+fn fault_injecting_store_fails_matching_operations(buffer: Vec<u8>) -> anyhow::Result<()> {
+    let faults = Faults::new();
+    let mut store = FaultInjectingStore::new(MemoryConfig::new().open()?, faults.clone());
+    let first_id = Uuid::new_v4().into();
+    let second_id = Uuid::new_v4().into();
+
+    faults.inject(
+        FaultRule::new(StoreOperation::WriteBlock, Fault::Fail)
+            .block_type(BlockType::Data)
+            .skip(1)
+            .times(1),
+    );
+
+    assert_that!(store.write_block(BlockKey::Data(first_id), &buffer)).is_ok();
+    assert_that!(store.write_block(BlockKey::Data(second_id), &buffer)).is_err();
+    assert_that!(store.write_block(BlockKey::Data(second_id), &buffer)).is_ok();
+    assert_that!(store.write_block(BlockKey::Super, &buffer)).is_ok();
+    assert_that!(faults.injected()).is_equal_to(1);
+
+    Ok(())
+}
+
+#[rstest]
+fn fault_injecting_store_completes_operation_before_failing_after(
+    buffer: Vec<u8>,
+) -> anyhow::Result<()> {
+    let faults = Faults::new();
+    let mut store = FaultInjectingStore::new(MemoryConfig::new().open()?, faults.clone());
+    let id = Uuid::new_v4().into();
+
+    faults.inject(FaultRule::new(StoreOperation::WriteBlock, Fault::FailAfter).times(1));
+
+    assert_that!(store.write_block(BlockKey::Data(id), &buffer)).is_err();
+    assert_that!(store.read_block(BlockKey::Data(id))).is_ok_containing(Some(buffer));
+
+    Ok(())
+}
+
+#[rstest]
+fn fault_injecting_store_corrupts_reads(buffer: Vec<u8>) -> anyhow::Result<()> {
+    let faults = Faults::new();
+    let mut store = FaultInjectingStore::new(MemoryConfig::new().open()?, faults.clone());
+    let id = Uuid::new_v4().into();
+
+    store.write_block(BlockKey::Data(id), &buffer)?;
+    faults.inject(
+        FaultRule::new(StoreOperation::ReadBlock, Fault::Corrupt)
+            .key(BlockKey::Data(id))
+            .times(1),
+    );
+
+    let corrupted = store.read_block(BlockKey::Data(id))?.unwrap();
+    assert_that!(corrupted).has_length(buffer.len());
+    assert_that!(corrupted).is_not_equal_to(&buffer);
+    assert_that!(store.read_block(BlockKey::Data(id))).is_ok_containing(Some(buffer));
+
+    Ok(())
+}
+
+#[rstest]
+fn failed_commit_leaves_previous_commit_intact(buffer: Vec<u8>) -> anyhow::Result<()> {
+    let faults = Faults::new();
+    let config = FaultInjectingConfig {
+        config: MemoryConfig::new(),
+        faults: faults.clone(),
+    };
+
+    let mut repo: KeyRepo<String> = OpenOptions::new().mode(OpenMode::CreateNew).open(&config)?;
+    let mut object = repo.insert(String::from("committed"));
+    object.write_all(&buffer)?;
+    object.commit()?;
+    drop(object);
+    repo.commit()?;
+
+    // The super block is written last, so failing it aborts the commit.
+    faults.inject(FaultRule::new(StoreOperation::WriteBlock, Fault::Fail).key(BlockKey::Super));
+    repo.insert(String::from("uncommitted"));
+    assert_that!(repo.commit()).is_err();
+    drop(repo);
+
+    faults.clear();
+    let repo: KeyRepo<String> = OpenOptions::new().open(&config)?;
+    assert_that!(repo.contains("committed")).is_true();
+    assert_that!(repo.contains("uncommitted")).is_false();
+
+    Ok(())
+}
+
+#[rstest]
+fn health_check_reports_failures() -> anyhow::Result<()> {
+    let mut store = FlakyStore::new(MemoryConfig::new().open()?, 1);
+    let report = store.health_check();
+
+    assert_that!(report.is_healthy()).is_false();
+    assert_that!(report.error().is_some()).is_true();
+    assert_that!(report.list).matches(|status| matches!(status, CheckStatus::Failed(_)));
+    assert_that!(report.write).is_equal_to(CheckStatus::Skipped);
+    assert_that!(report.read).is_equal_to(CheckStatus::Skipped);
+    assert_that!(report.remove).is_equal_to(CheckStatus::Skipped);
+
+    Ok(())
+}
+
+/// A data store which can be read from but not written to.
+#[derive(Debug)]
+struct ReadOnlyStore;
+
+impl DataStore for ReadOnlyStore {
+    fn write_block(&mut self, _key: BlockKey, _data: &[u8]) -> acid_store::store::Result<()> {
+        Err(acid_store::store::Error::msg("Permission denied."))
+    }
+
+    fn read_block(&mut self, _key: BlockKey) -> acid_store::store::Result<Option<Vec<u8>>> {
+        Ok(None)
+    }
+
+    fn remove_block(&mut self, _key: BlockKey) -> acid_store::store::Result<()> {
+        Err(acid_store::store::Error::msg("Permission denied."))
+    }
+
+    fn list_blocks(&mut self, _kind: BlockType) -> acid_store::store::Result<Vec<BlockId>> {
+        Ok(Vec::new())
+    }
+}
+
+#[derive(Debug)]
+struct ReadOnlyConfig;
+
+impl OpenStore for ReadOnlyConfig {
+    type Store = ReadOnlyStore;
+
+    fn open(&self) -> acid_store::Result<Self::Store> {
+        Ok(ReadOnlyStore)
+    }
+}
+
+#[rstest]
+fn store_errors_have_context() {
+    let result: acid_store::Result<KeyRepo<String>> = OpenOptions::new()
+        .mode(OpenMode::CreateNew)
+        .open(&ReadOnlyConfig);
+    let error = result.unwrap_err();
+
+    assert_that!(error.code()).is_equal_to(22);
+    assert_that!(error.code_name()).is_equal_to("store");
+    assert_that!(error.context().map(ErrorContext::operation))
+        .is_equal_to(Some(StoreOperation::WriteBlock));
+    assert_that!(error.context().and_then(ErrorContext::block_key)).is_some();
+}
+
+#[rstest]
+fn error_codes_are_stable() {
+    assert_that!(acid_store::Error::AlreadyExists.code()).is_equal_to(1);
+    assert_that!(acid_store::Error::NotFound.code_name()).is_equal_to("not_found");
+    assert_that!(acid_store::Error::InvalidData.code()).is_equal_to(20);
+    assert_that!(acid_store::Error::LimitExceeded.code()).is_equal_to(25);
+    assert_that!(acid_store::Error::LimitExceeded.code_name()).is_equal_to("limit_exceeded");
+    assert_that!(acid_store::Error::NotFound.context()).is_none();
+}
@@ -1,4 +1,4 @@
-#![cfg(all(feature = "repo-file", feature = "encryption", feature = "compression"))]
+#![cfg(all(feature = "repo-file", feature = "testing"))]
 
 use std::collections::HashSet;
 use std::fs::{create_dir, File};
@@ -10,11 +10,16 @@ use exacl::{AclEntry, AclEntryKind, AclOption, Flag, Perm};
 use relative_path::RelativePathBuf;
 use tempfile::TempDir;
 
-use acid_store::repo::file::{Entry, FileMode, FileRepo, WalkPredicate};
+use acid_store::repo::file::{
+    ArchiveOptions, ChangeDetection, Entry, EntryEvent, EntryLimits, ExtractOptions, FileMode,
+    FileRepo, ParentConflict, PathSemantics, ProgressAction, SyncOptions, WalkPredicate,
+};
 use acid_store::repo::{Commit, SwitchInstance, DEFAULT_INSTANCE};
 
 use acid_store::uuid::Uuid;
 use common::*;
+#[cfg(all(target_os = "linux", feature = "fuse-mount"))]
+use {acid_store::repo::file::MountOption, acid_store::store::MemoryConfig};
 #[cfg(all(unix, feature = "file-metadata"))]
 use {
     acid_store::repo::file::{
@@ -165,6 +170,42 @@ fn create_parent_of_top_level_file(mut repo: FileRepo) -> anyhow::Result<()> {
     Ok(())
 }
 
+#[rstest]
+fn create_parents_with_file_ancestor_errs(mut repo: FileRepo) -> anyhow::Result<()> {
+    repo.create("home", &Entry::file())?;
+
+    assert_that!(repo.create_parents("home/lostatc/test", &Entry::file()))
+        .is_err_variant(acid_store::Error::NotDirectory);
+    assert_that!(repo.is_file("home")).is_true();
+
+    Ok(())
+}
+
+#[rstest]
+fn create_parents_replacing_file_ancestor(mut repo: FileRepo) -> anyhow::Result<()> {
+    repo.create("home", &Entry::file())?;
+    repo.create_parents_with("home/lostatc/test", &Entry::file(), ParentConflict::Replace)?;
+
+    assert_that!(repo.is_directory("home")).is_true();
+    assert_that!(repo.is_file("home/lostatc/test")).is_true();
+
+    Ok(())
+}
+
+#[rstest]
+fn create_parents_renaming_file_ancestor(mut repo: FileRepo) -> anyhow::Result<()> {
+    repo.create("home", &Entry::file())?;
+    repo.create("home~1", &Entry::file())?;
+    repo.create_parents_with("home/lostatc/test", &Entry::file(), ParentConflict::Rename)?;
+
+    assert_that!(repo.is_directory("home")).is_true();
+    assert_that!(repo.is_file("home/lostatc/test")).is_true();
+    assert_that!(repo.is_file("home~1")).is_true();
+    assert_that!(repo.is_file("home~2")).is_true();
+
+    Ok(())
+}
+
 #[rstest]
 fn removing_nonexistent_path_errs(mut repo: FileRepo) {
     assert_that!(repo.remove("nonexistent")).is_err_variant(acid_store::Error::NotFound);
@@ -262,6 +303,71 @@ fn set_common_metadata(mut repo: FileRepo<NoSpecial, CommonMetadata>) -> anyhow:
     Ok(())
 }
 
+#[rstest]
+#[cfg(all(unix, feature = "file-metadata"))]
+fn time_tracking_updates_modified_time_on_commit(
+    mut repo: FileRepo<NoSpecial, CommonMetadata>,
+    buffer: Vec<u8>,
+) -> anyhow::Result<()> {
+    let original_metadata = CommonMetadata {
+        modified: SystemTime::UNIX_EPOCH,
+        accessed: SystemTime::UNIX_EPOCH,
+    };
+    repo.create("modified", &Entry::file())?;
+    repo.create("unmodified", &Entry::file())?;
+    repo.set_metadata("modified", Some(original_metadata.clone()))?;
+    repo.set_metadata("unmodified", Some(original_metadata.clone()))?;
+    repo.set_time_tracking(true);
+
+    let mut object = repo.open("modified")?;
+    object.write_all(&buffer)?;
+    object.commit()?;
+    drop(object);
+    repo.open("unmodified")?;
+
+    repo.commit()?;
+
+    let modified_metadata = repo.entry("modified")?.metadata.unwrap();
+    let unmodified_metadata = repo.entry("unmodified")?.metadata.unwrap();
+
+    assert_that!(modified_metadata.modified).is_greater_than(SystemTime::UNIX_EPOCH);
+    assert_that!(modified_metadata.accessed).is_equal_to(SystemTime::UNIX_EPOCH);
+    assert_that!(unmodified_metadata).is_equal_to(original_metadata);
+
+    Ok(())
+}
+
+#[rstest]
+#[cfg(all(unix, feature = "file-metadata"))]
+fn time_tracking_updates_changed_time(
+    mut repo: FileRepo<UnixSpecial, UnixMetadata>,
+) -> anyhow::Result<()> {
+    repo.create("file", &Entry::file())?;
+    let mut metadata = UnixMetadata {
+        mode: FileMode::S_IRUSR,
+        modified: SystemTime::UNIX_EPOCH,
+        accessed: SystemTime::UNIX_EPOCH,
+        changed: SystemTime::UNIX_EPOCH,
+        user: 0,
+        group: 0,
+        attributes: HashMap::new(),
+        acl: Acl::new(),
+    };
+
+    repo.set_metadata("file", Some(metadata.clone()))?;
+    assert_that!(repo.entry("file")?.metadata).contains_value(metadata.clone());
+
+    repo.set_time_tracking(true);
+    metadata.mode = FileMode::S_IRWXU;
+    repo.set_metadata("file", Some(metadata))?;
+
+    let actual_metadata = repo.entry("file")?.metadata.unwrap();
+    assert_that!(actual_metadata.changed).is_greater_than(SystemTime::UNIX_EPOCH);
+    assert_that!(actual_metadata.modified).is_equal_to(SystemTime::UNIX_EPOCH);
+
+    Ok(())
+}
+
 #[rstest]
 fn open_file(mut repo: FileRepo, buffer: Vec<u8>) -> anyhow::Result<()> {
     repo.create("file", &Entry::file())?;
@@ -357,6 +463,267 @@ fn copy_entry_with_invalid_destination_errs(mut repo: FileRepo) -> anyhow::Resul
     Ok(())
 }
 
+#[rstest]
+fn reflinked_file_shares_extents_until_modified(
+    mut repo: FileRepo,
+    buffer: Vec<u8>,
+) -> anyhow::Result<()> {
+    repo.create("source", &Entry::file())?;
+    let mut object = repo.open("source")?;
+    object.write_all(&buffer)?;
+    object.commit()?;
+    drop(object);
+
+    repo.reflink("source", "dest")?;
+
+    assert_that!(repo.shares_extents("source", "dest")).is_ok_containing(true);
+
+    let mut object = repo.open("dest")?;
+    object.write_all(b"new data")?;
+    object.commit()?;
+    drop(object);
+
+    assert_that!(repo.shares_extents("source", "dest")).is_ok_containing(false);
+
+    Ok(())
+}
+
+#[rstest]
+fn case_insensitive_paths_refer_to_same_entry(mut repo: FileRepo) -> anyhow::Result<()> {
+    repo.set_path_semantics(PathSemantics::CaseInsensitive)?;
+    repo.create("Directory", &Entry::directory())?;
+    repo.create("DIRECTORY/File", &Entry::file())?;
+
+    assert_that!(repo.is_file("directory/file")).is_true();
+    assert_that!(repo.create("directory/FILE", &Entry::file()))
+        .is_err_variant(acid_store::Error::AlreadyExists);
+    assert_that!(repo.descendants("")?.collect::<HashSet<_>>()).is_equal_to(HashSet::from([
+        RelativePathBuf::from("Directory"),
+        RelativePathBuf::from("Directory/File"),
+    ]));
+
+    Ok(())
+}
+
+#[rstest]
+fn changing_path_semantics_with_conflicting_paths_errs(mut repo: FileRepo) -> anyhow::Result<()> {
+    repo.create("file", &Entry::file())?;
+    repo.create("FILE", &Entry::file())?;
+
+    assert_that!(repo.set_path_semantics(PathSemantics::CaseInsensitive))
+        .is_err_variant(acid_store::Error::AlreadyExists);
+    assert_that!(repo.path_semantics()).is_equal_to(PathSemantics::CaseSensitive);
+
+    Ok(())
+}
+
+#[rstest]
+fn creating_path_longer_than_limit_errs(mut repo: FileRepo) -> anyhow::Result<()> {
+    let mut limits = EntryLimits::default();
+    limits.max_path_len = Some(8);
+    repo.set_limits(limits);
+
+    assert_that!(repo.create("short", &Entry::file())).is_ok();
+    assert_that!(repo.create("much-too-long", &Entry::file()))
+        .is_err_variant(acid_store::Error::LimitExceeded);
+    assert_that!(repo.rename("short", "much-too-long"))
+        .is_err_variant(acid_store::Error::LimitExceeded);
+    assert_that!(repo.exists("much-too-long")).is_false();
+    assert_that!(repo.exists("short")).is_true();
+
+    Ok(())
+}
+
+#[rstest]
+#[cfg(all(unix, feature = "file-metadata"))]
+fn setting_metadata_larger_than_limit_errs(
+    mut repo: FileRepo<UnixSpecial, UnixMetadata>,
+) -> anyhow::Result<()> {
+    repo.create("file", &Entry::file())?;
+
+    let mut limits = EntryLimits::default();
+    limits.max_metadata_size = Some(256);
+    repo.set_limits(limits);
+
+    let metadata = UnixMetadata {
+        mode: FileMode::S_IRUSR,
+        modified: SystemTime::UNIX_EPOCH,
+        accessed: SystemTime::UNIX_EPOCH,
+        changed: SystemTime::UNIX_EPOCH,
+        user: 0,
+        group: 0,
+        attributes: HashMap::from([(String::from("user.large"), vec![0u8; 1024])]),
+        acl: Acl::new(),
+    };
+
+    assert_that!(repo.set_metadata("file", Some(metadata)))
+        .is_err_variant(acid_store::Error::LimitExceeded);
+    assert_that!(repo.entry("file")?.metadata).is_none();
+
+    Ok(())
+}
+
+#[rstest]
+fn archiving_file_larger_than_limit_errs(
+    mut repo: FileRepo,
+    temp_dir: TempDir,
+    buffer: Vec<u8>,
+) -> anyhow::Result<()> {
+    let source_path = temp_dir.as_ref().join("source");
+    let mut source_file = File::create(&source_path)?;
+    source_file.write_all(&buffer)?;
+    source_file.flush()?;
+
+    let mut limits = EntryLimits::default();
+    limits.max_file_size = Some(buffer.len() as u64 - 1);
+    repo.set_limits(limits);
+
+    assert_that!(repo.archive(&source_path, "dest"))
+        .is_err_variant(acid_store::Error::LimitExceeded);
+    assert_that!(repo.exists("dest")).is_false();
+
+    Ok(())
+}
+
+#[rstest]
+fn watching_reports_entry_changes(mut repo: FileRepo) -> anyhow::Result<()> {
+    let events = repo.watch("");
+
+    repo.create("source", &Entry::file())?;
+    repo.set_metadata("source", None)?;
+    repo.rename("source", "dest")?;
+    repo.remove("dest")?;
+
+    assert_that!(events.try_iter().collect::<Vec<_>>()).is_equal_to(vec![
+        EntryEvent::Created(RelativePathBuf::from("source")),
+        EntryEvent::Modified(RelativePathBuf::from("source")),
+        EntryEvent::Renamed {
+            source: RelativePathBuf::from("source"),
+            dest: RelativePathBuf::from("dest"),
+        },
+        EntryEvent::Removed(RelativePathBuf::from("dest")),
+    ]);
+
+    Ok(())
+}
+
+#[rstest]
+fn watching_only_reports_changes_under_prefix(mut repo: FileRepo) -> anyhow::Result<()> {
+    let events = repo.watch("directory");
+
+    repo.create("directory", &Entry::directory())?;
+    repo.create("directory/file", &Entry::file())?;
+    repo.create("other", &Entry::file())?;
+    repo.remove_tree("directory")?;
+
+    let actual_events = events.try_iter().collect::<HashSet<_>>();
+    let expected_events = HashSet::from([
+        EntryEvent::Created(RelativePathBuf::from("directory")),
+        EntryEvent::Created(RelativePathBuf::from("directory/file")),
+        EntryEvent::Removed(RelativePathBuf::from("directory")),
+        EntryEvent::Removed(RelativePathBuf::from("directory/file")),
+    ]);
+
+    assert_that!(actual_events).is_equal_to(expected_events);
+
+    Ok(())
+}
+
+#[rstest]
+fn saved_version_can_be_read(mut repo: FileRepo, buffer: Vec<u8>) -> anyhow::Result<()> {
+    repo.create("file", &Entry::file())?;
+    let mut object = repo.open("file")?;
+    object.write_all(&buffer)?;
+    object.commit()?;
+    drop(object);
+
+    let version = repo.save_version("file")?;
+
+    let mut object = repo.open("file")?;
+    object.set_len(0)?;
+    object.write_all(b"new data")?;
+    object.commit()?;
+    drop(object);
+
+    let mut actual_data = Vec::new();
+    repo.open_version("file", version.id())?
+        .read_to_end(&mut actual_data)?;
+
+    assert_that!(version.size()).is_equal_to(buffer.len() as u64);
+    assert_that!(actual_data).is_equal_to(&buffer);
+
+    Ok(())
+}
+
+#[rstest]
+fn versions_are_listed_in_order(mut repo: FileRepo) -> anyhow::Result<()> {
+    repo.create("file", &Entry::file())?;
+    let first = repo.save_version("file")?;
+    let second = repo.save_version("file")?;
+
+    assert_that!(repo.versions("file")).is_ok_containing(vec![first, second]);
+    assert_that!(repo.versions("nonexistent")).is_ok_containing(Vec::new());
+
+    Ok(())
+}
+
+#[rstest]
+fn restore_version_of_removed_file(mut repo: FileRepo, buffer: Vec<u8>) -> anyhow::Result<()> {
+    repo.create("file", &Entry::file())?;
+    let mut object = repo.open("file")?;
+    object.write_all(&buffer)?;
+    object.commit()?;
+    drop(object);
+
+    let version = repo.save_version("file")?;
+    repo.remove("file")?;
+    repo.restore_version("file", version.id())?;
+
+    let mut actual_data = Vec::new();
+    repo.open("file")?.read_to_end(&mut actual_data)?;
+
+    assert_that!(actual_data).is_equal_to(&buffer);
+
+    Ok(())
+}
+
+#[rstest]
+fn removed_version_can_not_be_opened(mut repo: FileRepo) -> anyhow::Result<()> {
+    repo.create("file", &Entry::file())?;
+    let version = repo.save_version("file")?;
+
+    assert_that!(repo.remove_version("file", version.id())).is_ok_containing(true);
+    assert_that!(repo.remove_version("file", version.id())).is_ok_containing(false);
+    assert_that!(repo.open_version("file", version.id()))
+        .is_err_variant(acid_store::Error::NotFound);
+    assert_that!(repo.versions("file")).is_ok_containing(Vec::new());
+
+    Ok(())
+}
+
+#[rstest]
+fn saving_version_of_non_file_errs(mut repo: FileRepo) -> anyhow::Result<()> {
+    repo.create("directory", &Entry::directory())?;
+
+    assert_that!(repo.save_version("directory")).is_err_variant(acid_store::Error::NotFile);
+    assert_that!(repo.save_version("nonexistent")).is_err_variant(acid_store::Error::NotFound);
+
+    Ok(())
+}
+
+#[rstest]
+fn reflinking_non_file_errs(mut repo: FileRepo) -> anyhow::Result<()> {
+    repo.create("directory", &Entry::directory())?;
+    repo.create("file", &Entry::file())?;
+
+    assert_that!(repo.reflink("directory", "dest")).is_err_variant(acid_store::Error::NotFile);
+    assert_that!(repo.reflink("nonexistent", "dest")).is_err_variant(acid_store::Error::NotFound);
+    assert_that!(repo.shares_extents("file", "directory"))
+        .is_err_variant(acid_store::Error::NotFile);
+
+    Ok(())
+}
+
 #[rstest]
 fn copy_tree(mut repo: FileRepo) -> anyhow::Result<()> {
     repo.create_parents("source/file1", &Entry::file())?;
@@ -642,6 +1009,35 @@ fn removing_entry_does_not_affect_linked_entry(mut repo: FileRepo) -> anyhow::Re
     Ok(())
 }
 
+#[rstest]
+fn tree_stats_describe_descendants(mut repo: FileRepo, buffer: Vec<u8>) -> anyhow::Result<()> {
+    repo.create("outside", &Entry::file())?;
+    repo.create_parents("directory/child", &Entry::directory())?;
+    repo.create("directory/source", &Entry::file())?;
+    let mut object = repo.open("directory/source")?;
+    object.write_all(&buffer)?;
+    object.commit()?;
+    drop(object);
+
+    repo.reflink("directory/source", "directory/reflink")?;
+    repo.link("directory/source", "directory/child/link")?;
+
+    let file_stats = repo.tree_stats("directory/source")?;
+    let tree_stats = repo.tree_stats("directory")?;
+
+    assert_that!(file_stats.files()).is_equal_to(1);
+    assert_that!(file_stats.apparent_size()).is_equal_to(buffer.len() as u64);
+    assert_that!(tree_stats.files()).is_equal_to(2);
+    assert_that!(tree_stats.directories()).is_equal_to(1);
+    assert_that!(tree_stats.entries()).is_equal_to(3);
+    assert_that!(tree_stats.apparent_size()).is_equal_to(2 * buffer.len() as u64);
+    assert_that!(tree_stats.actual_size()).is_equal_to(file_stats.actual_size());
+    assert_that!(repo.tree_stats("")?.entries()).is_equal_to(5);
+    assert_that!(repo.tree_stats("nonexistent")).is_err_variant(acid_store::Error::NotFound);
+
+    Ok(())
+}
+
 #[rstest]
 fn link_count_returns_number_of_links(mut repo: FileRepo) -> anyhow::Result<()> {
     repo.create("one", &Entry::file())?;
@@ -869,6 +1265,94 @@ fn archive_file(mut repo: FileRepo, temp_dir: TempDir, buffer: Vec<u8>) -> anyho
     Ok(())
 }
 
+#[rstest]
+fn sync_tree_archives_new_and_changed_files(
+    mut repo: FileRepo,
+    temp_dir: TempDir,
+) -> anyhow::Result<()> {
+    let source_path = temp_dir.as_ref().join("source");
+    create_dir(&source_path)?;
+    File::create(source_path.join("unchanged"))?.write_all(b"unchanged")?;
+    File::create(source_path.join("changed"))?.write_all(b"old")?;
+
+    repo.archive_tree(&source_path, "dest")?;
+    let unchanged_id = repo.entry_id("dest/unchanged")?;
+
+    File::create(source_path.join("changed"))?.write_all(b"new contents")?;
+    File::create(source_path.join("new"))?.write_all(b"new")?;
+
+    repo.sync_tree(&source_path, "dest", SyncOptions::default())?;
+
+    let mut actual_contents = Vec::new();
+    repo.open("dest/changed")?
+        .read_to_end(&mut actual_contents)?;
+
+    assert_that!(actual_contents.as_slice()).is_equal_to(&b"new contents"[..]);
+    assert_that!(repo.is_file("dest/new")).is_true();
+    assert_that!(repo.entry_id("dest/unchanged")).is_ok_containing(unchanged_id);
+
+    Ok(())
+}
+
+#[rstest]
+fn sync_tree_detects_changed_contents(mut repo: FileRepo, temp_dir: TempDir) -> anyhow::Result<()> {
+    let source_path = temp_dir.as_ref().join("source");
+    create_dir(&source_path)?;
+    File::create(source_path.join("file"))?.write_all(b"old")?;
+
+    repo.archive_tree(&source_path, "dest")?;
+
+    File::create(source_path.join("file"))?.write_all(b"new")?;
+
+    let mut options = SyncOptions::default();
+    options.detection = ChangeDetection::Contents;
+    repo.sync_tree(&source_path, "dest", options)?;
+
+    let mut actual_contents = Vec::new();
+    repo.open("dest/file")?.read_to_end(&mut actual_contents)?;
+
+    assert_that!(actual_contents.as_slice()).is_equal_to(&b"new"[..]);
+
+    Ok(())
+}
+
+#[rstest]
+fn sync_tree_removes_deleted_files(mut repo: FileRepo, temp_dir: TempDir) -> anyhow::Result<()> {
+    let source_path = temp_dir.as_ref().join("source");
+    create_dir(&source_path)?;
+    create_dir(source_path.join("directory"))?;
+    File::create(source_path.join("directory/file"))?;
+    File::create(source_path.join("file"))?;
+
+    repo.archive_tree(&source_path, "dest")?;
+
+    std::fs::remove_dir_all(source_path.join("directory"))?;
+
+    repo.sync_tree(&source_path, "dest", SyncOptions::default())?;
+    assert_that!(repo.exists("dest/directory")).is_true();
+
+    let mut options = SyncOptions::default();
+    options.remove_deleted = true;
+    repo.sync_tree(&source_path, "dest", options)?;
+
+    assert_that!(repo.exists("dest/directory")).is_false();
+    assert_that!(repo.exists("dest/directory/file")).is_false();
+    assert_that!(repo.exists("dest/file")).is_true();
+
+    Ok(())
+}
+
+#[rstest]
+fn sync_tree_to_empty_path_errs(mut repo: FileRepo, temp_dir: TempDir) -> anyhow::Result<()> {
+    let source_path = temp_dir.as_ref().join("source");
+    File::create(&source_path)?;
+
+    assert_that!(repo.sync_tree(&source_path, "", SyncOptions::default()))
+        .is_err_variant(acid_store::Error::InvalidPath);
+
+    Ok(())
+}
+
 #[rstest]
 #[cfg(all(unix, feature = "file-metadata"))]
 fn archive_unix_special_files(
@@ -927,6 +1411,153 @@ fn archive_tree(mut repo: FileRepo, temp_dir: TempDir) -> anyhow::Result<()> {
     Ok(())
 }
 
+#[rstest]
+fn archive_tree_reports_progress(mut repo: FileRepo, temp_dir: TempDir) -> anyhow::Result<()> {
+    let source_path = temp_dir.as_ref().join("source");
+
+    create_dir(&source_path)?;
+    File::create(&source_path.join("file1"))?.write_all(b"data")?;
+    create_dir(&source_path.join("directory"))?;
+    File::create(&source_path.join("directory/file2"))?.write_all(b"more data")?;
+
+    let mut reports = Vec::new();
+    repo.archive_tree_with_progress(&source_path, "dest", |progress| {
+        reports.push((progress.files, progress.bytes));
+        ProgressAction::Continue
+    })?;
+
+    assert_that!(reports).has_length(4);
+    assert_that!(reports.last()).is_equal_to(Some(&(4, 13)));
+
+    Ok(())
+}
+
+#[rstest]
+fn archive_tree_with_filters(mut repo: FileRepo, temp_dir: TempDir) -> anyhow::Result<()> {
+    let source_path = temp_dir.as_ref().join("source");
+
+    create_dir(&source_path)?;
+    File::create(&source_path.join("file.txt"))?;
+    File::create(&source_path.join("file.bin"))?;
+    File::create(&source_path.join("large.txt"))?.write_all(&[0u8; 1024])?;
+    create_dir(&source_path.join("directory"))?;
+    File::create(&source_path.join("directory/nested.txt"))?;
+    create_dir(&source_path.join("excluded"))?;
+    File::create(&source_path.join("excluded/file.txt"))?;
+    create_dir(&source_path.join("empty"))?;
+
+    let mut options = ArchiveOptions::default();
+    options.include = vec![String::from("**/*.txt")];
+    options.exclude = vec![String::from("excluded")];
+    options.max_file_size = Some(512);
+
+    repo.archive_tree_with(&source_path, "dest", &options, |_| ProgressAction::Continue)?;
+
+    assert_that!(repo.is_file("dest/file.txt")).is_true();
+    assert_that!(repo.is_file("dest/directory/nested.txt")).is_true();
+    assert_that!(repo.exists("dest/file.bin")).is_false();
+    assert_that!(repo.exists("dest/large.txt")).is_false();
+    assert_that!(repo.exists("dest/excluded")).is_false();
+    assert_that!(repo.exists("dest/empty")).is_false();
+
+    Ok(())
+}
+
+#[rstest]
+#[cfg(unix)]
+fn archive_tree_preserves_hard_links(mut repo: FileRepo, temp_dir: TempDir) -> anyhow::Result<()> {
+    use std::os::unix::fs::MetadataExt;
+
+    let source_path = temp_dir.as_ref().join("source");
+    let dest_path = temp_dir.as_ref().join("dest");
+
+    create_dir(&source_path)?;
+    File::create(&source_path.join("original"))?.write_all(b"data")?;
+    std::fs::hard_link(source_path.join("original"), source_path.join("link"))?;
+
+    let mut options = ArchiveOptions::default();
+    options.detect_hard_links = true;
+
+    repo.archive_tree_with(&source_path, "source", &options, |_| {
+        ProgressAction::Continue
+    })?;
+
+    assert_that!(repo.entry_id("source/link")?).is_equal_to(repo.entry_id("source/original")?);
+    assert_that!(repo.link_count(repo.entry_id("source/original")?)).is_equal_to(2);
+
+    repo.extract_tree("source", &dest_path)?;
+
+    let original_metadata = std::fs::metadata(dest_path.join("original"))?;
+    let link_metadata = std::fs::metadata(dest_path.join("link"))?;
+    assert_that!(link_metadata.ino()).is_equal_to(original_metadata.ino());
+
+    Ok(())
+}
+
+#[rstest]
+fn archive_tree_with_max_depth(mut repo: FileRepo, temp_dir: TempDir) -> anyhow::Result<()> {
+    let source_path = temp_dir.as_ref().join("source");
+
+    create_dir(&source_path)?;
+    create_dir(&source_path.join("directory"))?;
+    File::create(&source_path.join("directory/file"))?;
+
+    let mut options = ArchiveOptions::default();
+    options.max_depth = Some(1);
+
+    repo.archive_tree_with(&source_path, "dest", &options, |_| ProgressAction::Continue)?;
+
+    assert_that!(repo.is_directory("dest/directory")).is_true();
+    assert_that!(repo.exists("dest/directory/file")).is_false();
+
+    Ok(())
+}
+
+#[rstest]
+fn archive_tree_with_invalid_pattern_errs(
+    mut repo: FileRepo,
+    temp_dir: TempDir,
+) -> anyhow::Result<()> {
+    let source_path = temp_dir.as_ref().join("source");
+    create_dir(&source_path)?;
+
+    let mut options = ArchiveOptions::default();
+    options.include = vec![String::from("[")];
+
+    assert_that!(repo.archive_tree_with(&source_path, "dest", &options, |_| {
+        ProgressAction::Continue
+    }))
+    .is_err_variant(acid_store::Error::InvalidPath);
+
+    Ok(())
+}
+
+#[rstest]
+fn cancel_archive_tree(mut repo: FileRepo, temp_dir: TempDir) -> anyhow::Result<()> {
+    let source_path = temp_dir.as_ref().join("source");
+
+    create_dir(&source_path)?;
+    File::create(&source_path.join("file1"))?;
+    File::create(&source_path.join("file2"))?;
+
+    let result = repo.archive_tree_with_progress(&source_path, "dest", |progress| {
+        if progress.files >= 2 {
+            ProgressAction::Cancel
+        } else {
+            ProgressAction::Continue
+        }
+    });
+
+    assert_that!(result).is_err_variant(acid_store::Error::Cancelled);
+    assert_that!(repo.descendants("dest")?.count()).is_equal_to(1);
+
+    repo.rollback()?;
+
+    assert_that!(repo.exists("dest")).is_false();
+
+    Ok(())
+}
+
 #[rstest]
 #[cfg(unix)]
 fn archive_tree_skips_unsupported_file_types(
@@ -1065,6 +1696,53 @@ fn extract_tree(mut repo: FileRepo, temp_dir: TempDir) -> anyhow::Result<()> {
     Ok(())
 }
 
+#[rstest]
+fn extract_tree_with_filters(mut repo: FileRepo, temp_dir: TempDir) -> anyhow::Result<()> {
+    let dest_path = temp_dir.as_ref().join("dest");
+
+    repo.create("source", &Entry::directory())?;
+    repo.create("source/file.txt", &Entry::file())?;
+    repo.create("source/file.bin", &Entry::file())?;
+    repo.create("source/directory", &Entry::directory())?;
+    repo.create("source/directory/nested.txt", &Entry::file())?;
+    repo.create("source/excluded", &Entry::directory())?;
+    repo.create("source/excluded/file.txt", &Entry::file())?;
+
+    let mut options = ExtractOptions::default();
+    options.include = vec![String::from("**/*.txt")];
+    options.exclude = vec![String::from("excluded")];
+
+    repo.extract_tree_with("source", &dest_path, &options, |_| ProgressAction::Continue)?;
+
+    assert_that!(dest_path.join("file.txt")).is_a_file();
+    assert_that!(dest_path.join("directory/nested.txt")).is_a_file();
+    assert_that!(dest_path.join("file.bin").exists()).is_false();
+    assert_that!(dest_path.join("excluded").exists()).is_false();
+
+    Ok(())
+}
+
+#[rstest]
+fn cancel_extract_tree(mut repo: FileRepo, temp_dir: TempDir) -> anyhow::Result<()> {
+    let dest_path = temp_dir.as_ref().join("dest");
+
+    repo.create("source", &Entry::directory())?;
+    repo.create("source/file1", &Entry::file())?;
+    repo.create("source/file2", &Entry::file())?;
+
+    let mut files = 0;
+    let result = repo.extract_tree_with_progress("source", &dest_path, |progress| {
+        files = progress.files;
+        ProgressAction::Cancel
+    });
+
+    assert_that!(result).is_err_variant(acid_store::Error::Cancelled);
+    assert_that!(files).is_equal_to(1);
+    assert_that!(dest_path).is_a_directory();
+
+    Ok(())
+}
+
 #[rstest]
 #[cfg(all(unix, feature = "file-metadata"))]
 fn write_unix_metadata(
@@ -1309,3 +1987,131 @@ fn verify_valid_repository_is_valid(mut repo: FileRepo) -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[rstest]
+fn query_and_commit_shared_repo_from_other_thread(repo_store: RepoStore) -> anyhow::Result<()> {
+    let mut repo: FileRepo = repo_store.create()?;
+    repo.create_parents("home/lostatc/file", &Entry::file())?;
+    let shared = repo.into_shared();
+
+    let thread = std::thread::spawn({
+        let shared = shared.clone();
+        move || -> acid_store::Result<Vec<RelativePathBuf>> {
+            let children = shared.children("home/lostatc")?;
+            shared.commit()?;
+            Ok(children)
+        }
+    });
+    let children = thread.join().unwrap()?;
+
+    assert_that!(children).is_equal_to(vec![RelativePathBuf::from("home/lostatc/file")]);
+    assert_that!(shared.is_file("home/lostatc/file")).is_true();
+    drop(shared.try_unwrap().unwrap());
+
+    let repo: FileRepo = repo_store.open()?;
+    assert_that!(repo.is_file("home/lostatc/file")).is_true();
+
+    Ok(())
+}
+
+#[rstest]
+fn read_file_through_shared_repo(mut repo: FileRepo, buffer: Vec<u8>) -> anyhow::Result<()> {
+    repo.create("file", &Entry::file())?;
+    let mut object = repo.open("file")?;
+    object.write_all(&buffer)?;
+    object.commit()?;
+    drop(object);
+    let shared = repo.into_shared();
+
+    let mut object = shared.open("file")?;
+    let mut actual_contents = Vec::new();
+    object.read_to_end(&mut actual_contents)?;
+
+    assert_that!(actual_contents).is_equal_to(buffer);
+    assert_that!(shared.open("nonexistent").map(|_| ()))
+        .is_err_variant(acid_store::Error::NotFound);
+
+    Ok(())
+}
+
+#[rstest]
+#[cfg(all(target_os = "linux", feature = "fuse-mount"))]
+fn shared_repo_sees_writes_while_mounted(
+    mut repo: FileRepo<UnixSpecial, UnixMetadata>,
+    buffer: Vec<u8>,
+) -> anyhow::Result<()> {
+    repo.create_parents("root/file", &Entry::file())?;
+    let shared = repo.into_shared();
+    let mount = MountedRepo::new(&shared, "root", Vec::new())?;
+    let mounted_path = mount.path().join("file");
+
+    // Read the file through the shared handle before and after writing to it through the file
+    // system. The file system must see the same contents as the shared handle.
+    let mut object = shared.open("root/file")?;
+    let mut old_contents = Vec::new();
+    object.read_to_end(&mut old_contents)?;
+    assert_that!(old_contents).is_empty();
+
+    std::fs::write(&mounted_path, &buffer)?;
+
+    let mut object = shared.open("root/file")?;
+    let mut actual_contents = Vec::new();
+    object.read_to_end(&mut actual_contents)?;
+    assert_that!(actual_contents).is_equal_to(&buffer);
+    assert_that!(std::fs::metadata(&mounted_path)?.len()).is_equal_to(buffer.len() as u64);
+    assert_that!(std::fs::read(&mounted_path)?).is_equal_to(&buffer);
+
+    Ok(())
+}
+
+#[rstest]
+#[cfg(all(target_os = "linux", feature = "fuse-mount"))]
+fn auto_commit_commits_changes_between_requests(
+    repo_store: RepoStore,
+    buffer: Vec<u8>,
+) -> anyhow::Result<()> {
+    let mut repo: FileRepo<UnixSpecial, UnixMetadata> = repo_store.create()?;
+    repo.create_parents("root/file", &Entry::file())?;
+    repo.commit()?;
+    let shared = repo.into_shared();
+    let mount = MountedRepo::new(
+        &shared,
+        "root",
+        vec![MountOption::AutoCommit(Duration::from_millis(100))],
+    )?;
+
+    // Keep the file open so that the changes aren't committed when it's closed.
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .open(mount.path().join("file"))?;
+    file.write_all(&buffer)?;
+
+    // Wait past the interval without sending any more requests to the file system.
+    std::thread::sleep(Duration::from_secs(1));
+
+    // Open a copy of the data store as it is now, ignoring the lock held by the mounted repository.
+    let copy_store = RepoStore {
+        store: MemoryConfig::from_bytes(&repo_store.store.to_bytes())?,
+        handler: Box::new(|_| true),
+        ..repo_store
+    };
+    let copy: FileRepo<UnixSpecial, UnixMetadata> = copy_store.open()?;
+    let mut object = copy.open("root/file")?;
+    let mut actual_contents = Vec::new();
+    object.read_to_end(&mut actual_contents)?;
+    assert_that!(actual_contents).is_equal_to(&buffer);
+
+    drop(file);
+    drop(mount);
+
+    Ok(())
+}
+
+#[rstest]
+fn unwrapping_shared_file_repo_with_clones_errs(repo: FileRepo) {
+    let shared = repo.into_shared();
+    let clone = shared.clone();
+    let shared = shared.try_unwrap().unwrap_err();
+    drop(clone);
+    assert_that!(shared.try_unwrap()).is_ok();
+}
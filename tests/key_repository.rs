@@ -1,15 +1,19 @@
-#![cfg(all(feature = "encryption", feature = "compression"))]
+#![cfg(feature = "testing")]
 
 use std::io::{Read, Write};
 
 use acid_store::repo::key::KeyRepo;
 use acid_store::repo::{
-    peek_info, Commit, Encryption, ResourceLimit, RestoreSavepoint, SwitchInstance, Unlock,
+    export_repo, peek_info, Chunking, Commit, ContentId, Encryption, KeyDerivation, LockInfo,
+    MergeConflict, OpenMode, OpenOptions, Packing, RepackOptions, RepoConfig, RepoEvent,
+    RepoExport, ResourceLimit, RestoreSavepoint, SwitchInstance, Unlock, DEFAULT_INSTANCE,
 };
-use acid_store::store::{BlockType, DataStore, OpenStore};
+use acid_store::store::{BlockKey, BlockType, DataStore, OpenStore};
 use common::*;
 use rstest_reuse::{self, *};
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
+use std::thread;
+use std::time::{Duration, SystemTime};
 use uuid::Uuid;
 
 mod common;
@@ -55,6 +59,57 @@ fn list_keys(mut repo: KeyRepo<String>) {
     ]);
 }
 
+#[rstest]
+fn list_keys_with_prefix(mut repo: KeyRepo<String>) {
+    repo.insert(String::from("dir/first"));
+    repo.insert(String::from("dir/second"));
+    repo.insert(String::from("other"));
+
+    assert_that!(repo
+        .keys_with_prefix("dir/")
+        .cloned()
+        .collect::<HashSet<_>>())
+    .is_equal_to(
+        [String::from("dir/first"), String::from("dir/second")]
+            .into_iter()
+            .collect::<HashSet<_>>(),
+    );
+    assert_that!(repo.keys_with_prefix("missing").next()).is_none();
+}
+
+#[rstest]
+fn list_keys_in_pages(mut repo: KeyRepo<u32>) {
+    for key in [5, 3, 8, 1, 9, 2, 7] {
+        repo.insert(key);
+    }
+
+    let first_page = repo.keys_page(None, 3);
+    let second_page = repo.keys_page(first_page.last().copied(), 3);
+    let last_page = repo.keys_page(second_page.last().copied(), 3);
+
+    assert_that!(first_page).is_equal_to(vec![&1, &2, &3]);
+    assert_that!(second_page).is_equal_to(vec![&5, &7, &8]);
+    assert_that!(last_page).is_equal_to(vec![&9]);
+}
+
+#[rstest]
+fn pages_are_not_affected_by_removed_keys(mut repo: KeyRepo<u32>) {
+    for key in 0..6 {
+        repo.insert(key);
+    }
+
+    let first_page = repo
+        .keys_page(None, 3)
+        .into_iter()
+        .copied()
+        .collect::<Vec<_>>();
+    repo.remove(&1);
+    repo.remove(&3);
+
+    assert_that!(first_page).is_equal_to(vec![0, 1, 2]);
+    assert_that!(repo.keys_page(Some(&2), 3)).is_equal_to(vec![&4, &5]);
+}
+
 #[rstest]
 fn can_not_get_object_from_removed_key(mut repo: KeyRepo<String>) {
     repo.insert(String::from("test"));
@@ -101,6 +156,86 @@ fn copied_object_must_exist(mut repo: KeyRepo<String>) {
     assert_that!(repo.copy("nonexistent", String::from("copy"))).is_false();
 }
 
+#[rstest]
+fn renamed_object_moves_to_new_key(
+    mut repo: KeyRepo<String>,
+    buffer: Vec<u8>,
+) -> anyhow::Result<()> {
+    let mut object = repo.insert(String::from("source"));
+    object.write_all(&buffer)?;
+    object.commit()?;
+    drop(object);
+
+    repo.rename("source", String::from("dest"))?;
+
+    let mut actual_contents = Vec::new();
+    repo.object("dest")
+        .unwrap()
+        .read_to_end(&mut actual_contents)?;
+
+    assert_that!(repo.contains("source")).is_false();
+    assert_that!(actual_contents).is_equal_to(buffer);
+
+    Ok(())
+}
+
+#[rstest]
+fn renaming_to_existing_key_errs(mut repo: KeyRepo<String>) {
+    repo.insert(String::from("source"));
+    repo.insert(String::from("dest"));
+
+    assert_that!(repo.rename("source", String::from("dest")))
+        .is_err_variant(acid_store::Error::AlreadyExists);
+    assert_that!(repo.rename("nonexistent", String::from("other")))
+        .is_err_variant(acid_store::Error::NotFound);
+    assert_that!(repo.contains("source")).is_true();
+}
+
+#[rstest]
+fn swapped_objects_exchange_keys(mut repo: KeyRepo<String>) -> anyhow::Result<()> {
+    for key in ["first", "second"] {
+        let mut object = repo.insert(String::from(key));
+        object.write_all(key.as_bytes())?;
+        object.commit()?;
+    }
+    let first_id = repo.object("first").unwrap().content_id()?;
+    let second_id = repo.object("second").unwrap().content_id()?;
+
+    repo.swap("first", "second")?;
+
+    assert_that!(repo.object("first").unwrap().content_id()).is_ok_containing(second_id);
+    assert_that!(repo.object("second").unwrap().content_id()).is_ok_containing(first_id);
+    assert_that!(repo.swap("first", "nonexistent")).is_err_variant(acid_store::Error::NotFound);
+
+    Ok(())
+}
+
+#[rstest]
+fn object_id_resolves_to_renamed_object(mut repo: KeyRepo<String>) -> anyhow::Result<()> {
+    let object_id = repo.insert(String::from("source")).object_id()?;
+    repo.insert(String::from("other"));
+
+    repo.rename("source", String::from("dest"))?;
+
+    assert_that!(repo.resolve(object_id)).is_equal_to(Some(&String::from("dest")));
+    assert_that!(repo.object_by_id(object_id).unwrap().object_id()).is_ok_containing(object_id);
+
+    Ok(())
+}
+
+#[rstest]
+fn object_id_of_removed_object_does_not_resolve(mut repo: KeyRepo<String>) -> anyhow::Result<()> {
+    let object_id = repo.insert(String::from("test")).object_id()?;
+    assert_that!(repo.resolve(object_id)).is_equal_to(Some(&String::from("test")));
+
+    repo.remove("test");
+
+    assert_that!(repo.resolve(object_id)).is_none();
+    assert_that!(repo.object_by_id(object_id)).is_none();
+
+    Ok(())
+}
+
 #[apply(object_config)]
 fn copying_overwrites_destination(
     #[case] repo_object: RepoObject,
@@ -159,252 +294,303 @@ fn copy_nonexistent_object(mut repo: KeyRepo<String>) {
 }
 
 #[rstest]
-fn object_is_not_accessible_from_another_instance(repo_object: RepoObject) -> anyhow::Result<()> {
-    let RepoObject { repo, key, .. } = repo_object;
-
-    assert_that!(repo.contains(&key)).is_true();
-    assert_that!(repo.object(&key)).is_some();
-
-    let repo: KeyRepo<String> = repo.switch_instance(Uuid::new_v4().into())?;
+fn insert_batch_writes_all_objects(
+    mut repo: KeyRepo<String>,
+    buffer: Vec<u8>,
+    smaller_buffer: Vec<u8>,
+) -> anyhow::Result<()> {
+    let mut object = repo.insert(String::from("first"));
+    object.write_all(&smaller_buffer)?;
+    object.commit()?;
+    drop(object);
 
-    assert_that!(repo.contains(&key)).is_false();
-    assert_that!(repo.object(&key)).is_none();
+    repo.insert_batch(vec![
+        (String::from("first"), buffer.as_slice()),
+        (String::from("second"), smaller_buffer.as_slice()),
+        (String::from("empty"), &[][..]),
+    ])?;
+
+    let mut first_data = Vec::new();
+    repo.object("first").unwrap().read_to_end(&mut first_data)?;
+    let mut second_data = Vec::new();
+    repo.object("second")
+        .unwrap()
+        .read_to_end(&mut second_data)?;
+
+    assert_that!(first_data).is_equal_to(&buffer);
+    assert_that!(second_data).is_equal_to(&smaller_buffer);
+    assert_that!(repo.object("empty").unwrap().size()).is_ok_containing(0);
+    assert_that!(repo.verify()).is_ok_containing(HashSet::new());
 
     Ok(())
 }
 
 #[rstest]
-fn committing_commits_all_instances(repo_store: RepoStore) -> anyhow::Result<()> {
-    let instance_1 = Uuid::new_v4().into();
-    let instance_2 = Uuid::new_v4().into();
-
-    let repo: KeyRepo<String> = repo_store.create()?;
-
-    let mut repo: KeyRepo<String> = repo.switch_instance(instance_1)?;
-    repo.insert(String::from("test1"));
-
-    let mut repo: KeyRepo<String> = repo.switch_instance(instance_2)?;
-    repo.insert(String::from("test2"));
-
-    repo.commit()?;
-    drop(repo);
-    let repo: KeyRepo<String> = repo_store.open()?;
-
-    let repo: KeyRepo<String> = repo.switch_instance(instance_1)?;
-    assert_that!(repo.contains("test1")).is_true();
-
-    let repo: KeyRepo<String> = repo.switch_instance(instance_2)?;
-    assert_that!(repo.contains("test2")).is_true();
+fn insert_batch_past_quota_errs(buffer: Vec<u8>) -> anyhow::Result<()> {
+    let mut config = RepoConfig::default();
+    config.quota = Some(buffer.len() as u64);
+    let mut repo: KeyRepo<String> = create_repo(config)?;
+
+    assert_that!(repo.insert_batch(vec![
+        (String::from("first"), buffer.as_slice()),
+        (String::from("second"), buffer.as_slice()),
+    ]))
+    .is_err_variant(acid_store::Error::QuotaExceeded);
+    assert_that!(repo.keys().next()).is_none();
 
     Ok(())
 }
 
 #[rstest]
-fn change_password(mut repo_store: RepoStore) -> anyhow::Result<()> {
-    repo_store.config.encryption = Encryption::XChaCha20Poly1305;
-    let mut repo: KeyRepo<String> = repo_store.create()?;
-
-    repo.change_password(
-        b"New password",
-        ResourceLimit::Interactive,
-        ResourceLimit::Interactive,
-    );
-    repo.commit()?;
-    drop(repo);
-
-    repo_store.password = String::from("New password");
-
-    assert_that!(repo_store.open::<KeyRepo<String>>()).is_ok();
+fn insert_batch_with_duplicate_keys_at_quota(buffer: Vec<u8>) -> anyhow::Result<()> {
+    let mut config = RepoConfig::default();
+    config.quota = Some(buffer.len() as u64);
+    let mut repo: KeyRepo<String> = create_repo(config)?;
+    repo.insert_batch(vec![(String::from("test"), buffer.as_slice())])?;
+
+    // Only the last object for each key is kept, and it replaces the existing object.
+    assert_that!(repo.insert_batch(vec![
+        (String::from("test"), buffer.as_slice()),
+        (String::from("test"), buffer.as_slice()),
+    ]))
+    .is_ok();
+    assert_that!(repo.object("test").unwrap().size()).is_ok_containing(buffer.len() as u64);
 
     Ok(())
 }
 
 #[rstest]
-fn peek_info_succeeds(repo_store: RepoStore) -> anyhow::Result<()> {
-    let repo: KeyRepo<String> = repo_store.create()?;
-    let expected_info = repo.info();
-    let actual_info = peek_info(&repo_store.store)?;
-
-    assert_that!(actual_info).is_equal_to(expected_info);
+fn remove_batch_removes_existing_keys(mut repo: KeyRepo<String>) {
+    repo.insert(String::from("first"));
+    repo.insert(String::from("second"));
+    repo.insert(String::from("third"));
 
-    Ok(())
+    assert_that!(repo.remove_batch(["first", "third", "nonexistent"])).is_equal_to(2);
+    assert_that!(repo.keys().collect::<Vec<_>>()).is_equal_to(vec![&String::from("second")]);
 }
 
-#[apply(store_config)]
-fn committed_changes_are_persisted(
-    #[case] repo_store: RepoStore,
+#[rstest]
+fn push_to_copies_objects(
+    mut repo: KeyRepo<String>,
     buffer: Vec<u8>,
+    smaller_buffer: Vec<u8>,
 ) -> anyhow::Result<()> {
-    let mut repo: KeyRepo<String> = repo_store.create()?;
-    let mut object = repo.insert(String::from("test"));
+    let mut dest: KeyRepo<String> = create_repo(encoding_config())?;
 
-    // Write some data to the repository.
-    object.write_all(&buffer)?;
+    let mut object = dest.insert(String::from("first"));
+    object.write_all(&smaller_buffer)?;
     object.commit()?;
     drop(object);
+    dest.insert(String::from("unrelated"));
 
-    repo.commit()?;
-    drop(repo);
+    let mut object = repo.insert(String::from("first"));
+    object.write_all(&buffer)?;
+    object.commit()?;
+    drop(object);
 
-    // Re-open the repository.
-    let repo: KeyRepo<String> = repo_store.open()?;
+    repo.push_to(&mut dest)?;
+    dest.commit()?;
 
-    // Read that data from the repository.
     let mut actual_data = Vec::new();
-    let mut object = repo.object("test").unwrap();
-    object.read_to_end(&mut actual_data)?;
+    dest.object("first")
+        .unwrap()
+        .read_to_end(&mut actual_data)?;
 
     assert_that!(actual_data).is_equal_to(&buffer);
+    assert_that!(dest.contains("unrelated")).is_true();
+    assert_that!(dest.verify()).is_ok_containing(HashSet::new());
 
     Ok(())
 }
 
-#[apply(store_config)]
-fn uncommitted_changes_are_not_persisted(
-    #[case] repo_store: RepoStore,
+#[rstest]
+fn pull_from_only_transfers_missing_chunks(
+    mut repo: KeyRepo<String>,
     buffer: Vec<u8>,
 ) -> anyhow::Result<()> {
-    let mut repo: KeyRepo<String> = repo_store.create()?;
-    let mut object = repo.insert(String::from("test"));
+    let mut dest: KeyRepo<String> = create_repo(RepoConfig::default())?;
 
-    // Write some data to the repository.
+    let mut object = repo.insert(String::from("first"));
     object.write_all(&buffer)?;
     object.commit()?;
     drop(object);
-    drop(repo);
 
-    // Re-open the repository.
-    let repo: KeyRepo<String> = repo_store.open()?;
+    dest.pull_from(&repo)?;
+    let actual_size = dest.stats().actual_size();
 
-    assert_that!(repo.contains("test")).is_false();
-    assert_that!(repo.object("test")).is_none();
+    repo.copy("first", String::from("second"));
+    dest.pull_from(&repo)?;
+
+    let mut actual_data = Vec::new();
+    dest.object("second")
+        .unwrap()
+        .read_to_end(&mut actual_data)?;
+
+    assert_that!(actual_data).is_equal_to(&buffer);
+    assert_that!(dest.stats().actual_size()).is_equal_to(actual_size);
+    assert_that!(dest.stats().apparent_size()).is_equal_to(2 * buffer.len() as u64);
 
     Ok(())
 }
 
 #[rstest]
-fn objects_are_removed_on_rollback(repo_object: RepoObject, buffer: Vec<u8>) -> anyhow::Result<()> {
-    let RepoObject {
-        mut repo,
-        mut object,
-        key,
-    } = repo_object;
-
-    object.write_all(&buffer)?;
+fn merge_applies_changes_and_reports_conflicts(mut repo: KeyRepo<String>) -> anyhow::Result<()> {
+    for key in ["unchanged", "changed", "removed", "conflict"] {
+        let mut object = repo.insert(String::from(key));
+        object.write_all(b"ancestor")?;
+        object.commit()?;
+    }
+
+    let mut ours: KeyRepo<String> = create_repo(RepoConfig::default())?;
+    let mut theirs: KeyRepo<String> = create_repo(RepoConfig::default())?;
+    ours.pull_from(&repo)?;
+    theirs.pull_from(&repo)?;
+
+    for (key, data) in [
+        ("changed", b"theirs"),
+        ("conflict", b"theirs"),
+        ("added", b"theirs"),
+    ] {
+        let mut object = theirs.insert(String::from(key));
+        object.write_all(data)?;
+        object.commit()?;
+    }
+    theirs.remove("removed");
+
+    let mut object = ours.insert(String::from("conflict"));
+    object.write_all(b"ours")?;
     object.commit()?;
     drop(object);
 
-    repo.rollback()?;
+    let report = ours.merge(&repo, &theirs)?;
 
-    assert_that!(repo.contains(&key)).is_false();
-    assert_that!(repo.object(&key)).is_none();
+    let mut actual_data = Vec::new();
+    ours.object("changed")
+        .unwrap()
+        .read_to_end(&mut actual_data)?;
+
+    assert_that!(actual_data).is_equal_to(b"theirs".to_vec());
+    assert_that!(ours.contains("added")).is_true();
+    assert_that!(ours.contains("removed")).is_false();
+    assert_that!(report.updated().len()).is_equal_to(2);
+    assert_that!(report.removed().contains("removed")).is_true();
+    assert_that!(report.conflicts().get("conflict")).is_equal_to(Some(&MergeConflict::BothChanged));
+    assert_that!(report.is_clean()).is_false();
+
+    assert_that!(ours.pull_object(&theirs, "conflict")).is_ok_containing(true);
+    actual_data.clear();
+    ours.object("conflict")
+        .unwrap()
+        .read_to_end(&mut actual_data)?;
+    assert_that!(actual_data).is_equal_to(b"theirs".to_vec());
 
     Ok(())
 }
 
-#[apply(object_config)]
-fn object_contents_are_modified_on_rollback(
-    #[case] repo_object: RepoObject,
+#[rstest]
+fn computed_content_id_matches_object(
+    repo_object: RepoObject,
     buffer: Vec<u8>,
 ) -> anyhow::Result<()> {
     let RepoObject {
-        mut repo,
-        mut object,
-        key,
+        repo, mut object, ..
     } = repo_object;
 
-    repo.commit()?;
-
     object.write_all(&buffer)?;
     object.commit()?;
-    drop(object);
-
-    repo.rollback()?;
-
-    let mut actual_data = Vec::new();
-    let mut object = repo.object(&key).unwrap();
-    object.read_to_end(&mut actual_data)?;
 
-    assert_that!(object.size()).is_ok_containing(0);
-    assert_that!(actual_data).is_empty();
+    assert_that!(repo.compute_content_id(buffer.as_slice())).is_ok_containing(object.content_id()?);
 
     Ok(())
 }
 
 #[rstest]
-fn rollback_before_first_commit_succeeds(mut repo: KeyRepo<String>) {
-    assert_that!(repo.rollback()).is_ok();
-}
-
-#[rstest]
-fn objects_are_removed_on_restore(mut repo: KeyRepo<String>) -> anyhow::Result<()> {
-    let savepoint = repo.savepoint()?;
+fn check_for_missing_chunks(
+    repo_object: RepoObject,
+    buffer: Vec<u8>,
+    smaller_buffer: Vec<u8>,
+) -> anyhow::Result<()> {
+    let RepoObject {
+        repo, mut object, ..
+    } = repo_object;
 
-    let mut object = repo.insert(String::from("test"));
-    object.write_all(b"test data")?;
+    object.write_all(&buffer)?;
     object.commit()?;
-    drop(object);
 
-    repo.restore(&savepoint)?;
+    let existing_content = object.content_id()?;
+    let new_content = repo.compute_content_id(smaller_buffer.as_slice())?;
 
-    assert_that!(repo.contains("test")).is_false();
-    assert_that!(repo.object("test")).is_none();
+    assert_that!(repo.contains_content(&existing_content)).is_true();
+    assert_that!(repo.missing_chunks(&existing_content)).is_empty();
+    assert_that!(existing_content
+        .chunk_ids()
+        .into_iter()
+        .all(|id| repo.contains_chunk(id)))
+    .is_true();
+
+    assert_that!(repo.contains_content(&new_content)).is_false();
+    assert_that!(repo.missing_chunks(&new_content)).is_equal_to(new_content.chunk_ids());
 
     Ok(())
 }
 
-#[apply(object_config)]
-fn object_contents_are_modified_on_restore(
-    #[case] repo_object: RepoObject,
+#[rstest]
+fn find_objects_by_content_id(
+    mut repo: KeyRepo<String>,
     buffer: Vec<u8>,
+    smaller_buffer: Vec<u8>,
 ) -> anyhow::Result<()> {
-    let RepoObject {
-        mut repo,
-        mut object,
-        key,
-    } = repo_object;
-
-    let savepoint = repo.savepoint()?;
-
+    let mut object = repo.insert(String::from("first"));
     object.write_all(&buffer)?;
     object.commit()?;
     drop(object);
 
-    assert_that!(repo.restore(&savepoint)).is_ok();
+    assert!(repo.copy("first", String::from("second")));
 
-    let mut actual_data = Vec::new();
-    let mut object = repo.object(&key).unwrap();
-    object.read_to_end(&mut actual_data)?;
+    let mut object = repo.insert(String::from("third"));
+    object.write_all(&smaller_buffer)?;
+    object.commit()?;
+    drop(object);
 
-    assert_that!(object.size()).is_ok_containing(0);
-    assert_that!(actual_data).is_empty();
+    let content_id = repo.compute_content_id(buffer.as_slice())?;
+    let expected_keys = [String::from("first"), String::from("second")];
+
+    assert_that!(repo.find_by_content_id(&content_id))
+        .is_equal_to(expected_keys.iter().collect::<HashSet<_>>());
 
     Ok(())
 }
 
 #[rstest]
-fn restore_can_redo_changes(repo_object: RepoObject, buffer: Vec<u8>) -> anyhow::Result<()> {
+fn content_id_round_trips_through_bytes(
+    repo_object: RepoObject,
+    buffer: Vec<u8>,
+) -> anyhow::Result<()> {
     let RepoObject {
-        mut repo,
-        mut object,
-        key,
+        repo, mut object, ..
     } = repo_object;
-
     object.write_all(&buffer)?;
     object.commit()?;
-    drop(object);
 
-    let before_savepoint = repo.savepoint()?;
+    let content_id = object.content_id()?;
+    let decoded = ContentId::from_bytes(&content_id.to_bytes())?;
 
-    repo.remove(&key);
+    assert_that!(decoded).is_equal_to(&content_id);
+    assert_that!(decoded.repo_id()).is_equal_to(repo.info().id());
+    assert_that!(ContentId::from_bytes(b"invalid")).is_err_variant(acid_store::Error::Deserialize);
 
-    let after_savepoint = repo.savepoint()?;
+    Ok(())
+}
+
+#[rstest]
+fn object_is_not_accessible_from_another_instance(repo_object: RepoObject) -> anyhow::Result<()> {
+    let RepoObject { repo, key, .. } = repo_object;
 
-    assert_that!(repo.restore(&before_savepoint)).is_ok();
     assert_that!(repo.contains(&key)).is_true();
     assert_that!(repo.object(&key)).is_some();
 
-    assert_that!(repo.restore(&after_savepoint)).is_ok();
+    let repo: KeyRepo<String> = repo.switch_instance(Uuid::new_v4().into())?;
+
     assert_that!(repo.contains(&key)).is_false();
     assert_that!(repo.object(&key)).is_none();
 
@@ -412,312 +598,2001 @@ fn restore_can_redo_changes(repo_object: RepoObject, buffer: Vec<u8>) -> anyhow:
 }
 
 #[rstest]
-fn committing_repo_invalidates_savepoint(mut repo: KeyRepo<String>) -> anyhow::Result<()> {
-    let before_savepoint = repo.savepoint()?;
-    repo.commit()?;
+fn committing_commits_all_instances(repo_store: RepoStore) -> anyhow::Result<()> {
+    let instance_1 = Uuid::new_v4().into();
+    let instance_2 = Uuid::new_v4().into();
 
-    assert_that!(before_savepoint.is_valid()).is_false();
-    assert_that!(repo.start_restore(&before_savepoint))
-        .is_err_variant(acid_store::Error::InvalidSavepoint);
+    let repo: KeyRepo<String> = repo_store.create()?;
 
-    let after_savepoint = repo.savepoint()?;
+    let mut repo: KeyRepo<String> = repo.switch_instance(instance_1)?;
+    repo.insert(String::from("test1"));
 
-    assert_that!(after_savepoint.is_valid()).is_true();
-    assert_that!(repo.start_restore(&after_savepoint)).is_ok();
+    let mut repo: KeyRepo<String> = repo.switch_instance(instance_2)?;
+    repo.insert(String::from("test2"));
 
-    Ok(())
-}
+    repo.commit()?;
+    drop(repo);
+    let repo: KeyRepo<String> = repo_store.open()?;
+
+    let repo: KeyRepo<String> = repo.switch_instance(instance_1)?;
+    assert_that!(repo.contains("test1")).is_true();
+
+    let repo: KeyRepo<String> = repo.switch_instance(instance_2)?;
+    assert_that!(repo.contains("test2")).is_true();
+
+    Ok(())
+}
+
+#[rstest]
+fn named_instances_can_be_opened_by_name(repo_store: RepoStore) -> anyhow::Result<()> {
+    let instance = Uuid::new_v4().into();
+
+    let repo: KeyRepo<String> = repo_store.create()?;
+    let mut repo: KeyRepo<String> = repo.switch_instance(instance)?;
+    repo.insert(String::from("test"));
+    repo.name_instance(instance, Some("backups"))?;
+    repo.commit()?;
+    drop(repo);
+
+    let repo: KeyRepo<String> = OpenOptions::new()
+        .password(repo_store.password.as_bytes())
+        .instance_name("backups")
+        .open(&repo_store.store)?;
+
+    assert_that!(repo.instance()).is_equal_to(instance);
+    assert_that!(repo.contains("test")).is_true();
+    assert_that!(repo.find_instance("backups")).is_equal_to(Some(instance));
+
+    Ok(())
+}
+
+#[rstest]
+fn opening_missing_instance_name_errs(repo_store: RepoStore) -> anyhow::Result<()> {
+    let mut repo: KeyRepo<String> = repo_store.create()?;
+    repo.commit()?;
+    drop(repo);
+
+    let result = OpenOptions::new()
+        .password(repo_store.password.as_bytes())
+        .instance_name("missing")
+        .open::<KeyRepo<String>, _>(&repo_store.store);
+
+    assert_that!(result).is_err_variant(acid_store::Error::NotFound);
+
+    Ok(())
+}
+
+#[rstest]
+fn list_instances_includes_names(repo_store: RepoStore) -> anyhow::Result<()> {
+    let instance = Uuid::new_v4().into();
+
+    let repo: KeyRepo<String> = repo_store.create()?;
+    let mut repo: KeyRepo<String> = repo.switch_instance(instance)?;
+    repo.name_instance(instance, Some("second"))?;
+
+    let instances = repo.list_instances();
+    let names = instances
+        .iter()
+        .map(|info| (info.id(), info.name()))
+        .collect::<Vec<_>>();
+
+    assert_that!(names).has_length(2);
+    assert_that!(names).contains((DEFAULT_INSTANCE, None));
+    assert_that!(names).contains((instance, Some("second")));
+    assert_that!(instances.iter().all(|info| info.created().is_some())).is_true();
+
+    Ok(())
+}
+
+#[rstest]
+fn instance_names_are_unique(repo_store: RepoStore) -> anyhow::Result<()> {
+    let instance = Uuid::new_v4().into();
+
+    let repo: KeyRepo<String> = repo_store.create()?;
+    let mut repo: KeyRepo<String> = repo.switch_instance(instance)?;
+
+    repo.name_instance(DEFAULT_INSTANCE, Some("name"))?;
+    assert_that!(repo.name_instance(instance, Some("name")))
+        .is_err_variant(acid_store::Error::AlreadyExists);
+    assert_that!(repo.name_instance(Uuid::new_v4().into(), Some("other")))
+        .is_err_variant(acid_store::Error::NotFound);
+
+    repo.name_instance(DEFAULT_INSTANCE, None)?;
+    assert_that!(repo.name_instance(instance, Some("name"))).is_ok();
+
+    Ok(())
+}
+
+#[rstest]
+fn change_password(mut repo_store: RepoStore) -> anyhow::Result<()> {
+    repo_store.config.encryption = Encryption::XChaCha20Poly1305;
+    let mut repo: KeyRepo<String> = repo_store.create()?;
+
+    repo.change_password(
+        b"New password",
+        ResourceLimit::Interactive,
+        ResourceLimit::Interactive,
+    );
+    repo.commit()?;
+    drop(repo);
+
+    repo_store.password = String::from("New password");
+
+    assert_that!(repo_store.open::<KeyRepo<String>>()).is_ok();
+
+    Ok(())
+}
+
+#[rstest]
+fn change_password_keeps_key_derivation(mut repo_store: RepoStore) -> anyhow::Result<()> {
+    let key_derivation = KeyDerivation::Pbkdf2Sha256 { iterations: 1000 };
+    repo_store.config.encryption = Encryption::XChaCha20Poly1305;
+    repo_store.config.key_derivation = Some(key_derivation);
+    let mut repo: KeyRepo<String> = repo_store.create()?;
+
+    repo.change_password(
+        b"New password",
+        ResourceLimit::Interactive,
+        ResourceLimit::Interactive,
+    );
+    repo.commit()?;
+    drop(repo);
+
+    repo_store.password = String::from("New password");
+    let repo: KeyRepo<String> = repo_store.open()?;
+
+    assert_that!(repo.info().config().key_derivation).is_equal_to(Some(key_derivation));
+
+    Ok(())
+}
+
+#[rstest]
+fn peek_info_succeeds(repo_store: RepoStore) -> anyhow::Result<()> {
+    let repo: KeyRepo<String> = repo_store.create()?;
+    let expected_info = repo.info();
+    let actual_info = peek_info(&repo_store.store)?;
+
+    assert_that!(actual_info).is_equal_to(expected_info);
+
+    Ok(())
+}
+
+#[rstest]
+fn encrypted_metadata_is_not_readable(mut repo_store: RepoStore) -> anyhow::Result<()> {
+    repo_store.config.encryption = Encryption::XChaCha20Poly1305;
+    repo_store.config.encrypt_metadata = true;
+    let mut repo: KeyRepo<String> = repo_store.create()?;
+    repo.insert(String::from("test"));
+    repo.commit()?;
+    drop(repo);
+
+    assert_that!(peek_info(&repo_store.store)).is_err_variant(acid_store::Error::Password);
+    assert_that!(export_repo(&repo_store.store)).is_err_variant(acid_store::Error::Password);
+
+    let repo: KeyRepo<String> = repo_store.open()?;
+    assert_that!(repo.contains("test")).is_true();
+    assert_that!(repo.info().config().encrypt_metadata).is_true();
+
+    Ok(())
+}
+
+#[rstest]
+fn encrypted_metadata_survives_password_change(mut repo_store: RepoStore) -> anyhow::Result<()> {
+    repo_store.config.encryption = Encryption::XChaCha20Poly1305;
+    repo_store.config.encrypt_metadata = true;
+    let mut repo: KeyRepo<String> = repo_store.create()?;
+
+    repo.change_password(
+        b"New password",
+        ResourceLimit::Interactive,
+        ResourceLimit::Interactive,
+    );
+    repo.commit()?;
+    drop(repo);
+
+    assert_that!(repo_store.open::<KeyRepo<String>>()).is_err_variant(acid_store::Error::Password);
+
+    repo_store.password = String::from("New password");
+    assert_that!(repo_store.open::<KeyRepo<String>>()).is_ok();
+
+    Ok(())
+}
+
+#[rstest]
+fn encrypting_metadata_without_encryption_errs(mut repo_store: RepoStore) {
+    repo_store.config.encrypt_metadata = true;
+    assert_that!(repo_store.create::<KeyRepo<String>>())
+        .is_err_variant(acid_store::Error::InvalidConfig);
+}
+
+#[rstest]
+fn password_callback_is_retried(mut repo_store: RepoStore) -> anyhow::Result<()> {
+    repo_store.config.encryption = Encryption::XChaCha20Poly1305;
+    drop(repo_store.create::<KeyRepo<String>>()?);
+
+    let mut attempts = Vec::new();
+    let repo = OpenOptions::new()
+        .password_callback(3, |attempt| {
+            attempts.push(attempt);
+            if attempt < 2 {
+                Some(b"wrong password".to_vec())
+            } else {
+                Some(repo_store.password.as_bytes().to_vec())
+            }
+        })
+        .open::<KeyRepo<String>, _>(&repo_store.store);
+
+    assert_that!(repo).is_ok();
+    assert_that!(attempts).is_equal_to(vec![0, 1, 2]);
+
+    Ok(())
+}
+
+#[rstest]
+fn password_callback_gives_up_after_attempts(mut repo_store: RepoStore) -> anyhow::Result<()> {
+    repo_store.config.encryption = Encryption::XChaCha20Poly1305;
+    drop(repo_store.create::<KeyRepo<String>>()?);
+
+    let mut attempts = 0;
+    let repo = OpenOptions::new()
+        .password_callback(2, |_| {
+            attempts += 1;
+            Some(b"wrong password".to_vec())
+        })
+        .open::<KeyRepo<String>, _>(&repo_store.store);
+
+    assert_that!(repo).is_err_variant(acid_store::Error::Password);
+    assert_that!(attempts).is_equal_to(2);
+
+    Ok(())
+}
+
+#[rstest]
+fn cancelling_password_callback_errs(mut repo_store: RepoStore) -> anyhow::Result<()> {
+    repo_store.config.encryption = Encryption::XChaCha20Poly1305;
+    drop(repo_store.create::<KeyRepo<String>>()?);
+
+    let repo = OpenOptions::new()
+        .password_callback(3, |_| None)
+        .open::<KeyRepo<String>, _>(&repo_store.store);
+
+    assert_that!(repo).is_err_variant(acid_store::Error::Cancelled);
+
+    Ok(())
+}
+
+#[rstest]
+fn password_callback_is_not_invoked_without_encryption(
+    repo_store: RepoStore,
+) -> anyhow::Result<()> {
+    drop(repo_store.create::<KeyRepo<String>>()?);
+
+    let repo = OpenOptions::new()
+        .password_callback(1, |_| {
+            panic!("The password callback should not be invoked.")
+        })
+        .open::<KeyRepo<String>, _>(&repo_store.store);
+
+    assert_that!(repo).is_ok();
+
+    Ok(())
+}
+
+#[rstest]
+fn fingerprint_changes_when_data_is_committed(
+    repo_store: RepoStore,
+    buffer: Vec<u8>,
+) -> anyhow::Result<()> {
+    let mut repo: KeyRepo<String> = repo_store.create()?;
+    repo.commit()?;
+    let empty_fingerprint = repo.info().fingerprint();
+
+    let mut object = repo.insert(String::from("test"));
+    object.write_all(&buffer)?;
+    object.commit()?;
+    drop(object);
+
+    assert_that!(repo.info().fingerprint()).is_equal_to(empty_fingerprint);
+
+    repo.commit()?;
+    let fingerprint = repo.info().fingerprint();
+
+    assert_that!(fingerprint).is_some();
+    assert_that!(fingerprint).is_not_equal_to(empty_fingerprint);
+    assert_that!(peek_info(&repo_store.store)?.fingerprint()).is_equal_to(fingerprint);
+
+    repo.remove("test");
+    repo.commit()?;
+
+    assert_that!(repo.info().fingerprint()).is_equal_to(empty_fingerprint);
+
+    Ok(())
+}
+
+#[rstest]
+fn commit_log_records_each_commit(mut repo_store: RepoStore) -> anyhow::Result<()> {
+    repo_store.config.encryption = Encryption::XChaCha20Poly1305;
+    repo_store.config.commit_log = true;
+    let mut repo: KeyRepo<String> = repo_store.create()?;
+
+    repo.commit()?;
+    repo.insert(String::from("test"));
+    repo.commit()?;
+    drop(repo);
+
+    let repo: KeyRepo<String> = repo_store.open()?;
+    let sequences = repo
+        .commit_log()
+        .iter()
+        .map(|record| record.sequence())
+        .collect::<Vec<_>>();
+
+    assert_that!(sequences).is_equal_to(vec![0, 1]);
+    assert_that!(repo.commit_log().last().map(|record| record.fingerprint()))
+        .is_equal_to(repo.info().fingerprint());
+    assert_that!(repo.verify_commit_log()).is_ok();
+
+    Ok(())
+}
+
+#[rstest]
+fn commit_log_is_empty_when_disabled(repo_store: RepoStore) -> anyhow::Result<()> {
+    let mut repo: KeyRepo<String> = repo_store.create()?;
+    repo.commit()?;
+
+    assert_that!(repo.commit_log().len()).is_equal_to(0);
+    assert_that!(repo.verify_commit_log()).is_ok();
+
+    Ok(())
+}
+
+#[rstest]
+fn older_commits_can_be_restored_when_retained(mut repo_store: RepoStore) -> anyhow::Result<()> {
+    repo_store.config.retained_commits = 3;
+    let mut repo: KeyRepo<String> = repo_store.create()?;
+
+    for contents in ["first", "second", "third"] {
+        let mut object = repo.insert(String::from("test"));
+        object.write_all(contents.as_bytes())?;
+        object.commit()?;
+        drop(object);
+        repo.commit()?;
+        repo.clean()?;
+    }
+
+    assert_that!(repo.previous_commits()).is_equal_to(2);
+
+    repo.restore_commit(2)?;
+    let mut object = repo.object("test").unwrap();
+    let mut actual_contents = String::new();
+    object.read_to_string(&mut actual_contents)?;
+    drop(object);
+
+    assert_that!(actual_contents.as_str()).is_equal_to("first");
+    assert_that!(repo.restore_commit(3)).is_err_variant(acid_store::Error::NotFound);
+
+    Ok(())
+}
+
+#[rstest]
+fn only_most_recent_commit_is_retained_by_default(repo_store: RepoStore) -> anyhow::Result<()> {
+    let mut repo: KeyRepo<String> = repo_store.create()?;
+    repo.commit()?;
+    repo.commit()?;
+
+    assert_that!(repo.previous_commits()).is_equal_to(0);
+    assert_that!(repo.restore_commit(1)).is_err_variant(acid_store::Error::NotFound);
+
+    Ok(())
+}
+
+#[rstest]
+fn creating_with_no_retained_commits_errs(mut repo_store: RepoStore) {
+    repo_store.config.retained_commits = 0;
+
+    assert_that!(repo_store.create::<KeyRepo<String>>())
+        .is_err_variant(acid_store::Error::InvalidConfig);
+}
+
+#[rstest]
+fn repos_with_same_data_have_same_fingerprint(buffer: Vec<u8>) -> anyhow::Result<()> {
+    let mut fingerprints = Vec::new();
+    for _ in 0..2 {
+        let mut repo: KeyRepo<String> = RepoStore::new(RepoConfig::default()).create()?;
+        let mut object = repo.insert(String::from("test"));
+        object.write_all(&buffer)?;
+        object.commit()?;
+        drop(object);
+        repo.commit()?;
+        fingerprints.push(repo.info().fingerprint());
+    }
+
+    assert_that!(fingerprints[0]).is_equal_to(fingerprints[1]);
+
+    Ok(())
+}
+
+#[rstest]
+fn exported_repo_can_be_read_back(repo_store: RepoStore) -> anyhow::Result<()> {
+    let repo: KeyRepo<String> = repo_store.create()?;
+    let expected_info = repo.info();
+    drop(repo);
+
+    let mut serialized_export = Vec::new();
+    export_repo(&repo_store.store)?.write(&mut serialized_export)?;
+    let export = RepoExport::read(serialized_export.as_slice())?;
+
+    assert_that!(export.info()).is_equal_to(expected_info);
+
+    Ok(())
+}
+
+#[rstest]
+fn attaching_exported_repo_restores_metadata(
+    repo_store: RepoStore,
+    buffer: Vec<u8>,
+) -> anyhow::Result<()> {
+    let mut repo: KeyRepo<String> = repo_store.create()?;
+    let mut object = repo.insert(String::from("test"));
+    object.write_all(&buffer)?;
+    object.commit()?;
+    drop(object);
+    repo.commit()?;
+    drop(repo);
+
+    let export = export_repo(&repo_store.store)?;
+
+    let mut store = repo_store.store.open()?;
+    for block_id in store
+        .list_blocks(BlockType::Header)
+        .map_err(anyhow::Error::msg)?
+    {
+        store
+            .remove_block(BlockKey::Header(block_id))
+            .map_err(anyhow::Error::msg)?;
+    }
+    store
+        .remove_block(BlockKey::Super)
+        .map_err(anyhow::Error::msg)?;
+    store
+        .remove_block(BlockKey::Version)
+        .map_err(anyhow::Error::msg)?;
+    drop(store);
+
+    assert_that!(repo_store.open::<KeyRepo<String>>()).is_err_variant(acid_store::Error::NotFound);
+
+    export.attach(&repo_store.store)?;
+
+    let repo: KeyRepo<String> = repo_store.open()?;
+    let mut actual_data = Vec::new();
+    repo.object("test").unwrap().read_to_end(&mut actual_data)?;
+
+    assert_that!(actual_data).is_equal_to(&buffer);
+
+    Ok(())
+}
+
+#[apply(store_config)]
+fn committed_changes_are_persisted(
+    #[case] repo_store: RepoStore,
+    buffer: Vec<u8>,
+) -> anyhow::Result<()> {
+    let mut repo: KeyRepo<String> = repo_store.create()?;
+    let mut object = repo.insert(String::from("test"));
+
+    // Write some data to the repository.
+    object.write_all(&buffer)?;
+    object.commit()?;
+    drop(object);
+
+    repo.commit()?;
+    drop(repo);
+
+    // Re-open the repository.
+    let repo: KeyRepo<String> = repo_store.open()?;
+
+    // Read that data from the repository.
+    let mut actual_data = Vec::new();
+    let mut object = repo.object("test").unwrap();
+    object.read_to_end(&mut actual_data)?;
+
+    assert_that!(actual_data).is_equal_to(&buffer);
+
+    Ok(())
+}
+
+#[apply(store_config)]
+fn uncommitted_changes_are_not_persisted(
+    #[case] repo_store: RepoStore,
+    buffer: Vec<u8>,
+) -> anyhow::Result<()> {
+    let mut repo: KeyRepo<String> = repo_store.create()?;
+    let mut object = repo.insert(String::from("test"));
+
+    // Write some data to the repository.
+    object.write_all(&buffer)?;
+    object.commit()?;
+    drop(object);
+    drop(repo);
+
+    // Re-open the repository.
+    let repo: KeyRepo<String> = repo_store.open()?;
+
+    assert_that!(repo.contains("test")).is_false();
+    assert_that!(repo.object("test")).is_none();
+
+    Ok(())
+}
+
+#[rstest]
+fn objects_are_removed_on_rollback(repo_object: RepoObject, buffer: Vec<u8>) -> anyhow::Result<()> {
+    let RepoObject {
+        mut repo,
+        mut object,
+        key,
+    } = repo_object;
+
+    object.write_all(&buffer)?;
+    object.commit()?;
+    drop(object);
+
+    repo.rollback()?;
+
+    assert_that!(repo.contains(&key)).is_false();
+    assert_that!(repo.object(&key)).is_none();
+
+    Ok(())
+}
+
+#[apply(object_config)]
+fn object_contents_are_modified_on_rollback(
+    #[case] repo_object: RepoObject,
+    buffer: Vec<u8>,
+) -> anyhow::Result<()> {
+    let RepoObject {
+        mut repo,
+        mut object,
+        key,
+    } = repo_object;
+
+    repo.commit()?;
+
+    object.write_all(&buffer)?;
+    object.commit()?;
+    drop(object);
+
+    repo.rollback()?;
+
+    let mut actual_data = Vec::new();
+    let mut object = repo.object(&key).unwrap();
+    object.read_to_end(&mut actual_data)?;
+
+    assert_that!(object.size()).is_ok_containing(0);
+    assert_that!(actual_data).is_empty();
+
+    Ok(())
+}
+
+#[rstest]
+fn rollback_before_first_commit_succeeds(mut repo: KeyRepo<String>) {
+    assert_that!(repo.rollback()).is_ok();
+}
+
+#[rstest]
+fn objects_are_removed_on_restore(mut repo: KeyRepo<String>) -> anyhow::Result<()> {
+    let savepoint = repo.savepoint()?;
+
+    let mut object = repo.insert(String::from("test"));
+    object.write_all(b"test data")?;
+    object.commit()?;
+    drop(object);
+
+    repo.restore(&savepoint)?;
+
+    assert_that!(repo.contains("test")).is_false();
+    assert_that!(repo.object("test")).is_none();
+
+    Ok(())
+}
+
+#[apply(object_config)]
+fn object_contents_are_modified_on_restore(
+    #[case] repo_object: RepoObject,
+    buffer: Vec<u8>,
+) -> anyhow::Result<()> {
+    let RepoObject {
+        mut repo,
+        mut object,
+        key,
+    } = repo_object;
+
+    let savepoint = repo.savepoint()?;
+
+    object.write_all(&buffer)?;
+    object.commit()?;
+    drop(object);
+
+    assert_that!(repo.restore(&savepoint)).is_ok();
+
+    let mut actual_data = Vec::new();
+    let mut object = repo.object(&key).unwrap();
+    object.read_to_end(&mut actual_data)?;
+
+    assert_that!(object.size()).is_ok_containing(0);
+    assert_that!(actual_data).is_empty();
+
+    Ok(())
+}
+
+#[rstest]
+fn restore_can_redo_changes(repo_object: RepoObject, buffer: Vec<u8>) -> anyhow::Result<()> {
+    let RepoObject {
+        mut repo,
+        mut object,
+        key,
+    } = repo_object;
+
+    object.write_all(&buffer)?;
+    object.commit()?;
+    drop(object);
+
+    let before_savepoint = repo.savepoint()?;
+
+    repo.remove(&key);
+
+    let after_savepoint = repo.savepoint()?;
+
+    assert_that!(repo.restore(&before_savepoint)).is_ok();
+    assert_that!(repo.contains(&key)).is_true();
+    assert_that!(repo.object(&key)).is_some();
+
+    assert_that!(repo.restore(&after_savepoint)).is_ok();
+    assert_that!(repo.contains(&key)).is_false();
+    assert_that!(repo.object(&key)).is_none();
+
+    Ok(())
+}
+
+#[rstest]
+fn committing_repo_invalidates_savepoint(mut repo: KeyRepo<String>) -> anyhow::Result<()> {
+    let before_savepoint = repo.savepoint()?;
+    repo.commit()?;
+
+    assert_that!(before_savepoint.is_valid()).is_false();
+    assert_that!(repo.start_restore(&before_savepoint))
+        .is_err_variant(acid_store::Error::InvalidSavepoint);
+
+    let after_savepoint = repo.savepoint()?;
+
+    assert_that!(after_savepoint.is_valid()).is_true();
+    assert_that!(repo.start_restore(&after_savepoint)).is_ok();
+
+    Ok(())
+}
+
+#[rstest]
+fn dropping_repo_invalidates_savepoint(mut repo: KeyRepo<String>) -> anyhow::Result<()> {
+    let savepoint = repo.savepoint()?;
+    drop(repo);
+
+    assert_that!(savepoint.is_valid()).is_false();
+
+    Ok(())
+}
+
+#[rstest]
+fn savepoint_must_be_associated_with_repo(
+    #[from(repo)] mut first_repo: KeyRepo<String>,
+    #[from(repo)] mut second_repo: KeyRepo<String>,
+) -> anyhow::Result<()> {
+    let savepoint = first_repo.savepoint()?;
+
+    assert_that!(savepoint.is_valid()).is_true();
+    assert_that!(second_repo.start_restore(&savepoint))
+        .is_err_variant(acid_store::Error::InvalidSavepoint);
+
+    Ok(())
+}
+
+#[apply(store_config)]
+fn unused_data_is_reclaimed_on_clean(
+    #[case] repo_store: RepoStore,
+    buffer: Vec<u8>,
+) -> anyhow::Result<()> {
+    let mut repo: KeyRepo<String> = repo_store.create()?;
+    let mut object = repo.insert(String::from("test"));
+
+    object.write_all(&buffer)?;
+    object.commit()?;
+    drop(object);
+    repo.commit()?;
+    drop(repo);
+
+    let mut store = repo_store.store.open()?;
+    let original_blocks = store
+        .list_blocks(BlockType::Data)
+        .map_err(anyhow::Error::msg)?
+        .len();
+    drop(store);
+
+    let mut repo: KeyRepo<String> = repo_store.open()?;
+    repo.remove("test");
+    repo.commit()?;
+    repo.clean()?;
+    drop(repo);
+
+    let mut store = repo_store.store.open()?;
+    let new_blocks = store
+        .list_blocks(BlockType::Data)
+        .map_err(anyhow::Error::msg)?
+        .len();
+
+    assert_that!(new_blocks).is_less_than(original_blocks);
+
+    Ok(())
+}
+
+#[rstest]
+fn removed_instance_data_is_reclaimed_on_clean(
+    repo_store: RepoStore,
+    buffer: Vec<u8>,
+) -> anyhow::Result<()> {
+    let instance = Uuid::new_v4().into();
+
+    let mut repo: KeyRepo<String> = repo_store.create()?;
+    repo.commit()?;
+    drop(repo);
+
+    let mut store = repo_store.store.open()?;
+    let empty_blocks = store
+        .list_blocks(BlockType::Data)
+        .map_err(anyhow::Error::msg)?
+        .len();
+    drop(store);
+
+    let repo: KeyRepo<String> = repo_store.open()?;
+    let mut repo: KeyRepo<String> = repo.switch_instance(instance)?;
+    let mut object = repo.insert(String::from("test"));
+    object.write_all(&buffer)?;
+    object.commit()?;
+    drop(object);
+    let mut repo: KeyRepo<String> = repo.switch_instance(DEFAULT_INSTANCE)?;
+    repo.commit()?;
+
+    assert_that!(repo.remove_instance(DEFAULT_INSTANCE)).is_err_variant(acid_store::Error::Locked);
+    assert_that!(repo.remove_instance(instance)?).is_true();
+    assert_that!(repo.remove_instance(instance)?).is_false();
+    assert_that!(repo.list_instances()).has_length(1);
+
+    repo.commit()?;
+    repo.clean()?;
+    drop(repo);
+
+    let mut store = repo_store.store.open()?;
+    let new_blocks = store
+        .list_blocks(BlockType::Data)
+        .map_err(anyhow::Error::msg)?
+        .len();
+
+    assert_that!(new_blocks).is_equal_to(empty_blocks);
+
+    Ok(())
+}
+
+#[apply(object_config)]
+fn clean_before_commit_does_not_prevent_rollback(
+    #[case] repo_object: RepoObject,
+    buffer: Vec<u8>,
+) -> anyhow::Result<()> {
+    let RepoObject {
+        mut repo,
+        mut object,
+        key,
+    } = repo_object;
+
+    // Write to an object and commit.
+    object.write_all(&buffer)?;
+    object.commit()?;
+    drop(object);
+    repo.commit()?;
+
+    // Delete that object, clean without committing first, and then roll back.
+    repo.remove(&key);
+    repo.clean()?;
+
+    assert_that!(repo.rollback()).is_ok();
+
+    // Check if the object still exists.
+    assert_that!(repo.contains(&key)).is_true();
+
+    // Check if the object's data was cleaned up.
+    let mut actual_data = Vec::new();
+    let mut object = repo.object(&key).unwrap();
+    object.read_to_end(&mut actual_data)?;
+
+    assert_that!(actual_data).is_equal_to(&buffer);
+
+    Ok(())
+}
+
+#[rstest]
+fn objects_can_be_read_after_clean_and_reopen(buffer: Vec<u8>) -> anyhow::Result<()> {
+    // Cleaning a packed repository rewrites the header, which must only be encoded once.
+    let mut config = encoding_config();
+    config.packing = acid_store::repo::Packing::Fixed(300);
+    let repo_store = RepoStore::new(config);
+
+    let mut repo: KeyRepo<String> = repo_store.create()?;
+    for key in ["remove", "keep"] {
+        let mut object = repo.insert(String::from(key));
+        object.write_all(&buffer)?;
+        object.commit()?;
+    }
+    repo.commit()?;
+    repo.remove("remove");
+    repo.commit()?;
+    repo.clean()?;
+    drop(repo);
+
+    let repo: KeyRepo<String> = repo_store.open()?;
+    let mut actual_data = Vec::new();
+    repo.object("keep").unwrap().read_to_end(&mut actual_data)?;
+
+    assert_that!(actual_data).is_equal_to(&buffer);
+
+    Ok(())
+}
+
+#[rstest]
+fn clear_instance_deletes_objects(repo_object: RepoObject) -> anyhow::Result<()> {
+    let RepoObject {
+        mut repo,
+        mut object,
+        key,
+    } = repo_object;
+
+    object.write_all(b"test data")?;
+    object.commit()?;
+    drop(object);
+
+    repo.clear_instance();
+
+    assert_that!(repo.contains(&key)).is_false();
+    assert_that!(repo.object("test")).is_none();
+
+    Ok(())
+}
+
+#[rstest]
+fn rollback_after_clear_instance(repo_object: RepoObject) -> anyhow::Result<()> {
+    let RepoObject {
+        mut repo,
+        mut object,
+        key,
+    } = repo_object;
+
+    object.write_all(b"test data")?;
+    object.commit()?;
+    drop(object);
+
+    repo.commit()?;
+    repo.clear_instance();
+    repo.rollback()?;
+
+    assert_that!(repo.contains(&key)).is_true();
+    assert_that!(repo.object(&key)).is_some();
+
+    Ok(())
+}
+
+#[apply(object_config)]
+fn verify_valid_repository_is_valid(
+    #[case] repo_object: RepoObject,
+    buffer: Vec<u8>,
+) -> anyhow::Result<()> {
+    let RepoObject {
+        repo, mut object, ..
+    } = repo_object;
+
+    object.write_all(&buffer)?;
+    object.commit()?;
+    drop(object);
+
+    assert_that!(repo.verify()).is_ok_containing(HashSet::new());
+
+    Ok(())
+}
+
+#[rstest]
+fn verify_with_multiple_threads_finds_corrupt_packs(
+    mut repo_store: RepoStore,
+    buffer: Vec<u8>,
+) -> anyhow::Result<()> {
+    repo_store.config.packing = Packing::Fixed(4096);
+    repo_store.config.verify_threads = 4;
+
+    let mut repo: KeyRepo<String> = repo_store.create()?;
+    for index in 0..8u8 {
+        // Shift the data so that the objects don't share any chunks.
+        let mut object = repo.insert(index.to_string());
+        object.write_all(&[index])?;
+        object.write_all(&buffer)?;
+        object.commit()?;
+    }
+    repo.commit()?;
+
+    assert_that!(repo.verify()).is_ok_containing(HashSet::new());
+
+    // Corrupt the start of each pack out from under the open repository.
+    let mut store = repo_store.store.open()?;
+    for block_id in store
+        .list_blocks(BlockType::Data)
+        .map_err(anyhow::Error::msg)?
+    {
+        let mut pack = store
+            .read_block(BlockKey::Data(block_id))
+            .map_err(anyhow::Error::msg)?
+            .unwrap();
+        pack[0] ^= 0xff;
+        store
+            .write_block(BlockKey::Data(block_id), &pack)
+            .map_err(anyhow::Error::msg)?;
+    }
+    drop(store);
+
+    let corrupt_keys = repo.verify()?.into_iter().cloned().collect::<HashSet<_>>();
+    assert_that!(corrupt_keys.is_empty()).is_false();
+
+    // Each object should be reported as corrupt exactly when verifying it on its own fails.
+    for index in 0..8u8 {
+        let key = index.to_string();
+        let is_valid = repo.object(&key).unwrap().verify()?;
+        assert_that!(corrupt_keys.contains(&key)).is_equal_to(!is_valid);
+    }
+
+    Ok(())
+}
+
+#[apply(object_config)]
+fn check_consistent_repository_is_consistent(
+    #[case] repo_object: RepoObject,
+    buffer: Vec<u8>,
+) -> anyhow::Result<()> {
+    let RepoObject {
+        mut repo,
+        mut object,
+        ..
+    } = repo_object;
+
+    object.write_all(&buffer)?;
+    object.commit()?;
+    drop(object);
+    repo.commit()?;
+
+    let report = repo.check()?;
+    assert_that!(report.is_consistent()).is_true();
+    assert_that!(report.is_repaired()).is_false();
+
+    let report = repo.repair()?;
+    assert_that!(report.is_consistent()).is_true();
+    assert_that!(report.is_repaired()).is_true();
+    assert_that!(repo.verify()).is_ok_containing(HashSet::new());
+
+    Ok(())
+}
+
+#[rstest]
+fn check_finds_missing_data(repo_store: RepoStore, buffer: Vec<u8>) -> anyhow::Result<()> {
+    let instance = Uuid::new_v4().into();
+
+    let repo: KeyRepo<String> = repo_store.create()?;
+    let mut repo: KeyRepo<String> = repo.switch_instance(instance)?;
+    let mut object = repo.insert(String::from("other"));
+    object.write_all(&buffer)?;
+    object.commit()?;
+    drop(object);
+
+    let mut repo: KeyRepo<String> = repo.switch_instance(DEFAULT_INSTANCE)?;
+    let mut object = repo.insert(String::from("test"));
+    object.write_all(&buffer)?;
+    object.commit()?;
+    drop(object);
+    repo.insert(String::from("empty"));
+    repo.commit()?;
+
+    // Remove the data out from under the open repository.
+    let mut store = repo_store.store.open()?;
+    for block_id in store
+        .list_blocks(BlockType::Data)
+        .map_err(anyhow::Error::msg)?
+    {
+        store
+            .remove_block(BlockKey::Data(block_id))
+            .map_err(anyhow::Error::msg)?;
+    }
+    drop(store);
+
+    let report = repo.check()?;
+
+    assert_that!(report.is_consistent()).is_false();
+    assert_that!(report.is_intact()).is_false();
+    assert_that!(report.missing_blocks()).is_greater_than(0);
+    assert_that!(report.damaged_objects().contains("test")).is_true();
+    assert_that!(report.damaged_objects().contains("empty")).is_false();
+    assert_that!(report.damaged_instances().contains(&instance)).is_true();
+
+    // Damaged objects can still be removed.
+    assert_that!(repo.remove("test")).is_true();
+    assert_that!(repo.check()?.damaged_objects().is_empty()).is_true();
+
+    Ok(())
+}
+
+#[rstest]
+fn set_chunking_is_persisted(repo_store: RepoStore, buffer: Vec<u8>) -> anyhow::Result<()> {
+    let chunking = Chunking::Fixed { size: 1024 };
+
+    let mut repo: KeyRepo<String> = repo_store.create()?;
+    repo.set_chunking(chunking.clone())?;
+
+    let mut object = repo.insert(String::from("test"));
+    object.write_all(&buffer)?;
+    object.commit()?;
+    let expected_chunks = (buffer.len() as u64 + 1023) / 1024;
+    assert_that!(object.stats()?.chunks()).is_equal_to(expected_chunks);
+    drop(object);
+    drop(repo);
+
+    let repo: KeyRepo<String> = repo_store.open()?;
+    assert_that!(repo.info().config().chunking).is_equal_to(&chunking);
+
+    Ok(())
+}
+
+#[rstest]
+fn rechunk_all_preserves_contents(repo_store: RepoStore, buffer: Vec<u8>) -> anyhow::Result<()> {
+    let mut repo: KeyRepo<String> = repo_store.create()?;
+    let mut object = repo.insert(String::from("test"));
+    object.write_all(&buffer)?;
+    object.commit()?;
+    object.set_len(buffer.len() as u64 + 100)?;
+    let original_id = object.object_id()?;
+    let original_stats = object.stats()?;
+    drop(object);
+    repo.commit()?;
+
+    repo.set_chunking(Chunking::Fixed { size: 1024 })?;
+    repo.rechunk_all()?;
+
+    let mut object = repo.object("test").unwrap();
+    let stats = object.stats()?;
+    let mut actual_data = Vec::new();
+    object.read_to_end(&mut actual_data)?;
+    drop(object);
+
+    let mut expected_data = buffer.clone();
+    expected_data.resize(buffer.len() + 100, 0);
+
+    assert_that!(actual_data).is_equal_to(&expected_data);
+    assert_that!(stats.chunks()).is_greater_than(original_stats.chunks());
+    assert_that!(stats.holes()).is_equal_to(original_stats.holes());
+    assert_that!(repo.object("test").unwrap().object_id()?).is_equal_to(original_id);
+    assert_that!(repo.check()?.is_consistent()).is_true();
+
+    repo.commit()?;
+    repo.clean()?;
+    assert_that!(repo.verify()).is_ok_containing(HashSet::new());
+
+    Ok(())
+}
+
+#[rstest]
+fn rechunk_missing_object_returns_false(mut repo: KeyRepo<String>) -> anyhow::Result<()> {
+    assert_that!(repo.rechunk("test")?).is_false();
+    Ok(())
+}
+
+#[rstest]
+fn expired_objects_are_removed(mut repo: KeyRepo<String>) {
+    repo.insert_with_ttl(String::from("expired"), Duration::ZERO);
+    repo.insert_with_ttl(String::from("unexpired"), Duration::from_secs(60 * 60));
+    repo.insert(String::from("permanent"));
+
+    assert_that!(repo.expiry("expired")).is_some();
+    assert_that!(repo.expiry("permanent")).is_none();
+    assert_that!(repo.expire()).is_equal_to(1);
+    assert_that!(repo.contains("expired")).is_false();
+    assert_that!(repo.contains("unexpired")).is_true();
+    assert_that!(repo.contains("permanent")).is_true();
+    assert_that!(repo.expire()).is_equal_to(0);
+}
+
+#[rstest]
+fn set_expiry_changes_expiration(mut repo: KeyRepo<String>) {
+    let past = SystemTime::now() - Duration::from_secs(1);
+
+    repo.insert(String::from("test"));
+    assert_that!(repo.set_expiry("test", Some(past))).is_true();
+    assert_that!(repo.set_expiry("nonexistent", Some(past))).is_false();
+    assert_that!(repo.expiry("test")).is_equal_to(Some(past));
+
+    assert_that!(repo.set_expiry("test", None)).is_true();
+    assert_that!(repo.expire()).is_equal_to(0);
+    assert_that!(repo.contains("test")).is_true();
+}
+
+#[rstest]
+fn expiry_follows_renamed_objects(mut repo: KeyRepo<String>) -> anyhow::Result<()> {
+    repo.insert_with_ttl(String::from("source"), Duration::ZERO);
+    repo.copy("source", String::from("copy"));
+    repo.rename("source", String::from("dest"))?;
+
+    assert_that!(repo.expiry("dest")).is_some();
+    assert_that!(repo.expiry("copy")).is_none();
+
+    // Replacing an object clears its expiration time.
+    repo.insert(String::from("dest"));
+    assert_that!(repo.expiry("dest")).is_none();
+
+    Ok(())
+}
+
+#[rstest]
+fn set_and_remove_attributes(mut repo: KeyRepo<String>) {
+    assert_that!(repo.set_attr("nonexistent", "mime", "text/plain")).is_false();
+
+    repo.insert(String::from("test"));
+    assert_that!(repo.set_attr("test", "mime", "text/plain")).is_true();
+    assert_that!(repo.set_attr("test", "origin", "/tmp/test.txt")).is_true();
+
+    assert_that!(repo.get_attr("test", "mime")).is_equal_to(Some(b"text/plain".to_vec()));
+    assert_that!(repo.attrs("test").unwrap().len()).is_equal_to(2);
+    assert_that!(repo.remove_attr("test", "mime")).is_equal_to(Some(b"text/plain".to_vec()));
+    assert_that!(repo.get_attr("test", "mime")).is_none();
+    assert_that!(repo.remove_attr("test", "mime")).is_none();
+}
+
+#[rstest]
+fn attributes_follow_copied_and_renamed_objects(mut repo: KeyRepo<String>) -> anyhow::Result<()> {
+    repo.insert(String::from("source"));
+    repo.set_attr("source", "mime", "text/plain");
+    repo.copy("source", String::from("copy"));
+    repo.rename("source", String::from("dest"))?;
+
+    assert_that!(repo.get_attr("copy", "mime")).is_equal_to(Some(b"text/plain".to_vec()));
+    assert_that!(repo.get_attr("dest", "mime")).is_equal_to(Some(b"text/plain".to_vec()));
+
+    // Replacing an object clears its attributes.
+    repo.insert(String::from("dest"));
+    assert_that!(repo.attrs("dest")).is_equal_to(Some(BTreeMap::new()));
+
+    Ok(())
+}
+
+#[rstest]
+fn attributes_are_persisted(repo_store: RepoStore) -> anyhow::Result<()> {
+    let mut repo: KeyRepo<String> = repo_store.create()?;
+    repo.insert(String::from("test"));
+    repo.set_attr("test", "mime", "text/plain");
+    repo.commit()?;
+    drop(repo);
+
+    let repo: KeyRepo<String> = repo_store.open()?;
+    assert_that!(repo.get_attr("test", "mime")).is_equal_to(Some(b"text/plain".to_vec()));
+
+    Ok(())
+}
+
+#[rstest]
+fn expiry_is_persisted(repo_store: RepoStore) -> anyhow::Result<()> {
+    let mut repo: KeyRepo<String> = repo_store.create()?;
+    repo.insert_with_ttl(String::from("test"), Duration::ZERO);
+    repo.commit()?;
+    drop(repo);
+
+    let mut repo: KeyRepo<String> = repo_store.open()?;
+    assert_that!(repo.expiry("test")).is_some();
+    assert_that!(repo.expire()).is_equal_to(1);
+
+    Ok(())
+}
+
+#[rstest]
+fn actual_and_apparent_size_are_correct(
+    repo_object: RepoObject,
+    buffer: Vec<u8>,
+) -> anyhow::Result<()> {
+    let RepoObject {
+        mut object,
+        mut repo,
+        ..
+    } = repo_object;
+
+    let hole_size = 100;
+
+    object.write_all(&buffer)?;
+    object.commit()?;
+    drop(object);
+
+    let mut object = repo.insert(String::from("test"));
+    object.write_all(&buffer)?;
+    object.commit()?;
+    object.set_len(buffer.len() as u64 + hole_size)?;
+    drop(object);
+
+    let stats = repo.stats();
+
+    assert_that!(stats.apparent_size()).is_equal_to((buffer.len() as u64 * 2) + hole_size);
+    assert_that!(stats.actual_size()).is_equal_to(buffer.len() as u64);
+
+    Ok(())
+}
 
 #[rstest]
-fn dropping_repo_invalidates_savepoint(mut repo: KeyRepo<String>) -> anyhow::Result<()> {
-    let savepoint = repo.savepoint()?;
+fn actual_and_apparent_size_are_for_current_instance(
+    repo_object: RepoObject,
+    #[from(buffer)] current_buffer: Vec<u8>,
+    #[from(buffer)] other_buffer: Vec<u8>,
+) -> anyhow::Result<()> {
+    let RepoObject {
+        mut object, repo, ..
+    } = repo_object;
+
+    let instance_id = Uuid::new_v4().into();
+
+    object.write_all(&other_buffer)?;
+    object.commit()?;
+    drop(object);
+
+    let mut repo: KeyRepo<String> = repo.switch_instance(instance_id)?;
+
+    let mut object = repo.insert(String::from("test"));
+    object.write_all(&current_buffer)?;
+    object.commit()?;
+    drop(object);
+
+    let stats = repo.stats();
+
+    assert_that!(stats.apparent_size()).is_equal_to(current_buffer.len() as u64);
+    assert_that!(stats.actual_size()).is_equal_to(current_buffer.len() as u64);
+
+    Ok(())
+}
+
+#[rstest]
+fn instance_stats_are_reported_for_each_instance(
+    repo_object: RepoObject,
+    #[from(buffer)] current_buffer: Vec<u8>,
+    #[from(buffer)] other_buffer: Vec<u8>,
+) -> anyhow::Result<()> {
+    let RepoObject {
+        mut object, repo, ..
+    } = repo_object;
+
+    let instance_id = Uuid::new_v4().into();
+
+    object.write_all(&other_buffer)?;
+    object.commit()?;
+    drop(object);
+
+    let mut repo: KeyRepo<String> = repo.switch_instance(instance_id)?;
+
+    let mut object = repo.insert(String::from("test"));
+    object.write_all(&current_buffer)?;
+    object.write_all(&current_buffer)?;
+    object.commit()?;
+    drop(object);
+
+    let stats = repo.instance_stats()?;
+
+    assert_that!(stats).has_length(2);
+    assert_that!(stats[&DEFAULT_INSTANCE].objects()).is_equal_to(1);
+    assert_that!(stats[&DEFAULT_INSTANCE].apparent_size()).is_equal_to(other_buffer.len() as u64);
+    assert_that!(stats[&instance_id].objects()).is_equal_to(1);
+    assert_that!(stats[&instance_id].apparent_size()).is_equal_to(2 * current_buffer.len() as u64);
+    assert_that!(stats[&instance_id].actual_size()).is_equal_to(repo.stats().actual_size());
+
+    Ok(())
+}
+
+#[rstest]
+fn stats_for_keys_only_counts_matching_objects(
+    mut repo: KeyRepo<String>,
+    #[from(buffer)] first_buffer: Vec<u8>,
+    #[from(buffer)] second_buffer: Vec<u8>,
+) -> anyhow::Result<()> {
+    for (key, data) in [("tenant1/a", &first_buffer), ("tenant2/a", &second_buffer)] {
+        let mut object = repo.insert(String::from(key));
+        object.write_all(data)?;
+        object.commit()?;
+    }
+
+    let stats = repo.stats_for_keys(|key| key.starts_with("tenant1/"));
+
+    assert_that!(stats.objects()).is_equal_to(1);
+    assert_that!(stats.apparent_size()).is_equal_to(first_buffer.len() as u64);
+    assert_that!(stats.actual_size()).is_equal_to(first_buffer.len() as u64);
+    assert_that!(repo.stats_for_keys(|_| false).objects()).is_equal_to(0);
+
+    Ok(())
+}
+
+#[rstest]
+fn repo_size_is_correct(
+    repo_object: RepoObject,
+    #[from(buffer)] first_buffer: Vec<u8>,
+    #[from(buffer)] second_buffer: Vec<u8>,
+) -> anyhow::Result<()> {
+    let RepoObject {
+        mut object, repo, ..
+    } = repo_object;
+
+    let instance_id = Uuid::new_v4().into();
+
+    object.write_all(&first_buffer)?;
+    object.commit()?;
+    drop(object);
+
+    let mut repo: KeyRepo<String> = repo.switch_instance(instance_id)?;
+
+    let mut object = repo.insert(String::from("test1"));
+    object.write_all(&first_buffer)?;
+    object.commit()?;
+    drop(object);
+
+    let mut object = repo.insert(String::from("test2"));
+    object.write_all(&second_buffer)?;
+    object.commit()?;
+    drop(object);
+
+    let stats = repo.stats();
+
+    assert_that!(stats.repo_size())
+        .is_equal_to(first_buffer.len() as u64 + second_buffer.len() as u64);
+
+    Ok(())
+}
+
+#[rstest]
+fn header_size_is_updated_on_commit(repo_store: RepoStore, buffer: Vec<u8>) -> anyhow::Result<()> {
+    let mut repo: KeyRepo<String> = repo_store.create()?;
+    let initial_stats = repo.stats();
+
+    assert_that!(initial_stats.header_size()).is_greater_than(0);
+    assert_that!(initial_stats.apparent_header_size()).is_greater_than(0);
+
+    let mut object = repo.insert(String::from("test"));
+    object.write_all(&buffer)?;
+    object.commit()?;
+    drop(object);
+
+    // The header is only rewritten when changes are committed.
+    assert_that!(repo.stats().apparent_header_size())
+        .is_equal_to(initial_stats.apparent_header_size());
+
+    repo.commit()?;
     drop(repo);
 
-    assert_that!(savepoint.is_valid()).is_false();
+    let repo: KeyRepo<String> = repo_store.open()?;
+    assert_that!(repo.stats().apparent_header_size())
+        .is_greater_than(initial_stats.apparent_header_size());
 
     Ok(())
 }
 
 #[rstest]
-fn savepoint_must_be_associated_with_repo(
-    #[from(repo)] mut first_repo: KeyRepo<String>,
-    #[from(repo)] mut second_repo: KeyRepo<String>,
+fn unlock_repo(repo_store: RepoStore) -> anyhow::Result<()> {
+    let repo: KeyRepo<String> = repo_store.create()?;
+    repo.unlock()?;
+    assert_that!(repo_store.open::<KeyRepo<String>>()).is_ok();
+    Ok(())
+}
+
+#[rstest]
+fn check_repo_is_locked(repo_store: RepoStore) -> anyhow::Result<()> {
+    let repo: KeyRepo<String> = repo_store.create()?;
+    assert_that!(repo.is_locked()).is_ok_containing(true);
+    repo.unlock()?;
+    assert_that!(repo.is_locked()).is_ok_containing(false);
+    Ok(())
+}
+
+#[rstest]
+fn get_lock_context(mut repo_store: RepoStore) -> anyhow::Result<()> {
+    repo_store.context = b"lock context value".to_vec();
+    let repo: KeyRepo<String> = repo_store.create()?;
+    assert_that!(repo.context()).is_ok_containing(&repo_store.context);
+    Ok(())
+}
+
+#[rstest]
+fn update_lock_context(mut repo_store: RepoStore) -> anyhow::Result<()> {
+    repo_store.context = b"initial context".to_vec();
+    let repo: KeyRepo<String> = repo_store.create()?;
+    repo.update_context(b"updated context")?;
+    repo_store.handler = Box::new(|context| {
+        assert_that!(context).is_equal_to(&b"updated context"[..]);
+        true
+    });
+    assert_that!(repo_store.open::<KeyRepo<String>>()).is_ok();
+    Ok(())
+}
+
+#[rstest]
+fn lock_info_is_stored_in_context(repo_store: RepoStore) -> anyhow::Result<()> {
+    let repo: KeyRepo<String> = OpenOptions::new()
+        .config(repo_store.config.clone())
+        .password(repo_store.password.as_bytes())
+        .lock_description("test client")
+        .mode(OpenMode::CreateNew)
+        .open(&repo_store.store)?;
+
+    let info = repo.lock_info()?.unwrap();
+
+    assert_that!(info.description()).is_equal_to("test client");
+    assert_that!(info.pid()).is_equal_to(std::process::id());
+    assert_that!(info.hostname()).is_equal_to(LockInfo::new("").hostname());
+
+    Ok(())
+}
+
+#[rstest]
+fn lock_handler_receives_lock_info(mut repo_store: RepoStore) -> anyhow::Result<()> {
+    let _repo: KeyRepo<String> = OpenOptions::new()
+        .config(repo_store.config.clone())
+        .password(repo_store.password.as_bytes())
+        .lock_description("test client")
+        .mode(OpenMode::CreateNew)
+        .open(&repo_store.store)?;
+
+    repo_store.handler = Box::new(|context| {
+        let info = LockInfo::from_context(context).unwrap();
+        info.description() != "test client"
+    });
+
+    assert_that!(repo_store.open::<KeyRepo<String>>()).is_err_variant(acid_store::Error::Locked);
+
+    Ok(())
+}
+
+#[rstest]
+fn raw_lock_context_has_no_lock_info(mut repo_store: RepoStore) -> anyhow::Result<()> {
+    repo_store.context = b"lock context value".to_vec();
+    let repo: KeyRepo<String> = repo_store.create()?;
+    assert_that!(repo.lock_info()).is_ok_containing(None);
+    Ok(())
+}
+
+#[rstest]
+fn unexpired_lease_is_respected(repo_store: RepoStore) -> anyhow::Result<()> {
+    let _repo: KeyRepo<String> = OpenOptions::new()
+        .config(repo_store.config.clone())
+        .password(repo_store.password.as_bytes())
+        .lease(Duration::from_millis(300))
+        .mode(OpenMode::CreateNew)
+        .open(&repo_store.store)?;
+
+    // The lease is refreshed while the repository is open, so it should not expire.
+    thread::sleep(Duration::from_secs(1));
+
+    assert_that!(repo_store.open::<KeyRepo<String>>()).is_err_variant(acid_store::Error::Locked);
+    Ok(())
+}
+
+#[rstest]
+fn expired_lease_is_removed(repo_store: RepoStore) -> anyhow::Result<()> {
+    let repo: KeyRepo<String> = OpenOptions::new()
+        .config(repo_store.config.clone())
+        .password(repo_store.password.as_bytes())
+        .lease(Duration::from_millis(100))
+        .mode(OpenMode::CreateNew)
+        .open(&repo_store.store)?;
+
+    // Restore the lock after the repository releases it to simulate a client which crashed.
+    let mut store = repo_store.store.open()?;
+    let lock_id = store
+        .list_blocks(BlockType::Lock)
+        .map_err(anyhow::Error::msg)?[0];
+    let lock = store
+        .read_block(BlockKey::Lock(lock_id))
+        .map_err(anyhow::Error::msg)?
+        .unwrap();
+    drop(repo);
+    store
+        .write_block(BlockKey::Lock(lock_id), &lock)
+        .map_err(anyhow::Error::msg)?;
+
+    thread::sleep(Duration::from_millis(300));
+
+    // The lock handler always respects existing locks, so this only succeeds if the lock expired.
+    assert_that!(repo_store.open::<KeyRepo<String>>()).is_ok();
+    Ok(())
+}
+
+#[rstest]
+fn update_context_preserves_lease(repo_store: RepoStore) -> anyhow::Result<()> {
+    let repo: KeyRepo<String> = OpenOptions::new()
+        .config(repo_store.config.clone())
+        .password(repo_store.password.as_bytes())
+        .locking(b"initial context", |_| false)
+        .lease(Duration::from_secs(60))
+        .mode(OpenMode::CreateNew)
+        .open(&repo_store.store)?;
+
+    assert_that!(repo.context()).is_ok_containing(b"initial context".to_vec());
+    repo.update_context(b"updated context")?;
+    assert_that!(repo.context()).is_ok_containing(b"updated context".to_vec());
+    assert_that!(repo_store.open::<KeyRepo<String>>()).is_err_variant(acid_store::Error::Locked);
+
+    Ok(())
+}
+
+#[rstest]
+fn insert_from_multiple_threads(repo_store: RepoStore, buffer: Vec<u8>) -> anyhow::Result<()> {
+    let repo: KeyRepo<String> = repo_store.create()?;
+    let mut shared = repo.into_shared();
+
+    let threads = (0..8)
+        .map(|thread_index| {
+            let shared = shared.clone();
+            let buffer = buffer.clone();
+            std::thread::spawn(move || -> acid_store::Result<()> {
+                for object_index in 0..8 {
+                    let mut object = shared.insert(format!("{}-{}", thread_index, object_index));
+                    object.write_all(&buffer)?;
+                    object.commit()?;
+                }
+                Ok(())
+            })
+        })
+        .collect::<Vec<_>>();
+    for thread in threads {
+        thread.join().unwrap()?;
+    }
+
+    shared.commit()?;
+    assert_that!(shared.keys()).has_length(64);
+
+    let repo = shared.try_unwrap().unwrap();
+    drop(repo);
+
+    let repo: KeyRepo<String> = repo_store.open()?;
+    let mut actual_data = Vec::new();
+    repo.object("7-7").unwrap().read_to_end(&mut actual_data)?;
+    assert_that!(repo.keys().len()).is_equal_to(64);
+    assert_that!(actual_data).is_equal_to(buffer);
+
+    Ok(())
+}
+
+#[rstest]
+fn unwrapping_shared_repo_with_clones_errs(repo: KeyRepo<String>) {
+    let shared = repo.into_shared();
+    let clone = shared.clone();
+    let shared = shared.try_unwrap().unwrap_err();
+    drop(clone);
+    assert_that!(shared.try_unwrap()).is_ok();
+}
+
+#[apply(store_config)]
+fn repacking_preserves_data(#[case] repo_store: RepoStore, buffer: Vec<u8>) -> anyhow::Result<()> {
+    let mut repo: KeyRepo<String> = repo_store.create()?;
+    let mut object = repo.insert(String::from("test"));
+    object.write_all(&buffer)?;
+    object.commit()?;
+    drop(object);
+
+    let new_packing = match repo.info().config().packing {
+        Packing::None => Packing::FIXED,
+        Packing::Fixed(_) => Packing::None,
+    };
+    let mut options = RepackOptions::default();
+    options.packing = Some(new_packing.clone());
+    repo.repack(options)?;
+    drop(repo);
+
+    let repo: KeyRepo<String> = repo_store.open()?;
+    let mut actual_data = Vec::new();
+    repo.object("test").unwrap().read_to_end(&mut actual_data)?;
+
+    assert_that!(repo.info().config().packing).is_equal_to(&new_packing);
+    assert_that!(actual_data).is_equal_to(&buffer);
+    assert_that!(repo.verify()).is_ok_containing(HashSet::new());
+
+    Ok(())
+}
+
+#[apply(store_config)]
+fn defragmenting_preserves_data(
+    #[case] repo_store: RepoStore,
+    buffer: Vec<u8>,
 ) -> anyhow::Result<()> {
-    let savepoint = first_repo.savepoint()?;
+    let mut repo: KeyRepo<String> = repo_store.create()?;
+    for key in ["remove", "keep"] {
+        let mut object = repo.insert(String::from(key));
+        object.write_all(&buffer)?;
+        object.commit()?;
+    }
+    repo.commit()?;
+    repo.remove("remove");
+    repo.commit()?;
+    repo.clean()?;
 
-    assert_that!(savepoint.is_valid()).is_true();
-    assert_that!(second_repo.start_restore(&savepoint))
-        .is_err_variant(acid_store::Error::InvalidSavepoint);
+    let mut options = RepackOptions::default();
+    options.defragment = true;
+    repo.repack(options)?;
+    drop(repo);
+
+    let repo: KeyRepo<String> = repo_store.open()?;
+    let mut actual_data = Vec::new();
+    repo.object("keep").unwrap().read_to_end(&mut actual_data)?;
+
+    assert_that!(actual_data).is_equal_to(&buffer);
 
     Ok(())
 }
 
-#[apply(store_config)]
-fn unused_data_is_reclaimed_on_clean(
-    #[case] repo_store: RepoStore,
-    buffer: Vec<u8>,
-) -> anyhow::Result<()> {
+#[rstest]
+fn flushed_changes_are_not_committed(repo_store: RepoStore, buffer: Vec<u8>) -> anyhow::Result<()> {
     let mut repo: KeyRepo<String> = repo_store.create()?;
     let mut object = repo.insert(String::from("test"));
-
     object.write_all(&buffer)?;
     object.commit()?;
     drop(object);
-    repo.commit()?;
+    repo.flush()?;
     drop(repo);
 
-    let mut store = repo_store.store.open()?;
-    let original_blocks = store
-        .list_blocks(BlockType::Data)
-        .map_err(anyhow::Error::msg)?
-        .len();
-    drop(store);
+    let repo: KeyRepo<String> = repo_store.open()?;
+    assert_that!(repo.contains("test")).is_false();
 
-    let mut repo: KeyRepo<String> = repo_store.open()?;
-    repo.remove("test");
-    repo.commit()?;
-    repo.clean()?;
+    Ok(())
+}
+
+#[rstest]
+fn restore_flushed_changes(repo_store: RepoStore, buffer: Vec<u8>) -> anyhow::Result<()> {
+    let mut repo: KeyRepo<String> = repo_store.create()?;
+    let mut object = repo.insert(String::from("test"));
+    object.write_all(&buffer)?;
+    object.commit()?;
+    drop(object);
+    repo.flush()?;
+    repo.insert(String::from("unflushed"));
     drop(repo);
 
-    let mut store = repo_store.store.open()?;
-    let new_blocks = store
-        .list_blocks(BlockType::Data)
-        .map_err(anyhow::Error::msg)?
-        .len();
+    let mut repo: KeyRepo<String> = repo_store.open()?;
+    assert_that!(repo.restore_flushed()).is_ok_containing(true);
 
-    assert_that!(new_blocks).is_less_than(original_blocks);
+    let mut actual_data = Vec::new();
+    repo.object("test").unwrap().read_to_end(&mut actual_data)?;
+
+    assert_that!(actual_data).is_equal_to(&buffer);
+    assert_that!(repo.contains("unflushed")).is_false();
 
     Ok(())
 }
 
-#[apply(object_config)]
-fn clean_before_commit_does_not_prevent_rollback(
-    #[case] repo_object: RepoObject,
-    buffer: Vec<u8>,
-) -> anyhow::Result<()> {
-    let RepoObject {
-        mut repo,
-        mut object,
-        key,
-    } = repo_object;
+#[rstest]
+fn committing_discards_flushed_changes(mut repo: KeyRepo<String>) -> anyhow::Result<()> {
+    assert_that!(repo.restore_flushed()).is_ok_containing(false);
 
-    // Write to an object and commit.
-    object.write_all(&buffer)?;
-    object.commit()?;
-    drop(object);
+    repo.insert(String::from("test"));
+    repo.flush()?;
     repo.commit()?;
 
-    // Delete that object, clean without committing first, and then roll back.
-    repo.remove(&key);
-    repo.clean()?;
+    assert_that!(repo.restore_flushed()).is_ok_containing(false);
 
-    assert_that!(repo.rollback()).is_ok();
+    Ok(())
+}
 
-    // Check if the object still exists.
-    assert_that!(repo.contains(&key)).is_true();
+#[rstest]
+fn cleaning_discards_flushed_changes(mut repo: KeyRepo<String>) -> anyhow::Result<()> {
+    repo.insert(String::from("test"));
+    repo.flush()?;
+    repo.clean()?;
 
-    // Check if the object's data was cleaned up.
-    let mut actual_data = Vec::new();
-    let mut object = repo.object(&key).unwrap();
-    object.read_to_end(&mut actual_data)?;
+    assert_that!(repo.restore_flushed()).is_ok_containing(false);
 
-    assert_that!(actual_data).is_equal_to(&buffer);
+    Ok(())
+}
 
+#[rstest]
+fn creating_existing_checkpoint_errs(mut repo: KeyRepo<String>) -> anyhow::Result<()> {
+    repo.create_checkpoint("checkpoint")?;
+    assert_that!(repo.create_checkpoint("checkpoint"))
+        .is_err_variant(acid_store::Error::AlreadyExists);
     Ok(())
 }
 
 #[rstest]
-fn clear_instance_deletes_objects(repo_object: RepoObject) -> anyhow::Result<()> {
-    let RepoObject {
-        mut repo,
-        mut object,
-        key,
-    } = repo_object;
+fn list_checkpoints(mut repo: KeyRepo<String>) -> anyhow::Result<()> {
+    repo.create_checkpoint("b")?;
+    repo.create_checkpoint("a")?;
+    repo.create_checkpoint("c")?;
+    assert_that!(repo.remove_checkpoint("c")).is_true();
+    assert_that!(repo.remove_checkpoint("c")).is_false();
 
-    object.write_all(b"test data")?;
+    assert_that!(repo.checkpoints().collect::<Vec<_>>()).is_equal_to(vec!["a", "b"]);
+    assert_that!(repo.contains_checkpoint("a")).is_true();
+    assert_that!(repo.contains_checkpoint("c")).is_false();
+
+    Ok(())
+}
+
+#[rstest]
+fn restore_checkpoint(mut repo: KeyRepo<String>, buffer: Vec<u8>) -> anyhow::Result<()> {
+    let mut object = repo.insert(String::from("test"));
+    object.write_all(&buffer)?;
     object.commit()?;
     drop(object);
+    repo.create_checkpoint("checkpoint")?;
+    repo.remove("test");
+    repo.insert(String::from("other"));
+    repo.create_checkpoint("later")?;
 
-    repo.clear_instance();
+    repo.restore_checkpoint("checkpoint")?;
 
-    assert_that!(repo.contains(&key)).is_false();
-    assert_that!(repo.object("test")).is_none();
+    let mut actual_data = Vec::new();
+    repo.object("test").unwrap().read_to_end(&mut actual_data)?;
+
+    assert_that!(actual_data).is_equal_to(&buffer);
+    assert_that!(repo.contains("other")).is_false();
+    assert_that!(repo.contains_checkpoint("later")).is_true();
+    assert_that!(repo.restore_checkpoint("nonexistent"))
+        .is_err_variant(acid_store::Error::NotFound);
 
     Ok(())
 }
 
 #[rstest]
-fn rollback_after_clear_instance(repo_object: RepoObject) -> anyhow::Result<()> {
-    let RepoObject {
-        mut repo,
-        mut object,
-        key,
-    } = repo_object;
-
-    object.write_all(b"test data")?;
-    object.commit()?;
-    drop(object);
-
+fn rolling_back_discards_new_checkpoints(mut repo: KeyRepo<String>) -> anyhow::Result<()> {
+    repo.create_checkpoint("committed")?;
     repo.commit()?;
-    repo.clear_instance();
+    repo.create_checkpoint("uncommitted")?;
     repo.rollback()?;
 
-    assert_that!(repo.contains(&key)).is_true();
-    assert_that!(repo.object(&key)).is_some();
+    assert_that!(repo.checkpoints().collect::<Vec<_>>()).is_equal_to(vec!["committed"]);
 
     Ok(())
 }
 
-#[apply(object_config)]
-fn verify_valid_repository_is_valid(
-    #[case] repo_object: RepoObject,
+#[apply(store_config)]
+fn checkpoints_survive_commit_and_clean(
+    #[case] repo_store: RepoStore,
     buffer: Vec<u8>,
 ) -> anyhow::Result<()> {
-    let RepoObject {
-        repo, mut object, ..
-    } = repo_object;
-
+    let mut repo: KeyRepo<String> = repo_store.create()?;
+    let mut object = repo.insert(String::from("test"));
     object.write_all(&buffer)?;
     object.commit()?;
     drop(object);
+    repo.create_checkpoint("checkpoint")?;
+    repo.remove("test");
+    repo.commit()?;
+    repo.clean()?;
+    drop(repo);
+
+    let mut repo: KeyRepo<String> = repo_store.open()?;
+    assert_that!(repo.contains("test")).is_false();
+    repo.restore_checkpoint("checkpoint")?;
 
+    let mut actual_data = Vec::new();
+    repo.object("test").unwrap().read_to_end(&mut actual_data)?;
+
+    assert_that!(actual_data).is_equal_to(&buffer);
     assert_that!(repo.verify()).is_ok_containing(HashSet::new());
 
     Ok(())
 }
 
-#[rstest]
-fn actual_and_apparent_size_are_correct(
-    repo_object: RepoObject,
+#[apply(store_config)]
+fn checkpoints_survive_repacking(
+    #[case] repo_store: RepoStore,
     buffer: Vec<u8>,
 ) -> anyhow::Result<()> {
-    let RepoObject {
-        mut object,
-        mut repo,
-        ..
-    } = repo_object;
-
-    let hole_size = 100;
-
-    object.write_all(&buffer)?;
-    object.commit()?;
-    drop(object);
-
+    let mut repo: KeyRepo<String> = repo_store.create()?;
     let mut object = repo.insert(String::from("test"));
     object.write_all(&buffer)?;
     object.commit()?;
-    object.set_len(buffer.len() as u64 + hole_size)?;
     drop(object);
+    repo.create_checkpoint("checkpoint")?;
+    repo.remove("test");
 
-    let stats = repo.stats();
+    let new_packing = match repo.info().config().packing {
+        Packing::None => Packing::FIXED,
+        Packing::Fixed(_) => Packing::None,
+    };
+    let mut options = RepackOptions::default();
+    options.packing = Some(new_packing);
+    repo.repack(options)?;
+    drop(repo);
 
-    assert_that!(stats.apparent_size()).is_equal_to((buffer.len() as u64 * 2) + hole_size);
-    assert_that!(stats.actual_size()).is_equal_to(buffer.len() as u64);
+    let mut repo: KeyRepo<String> = repo_store.open()?;
+    repo.restore_checkpoint("checkpoint")?;
+
+    let mut actual_data = Vec::new();
+    repo.object("test").unwrap().read_to_end(&mut actual_data)?;
+
+    assert_that!(actual_data).is_equal_to(&buffer);
 
     Ok(())
 }
 
 #[rstest]
-fn actual_and_apparent_size_are_for_current_instance(
-    repo_object: RepoObject,
-    #[from(buffer)] current_buffer: Vec<u8>,
-    #[from(buffer)] other_buffer: Vec<u8>,
-) -> anyhow::Result<()> {
-    let RepoObject {
-        mut object, repo, ..
-    } = repo_object;
+fn writing_past_quota_errs(buffer: Vec<u8>) -> anyhow::Result<()> {
+    let mut config = RepoConfig::default();
+    config.quota = Some(buffer.len() as u64 - 1);
+    let mut repo: KeyRepo<String> = create_repo(config)?;
 
-    let instance_id = Uuid::new_v4().into();
+    let mut object = repo.insert(String::from("test"));
+    assert_that!(object.write_all(&buffer).map_err(acid_store::Error::from))
+        .is_err_variant(acid_store::Error::QuotaExceeded);
+    assert_that!(object.set_len(buffer.len() as u64))
+        .is_err_variant(acid_store::Error::QuotaExceeded);
 
-    object.write_all(&other_buffer)?;
-    object.commit()?;
-    drop(object);
+    Ok(())
+}
 
-    let mut repo: KeyRepo<String> = repo.switch_instance(instance_id)?;
+#[rstest]
+fn set_quota_overrides_config(buffer: Vec<u8>) -> anyhow::Result<()> {
+    let mut config = RepoConfig::default();
+    config.quota = Some(1);
+    let mut repo: KeyRepo<String> = create_repo(config)?;
+
+    assert_that!(repo.quota()).is_equal_to(Some(1));
+    repo.set_quota(Some(u64::MAX));
+    assert_that!(repo.quota()).is_none();
 
     let mut object = repo.insert(String::from("test"));
-    object.write_all(&current_buffer)?;
+    object.write_all(&buffer)?;
     object.commit()?;
     drop(object);
 
-    let stats = repo.stats();
-
-    assert_that!(stats.apparent_size()).is_equal_to(current_buffer.len() as u64);
-    assert_that!(stats.actual_size()).is_equal_to(current_buffer.len() as u64);
+    repo.set_quota(None);
+    assert_that!(repo.quota()).is_equal_to(Some(1));
 
     Ok(())
 }
 
 #[rstest]
-fn repo_size_is_correct(
-    repo_object: RepoObject,
-    #[from(buffer)] first_buffer: Vec<u8>,
-    #[from(buffer)] second_buffer: Vec<u8>,
-) -> anyhow::Result<()> {
-    let RepoObject {
-        mut object, repo, ..
-    } = repo_object;
+fn removing_objects_frees_quota(mut repo: KeyRepo<String>, buffer: Vec<u8>) -> anyhow::Result<()> {
+    repo.set_quota(Some(buffer.len() as u64));
 
-    let instance_id = Uuid::new_v4().into();
-
-    object.write_all(&first_buffer)?;
-    object.commit()?;
-    drop(object);
-
-    let mut repo: KeyRepo<String> = repo.switch_instance(instance_id)?;
-
-    let mut object = repo.insert(String::from("test1"));
-    object.write_all(&first_buffer)?;
+    let mut object = repo.insert(String::from("first"));
+    object.write_all(&buffer)?;
     object.commit()?;
     drop(object);
 
-    let mut object = repo.insert(String::from("test2"));
-    object.write_all(&second_buffer)?;
-    object.commit()?;
+    let mut object = repo.insert(String::from("second"));
+    assert_that!(object.set_len(1)).is_err_variant(acid_store::Error::QuotaExceeded);
     drop(object);
 
-    let stats = repo.stats();
+    repo.remove("first");
 
-    assert_that!(stats.repo_size())
-        .is_equal_to(first_buffer.len() as u64 + second_buffer.len() as u64);
+    let mut object = repo.insert(String::from("second"));
+    object.write_all(&buffer)?;
+    object.commit()?;
 
     Ok(())
 }
 
+#[cfg(feature = "observability")]
 #[rstest]
-fn unlock_repo(repo_store: RepoStore) -> anyhow::Result<()> {
-    let repo: KeyRepo<String> = repo_store.create()?;
-    repo.unlock()?;
-    assert_that!(repo_store.open::<KeyRepo<String>>()).is_ok();
+fn metrics_are_reported() -> anyhow::Result<()> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use acid_store::repo::Metrics;
+    use acid_store::store::{MemoryConfig, StoreOperation};
+
+    #[derive(Debug, Default)]
+    struct CountingMetrics {
+        chunks_written: AtomicUsize,
+        chunks_deduplicated: AtomicUsize,
+        store_writes: AtomicUsize,
+    }
+
+    impl Metrics for CountingMetrics {
+        fn chunk_written(&self, _size: usize) {
+            self.chunks_written.fetch_add(1, Ordering::SeqCst);
+        }
+
+        fn chunk_deduplicated(&self, _size: usize) {
+            self.chunks_deduplicated.fetch_add(1, Ordering::SeqCst);
+        }
+
+        fn store_operation(&self, operation: StoreOperation, _duration: Duration, _: bool) {
+            if operation == StoreOperation::WriteBlock {
+                self.store_writes.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+    }
+
+    let metrics = Arc::new(CountingMetrics::default());
+    let mut repo: KeyRepo<String> = OpenOptions::new()
+        .mode(OpenMode::CreateNew)
+        .metrics(metrics.clone())
+        .open(&MemoryConfig::new())?;
+
+    for key in ["first", "second"] {
+        let mut object = repo.insert(String::from(key));
+        object.write_all(b"data")?;
+        object.commit()?;
+    }
+    repo.commit()?;
+
+    assert_that!(metrics.chunks_written.load(Ordering::SeqCst)).is_greater_than_or_equal_to(1);
+    assert_that!(metrics.chunks_deduplicated.load(Ordering::SeqCst)).is_greater_than_or_equal_to(1);
+    assert_that!(metrics.store_writes.load(Ordering::SeqCst)).is_greater_than(0);
+
     Ok(())
 }
 
 #[rstest]
-fn check_repo_is_locked(repo_store: RepoStore) -> anyhow::Result<()> {
-    let repo: KeyRepo<String> = repo_store.create()?;
-    assert_that!(repo.is_locked()).is_ok_containing(true);
-    repo.unlock()?;
-    assert_that!(repo.is_locked()).is_ok_containing(false);
+fn hooks_are_called_at_transaction_boundaries(mut repo: KeyRepo<String>) -> anyhow::Result<()> {
+    use std::sync::{Arc, Mutex};
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    for event in [
+        RepoEvent::PreCommit,
+        RepoEvent::PostCommit,
+        RepoEvent::PostClean,
+    ] {
+        let events = events.clone();
+        repo.on_event(event, move |_| events.lock().unwrap().push(event));
+    }
+
+    repo.insert(String::from("test"));
+    repo.commit()?;
+    repo.clean()?;
+
+    assert_that!(*events.lock().unwrap()).is_equal_to(vec![
+        RepoEvent::PreCommit,
+        RepoEvent::PostCommit,
+        RepoEvent::PostClean,
+    ]);
+
     Ok(())
 }
 
 #[rstest]
-fn get_lock_context(mut repo_store: RepoStore) -> anyhow::Result<()> {
-    repo_store.context = b"lock context value".to_vec();
-    let repo: KeyRepo<String> = repo_store.create()?;
-    assert_that!(repo.context()).is_ok_containing(&repo_store.context);
+fn hooks_receive_committed_info(mut repo: KeyRepo<String>) -> anyhow::Result<()> {
+    use std::sync::{Arc, Mutex};
+
+    let fingerprint = Arc::new(Mutex::new(None));
+    let hook_fingerprint = fingerprint.clone();
+    repo.on_event(RepoEvent::PostCommit, move |info| {
+        *hook_fingerprint.lock().unwrap() = info.fingerprint();
+    });
+
+    let mut object = repo.insert(String::from("test"));
+    object.write_all(b"data")?;
+    object.commit()?;
+    drop(object);
+    repo.commit()?;
+
+    assert_that!(*fingerprint.lock().unwrap()).is_equal_to(repo.info().fingerprint());
+    assert_that!(*fingerprint.lock().unwrap()).is_some();
+
     Ok(())
 }
 
 #[rstest]
-fn update_lock_context(mut repo_store: RepoStore) -> anyhow::Result<()> {
-    repo_store.context = b"initial context".to_vec();
-    let repo: KeyRepo<String> = repo_store.create()?;
-    repo.update_context(b"updated context")?;
-    repo_store.handler = Box::new(|context| {
-        assert_that!(context).is_equal_to(&b"updated context"[..]);
-        true
+fn removed_hooks_are_not_called(mut repo: KeyRepo<String>) -> anyhow::Result<()> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let count = Arc::new(AtomicUsize::new(0));
+    let hook_count = count.clone();
+    let id = repo.on_event(RepoEvent::PostCommit, move |_| {
+        hook_count.fetch_add(1, Ordering::SeqCst);
     });
-    assert_that!(repo_store.open::<KeyRepo<String>>()).is_ok();
+
+    repo.commit()?;
+    assert_that!(repo.remove_hook(id)).is_true();
+    assert_that!(repo.remove_hook(id)).is_false();
+    repo.commit()?;
+
+    assert_that!(count.load(Ordering::SeqCst)).is_equal_to(1);
+
     Ok(())
 }
@@ -1,10 +1,12 @@
-#![cfg(all(feature = "encryption", feature = "compression"))]
+#![cfg(feature = "testing")]
 
 use std::convert::TryFrom;
 use std::io::{Read, Seek, SeekFrom, Write};
 
 use acid_store::repo::key::KeyRepo;
-use acid_store::repo::{Chunking, Commit, ReadOnlyObject, RepoConfig, RestoreSavepoint};
+use acid_store::repo::{
+    Access, Chunking, Commit, Packing, ReadOnlyObject, RepoConfig, RestoreSavepoint,
+};
 use common::*;
 use rstest_reuse::{self, *};
 
@@ -380,6 +382,109 @@ fn check_file_stats_with_holes(
     Ok(())
 }
 
+#[apply(object_config)]
+fn object_stats_describe_chunks_and_packs(
+    #[case] repo_object: RepoObject,
+    buffer: Vec<u8>,
+) -> anyhow::Result<()> {
+    let RepoObject {
+        repo, mut object, ..
+    } = repo_object;
+    object.write_all(&buffer)?;
+    object.commit()?;
+
+    let stats = object.stats()?;
+
+    assert_that!(stats.chunks()).is_greater_than(0);
+    assert_that!(stats.compressed_size()).is_some();
+    assert_that!(stats.compression_ratio()).is_some();
+    assert_that!(stats.shared_chunks()).is_equal_to(0);
+    match repo.info().config().packing {
+        Packing::None => assert_that!(stats.packs()).is_none(),
+        _ => assert_that!(stats.packs().unwrap_or(0)).is_greater_than(0),
+    }
+
+    Ok(())
+}
+
+#[rstest]
+fn object_stats_count_shared_chunks(
+    repo_object: RepoObject,
+    buffer: Vec<u8>,
+) -> anyhow::Result<()> {
+    let RepoObject {
+        mut repo,
+        mut object,
+        ..
+    } = repo_object;
+    object.write_all(&buffer)?;
+    object.commit()?;
+
+    let mut other_object = repo.insert(String::from("other"));
+    other_object.write_all(&buffer)?;
+    other_object.commit()?;
+
+    let stats = object.stats()?;
+    assert_that!(stats.shared_chunks()).is_equal_to(stats.chunks());
+
+    Ok(())
+}
+
+#[apply(object_config)]
+fn punching_hole_deallocates_range(
+    #[case] repo_object: RepoObject,
+    buffer: Vec<u8>,
+) -> anyhow::Result<()> {
+    let mut object = repo_object.object;
+    object.write_all(&buffer)?;
+    object.commit()?;
+
+    let hole = 100..1000;
+    object.punch_hole(hole.clone())?;
+    object.punch_hole(buffer.len() as u64..(buffer.len() as u64 * 2))?;
+
+    let mut expected_data = buffer.clone();
+    expected_data[100..1000].fill(0);
+
+    let mut actual_data = Vec::new();
+    object.seek(SeekFrom::Start(0))?;
+    object.read_to_end(&mut actual_data)?;
+
+    assert_that!(object.size()).is_ok_containing(buffer.len() as u64);
+    assert_that!(object.stats()?.holes()).is_equal_to(&[hole][..]);
+    assert_that!(actual_data).is_equal_to(&expected_data);
+
+    Ok(())
+}
+
+#[apply(object_config)]
+fn allocating_fills_holes(#[case] repo_object: RepoObject) -> anyhow::Result<()> {
+    let mut object = repo_object.object;
+    object.set_len(1000)?;
+    object.allocate(200..1200)?;
+
+    let mut actual_data = Vec::new();
+    object.seek(SeekFrom::Start(0))?;
+    object.read_to_end(&mut actual_data)?;
+
+    assert_that!(object.size()).is_ok_containing(1200);
+    assert_that!(object.stats()?.holes()).is_equal_to(&[0..200][..]);
+    assert_that!(actual_data).is_equal_to(vec![0u8; 1200]);
+
+    Ok(())
+}
+
+#[rstest]
+fn punching_hole_with_transaction_in_progress_errs(repo_object: RepoObject) -> anyhow::Result<()> {
+    let mut object = repo_object.object;
+    object.write_all(b"test data")?;
+
+    assert_that!(object.punch_hole(0..4)).is_err_variant(acid_store::Error::TransactionInProgress);
+    assert_that!(object.allocate(0..4)).is_err_variant(acid_store::Error::TransactionInProgress);
+
+    Ok(())
+}
+
 #[apply(repo_config)]
 fn compare_content_ids(
     #[case] mut repo: KeyRepo<String>,
@@ -555,6 +660,146 @@ fn write_buffer_with_same_size_as_fixed_chunk_size(
     Ok(())
 }
 
+#[apply(object_config)]
+fn read_prefetched_data(#[case] repo_object: RepoObject, buffer: Vec<u8>) -> anyhow::Result<()> {
+    let mut object = repo_object.object;
+    let mut actual_data = Vec::new();
+
+    object.write_all(&buffer)?;
+    object.commit()?;
+    object.prefetch(0..buffer.len() as u64)?;
+    object.seek(SeekFrom::Start(0))?;
+    object.read_to_end(&mut actual_data)?;
+
+    assert_that!(&actual_data).is_equal_to(&buffer);
+
+    Ok(())
+}
+
+#[apply(object_config)]
+fn read_part_of_prefetched_data(
+    #[case] repo_object: RepoObject,
+    buffer: Vec<u8>,
+) -> anyhow::Result<()> {
+    let mut object = repo_object.object;
+    let middle = buffer.len() / 2;
+    let mut actual_data = Vec::new();
+
+    object.write_all(&buffer)?;
+    object.commit()?;
+    object.prefetch(middle as u64..u64::MAX)?;
+    object.seek(SeekFrom::Start(middle as u64))?;
+    object.read_to_end(&mut actual_data)?;
+
+    assert_that!(actual_data.as_slice()).is_equal_to(&buffer[middle..]);
+
+    Ok(())
+}
+
+#[apply(object_config)]
+fn read_data_sequentially(#[case] repo_object: RepoObject, buffer: Vec<u8>) -> anyhow::Result<()> {
+    let mut object = repo_object.object;
+    let mut actual_data = Vec::new();
+
+    object.write_all(&buffer)?;
+    object.commit()?;
+    object.advise(Access::Sequential);
+    object.seek(SeekFrom::Start(0))?;
+    object.read_to_end(&mut actual_data)?;
+
+    assert_that!(&actual_data).is_equal_to(&buffer);
+
+    Ok(())
+}
+
+#[rstest]
+fn read_sequentially_after_overwriting(
+    repo_object: RepoObject,
+    buffer: Vec<u8>,
+    #[from(buffer)] new_buffer: Vec<u8>,
+) -> anyhow::Result<()> {
+    let mut object = repo_object.object;
+    let mut actual_data = Vec::new();
+
+    object.write_all(&buffer)?;
+    object.commit()?;
+    object.advise(Access::Sequential);
+    object.prefetch(0..buffer.len() as u64)?;
+
+    object.seek(SeekFrom::Start(0))?;
+    object.set_len(0)?;
+    object.write_all(&new_buffer)?;
+    object.commit()?;
+
+    object.seek(SeekFrom::Start(0))?;
+    object.read_to_end(&mut actual_data)?;
+
+    assert_that!(&actual_data).is_equal_to(&new_buffer);
+
+    Ok(())
+}
+
+#[apply(object_config)]
+fn read_data_written_with_buffer(
+    #[case] repo_object: RepoObject,
+    buffer: Vec<u8>,
+) -> anyhow::Result<()> {
+    let mut object = repo_object.object;
+    let mut actual_data = Vec::new();
+
+    object.set_write_buffer_size(1024);
+    for piece in buffer.chunks(7) {
+        object.write_all(piece)?;
+    }
+    object.commit()?;
+    object.seek(SeekFrom::Start(0))?;
+    object.read_to_end(&mut actual_data)?;
+
+    assert_that!(&actual_data).is_equal_to(&buffer);
+
+    Ok(())
+}
+
+#[apply(object_config)]
+fn overwrite_middle_with_buffer(
+    #[case] repo_object: RepoObject,
+    buffer: Vec<u8>,
+) -> anyhow::Result<()> {
+    let mut object = repo_object.object;
+    let middle = buffer.len() / 2;
+    let patch = b"buffered patch";
+    let mut actual_data = Vec::new();
+
+    object.write_all(&buffer)?;
+    object.commit()?;
+
+    object.set_write_buffer_size(1024 * 1024);
+    object.seek(SeekFrom::Start(middle as u64))?;
+    object.write_all(patch)?;
+    object.commit()?;
+
+    let mut expected_data = buffer.clone();
+    expected_data[middle..middle + patch.len()].copy_from_slice(patch);
+
+    object.seek(SeekFrom::Start(0))?;
+    object.read_to_end(&mut actual_data)?;
+
+    assert_that!(&actual_data).is_equal_to(&expected_data);
+
+    Ok(())
+}
+
+#[rstest]
+fn prefetching_with_uncommitted_changes_errs(repo_object: RepoObject) -> anyhow::Result<()> {
+    let mut object = repo_object.object;
+
+    object.write_all(b"test data")?;
+
+    assert_that!(object.prefetch(0..9)).is_err_variant(acid_store::Error::TransactionInProgress);
+
+    Ok(())
+}
+
 #[rstest]
 fn reading_seeking_with_uncommitted_changes_errs(repo_object: RepoObject) -> anyhow::Result<()> {
     let mut object = repo_object.object;
@@ -782,6 +1027,92 @@ fn converting_to_read_only_with_uncommitted_changes_errs(
     Ok(())
 }
 
+#[rstest]
+fn stream_is_not_affected_by_changes(
+    repo_object: RepoObject,
+    buffer: Vec<u8>,
+    smaller_buffer: Vec<u8>,
+) -> anyhow::Result<()> {
+    let RepoObject {
+        mut repo,
+        mut object,
+        key,
+    } = repo_object;
+    object.write_all(&buffer)?;
+    object.commit()?;
+    object.seek(SeekFrom::Start(0))?;
+    let mut stream = object.into_stream()?;
+
+    let mut object = repo.insert(key.clone());
+    object.write_all(&smaller_buffer)?;
+    object.commit()?;
+    drop(object);
+    repo.remove(&key);
+
+    let mut actual_data = Vec::new();
+    stream.read_to_end(&mut actual_data)?;
+
+    assert_that!(stream.is_valid()).is_true();
+    assert_that!(stream.size()).is_ok_containing(buffer.len() as u64);
+    assert_that!(actual_data).is_equal_to(&buffer);
+
+    Ok(())
+}
+
+#[rstest]
+fn stream_can_be_read_from_another_thread(
+    repo_object: RepoObject,
+    buffer: Vec<u8>,
+) -> anyhow::Result<()> {
+    let RepoObject {
+        repo, mut object, ..
+    } = repo_object;
+    object.write_all(&buffer)?;
+    object.commit()?;
+    object.seek(SeekFrom::Start(0))?;
+    let mut stream = ReadOnlyObject::try_from(object)?.into_stream()?;
+
+    let actual_data = std::thread::spawn(move || {
+        let mut actual_data = Vec::new();
+        stream.read_to_end(&mut actual_data).map(|_| actual_data)
+    })
+    .join()
+    .unwrap()?;
+    drop(repo);
+
+    assert_that!(actual_data).is_equal_to(&buffer);
+
+    Ok(())
+}
+
+#[rstest]
+fn converting_to_stream_with_uncommitted_changes_errs(
+    repo_object: RepoObject,
+) -> anyhow::Result<()> {
+    let mut object = repo_object.object;
+    object.write_all(b"test data")?;
+
+    assert_that!(object.into_stream().map(|_| ()))
+        .is_err_variant(acid_store::Error::TransactionInProgress);
+
+    Ok(())
+}
+
+#[rstest]
+fn accessing_stream_once_repo_is_dropped_errs(repo_object: RepoObject) -> anyhow::Result<()> {
+    let mut stream = repo_object.object.into_stream()?;
+    drop(repo_object.repo);
+
+    let mut content = Vec::new();
+
+    assert_that!(stream.is_valid()).is_false();
+    assert_that!(stream.size()).is_err_variant(acid_store::Error::InvalidObject);
+    assert_that!(stream.read(&mut content).map_err(acid_store::Error::from))
+        .is_err_variant(acid_store::Error::InvalidObject);
+
+    Ok(())
+}
+
 #[rstest]
 fn rolling_back_repo_invalidates_objects(repo_object: RepoObject) -> anyhow::Result<()> {
     let RepoObject {
@@ -1,19 +1,39 @@
-#![cfg(all(
-    feature = "repo-value",
-    feature = "encryption",
-    feature = "compression"
-))]
+#![cfg(all(feature = "repo-value", feature = "testing"))]
 
 use acid_store::repo::key::KeyRepo;
 use acid_store::repo::value::ValueRepo;
 use acid_store::repo::{
-    Chunking, Commit, Compression, Encryption, OpenMode, OpenOptions, RepoConfig, ResourceLimit,
+    Chunking, Commit, Compression, ConfigPreset, Encryption, KeyDerivation, KeyProvider, OpenMode,
+    OpenOptions, Packing, RepoConfig, ResourceLimit,
 };
-use acid_store::store::MemoryConfig;
+use acid_store::store::{BlockId, BlockKey, BlockType, DataStore, MemoryConfig, OpenStore};
 use common::*;
+use std::collections::HashSet;
+use std::io::{Read, Write};
+use std::sync::Arc;
 
 mod common;
 
+/// A `KeyProvider` which wraps keys by XORing them with a secret byte.
+struct XorKeyProvider(u8);
+
+impl KeyProvider for XorKeyProvider {
+    fn wrap_key(&self, key: &[u8]) -> acid_store::Result<Vec<u8>> {
+        let mut wrapped_key = vec![self.0];
+        wrapped_key.extend(key.iter().map(|byte| byte ^ self.0));
+        Ok(wrapped_key)
+    }
+
+    fn unwrap_key(&self, wrapped_key: &[u8]) -> acid_store::Result<Vec<u8>> {
+        match wrapped_key.split_first() {
+            Some((tag, key)) if *tag == self.0 => {
+                Ok(key.iter().map(|byte| byte ^ self.0).collect())
+            }
+            _ => Err(acid_store::Error::Password),
+        }
+    }
+}
+
 #[rstest]
 fn set_existing_config_and_create_new_repo(mut repo_store: RepoStore) -> anyhow::Result<()> {
     // These are random config values for testing. This should not be used as an example config.
@@ -30,6 +50,33 @@ fn set_existing_config_and_create_new_repo(mut repo_store: RepoStore) -> anyhow:
     Ok(())
 }
 
+#[rstest]
+#[case(RepoConfig::backup(), ConfigPreset::Backup)]
+#[case(RepoConfig::small_files(), ConfigPreset::SmallFiles)]
+#[case(RepoConfig::media_archive(), ConfigPreset::MediaArchive)]
+fn preset_is_recorded_in_repo_info(
+    mut repo_store: RepoStore,
+    #[case] config: RepoConfig,
+    #[case] preset: ConfigPreset,
+) -> anyhow::Result<()> {
+    repo_store.config = config;
+    let repo: KeyRepo<String> = repo_store.create()?;
+    drop(repo);
+
+    let repo: KeyRepo<String> = repo_store.open()?;
+    assert_that!(repo.info().config().preset).is_equal_to(Some(preset));
+    assert_that!(repo.info().config()).is_equal_to(&repo_store.config);
+
+    Ok(())
+}
+
+#[rstest]
+fn default_config_has_no_preset(repo_store: RepoStore) -> anyhow::Result<()> {
+    let repo: KeyRepo<String> = repo_store.create()?;
+    assert_that!(repo.info().config().preset).is_none();
+    Ok(())
+}
+
 #[rstest]
 fn configure_and_create_new_repo() -> anyhow::Result<()> {
     // These are random config values for testing. This should not be used as an example config.
@@ -56,6 +103,168 @@ fn configure_and_create_new_repo() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[rstest]
+#[case(KeyDerivation::Argon2id { memory_kib: 1024 * 8, iterations: 2, parallelism: 2 })]
+#[case(KeyDerivation::Scrypt { log_n: 10, r: 8, p: 1 })]
+#[case(KeyDerivation::Pbkdf2Sha256 { iterations: 1000 })]
+fn create_and_open_repo_with_key_derivation(
+    mut repo_store: RepoStore,
+    #[case] key_derivation: KeyDerivation,
+) -> anyhow::Result<()> {
+    repo_store.config.encryption = Encryption::XChaCha20Poly1305;
+    repo_store.config.key_derivation = Some(key_derivation);
+    repo_store.create::<KeyRepo<String>>()?;
+
+    let repo: KeyRepo<String> = repo_store.open()?;
+    assert_that!(repo.info().config().key_derivation).is_equal_to(Some(key_derivation));
+
+    repo_store.password = String::from("Not the password");
+    assert_that!(repo_store.open::<KeyRepo<String>>()).is_err_variant(acid_store::Error::Password);
+
+    Ok(())
+}
+
+#[rstest]
+#[case(KeyDerivation::Argon2id { memory_kib: 8, iterations: 1, parallelism: 2 })]
+#[case(KeyDerivation::Scrypt { log_n: 10, r: 8, p: 0 })]
+#[case(KeyDerivation::Pbkdf2Sha256 { iterations: 0 })]
+fn creating_with_invalid_key_derivation_errs(
+    mut repo_store: RepoStore,
+    #[case] key_derivation: KeyDerivation,
+) {
+    repo_store.config.encryption = Encryption::XChaCha20Poly1305;
+    repo_store.config.key_derivation = Some(key_derivation);
+
+    assert_that!(repo_store.create::<KeyRepo<String>>())
+        .is_err_variant(acid_store::Error::InvalidConfig);
+}
+
+#[rstest]
+fn create_and_open_repo_with_key_provider() -> anyhow::Result<()> {
+    let config = MemoryConfig::new();
+    let mut repo: ValueRepo<String> = OpenOptions::new()
+        .encryption(Encryption::XChaCha20Poly1305)
+        .key_provider(Arc::new(XorKeyProvider(42)))
+        .mode(OpenMode::CreateNew)
+        .open(&config)?;
+    repo.insert(String::from("Key"), &String::from("Value"))?;
+    repo.commit()?;
+    drop(repo);
+
+    let repo: ValueRepo<String> = OpenOptions::new()
+        .key_provider(Arc::new(XorKeyProvider(42)))
+        .open(&config)?;
+
+    assert_that!(&repo.get::<str, String>("Key")).is_ok_containing(String::from("Value"));
+
+    Ok(())
+}
+
+#[rstest]
+fn opening_with_wrong_key_provider_errs() -> anyhow::Result<()> {
+    let config = MemoryConfig::new();
+    OpenOptions::new()
+        .encryption(Encryption::XChaCha20Poly1305)
+        .key_provider(Arc::new(XorKeyProvider(42)))
+        .mode(OpenMode::CreateNew)
+        .open::<KeyRepo<String>, _>(&config)?;
+
+    assert_that!(OpenOptions::new()
+        .key_provider(Arc::new(XorKeyProvider(7)))
+        .open::<KeyRepo<String>, _>(&config))
+    .is_err_variant(acid_store::Error::Password);
+    assert_that!(OpenOptions::new()
+        .password(b"password")
+        .open::<KeyRepo<String>, _>(&config))
+    .is_err_variant(acid_store::Error::Password);
+
+    Ok(())
+}
+
+/// Create a repository with convergent encryption, write `data` to it, and return its data blocks.
+fn write_convergent_repo(
+    config: &MemoryConfig,
+    password: &[u8],
+    data: &[u8],
+) -> anyhow::Result<HashSet<(BlockId, Vec<u8>)>> {
+    let mut repo: KeyRepo<String> = OpenOptions::new()
+        .encryption(Encryption::XChaCha20Poly1305)
+        .convergent_encryption(true)
+        .convergence_secret(b"convergence secret")
+        .password(password)
+        .mode(OpenMode::CreateNew)
+        .open(config)?;
+    let mut object = repo.insert(String::from("test"));
+    object.write_all(data)?;
+    object.commit()?;
+    drop(object);
+    repo.commit()?;
+    drop(repo);
+
+    let mut store = config.open()?;
+    let mut blocks = HashSet::new();
+    for id in store
+        .list_blocks(BlockType::Data)
+        .map_err(anyhow::Error::msg)?
+    {
+        let block = store
+            .read_block(BlockKey::Data(id))
+            .map_err(anyhow::Error::msg)?
+            .unwrap();
+        blocks.insert((id, block));
+    }
+    Ok(blocks)
+}
+
+#[rstest]
+fn identical_data_is_encrypted_identically_with_convergent_encryption(
+    buffer: Vec<u8>,
+) -> anyhow::Result<()> {
+    let first_config = MemoryConfig::new();
+    let second_config = MemoryConfig::new();
+
+    let first_blocks = write_convergent_repo(&first_config, b"first password", &buffer)?;
+    let second_blocks = write_convergent_repo(&second_config, b"second password", &buffer)?;
+
+    assert_that!(first_blocks).is_not_empty();
+    assert_that!(first_blocks).is_equal_to(second_blocks);
+
+    let repo: KeyRepo<String> = OpenOptions::new()
+        .password(b"second password")
+        .open(&second_config)?;
+    let mut object = repo.object("test").unwrap();
+    let mut actual_data = Vec::new();
+    object.read_to_end(&mut actual_data)?;
+
+    assert_that!(actual_data).is_equal_to(buffer);
+
+    Ok(())
+}
+
+#[rstest]
+fn creating_with_convergent_encryption_without_secret_errs() {
+    assert_that!(OpenOptions::new()
+        .encryption(Encryption::XChaCha20Poly1305)
+        .convergent_encryption(true)
+        .password(b"password")
+        .mode(OpenMode::CreateNew)
+        .open::<KeyRepo<String>, _>(&MemoryConfig::new()))
+    .is_err_variant(acid_store::Error::InvalidConfig);
+}
+
+#[rstest]
+fn creating_with_convergent_encryption_and_packing_errs() {
+    assert_that!(OpenOptions::new()
+        .encryption(Encryption::XChaCha20Poly1305)
+        .packing(Packing::Fixed(1024))
+        .convergent_encryption(true)
+        .convergence_secret(b"convergence secret")
+        .password(b"password")
+        .mode(OpenMode::CreateNew)
+        .open::<KeyRepo<String>, _>(&MemoryConfig::new()))
+    .is_err_variant(acid_store::Error::InvalidConfig);
+}
+
 #[rstest]
 fn creating_new_existing_repo_errs(repo_store: RepoStore) -> anyhow::Result<()> {
     repo_store.create::<KeyRepo<String>>()?;
@@ -179,3 +388,16 @@ fn lock_handler_is_passed_context_of_existing_lock(
     assert_that!(repo_store.open::<KeyRepo<String>>()).is_ok();
     Ok(())
 }
+
+#[rstest]
+fn open_with_health_check() -> anyhow::Result<()> {
+    let config = MemoryConfig::new();
+    let repo: acid_store::Result<KeyRepo<String>> = OpenOptions::new()
+        .mode(OpenMode::CreateNew)
+        .health_check(true)
+        .open(&config);
+
+    assert_that!(repo).is_ok();
+
+    Ok(())
+}
@@ -0,0 +1,93 @@
+#![cfg(all(feature = "repo-session", feature = "testing"))]
+
+use std::time::Duration;
+
+use acid_store::repo::session::SessionRepo;
+use acid_store::repo::Commit;
+use common::*;
+
+mod common;
+
+const TEST_VALUE: (bool, i32) = (true, 42);
+
+const LONG_TTL: Duration = Duration::from_secs(3600);
+
+#[rstest]
+fn insert_value(mut repo: SessionRepo<String>) {
+    assert_that!(repo.insert("test".into(), &TEST_VALUE, LONG_TTL)).is_ok();
+    assert_that!(repo.contains("test")).is_true();
+    assert_that!(repo.get("test")).is_ok_containing(TEST_VALUE);
+    assert_that!(repo.expires_at("test")).is_some();
+}
+
+#[rstest]
+fn expired_value_is_not_found(mut repo: SessionRepo<String>) {
+    assert_that!(repo.insert("test".into(), &TEST_VALUE, Duration::ZERO)).is_ok();
+    assert_that!(repo.contains("test")).is_false();
+    assert_that!(repo.get::<_, (bool, i32)>("test")).is_err_variant(acid_store::Error::NotFound);
+    assert_that!(repo.expires_at("test")).is_none();
+    assert_that!(repo.keys().count()).is_equal_to(0);
+}
+
+#[rstest]
+fn touching_expired_value_errs(mut repo: SessionRepo<String>) {
+    assert_that!(repo.insert("test".into(), &TEST_VALUE, Duration::ZERO)).is_ok();
+    assert_that!(repo.touch("test", LONG_TTL)).is_err_variant(acid_store::Error::NotFound);
+}
+
+#[rstest]
+fn touching_value_extends_expiration(mut repo: SessionRepo<String>) -> anyhow::Result<()> {
+    repo.insert("test".into(), &TEST_VALUE, Duration::from_secs(60))?;
+    let initial_expiration = repo.expires_at("test").unwrap();
+    repo.touch("test", LONG_TTL)?;
+    assert_that!(repo.expires_at("test"))
+        .is_some()
+        .is_greater_than(initial_expiration);
+    Ok(())
+}
+
+#[rstest]
+fn purge_expired_removes_only_expired_values(mut repo: SessionRepo<String>) -> anyhow::Result<()> {
+    repo.insert("expired1".into(), &TEST_VALUE, Duration::ZERO)?;
+    repo.insert("expired2".into(), &TEST_VALUE, Duration::ZERO)?;
+    repo.insert("live".into(), &TEST_VALUE, LONG_TTL)?;
+
+    assert_that!(repo.purge_expired()).is_equal_to(2);
+    assert_that!(repo.purge_expired()).is_equal_to(0);
+    assert_that!(repo.keys().cloned().collect::<Vec<_>>()).is_equal_to(vec![String::from("live")]);
+
+    Ok(())
+}
+
+#[rstest]
+fn purged_values_are_reclaimed(
+    mut repo: SessionRepo<String>,
+    buffer: Vec<u8>,
+) -> anyhow::Result<()> {
+    repo.insert("expired".into(), &buffer, Duration::ZERO)?;
+    repo.commit()?;
+    let initial_size = repo.stats().apparent_size();
+
+    repo.purge_expired();
+    repo.commit()?;
+    repo.clean()?;
+
+    assert_that!(repo.stats().apparent_size()).is_less_than(initial_size);
+
+    Ok(())
+}
+
+#[rstest]
+fn expiration_persists_after_commit(repo_store: RepoStore) -> anyhow::Result<()> {
+    let mut repo: SessionRepo<String> = repo_store.create()?;
+    repo.insert("live".into(), &TEST_VALUE, LONG_TTL)?;
+    let expiration = repo.expires_at("live");
+    repo.commit()?;
+    drop(repo);
+
+    let repo: SessionRepo<String> = repo_store.open()?;
+    assert_that!(repo.expires_at("live")).is_equal_to(expiration);
+    assert_that!(repo.get("live")).is_ok_containing(TEST_VALUE);
+
+    Ok(())
+}
@@ -0,0 +1,70 @@
+#![cfg(all(feature = "repo-single", feature = "testing"))]
+
+use std::io::Write;
+
+use acid_store::repo::single::SingleObjectRepo;
+use acid_store::repo::Commit;
+use common::*;
+
+mod common;
+
+#[rstest]
+fn new_repo_is_empty(repo: SingleObjectRepo) {
+    assert_that!(repo.size()).is_ok_containing(0);
+    assert_that!(repo.read()).is_ok_containing(Vec::new());
+}
+
+#[rstest]
+fn write_and_read_data(mut repo: SingleObjectRepo, buffer: Vec<u8>) {
+    assert_that!(repo.write(&buffer)).is_ok();
+    assert_that!(repo.size()).is_ok_containing(buffer.len() as u64);
+    assert_that!(repo.read()).is_ok_containing(buffer);
+}
+
+#[rstest]
+fn write_replaces_existing_data(
+    mut repo: SingleObjectRepo,
+    buffer: Vec<u8>,
+    smaller_buffer: Vec<u8>,
+) {
+    assert_that!(repo.write(&buffer)).is_ok();
+    assert_that!(repo.write(&smaller_buffer)).is_ok();
+    assert_that!(repo.read()).is_ok_containing(smaller_buffer);
+}
+
+#[rstest]
+fn uncommitted_object_changes_prevent_read(mut repo: SingleObjectRepo, buffer: Vec<u8>) {
+    let mut object = repo.object();
+    object.write_all(&buffer).unwrap();
+
+    assert_that!(repo.read()).is_err_variant(acid_store::Error::TransactionInProgress);
+
+    object.commit().unwrap();
+    drop(object);
+
+    assert_that!(repo.read()).is_ok_containing(buffer);
+}
+
+#[rstest]
+fn rollback_restores_previous_data(mut repo: SingleObjectRepo, buffer: Vec<u8>) {
+    repo.write(&buffer).unwrap();
+    repo.commit().unwrap();
+    repo.write(b"new data").unwrap();
+    repo.rollback().unwrap();
+
+    assert_that!(repo.read()).is_ok_containing(buffer);
+}
+
+#[rstest]
+fn committed_data_persists(repo_store: RepoStore, buffer: Vec<u8>) -> anyhow::Result<()> {
+    let mut repo: SingleObjectRepo = repo_store.create()?;
+    repo.write(&buffer)?;
+    repo.commit()?;
+    drop(repo);
+
+    let repo: SingleObjectRepo = repo_store.open()?;
+    assert_that!(repo.read()).is_ok_containing(buffer);
+    assert_that!(repo.verify()).is_ok_containing(true);
+
+    Ok(())
+}
@@ -0,0 +1,125 @@
+#![cfg(all(feature = "repo-snapshot", feature = "testing"))]
+
+use std::collections::HashSet;
+use std::io::{Read, Write};
+
+use acid_store::repo::snapshot::{Change, SnapshotRepo};
+use acid_store::repo::Commit;
+use common::*;
+
+mod common;
+
+fn write_object(repo: &mut SnapshotRepo<String>, key: &str, data: &[u8]) -> anyhow::Result<()> {
+    let mut object = repo.insert(key.to_string());
+    object.write_all(data)?;
+    object.commit()?;
+    Ok(())
+}
+
+#[rstest]
+fn snapshot_is_not_affected_by_changes(mut repo: SnapshotRepo<String>) -> anyhow::Result<()> {
+    write_object(&mut repo, "test", b"old")?;
+    repo.create_snapshot("snapshot")?;
+    write_object(&mut repo, "test", b"new")?;
+    repo.remove("test");
+
+    let mut actual_data = Vec::new();
+    repo.snapshot_object("snapshot", "test")?
+        .read_to_end(&mut actual_data)?;
+
+    assert_that!(repo.contains("test")).is_false();
+    assert_that!(actual_data.as_slice()).is_equal_to(&b"old"[..]);
+
+    Ok(())
+}
+
+#[rstest]
+fn creating_existing_snapshot_errs(mut repo: SnapshotRepo<String>) -> anyhow::Result<()> {
+    repo.create_snapshot("snapshot")?;
+    assert_that!(repo.create_snapshot("snapshot")).is_err_variant(acid_store::Error::AlreadyExists);
+    Ok(())
+}
+
+#[rstest]
+fn list_snapshots(mut repo: SnapshotRepo<String>) -> anyhow::Result<()> {
+    repo.create_snapshot("b")?;
+    repo.create_snapshot("a")?;
+    repo.create_snapshot("c")?;
+    assert_that!(repo.remove_snapshot("c")).is_true();
+
+    assert_that!(repo.snapshots().collect::<Vec<_>>()).is_equal_to(vec!["a", "b"]);
+    assert_that!(repo.contains_snapshot("c")).is_false();
+    assert_that!(repo.snapshot_time("a")).is_some();
+    assert_that!(repo.snapshot_time("c")).is_none();
+
+    Ok(())
+}
+
+#[rstest]
+fn diff_snapshots(mut repo: SnapshotRepo<String>) -> anyhow::Result<()> {
+    write_object(&mut repo, "unchanged", b"unchanged")?;
+    write_object(&mut repo, "modified", b"old")?;
+    write_object(&mut repo, "removed", b"removed")?;
+    repo.create_snapshot("old")?;
+
+    write_object(&mut repo, "modified", b"new")?;
+    write_object(&mut repo, "added", b"added")?;
+    repo.remove("removed");
+    repo.create_snapshot("new")?;
+
+    let changes = repo.diff("old", "new")?.into_iter().collect::<HashSet<_>>();
+    let expected_changes = vec![
+        Change::Added(String::from("added")),
+        Change::Removed(String::from("removed")),
+        Change::Modified(String::from("modified")),
+    ]
+    .into_iter()
+    .collect::<HashSet<_>>();
+
+    assert_that!(changes).is_equal_to(expected_changes);
+    assert_that!(repo.diff("old", "nonexistent")).is_err_variant(acid_store::Error::NotFound);
+
+    Ok(())
+}
+
+#[rstest]
+fn restore_snapshot(mut repo: SnapshotRepo<String>) -> anyhow::Result<()> {
+    write_object(&mut repo, "test", b"old")?;
+    repo.create_snapshot("snapshot")?;
+    write_object(&mut repo, "test", b"new")?;
+    write_object(&mut repo, "other", b"other")?;
+
+    repo.restore_snapshot("snapshot")?;
+
+    let mut actual_data = Vec::new();
+    repo.object("test").unwrap().read_to_end(&mut actual_data)?;
+
+    assert_that!(actual_data.as_slice()).is_equal_to(&b"old"[..]);
+    assert_that!(repo.contains("other")).is_false();
+    assert_that!(repo.contains_snapshot("snapshot")).is_true();
+    assert_that!(repo.restore_snapshot("nonexistent")).is_err_variant(acid_store::Error::NotFound);
+
+    Ok(())
+}
+
+#[rstest]
+fn snapshots_are_persisted(repo_store: RepoStore) -> anyhow::Result<()> {
+    let mut repo: SnapshotRepo<String> = repo_store.create()?;
+    write_object(&mut repo, "test", b"data")?;
+    repo.create_snapshot("snapshot")?;
+    repo.remove("test");
+    repo.commit()?;
+    repo.clean()?;
+    drop(repo);
+
+    let repo: SnapshotRepo<String> = repo_store.open()?;
+    let mut actual_data = Vec::new();
+    repo.snapshot_object("snapshot", "test")?
+        .read_to_end(&mut actual_data)?;
+
+    assert_that!(repo.snapshot_keys("snapshot")?.collect::<Vec<_>>())
+        .is_equal_to(vec![&String::from("test")]);
+    assert_that!(actual_data.as_slice()).is_equal_to(&b"data"[..]);
+
+    Ok(())
+}
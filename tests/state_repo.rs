@@ -1,4 +1,6 @@
-#![cfg(all(feature = "encryption", feature = "compression"))]
+#![cfg(feature = "testing")]
+
+use std::collections::HashSet;
 
 use uuid::Uuid;
 
@@ -70,3 +72,62 @@ fn ids_from_different_instances_are_not_valid(mut repo: StateRepo<String>) -> an
 
     Ok(())
 }
+
+#[rstest]
+fn segments_are_persisted_on_commit(repo_store: RepoStore) -> anyhow::Result<()> {
+    let mut repo: StateRepo<String> = repo_store.create()?;
+    repo.set_segment("first", &vec![1u32, 2, 3])?;
+    repo.set_segment("second", &String::from("Segment"))?;
+    repo.commit()?;
+    drop(repo);
+    let repo: StateRepo<String> = repo_store.open()?;
+
+    assert_that!(repo.segment::<Vec<u32>>("first")).is_ok_containing(Some(vec![1, 2, 3]));
+    assert_that!(repo.segment::<String>("second")).is_ok_containing(Some(String::from("Segment")));
+    assert_that!(repo.segments().collect::<HashSet<_>>())
+        .is_equal_to(["first", "second"].into_iter().collect::<HashSet<_>>());
+
+    Ok(())
+}
+
+#[rstest]
+fn segments_are_rolled_back(mut repo: StateRepo<String>) -> anyhow::Result<()> {
+    repo.set_segment("segment", &String::from("Initial segment"))?;
+    repo.commit()?;
+    repo.set_segment("segment", &String::from("New segment"))?;
+    repo.set_segment("other", &String::from("Other segment"))?;
+    repo.rollback()?;
+
+    assert_that!(repo.segment::<String>("segment"))
+        .is_ok_containing(Some(String::from("Initial segment")));
+    assert_that!(repo.contains_segment("other")).is_false();
+
+    Ok(())
+}
+
+#[rstest]
+fn removed_segment_does_not_exist(mut repo: StateRepo<String>) -> anyhow::Result<()> {
+    repo.set_segment("segment", &String::from("Segment"))?;
+
+    assert_that!(repo.remove_segment("segment")).is_true();
+    assert_that!(repo.contains_segment("segment")).is_false();
+    assert_that!(repo.segment::<String>("segment")).is_ok_containing(None);
+    assert_that!(repo.remove_segment("segment")).is_false();
+
+    Ok(())
+}
+
+#[rstest]
+fn unmodified_state_is_persisted_on_commit(repo_store: RepoStore) -> anyhow::Result<()> {
+    let mut repo: StateRepo<String> = repo_store.create()?;
+    *repo.state_mut() = String::from("New state");
+    repo.commit()?;
+    repo.set_segment("segment", &String::from("Segment"))?;
+    repo.commit()?;
+    drop(repo);
+    let repo: StateRepo<String> = repo_store.open()?;
+
+    assert_that!(repo.state()).is_equal_to(&String::from("New state"));
+
+    Ok(())
+}
@@ -1,4 +1,4 @@
-#![cfg(all(feature = "encryption", feature = "compression"))]
+#![cfg(feature = "testing")]
 
 use std::io::Write;
 
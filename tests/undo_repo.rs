@@ -0,0 +1,95 @@
+#![cfg(feature = "testing")]
+
+use std::io::Write;
+
+use acid_store::repo::key::KeyRepo;
+use acid_store::repo::{Commit, UndoRepo};
+use common::*;
+
+mod common;
+
+fn insert_key(repo: &mut UndoRepo<KeyRepo<String>>, key: &str) -> acid_store::Result<()> {
+    repo.modify(|repo| {
+        let mut object = repo.insert(key.to_string());
+        object.write_all(key.as_bytes())?;
+        object.commit()
+    })
+}
+
+#[rstest]
+fn undo_and_redo_changes(repo: KeyRepo<String>) -> anyhow::Result<()> {
+    let mut repo = UndoRepo::new(repo, 10);
+    insert_key(&mut repo, "first")?;
+    insert_key(&mut repo, "second")?;
+
+    assert_that!(repo.undo()).is_ok_containing(true);
+    assert_that!(repo.repo().contains("first")).is_true();
+    assert_that!(repo.repo().contains("second")).is_false();
+
+    assert_that!(repo.undo()).is_ok_containing(true);
+    assert_that!(repo.repo().contains("first")).is_false();
+    assert_that!(repo.undo()).is_ok_containing(false);
+
+    assert_that!(repo.redo()).is_ok_containing(true);
+    assert_that!(repo.redo()).is_ok_containing(true);
+    assert_that!(repo.repo().contains("second")).is_true();
+    assert_that!(repo.redo()).is_ok_containing(false);
+
+    Ok(())
+}
+
+#[rstest]
+fn new_change_discards_redo_history(repo: KeyRepo<String>) -> anyhow::Result<()> {
+    let mut repo = UndoRepo::new(repo, 10);
+    insert_key(&mut repo, "first")?;
+    repo.undo()?;
+    assert_that!(repo.redo_len()).is_equal_to(1);
+
+    insert_key(&mut repo, "second")?;
+    assert_that!(repo.redo_len()).is_equal_to(0);
+    assert_that!(repo.redo()).is_ok_containing(false);
+
+    Ok(())
+}
+
+#[rstest]
+fn history_is_bounded(repo: KeyRepo<String>) -> anyhow::Result<()> {
+    let mut repo = UndoRepo::new(repo, 2);
+    insert_key(&mut repo, "first")?;
+    insert_key(&mut repo, "second")?;
+    insert_key(&mut repo, "third")?;
+
+    assert_that!(repo.undo_len()).is_equal_to(2);
+    repo.undo()?;
+    repo.undo()?;
+    assert_that!(repo.undo()).is_ok_containing(false);
+    assert_that!(repo.repo().contains("first")).is_true();
+
+    Ok(())
+}
+
+#[rstest]
+fn failed_change_is_rolled_back(repo: KeyRepo<String>) {
+    let mut repo = UndoRepo::new(repo, 10);
+    let result = repo.modify(|repo| -> acid_store::Result<()> {
+        repo.insert(String::from("test"));
+        Err(acid_store::Error::InvalidData)
+    });
+
+    assert_that!(result).is_err_variant(acid_store::Error::InvalidData);
+    assert_that!(repo.repo().contains("test")).is_false();
+    assert_that!(repo.undo_len()).is_equal_to(0);
+}
+
+#[rstest]
+fn commit_clears_history(repo: KeyRepo<String>) -> anyhow::Result<()> {
+    let mut repo = UndoRepo::new(repo, 10);
+    insert_key(&mut repo, "first")?;
+    repo.commit()?;
+
+    assert_that!(repo.undo_len()).is_equal_to(0);
+    assert_that!(repo.undo()).is_ok_containing(false);
+    assert_that!(repo.repo().contains("first")).is_true();
+
+    Ok(())
+}
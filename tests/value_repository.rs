@@ -1,13 +1,11 @@
-#![cfg(all(
-    feature = "repo-value",
-    feature = "encryption",
-    feature = "compression"
-))]
+#![cfg(all(feature = "repo-value", feature = "testing"))]
 
 use std::collections::HashSet;
 
-use acid_store::repo::value::ValueRepo;
-use acid_store::repo::{Commit, SwitchInstance, DEFAULT_INSTANCE};
+use acid_store::repo::value::{OrderedValueRepo, ValueRepo};
+#[cfg(feature = "format-json")]
+use acid_store::repo::Json;
+use acid_store::repo::{Commit, MergeConflict, RepoConfig, SwitchInstance, DEFAULT_INSTANCE};
 use acid_store::uuid::Uuid;
 use common::*;
 
@@ -62,12 +60,212 @@ fn remove_value(mut repo: ValueRepo<String>) {
     assert_that!(repo.contains("Key")).is_false();
 }
 
+#[rstest]
+fn insert_and_remove_batch(mut repo: ValueRepo<String>) -> anyhow::Result<()> {
+    repo.insert("Key1".into(), &(false, 0))?;
+    repo.insert_batch(vec![
+        (String::from("Key1"), TEST_VALUE),
+        (String::from("Key2"), TEST_VALUE),
+        (String::from("Key3"), TEST_VALUE),
+    ])?;
+
+    assert_that!(repo.get("Key1")).is_ok_containing(TEST_VALUE);
+    assert_that!(repo.get("Key3")).is_ok_containing(TEST_VALUE);
+    assert_that!(repo.remove_batch(["Key1", "Key2", "Key4"])).is_equal_to(2);
+    assert_that!(repo.keys().collect::<Vec<_>>()).is_equal_to(vec![&String::from("Key3")]);
+
+    Ok(())
+}
+
+#[rstest]
+fn insert_if_absent_does_not_replace_values(mut repo: ValueRepo<String>) -> anyhow::Result<()> {
+    assert_that!(repo.insert_if_absent("test".into(), &TEST_VALUE)).is_ok_containing(true);
+    assert_that!(repo.insert_if_absent("test".into(), &(false, 0))).is_ok_containing(false);
+    assert_that!(repo.get("test")).is_ok_containing(TEST_VALUE);
+
+    Ok(())
+}
+
+#[rstest]
+fn compare_and_swap_replaces_expected_value(mut repo: ValueRepo<String>) -> anyhow::Result<()> {
+    let new_value = (false, 0);
+    repo.insert("test".into(), &TEST_VALUE)?;
+
+    assert_that!(repo.compare_and_swap("test", &new_value, &new_value)).is_ok_containing(false);
+    assert_that!(repo.get("test")).is_ok_containing(TEST_VALUE);
+
+    assert_that!(repo.compare_and_swap("test", &TEST_VALUE, &new_value)).is_ok_containing(true);
+    assert_that!(repo.get("test")).is_ok_containing(new_value);
+
+    Ok(())
+}
+
+#[rstest]
+fn compare_and_swap_missing_value_does_nothing(mut repo: ValueRepo<String>) {
+    assert_that!(repo.compare_and_swap("test", &TEST_VALUE, &TEST_VALUE)).is_ok_containing(false);
+    assert_that!(repo.contains("test")).is_false();
+}
+
+#[rstest]
+fn insert_values_from_multiple_threads(repo_store: RepoStore) -> anyhow::Result<()> {
+    let repo: ValueRepo<String> = repo_store.create()?;
+    let mut shared = repo.into_shared();
+
+    let threads = (0..8)
+        .map(|thread_index| {
+            let shared = shared.clone();
+            std::thread::spawn(move || -> acid_store::Result<()> {
+                for value_index in 0..8 {
+                    shared.insert(format!("{}-{}", thread_index, value_index), &TEST_VALUE)?;
+                }
+                Ok(())
+            })
+        })
+        .collect::<Vec<_>>();
+    for thread in threads {
+        thread.join().unwrap()?;
+    }
+
+    shared.commit()?;
+    assert_that!(shared.keys()).has_length(64);
+
+    let repo = shared.try_unwrap().unwrap();
+    drop(repo);
+
+    let repo: ValueRepo<String> = repo_store.open()?;
+    assert_that!(repo.keys().count()).is_equal_to(64);
+    assert_that!(repo.get::<_, TestType>("7-7")).is_ok_containing(TEST_VALUE);
+
+    Ok(())
+}
+
+#[rstest]
+fn compare_and_swap_from_multiple_threads(mut repo: ValueRepo<String>) -> anyhow::Result<()> {
+    repo.insert("counter".to_string(), &0u32)?;
+    let shared = repo.into_shared();
+
+    let threads = (0..8)
+        .map(|_| {
+            let shared = shared.clone();
+            std::thread::spawn(move || -> acid_store::Result<()> {
+                let mut increments = 0;
+                while increments < 8 {
+                    let current: u32 = shared.get("counter")?;
+                    if shared.compare_and_swap("counter", &current, &(current + 1))? {
+                        increments += 1;
+                    }
+                }
+                Ok(())
+            })
+        })
+        .collect::<Vec<_>>();
+    for thread in threads {
+        thread.join().unwrap()?;
+    }
+
+    assert_that!(shared.get::<_, u32>("counter")).is_ok_containing(64);
+
+    Ok(())
+}
+
+#[rstest]
+fn shared_value_repo_rolls_back_changes(mut repo: ValueRepo<String>) -> anyhow::Result<()> {
+    repo.insert("existing".to_string(), &TEST_VALUE)?;
+    repo.commit()?;
+    let mut shared = repo.into_shared();
+
+    assert_that!(shared.get::<_, TestType>("existing")).is_ok_containing(TEST_VALUE);
+    assert_that!(shared.remove("existing")).is_true();
+    shared.insert("new".to_string(), &TEST_VALUE)?;
+    shared.rollback()?;
+
+    assert_that!(shared.contains("existing")).is_true();
+    assert_that!(shared.contains("new")).is_false();
+
+    let repo = shared.try_unwrap().unwrap();
+    assert_that!(repo.get::<_, TestType>("existing")).is_ok_containing(TEST_VALUE);
+    assert_that!(repo.contains("new")).is_false();
+
+    Ok(())
+}
+
+#[rstest]
+fn unwrapping_shared_value_repo_with_clones_errs(repo: ValueRepo<String>) {
+    let shared = repo.into_shared();
+    let clone = shared.clone();
+    let shared = shared.try_unwrap().unwrap_err();
+    drop(clone);
+    assert_that!(shared.try_unwrap()).is_ok();
+}
+
+#[rstest]
+fn insert_ordered_values_from_multiple_threads(repo_store: RepoStore) -> anyhow::Result<()> {
+    let repo: OrderedValueRepo<u32> = repo_store.create()?;
+    let mut shared = repo.into_shared();
+
+    let threads = (0..8u32)
+        .map(|thread_index| {
+            let shared = shared.clone();
+            std::thread::spawn(move || -> acid_store::Result<()> {
+                for value_index in 0..8u32 {
+                    shared.insert(value_index * 8 + thread_index, &TEST_VALUE)?;
+                }
+                Ok(())
+            })
+        })
+        .collect::<Vec<_>>();
+    for thread in threads {
+        thread.join().unwrap()?;
+    }
+
+    assert_that!(shared.keys()).is_equal_to((0..64).collect::<Vec<_>>());
+    shared.commit()?;
+    drop(shared.try_unwrap().unwrap());
+
+    let repo: OrderedValueRepo<u32> = repo_store.open()?;
+    assert_that!(repo.keys().copied().collect::<Vec<_>>()).is_equal_to((0..64).collect::<Vec<_>>());
+    assert_that!(repo.get::<_, TestType>(&63)).is_ok_containing(TEST_VALUE);
+
+    Ok(())
+}
+
 #[rstest]
 fn deserializing_value_to_wrong_type_errs(mut repo: ValueRepo<String>) {
     assert_that!(repo.insert("Key".into(), &TEST_VALUE)).is_ok();
     assert_that!(repo.get::<_, String>("Key")).is_err_variant(acid_store::Error::Deserialize);
 }
 
+#[rstest]
+fn large_value_is_streamed(mut repo: ValueRepo<String>) -> anyhow::Result<()> {
+    let value = vec![String::from("large value"); 1024 * 16];
+    repo.insert("Key".into(), &value)?;
+
+    assert_that!(repo.get("Key")).is_ok_containing(value);
+
+    Ok(())
+}
+
+#[rstest]
+fn inserting_value_over_quota_errs(mut repo: ValueRepo<String>) {
+    repo.set_quota(Some(16));
+    let value = vec![String::from("large value"); 1024 * 16];
+
+    assert_that!(repo.insert("Key".into(), &value))
+        .is_err_variant(acid_store::Error::QuotaExceeded);
+    assert_that!(repo.contains("Key")).is_false();
+}
+
+#[rstest]
+fn serialized_value_can_be_borrowed(mut repo: ValueRepo<String>) -> anyhow::Result<()> {
+    repo.insert("Key".into(), &(String::from("borrowed"), 42u32))?;
+    let serialized_value = repo.get_serialized("Key")?;
+
+    assert_that!(serialized_value.deserialize::<(&str, u32)>()).is_ok_containing(("borrowed", 42));
+    assert_that!(repo.get_serialized("Missing")).is_err_variant(acid_store::Error::NotFound);
+
+    Ok(())
+}
+
 #[rstest]
 fn list_keys(mut repo: ValueRepo<String>) -> anyhow::Result<()> {
     repo.insert("Key1".into(), &TEST_VALUE)?;
@@ -129,3 +327,138 @@ fn verify_valid_repository_is_valid(mut repo: ValueRepo<String>) -> anyhow::Resu
 
     Ok(())
 }
+
+#[rstest]
+fn flushed_values_are_restored_after_rollback(mut repo: ValueRepo<String>) -> anyhow::Result<()> {
+    repo.insert("test".into(), &TEST_VALUE)?;
+    repo.flush()?;
+
+    repo.rollback()?;
+    assert_that!(repo.contains("test")).is_false();
+
+    assert_that!(repo.restore_flushed()).is_ok_containing(true);
+    assert_that!(repo.get::<_, TestType>("test")).is_ok_containing(TEST_VALUE);
+
+    Ok(())
+}
+
+#[rstest]
+fn ordered_keys_are_sorted(mut repo: OrderedValueRepo<u32>) -> anyhow::Result<()> {
+    for key in [3, 1, 4, 5, 9, 2, 6] {
+        repo.insert(key, &TEST_VALUE)?;
+    }
+
+    assert_that!(repo.keys().copied().collect::<Vec<_>>()).is_equal_to(vec![1, 2, 3, 4, 5, 6, 9]);
+    assert_that!(repo.first_key()).is_equal_to(Some(&1));
+    assert_that!(repo.last_key()).is_equal_to(Some(&9));
+
+    Ok(())
+}
+
+#[rstest]
+fn query_range_of_ordered_keys(mut repo: OrderedValueRepo<u32>) -> anyhow::Result<()> {
+    for key in 0..10 {
+        repo.insert(key, &TEST_VALUE)?;
+    }
+
+    assert_that!(repo.range(3..6).copied().collect::<Vec<_>>()).is_equal_to(vec![3, 4, 5]);
+    assert_that!(repo.range(8..).rev().copied().collect::<Vec<_>>()).is_equal_to(vec![9, 8]);
+    assert_that!(repo.range(20..30).next()).is_none();
+
+    Ok(())
+}
+
+#[rstest]
+fn ordered_keys_are_persisted(repo_store: RepoStore) -> anyhow::Result<()> {
+    let mut repo: OrderedValueRepo<String> = repo_store.create()?;
+    repo.insert(String::from("b"), &TEST_VALUE)?;
+    repo.insert(String::from("a"), &TEST_VALUE)?;
+    repo.commit()?;
+    drop(repo);
+
+    let repo: OrderedValueRepo<String> = repo_store.open()?;
+
+    assert_that!(repo.first_key()).is_equal_to(Some(&String::from("a")));
+    assert_that!(repo.get::<_, TestType>("b")).is_ok_containing(TEST_VALUE);
+
+    Ok(())
+}
+
+#[rstest]
+#[cfg(feature = "format-json")]
+fn values_can_be_serialized_as_json(repo_store: RepoStore) -> anyhow::Result<()> {
+    let mut repo: ValueRepo<String, Json> = repo_store.create()?;
+    repo.insert(String::from("test"), &TEST_VALUE)?;
+    repo.commit()?;
+    drop(repo);
+
+    let repo: ValueRepo<String, Json> = repo_store.open()?;
+
+    assert_that!(repo.get::<_, TestType>("test")).is_ok_containing(TEST_VALUE);
+
+    Ok(())
+}
+
+#[rstest]
+#[cfg(feature = "format-json")]
+fn opening_repo_with_different_format_errs(repo_store: RepoStore) -> anyhow::Result<()> {
+    let mut repo: ValueRepo<String, Json> = repo_store.create()?;
+    repo.insert(String::from("test"), &TEST_VALUE)?;
+    repo.commit()?;
+    drop(repo);
+
+    assert_that!(repo_store.open::<ValueRepo<String>>())
+        .is_err_variant(acid_store::Error::UnsupportedRepo);
+
+    Ok(())
+}
+
+#[rstest]
+fn merge_applies_changes_and_reports_conflicts(mut repo: ValueRepo<String>) -> anyhow::Result<()> {
+    let mut ours: ValueRepo<String> = create_repo(RepoConfig::default())?;
+    let mut theirs: ValueRepo<String> = create_repo(RepoConfig::default())?;
+    for key in ["unchanged", "changed", "removed", "conflict"] {
+        repo.insert(key.to_string(), &0)?;
+        ours.insert(key.to_string(), &0)?;
+        theirs.insert(key.to_string(), &0)?;
+    }
+
+    theirs.insert("changed".to_string(), &1)?;
+    theirs.insert("conflict".to_string(), &1)?;
+    theirs.insert("added".to_string(), &1)?;
+    theirs.remove("removed");
+    ours.insert("conflict".to_string(), &2)?;
+
+    let report = ours.merge(&repo, &theirs)?;
+
+    assert_that!(ours.get::<_, i32>("unchanged")).is_ok_containing(0);
+    assert_that!(ours.get::<_, i32>("changed")).is_ok_containing(1);
+    assert_that!(ours.get::<_, i32>("added")).is_ok_containing(1);
+    assert_that!(ours.get::<_, i32>("conflict")).is_ok_containing(2);
+    assert_that!(ours.contains("removed")).is_false();
+    assert_that!(report.updated().len()).is_equal_to(2);
+    assert_that!(report.removed().contains("removed")).is_true();
+    assert_that!(report.conflicts().get("conflict")).is_equal_to(Some(&MergeConflict::BothChanged));
+    assert_that!(ours.verify()).is_ok_containing(HashSet::new());
+
+    Ok(())
+}
+
+#[rstest]
+fn merge_ignores_values_changed_the_same_way(mut repo: ValueRepo<String>) -> anyhow::Result<()> {
+    let mut ours: ValueRepo<String> = create_repo(RepoConfig::default())?;
+    let mut theirs: ValueRepo<String> = create_repo(RepoConfig::default())?;
+    repo.insert("changed".to_string(), &0)?;
+    ours.insert("changed".to_string(), &1)?;
+    theirs.insert("changed".to_string(), &1)?;
+    ours.insert("added".to_string(), &1)?;
+    theirs.insert("added".to_string(), &1)?;
+
+    let report = ours.merge(&repo, &theirs)?;
+
+    assert_that!(report.is_clean()).is_true();
+    assert_that!(report.updated().is_empty()).is_true();
+    assert_that!(ours.get::<_, i32>("changed")).is_ok_containing(1);
+
+    Ok(())
+}